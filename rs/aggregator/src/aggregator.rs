@@ -51,7 +51,7 @@ impl Aggregator for AggregatorServerImpl {
             .ok_or_else(|| {
                 tonic::Status::internal(format!("No nodes found for index: {}", index_name))
             })?;
-        let ef_construction = req.ef_construction;
+        let ef_search = req.ef_search;
         let low_user_ids = req.low_user_ids;
         let high_user_ids = req.high_user_ids;
 
@@ -96,7 +96,7 @@ impl Aggregator for AggregatorServerImpl {
                     vector: req.vector.clone(),
                     top_k: req.top_k,
                     record_metrics: req.record_metrics,
-                    ef_construction,
+                    ef_search: Some(ef_search),
                     low_user_ids: low_user_ids.clone(),
                     high_user_ids: high_user_ids.clone(),
                 }))
@@ -0,0 +1,180 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use index::index::BoxedSearchable;
+use index::utils::SearchContext;
+use index_writer::detection::IndexReader;
+use log::info;
+use rayon::prelude::*;
+use utils::io::fvecs::FvecsReader;
+use utils::io::ivecs::IvecsReader;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    // Path to the base_config.yaml written next to the index by the index writer
+    #[arg(long)]
+    index_config: String,
+
+    // Query vectors, in FVECS format
+    #[arg(long)]
+    query_path: String,
+
+    // Ground-truth nearest neighbors of each query, in IVECS format
+    #[arg(long)]
+    ground_truth_path: String,
+
+    // Number of probed centroids to benchmark, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "1,2,4,8,16")]
+    nprobes_values: Vec<u32>,
+
+    #[arg(short, long, default_value_t = 10)]
+    k: usize,
+
+    // Number of leading queries run before measuring, for each nprobes value
+    #[arg(long, default_value_t = 0)]
+    warmup_queries: usize,
+}
+
+struct BenchResult {
+    nprobes: u32,
+    qps: f64,
+    recall: f64,
+    p99_latency: Duration,
+}
+
+/// Runs all queries in parallel and returns the ids found for each query, along with its latency.
+fn run_queries(
+    index: &BoxedSearchable,
+    queries: &[Vec<f32>],
+    k: usize,
+    nprobes: u32,
+) -> Vec<(Vec<u128>, Duration)> {
+    queries
+        .par_iter()
+        .map(|query| {
+            let mut context = SearchContext::new(false);
+            let start = Instant::now();
+            let ids = index
+                .search(query, k, nprobes, &mut context)
+                .map(|results| results.iter().map(|r| r.id).collect())
+                .unwrap_or_default();
+            (ids, start.elapsed())
+        })
+        .collect()
+}
+
+/// Fraction of the `k` true nearest neighbors found, averaged over all queries.
+fn recall_at_k(results: &[Vec<u128>], ground_truth: &[Vec<u32>], k: usize) -> f64 {
+    if results.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = results
+        .iter()
+        .zip(ground_truth)
+        .map(|(ids, truth)| {
+            let truth: HashSet<u128> = truth.iter().take(k).map(|&id| id as u128).collect();
+            if truth.is_empty() {
+                return 1.0;
+            }
+            let found = ids.iter().take(k).filter(|id| truth.contains(id)).count();
+            found as f64 / truth.len() as f64
+        })
+        .sum();
+    total / results.len() as f64
+}
+
+fn percentile(latencies: &mut [Duration], percentile: f64) -> Duration {
+    if latencies.is_empty() {
+        return Duration::ZERO;
+    }
+    latencies.sort();
+    let rank = ((latencies.len() as f64 * percentile).ceil() as usize).max(1);
+    latencies[rank.min(latencies.len()) - 1]
+}
+
+fn bench(
+    index: &BoxedSearchable,
+    queries: &[Vec<f32>],
+    ground_truth: &[Vec<u32>],
+    k: usize,
+    nprobes: u32,
+    warmup_queries: usize,
+) -> BenchResult {
+    let warmup_queries = warmup_queries.min(queries.len());
+    run_queries(index, &queries[..warmup_queries], k, nprobes);
+
+    let queries = &queries[warmup_queries..];
+    let start = Instant::now();
+    let results = run_queries(index, queries, k, nprobes);
+    let elapsed = start.elapsed();
+
+    let (ids, mut latencies): (Vec<_>, Vec<_>) = results.into_iter().unzip();
+    BenchResult {
+        nprobes,
+        qps: queries.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        recall: recall_at_k(&ids, &ground_truth[warmup_queries..], k),
+        p99_latency: percentile(&mut latencies, 0.99),
+    }
+}
+
+fn print_markdown_table(results: &[BenchResult], k: usize) {
+    println!("| nprobes | QPS | Recall@{} | P99 latency (ms) |", k);
+    println!("|---:|---:|---:|---:|");
+    for result in results {
+        println!(
+            "| {} | {:.1} | {:.4} | {:.3} |",
+            result.nprobes,
+            result.qps,
+            result.recall,
+            result.p99_latency.as_secs_f64() * 1000.0
+        );
+    }
+}
+
+pub fn main() -> Result<()> {
+    env_logger::init();
+
+    let arg = Args::parse();
+    let index_directory = Path::new(&arg.index_config)
+        .parent()
+        .and_then(|p| p.to_str())
+        .ok_or_else(|| anyhow!("Invalid index config path: {}", arg.index_config))?;
+    let index = IndexReader::new(index_directory)?.read()?;
+
+    let queries = FvecsReader::read(&arg.query_path)?;
+    let ground_truth = IvecsReader::read(&arg.ground_truth_path)?;
+    if queries.len() != ground_truth.len() {
+        return Err(anyhow!(
+            "Got {} queries but {} ground-truth entries",
+            queries.len(),
+            ground_truth.len()
+        ));
+    }
+    info!(
+        "Running {} queries ({} for warm-up) for nprobes {:?}",
+        queries.len(),
+        arg.warmup_queries,
+        arg.nprobes_values
+    );
+
+    let results: Vec<BenchResult> = arg
+        .nprobes_values
+        .iter()
+        .map(|&nprobes| {
+            bench(
+                &index,
+                &queries,
+                &ground_truth,
+                arg.k,
+                nprobes,
+                arg.warmup_queries,
+            )
+        })
+        .collect();
+    print_markdown_table(&results, arg.k);
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use compression::adaptive::adaptive::{AdaptiveDecoder, AdaptiveEncoder};
+use compression::benchmark::{benchmark_encoder, BenchmarkResult};
+use compression::compression::IntSeqDecoder;
+use compression::elias_fano::ef::{EliasFano, EliasFanoDecoder};
+use compression::noc::noc::{PlainDecoder, PlainEncoder};
+use compression::roaring::roaring::{RoaringDecoder, RoaringEncoder};
+use config::enums::IntSeqEncodingType;
+use index::posting_list::combined_file::FixedIndexFile;
+use index_writer::config::IvfConfig;
+use log::info;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    // Directory of a built IVF index, containing the `index` file and `ivf_config.yaml`
+    #[arg(long)]
+    input: String,
+}
+
+/// Decodes all posting lists of the index file, using the encoding the index was built with.
+fn read_posting_lists<D: IntSeqDecoder<Item = u64>>(
+    index_file: &FixedIndexFile,
+) -> Result<Vec<Vec<u64>>> {
+    (0..index_file.header().num_clusters as usize)
+        .map(|i| {
+            let byte_slice = index_file.get_posting_list(i)?;
+            let decoder = D::new_decoder(byte_slice)?;
+            Ok(decoder.get_iterator(byte_slice).collect())
+        })
+        .collect()
+}
+
+fn print_result(name: &str, result: &BenchmarkResult) {
+    println!(
+        "{:<12} {:>16} {:>16.3} {:>20.0}",
+        name, result.compressed_bytes, result.bytes_per_integer, result.decoded_integers_per_second
+    );
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let ivf_config_path = format!("{}/ivf_config.yaml", args.input);
+    let ivf_config: IvfConfig = serde_yaml::from_str(
+        &std::fs::read_to_string(&ivf_config_path)
+            .map_err(|e| anyhow!("Failed to read config {}: {}", ivf_config_path, e))?,
+    )?;
+    let index_file = FixedIndexFile::new(format!("{}/index", args.input))?;
+    let posting_lists = match ivf_config.posting_list_encoding_type {
+        IntSeqEncodingType::PlainEncoding => read_posting_lists::<PlainDecoder>(&index_file)?,
+        IntSeqEncodingType::EliasFano => read_posting_lists::<EliasFanoDecoder>(&index_file)?,
+        IntSeqEncodingType::Adaptive => read_posting_lists::<AdaptiveDecoder>(&index_file)?,
+        IntSeqEncodingType::Roaring => read_posting_lists::<RoaringDecoder>(&index_file)?,
+    };
+    info!(
+        "Read {} posting lists encoded with {:?}",
+        posting_lists.len(),
+        ivf_config.posting_list_encoding_type
+    );
+
+    let num_integers = posting_lists.iter().map(|pl| pl.len()).sum::<usize>();
+    println!(
+        "{} posting lists, {} integers",
+        posting_lists.len(),
+        num_integers
+    );
+    println!(
+        "{:<12} {:>16} {:>16} {:>20}",
+        "encoding", "bytes", "bytes/integer", "decoded integers/s"
+    );
+    print_result(
+        "plain",
+        &benchmark_encoder::<PlainEncoder, PlainDecoder>(&posting_lists)?,
+    );
+    print_result(
+        "elias_fano",
+        &benchmark_encoder::<EliasFano, EliasFanoDecoder>(&posting_lists)?,
+    );
+    print_result(
+        "adaptive",
+        &benchmark_encoder::<AdaptiveEncoder, AdaptiveDecoder>(&posting_lists)?,
+    );
+    print_result(
+        "roaring",
+        &benchmark_encoder::<RoaringEncoder, RoaringDecoder>(&posting_lists)?,
+    );
+    Ok(())
+}
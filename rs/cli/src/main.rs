@@ -41,7 +41,7 @@ async fn main() -> Result<()> {
                 vector: vec![1.0, 2.0, 3.0],
                 top_k: 10,
                 record_metrics: true,
-                ef_construction: 100,
+                ef_search: 100,
                 low_user_ids: vec![0],
                 high_user_ids: vec![0],
             });
@@ -64,7 +64,7 @@ async fn main() -> Result<()> {
                 vector: vec,
                 top_k: 10,
                 record_metrics: true,
-                ef_construction: 100,
+                ef_search: Some(100),
                 low_user_ids: vec![0],
                 high_user_ids: vec![0],
             });
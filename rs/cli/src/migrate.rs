@@ -0,0 +1,355 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use index::posting_list::combined_file::FixedIndexFile;
+use log::info;
+
+// Name of the `FixedIndexFile`s inside an index directory.
+const INDEX_FILE_NAME: &str = "index";
+
+// HNSW index files are also named `index` and start with a 0 version byte, but live in this
+// directory and have their own format.
+const HNSW_DIRECTORY_NAME: &str = "hnsw";
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    // Directory of the index to migrate
+    #[arg(long)]
+    src: String,
+
+    // Directory to write the migrated index to
+    #[arg(long)]
+    dst: String,
+
+    #[arg(long)]
+    from_version: u8,
+
+    #[arg(long)]
+    to_version: u8,
+
+    // Report the files that would be migrated without writing anything
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+/// Rewrites the content of an index file from one version to the next.
+type MigrationFn = fn(&[u8]) -> Result<Vec<u8>>;
+
+type MigrationRegistry = BTreeMap<(u8, u8), MigrationFn>;
+
+/// All known migrations of `FixedIndexFile`. New versions register their migration from the
+/// previous one here.
+fn registry() -> MigrationRegistry {
+    let mut registry: MigrationRegistry = BTreeMap::new();
+    registry.insert((0, 1), migrate_v0_to_v1);
+    registry
+}
+
+/// Adds the section checksums of `Version::V1`, and checks them against the migrated content.
+fn migrate_v0_to_v1(content: &[u8]) -> Result<Vec<u8>> {
+    let migrated = FixedIndexFile::add_section_checksums(content)?;
+    FixedIndexFile::new_from_bytes("migrated".to_string(), &migrated, 0)?.verify_sections()?;
+    Ok(migrated)
+}
+
+fn is_index_file(path: &Path) -> bool {
+    let in_hnsw_directory = path
+        .parent()
+        .and_then(|parent| parent.file_name())
+        .and_then(|name| name.to_str())
+        == Some(HNSW_DIRECTORY_NAME);
+    path.file_name().and_then(|name| name.to_str()) == Some(INDEX_FILE_NAME) && !in_hnsw_directory
+}
+
+/// Returns the shortest chain of migrations going from `from_version` to `to_version`.
+fn find_chain(
+    registry: &MigrationRegistry,
+    from_version: u8,
+    to_version: u8,
+) -> Result<Vec<(u8, u8)>> {
+    let mut previous: HashMap<u8, u8> = HashMap::new();
+    let mut queue = VecDeque::from([from_version]);
+    while let Some(version) = queue.pop_front() {
+        if version == to_version {
+            let mut chain = vec![];
+            let mut current = to_version;
+            while current != from_version {
+                let prev = previous[&current];
+                chain.push((prev, current));
+                current = prev;
+            }
+            chain.reverse();
+            return Ok(chain);
+        }
+        for &(from, to) in registry.keys() {
+            if from == version && to != from_version && !previous.contains_key(&to) {
+                previous.insert(to, from);
+                queue.push_back(to);
+            }
+        }
+    }
+    Err(anyhow!(
+        "No migration from version {} to version {}",
+        from_version,
+        to_version
+    ))
+}
+
+fn list_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copies the index in `src` to `dst`, migrating every index file along the way.
+/// Returns the paths, relative to `src`, of the migrated index files.
+fn migrate(
+    registry: &MigrationRegistry,
+    src: &str,
+    dst: &str,
+    from_version: u8,
+    to_version: u8,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let chain = find_chain(registry, from_version, to_version)?;
+    let src = Path::new(src);
+    let dst = Path::new(dst);
+
+    let mut migrated = vec![];
+    for path in list_files(src)? {
+        let relative_path = path.strip_prefix(src)?.to_path_buf();
+        let mut content = std::fs::read(&path)?;
+        if is_index_file(&path) {
+            let version = *content
+                .first()
+                .ok_or_else(|| anyhow!("Empty index file {}", path.display()))?;
+            if version != from_version {
+                return Err(anyhow!(
+                    "{} has version {}, expected {}",
+                    path.display(),
+                    version,
+                    from_version
+                ));
+            }
+            let old_len = content.len();
+            for step in &chain {
+                content = registry[step](&content)?;
+            }
+            info!(
+                "{}: version {} -> {} ({} -> {} bytes)",
+                relative_path.display(),
+                from_version,
+                to_version,
+                old_len,
+                content.len()
+            );
+            migrated.push(relative_path.clone());
+        }
+
+        if !dry_run {
+            let dst_path = dst.join(&relative_path);
+            if let Some(parent) = dst_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(dst_path, content)?;
+        }
+    }
+    Ok(migrated)
+}
+
+pub fn main() -> Result<()> {
+    env_logger::init();
+
+    let arg = Args::parse();
+    let migrated = migrate(
+        &registry(),
+        &arg.src,
+        &arg.dst,
+        arg.from_version,
+        arg.to_version,
+        arg.dry_run,
+    )?;
+    let action = if arg.dry_run {
+        "Would migrate"
+    } else {
+        "Migrated"
+    };
+    println!("{} {} index files", action, migrated.len());
+    for path in migrated {
+        println!("  {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use compression::noc::noc::PlainEncoder;
+    use index::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+    use index::ivf::writer::IvfWriter;
+    use index::posting_list::combined_file::Version;
+    use quantization::noq::noq::NoQuantizer;
+    use tempdir::TempDir;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::kmeans_builder::kmeans_builder::KMeansVariant;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+
+    fn bump_version(content: &[u8]) -> Result<Vec<u8>> {
+        let mut content = content.to_vec();
+        content[0] += 1;
+        Ok(content)
+    }
+
+    fn append_marker(content: &[u8]) -> Result<Vec<u8>> {
+        let mut content = bump_version(content)?;
+        content.push(0xff);
+        Ok(content)
+    }
+
+    fn test_registry() -> MigrationRegistry {
+        let mut registry: MigrationRegistry = BTreeMap::new();
+        registry.insert((0, 1), bump_version);
+        registry.insert((1, 2), append_marker);
+        registry
+    }
+
+    #[test]
+    fn test_find_chain() {
+        let registry = test_registry();
+        assert_eq!(find_chain(&registry, 0, 2).unwrap(), vec![(0, 1), (1, 2)]);
+        assert_eq!(find_chain(&registry, 1, 2).unwrap(), vec![(1, 2)]);
+        assert!(find_chain(&registry, 0, 0).unwrap().is_empty());
+        assert!(find_chain(&registry, 2, 0).is_err());
+        assert_eq!(find_chain(&super::registry(), 0, 1).unwrap(), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let temp_dir = TempDir::new("test_migrate").unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        let src = format!("{}/src", root);
+        let dst = format!("{}/dst", root);
+        std::fs::create_dir_all(format!("{}/segment1/quantizer", src)).unwrap();
+        std::fs::write(format!("{}/segment1/index", src), [0u8, 7]).unwrap();
+        std::fs::create_dir_all(format!("{}/segment1/hnsw", src)).unwrap();
+        std::fs::write(format!("{}/segment1/hnsw/index", src), [0u8, 8]).unwrap();
+        std::fs::write(format!("{}/segment1/vectors", src), [0u8, 1, 2]).unwrap();
+        std::fs::write(format!("{}/segment1/quantizer/config", src), b"config").unwrap();
+
+        let registry = test_registry();
+        let migrated = migrate(&registry, &src, &dst, 0, 2, true).unwrap();
+        assert_eq!(migrated, vec![PathBuf::from("segment1/index")]);
+        assert!(!Path::new(&dst).exists());
+
+        migrate(&registry, &src, &dst, 0, 2, false).unwrap();
+        assert_eq!(
+            std::fs::read(format!("{}/segment1/index", dst)).unwrap(),
+            vec![2u8, 7, 0xff]
+        );
+        assert_eq!(
+            std::fs::read(format!("{}/segment1/hnsw/index", dst)).unwrap(),
+            vec![0u8, 8]
+        );
+        assert_eq!(
+            std::fs::read(format!("{}/segment1/vectors", dst)).unwrap(),
+            vec![0u8, 1, 2]
+        );
+        assert_eq!(
+            std::fs::read(format!("{}/segment1/quantizer/config", dst)).unwrap(),
+            b"config"
+        );
+
+        // The source index is not at version 1.
+        assert!(migrate(&registry, &src, &dst, 1, 2, true).is_err());
+    }
+
+    #[test]
+    fn test_migrate_v0_to_v1() {
+        let temp_dir = TempDir::new("test_migrate_v0_to_v1").unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        let src = format!("{}/src", root);
+        let dst = format!("{}/dst", root);
+        std::fs::create_dir_all(&src).unwrap();
+
+        let num_features = 4;
+        let writer = IvfWriter::<_, PlainEncoder, L2DistanceCalculator>::new(
+            src.clone(),
+            NoQuantizer::<L2DistanceCalculator>::new(num_features),
+        );
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters: 10,
+            num_data_points_for_clustering: 1000,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: src.clone(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .unwrap();
+        for i in 0..1000 {
+            builder
+                .add_vector(i as u128, &generate_random_vector(num_features))
+                .unwrap();
+        }
+        builder.build().unwrap();
+        writer.write(&mut builder, false).unwrap();
+
+        let migrated = migrate(&registry(), &src, &dst, 0, 1, false).unwrap();
+        assert_eq!(migrated, vec![PathBuf::from("index")]);
+        assert_eq!(
+            std::fs::read(format!("{}/vectors", dst)).unwrap(),
+            std::fs::read(format!("{}/vectors", src)).unwrap()
+        );
+
+        let old = FixedIndexFile::new(format!("{}/index", src)).unwrap();
+        let new = FixedIndexFile::new_verified(format!("{}/index", dst)).unwrap();
+        assert_eq!(old.header().version, Version::V0);
+        assert_eq!(new.header().version, Version::V1);
+        assert_eq!(new.header().num_vectors, old.header().num_vectors);
+        assert_eq!(new.header().num_clusters, old.header().num_clusters);
+        assert!(new.size_in_bytes() > old.size_in_bytes());
+        for i in 0..old.header().num_vectors as usize {
+            assert_eq!(new.get_doc_id(i).unwrap(), old.get_doc_id(i).unwrap());
+        }
+        for i in 0..old.header().num_clusters as usize {
+            assert_eq!(new.get_centroid(i).unwrap(), old.get_centroid(i).unwrap());
+            assert_eq!(
+                new.get_posting_list(i).unwrap(),
+                old.get_posting_list(i).unwrap()
+            );
+        }
+
+        // Corrupting a section of the migrated file is detected
+        let mut bytes = std::fs::read(format!("{}/index", dst)).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(format!("{}/index", dst), &bytes).unwrap();
+        assert!(FixedIndexFile::new_verified(format!("{}/index", dst)).is_err());
+
+        // The migrated index is already at version 1
+        assert!(migrate(&registry(), &dst, &format!("{}/dst2", root), 0, 1, true).is_err());
+    }
+}
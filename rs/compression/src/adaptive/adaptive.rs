@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use anyhow::{anyhow, Result};
+use config::enums::IntSeqEncodingType;
+use utils::io::wrap_write;
+
+use crate::compression::{IntSeqDecoder, IntSeqEncoder};
+use crate::elias_fano::ef::{EliasFano, EliasFanoDecoder, EliasFanoDecodingIterator};
+use crate::noc::noc::{PlainDecoder, PlainDecodingIterator, PlainEncoder};
+
+// Sequences shorter than this are stored plain: Elias-Fano saves little on them, and its
+// metadata alone is 4 u64s.
+pub const ADAPTIVE_MIN_ELIAS_FANO_LEN: usize = 64;
+
+// Tags written before each sequence. A full u64 keeps the encoded data 8-byte aligned.
+const PLAIN_TAG: u64 = 0;
+const ELIAS_FANO_TAG: u64 = 1;
+const TAG_SIZE: usize = std::mem::size_of::<u64>();
+
+/// Picks the encoding per sequence based on its length: plain for short sequences (fast to
+/// decode, no overhead), Elias-Fano for long ones (better compression). The chosen encoding is
+/// stored as a tag before the encoded data, so `AdaptiveDecoder` can dispatch on it.
+pub enum AdaptiveEncoder {
+    Plain(PlainEncoder),
+    EliasFano(EliasFano),
+}
+
+impl IntSeqEncoder for AdaptiveEncoder {
+    fn new_encoder(universe: usize, num_elem: usize) -> Self {
+        if num_elem < ADAPTIVE_MIN_ELIAS_FANO_LEN {
+            Self::Plain(PlainEncoder::new_encoder(universe, num_elem))
+        } else {
+            Self::EliasFano(EliasFano::new_encoder(universe, num_elem))
+        }
+    }
+
+    fn encode_batch(&mut self, slice: &[u64]) -> Result<()> {
+        match self {
+            Self::Plain(encoder) => encoder.encode_batch(slice),
+            Self::EliasFano(encoder) => encoder.encode_batch(slice),
+        }
+    }
+
+    fn encode_value(&mut self, value: &u64) -> Result<()> {
+        match self {
+            Self::Plain(encoder) => encoder.encode_value(value),
+            Self::EliasFano(encoder) => encoder.encode_value(value),
+        }
+    }
+
+    fn len(&self) -> usize {
+        TAG_SIZE
+            + match self {
+                Self::Plain(encoder) => encoder.len(),
+                Self::EliasFano(encoder) => encoder.len(),
+            }
+    }
+
+    fn write(&self, writer: &mut BufWriter<&mut File>) -> Result<usize> {
+        match self {
+            Self::Plain(encoder) => {
+                Ok(wrap_write(writer, &PLAIN_TAG.to_le_bytes())? + encoder.write(writer)?)
+            }
+            Self::EliasFano(encoder) => {
+                Ok(wrap_write(writer, &ELIAS_FANO_TAG.to_le_bytes())? + encoder.write(writer)?)
+            }
+        }
+    }
+}
+
+pub enum AdaptiveDecoder {
+    Plain(PlainDecoder),
+    EliasFano(EliasFanoDecoder),
+}
+
+impl IntSeqDecoder for AdaptiveDecoder {
+    type IteratorType<'a> = AdaptiveDecodingIterator<'a>;
+    type Item = u64;
+    type Encoder = AdaptiveEncoder;
+
+    fn new_decoder(byte_slice: &[u8]) -> Result<Self> {
+        if byte_slice.len() < TAG_SIZE {
+            return Err(anyhow!("Missing encoding tag in adaptive encoded data"));
+        }
+        let (tag, data) = byte_slice.split_at(TAG_SIZE);
+        match u64::from_le_bytes(tag.try_into()?) {
+            PLAIN_TAG => Ok(Self::Plain(PlainDecoder::new_decoder(data)?)),
+            ELIAS_FANO_TAG => Ok(Self::EliasFano(EliasFanoDecoder::new_decoder(data)?)),
+            tag => Err(anyhow!("Unknown encoding tag {}", tag)),
+        }
+    }
+
+    fn get_iterator<'a>(&self, byte_slice: &'a [u8]) -> Self::IteratorType<'a> {
+        let data = &byte_slice[TAG_SIZE..];
+        match self {
+            Self::Plain(decoder) => AdaptiveDecodingIterator::Plain(decoder.get_iterator(data)),
+            Self::EliasFano(decoder) => {
+                AdaptiveDecodingIterator::EliasFano(decoder.get_iterator(data))
+            }
+        }
+    }
+
+    fn encoding_type() -> IntSeqEncodingType {
+        IntSeqEncodingType::Adaptive
+    }
+}
+
+pub enum AdaptiveDecodingIterator<'a> {
+    Plain(PlainDecodingIterator<'a>),
+    EliasFano(EliasFanoDecodingIterator<'a>),
+}
+
+impl<'a> Iterator for AdaptiveDecodingIterator<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Plain(iterator) => iterator.next(),
+            Self::EliasFano(iterator) => iterator.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn encode(values: &[u64]) -> Vec<u64> {
+        let mut encoder =
+            AdaptiveEncoder::new_encoder(*values.last().unwrap_or(&0) as usize, values.len());
+        encoder.encode_batch(values).unwrap();
+
+        let temp_dir = TempDir::new("test_adaptive_encoding").unwrap();
+        let file_path = temp_dir.path().join("encoded");
+        let mut file = File::create(&file_path).unwrap();
+        let mut writer = BufWriter::new(&mut file);
+        assert_eq!(encoder.write(&mut writer).unwrap(), encoder.len());
+        drop(writer);
+
+        let mut bytes = vec![];
+        File::open(&file_path)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        // Keep the data aligned for the decoders
+        bytes
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_adaptive_encoding() {
+        let short = vec![3, 7, 7, 20];
+        let long = (0..200).map(|i| i * 5).collect::<Vec<u64>>();
+
+        for (values, expected_tag) in [
+            (short, PLAIN_TAG),
+            (long, ELIAS_FANO_TAG),
+            (vec![], PLAIN_TAG),
+        ] {
+            let encoded = encode(&values);
+            assert_eq!(encoded[0], expected_tag);
+
+            let byte_slice = utils::mem::transmute_slice_to_u8(&encoded);
+            let decoder = AdaptiveDecoder::new_decoder(byte_slice).unwrap();
+            assert_eq!(
+                decoder.get_iterator(byte_slice).collect::<Vec<u64>>(),
+                values
+            );
+        }
+
+        // Long sequences are smaller than plain ones
+        let long = (0..200).map(|i| i * 5).collect::<Vec<u64>>();
+        assert!(encode(&long).len() < long.len());
+
+        assert!(AdaptiveDecoder::new_decoder(&[0u8; 4]).is_err());
+        assert!(AdaptiveDecoder::new_decoder(&7u64.to_le_bytes()).is_err());
+    }
+}
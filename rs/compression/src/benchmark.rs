@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use tempdir::TempDir;
+use utils::mem::transmute_slice_to_u8;
+
+use crate::compression::{IntSeqDecoder, IntSeqEncoder};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkResult {
+    pub num_sequences: usize,
+    pub num_integers: usize,
+    // Total size of the encoded sequences, as written to disk
+    pub compressed_bytes: usize,
+    pub bytes_per_integer: f64,
+    pub decoded_integers_per_second: f64,
+}
+
+/// Encodes every sorted sequence in `data` with `E`, then decodes them all back with `D`,
+/// measuring the compressed size and the decoding throughput. Decoded sequences are checked
+/// against the input.
+pub fn benchmark_encoder<E: IntSeqEncoder, D: IntSeqDecoder<Item = u64>>(
+    data: &[Vec<u64>],
+) -> Result<BenchmarkResult> {
+    // Encoders can only write to files, so go through a temporary one
+    let temp_dir = TempDir::new("benchmark_encoder")?;
+    let file_path = temp_dir.path().join("encoded");
+    let mut ranges = Vec::with_capacity(data.len());
+    {
+        let mut file = File::create(&file_path)?;
+        let mut writer = BufWriter::new(&mut file);
+        let mut offset = 0;
+        for sequence in data {
+            // Same universe as the IVF writer uses for posting lists
+            let mut encoder = E::new_encoder(
+                sequence.last().copied().unwrap_or(0) as usize,
+                sequence.len(),
+            );
+            encoder.encode_batch(sequence)?;
+            let len = encoder.write(&mut writer)?;
+            ranges.push(offset..offset + len);
+            offset += len;
+        }
+    }
+
+    let mut bytes = vec![];
+    File::open(&file_path)?.read_to_end(&mut bytes)?;
+    // Decoders read the data as u64, so keep it aligned
+    let words = bytes
+        .chunks_exact(std::mem::size_of::<u64>())
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect::<Vec<u64>>();
+    let encoded = transmute_slice_to_u8(&words);
+    if encoded.len() != bytes.len() {
+        return Err(anyhow!("Encoded data is not a multiple of 8 bytes"));
+    }
+
+    let start = Instant::now();
+    let mut num_decoded = 0;
+    for range in ranges.iter() {
+        let byte_slice = &encoded[range.clone()];
+        let decoder = D::new_decoder(byte_slice)?;
+        num_decoded += decoder.get_iterator(byte_slice).count();
+    }
+    // Avoid dividing by zero for tiny inputs
+    let elapsed_secs = start.elapsed().as_secs_f64().max(f64::EPSILON);
+
+    for (i, (sequence, range)) in data.iter().zip(ranges.iter()).enumerate() {
+        let byte_slice = &encoded[range.clone()];
+        let decoder = D::new_decoder(byte_slice)?;
+        if !decoder
+            .get_iterator(byte_slice)
+            .eq(sequence.iter().copied())
+        {
+            return Err(anyhow!("Sequence {} does not decode to its input", i));
+        }
+    }
+
+    let num_integers = data.iter().map(|sequence| sequence.len()).sum::<usize>();
+    if num_decoded != num_integers {
+        return Err(anyhow!(
+            "Decoded {} integers, expected {}",
+            num_decoded,
+            num_integers
+        ));
+    }
+    Ok(BenchmarkResult {
+        num_sequences: data.len(),
+        num_integers,
+        compressed_bytes: bytes.len(),
+        bytes_per_integer: if num_integers == 0 {
+            0.0
+        } else {
+            bytes.len() as f64 / num_integers as f64
+        },
+        decoded_integers_per_second: num_integers as f64 / elapsed_secs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elias_fano::ef::{EliasFano, EliasFanoDecoder};
+    use crate::noc::noc::{PlainDecoder, PlainEncoder};
+
+    #[test]
+    fn test_benchmark_encoder() {
+        let data = vec![
+            (0..1000).map(|i| i * 3).collect::<Vec<u64>>(),
+            vec![],
+            vec![5, 8, 8, 15, 32],
+        ];
+
+        let plain = benchmark_encoder::<PlainEncoder, PlainDecoder>(&data).unwrap();
+        assert_eq!(plain.num_sequences, 3);
+        assert_eq!(plain.num_integers, 1005);
+        assert_eq!(plain.compressed_bytes, 1005 * 8);
+        assert_eq!(plain.bytes_per_integer, 8.0);
+        assert!(plain.decoded_integers_per_second > 0.0);
+
+        let elias_fano = benchmark_encoder::<EliasFano, EliasFanoDecoder>(&data).unwrap();
+        assert_eq!(elias_fano.num_integers, 1005);
+        assert!(elias_fano.bytes_per_integer < 1.0);
+    }
+}
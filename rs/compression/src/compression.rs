@@ -2,6 +2,7 @@ use std::fs::File;
 use std::io::BufWriter;
 
 use anyhow::Result;
+use config::enums::IntSeqEncodingType;
 
 pub trait IntSeqEncoder {
     /// Creates an encoder
@@ -26,6 +27,8 @@ pub trait IntSeqEncoder {
 pub trait IntSeqDecoder {
     type IteratorType<'a>: Iterator<Item = Self::Item>;
     type Item;
+    /// Encoder writing the data this decoder reads
+    type Encoder: IntSeqEncoder;
 
     /// Creates a decoder
     fn new_decoder(byte_slice: &[u8]) -> Result<Self>
@@ -35,4 +38,7 @@ pub trait IntSeqDecoder {
     /// Creates an iterator that iterates the encoded data and decodes one element at a time on the
     /// fly
     fn get_iterator<'a>(&self, byte_slice: &'a [u8]) -> Self::IteratorType<'a>;
+
+    /// Encoding of the data this decoder reads
+    fn encoding_type() -> IntSeqEncodingType;
 }
@@ -3,6 +3,7 @@ use std::io::{BufWriter, Write};
 
 use anyhow::{anyhow, Result};
 use bitvec::prelude::*;
+use config::enums::IntSeqEncodingType;
 use utils::io::wrap_write;
 use utils::mem::transmute_u8_to_slice;
 
@@ -195,6 +196,7 @@ impl EliasFanoDecoder {
 impl IntSeqDecoder for EliasFanoDecoder {
     type IteratorType<'a> = EliasFanoDecodingIterator<'a>;
     type Item = u64;
+    type Encoder = EliasFano;
 
     fn new_decoder(byte_slice: &[u8]) -> Result<Self> {
         let encoded_data = transmute_u8_to_slice::<u64>(byte_slice);
@@ -236,6 +238,10 @@ impl IntSeqDecoder for EliasFanoDecoder {
             lower_bit_length: self.lower_bit_length,
         }
     }
+
+    fn encoding_type() -> IntSeqEncodingType {
+        IntSeqEncodingType::EliasFano
+    }
 }
 
 pub struct EliasFanoDecodingIterator<'a> {
@@ -1,3 +1,6 @@
+pub mod adaptive;
+pub mod benchmark;
 pub mod compression;
 pub mod elias_fano;
 pub mod noc;
+pub mod roaring;
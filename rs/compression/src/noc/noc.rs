@@ -2,6 +2,7 @@ use std::fs::File;
 use std::io::{BufWriter, Write};
 
 use anyhow::{anyhow, Result};
+use config::enums::IntSeqEncodingType;
 use utils::io::wrap_write;
 
 use crate::compression::{IntSeqDecoder, IntSeqEncoder};
@@ -72,6 +73,7 @@ impl PlainDecoder {
 impl IntSeqDecoder for PlainDecoder {
     type IteratorType<'a> = PlainDecodingIterator<'a>;
     type Item = u64;
+    type Encoder = PlainEncoder;
 
     fn new_decoder(byte_slice: &[u8]) -> Result<Self> {
         Ok(Self {
@@ -86,6 +88,10 @@ impl IntSeqDecoder for PlainDecoder {
             encoded_data: utils::mem::transmute_u8_to_slice(byte_slice),
         }
     }
+
+    fn encoding_type() -> IntSeqEncodingType {
+        IntSeqEncodingType::PlainEncoding
+    }
 }
 
 pub struct PlainDecodingIterator<'a> {
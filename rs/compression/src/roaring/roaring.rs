@@ -0,0 +1,181 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use ::roaring::treemap::IntoIter;
+use ::roaring::RoaringTreemap;
+use anyhow::{anyhow, Result};
+use config::enums::IntSeqEncodingType;
+use utils::io::wrap_write;
+
+use crate::compression::{IntSeqDecoder, IntSeqEncoder};
+
+// The serialized treemap is prefixed with its length in bytes
+const LEN_SIZE: usize = std::mem::size_of::<u64>();
+
+/// Encodes a sequence as a `RoaringTreemap`, in its standard serialization format. Unlike plain
+/// and Elias-Fano encodings, its size doesn't depend on the largest value, only on how values
+/// are clustered. Duplicates are dropped.
+///
+/// The serialized treemap is padded to a multiple of 8 bytes, to keep what follows it aligned.
+pub struct RoaringEncoder {
+    treemap: RoaringTreemap,
+}
+
+impl RoaringEncoder {
+    fn serialized_len(&self) -> usize {
+        self.treemap.serialized_size()
+    }
+}
+
+impl IntSeqEncoder for RoaringEncoder {
+    fn new_encoder(_universe: usize, _num_elem: usize) -> Self {
+        Self {
+            treemap: RoaringTreemap::new(),
+        }
+    }
+
+    fn encode_batch(&mut self, slice: &[u64]) -> Result<()> {
+        for value in slice {
+            self.treemap.insert(*value);
+        }
+        Ok(())
+    }
+
+    fn encode_value(&mut self, value: &u64) -> Result<()> {
+        self.treemap.insert(*value);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        LEN_SIZE + self.serialized_len().next_multiple_of(8)
+    }
+
+    fn write(&self, writer: &mut BufWriter<&mut File>) -> Result<usize> {
+        let mut bytes = Vec::with_capacity(self.len());
+        bytes.extend_from_slice(&(self.serialized_len() as u64).to_le_bytes());
+        self.treemap.serialize_into(&mut bytes)?;
+        bytes.resize(self.len(), 0);
+
+        let total_bytes_written = wrap_write(writer, &bytes)?;
+        if total_bytes_written != self.len() {
+            return Err(anyhow!(
+                "Expected to write {} bytes but wrote {} bytes",
+                self.len(),
+                total_bytes_written
+            ));
+        }
+        Ok(total_bytes_written)
+    }
+}
+
+/// Deserializes the treemap when created, so that posting lists are only decoded when read.
+pub struct RoaringDecoder {
+    treemap: RoaringTreemap,
+}
+
+impl RoaringDecoder {
+    pub fn num_elem(&self) -> usize {
+        self.treemap.len() as usize
+    }
+}
+
+impl IntSeqDecoder for RoaringDecoder {
+    type IteratorType<'a> = IntoIter;
+    type Item = u64;
+    type Encoder = RoaringEncoder;
+
+    fn new_decoder(byte_slice: &[u8]) -> Result<Self> {
+        if byte_slice.len() < LEN_SIZE {
+            return Err(anyhow!("Missing length in roaring encoded data"));
+        }
+        let (len, data) = byte_slice.split_at(LEN_SIZE);
+        let len = u64::from_le_bytes(len.try_into()?) as usize;
+        if len > data.len() {
+            return Err(anyhow!(
+                "Roaring encoded data is {} bytes, expected at least {}",
+                data.len(),
+                len
+            ));
+        }
+        Ok(Self {
+            treemap: RoaringTreemap::deserialize_from(&data[..len])?,
+        })
+    }
+
+    fn get_iterator<'a>(&self, _byte_slice: &'a [u8]) -> Self::IteratorType<'a> {
+        self.treemap.clone().into_iter()
+    }
+
+    fn encoding_type() -> IntSeqEncodingType {
+        IntSeqEncodingType::Roaring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::noc::noc::PlainEncoder;
+
+    fn encode<E: IntSeqEncoder>(values: &[u64]) -> Vec<u8> {
+        let mut encoder = E::new_encoder(*values.last().unwrap_or(&0) as usize, values.len());
+        encoder.encode_batch(values).unwrap();
+
+        let temp_dir = TempDir::new("test_roaring_encoding").unwrap();
+        let file_path = temp_dir.path().join("encoded");
+        let mut file = File::create(&file_path).unwrap();
+        let mut writer = BufWriter::new(&mut file);
+        assert_eq!(encoder.write(&mut writer).unwrap(), encoder.len());
+        drop(writer);
+
+        let mut bytes = vec![];
+        File::open(&file_path)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_roaring_encoding() {
+        let values = vec![0, 3, 7, 20, 1 << 40, u64::MAX];
+        for values in [values, vec![]] {
+            let bytes = encode::<RoaringEncoder>(&values);
+            assert_eq!(bytes.len() % 8, 0);
+
+            let decoder = RoaringDecoder::new_decoder(&bytes).unwrap();
+            assert_eq!(decoder.num_elem(), values.len());
+            assert_eq!(decoder.get_iterator(&bytes).collect::<Vec<u64>>(), values);
+        }
+
+        assert!(RoaringDecoder::new_decoder(&[0u8; 4]).is_err());
+        assert!(RoaringDecoder::new_decoder(&64u64.to_le_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_roaring_encoding_size() {
+        // Consecutive ids, in a few clusters spread over the whole u64 range
+        let clustered = (0..4u64)
+            .flat_map(|cluster| (0..1000).map(move |i| cluster * (u64::MAX / 4) + i * 3))
+            .collect::<Vec<u64>>();
+        let roaring_len = encode::<RoaringEncoder>(&clustered).len();
+        let plain_len = encode::<PlainEncoder>(&clustered).len();
+        assert!(roaring_len * 3 < plain_len);
+
+        // Ids scattered uniformly over the whole u64 range share no container: every id pays
+        // for a container of its own, which is larger than a plain u64
+        let mut scattered = (0..4000u64)
+            .map(|i| i.wrapping_mul(0x9E3779B97F4A7C15))
+            .collect::<Vec<u64>>();
+        scattered.sort();
+        let roaring_len = encode::<RoaringEncoder>(&scattered).len();
+        let plain_len = encode::<PlainEncoder>(&scattered).len();
+        assert!(roaring_len > plain_len);
+
+        let decoder = RoaringDecoder::new_decoder(&encode::<RoaringEncoder>(&scattered)).unwrap();
+        assert_eq!(decoder.get_iterator(&[]).collect::<Vec<u64>>(), scattered);
+    }
+}
@@ -1,6 +1,39 @@
 use serde::{Deserialize, Serialize};
 
-use crate::enums::{IntSeqEncodingType, QuantizerType};
+use crate::enums::{DistanceType, IntSeqEncodingType, QuantizerType};
+
+/// When `Collection::maybe_merge_segments` merges small segments together.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub enum SegmentMergePolicy {
+    /// Segments are never merged.
+    #[default]
+    None,
+    /// Merge the smallest segments until there are at most `threshold` segments.
+    MaxSegments { threshold: usize },
+    /// Merge the smallest segments while at least two of them are smaller than `threshold`
+    /// bytes on disk.
+    MaxSegmentSizeBytes { threshold: usize },
+}
+
+/// How the segments of a collection are warmed up once read by `CollectionReader::read_async`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub enum SegmentWarmupStrategy {
+    /// Pages of the segments are only read by searches.
+    #[default]
+    None,
+    /// Read the first `num_vectors` vectors of every segment in a background task, to populate
+    /// the page cache.
+    Prefetch { num_vectors: usize },
+}
+
+/// Limit on the rate of search requests to a collection, shared by all clients. Requests take
+/// a token from a bucket of `capacity` tokens, refilled at `refill_rate_per_second`. Unlike the
+/// server's per-client `rate_limit` config, it only applies to one collection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenBucketConfig {
+    pub capacity: u64,
+    pub refill_rate_per_second: u64,
+}
 
 /// Config for a collection.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -112,6 +145,59 @@ pub struct CollectionConfig {
     /// increased build time.
     /// Default: true
     pub reindex: bool,
+
+    /// Number of seconds after which a flushed segment expires. Expired segments are hidden from
+    /// searches and eventually deleted.
+    /// Default: None (segments never expire)
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+
+    /// Distance metric between vectors. Only L2 can be searched for now.
+    /// Default: DistanceType::L2
+    #[serde(default)]
+    pub distance_metric: DistanceType,
+
+    /// Max number of vectors in the mutable segment. Once an insert reaches it, the segment is
+    /// flushed and a new one is started. Lower values flush more often, with smaller segments.
+    /// Default: usize::MAX (segments are only flushed explicitly)
+    #[serde(default = "default_max_segment_vectors")]
+    pub max_segment_vectors: usize,
+
+    /// ef_search used by queries that don't set it: the size of the candidate list for the
+    /// centroids graph. Unlike `centroids_ef_construction`, it only affects search.
+    /// Default: 100
+    #[serde(default = "default_ef_search")]
+    pub ef_search: u32,
+
+    /// Number of vectors of every segment kept in a cache shared by all searches, for
+    /// collections whose searches keep hitting the same vectors.
+    /// Default: None (no cache)
+    #[serde(default)]
+    pub vector_cache_capacity: Option<usize>,
+
+    /// When small segments are merged together, see `SegmentMergePolicy`. Many small segments
+    /// make searches slower, since every segment is searched.
+    /// Default: SegmentMergePolicy::None
+    #[serde(default)]
+    pub merge_policy: SegmentMergePolicy,
+
+    /// Limit on the rate of searches, so that a single busy client doesn't starve the other
+    /// collections of the server. Rejected searches fail with RESOURCE_EXHAUSTED.
+    /// Default: None (searches are not limited)
+    #[serde(default)]
+    pub rate_limit: Option<TokenBucketConfig>,
+    /// How segments are warmed up after the collection is read, see `SegmentWarmupStrategy`.
+    /// Default: SegmentWarmupStrategy::None
+    #[serde(default)]
+    pub warmup_strategy: SegmentWarmupStrategy,
+}
+
+fn default_max_segment_vectors() -> usize {
+    usize::MAX
+}
+
+fn default_ef_search() -> u32 {
+    100
 }
 
 impl Default for CollectionConfig {
@@ -139,6 +225,14 @@ impl Default for CollectionConfig {
             max_posting_list_size: usize::MAX,
             posting_list_kmeans_unbalanced_penalty: 0.0,
             reindex: true,
+            ttl_seconds: None,
+            distance_metric: DistanceType::L2,
+            max_segment_vectors: usize::MAX,
+            ef_search: default_ef_search(),
+            vector_cache_capacity: None,
+            merge_policy: SegmentMergePolicy::None,
+            rate_limit: None,
+            warmup_strategy: SegmentWarmupStrategy::None,
         }
     }
 }
@@ -168,6 +262,14 @@ impl CollectionConfig {
             posting_list_kmeans_unbalanced_penalty: 0.1,
             reindex: true,
             quantization_type: QuantizerType::NoQuantizer,
+            ttl_seconds: None,
+            distance_metric: DistanceType::L2,
+            max_segment_vectors: usize::MAX,
+            ef_search: default_ef_search(),
+            vector_cache_capacity: None,
+            merge_policy: SegmentMergePolicy::None,
+            rate_limit: None,
+            warmup_strategy: SegmentWarmupStrategy::None,
         }
     }
 }
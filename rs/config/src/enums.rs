@@ -6,6 +6,21 @@ pub enum QuantizerType {
     ProductQuantizer,
     #[default]
     NoQuantizer,
+    // Two product quantizers, the second one quantizing the residual of the first one.
+    // Only supported by HNSW and IVF indices.
+    ResidualQuantizer,
+    // 4-bit scalar quantizer. Only supported by HNSW and IVF indices.
+    Sq4,
+    // Scalar quantizer with up to 8 bits per dimension. Only supported by HNSW and IVF indices.
+    ScalarQuantizer,
+    // One bit per dimension, compared by Hamming distance. Only supported by HNSW and IVF indices.
+    BinaryQuantizer,
+    // Gaussian random projection to `output_dimension` dimensions, seeded by `seed`. Only
+    // supported by HNSW indices.
+    RandomProjection {
+        output_dimension: usize,
+        seed: u64,
+    },
 }
 
 impl From<i32> for QuantizerType {
@@ -23,6 +38,9 @@ pub enum DistanceType {
     DotProduct,
     #[default]
     L2,
+    // Vectors are L2-normalized on input and compared by dot product. Queries don't need to be
+    // normalized, their norm doesn't change the ranking.
+    Cosine,
 }
 
 // TODO(tyb): support more encoding
@@ -31,6 +49,10 @@ pub enum IntSeqEncodingType {
     EliasFano,
     #[default]
     PlainEncoding,
+    // Plain for short posting lists, Elias-Fano for long ones, chosen per posting list
+    Adaptive,
+    // Roaring treemap, for ids scattered over a large range
+    Roaring,
 }
 
 impl From<i32> for IntSeqEncodingType {
@@ -38,6 +60,8 @@ impl From<i32> for IntSeqEncodingType {
         match value {
             0 => IntSeqEncodingType::PlainEncoding,
             1 => IntSeqEncodingType::EliasFano,
+            2 => IntSeqEncodingType::Adaptive,
+            3 => IntSeqEncodingType::Roaring,
             _ => IntSeqEncodingType::PlainEncoding, // Default to PlainEncoding for unknown values
         }
     }
@@ -49,4 +73,15 @@ pub enum IndexType {
     Ivf,
     #[default]
     Spann,
+    // Exact search over all vectors, mostly used to measure the recall of the other indexes
+    Flat,
+}
+
+// Where index files are read from when serving queries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Local,
+    S3,
 }
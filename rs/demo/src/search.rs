@@ -53,7 +53,7 @@ async fn main() -> Result<()> {
         collection_name: "test-collection-1".to_string(),
         vector: query_vector,
         top_k: 5,
-        ef_construction: 100,
+        ef_search: Some(100),
         record_metrics: false,
         low_user_ids: vec![0],
         high_user_ids: vec![0],
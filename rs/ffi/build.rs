@@ -0,0 +1,29 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
+    // Generate the C header from the extern "C" functions in src/lib.rs
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))?;
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()?
+        .write_to_file(out_dir.join("muopdb.h"));
+
+    // Compile the C test harness without linking it: only the unit tests of this crate link it,
+    // through the `#[link]` attribute of their `run_ffi_test` declaration.
+    cc::Build::new()
+        .file(crate_dir.join("tests/ffi_test.c"))
+        .include(&out_dir)
+        .cargo_metadata(false)
+        .compile("ffi_test");
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=tests/ffi_test.c");
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! C interface to open a collection and search it. The header is generated by cbindgen into
+//! `muopdb.h` in the `OUT_DIR` of the build script when this crate is built, or can be generated
+//! with `cbindgen --config cbindgen.toml --output muopdb.h`.
+
+use std::ffi::{c_char, c_void, CStr};
+use std::sync::Arc;
+
+use index::collection::reader::CollectionReader;
+use index::collection::Collection;
+use index::index::Searchable;
+use index::utils::SearchContext;
+use log::error;
+
+// Number of probed centroids used for every search.
+const EF_CONSTRUCTION: u32 = 100;
+
+/// Opens the collection stored at `path`.
+/// Returns an opaque handle, or null if the collection cannot be read.
+/// The handle must be released with `muopdb_collection_close`.
+///
+/// # Safety
+/// `path` must be null or a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn muopdb_collection_open(path: *const c_char) -> *mut c_void {
+    if path.is_null() {
+        return std::ptr::null_mut();
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path.to_string(),
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    match CollectionReader::new(path.clone()).read() {
+        Ok(collection) => Box::into_raw(Box::new(collection)) as *mut c_void,
+        Err(e) => {
+            error!("Failed to open collection at {}: {}", path, e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Searches the collection for the `k` nearest neighbors of `query`, which has `dim` elements.
+/// Writes the lower 64 bits of the doc ids to `out_ids` and the scores to `out_scores`, both of
+/// which must have room for `k` elements. Results are sorted by increasing score.
+/// Returns the number of results, or -1 on error.
+///
+/// # Safety
+/// `handle` must be returned by `muopdb_collection_open` and not yet closed. `query` must point
+/// to `dim` floats, `out_ids` and `out_scores` to `k` elements each.
+#[no_mangle]
+pub unsafe extern "C" fn muopdb_search(
+    handle: *mut c_void,
+    query: *const f32,
+    dim: usize,
+    k: usize,
+    out_ids: *mut u64,
+    out_scores: *mut f32,
+) -> i32 {
+    if handle.is_null() || query.is_null() || out_ids.is_null() || out_scores.is_null() {
+        return -1;
+    }
+    let collection = &*(handle as *const Arc<Collection>);
+    if dim != collection.dimensions() {
+        return -1;
+    }
+    let query = std::slice::from_raw_parts(query, dim);
+
+    let snapshot = match collection.clone().get_snapshot() {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            error!("Failed to get snapshot: {}", e);
+            return -1;
+        }
+    };
+    let mut context = SearchContext::new(false);
+    let results = match snapshot.search(query, k, EF_CONSTRUCTION, &mut context) {
+        Some(results) => results,
+        None => return -1,
+    };
+
+    let out_ids = std::slice::from_raw_parts_mut(out_ids, k);
+    let out_scores = std::slice::from_raw_parts_mut(out_scores, k);
+    let count = results.len().min(k);
+    for (i, result) in results.iter().take(count).enumerate() {
+        out_ids[i] = result.id as u64;
+        out_scores[i] = result.score;
+    }
+    count as i32
+}
+
+/// Releases a handle returned by `muopdb_collection_open`. Does nothing if `handle` is null.
+///
+/// # Safety
+/// `handle` must be null or returned by `muopdb_collection_open`, and not closed before.
+#[no_mangle]
+pub unsafe extern "C" fn muopdb_collection_close(handle: *mut c_void) {
+    if handle.is_null() {
+        return;
+    }
+    drop(Box::from_raw(handle as *mut Arc<Collection>));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_int, CString};
+
+    use config::collection::CollectionConfig;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[link(name = "ffi_test", kind = "static")]
+    extern "C" {
+        // Defined in tests/ffi_test.c
+        fn run_ffi_test(collection_path: *const c_char) -> c_int;
+    }
+
+    #[test]
+    fn test_ffi() {
+        let temp_dir = TempDir::new("test_ffi").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+
+        let config = CollectionConfig::default_test_config();
+        Collection::init_new_collection(base_directory.clone(), &config).unwrap();
+        let collection = Collection::new(base_directory.clone(), config).unwrap();
+        for i in 0..100 {
+            let offset = i as f32;
+            collection
+                .insert(i, &[1.0 + offset, 2.0 + offset, 3.0 + offset, 4.0 + offset])
+                .unwrap();
+        }
+        collection.flush().unwrap();
+
+        let path = CString::new(base_directory).unwrap();
+        assert_eq!(unsafe { run_ffi_test(path.as_ptr()) }, 0);
+    }
+}
@@ -0,0 +1,59 @@
+#include <stddef.h>
+#include <stdint.h>
+
+#include "muopdb.h"
+
+#define DIMENSION 4
+#define TOP_K 3
+
+/*
+ * Exercises the C API against the collection at `collection_path`, which must contain a vector
+ * [1.0, 2.0, 3.0, 4.0] among others. Returns 0 on success, or the number of the failed check.
+ */
+int run_ffi_test(const char *collection_path) {
+  if (muopdb_collection_open(NULL) != NULL) {
+    return 1;
+  }
+  if (muopdb_collection_open("/nonexistent/muopdb/collection") != NULL) {
+    return 2;
+  }
+
+  void *handle = muopdb_collection_open(collection_path);
+  if (handle == NULL) {
+    return 3;
+  }
+
+  float query[DIMENSION] = {1.0f, 2.0f, 3.0f, 4.0f};
+  uint64_t ids[TOP_K];
+  float scores[TOP_K];
+
+  /* Wrong dimension */
+  if (muopdb_search(handle, query, DIMENSION - 1, TOP_K, ids, scores) != -1) {
+    muopdb_collection_close(handle);
+    return 4;
+  }
+
+  int32_t count = muopdb_search(handle, query, DIMENSION, TOP_K, ids, scores);
+  if (count <= 0 || count > TOP_K) {
+    muopdb_collection_close(handle);
+    return 5;
+  }
+
+  /* Scores are sorted, and the query itself is in the collection */
+  if (scores[0] != 0.0f) {
+    muopdb_collection_close(handle);
+    return 6;
+  }
+  for (int32_t i = 1; i < count; i++) {
+    if (scores[i] < scores[i - 1]) {
+      muopdb_collection_close(handle);
+      return 7;
+    }
+  }
+
+  muopdb_collection_close(handle);
+
+  /* Closing a null handle is a no-op */
+  muopdb_collection_close(NULL);
+  return 0;
+}
@@ -0,0 +1,86 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use index::utils::SearchContext;
+use index::vector::compressed::{
+    compressed_vector_file_path, CompressedVectorStorage, DEFAULT_NUM_CACHED_BLOCKS,
+    DEFAULT_VECTORS_PER_BLOCK,
+};
+use index::vector::fixed_file::FixedFileVectorStorage;
+use rand::Rng;
+use tempdir::TempDir;
+use utils::test_utils::generate_random_vector;
+
+const NUM_VECTORS: usize = 100000;
+const NUM_FEATURES: usize = 128;
+const NUM_READS: usize = 10000;
+const ZSTD_LEVEL: i32 = 3;
+
+fn write_vector_file(path: &str) {
+    let mut bytes = (NUM_VECTORS as u64).to_le_bytes().to_vec();
+    for _ in 0..NUM_VECTORS {
+        for value in generate_random_vector(NUM_FEATURES) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    std::fs::write(path, bytes).unwrap();
+}
+
+fn bench_compressed_vector_storage(c: &mut Criterion) {
+    let temp_dir = TempDir::new("bench_compressed_vector_storage").unwrap();
+    let base_directory = temp_dir.path().to_str().unwrap().to_string();
+    let path = format!("{}/vectors", base_directory);
+    let compressed_path = compressed_vector_file_path(&path);
+    write_vector_file(&path);
+    CompressedVectorStorage::<f32>::compress_file(
+        &path,
+        &compressed_path,
+        DEFAULT_VECTORS_PER_BLOCK,
+        ZSTD_LEVEL,
+    )
+    .unwrap();
+
+    let fixed = FixedFileVectorStorage::<f32>::new(path.clone(), NUM_FEATURES).unwrap();
+    let compressed =
+        CompressedVectorStorage::<f32>::new(compressed_path.clone(), DEFAULT_NUM_CACHED_BLOCKS)
+            .unwrap();
+    println!(
+        "Storage size: {} bytes uncompressed, {} bytes compressed",
+        std::fs::metadata(&path).unwrap().len(),
+        compressed.size_in_bytes()
+    );
+
+    let mut rng = rand::thread_rng();
+    let indices: Vec<usize> = (0..NUM_READS)
+        .map(|_| rng.gen_range(0..NUM_VECTORS))
+        .collect();
+
+    let mut group = c.benchmark_group("CompressedVectorStorage");
+    group.sample_size(10);
+    group.bench_with_input(
+        BenchmarkId::new("FixedFileRandomGet", NUM_READS),
+        &indices,
+        |bencher, indices| {
+            let mut context = SearchContext::new(false);
+            bencher.iter(|| {
+                for &index in indices {
+                    black_box(fixed.get(index, &mut context));
+                }
+            })
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("CompressedRandomGet", NUM_READS),
+        &indices,
+        |bencher, indices| {
+            let mut context = SearchContext::new(false);
+            bencher.iter(|| {
+                for &index in indices {
+                    black_box(compressed.get(index, &mut context));
+                }
+            })
+        },
+    );
+    group.finish();
+}
+
+criterion_group!(benches, bench_compressed_vector_storage);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use index::hnsw::builder::HnswBuilder;
+use quantization::noq::noq::NoQuantizer;
+use tempdir::TempDir;
+use utils::distance::l2::L2DistanceCalculator;
+use utils::test_utils::generate_random_vector;
+
+const NUM_VECTORS: usize = 100_000;
+const NUM_FEATURES: usize = 128;
+
+fn new_builder() -> (TempDir, HnswBuilder<NoQuantizer<L2DistanceCalculator>>) {
+    let temp_dir = TempDir::new("bench_hnsw_par_insert_batch").unwrap();
+    let builder = HnswBuilder::new(
+        32,
+        4,
+        200,
+        1024 * 1024 * 1024,
+        1024 * 1024 * 1024,
+        NUM_FEATURES,
+        NoQuantizer::new(NUM_FEATURES),
+        temp_dir.path().to_str().unwrap().to_string(),
+    );
+    (temp_dir, builder)
+}
+
+fn bench_par_insert_batch(c: &mut Criterion) {
+    let vectors: Vec<Vec<f32>> = (0..NUM_VECTORS)
+        .map(|_| generate_random_vector(NUM_FEATURES))
+        .collect();
+    let rows: Vec<(u64, &[f32])> = vectors
+        .iter()
+        .enumerate()
+        .map(|(i, vector)| (i as u64, vector.as_slice()))
+        .collect();
+
+    let mut group = c.benchmark_group("HnswBuilder build");
+    group.sample_size(10);
+    group.bench_function("insert", |bencher| {
+        bencher.iter_batched(
+            new_builder,
+            |(temp_dir, mut builder)| {
+                for (id, vector) in rows.iter() {
+                    builder.insert(*id as u128, vector).unwrap();
+                }
+                (temp_dir, builder)
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    // Uses the threads of rayon's global pool, set RAYON_NUM_THREADS to change their number
+    group.bench_function("par_insert_batch", |bencher| {
+        bencher.iter_batched(
+            new_builder,
+            |(temp_dir, mut builder)| {
+                builder.par_insert_batch(&rows).unwrap();
+                (temp_dir, builder)
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_par_insert_batch);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use index::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+use tempdir::TempDir;
+use utils::distance::l2::L2DistanceCalculator;
+use utils::kmeans_builder::kmeans_builder::KMeansVariant;
+use utils::test_utils::generate_random_vector;
+
+const NUM_VECTORS: usize = 1_000_000;
+const NUM_FEATURES: usize = 16;
+
+fn new_builder(batch_size: usize) -> (TempDir, IvfBuilder<L2DistanceCalculator>) {
+    let temp_dir = TempDir::new("bench_ivf_builder_add_vector").unwrap();
+    let builder = IvfBuilder::<L2DistanceCalculator>::new(IvfBuilderConfig {
+        max_iteration: 100,
+        batch_size,
+        num_clusters: 10,
+        num_data_points_for_clustering: 1000,
+        max_clusters_per_vector: 1,
+        distance_threshold: 0.1,
+        base_directory: temp_dir.path().to_str().unwrap().to_string(),
+        // Small memory size so that the vectors spill to the backing files
+        memory_size: 1024 * 1024,
+        file_size: 64 * 1024 * 1024,
+        num_features: NUM_FEATURES,
+        tolerance: 0.0,
+        kmeans_variant: KMeansVariant::Lloyd,
+        max_posting_list_size: usize::MAX,
+        initial_centroids_fvecs_path: None,
+        allow_online_insertion: false,
+    })
+    .unwrap();
+    (temp_dir, builder)
+}
+
+fn bench_add_vector(c: &mut Criterion) {
+    let vectors: Vec<Vec<f32>> = (0..NUM_VECTORS)
+        .map(|_| generate_random_vector(NUM_FEATURES))
+        .collect();
+
+    let mut group = c.benchmark_group("IvfBuilder add_vector");
+    group.sample_size(10);
+    for batch_size in [1, 1024] {
+        group.bench_with_input(
+            BenchmarkId::new("BatchSize", batch_size),
+            &batch_size,
+            |bencher, &batch_size| {
+                bencher.iter_batched(
+                    || new_builder(batch_size),
+                    |(temp_dir, mut builder)| {
+                        for (i, vector) in vectors.iter().enumerate() {
+                            builder.add_vector(i as u128, vector).unwrap();
+                        }
+                        builder.flush().unwrap();
+                        (temp_dir, builder)
+                    },
+                    BatchSize::PerIteration,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_add_vector);
+criterion_main!(benches);
@@ -0,0 +1,103 @@
+use compression::noc::noc::{PlainDecoder, PlainEncoder};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use index::index::Searchable;
+use index::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+use index::ivf::index::Ivf;
+use index::ivf::reader::IvfReader;
+use index::ivf::writer::IvfWriter;
+use index::utils::SearchContext;
+use quantization::noq::noq::NoQuantizer;
+use quantization::quantization::WritableQuantizer;
+use tempdir::TempDir;
+use utils::distance::l2::L2DistanceCalculator;
+use utils::kmeans_builder::kmeans_builder::KMeansVariant;
+use utils::test_utils::generate_random_vector;
+
+type BenchIvf = Ivf<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, PlainDecoder>;
+
+const NUM_CLUSTERS: usize = 100;
+const NUM_VECTORS: usize = 10000;
+const NUM_FEATURES: usize = 128;
+const NUM_HOT_VECTORS: usize = 100;
+const NUM_QUERIES: usize = 10000;
+const NUM_PROBES: u32 = 1;
+
+fn build_ivf(base_directory: &str) -> Vec<Vec<f32>> {
+    let quantizer = NoQuantizer::<L2DistanceCalculator>::new(NUM_FEATURES);
+    let quantizer_directory = format!("{}/quantizer", base_directory);
+    std::fs::create_dir_all(&quantizer_directory).unwrap();
+    quantizer.write_to_directory(&quantizer_directory).unwrap();
+    let writer = IvfWriter::<_, PlainEncoder, L2DistanceCalculator>::new(
+        base_directory.to_string(),
+        quantizer,
+    );
+
+    let mut builder = IvfBuilder::<L2DistanceCalculator>::new(IvfBuilderConfig {
+        max_iteration: 100,
+        batch_size: 4,
+        num_clusters: NUM_CLUSTERS,
+        num_data_points_for_clustering: NUM_VECTORS,
+        max_clusters_per_vector: 1,
+        distance_threshold: 0.1,
+        base_directory: base_directory.to_string(),
+        memory_size: 1024 * 1024,
+        file_size: 1024 * 1024,
+        num_features: NUM_FEATURES,
+        tolerance: 0.0,
+        kmeans_variant: KMeansVariant::Lloyd,
+        max_posting_list_size: usize::MAX,
+        initial_centroids_fvecs_path: None,
+        allow_online_insertion: false,
+    })
+    .unwrap();
+    let vectors: Vec<Vec<f32>> = (0..NUM_VECTORS)
+        .map(|_| generate_random_vector(NUM_FEATURES))
+        .collect();
+    for (i, vector) in vectors.iter().enumerate() {
+        builder.add_vector(i as u128, vector).unwrap();
+    }
+    builder.build().unwrap();
+    writer.write(&mut builder, false).unwrap();
+    vectors
+}
+
+fn run_queries(ivf: &BenchIvf, queries: &[Vec<f32>]) {
+    let mut context = SearchContext::new(false);
+    for query in queries {
+        black_box(ivf.search(query, 10, NUM_PROBES, &mut context));
+    }
+}
+
+fn bench_lru_vector_cache(c: &mut Criterion) {
+    let temp_dir = TempDir::new("bench_lru_vector_cache").unwrap();
+    let base_directory = temp_dir.path().to_str().unwrap().to_string();
+    let vectors = build_ivf(&base_directory);
+
+    // Every query is one of the first vectors, so searches keep scanning the same posting lists
+    let queries: Vec<Vec<f32>> = (0..NUM_QUERIES)
+        .map(|i| vectors[i % NUM_HOT_VECTORS].clone())
+        .collect();
+
+    let without_cache: BenchIvf = IvfReader::new(base_directory.clone()).read().unwrap();
+    let with_cache: BenchIvf = IvfReader::new(base_directory.clone())
+        .with_vector_cache_capacity(Some(NUM_VECTORS))
+        .read()
+        .unwrap();
+
+    let mut group = c.benchmark_group("SharedVectorCache");
+    group.sample_size(10);
+    group.bench_with_input(
+        BenchmarkId::new("NoCache", NUM_QUERIES),
+        &queries,
+        |bencher, queries| bencher.iter(|| run_queries(&without_cache, queries)),
+    );
+    group.bench_with_input(
+        BenchmarkId::new("Cache", NUM_QUERIES),
+        &queries,
+        |bencher, queries| bencher.iter(|| run_queries(&with_cache, queries)),
+    );
+    group.finish();
+}
+
+criterion_group!(benches, bench_lru_vector_cache);
+criterion_main!(benches);
@@ -0,0 +1,124 @@
+use compression::noc::noc::{PlainDecoder, PlainEncoder};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use index::index::Searchable;
+use index::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+use index::ivf::index::Ivf;
+use index::ivf::reader::IvfReader;
+use index::ivf::writer::IvfWriter;
+use index::utils::SearchContext;
+use quantization::noq::noq::NoQuantizer;
+use quantization::quantization::WritableQuantizer;
+use tempdir::TempDir;
+use utils::distance::l2::L2DistanceCalculator;
+use utils::kmeans_builder::kmeans_builder::KMeansVariant;
+use utils::test_utils::generate_random_vector;
+
+type BenchIvf = Ivf<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, PlainDecoder>;
+
+const NUM_CLUSTERS: usize = 10;
+const NUM_VECTORS: usize = 10000;
+const NUM_FEATURES: usize = 128;
+const NUM_QUERIES: usize = 1000;
+const NUM_PROBES: u32 = 2;
+
+fn build_ivf(base_directory: &str) -> BenchIvf {
+    let quantizer = NoQuantizer::<L2DistanceCalculator>::new(NUM_FEATURES);
+    let quantizer_directory = format!("{}/quantizer", base_directory);
+    std::fs::create_dir_all(&quantizer_directory).unwrap();
+    quantizer.write_to_directory(&quantizer_directory).unwrap();
+    let writer = IvfWriter::<_, PlainEncoder, L2DistanceCalculator>::new(
+        base_directory.to_string(),
+        quantizer,
+    );
+
+    let mut builder = IvfBuilder::<L2DistanceCalculator>::new(IvfBuilderConfig {
+        max_iteration: 100,
+        batch_size: 4,
+        num_clusters: NUM_CLUSTERS,
+        num_data_points_for_clustering: NUM_VECTORS,
+        max_clusters_per_vector: 1,
+        distance_threshold: 0.1,
+        base_directory: base_directory.to_string(),
+        memory_size: 1024 * 1024,
+        file_size: 1024 * 1024,
+        num_features: NUM_FEATURES,
+        tolerance: 0.0,
+        kmeans_variant: KMeansVariant::Lloyd,
+        max_posting_list_size: usize::MAX,
+        initial_centroids_fvecs_path: None,
+        allow_online_insertion: false,
+    })
+    .unwrap();
+    for i in 0..NUM_VECTORS {
+        builder
+            .add_vector(i as u128, &generate_random_vector(NUM_FEATURES))
+            .unwrap();
+    }
+    builder.build().unwrap();
+    writer.write(&mut builder, false).unwrap();
+
+    IvfReader::new(base_directory.to_string()).read().unwrap()
+}
+
+fn run_queries(ivf: &BenchIvf, queries: &[Vec<f32>], context: &mut SearchContext) {
+    for query in queries {
+        black_box(ivf.search(query, 10, NUM_PROBES, context));
+    }
+}
+
+fn bench_search_context_cache(c: &mut Criterion) {
+    let temp_dir = TempDir::new("bench_search_context_cache").unwrap();
+    let base_directory = temp_dir.path().to_str().unwrap().to_string();
+    let ivf = build_ivf(&base_directory);
+
+    // Queries are drawn around a few of the vectors, so the same clusters are probed repeatedly
+    let hot_vectors: Vec<Vec<f32>> = (0..5)
+        .map(|_| generate_random_vector(NUM_FEATURES))
+        .collect();
+    let queries: Vec<Vec<f32>> = (0..NUM_QUERIES)
+        .map(|i| {
+            hot_vectors[i % hot_vectors.len()]
+                .iter()
+                .zip(generate_random_vector(NUM_FEATURES))
+                .map(|(x, noise)| x + noise * 0.01)
+                .collect()
+        })
+        .collect();
+
+    let mut context = SearchContext::new_with_cache_size(64 * 1024 * 1024);
+    run_queries(&ivf, &queries, &mut context);
+    let total = context.cache_hits() + context.cache_misses();
+    println!(
+        "Posting list cache hit rate over {} queries: {:.2}% ({} hits, {} misses)",
+        NUM_QUERIES,
+        context.cache_hits() as f64 * 100.0 / total as f64,
+        context.cache_hits(),
+        context.cache_misses()
+    );
+
+    let mut group = c.benchmark_group("SearchContext cache");
+    group.bench_with_input(
+        BenchmarkId::new("NoCache", NUM_QUERIES),
+        &queries,
+        |bencher, queries| {
+            bencher.iter(|| run_queries(&ivf, queries, &mut SearchContext::new(false)))
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("Cache", NUM_QUERIES),
+        &queries,
+        |bencher, queries| {
+            bencher.iter(|| {
+                run_queries(
+                    &ivf,
+                    queries,
+                    &mut SearchContext::new_with_cache_size(64 * 1024 * 1024),
+                )
+            })
+        },
+    );
+    group.finish();
+}
+
+criterion_group!(benches, bench_search_context_cache);
+criterion_main!(benches);
@@ -0,0 +1,405 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+// Identifies a file produced by `Collection::export`
+const EXPORT_MAGIC: &[u8; 8] = b"MUOPEXPT";
+const EXPORT_FORMAT_VERSION: u32 = 0;
+
+// Size of the fixed part of the header: magic, format version, collection version and
+// number of sections
+const FIXED_HEADER_LEN: u64 = 8 + 4 + 8 + 4;
+// Size of a section entry in the header, excluding the path bytes: path length, offset,
+// length and CRC32
+const SECTION_ENTRY_LEN: u64 = 4 + 8 + 8 + 4;
+
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A single file stored in an export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSection {
+    // Path of the file, relative to the collection directory
+    pub path: String,
+    // Offset of the file content from the start of the export
+    pub offset: u64,
+    pub len: u64,
+    pub crc32: u32,
+}
+
+/// Describes the content of an exported collection.
+///
+/// Layout of the export file (all integers are little-endian):
+/// | magic (8 bytes) | format version (u32) | collection version (u64) | num sections (u32) |
+/// | for each section: path length (u32) | path | offset (u64) | length (u64) | crc32 (u32) |
+/// | section data, in the same order as the header |
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportManifest {
+    // Version of the collection at the time of the export
+    pub version: u64,
+    pub sections: Vec<ExportSection>,
+}
+
+/// Where the content of a section comes from during export.
+pub enum SectionSource {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+impl SectionSource {
+    fn len(&self) -> Result<u64> {
+        match self {
+            SectionSource::Bytes(bytes) => Ok(bytes.len() as u64),
+            SectionSource::File(path) => Ok(std::fs::metadata(path)?.len()),
+        }
+    }
+}
+
+impl ExportManifest {
+    fn header_len(&self) -> u64 {
+        FIXED_HEADER_LEN
+            + self
+                .sections
+                .iter()
+                .map(|section| SECTION_ENTRY_LEN + section.path.len() as u64)
+                .sum::<u64>()
+    }
+
+    fn write_header<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(EXPORT_MAGIC)?;
+        writer.write_all(&EXPORT_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&(self.sections.len() as u32).to_le_bytes())?;
+        for section in &self.sections {
+            writer.write_all(&(section.path.len() as u32).to_le_bytes())?;
+            writer.write_all(section.path.as_bytes())?;
+            writer.write_all(&section.offset.to_le_bytes())?;
+            writer.write_all(&section.len.to_le_bytes())?;
+            writer.write_all(&section.crc32.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads the header of an export of `file_len` bytes. Sizes read from the header are
+    /// checked against `file_len` before anything is allocated.
+    fn read_header<R: Read>(reader: &mut R, file_len: u64) -> Result<Self> {
+        if file_len < FIXED_HEADER_LEN {
+            return Err(anyhow!("Not a collection export file"));
+        }
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != EXPORT_MAGIC {
+            return Err(anyhow!("Not a collection export file"));
+        }
+        let format_version = read_u32(reader)?;
+        if format_version != EXPORT_FORMAT_VERSION {
+            return Err(anyhow!(
+                "Unsupported export format version {}",
+                format_version
+            ));
+        }
+
+        let version = read_u64(reader)?;
+        let num_sections = read_u32(reader)? as u64;
+        let mut remaining = file_len - FIXED_HEADER_LEN;
+        if num_sections > remaining / SECTION_ENTRY_LEN {
+            return Err(anyhow!(
+                "Export header lists {} sections, more than the file can hold",
+                num_sections
+            ));
+        }
+        let mut sections = Vec::with_capacity(num_sections as usize);
+        for _ in 0..num_sections {
+            let path_len = read_u32(reader)? as u64;
+            remaining -= SECTION_ENTRY_LEN;
+            if path_len > remaining {
+                return Err(anyhow!("Export section path is longer than the file"));
+            }
+            remaining -= path_len;
+            let mut path = vec![0u8; path_len as usize];
+            reader.read_exact(&mut path)?;
+            let section = ExportSection {
+                path: String::from_utf8(path)?,
+                offset: read_u64(reader)?,
+                len: read_u64(reader)?,
+                crc32: read_u32(reader)?,
+            };
+            if section
+                .offset
+                .checked_add(section.len)
+                .filter(|&end| end <= file_len)
+                .is_none()
+            {
+                return Err(anyhow!(
+                    "Section {} at offset {} is out of bounds",
+                    section.path,
+                    section.offset
+                ));
+            }
+            sections.push(section);
+        }
+        Ok(Self { version, sections })
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Returns all files under `directory` as (path relative to `prefix`, absolute path), sorted by
+/// path so that exports are deterministic.
+pub fn list_files(directory: &Path, prefix: &str) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| anyhow!("Invalid file name in {}", directory.display()))?;
+        let relative_path = format!("{}/{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            files.extend(list_files(&entry.path(), &relative_path)?);
+        } else {
+            files.push((relative_path, entry.path()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Writes `sections` into a single export file at `destination_path`.
+pub fn write_export(
+    destination_path: &str,
+    version: u64,
+    sections: Vec<(String, SectionSource)>,
+) -> Result<ExportManifest> {
+    let mut manifest = ExportManifest {
+        version,
+        sections: sections
+            .iter()
+            .map(|(path, source)| {
+                Ok(ExportSection {
+                    path: path.clone(),
+                    offset: 0,
+                    len: source.len()?,
+                    crc32: 0,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?,
+    };
+
+    let mut offset = manifest.header_len();
+    for section in manifest.sections.iter_mut() {
+        section.offset = offset;
+        offset += section.len;
+    }
+
+    // The checksums are only known once the data is written, so write a placeholder header
+    // first and rewrite it at the end.
+    let mut writer = BufWriter::new(File::create(destination_path)?);
+    manifest.write_header(&mut writer)?;
+    for (section, (_, source)) in manifest.sections.iter_mut().zip(sections.iter()) {
+        let mut hasher = crc32fast::Hasher::new();
+        let written = match source {
+            SectionSource::Bytes(bytes) => {
+                hasher.update(bytes);
+                writer.write_all(bytes)?;
+                bytes.len() as u64
+            }
+            SectionSource::File(path) => {
+                let mut reader = BufReader::new(File::open(path)?);
+                copy_with_checksum(&mut reader, &mut writer, &mut hasher, section.len)?
+            }
+        };
+        if written != section.len {
+            return Err(anyhow!("{} changed during export", section.path));
+        }
+        section.crc32 = hasher.finalize();
+    }
+
+    writer.seek(SeekFrom::Start(0))?;
+    manifest.write_header(&mut writer)?;
+    writer.flush()?;
+    Ok(manifest)
+}
+
+/// Unpacks the export at `source_path` into `destination_dir`, verifying the checksum of every
+/// section.
+pub fn read_export(source_path: &str, destination_dir: &str) -> Result<ExportManifest> {
+    let file = File::open(source_path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let manifest = ExportManifest::read_header(&mut reader, file_len)?;
+
+    std::fs::create_dir_all(destination_dir)?;
+    for section in &manifest.sections {
+        let relative_path = Path::new(&section.path);
+        if !relative_path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow!("Invalid section path {}", section.path));
+        }
+        let path = Path::new(destination_dir).join(relative_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        reader.seek(SeekFrom::Start(section.offset))?;
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let mut hasher = crc32fast::Hasher::new();
+        let read = copy_with_checksum(&mut reader, &mut writer, &mut hasher, section.len)
+            .with_context(|| format!("Failed to read section {}", section.path))?;
+        writer.flush()?;
+        if read != section.len || hasher.finalize() != section.crc32 {
+            return Err(anyhow!(
+                "Section {} at offset {} is corrupted",
+                section.path,
+                section.offset
+            ));
+        }
+    }
+    Ok(manifest)
+}
+
+/// Copies at most `len` bytes from `reader` to `writer`, returning the number of bytes copied.
+fn copy_with_checksum<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    hasher: &mut crc32fast::Hasher,
+    len: u64,
+) -> Result<u64> {
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut copied = 0;
+    while copied < len {
+        let to_read = std::cmp::min(COPY_BUFFER_SIZE as u64, len - copied) as usize;
+        let n = reader.read(&mut buffer[..to_read])?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        writer.write_all(&buffer[..n])?;
+        copied += n as u64;
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_export_roundtrip() {
+        let temp_dir = TempDir::new("test_export_roundtrip").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let source_dir = format!("{}/source", base_directory);
+        std::fs::create_dir_all(format!("{}/segment/nested", source_dir)).unwrap();
+        std::fs::write(format!("{}/segment/a", source_dir), b"hello").unwrap();
+        std::fs::write(
+            format!("{}/segment/nested/b", source_dir),
+            vec![7u8; 100_000],
+        )
+        .unwrap();
+
+        let mut sections = vec![(
+            "version_3".to_string(),
+            SectionSource::Bytes(b"toc".to_vec()),
+        )];
+        sections.extend(
+            list_files(Path::new(&format!("{}/segment", source_dir)), "segment")
+                .unwrap()
+                .into_iter()
+                .map(|(path, file)| (path, SectionSource::File(file))),
+        );
+
+        let export_path = format!("{}/export.bin", base_directory);
+        let manifest = write_export(&export_path, 3, sections).unwrap();
+        assert_eq!(manifest.version, 3);
+        let paths: Vec<&str> = manifest.sections.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(paths, vec!["version_3", "segment/a", "segment/nested/b"]);
+
+        let destination_dir = format!("{}/destination", base_directory);
+        let imported = read_export(&export_path, &destination_dir).unwrap();
+        assert_eq!(imported, manifest);
+        assert_eq!(
+            std::fs::read(format!("{}/version_3", destination_dir)).unwrap(),
+            b"toc"
+        );
+        assert_eq!(
+            std::fs::read(format!("{}/segment/nested/b", destination_dir)).unwrap(),
+            vec![7u8; 100_000]
+        );
+    }
+
+    #[test]
+    fn test_export_corrupted() {
+        let temp_dir = TempDir::new("test_export_corrupted").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let export_path = format!("{}/export.bin", base_directory);
+        let manifest = write_export(
+            &export_path,
+            0,
+            vec![("a".to_string(), SectionSource::Bytes(vec![1, 2, 3, 4]))],
+        )
+        .unwrap();
+
+        let mut bytes = std::fs::read(&export_path).unwrap();
+        bytes[manifest.sections[0].offset as usize + 1] ^= 0xff;
+        std::fs::write(&export_path, bytes).unwrap();
+        let err = read_export(&export_path, &format!("{}/destination", base_directory))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Section a at offset"));
+
+        // Paths escaping the destination directory are rejected
+        write_export(
+            &export_path,
+            0,
+            vec![("../a".to_string(), SectionSource::Bytes(vec![1]))],
+        )
+        .unwrap();
+        assert!(read_export(&export_path, &format!("{}/destination", base_directory)).is_err());
+    }
+
+    #[test]
+    fn test_export_header_out_of_bounds() {
+        let temp_dir = TempDir::new("test_export_header_out_of_bounds").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let export_path = format!("{}/export.bin", base_directory);
+        let destination_dir = format!("{}/destination", base_directory);
+        write_export(
+            &export_path,
+            0,
+            vec![("a".to_string(), SectionSource::Bytes(vec![1, 2, 3, 4]))],
+        )
+        .unwrap();
+        let bytes = std::fs::read(&export_path).unwrap();
+        let num_sections_offset = FIXED_HEADER_LEN as usize - 4;
+        let path_len_offset = FIXED_HEADER_LEN as usize;
+        let section_len_offset = path_len_offset + 4 + 1 + 8;
+
+        // Each of these sizes would allocate or read far past the end of the file
+        for (offset, value) in [
+            (num_sections_offset, u32::MAX.to_le_bytes().to_vec()),
+            (path_len_offset, u32::MAX.to_le_bytes().to_vec()),
+            (section_len_offset, u64::MAX.to_le_bytes().to_vec()),
+        ] {
+            let mut corrupted = bytes.clone();
+            corrupted[offset..offset + value.len()].copy_from_slice(&value);
+            std::fs::write(&export_path, corrupted).unwrap();
+            assert!(read_export(&export_path, &destination_dir).is_err());
+        }
+        assert!(!Path::new(&destination_dir).join("a").exists());
+    }
+}
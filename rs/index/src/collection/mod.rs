@@ -1,39 +1,122 @@
+pub mod export;
 pub mod reader;
 pub mod snapshot;
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::{Arc, Mutex, RwLock};
 
 use anyhow::{Ok, Result};
-use config::collection::CollectionConfig;
+use config::collection::{
+    CollectionConfig, SegmentMergePolicy, SegmentWarmupStrategy, TokenBucketConfig,
+};
 use config::enums::QuantizerType;
 use dashmap::DashMap;
+use export::{ExportManifest, SectionSource};
+use log::warn;
 use quantization::noq::noq::NoQuantizer;
 use quantization::pq::pq::ProductQuantizer;
 use serde::{Deserialize, Serialize};
 use snapshot::Snapshot;
 use utils::distance::l2::L2DistanceCalculator;
 
-use crate::index::Searchable;
+use crate::index::{DocIdFilter, Searchable};
+use crate::multi_spann::builder::MultiSpannBuilder;
 use crate::multi_spann::reader::MultiSpannReader;
+use crate::multi_spann::user_stats::{now_epoch_ms, GlobalStats, UserStats};
+use crate::multi_spann::writer::MultiSpannWriter;
 use crate::segment::immutable_segment::ImmutableSegment;
 use crate::segment::mutable_segment::MutableSegment;
 use crate::segment::Segment;
+use crate::utils::{IdWithScore, SearchContext};
+use crate::wal::{Wal, WalRecord};
 
 pub trait SegmentSearchable: Searchable + Segment {}
 pub type BoxedSegmentSearchable = Box<dyn SegmentSearchable + Send + Sync>;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Lifetime of a segment in a collection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SegmentInfo {
+    pub created_at_epoch_ms: u64,
+    // The segment expires `ttl_ms` after its creation. None means it never expires.
+    pub ttl_ms: Option<u64>,
+}
+
+impl SegmentInfo {
+    pub fn is_expired(&self, now_epoch_ms: u64) -> bool {
+        self.ttl_ms
+            .is_some_and(|ttl_ms| self.created_at_epoch_ms.saturating_add(ttl_ms) <= now_epoch_ms)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TableOfContent {
     pub toc: Vec<String>,
+
+    // Lifetime of the segments in `toc`, by name. Segments without an entry never expire.
+    #[serde(default)]
+    pub segment_infos: HashMap<String, SegmentInfo>,
+
+    // Versions of the write-ahead log whose inserts are in the segments of `toc`. Their records
+    // are skipped on replay, in case the process crashed before the log was checkpointed.
+    #[serde(default)]
+    pub flushed_wal_versions: FlushedWalVersions,
 }
 
 impl TableOfContent {
     pub fn new(toc: Vec<String>) -> Self {
-        Self { toc }
+        Self {
+            toc,
+            segment_infos: HashMap::new(),
+            flushed_wal_versions: FlushedWalVersions::default(),
+        }
+    }
+
+    /// Returns the names of the segments that are expired at `now_epoch_ms`.
+    pub fn expired_segments(&self, now_epoch_ms: u64) -> Vec<String> {
+        self.toc
+            .iter()
+            .filter(|name| {
+                self.segment_infos
+                    .get(*name)
+                    .is_some_and(|info| info.is_expired(now_epoch_ms))
+            })
+            .cloned()
+            .collect()
     }
 }
 
+/// Versions of the write-ahead log that were flushed to segments. Versions are usually flushed in
+/// order, but a failed flush leaves its version behind while later ones succeed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FlushedWalVersions {
+    // Every version below this one is flushed, so the log can be checkpointed up to it
+    pub below: u64,
+    // Flushed versions above `below`
+    pub above: BTreeSet<u64>,
+}
+
+impl FlushedWalVersions {
+    pub fn contains(&self, version: u64) -> bool {
+        version < self.below || self.above.contains(&version)
+    }
+
+    pub fn insert(&mut self, version: u64) {
+        if version >= self.below {
+            self.above.insert(version);
+        }
+        while self.above.remove(&self.below) {
+            self.below += 1;
+        }
+    }
+}
+
+/// Write-ahead log of the mutable segment.
+struct MutableSegmentWal {
+    wal: Wal,
+    // Versions of the log whose records are in the mutable segment
+    versions: BTreeSet<u64>,
+}
+
 pub struct VersionsInfo {
     pub current_version: u64,
     pub version_ref_counts: HashMap<u64, usize>,
@@ -58,8 +141,15 @@ pub struct Collection {
     versions_info: RwLock<VersionsInfo>,
     base_directory: String,
     mutable_segment: RwLock<MutableSegment>,
+    // Inserts into the mutable segment, replayed if the collection crashes before flushing it.
+    // Locked after the mutable segment when both are needed.
+    wal: Mutex<MutableSegmentWal>,
     segment_config: CollectionConfig,
 
+    // Segments dropped from the TOC, with the version that dropped them. Older versions may
+    // still be used by snapshots, so the segments are only deleted once those are released.
+    retired_segments: Mutex<Vec<(u64, String)>>,
+
     // A mutex for flushing
     flushing: Mutex<()>,
 }
@@ -67,24 +157,20 @@ pub struct Collection {
 impl Collection {
     pub fn new(base_directory: String, segment_config: CollectionConfig) -> Result<Self> {
         let versions: DashMap<u64, TableOfContent> = DashMap::new();
-        versions.insert(0, TableOfContent::new(vec![]));
-
-        // Create a new segment_config with a random name
-        let random_name = format!("tmp_segment_{}", rand::random::<u64>());
-        let segment_base_directory = format!("{}/{}", base_directory, random_name);
-
-        let mutable_segment = RwLock::new(MutableSegment::new(
-            segment_config.clone(),
-            segment_base_directory,
-        )?);
+        let toc = TableOfContent::new(vec![]);
+        let (mutable_segment, wal) =
+            Self::open_mutable_segment(&base_directory, &segment_config, &toc)?;
+        versions.insert(0, toc);
 
         Ok(Self {
             versions,
             all_segments: DashMap::new(),
             versions_info: RwLock::new(VersionsInfo::new()),
             base_directory,
-            mutable_segment,
+            mutable_segment: RwLock::new(mutable_segment),
+            wal: Mutex::new(wal),
             segment_config,
+            retired_segments: Mutex::new(vec![]),
             flushing: Mutex::new(()),
         })
     }
@@ -94,7 +180,7 @@ impl Collection {
 
         // Write version 0
         let toc_path = format!("{}/version_0", base_directory);
-        let toc = TableOfContent { toc: vec![] };
+        let toc = TableOfContent::new(vec![]);
         serde_json::to_writer_pretty(std::fs::File::create(toc_path)?, &toc)?;
 
         // Write the config file
@@ -127,38 +213,88 @@ impl Collection {
                 all_segments.insert(name.clone(), segment.clone());
             });
 
+        let (mutable_segment, wal) =
+            Self::open_mutable_segment(&base_directory, &segment_config, &toc)?;
         let versions = DashMap::new();
         versions.insert(version, toc);
 
-        // Create a new segment_config with a random name
-        let random_name = format!("tmp_segment_{}", rand::random::<u64>());
-        let random_base_directory = format!("{}/{}", base_directory, random_name);
-        let mutable_segment = RwLock::new(MutableSegment::new(
-            segment_config.clone(),
-            random_base_directory,
-        )?);
-
         Ok(Self {
             versions,
             all_segments,
             versions_info,
             base_directory,
-            mutable_segment,
+            mutable_segment: RwLock::new(mutable_segment),
+            wal: Mutex::new(wal),
             segment_config,
+            retired_segments: Mutex::new(vec![]),
             flushing: Mutex::new(()),
         })
     }
 
+    /// Creates a new mutable segment with a random name, and replays the inserts of the
+    /// write-ahead log in `base_directory` into it, except those already flushed to the segments
+    /// of `toc`.
+    fn open_mutable_segment(
+        base_directory: &str,
+        segment_config: &CollectionConfig,
+        toc: &TableOfContent,
+    ) -> Result<(MutableSegment, MutableSegmentWal)> {
+        let random_name = format!("tmp_segment_{}", rand::random::<u64>());
+        let segment_base_directory = format!("{}/{}", base_directory, random_name);
+        let mut mutable_segment =
+            MutableSegment::new(segment_config.clone(), segment_base_directory)?;
+
+        let flushed_wal_versions = &toc.flushed_wal_versions;
+        let mut versions = BTreeSet::new();
+        let mut wal = Wal::open_and_replay(base_directory, |version, record| {
+            if flushed_wal_versions.contains(version) {
+                return Ok(());
+            }
+            versions.insert(version);
+            match record {
+                WalRecord::Insert {
+                    user_id,
+                    doc_id,
+                    data,
+                } => mutable_segment.insert_for_user(*user_id, *doc_id, data),
+                WalRecord::Delete { .. } => Err(anyhow::anyhow!(
+                    "Collections don't support deletes, found one in the write-ahead log"
+                )),
+            }
+        })?;
+        // The log may have been checkpointed up to the flushed versions, new inserts must not
+        // be tagged with one of them
+        wal.advance_to(
+            flushed_wal_versions
+                .above
+                .last()
+                .map_or(flushed_wal_versions.below, |version| version + 1),
+        );
+        versions.insert(wal.version());
+        Ok((mutable_segment, MutableSegmentWal { wal, versions }))
+    }
+
     pub fn insert(&self, doc_id: u128, data: &[f32]) -> Result<()> {
-        self.mutable_segment.write().unwrap().insert(doc_id, data)
+        self.insert_for_users(&[0], doc_id, data)
     }
 
+    /// Logs the inserts to the write-ahead log before applying them, so that they are not lost
+    /// if the process crashes before the mutable segment is flushed.
     pub fn insert_for_users(&self, user_ids: &[u128], doc_id: u128, data: &[f32]) -> Result<()> {
         for user_id in user_ids {
-            self.mutable_segment
-                .write()
-                .unwrap()
-                .insert_for_user(*user_id, doc_id, data)?;
+            // Log under the segment lock, so that a flush can't swap the segment in between
+            let mut mutable_segment = self.mutable_segment.write().unwrap();
+            self.wal.lock().unwrap().wal.append(&WalRecord::Insert {
+                user_id: *user_id,
+                doc_id,
+                data: data.to_vec(),
+            })?;
+            mutable_segment.insert_for_user(*user_id, doc_id, data)?;
+        }
+        // The inserts are already logged and applied, failing them would make clients retry and
+        // insert the doc twice. The next insert into the full segment retries the flush.
+        if let Err(e) = self.flush_if_full() {
+            warn!("Failed to flush the full mutable segment: {}", e);
         }
         Ok(())
     }
@@ -167,55 +303,111 @@ impl Collection {
         self.segment_config.num_features
     }
 
+    /// ef_search for queries that don't specify one.
+    pub fn default_ef_search(&self) -> u32 {
+        self.segment_config.ef_search
+    }
+
+    /// Limit on the rate of searches, if any.
+    pub fn rate_limit(&self) -> Option<&TokenBucketConfig> {
+        self.segment_config.rate_limit.as_ref()
+    }
+
+    /// How segments are warmed up after the collection is read.
+    pub fn warmup_strategy(&self) -> &SegmentWarmupStrategy {
+        &self.segment_config.warmup_strategy
+    }
+
     /// Turns mutable segment into immutable one, which is the only queryable segment type
     /// currently.
     pub fn flush(&self) -> Result<()> {
         // Try to acquire the flushing lock. If it fails, then another thread is already flushing.
         // This is a best effort approach, and we don't want to block the main thread.
         match self.flushing.try_lock() {
-            std::result::Result::Ok(_) => {
-                let tmp_name = format!("tmp_segment_{}", rand::random::<u64>());
-                let writable_base_directory = format!("{}/{}", self.base_directory, tmp_name);
-                let mut new_writable_segment =
-                    MutableSegment::new(self.segment_config.clone(), writable_base_directory)?;
-
-                {
-                    // Grab the write lock and swap tmp_segment with mutable_segment
-                    let mut mutable_segment = self.mutable_segment.write().unwrap();
-                    std::mem::swap(&mut *mutable_segment, &mut new_writable_segment);
-                }
+            std::result::Result::Ok(_) => self.flush_segment(),
+            Err(_) => Err(anyhow::anyhow!("Another thread is already flushing")),
+        }
+    }
 
-                let name_for_new_segment = format!("segment_{}", rand::random::<u64>());
-                new_writable_segment
-                    .build(self.base_directory.clone(), name_for_new_segment.clone())?;
-
-                // Read the segment
-                let spann_reader = MultiSpannReader::new(format!(
-                    "{}/{}",
-                    self.base_directory, name_for_new_segment
-                ));
-                match self.segment_config.quantization_type {
-                    QuantizerType::ProductQuantizer => {
-                        let index =
-                            spann_reader.read::<ProductQuantizer<L2DistanceCalculator>>()?;
-                        let segment: Arc<Box<dyn SegmentSearchable + Send + Sync>> =
-                            Arc::new(Box::new(ImmutableSegment::new(index)));
-
-                        self.add_segments(vec![name_for_new_segment], vec![segment])
-                    }
-                    QuantizerType::NoQuantizer => {
-                        let index = spann_reader.read::<NoQuantizer<L2DistanceCalculator>>()?;
-                        let segment: Arc<Box<dyn SegmentSearchable + Send + Sync>> =
-                            Arc::new(Box::new(ImmutableSegment::new(index)));
-
-                        self.add_segments(vec![name_for_new_segment], vec![segment])
-                    }
-                }
+    /// Flushes the mutable segment if it holds `max_segment_vectors` vectors or more. Does
+    /// nothing if another thread is already flushing, since that flush starts a new segment.
+    fn flush_if_full(&self) -> Result<()> {
+        let is_full = || {
+            self.mutable_segment.read().unwrap().num_vectors()
+                >= self.segment_config.max_segment_vectors
+        };
+        if !is_full() {
+            return Ok(());
+        }
+        match self.flushing.try_lock() {
+            // Check again, the segment may have been flushed before we got the lock
+            std::result::Result::Ok(_) if is_full() => self.flush_segment(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the mutable segment, adds it to the TOC and starts a new empty segment. The
+    /// caller must hold the flushing lock.
+    fn flush_segment(&self) -> Result<()> {
+        let tmp_name = format!("tmp_segment_{}", rand::random::<u64>());
+        let writable_base_directory = format!("{}/{}", self.base_directory, tmp_name);
+        let mut new_writable_segment =
+            MutableSegment::new(self.segment_config.clone(), writable_base_directory)?;
+
+        let flushed_wal_versions = {
+            // Grab the write lock and swap tmp_segment with mutable_segment
+            let mut mutable_segment = self.mutable_segment.write().unwrap();
+            std::mem::swap(&mut *mutable_segment, &mut new_writable_segment);
+            // Inserts logged from now on go to the new mutable segment
+            let mut wal = self.wal.lock().unwrap();
+            let new_version = wal.wal.advance_version();
+            std::mem::replace(&mut wal.versions, BTreeSet::from([new_version]))
+        };
+
+        let name_for_new_segment = format!("segment_{}", rand::random::<u64>());
+        new_writable_segment.build(self.base_directory.clone(), name_for_new_segment.clone())?;
+
+        // Read the segment
+        let spann_reader =
+            MultiSpannReader::new(format!("{}/{}", self.base_directory, name_for_new_segment))
+                .with_distance_metric(self.segment_config.distance_metric.clone())
+                .with_vector_cache_capacity(self.segment_config.vector_cache_capacity);
+        match self.segment_config.quantization_type {
+            QuantizerType::ProductQuantizer => {
+                let index = spann_reader.read::<ProductQuantizer<L2DistanceCalculator>>()?;
+                let segment: Arc<Box<dyn SegmentSearchable + Send + Sync>> =
+                    Arc::new(Box::new(ImmutableSegment::new(index)));
+
+                self.add_flushed_segment(name_for_new_segment, segment, &flushed_wal_versions)?;
             }
-            Err(_) => {
-                return Err(anyhow::anyhow!("Another thread is already flushing"));
+            QuantizerType::NoQuantizer => {
+                let index = spann_reader.read::<NoQuantizer<L2DistanceCalculator>>()?;
+                let segment: Arc<Box<dyn SegmentSearchable + Send + Sync>> =
+                    Arc::new(Box::new(ImmutableSegment::new(index)));
+
+                self.add_flushed_segment(name_for_new_segment, segment, &flushed_wal_versions)?;
+            }
+            QuantizerType::ResidualQuantizer
+            | QuantizerType::Sq4
+            | QuantizerType::ScalarQuantizer
+            | QuantizerType::BinaryQuantizer
+            | QuantizerType::RandomProjection { .. } => {
+                return Err(anyhow::anyhow!(
+                    "{:?} is not supported for collections",
+                    self.segment_config.quantization_type
+                ))
             }
         }
+
+        // Drop the records of the flushed inserts, unless the flush of an older version failed:
+        // its records are still needed, they are replayed when the collection is reopened
+        let checkpoint_version = self
+            .versions
+            .get(&self.current_version())
+            .unwrap()
+            .flushed_wal_versions
+            .below;
+        self.wal.lock().unwrap().wal.checkpoint(checkpoint_version)
     }
 
     /// Get a consistent snapshot for the collection
@@ -233,9 +425,13 @@ impl Collection {
             return Err(anyhow::anyhow!("Collection is empty"));
         }
 
-        let toc = latest_version.unwrap().toc.clone();
+        // Expired segments are hidden right away, even before they are purged
+        let toc = latest_version.unwrap().clone();
+        let expired_segments = toc.expired_segments(now_epoch_ms());
         Ok(Snapshot::new(
-            toc.iter()
+            toc.toc
+                .iter()
+                .filter(|name| !expired_segments.contains(name))
                 .map(|name| self.all_segments.get(name).unwrap().clone())
                 .collect(),
             current_version_number,
@@ -243,11 +439,49 @@ impl Collection {
         ))
     }
 
+    /// Searches all segments of the current version for the `k` nearest neighbors of `query`
+    /// among the documents accepted by `filter`, e.g. to restrict results to a time range or a
+    /// tenant, or to hide deleted documents.
+    pub fn search_with_filter(
+        self: Arc<Self>,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        filter: DocIdFilter,
+    ) -> Result<Vec<IdWithScore>> {
+        let snapshot = self.get_snapshot()?;
+        let mut context = SearchContext::new(false);
+        match snapshot.search_with_filter(query, k, ef_search, &filter, &mut context) {
+            Some(results) => Ok(results.0),
+            None => Ok(vec![]),
+        }
+    }
+
     /// Add segments to the collection, effectively creating a new version.
     pub fn add_segments(
         &self,
         names: Vec<String>,
         segments: Vec<Arc<BoxedSegmentSearchable>>,
+    ) -> Result<()> {
+        self.add_segments_with_wal_versions(names, segments, &BTreeSet::new())
+    }
+
+    /// Adds a segment flushed from the mutable segment, which holds the inserts logged with
+    /// `wal_versions`.
+    fn add_flushed_segment(
+        &self,
+        name: String,
+        segment: Arc<BoxedSegmentSearchable>,
+        wal_versions: &BTreeSet<u64>,
+    ) -> Result<()> {
+        self.add_segments_with_wal_versions(vec![name], vec![segment], wal_versions)
+    }
+
+    fn add_segments_with_wal_versions(
+        &self,
+        names: Vec<String>,
+        segments: Vec<Arc<BoxedSegmentSearchable>>,
+        wal_versions: &BTreeSet<u64>,
     ) -> Result<()> {
         for (name, segment) in names.iter().zip(segments) {
             self.all_segments.insert(name.clone(), segment);
@@ -259,14 +493,37 @@ impl Collection {
         // - Insert the new version to the toc
         let mut locked_versions_info = self.versions_info.write().unwrap();
         let current_version = locked_versions_info.current_version;
-        let new_version = current_version + 1;
 
-        let mut new_toc = self.versions.get(&current_version).unwrap().toc.clone();
-        new_toc.extend_from_slice(&names);
+        let mut toc = self.versions.get(&current_version).unwrap().clone();
+        toc.toc.extend_from_slice(&names);
+        let segment_info = SegmentInfo {
+            created_at_epoch_ms: now_epoch_ms(),
+            ttl_ms: self
+                .segment_config
+                .ttl_seconds
+                .map(|ttl_seconds| ttl_seconds.saturating_mul(1000)),
+        };
+        for name in &names {
+            toc.segment_infos.insert(name.clone(), segment_info.clone());
+        }
+        for version in wal_versions {
+            toc.flushed_wal_versions.insert(*version);
+        }
+
+        self.publish_version(&mut locked_versions_info, toc)
+    }
+
+    /// Writes `toc` to disk as the version after the current one, then makes it current. The
+    /// caller must hold the write lock of `versions_info`.
+    fn publish_version(
+        &self,
+        locked_versions_info: &mut VersionsInfo,
+        toc: TableOfContent,
+    ) -> Result<()> {
+        let new_version = locked_versions_info.current_version + 1;
 
         // Write the TOC to disk.
         let toc_path = format!("{}/version_{}", self.base_directory, new_version);
-        let toc = TableOfContent { toc: new_toc };
         serde_json::to_writer(std::fs::File::create(toc_path)?, &toc)?;
 
         // Once success, update the current version and ref counts.
@@ -280,6 +537,192 @@ impl Collection {
         Ok(())
     }
 
+    /// Writes a new version without the expired segments and deletes their files. Returns the
+    /// number of segments purged.
+    ///
+    /// Expired segments are already hidden from new snapshots, so this only reclaims space.
+    pub fn purge_expired_segments(&self) -> Result<usize> {
+        let expired_segments = {
+            let mut locked_versions_info = self.versions_info.write().unwrap();
+            let current_version = locked_versions_info.current_version;
+            let mut toc = self.versions.get(&current_version).unwrap().clone();
+            let expired_segments = toc.expired_segments(now_epoch_ms());
+            if expired_segments.is_empty() {
+                return Ok(0);
+            }
+
+            toc.toc.retain(|name| !expired_segments.contains(name));
+            for name in &expired_segments {
+                toc.segment_infos.remove(name);
+            }
+
+            self.publish_version(&mut locked_versions_info, toc)?;
+            self.retire_segments(locked_versions_info.current_version, &expired_segments);
+            expired_segments
+        };
+
+        self.remove_unreferenced_segments()?;
+        Ok(expired_segments.len())
+    }
+
+    /// Marks the given segments for removal. `version` is the first version without them. The
+    /// caller must hold the write lock of `versions_info`, so that no snapshot of an older
+    /// version is taken in between.
+    fn retire_segments(&self, version: u64, names: &[String]) {
+        self.retired_segments
+            .lock()
+            .unwrap()
+            .extend(names.iter().map(|name| (version, name.clone())));
+    }
+
+    /// Forgets the retired segments that no snapshot can use anymore, and deletes their files.
+    /// Snapshots only take the current version, so a segment can't be used once every version
+    /// older than the one that dropped it is released.
+    fn remove_unreferenced_segments(&self) -> Result<()> {
+        let removable_segments = {
+            let locked_versions_info = self.versions_info.read().unwrap();
+            let is_referenced = |version: u64| {
+                locked_versions_info
+                    .version_ref_counts
+                    .iter()
+                    .any(|(v, count)| *v < version && *count > 0)
+            };
+            let mut retired_segments = self.retired_segments.lock().unwrap();
+            let (referenced, removable): (Vec<_>, Vec<_>) = retired_segments
+                .drain(..)
+                .partition(|(version, _)| is_referenced(*version));
+            *retired_segments = referenced;
+            removable
+        };
+
+        for (_, name) in removable_segments {
+            self.all_segments.remove(&name);
+            let segment_directory = format!("{}/{}", self.base_directory, name);
+            if std::path::Path::new(&segment_directory).exists() {
+                std::fs::remove_dir_all(&segment_directory)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges the two smallest segments of the current version into one, for as long as the
+    /// merge policy of the collection asks for it. Expired segments are never merged.
+    ///
+    /// Every merge creates a new version without the merged segments, and deletes their files.
+    pub fn maybe_merge_segments(&self) -> Result<()> {
+        // Merges don't overlap with each other, nor with flushes
+        let _flushing = self.flushing.lock().unwrap();
+        while let Some((seg_a, seg_b)) = self.next_segments_to_merge() {
+            self.merge_segments(&seg_a, &seg_b)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the two smallest segments of the current version, if the merge policy wants them
+    /// merged.
+    fn next_segments_to_merge(&self) -> Option<(String, String)> {
+        let toc = self.versions.get(&self.current_version()).unwrap().clone();
+        let expired_segments = toc.expired_segments(now_epoch_ms());
+        let mut candidates: Vec<(u64, String)> = toc
+            .toc
+            .into_iter()
+            .filter(|name| !expired_segments.contains(name))
+            .map(|name| (self.all_segments.get(&name).unwrap().size_in_bytes(), name))
+            .collect();
+        candidates.sort();
+
+        match &self.segment_config.merge_policy {
+            SegmentMergePolicy::None => return None,
+            SegmentMergePolicy::MaxSegments { threshold } => {
+                if candidates.len() <= *threshold {
+                    return None;
+                }
+            }
+            SegmentMergePolicy::MaxSegmentSizeBytes { threshold } => {
+                candidates.retain(|(size_in_bytes, _)| *size_in_bytes < *threshold as u64);
+            }
+        }
+
+        let mut candidates = candidates.into_iter().map(|(_, name)| name);
+        Some((candidates.next()?, candidates.next()?))
+    }
+
+    /// Builds a new segment with the documents of both segments, and replaces them with it in a
+    /// new version.
+    fn merge_segments(&self, seg_a: &str, seg_b: &str) -> Result<()> {
+        if self.segment_config.quantization_type != QuantizerType::NoQuantizer {
+            return Err(anyhow::anyhow!(
+                "Merging segments is not supported with {:?}",
+                self.segment_config.quantization_type
+            ));
+        }
+        let read_segment = |name: &str| {
+            MultiSpannReader::new(format!("{}/{}", self.base_directory, name))
+                .with_distance_metric(self.segment_config.distance_metric.clone())
+                .with_vector_cache_capacity(self.segment_config.vector_cache_capacity)
+                .read::<NoQuantizer<L2DistanceCalculator>>()
+        };
+
+        let builder_directory = format!(
+            "{}/tmp_segment_{}",
+            self.base_directory,
+            rand::random::<u64>()
+        );
+        let mut builder = MultiSpannBuilder::merge_from(
+            &read_segment(seg_a)?,
+            &read_segment(seg_b)?,
+            self.segment_config.clone(),
+            builder_directory.clone(),
+        )?;
+        builder.build()?;
+
+        let name_for_new_segment = format!("segment_{}", rand::random::<u64>());
+        let segment_directory = format!("{}/{}", self.base_directory, name_for_new_segment);
+        std::fs::create_dir_all(&segment_directory)?;
+        MultiSpannWriter::new(segment_directory).write(&mut builder)?;
+        if std::path::Path::new(&builder_directory).exists() {
+            std::fs::remove_dir_all(&builder_directory)?;
+        }
+
+        let segment: Arc<BoxedSegmentSearchable> = Arc::new(Box::new(ImmutableSegment::new(
+            read_segment(&name_for_new_segment)?,
+        )));
+        self.all_segments
+            .insert(name_for_new_segment.clone(), segment);
+
+        let merged_segments = vec![seg_a.to_string(), seg_b.to_string()];
+        {
+            let mut locked_versions_info = self.versions_info.write().unwrap();
+            let current_version = locked_versions_info.current_version;
+            let mut toc = self.versions.get(&current_version).unwrap().clone();
+            toc.toc.retain(|name| !merged_segments.contains(name));
+            toc.toc.push(name_for_new_segment.clone());
+
+            // The merged segment expires with the oldest of the segments it replaces
+            let created_at_epoch_ms = merged_segments
+                .iter()
+                .filter_map(|name| toc.segment_infos.remove(name))
+                .map(|info| info.created_at_epoch_ms)
+                .min()
+                .unwrap_or_else(now_epoch_ms);
+            toc.segment_infos.insert(
+                name_for_new_segment,
+                SegmentInfo {
+                    created_at_epoch_ms,
+                    ttl_ms: self
+                        .segment_config
+                        .ttl_seconds
+                        .map(|ttl_seconds| ttl_seconds.saturating_mul(1000)),
+                },
+            );
+
+            self.publish_version(&mut locked_versions_info, toc)?;
+            self.retire_segments(locked_versions_info.current_version, &merged_segments);
+        }
+
+        self.remove_unreferenced_segments()
+    }
+
     pub fn current_version(&self) -> u64 {
         self.versions_info.read().unwrap().current_version
     }
@@ -294,11 +737,20 @@ impl Collection {
             .clone()
     }
 
-    /// Release the ref count for the version once the snapshot is no longer needed.
+    /// Release the ref count for the version once the snapshot is no longer needed. Deletes the
+    /// retired segments that were only kept for this version.
     pub fn release_version(&self, version_number: u64) {
-        let mut lock = self.versions_info.write().unwrap();
-        let count = *lock.version_ref_counts.get(&version_number).unwrap_or(&0);
-        lock.version_ref_counts.insert(version_number, count - 1);
+        let is_released = {
+            let mut lock = self.versions_info.write().unwrap();
+            let count = *lock.version_ref_counts.get(&version_number).unwrap_or(&0);
+            lock.version_ref_counts.insert(version_number, count - 1);
+            count == 1
+        };
+        if is_released {
+            if let Err(e) = self.remove_unreferenced_segments() {
+                warn!("Failed to remove retired segments: {}", e);
+            }
+        }
     }
 
     /// This is thread-safe, and will increment the ref count for the version.
@@ -318,59 +770,120 @@ impl Collection {
             .map(|pair| pair.key().clone())
             .collect()
     }
-}
-
-// Test
-#[cfg(test)]
-mod tests {
-
-    use std::sync::atomic::AtomicBool;
-    use std::sync::Arc;
 
-    use anyhow::{Ok, Result};
-    use config::collection::CollectionConfig;
-    use tempdir::TempDir;
+    pub fn get_segment(&self, name: &str) -> Option<Arc<BoxedSegmentSearchable>> {
+        self.all_segments.get(name).map(|pair| pair.value().clone())
+    }
 
-    use super::SegmentSearchable;
-    use crate::collection::{BoxedSegmentSearchable, Collection};
-    use crate::index::Searchable;
-    use crate::segment::Segment;
+    pub fn num_segments(&self) -> usize {
+        self.all_segments.len()
+    }
 
-    struct MockSearchable {}
+    /// Returns the total number of vectors in all segments.
+    pub fn num_vectors(&self) -> u64 {
+        self.all_segments
+            .iter()
+            .map(|pair| pair.value().num_vectors())
+            .sum()
+    }
 
-    impl MockSearchable {
-        pub fn new() -> Self {
-            Self {}
-        }
+    /// Returns the size of all segments on disk, in bytes.
+    pub fn size_in_bytes(&self) -> u64 {
+        self.all_segments
+            .iter()
+            .map(|pair| pair.value().size_in_bytes())
+            .sum()
     }
 
-    impl SegmentSearchable for MockSearchable {}
+    /// Returns the access statistics of the given user, combined over all segments, or None if
+    /// the user was never queried.
+    pub fn user_stats(&self, user_id: u128) -> Option<UserStats> {
+        self.all_segments
+            .iter()
+            .filter_map(|pair| pair.value().user_stats(user_id))
+            .reduce(|mut stats, other| {
+                stats.merge(&other);
+                stats
+            })
+    }
 
-    impl Segment for MockSearchable {
-        fn insert(&mut self, _doc_id: u64, _data: &[f32]) -> Result<()> {
-            todo!()
+    /// Returns the access statistics summed over all segments.
+    pub fn global_stats(&self) -> GlobalStats {
+        let mut global_stats = GlobalStats::default();
+        for pair in self.all_segments.iter() {
+            global_stats.merge(&pair.value().global_stats());
         }
+        global_stats
+    }
 
-        fn remove(&mut self, _doc_id: u64) -> Result<bool> {
-            todo!()
-        }
+    /// Writes the config, the current TOC and every file of the current segments into a single
+    /// file at `destination_path`, which `CollectionReader::import` can unpack elsewhere.
+    pub fn export(&self, destination_path: &str) -> Result<ExportManifest> {
+        // Hold a reference on the version so that its segments stay around during the export
+        let version = self.get_current_version_and_increment();
+        let result = self.export_version(version, destination_path);
+        self.release_version(version);
+        result
+    }
 
-        fn may_contains(&self, _doc_id: u64) -> bool {
-            todo!()
+    fn export_version(&self, version: u64, destination_path: &str) -> Result<ExportManifest> {
+        let toc = self
+            .versions
+            .get(&version)
+            .ok_or_else(|| anyhow::anyhow!("Version {} not found", version))?
+            .clone();
+
+        let mut sections = vec![
+            (
+                "collection_config.json".to_string(),
+                SectionSource::Bytes(serde_json::to_vec_pretty(&self.segment_config)?),
+            ),
+            (
+                format!("version_{}", version),
+                SectionSource::Bytes(serde_json::to_vec_pretty(&toc)?),
+            ),
+        ];
+        for name in &toc.toc {
+            let segment_directory = std::path::Path::new(&self.base_directory).join(name);
+            sections.extend(
+                export::list_files(&segment_directory, name)?
+                    .into_iter()
+                    .map(|(path, file)| (path, SectionSource::File(file))),
+            );
         }
+        export::write_export(destination_path, version, sections)
     }
 
-    impl Searchable for MockSearchable {
-        fn search(
-            &self,
-            _query: &[f32],
-            _k: usize,
-            _ef_construction: u32,
-            _context: &mut crate::utils::SearchContext,
-        ) -> Option<Vec<crate::utils::IdWithScore>> {
-            todo!()
-        }
+    /// Returns the name and description of every segment, sorted by name.
+    pub fn describe_segments(&self) -> Vec<(String, String)> {
+        let mut descriptions: Vec<(String, String)> = self
+            .all_segments
+            .iter()
+            .map(|pair| (pair.key().clone(), pair.value().describe()))
+            .collect();
+        descriptions.sort();
+        descriptions
     }
+}
+
+// Test
+#[cfg(test)]
+mod tests {
+
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use anyhow::{Ok, Result};
+    use config::collection::{CollectionConfig, SegmentMergePolicy};
+    use tempdir::TempDir;
+
+    use crate::collection::reader::CollectionReader;
+    use crate::collection::{
+        BoxedSegmentSearchable, Collection, FlushedWalVersions, SegmentInfo, TableOfContent,
+    };
+    use crate::index::{DocIdFilter, Searchable};
+    use crate::mock::MockSearchable;
+    use crate::utils::{IdWithScore, SearchContext};
 
     #[test]
     fn test_collection() -> Result<()> {
@@ -380,8 +893,10 @@ mod tests {
         let collection = Arc::new(Collection::new(base_directory.clone(), segment_config).unwrap());
 
         {
-            let segment1: Arc<BoxedSegmentSearchable> = Arc::new(Box::new(MockSearchable::new()));
-            let segment2: Arc<BoxedSegmentSearchable> = Arc::new(Box::new(MockSearchable::new()));
+            let segment1: Arc<BoxedSegmentSearchable> =
+                Arc::new(Box::new(MockSearchable::returning(vec![])));
+            let segment2: Arc<BoxedSegmentSearchable> =
+                Arc::new(Box::new(MockSearchable::returning(vec![])));
 
             collection
                 .add_segments(
@@ -417,8 +932,8 @@ mod tests {
                 .add_segments(
                     vec!["segment3".to_string(), "segment4".to_string()],
                     vec![
-                        Arc::new(Box::new(MockSearchable::new())),
-                        Arc::new(Box::new(MockSearchable::new())),
+                        Arc::new(Box::new(MockSearchable::returning(vec![]))),
+                        Arc::new(Box::new(MockSearchable::returning(vec![]))),
                     ],
                 )
                 .unwrap();
@@ -439,6 +954,364 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_collection_search_with_filter() -> Result<()> {
+        let temp_dir = TempDir::new("test_collection_search_with_filter")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let segment_config = CollectionConfig::default_test_config();
+        let collection = Arc::new(Collection::new(base_directory.clone(), segment_config).unwrap());
+
+        let segment1: Arc<BoxedSegmentSearchable> =
+            Arc::new(Box::new(MockSearchable::returning(vec![
+                IdWithScore { id: 1, score: 0.1 },
+                IdWithScore { id: 2, score: 0.2 },
+                IdWithScore { id: 3, score: 0.5 },
+            ])));
+        let segment2: Arc<BoxedSegmentSearchable> =
+            Arc::new(Box::new(MockSearchable::returning(vec![
+                IdWithScore { id: 4, score: 0.3 },
+                IdWithScore { id: 5, score: 0.4 },
+            ])));
+        collection.add_segments(
+            vec!["segment1".to_string(), "segment2".to_string()],
+            vec![segment1, segment2],
+        )?;
+
+        // Results of both segments are merged after filtering out even doc ids
+        let results = collection.clone().search_with_filter(
+            &[1.0, 2.0, 3.0, 4.0],
+            2,
+            10,
+            Arc::new(|doc_id| doc_id % 2 == 1),
+        )?;
+        assert_eq!(
+            results,
+            vec![
+                IdWithScore { id: 1, score: 0.1 },
+                IdWithScore { id: 5, score: 0.4 },
+            ]
+        );
+
+        // The snapshot used by the search is released
+        assert_eq!(collection.get_ref_count(collection.current_version()), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_stats() -> Result<()> {
+        let temp_dir = TempDir::new("test_collection_stats")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let segment_config = CollectionConfig::default_test_config();
+        Collection::init_new_collection(base_directory.clone(), &segment_config)?;
+        let collection = Arc::new(Collection::new(base_directory.clone(), segment_config)?);
+        assert_eq!(collection.num_segments(), 0);
+        assert_eq!(collection.num_vectors(), 0);
+        assert_eq!(collection.size_in_bytes(), 0);
+
+        for i in 0..10 {
+            let v = i as f32;
+            collection.insert(i, &[v, v + 1.0, v + 2.0, v + 3.0])?;
+        }
+        collection.flush()?;
+        assert_eq!(collection.num_segments(), 1);
+        assert_eq!(collection.num_vectors(), 10);
+        assert!(collection.size_in_bytes() > 0);
+        assert!(collection.user_stats(0).is_none());
+
+        let snapshot = collection.clone().get_snapshot()?;
+        let mut context = SearchContext::new(false);
+        for _ in 0..2 {
+            assert!(snapshot
+                .search_for_ids(&[0], &[1.0, 2.0, 3.0, 4.0], 3, 10, &mut context)
+                .is_some());
+        }
+        drop(snapshot);
+        let user_stats = collection.user_stats(0).unwrap();
+        assert_eq!(user_stats.query_count, 2);
+        // num_vectors() above already loaded the index of the user
+        assert_eq!(user_stats.cache_hits, 2);
+        assert_eq!(user_stats.cache_misses, 0);
+        assert!(user_stats.last_accessed_epoch_ms > 0);
+        assert!(collection.user_stats(1).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_segment_ttl() -> Result<()> {
+        let temp_dir = TempDir::new("test_collection_segment_ttl")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let segment_config = CollectionConfig {
+            ttl_seconds: Some(0),
+            ..CollectionConfig::default_test_config()
+        };
+        Collection::init_new_collection(base_directory.clone(), &segment_config)?;
+        let collection = Arc::new(Collection::new(base_directory.clone(), segment_config)?);
+        for i in 0..10 {
+            let v = i as f32;
+            collection.insert(i, &[v, v + 1.0, v + 2.0, v + 3.0])?;
+        }
+        collection.flush()?;
+        let segment_name = collection.get_all_segment_names()[0].clone();
+        assert_eq!(collection.num_segments(), 1);
+
+        // The segment expired right away, so it is hidden from snapshots
+        let snapshot = collection.clone().get_snapshot()?;
+        assert!(snapshot.segments.is_empty());
+        drop(snapshot);
+
+        assert_eq!(collection.purge_expired_segments()?, 1);
+        assert_eq!(collection.num_segments(), 0);
+        assert_eq!(collection.current_version(), 2);
+        assert!(!std::path::Path::new(&format!("{}/{}", base_directory, segment_name)).exists());
+        let toc: TableOfContent = serde_json::from_reader(std::fs::File::open(format!(
+            "{}/version_2",
+            base_directory
+        ))?)?;
+        assert!(toc.toc.is_empty());
+        assert_eq!(collection.purge_expired_segments()?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_auto_flush() -> Result<()> {
+        let temp_dir = TempDir::new("test_collection_auto_flush")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let segment_config = CollectionConfig {
+            max_segment_vectors: 10,
+            ..CollectionConfig::default_test_config()
+        };
+        Collection::init_new_collection(base_directory.clone(), &segment_config)?;
+        let collection = Collection::new(base_directory.clone(), segment_config)?;
+        for i in 0..25 {
+            let v = i as f32;
+            collection.insert(i, &[v, v + 1.0, v + 2.0, v + 3.0])?;
+        }
+
+        // Two full segments were flushed, the last 5 vectors are still in the mutable segment
+        assert_eq!(collection.num_segments(), 2);
+        assert_eq!(collection.current_version(), 2);
+        assert_eq!(collection.num_vectors(), 20);
+        assert_eq!(collection.mutable_segment.read().unwrap().num_vectors(), 5);
+
+        // Default config never flushes on its own
+        let temp_dir = TempDir::new("test_collection_auto_flush_default")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let collection = Collection::new(base_directory, CollectionConfig::default_test_config())?;
+        for i in 0..25 {
+            let v = i as f32;
+            collection.insert(i, &[v, v + 1.0, v + 2.0, v + 3.0])?;
+        }
+        assert_eq!(collection.num_segments(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_wal_replay() -> Result<()> {
+        let temp_dir = TempDir::new("test_collection_wal_replay")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let segment_config = CollectionConfig::default_test_config();
+        Collection::init_new_collection(base_directory.clone(), &segment_config)?;
+        let vectors: Vec<Vec<f32>> = (0..20)
+            .map(|i| {
+                let v = i as f32 * 10.0;
+                vec![v, v + 1.0, v + 2.0, v + 3.0]
+            })
+            .collect();
+        {
+            let collection = Collection::new(base_directory.clone(), segment_config.clone())?;
+            for (i, vector) in vectors.iter().enumerate() {
+                collection.insert(i as u128, vector)?;
+            }
+            // Dropped without flushing, as if the process crashed
+        }
+
+        let collection = Arc::new(Collection::new(
+            base_directory.clone(),
+            segment_config.clone(),
+        )?);
+        assert_eq!(collection.mutable_segment.read().unwrap().num_vectors(), 20);
+        collection.flush()?;
+        assert_eq!(collection.num_vectors(), 20);
+        for (i, vector) in vectors.iter().enumerate() {
+            let results =
+                collection
+                    .clone()
+                    .search_with_filter(vector, 1, 100, Arc::new(|_| true))?;
+            assert_eq!(results[0].id, i as u128);
+        }
+
+        // The flush checkpointed the log, so nothing is replayed anymore
+        let collection = Collection::new(base_directory, segment_config)?;
+        assert_eq!(collection.mutable_segment.read().unwrap().num_vectors(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_wal_replay_skips_flushed_versions() -> Result<()> {
+        let temp_dir = TempDir::new("test_collection_wal_replay_skips_flushed_versions")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let segment_config = CollectionConfig::default_test_config();
+        Collection::init_new_collection(base_directory.clone(), &segment_config)?;
+        let wal_path = crate::wal::Wal::path(&base_directory);
+        {
+            let collection = Collection::new(base_directory.clone(), segment_config.clone())?;
+            for i in 0..10 {
+                let v = i as f32;
+                collection.insert(i, &[v, v + 1.0, v + 2.0, v + 3.0])?;
+            }
+            // Simulate a crash between publishing the new TOC and checkpointing the log
+            let wal_before_flush = std::fs::read(&wal_path)?;
+            collection.flush()?;
+            std::fs::write(&wal_path, wal_before_flush)?;
+        }
+
+        // The flushed inserts are not replayed again
+        let collection = CollectionReader::new(base_directory.clone()).read()?;
+        assert_eq!(collection.num_vectors(), 10);
+        assert_eq!(collection.mutable_segment.read().unwrap().num_vectors(), 0);
+
+        // New inserts are not mistaken for flushed ones
+        collection.insert(10, &[10.0, 11.0, 12.0, 13.0])?;
+        drop(collection);
+        let collection = CollectionReader::new(base_directory).read()?;
+        assert_eq!(collection.mutable_segment.read().unwrap().num_vectors(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flushed_wal_versions() {
+        let mut flushed = FlushedWalVersions::default();
+        assert!(!flushed.contains(0));
+
+        // The flush of version 1 failed, version 2 succeeded
+        flushed.insert(0);
+        flushed.insert(2);
+        assert_eq!(flushed.below, 1);
+        assert!(flushed.contains(0));
+        assert!(!flushed.contains(1));
+        assert!(flushed.contains(2));
+
+        // Once version 1 is flushed, the log can be checkpointed past version 2
+        flushed.insert(1);
+        assert_eq!(flushed.below, 3);
+        assert!(flushed.above.is_empty());
+    }
+
+    #[test]
+    fn test_collection_merge_segments() -> Result<()> {
+        let temp_dir = TempDir::new("test_collection_merge_segments")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let segment_config = CollectionConfig {
+            merge_policy: SegmentMergePolicy::MaxSegments { threshold: 1 },
+            ..CollectionConfig::default_test_config()
+        };
+        Collection::init_new_collection(base_directory.clone(), &segment_config)?;
+        let collection = Arc::new(Collection::new(base_directory.clone(), segment_config)?);
+        let vectors: Vec<Vec<f32>> = (0..50)
+            .map(|i| {
+                let v = i as f32 * 10.0;
+                vec![v, v + 1.0, v + 2.0, v + 3.0]
+            })
+            .collect();
+        for (chunk_index, chunk) in vectors.chunks(10).enumerate() {
+            for (i, vector) in chunk.iter().enumerate() {
+                collection.insert((chunk_index * 10 + i) as u128, vector)?;
+            }
+            collection.flush()?;
+        }
+        let old_segments = collection.get_all_segment_names();
+        assert_eq!(old_segments.len(), 5);
+        assert_eq!(collection.current_version(), 5);
+
+        // Every merge of two segments creates a version
+        collection.maybe_merge_segments()?;
+        assert_eq!(collection.num_segments(), 1);
+        assert_eq!(collection.current_version(), 9);
+        assert_eq!(collection.num_vectors(), 50);
+        let toc: TableOfContent = serde_json::from_reader(std::fs::File::open(format!(
+            "{}/version_9",
+            base_directory
+        ))?)?;
+        assert_eq!(toc.toc, collection.get_all_segment_names());
+        for name in &old_segments {
+            assert!(!std::path::Path::new(&format!("{}/{}", base_directory, name)).exists());
+        }
+        for (i, vector) in vectors.iter().enumerate() {
+            let results =
+                collection
+                    .clone()
+                    .search_with_filter(vector, 1, 100, Arc::new(|_| true))?;
+            assert_eq!(results[0].id, i as u128);
+        }
+
+        // Nothing left to merge
+        collection.maybe_merge_segments()?;
+        assert_eq!(collection.current_version(), 9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_merge_segments_with_snapshot() -> Result<()> {
+        let temp_dir = TempDir::new("test_collection_merge_segments_with_snapshot")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let segment_config = CollectionConfig {
+            merge_policy: SegmentMergePolicy::MaxSegments { threshold: 1 },
+            ..CollectionConfig::default_test_config()
+        };
+        Collection::init_new_collection(base_directory.clone(), &segment_config)?;
+        let collection = Arc::new(Collection::new(base_directory.clone(), segment_config)?);
+        for i in 0..20 {
+            let v = i as f32 * 10.0;
+            collection.insert(i, &[v, v + 1.0, v + 2.0, v + 3.0])?;
+            if i % 10 == 9 {
+                collection.flush()?;
+            }
+        }
+        let old_segments = collection.get_all_segment_names();
+
+        // The merged segments are kept while a snapshot of an older version uses them
+        let snapshot = collection.clone().get_snapshot()?;
+        collection.maybe_merge_segments()?;
+        for name in &old_segments {
+            assert!(std::path::Path::new(&format!("{}/{}", base_directory, name)).exists());
+        }
+        let mut context = SearchContext::new(false);
+        let filter: DocIdFilter = Arc::new(|_| true);
+        let results = snapshot
+            .search_with_filter(&[10.0, 11.0, 12.0, 13.0], 1, 100, &filter, &mut context)
+            .unwrap();
+        assert_eq!(results.0[0].id, 1);
+        // New snapshots only see the merged segment
+        assert_eq!(collection.clone().get_snapshot()?.segments.len(), 1);
+
+        drop(snapshot);
+        assert_eq!(collection.num_segments(), 1);
+        for name in &old_segments {
+            assert!(!std::path::Path::new(&format!("{}/{}", base_directory, name)).exists());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_segment_info() {
+        let info = SegmentInfo {
+            created_at_epoch_ms: 1000,
+            ttl_ms: Some(500),
+        };
+        assert!(!info.is_expired(1499));
+        assert!(info.is_expired(1500));
+        let info = SegmentInfo {
+            ttl_ms: None,
+            ..info
+        };
+        assert!(!info.is_expired(u64::MAX));
+
+        // TOCs written before segment infos existed never expire
+        let toc: TableOfContent = serde_json::from_str(r#"{"toc": ["segment1"]}"#).unwrap();
+        assert!(toc.segment_infos.is_empty());
+        assert!(toc.expired_segments(u64::MAX).is_empty());
+    }
+
     #[test]
     fn test_collection_multi_thread() -> Result<()> {
         let temp_dir = TempDir::new("test_collection")?;
@@ -452,8 +1325,10 @@ mod tests {
         let stopped_cpy = stopped.clone();
         let collection_cpy = collection.clone();
         std::thread::spawn(move || {
-            let segment1: Arc<BoxedSegmentSearchable> = Arc::new(Box::new(MockSearchable::new()));
-            let segment2: Arc<BoxedSegmentSearchable> = Arc::new(Box::new(MockSearchable::new()));
+            let segment1: Arc<BoxedSegmentSearchable> =
+                Arc::new(Box::new(MockSearchable::returning(vec![])));
+            let segment2: Arc<BoxedSegmentSearchable> =
+                Arc::new(Box::new(MockSearchable::returning(vec![])));
 
             collection_cpy
                 .add_segments(
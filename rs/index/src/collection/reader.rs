@@ -1,13 +1,15 @@
 use std::sync::Arc;
 
 use anyhow::{Ok, Result};
-use config::collection::CollectionConfig;
+use config::collection::{CollectionConfig, SegmentWarmupStrategy};
 use config::enums::QuantizerType;
+use log::{info, log_enabled, Level};
 use quantization::noq::noq::NoQuantizer;
 use quantization::pq::pq::ProductQuantizer;
 use utils::distance::l2::L2DistanceCalculator;
 use utils::io::get_latest_version;
 
+use super::export::read_export;
 use super::{Collection, TableOfContent};
 use crate::collection::BoxedSegmentSearchable;
 use crate::multi_spann::reader::MultiSpannReader;
@@ -22,6 +24,38 @@ impl CollectionReader {
         Self { path }
     }
 
+    /// Unpacks a file written by `Collection::export` into `destination_dir`, verifying every
+    /// section, and reads the collection from there.
+    pub fn import(source_path: &str, destination_dir: &str) -> Result<Arc<Collection>> {
+        read_export(source_path, destination_dir)?;
+        CollectionReader::new(destination_dir.to_string()).read()
+    }
+
+    /// Same as `read`, on a blocking thread so that reading and mapping the segments doesn't
+    /// block the executor. With `SegmentWarmupStrategy::Prefetch`, every segment is then warmed
+    /// up in a background task.
+    pub async fn read_async(&self) -> Result<Arc<Collection>> {
+        let reader = CollectionReader::new(self.path.clone());
+        let collection = tokio::task::spawn_blocking(move || reader.read()).await??;
+
+        if let SegmentWarmupStrategy::Prefetch { num_vectors } = *collection.warmup_strategy() {
+            for name in collection.get_all_segment_names() {
+                let Some(segment) = collection.get_segment(&name) else {
+                    continue;
+                };
+                let path = self.path.clone();
+                tokio::task::spawn_blocking(move || {
+                    let num_read = segment.prefetch(num_vectors);
+                    info!(
+                        "Prefetched {} vectors of segment {} of {}",
+                        num_read, name, path
+                    );
+                });
+            }
+        }
+        Ok(collection)
+    }
+
     pub fn read(&self) -> Result<Arc<Collection>> {
         // Read the SpannBuilderConfig
         let spann_builder_config_path = format!("{}/collection_config.json", self.path);
@@ -37,7 +71,9 @@ impl CollectionReader {
         let mut segments: Vec<Arc<BoxedSegmentSearchable>> = vec![];
         for name in &toc.toc {
             let spann_path = format!("{}/{}", self.path, name);
-            let spann_reader = MultiSpannReader::new(spann_path);
+            let spann_reader = MultiSpannReader::new(spann_path)
+                .with_distance_metric(collection_config.distance_metric.clone())
+                .with_vector_cache_capacity(collection_config.vector_cache_capacity);
             match collection_config.quantization_type {
                 QuantizerType::ProductQuantizer => {
                     let index = spann_reader.read::<ProductQuantizer<L2DistanceCalculator>>()?;
@@ -47,6 +83,16 @@ impl CollectionReader {
                     let index = spann_reader.read::<NoQuantizer<L2DistanceCalculator>>()?;
                     segments.push(Arc::new(Box::new(ImmutableSegment::new(index))));
                 }
+                QuantizerType::ResidualQuantizer
+                | QuantizerType::Sq4
+                | QuantizerType::ScalarQuantizer
+                | QuantizerType::BinaryQuantizer
+                | QuantizerType::RandomProjection { .. } => {
+                    return Err(anyhow::anyhow!(
+                        "{:?} is not supported for collections",
+                        collection_config.quantization_type
+                    ));
+                }
             };
         }
 
@@ -57,6 +103,12 @@ impl CollectionReader {
             segments,
             collection_config,
         )?);
+
+        if log_enabled!(Level::Info) {
+            for (name, description) in collection.describe_segments() {
+                info!("Segment {} of {}:\n{}", name, self.path, description);
+            }
+        }
         Ok(collection)
     }
 }
@@ -64,6 +116,8 @@ impl CollectionReader {
 // TODO(hicder): Add tests once I write builder and writer for SPANN.
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     use anyhow::Result;
     use config::collection::CollectionConfig;
     use tempdir::TempDir;
@@ -72,6 +126,8 @@ mod tests {
     use super::*;
     use crate::multi_spann::builder::MultiSpannBuilder;
     use crate::multi_spann::writer::MultiSpannWriter;
+    use crate::segment::Segment;
+    use crate::utils::SearchContext;
 
     fn collection_config() -> CollectionConfig {
         CollectionConfig::default_test_config()
@@ -101,14 +157,10 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_reader() {
-        let temp_dir = TempDir::new("test_reader").unwrap();
-        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
-
+    /// Writes a collection with 2 segments to `base_directory`.
+    fn write_collection(base_directory: &str, collection_config: &CollectionConfig) {
         // Write the collection config
         let collection_config_path = format!("{}/collection_config.json", base_directory);
-        let collection_config = collection_config();
         serde_json::to_writer(
             std::fs::File::create(collection_config_path).unwrap(),
             &collection_config,
@@ -133,6 +185,13 @@ mod tests {
         let toc_path = format!("{}/version_1", base_directory);
         let toc = TableOfContent::new(vec!["segment1".to_string(), "segment2".to_string()]);
         serde_json::to_writer(std::fs::File::create(toc_path).unwrap(), &toc).unwrap();
+    }
+
+    #[test]
+    fn test_reader() {
+        let temp_dir = TempDir::new("test_reader").unwrap();
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        write_collection(&base_directory, &collection_config());
 
         let reader = CollectionReader::new(base_directory.clone());
         let collection = reader.read().unwrap();
@@ -143,5 +202,105 @@ mod tests {
         // Get current snapshot
         let snapshot = collection.get_snapshot().unwrap();
         assert_eq!(snapshot.segments.len(), 2);
+
+        // The distance metric of the config is used to read the segments
+        let collection_config_path = format!("{}/collection_config.json", base_directory);
+        let json = std::fs::read_to_string(&collection_config_path).unwrap();
+        assert!(json.contains("\"distance_metric\":\"L2\""));
+        std::fs::write(
+            &collection_config_path,
+            json.replace("\"L2\"", "\"DotProduct\""),
+        )
+        .unwrap();
+        assert!(reader.read().is_err());
+
+        // Configs written before the distance metric existed use L2
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value.as_object_mut().unwrap().remove("distance_metric");
+        std::fs::write(&collection_config_path, value.to_string()).unwrap();
+        assert!(reader.read().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_read_async() {
+        let temp_dir = TempDir::new("test_read_async").unwrap();
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let collection_config = CollectionConfig {
+            warmup_strategy: SegmentWarmupStrategy::Prefetch { num_vectors: 100 },
+            ..collection_config()
+        };
+        write_collection(&base_directory, &collection_config);
+
+        // The test runtime has a single thread, which keeps running other tasks while the
+        // segments are read
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = tokio::spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        let collection = CollectionReader::new(base_directory)
+            .read_async()
+            .await
+            .unwrap();
+        ticker.abort();
+        assert!(ticks.load(Ordering::Relaxed) > 0);
+
+        assert_eq!(collection.current_version(), 1);
+        assert_eq!(
+            collection.get_segment("segment1").unwrap().prefetch(100),
+            100
+        );
+        let snapshot = collection.get_snapshot().unwrap();
+        let results = snapshot
+            .search_for_ids(
+                &[0],
+                &generate_random_vector(4),
+                5,
+                10,
+                &mut SearchContext::new(false),
+            )
+            .unwrap();
+        assert_eq!(results.len(), 5);
+    }
+
+    #[test]
+    fn test_export_import() -> Result<()> {
+        let temp_dir = TempDir::new("test_export_import")?;
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+        let source_directory = format!("{}/source", base_directory);
+        let collection_config = collection_config();
+        Collection::init_new_collection(source_directory.clone(), &collection_config)?;
+        let collection = Collection::new(source_directory.clone(), collection_config)?;
+        for i in 0..10 {
+            let v = i as f32;
+            collection.insert(i, &[v, v + 1.0, v + 2.0, v + 3.0])?;
+        }
+        collection.flush()?;
+
+        let export_path = format!("{}/collection.export", base_directory);
+        let manifest = collection.export(&export_path)?;
+        assert_eq!(manifest.version, collection.current_version());
+        assert_eq!(manifest.sections[0].path, "collection_config.json");
+        assert_eq!(
+            manifest.sections[1].path,
+            format!("version_{}", manifest.version)
+        );
+        assert!(manifest.sections.len() > 2);
+        assert_eq!(collection.get_ref_count(manifest.version), 0);
+
+        let destination_directory = format!("{}/destination", base_directory);
+        let imported = CollectionReader::import(&export_path, &destination_directory)?;
+        assert_eq!(imported.current_version(), manifest.version);
+        assert_eq!(
+            imported.get_all_segment_names(),
+            collection.get_all_segment_names()
+        );
+        assert_eq!(imported.num_vectors(), 10);
+        Ok(())
     }
 }
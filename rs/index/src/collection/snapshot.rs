@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use super::{BoxedSegmentSearchable, Collection};
-use crate::index::Searchable;
-use crate::utils::{IdWithScore, SearchContext};
+use crate::index::{DocIdFilter, Searchable};
+use crate::utils::{IdWithScore, SearchContext, SearchResult};
 
 /// Snapshot provides a view of the collection at a given point in time
 pub struct Snapshot {
@@ -33,12 +33,12 @@ impl Snapshot {
         ids: &[u128],
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>> {
+    ) -> Option<SearchResult> {
         let mut results: Vec<IdWithScore> = vec![];
         for id in ids {
-            match self.search_with_id(*id, query, k, ef_construction, context) {
+            match self.search_with_id(*id, query, k, ef_search, context) {
                 Some(id_results) => {
                     results.extend(id_results);
                 }
@@ -49,7 +49,7 @@ impl Snapshot {
         results.sort();
         results.truncate(k);
 
-        Some(results)
+        Some(results.into())
     }
 }
 
@@ -60,15 +60,15 @@ impl Searchable for Snapshot {
         id: u128,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>> {
+    ) -> Option<SearchResult> {
         // Query each index, then take the top k results
         // TODO(hicder): Handle case where docs are deleted in later segments
         let mut scored_results: Vec<_> = self
             .segments
             .iter()
-            .filter_map(|index| index.search_with_id(id, query, k, ef_construction, context))
+            .filter_map(|index| index.search_with_id(id, query, k, ef_search, context))
             .flat_map(|results| results.into_iter().map(|id_score| id_score))
             .collect();
 
@@ -76,17 +76,36 @@ impl Searchable for Snapshot {
         scored_results.sort_by(|x, y| x.cmp(y));
         scored_results.truncate(k);
 
-        Some(scored_results)
+        Some(scored_results.into())
     }
 
     fn search(
         &self,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>> {
-        self.search_with_id(0u128, query, k, ef_construction, context)
+    ) -> Option<SearchResult> {
+        self.search_with_id(0u128, query, k, ef_search, context)
+    }
+
+    fn search_with_filter(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        filter: &DocIdFilter,
+        context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        // Each segment returns its own filtered top k, then we merge them.
+        let scored_results: SearchResult = self
+            .segments
+            .iter()
+            .filter_map(|index| index.search_with_filter(query, k, ef_search, filter, context))
+            .flat_map(|results| results.0)
+            .collect();
+
+        Some(scored_results.top_k(k))
     }
 }
 
@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+
+use crate::index::Searchable;
+use crate::utils::{SearchContext, SearchResult};
+
+/// Summary of how much the results of two indexes agree on the same queries.
+#[derive(Debug)]
+pub struct IndexDiff {
+    /// Average Jaccard similarity of the top-k doc id sets.
+    pub mean_jaccard_similarity: f32,
+
+    /// Pearson correlation of the first result's score. NaN if it can't be computed
+    /// (less than 2 queries with results from both indexes, or constant scores).
+    pub score_correlation: f32,
+
+    /// Indices of the queries whose top-1 result differs.
+    pub queries_with_disagreement: Vec<usize>,
+}
+
+/// Run `queries` against both indexes and compare the results.
+/// Useful to verify that a re-indexed or migrated collection produces equivalent results.
+pub fn diff_indexes(
+    a: &dyn Searchable,
+    b: &dyn Searchable,
+    queries: &[Vec<f32>],
+    k: usize,
+    ef_search: u32,
+) -> IndexDiff {
+    let mut total_jaccard_similarity = 0.0;
+    let mut first_scores = vec![];
+    let mut queries_with_disagreement = vec![];
+
+    for (query_idx, query) in queries.iter().enumerate() {
+        let results_a = a
+            .search(query, k, ef_search, &mut SearchContext::new(false))
+            .unwrap_or_default();
+        let results_b = b
+            .search(query, k, ef_search, &mut SearchContext::new(false))
+            .unwrap_or_default();
+
+        total_jaccard_similarity += jaccard_similarity(&results_a, &results_b);
+
+        match (results_a.first(), results_b.first()) {
+            (Some(first_a), Some(first_b)) => {
+                if first_a.id != first_b.id {
+                    queries_with_disagreement.push(query_idx);
+                }
+                first_scores.push((first_a.score, first_b.score));
+            }
+            (None, None) => {}
+            _ => queries_with_disagreement.push(query_idx),
+        }
+    }
+
+    let mean_jaccard_similarity = if queries.is_empty() {
+        1.0
+    } else {
+        total_jaccard_similarity / queries.len() as f32
+    };
+
+    IndexDiff {
+        mean_jaccard_similarity,
+        score_correlation: pearson_correlation(&first_scores),
+        queries_with_disagreement,
+    }
+}
+
+fn jaccard_similarity(a: &SearchResult, b: &SearchResult) -> f32 {
+    let ids_a: HashSet<u128> = a.iter().map(|x| x.id).collect();
+    let ids_b: HashSet<u128> = b.iter().map(|x| x.id).collect();
+    let union = ids_a.union(&ids_b).count();
+    if union == 0 {
+        // Two empty results agree
+        return 1.0;
+    }
+    ids_a.intersection(&ids_b).count() as f32 / union as f32
+}
+
+fn pearson_correlation(pairs: &[(f32, f32)]) -> f32 {
+    if pairs.len() < 2 {
+        return f32::NAN;
+    }
+
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(x, _)| *x as f64).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| *y as f64).sum::<f64>() / n;
+    let mut covariance = 0.0;
+    let mut variance_x = 0.0;
+    let mut variance_y = 0.0;
+    for (x, y) in pairs {
+        let dx = *x as f64 - mean_x;
+        let dy = *y as f64 - mean_y;
+        covariance += dx * dy;
+        variance_x += dx * dx;
+        variance_y += dy * dy;
+    }
+
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return f32::NAN;
+    }
+    (covariance / (variance_x * variance_y).sqrt()) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::IdWithScore;
+
+    /// Returns the ids in `ids` as results, with the query's first component added to the score
+    struct FixedSearchable {
+        ids: Vec<u128>,
+    }
+
+    impl Searchable for FixedSearchable {
+        fn search(
+            &self,
+            query: &[f32],
+            k: usize,
+            _ef_search: u32,
+            _context: &mut SearchContext,
+        ) -> Option<SearchResult> {
+            Some(
+                self.ids
+                    .iter()
+                    .take(k)
+                    .enumerate()
+                    .map(|(i, id)| IdWithScore {
+                        id: *id,
+                        score: query[0] + i as f32,
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_diff_identical_indexes() {
+        let a = FixedSearchable { ids: vec![1, 2, 3] };
+        let queries = vec![vec![1.0], vec![2.0], vec![5.0]];
+        let diff = diff_indexes(&a, &a, &queries, 3, 10);
+        assert_eq!(diff.mean_jaccard_similarity, 1.0);
+        assert!((diff.score_correlation - 1.0).abs() < 1e-6);
+        assert!(diff.queries_with_disagreement.is_empty());
+    }
+
+    #[test]
+    fn test_diff_different_indexes() {
+        let a = FixedSearchable { ids: vec![1, 2, 3] };
+        let b = FixedSearchable { ids: vec![4, 2, 3] };
+        let queries = vec![vec![1.0], vec![2.0]];
+        let diff = diff_indexes(&a, &b, &queries, 3, 10);
+        // {1, 2, 3} vs {4, 2, 3}: 2 common ids out of 4
+        assert_eq!(diff.mean_jaccard_similarity, 0.5);
+        assert_eq!(diff.queries_with_disagreement, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_pearson_correlation() {
+        assert!((pearson_correlation(&[(1.0, 2.0), (2.0, 4.0), (3.0, 6.0)]) - 1.0).abs() < 1e-6);
+        assert!((pearson_correlation(&[(1.0, 3.0), (2.0, 2.0), (3.0, 1.0)]) + 1.0).abs() < 1e-6);
+        assert!(pearson_correlation(&[(1.0, 2.0)]).is_nan());
+        assert!(pearson_correlation(&[(1.0, 2.0), (1.0, 3.0)]).is_nan());
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::{create_dir, create_dir_all, remove_dir_all};
+
+use anyhow::{anyhow, Result};
+use utils::validation::validate_vector;
+
+use crate::vector::file::FileBackedAppendableVectorStorage;
+use crate::vector::VectorStorage;
+
+pub struct FlatIndexBuilderConfig {
+    pub base_directory: String,
+    pub memory_size: usize,
+    pub file_size: usize,
+    pub num_features: usize,
+}
+
+/// Collects the vectors of a `FlatIndex`. There is nothing to train, vectors are stored as is.
+pub struct FlatIndexBuilder {
+    config: FlatIndexBuilderConfig,
+    vectors: FileBackedAppendableVectorStorage<f32>,
+    doc_ids: Vec<u128>,
+    skip_vector_validation: bool,
+}
+
+impl FlatIndexBuilder {
+    pub fn new(config: FlatIndexBuilderConfig) -> Result<Self> {
+        create_dir_all(&config.base_directory)?;
+
+        let vectors_path = format!("{}/builder_vector_storage", config.base_directory);
+        create_dir(&vectors_path)?;
+        let vectors = FileBackedAppendableVectorStorage::<f32>::new(
+            vectors_path,
+            config.memory_size,
+            config.file_size,
+            config.num_features,
+        );
+
+        Ok(Self {
+            config,
+            vectors,
+            doc_ids: Vec::new(),
+            skip_vector_validation: false,
+        })
+    }
+
+    pub fn config(&self) -> &FlatIndexBuilderConfig {
+        &self.config
+    }
+
+    pub fn vectors(&self) -> &FileBackedAppendableVectorStorage<f32> {
+        &self.vectors
+    }
+
+    pub fn doc_ids(&self) -> &[u128] {
+        &self.doc_ids
+    }
+
+    /// Disables the NaN/Inf check in `add_vector`, for inputs that are known to be valid.
+    pub fn set_skip_vector_validation(&mut self, skip_vector_validation: bool) {
+        self.skip_vector_validation = skip_vector_validation;
+    }
+
+    pub fn add_vector(&mut self, doc_id: u128, data: &[f32]) -> Result<()> {
+        if !self.skip_vector_validation {
+            validate_vector(data, "FlatIndexBuilder::add_vector")?;
+        }
+        if data.len() != self.config.num_features {
+            return Err(anyhow!(
+                "vector length mismatch: expected {}, got {}",
+                self.config.num_features,
+                data.len()
+            ));
+        }
+        self.vectors.append(data)?;
+        self.doc_ids.push(doc_id);
+        Ok(())
+    }
+
+    pub fn cleanup(&mut self) -> Result<()> {
+        remove_dir_all(format!(
+            "{}/builder_vector_storage",
+            self.config.base_directory
+        ))?;
+        Ok(())
+    }
+}
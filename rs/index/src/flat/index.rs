@@ -0,0 +1,210 @@
+use std::collections::BinaryHeap;
+use std::marker::PhantomData;
+
+use anyhow::{anyhow, Result};
+use utils::evaluation::mean_recall_at_k;
+use utils::DistanceCalculator;
+
+use crate::index::{DocIdFilter, Searchable};
+use crate::utils::{IdWithScore, PointAndDistance, SearchContext, SearchResult};
+use crate::vector::fixed_file::FixedFileVectorStorage;
+
+/// Index without any structure: every search computes the exact distance to all vectors. It is
+/// slow on large datasets, but serves as the ground truth to measure the recall of the
+/// approximate indexes.
+pub struct FlatIndex<D: DistanceCalculator> {
+    vector_storage: FixedFileVectorStorage<f32>,
+    doc_ids: Vec<u128>,
+    _marker: PhantomData<D>,
+}
+
+impl<D: DistanceCalculator> FlatIndex<D> {
+    pub fn new(vector_storage: FixedFileVectorStorage<f32>, doc_ids: Vec<u128>) -> Result<Self> {
+        if vector_storage.num_vectors != doc_ids.len() {
+            return Err(anyhow!(
+                "{} vectors but {} doc ids",
+                vector_storage.num_vectors,
+                doc_ids.len()
+            ));
+        }
+        Ok(Self {
+            vector_storage,
+            doc_ids,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn num_vectors(&self) -> usize {
+        self.vector_storage.num_vectors
+    }
+
+    /// Returns the exact `k` nearest neighbors of `query` among the documents accepted by
+    /// `filter`, closest first.
+    pub fn exact_search(
+        &self,
+        query: &[f32],
+        k: usize,
+        filter: Option<&dyn Fn(u128) -> bool>,
+        context: &mut SearchContext,
+    ) -> Vec<IdWithScore> {
+        let mut heap = BinaryHeap::with_capacity(k.min(self.num_vectors()));
+        for (point_id, doc_id) in self.doc_ids.iter().enumerate() {
+            if filter.is_some_and(|filter| !filter(*doc_id)) {
+                continue;
+            }
+            let Some(vector) = self.vector_storage.get(point_id, context) else {
+                continue;
+            };
+            let point = PointAndDistance::new(D::calculate(query, &vector), point_id as u32);
+            if heap.len() < k {
+                heap.push(point);
+            } else if let Some(max) = heap.peek() {
+                if point < *max {
+                    heap.pop();
+                    heap.push(point);
+                }
+            }
+        }
+        context.stats.num_candidates_scanned += self.num_vectors() as u64;
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|point| IdWithScore {
+                id: self.doc_ids[point.point_id as usize],
+                score: *point.distance,
+            })
+            .collect()
+    }
+}
+
+impl<D: DistanceCalculator> Searchable for FlatIndex<D> {
+    // `ef_search` is ignored, the search is always exhaustive
+    fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        _ef_search: u32,
+        context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        Some(self.exact_search(query, k, None, context).into())
+    }
+
+    fn search_with_filter(
+        &self,
+        query: &[f32],
+        k: usize,
+        _ef_search: u32,
+        filter: &DocIdFilter,
+        context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        Some(self.exact_search(query, k, Some(&**filter), context).into())
+    }
+}
+
+/// Results of `index` for each of `queries`, to be used as the ground truth of
+/// `compute_recall_at_k`. `ef_search` is 0, so `index` should be exhaustive, e.g. a `FlatIndex`.
+/// Failed searches have no results.
+pub fn generate_ground_truth(
+    index: &dyn Searchable,
+    queries: &[Vec<f32>],
+    k: usize,
+    context: &mut SearchContext,
+) -> Vec<Vec<IdWithScore>> {
+    queries
+        .iter()
+        .map(|query| {
+            index
+                .search(query, k, 0, context)
+                .map(|results| results.0)
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Mean recall@k of the `approximate` results of each query, against the `exact` results of
+/// the same query, e.g. from `generate_ground_truth`.
+pub fn compute_recall_at_k(
+    exact: &[Vec<IdWithScore>],
+    approximate: &[Vec<IdWithScore>],
+    k: usize,
+) -> f64 {
+    let ids = |results: &[Vec<IdWithScore>]| -> Vec<Vec<u128>> {
+        results
+            .iter()
+            .map(|results| results.iter().map(|x| x.id).collect())
+            .collect()
+    };
+    mean_recall_at_k(&ids(exact), &ids(approximate), k)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use utils::distance::l2::L2DistanceCalculator;
+
+    use super::*;
+    use crate::mock::MockSearchable;
+
+    fn build_flat_index() -> FlatIndex<L2DistanceCalculator> {
+        let mut bytes = 10usize.to_le_bytes().to_vec();
+        for i in 0..10 {
+            for value in [i as f32, i as f32] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        let storage = FixedFileVectorStorage::<f32>::new_from_bytes(&bytes, 2, 0).unwrap();
+        FlatIndex::new(storage, (100..110).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_flat_index_search() {
+        let flat = build_flat_index();
+        let mut context = SearchContext::new(false);
+        let results = flat.search(&[6.2, 6.2], 3, 0, &mut context).unwrap();
+        assert_eq!(
+            results.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![106, 107, 105]
+        );
+        assert_eq!(context.stats.num_candidates_scanned, 10);
+
+        let filter: DocIdFilter = Arc::new(|doc_id| doc_id % 2 == 1);
+        let results = flat
+            .search_with_filter(&[6.2, 6.2], 3, 0, &filter, &mut context)
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![107, 105, 109]
+        );
+    }
+
+    #[test]
+    fn test_compute_recall_at_k() {
+        let flat = build_flat_index();
+        let queries = vec![vec![0.0, 0.0], vec![9.0, 9.0]];
+        let mut context = SearchContext::new(false);
+        let exact = generate_ground_truth(&flat, &queries, 2, &mut context);
+        assert_eq!(
+            exact[1].iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![109, 108]
+        );
+        assert_eq!(compute_recall_at_k(&exact, &exact, 2), 1.0);
+
+        // Finds 100 for the first query, and nothing for the second one
+        let approx = MockSearchable::returning(vec![
+            IdWithScore {
+                id: 100,
+                score: 0.0,
+            },
+            IdWithScore {
+                id: 105,
+                score: 50.0,
+            },
+        ]);
+        let approximate = generate_ground_truth(&approx, &queries, 2, &mut context);
+        assert_eq!(compute_recall_at_k(&exact, &approximate, 2), 0.25);
+        let failed = generate_ground_truth(&MockSearchable::failing(), &queries, 2, &mut context);
+        assert!(failed.iter().all(|results| results.is_empty()));
+        assert_eq!(compute_recall_at_k(&exact, &failed, 2), 0.0);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod builder;
+pub mod index;
+pub mod reader;
+pub mod writer;
@@ -0,0 +1,93 @@
+use anyhow::{anyhow, Result};
+use utils::DistanceCalculator;
+
+use crate::flat::index::FlatIndex;
+use crate::vector::fixed_file::FixedFileVectorStorage;
+
+// Number of vectors and dimension, as u64
+const HEADER_SIZE: usize = 16;
+
+pub struct FlatIndexReader {
+    base_directory: String,
+}
+
+impl FlatIndexReader {
+    pub fn new(base_directory: String) -> Self {
+        Self { base_directory }
+    }
+
+    pub fn read<D: DistanceCalculator>(&self) -> Result<FlatIndex<D>> {
+        let index_path = format!("{}/index", self.base_directory);
+        let bytes = std::fs::read(&index_path)?;
+        if bytes.len() < HEADER_SIZE {
+            return Err(anyhow!("{} is too small to be a flat index", index_path));
+        }
+        let num_vectors = u64::from_le_bytes(bytes[0..8].try_into()?) as usize;
+        let num_features = u64::from_le_bytes(bytes[8..16].try_into()?) as usize;
+        if bytes.len() != HEADER_SIZE + num_vectors * size_of::<u128>() {
+            return Err(anyhow!(
+                "{} has {} bytes, expected {} doc ids",
+                index_path,
+                bytes.len(),
+                num_vectors
+            ));
+        }
+        let doc_ids = bytes[HEADER_SIZE..]
+            .chunks_exact(size_of::<u128>())
+            .map(|chunk| u128::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        let vector_storage = FixedFileVectorStorage::<f32>::new(
+            format!("{}/vectors", self.base_directory),
+            num_features,
+        )?;
+        FlatIndex::new(vector_storage, doc_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::flat::builder::{FlatIndexBuilder, FlatIndexBuilderConfig};
+    use crate::flat::writer::FlatIndexWriter;
+    use crate::index::Searchable;
+    use crate::utils::SearchContext;
+
+    #[test]
+    fn test_flat_index_write_and_read() {
+        let temp_dir = tempdir::TempDir::new("flat_index_reader_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let mut builder = FlatIndexBuilder::new(FlatIndexBuilderConfig {
+            base_directory: base_directory.clone(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features: 4,
+        })
+        .unwrap();
+        let vectors: Vec<Vec<f32>> = (0..500).map(|_| generate_random_vector(4)).collect();
+        for (i, vector) in vectors.iter().enumerate() {
+            builder.add_vector(i as u128 + 100, vector).unwrap();
+        }
+        assert!(builder.add_vector(0, &[1.0]).is_err());
+
+        FlatIndexWriter::new(base_directory.clone())
+            .write(&builder)
+            .unwrap();
+        builder.cleanup().unwrap();
+
+        let flat = FlatIndexReader::new(base_directory)
+            .read::<L2DistanceCalculator>()
+            .unwrap();
+        assert_eq!(flat.num_vectors(), 500);
+
+        let mut context = SearchContext::new(false);
+        let results = flat.search(&vectors[42], 5, 0, &mut context).unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].id, 142);
+        assert_eq!(results[0].score, 0.0);
+        assert!(results.windows(2).all(|x| x[0].score <= x[1].score));
+    }
+}
@@ -0,0 +1,43 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::Result;
+use utils::io::wrap_write;
+
+use crate::flat::builder::FlatIndexBuilder;
+use crate::vector::VectorStorage;
+
+/// Writes a `FlatIndex` to `base_directory`:
+/// - `vectors`: the vectors, in the format read by `FixedFileVectorStorage`.
+/// - `index`: the number of vectors and their dimension as u64, then the doc id of every
+///   vector as u128, all little-endian.
+pub struct FlatIndexWriter {
+    base_directory: String,
+}
+
+impl FlatIndexWriter {
+    pub fn new(base_directory: String) -> Self {
+        Self { base_directory }
+    }
+
+    pub fn write(&self, builder: &FlatIndexBuilder) -> Result<()> {
+        let mut vectors_file = File::create(format!("{}/vectors", self.base_directory))?;
+        let mut vectors_writer = BufWriter::new(&mut vectors_file);
+        builder.vectors().write(&mut vectors_writer)?;
+        vectors_writer.flush()?;
+
+        let mut index_file = File::create(format!("{}/index", self.base_directory))?;
+        let mut index_writer = BufWriter::new(&mut index_file);
+        let doc_ids = builder.doc_ids();
+        wrap_write(&mut index_writer, &(doc_ids.len() as u64).to_le_bytes())?;
+        wrap_write(
+            &mut index_writer,
+            &(builder.config().num_features as u64).to_le_bytes(),
+        )?;
+        for doc_id in doc_ids {
+            wrap_write(&mut index_writer, &doc_id.to_le_bytes())?;
+        }
+        index_writer.flush()?;
+        Ok(())
+    }
+}
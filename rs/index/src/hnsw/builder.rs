@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cmp::min;
 use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::vec;
@@ -9,13 +10,105 @@ use ordered_float::NotNan;
 use quantization::quantization::Quantizer;
 use quantization::typing::VectorOps;
 use rand::Rng;
+use rayon::prelude::*;
+use utils::l2_normalize;
+use utils::validation::validate_vector;
 
-use super::index::Hnsw;
-use super::utils::{BuilderContext, GraphTraversal};
+use super::index::{Hnsw, LayerStats};
+use super::spill::EdgeSpill;
+use super::utils::{degree_histogram, hub_nodes, BuilderContext, GraphTraversal};
 use crate::utils::{PointAndDistance, SearchContext};
 use crate::vector::file::FileBackedAppendableVectorStorage;
 use crate::vector::{VectorStorage, VectorStorageConfig};
 
+// Number of points searched in parallel by `par_insert_batch` before their edges are added
+const PARALLEL_INSERT_CHUNK_SIZE: usize = 1024;
+
+// Directory of the full-precision upper layer vectors, under the builder's base directory
+const UPPER_LAYER_VECTORS_DIRECTORY_NAME: &str = "upper_layer_vector_storage";
+
+/// Full-precision vectors of the points at layers >= 1, keyed by point id. They are appended to a
+/// file-backed storage, which spills to disk past its memory threshold like the quantized
+/// vectors do.
+pub struct UpperLayerVectors {
+    directory: String,
+    memory_threshold: usize,
+    file_size: usize,
+
+    // Created with the first vector, once its dimension is known
+    storage: Option<FileBackedAppendableVectorStorage<f32>>,
+
+    // Point id -> index of its vector in `storage`
+    ids: HashMap<u32, u32>,
+}
+
+impl UpperLayerVectors {
+    pub fn new(directory: String, memory_threshold: usize, file_size: usize) -> Self {
+        Self {
+            directory,
+            memory_threshold,
+            file_size,
+            storage: None,
+            ids: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, point_id: u32) -> Option<&[f32]> {
+        let index = self.ids.get(&point_id)?;
+        self.storage.as_ref()?.get(*index).ok()
+    }
+
+    pub fn insert(&mut self, point_id: u32, vector: &[f32]) -> Result<()> {
+        if self.storage.is_none() {
+            std::fs::create_dir_all(&self.directory)?;
+            self.storage = Some(FileBackedAppendableVectorStorage::new(
+                self.directory.clone(),
+                self.memory_threshold,
+                self.file_size,
+                vector.len(),
+            ));
+        }
+        let storage = self.storage.as_mut().unwrap();
+        storage.append(vector)?;
+        self.ids.insert(point_id, storage.len() as u32 - 1);
+        Ok(())
+    }
+
+    /// Moves the vectors to the point ids returned by `new_id`, and drops the ones it returns
+    /// None for. The stored vectors don't move.
+    pub fn remap_point_ids(
+        &mut self,
+        mut new_id: impl FnMut(u32) -> Result<Option<u32>>,
+    ) -> Result<()> {
+        let mut ids = HashMap::with_capacity(self.ids.len());
+        for (point_id, index) in self.ids.iter() {
+            if let Some(new_point_id) = new_id(*point_id)? {
+                ids.insert(new_point_id, *index);
+            }
+        }
+        self.ids = ids;
+        Ok(())
+    }
+
+    /// Returns the (point id, vector) pairs, sorted by point id.
+    pub fn iter_sorted(&self) -> Vec<(u32, &[f32])> {
+        let mut point_ids: Vec<u32> = self.ids.keys().copied().collect();
+        point_ids.sort();
+        point_ids
+            .into_iter()
+            .filter_map(|point_id| Some((point_id, self.get(point_id)?)))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
 /// TODO(hicder): support bare vector in addition to quantized one.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Layer {
@@ -47,7 +140,7 @@ impl Layer {
 
 /// The actual builder
 pub struct HnswBuilder<Q: Quantizer> {
-    vectors: Box<dyn VectorStorage<Q::QuantizedT>>,
+    vectors: Box<dyn VectorStorage<Q::QuantizedT> + Send + Sync>,
 
     max_neighbors: usize,
     pub layers: Vec<Layer>,
@@ -57,6 +150,47 @@ pub struct HnswBuilder<Q: Quantizer> {
     pub entry_point: Vec<u32>,
     max_layer: u8,
     pub doc_id_mapping: Vec<u128>,
+
+    // Points of documents inserted with `insert_multi`, keyed by doc id.
+    pub multi_vector_doc_ids: HashMap<u128, Vec<u32>>,
+
+    // Doc ids removed with `delete`. Their points stay in the graph until `compact` is called.
+    pub tombstones: HashSet<u128>,
+
+    // Skip the NaN/Inf check on inserted vectors
+    skip_vector_validation: bool,
+
+    // Keep full-precision vectors for points at layers >= 1, so that only layer 0 is quantized.
+    quantize_layer_0: bool,
+
+    // Full-precision vectors of points at layers >= 1, when `quantize_layer_0` is set.
+    pub upper_layer_vectors: UpperLayerVectors,
+
+    // L2-normalize vectors before inserting them
+    normalize_on_insert: bool,
+
+    // Norms of the inserted vectors, when `normalize_on_insert` is set
+    original_norms: HashMap<u32, f32>,
+
+    // Directory for temporary files of the builder
+    base_directory: String,
+
+    // Upper bound for the memory of the neighbor lists kept in `layers`. None means no bound.
+    max_graph_memory_bytes: Option<usize>,
+
+    // Number of edges in the neighbor lists kept in `layers`
+    num_in_memory_edges: usize,
+
+    // Neighbor lists moved to disk to stay under `max_graph_memory_bytes`. Spilled points keep
+    // an empty list in `layers`, so that the points of each layer don't change.
+    edge_spill: Option<EdgeSpill>,
+
+    // Points below this id have no neighbor lists left in memory, unless they were loaded back
+    next_point_to_spill: u32,
+
+    // (layer, point id) of the neighbor lists below `next_point_to_spill` that were loaded back
+    // into memory. They are spilled again before moving on to the next points.
+    reloaded_edges: Vec<(u8, u32)>,
 }
 
 // TODO(hicder): support bare vector in addition to quantized one.
@@ -77,6 +211,11 @@ impl<Q: Quantizer> HnswBuilder<Q> {
             vector_storage_file_size,
             num_features,
         ));
+        let upper_layer_vectors = UpperLayerVectors::new(
+            format!("{}/{}", base_directory, UPPER_LAYER_VECTORS_DIRECTORY_NAME),
+            vector_storage_memory_size,
+            vector_storage_file_size,
+        );
 
         Self {
             vectors,
@@ -88,9 +227,57 @@ impl<Q: Quantizer> HnswBuilder<Q> {
             ef_contruction: ef_construction,
             entry_point: vec![],
             doc_id_mapping: Vec::new(),
+            multi_vector_doc_ids: HashMap::new(),
+            tombstones: HashSet::new(),
+            skip_vector_validation: false,
+            quantize_layer_0: false,
+            upper_layer_vectors,
+            normalize_on_insert: false,
+            original_norms: HashMap::new(),
+            base_directory,
+            max_graph_memory_bytes: None,
+            num_in_memory_edges: 0,
+            edge_spill: None,
+            next_point_to_spill: 0,
+            reloaded_edges: vec![],
         }
     }
 
+    /// Disables the NaN/Inf check in `insert`, for inputs that are known to be valid.
+    pub fn set_skip_vector_validation(&mut self, skip_vector_validation: bool) {
+        self.skip_vector_validation = skip_vector_validation;
+    }
+
+    /// Keeps full-precision vectors for points at layers >= 1 and uses exact distances there.
+    /// Only layer 0 uses quantized vectors. Needs to be set before the first insert.
+    pub fn set_quantize_layer_0(&mut self, quantize_layer_0: bool) {
+        self.quantize_layer_0 = quantize_layer_0;
+    }
+
+    /// L2-normalizes vectors before inserting them, so that the dot product distance ranks
+    /// points by cosine similarity. Inserting a zero vector fails. Needs to be set before the
+    /// first insert.
+    pub fn set_normalize_on_insert(&mut self, normalize_on_insert: bool) {
+        self.normalize_on_insert = normalize_on_insert;
+    }
+
+    /// Bounds the memory of the neighbor lists. Once it is exceeded, the lists of the lowest
+    /// point ids are spilled to a file in the base directory, and read back when traversed.
+    pub fn set_max_graph_memory_bytes(&mut self, max_graph_memory_bytes: Option<usize>) {
+        self.max_graph_memory_bytes = max_graph_memory_bytes;
+    }
+
+    /// Memory used by the neighbor lists kept in memory.
+    pub fn graph_memory_bytes(&self) -> usize {
+        self.num_in_memory_edges * std::mem::size_of::<PointAndDistance>()
+    }
+
+    pub fn num_spilled_edge_lists(&self) -> usize {
+        self.edge_spill
+            .as_ref()
+            .map_or(0, |edge_spill| edge_spill.num_spilled())
+    }
+
     pub fn from_hnsw(
         hnsw: Hnsw<Q>,
         output_directory: String,
@@ -120,7 +307,7 @@ impl<Q: Quantizer> HnswBuilder<Q> {
         for i in 0..hnsw.get_doc_id_mapping_slice().len() {
             let vector = hnsw.vector_storage.get(i, &mut context).unwrap();
             vector_storage
-                .append(vector)
+                .append(&vector)
                 .unwrap_or_else(|_| panic!("append failed"));
         }
 
@@ -132,7 +319,7 @@ impl<Q: Quantizer> HnswBuilder<Q> {
                     .get(from as usize, &mut context)
                     .unwrap();
                 let to_v = hnsw.vector_storage.get(to as usize, &mut context).unwrap();
-                let distance = Q::QuantizedT::distance(from_v, to_v, &hnsw.quantizer);
+                let distance = Q::QuantizedT::distance(&from_v, &to_v, &hnsw.quantizer);
                 layer
                     .edges
                     .entry(from)
@@ -157,6 +344,26 @@ impl<Q: Quantizer> HnswBuilder<Q> {
 
         let all_entry_points = hnsw.get_all_entry_points();
         let doc_id_mapping = hnsw.get_doc_id_mapping_slice().to_vec();
+        let multi_vector_doc_ids = hnsw.get_multi_vector_doc_ids().clone();
+        let tombstones = hnsw.get_tombstones().clone();
+        let mut upper_layer_vectors = UpperLayerVectors::new(
+            format!(
+                "{}/{}",
+                output_directory, UPPER_LAYER_VECTORS_DIRECTORY_NAME
+            ),
+            vector_storage_config.memory_threshold,
+            vector_storage_config.file_size,
+        );
+        for (point_id, vector) in hnsw.get_upper_layer_vectors() {
+            upper_layer_vectors
+                .insert(*point_id, vector)
+                .unwrap_or_else(|_| panic!("append failed"));
+        }
+        let num_in_memory_edges = layers
+            .iter()
+            .flat_map(|layer| layer.edges.values())
+            .map(|edges| edges.len())
+            .sum();
 
         Self {
             vectors: vector_storage,
@@ -168,6 +375,19 @@ impl<Q: Quantizer> HnswBuilder<Q> {
             ef_contruction: 100,
             entry_point: all_entry_points,
             doc_id_mapping: doc_id_mapping,
+            multi_vector_doc_ids,
+            tombstones,
+            skip_vector_validation: false,
+            quantize_layer_0: !upper_layer_vectors.is_empty(),
+            upper_layer_vectors,
+            normalize_on_insert: false,
+            original_norms: HashMap::new(),
+            base_directory: output_directory,
+            max_graph_memory_bytes: None,
+            num_in_memory_edges,
+            edge_spill: None,
+            next_point_to_spill: 0,
+            reloaded_edges: vec![],
         }
     }
 
@@ -248,7 +468,10 @@ impl<Q: Quantizer> HnswBuilder<Q> {
     // Reindex the vectors based on the bottom layer
     // Vectors that are connected should have their id close to each other
     // This optimization is useful for disk-based indexing
+    //
+    // Reindexing needs all neighbor lists, so spilled ones are loaded back into memory.
     pub fn reindex(&mut self, temp_dir: String) -> Result<()> {
+        self.load_spilled_edges()?;
         let assigned_ids = self.get_reassigned_ids()?;
         for (i, layer) in self.layers.iter_mut().enumerate() {
             layer
@@ -263,6 +486,30 @@ impl<Q: Quantizer> HnswBuilder<Q> {
             ))?;
             self.doc_id_mapping[*new_id as usize] = doc_id;
         }
+        for point_ids in self.multi_vector_doc_ids.values_mut() {
+            for point_id in point_ids.iter_mut() {
+                let new_id = assigned_ids.get(*point_id as usize).ok_or(anyhow!(
+                    "point id {} is larger than size of vectors",
+                    *point_id
+                ))?;
+                *point_id = *new_id as u32;
+            }
+        }
+        self.upper_layer_vectors.remap_point_ids(|point_id| {
+            let new_id = assigned_ids.get(point_id as usize).ok_or(anyhow!(
+                "point id {} is larger than size of vectors",
+                point_id
+            ))?;
+            Ok(Some(*new_id as u32))
+        })?;
+        let original_norms = std::mem::take(&mut self.original_norms);
+        for (point_id, norm) in original_norms.into_iter() {
+            let new_id = assigned_ids.get(point_id as usize).ok_or(anyhow!(
+                "point id {} is larger than size of vectors",
+                point_id
+            ))?;
+            self.original_norms.insert(*new_id as u32, norm);
+        }
         for entry in self.entry_point.iter_mut() {
             let new_id = assigned_ids.get(*entry as usize).ok_or(anyhow!(
                 "entrypoint id {} is larger than size of vectors",
@@ -299,16 +546,48 @@ impl<Q: Quantizer> HnswBuilder<Q> {
         Ok(())
     }
 
-    /// Insert a vector into the index
-    pub fn insert(&mut self, doc_id: u128, vector: &[f32]) -> Result<()> {
+    /// Returns `vector` divided by its norm when `normalize_on_insert` is set, with the norm.
+    fn normalize<'a>(&self, vector: &'a [f32]) -> Result<(Cow<'a, [f32]>, Option<f32>)> {
+        if !self.normalize_on_insert {
+            return Ok((Cow::Borrowed(vector), None));
+        }
+        let mut normalized = vector.to_vec();
+        let norm = l2_normalize(&mut normalized)?;
+        Ok((Cow::Owned(normalized), Some(norm)))
+    }
+
+    /// Stores the vector of a new point and picks its top layer, without adding any edge.
+    /// `norm` is the norm of the vector before `normalize`.
+    fn add_point(
+        &mut self,
+        doc_id: u128,
+        vector: &[f32],
+        norm: Option<f32>,
+    ) -> Result<(u32, Vec<Q::QuantizedT>, u8)> {
         let quantized_query = Q::QuantizedT::process_vector(vector, &self.quantizer);
         let point_id = self.generate_id(doc_id);
-        let mut context = BuilderContext::new(point_id + 1);
-
-        let empty_graph = point_id == 0;
         self.vectors.append(&quantized_query)?;
+        if let Some(norm) = norm {
+            self.original_norms.insert(point_id, norm);
+        }
         let layer = self.get_random_layer();
+        if self.quantize_layer_0 && layer > 0 {
+            self.upper_layer_vectors.insert(point_id, vector)?;
+        }
+        Ok((point_id, quantized_query, layer))
+    }
+
+    /// Insert a vector into the index
+    pub fn insert(&mut self, doc_id: u128, vector: &[f32]) -> Result<()> {
+        if !self.skip_vector_validation {
+            validate_vector(vector, "HnswBuilder::insert")?;
+        }
+        let (vector, norm) = self.normalize(vector)?;
+        let vector = vector.as_ref();
+        let (point_id, quantized_query, layer) = self.add_point(doc_id, vector, norm)?;
+        let mut context = BuilderContext::new(point_id + 1);
 
+        let empty_graph = point_id == 0;
         if empty_graph {
             self.entry_point = vec![point_id];
             // Initialize the layers
@@ -324,69 +603,545 @@ impl<Q: Quantizer> HnswBuilder<Q> {
         let mut entry_point = self.entry_point[0];
         if layer < self.current_top_layer {
             for l in ((layer + 1)..=self.current_top_layer).rev() {
-                let nearest_elements =
-                    self.search_layer(&mut context, &quantized_query, entry_point, 1, l);
+                let nearest_elements = self.search_layer_for_insert(
+                    &mut context,
+                    &quantized_query,
+                    vector,
+                    entry_point,
+                    1,
+                    l,
+                )?;
                 entry_point = nearest_elements[0].point_id as u32;
             }
         } else if layer > self.current_top_layer {
-            // Initialize the layers
-            for _ in 0..(layer - self.current_top_layer) {
-                self.layers.push(Layer {
-                    edges: HashMap::from([(point_id, vec![])]),
-                });
-            }
+            self.add_layers_for_point(point_id, layer);
         }
 
         for l in (0..=min(layer, self.current_top_layer)).rev() {
-            let nearest_elements = self.search_layer(
+            let nearest_elements = self.search_layer_for_insert(
                 &mut context,
                 &quantized_query,
+                vector,
                 entry_point,
                 self.ef_contruction,
                 l,
-            );
-            let neighbors =
-                self.select_neighbors_heuristic(&nearest_elements, self.max_neighbors as usize);
-            for e in &neighbors {
-                self.layers[l as usize]
-                    .edges
-                    .entry(e.point_id)
-                    .or_insert_with(|| vec![])
-                    .push(PointAndDistance {
-                        point_id,
-                        distance: e.distance.clone(),
+            )?;
+            self.connect_point(point_id, l, &nearest_elements)?;
+            entry_point = nearest_elements[0].point_id;
+        }
+
+        self.update_entry_point(point_id, layer);
+        self.spill_edges_if_needed()
+    }
+
+    /// Insert vectors like `insert`, searching the neighbors of many vectors in parallel.
+    ///
+    /// Rows are processed in chunks. The neighbors of all points of a chunk are first searched
+    /// in parallel in the graph as it was before the chunk, and among the points of the chunk
+    /// inserted before them. Edges are then added one point at a time, so the graph is only
+    /// modified by a single thread.
+    pub fn par_insert_batch(&mut self, rows: &[(u64, &[f32])]) -> Result<()>
+    where
+        Q: Sync,
+    {
+        if !self.skip_vector_validation {
+            rows.par_iter().try_for_each(|(_, vector)| {
+                validate_vector(vector, "HnswBuilder::par_insert_batch")
+            })?;
+        }
+        // Points of a chunk only have each other as neighbors until the graph is large enough
+        let num_sequential_rows = PARALLEL_INSERT_CHUNK_SIZE
+            .saturating_sub(self.doc_id_mapping.len())
+            .min(rows.len());
+        let (sequential_rows, parallel_rows) = rows.split_at(num_sequential_rows);
+        for (doc_id, vector) in sequential_rows {
+            self.insert_without_validation(*doc_id as u128, vector)?;
+        }
+        for chunk in parallel_rows.chunks(PARALLEL_INSERT_CHUNK_SIZE) {
+            self.insert_chunk(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn insert_without_validation(&mut self, doc_id: u128, vector: &[f32]) -> Result<()> {
+        let skip_vector_validation = self.skip_vector_validation;
+        self.skip_vector_validation = true;
+        let result = self.insert(doc_id, vector);
+        self.skip_vector_validation = skip_vector_validation;
+        result
+    }
+
+    fn insert_chunk(&mut self, rows: &[(u64, &[f32])]) -> Result<()>
+    where
+        Q: Sync,
+    {
+        let normalized_rows = rows
+            .iter()
+            .map(|(_, vector)| self.normalize(vector))
+            .collect::<Result<Vec<_>>>()?;
+        let points = rows
+            .iter()
+            .zip(normalized_rows.iter())
+            .map(|((doc_id, _), (vector, norm))| self.add_point(*doc_id as u128, vector, *norm))
+            .collect::<Result<Vec<_>>>()?;
+        let point_layers = points
+            .iter()
+            .map(|(point_id, _, layer)| (*point_id, *layer))
+            .collect::<Vec<_>>();
+
+        let graph = &*self;
+        let candidates = points
+            .par_iter()
+            .zip(normalized_rows.par_iter())
+            .enumerate()
+            .map(|(i, ((point_id, quantized_query, layer), (vector, _)))| {
+                graph.candidates_for_insert(
+                    *point_id,
+                    quantized_query,
+                    vector,
+                    *layer,
+                    &point_layers[..i],
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for ((point_id, layer), candidates) in point_layers.into_iter().zip(candidates) {
+            if layer > self.current_top_layer {
+                self.add_layers_for_point(point_id, layer);
+            }
+            for (l, nearest_elements) in candidates.iter().enumerate().rev() {
+                self.connect_point(point_id, l as u8, nearest_elements)?;
+            }
+            self.update_entry_point(point_id, layer);
+            self.spill_edges_if_needed()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the closest points to a new point at each layer from 0 to `layer`, among the
+    /// points already linked in the graph and `earlier_points`, the (point id, layer) of the
+    /// points to be linked before it.
+    fn candidates_for_insert(
+        &self,
+        point_id: u32,
+        quantized_query: &[Q::QuantizedT],
+        vector: &[f32],
+        layer: u8,
+        earlier_points: &[(u32, u8)],
+    ) -> Result<Vec<Vec<PointAndDistance>>> {
+        let mut context = BuilderContext::new(self.doc_id_mapping.len() as u32);
+        let mut candidates = vec![vec![]; layer as usize + 1];
+        let mut entry_point = self.entry_point[0];
+        for l in (0..=self.current_top_layer).rev() {
+            let ef = if l > layer { 1 } else { self.ef_contruction };
+            let nearest_elements = self.search_layer_for_insert(
+                &mut context,
+                quantized_query,
+                vector,
+                entry_point,
+                ef,
+                l,
+            )?;
+            entry_point = nearest_elements[0].point_id;
+            if l <= layer {
+                candidates[l as usize] = nearest_elements;
+            }
+        }
+
+        for (l, layer_candidates) in candidates.iter_mut().enumerate() {
+            for (other_point_id, other_layer) in earlier_points {
+                if *other_layer as usize >= l {
+                    let distance = self.distance_two_points(point_id, *other_point_id, l as u8);
+                    layer_candidates.push(PointAndDistance {
+                        point_id: *other_point_id,
+                        distance: NotNan::new(distance).unwrap(),
                     });
+                }
+            }
+            layer_candidates.sort();
+            layer_candidates.truncate(self.ef_contruction as usize);
+        }
+        Ok(candidates)
+    }
+
+    /// Adds the layers above the current top layer, up to `layer`, with `point_id` as their
+    /// only point.
+    fn add_layers_for_point(&mut self, point_id: u32, layer: u8) {
+        for _ in self.layers.len()..=layer as usize {
+            self.layers.push(Layer {
+                edges: HashMap::from([(point_id, vec![])]),
+            });
+        }
+    }
+
+    /// Links `point_id` at layer `l` to its neighbors among `nearest_elements`, and trims the
+    /// edges of the neighbors that have too many.
+    fn connect_point(
+        &mut self,
+        point_id: u32,
+        l: u8,
+        nearest_elements: &[PointAndDistance],
+    ) -> Result<()> {
+        let neighbors =
+            self.select_neighbors_heuristic(nearest_elements, self.max_neighbors as usize, l);
+        for e in &neighbors {
+            self.load_edges(l, e.point_id)?;
+            self.layers[l as usize]
+                .edges
+                .entry(e.point_id)
+                .or_insert_with(|| vec![])
+                .push(PointAndDistance {
+                    point_id,
+                    distance: e.distance.clone(),
+                });
+            self.layers[l as usize]
+                .edges
+                .entry(point_id)
+                .or_insert_with(|| vec![])
+                .push(e.clone());
+            self.num_in_memory_edges += 2;
+        }
+
+        for e in &neighbors {
+            let e_edges = &self.layers[l as usize].edges[&e.point_id];
+            let num_edges = e_edges.len();
+            if num_edges > self.max_neighbors as usize {
+                // Trim the edges
+                let new_edges_for_e =
+                    self.select_neighbors_heuristic(&e_edges, self.max_neighbors, l);
+                self.num_in_memory_edges -= num_edges - new_edges_for_e.len();
                 self.layers[l as usize]
                     .edges
-                    .entry(point_id)
-                    .or_insert_with(|| vec![])
-                    .push(e.clone());
-            }
-
-            for e in &neighbors {
-                let e_edges = &self.layers[l as usize].edges[&e.point_id];
-                let num_edges = e_edges.len();
-                if num_edges > self.max_neighbors as usize {
-                    // Trim the edges
-                    let new_edges_for_e =
-                        self.select_neighbors_heuristic(&e_edges, self.max_neighbors);
-                    self.layers[l as usize]
-                        .edges
-                        .insert(e.point_id, new_edges_for_e);
-                }
+                    .insert(e.point_id, new_edges_for_e);
             }
-            entry_point = nearest_elements[0].point_id;
         }
+        Ok(())
+    }
 
+    fn update_entry_point(&mut self, point_id: u32, layer: u8) {
         if layer > self.current_top_layer {
             self.current_top_layer = layer;
             self.entry_point = vec![point_id];
         } else if layer == self.current_top_layer {
             self.entry_point.push(point_id);
         }
+    }
+
+    /// Returns the edges of `point_id` at `layer`, reading them from disk if they were spilled.
+    pub fn edges_for_point(
+        &self,
+        layer: u8,
+        point_id: u32,
+    ) -> Result<Option<Cow<'_, [PointAndDistance]>>> {
+        if let Some(edge_spill) = &self.edge_spill {
+            if let Some(edges) = edge_spill.get(layer, point_id)? {
+                return Ok(Some(Cow::Owned(edges)));
+            }
+        }
+        Ok(self.layers[layer as usize]
+            .edges
+            .get(&point_id)
+            .map(|edges| Cow::Borrowed(edges.as_slice())))
+    }
+
+    /// Returns the (point id, degree) of every point at `layer`, by point id.
+    fn point_degrees(&self, layer: usize) -> Vec<(u32, usize)> {
+        let Some(edges) = self.layers.get(layer).map(|layer| &layer.edges) else {
+            return vec![];
+        };
+        let mut degrees = edges
+            .iter()
+            .map(|(point_id, point_edges)| {
+                let num_spilled = self
+                    .edge_spill
+                    .as_ref()
+                    .and_then(|edge_spill| edge_spill.num_edges(layer as u8, *point_id));
+                (*point_id, num_spilled.unwrap_or(point_edges.len()))
+            })
+            .collect::<Vec<_>>();
+        degrees.sort();
+        degrees
+    }
+
+    /// Returns (degree, number of points) pairs for the points at `layer`, by increasing degree.
+    pub fn degree_histogram(&self, layer: usize) -> Vec<(usize, usize)> {
+        degree_histogram(
+            self.point_degrees(layer)
+                .into_iter()
+                .map(|(_, degree)| degree),
+        )
+    }
+
+    /// Returns the number of points and edges in each layer, starting from the bottom layer.
+    pub fn layer_stats(&self) -> Vec<LayerStats> {
+        (0..self.layers.len())
+            .map(|layer| {
+                let degrees = self.point_degrees(layer);
+                LayerStats {
+                    num_points: degrees.len(),
+                    num_edges: degrees.iter().map(|(_, degree)| degree).sum(),
+                    degree_histogram: degree_histogram(
+                        degrees.into_iter().map(|(_, degree)| degree),
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the points at `layer` with more than 3 times the average degree of the layer.
+    pub fn hub_nodes(&self, layer: usize) -> Vec<u32> {
+        hub_nodes(&self.point_degrees(layer))
+    }
+
+    /// Moves the edges of `point_id` at `layer` back into memory, so that they can be modified.
+    fn load_edges(&mut self, layer: u8, point_id: u32) -> Result<()> {
+        let Some(edge_spill) = self.edge_spill.as_mut() else {
+            return Ok(());
+        };
+        if let Some(edges) = edge_spill.take(layer, point_id)? {
+            self.num_in_memory_edges += edges.len();
+            self.layers[layer as usize].edges.insert(point_id, edges);
+            if point_id < self.next_point_to_spill {
+                self.reloaded_edges.push((layer, point_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves all spilled edges back into memory.
+    pub fn load_spilled_edges(&mut self) -> Result<()> {
+        let Some(edge_spill) = self.edge_spill.take() else {
+            return Ok(());
+        };
+        for (layer_idx, layer) in self.layers.iter_mut().enumerate() {
+            for (point_id, edges) in layer.edges.iter_mut() {
+                if let Some(spilled_edges) = edge_spill.get(layer_idx as u8, *point_id)? {
+                    self.num_in_memory_edges += spilled_edges.len();
+                    *edges = spilled_edges;
+                }
+            }
+        }
+        self.next_point_to_spill = 0;
+        self.reloaded_edges.clear();
+        Ok(())
+    }
+
+    /// Spills the neighbor lists of the lowest point ids until the graph fits in
+    /// `max_graph_memory_bytes`.
+    fn spill_edges_if_needed(&mut self) -> Result<()> {
+        let Some(max_graph_memory_bytes) = self.max_graph_memory_bytes else {
+            return Ok(());
+        };
+        while self.graph_memory_bytes() > max_graph_memory_bytes {
+            let Some((layer, point_id)) = self.reloaded_edges.pop() else {
+                break;
+            };
+            self.spill_edges(layer, point_id)?;
+        }
+        let num_points = self.doc_id_mapping.len() as u32;
+        while self.graph_memory_bytes() > max_graph_memory_bytes
+            && self.next_point_to_spill < num_points
+        {
+            let point_id = self.next_point_to_spill;
+            for layer in 0..self.layers.len() {
+                self.spill_edges(layer as u8, point_id)?;
+            }
+            self.next_point_to_spill += 1;
+        }
+        Ok(())
+    }
+
+    /// Moves the edges of `point_id` at `layer` to the spill file, if it has any in memory.
+    fn spill_edges(&mut self, layer: u8, point_id: u32) -> Result<()> {
+        let edges = match self.layers[layer as usize].edges.get_mut(&point_id) {
+            Some(edges) if !edges.is_empty() => std::mem::take(edges),
+            _ => return Ok(()),
+        };
+        if self.edge_spill.is_none() {
+            std::fs::create_dir_all(&self.base_directory)?;
+            self.edge_spill = Some(EdgeSpill::new(format!(
+                "{}/graph_spill",
+                self.base_directory
+            ))?);
+        }
+        self.edge_spill
+            .as_mut()
+            .unwrap()
+            .spill(layer, point_id, &edges)?;
+        self.num_in_memory_edges -= edges.len();
+        Ok(())
+    }
+
+    /// Insert all vectors of a document. Each vector becomes its own point in the graph, and the
+    /// points are recorded in `multi_vector_doc_ids`.
+    pub fn insert_multi(&mut self, doc_id: u128, vectors: &[&[f32]]) -> Result<()> {
+        if !self.skip_vector_validation {
+            for vector in vectors {
+                validate_vector(vector, "HnswBuilder::insert_multi")?;
+            }
+        }
+        for vector in vectors {
+            let point_id = self.doc_id_mapping.len() as u32;
+            self.insert(doc_id, vector)?;
+            self.multi_vector_doc_ids
+                .entry(doc_id)
+                .or_default()
+                .push(point_id);
+        }
+        Ok(())
+    }
+
+    /// Marks all points of `doc_id` as deleted. They are still traversed by searches, but never
+    /// returned, until `compact` removes them from the graph.
+    pub fn delete(&mut self, doc_id: u128) -> Result<()> {
+        if !self.doc_id_mapping.contains(&doc_id) {
+            return Err(anyhow!("Doc id {} is not in the index", doc_id));
+        }
+        self.tombstones.insert(doc_id);
+        Ok(())
+    }
+
+    /// Removes the points of deleted documents from the graph and the vectors, and assigns new
+    /// ids to the remaining points in their current order. The remaining vectors are copied to
+    /// a new storage in `temp_dir`.
+    pub fn compact(&mut self, temp_dir: String) -> Result<()> {
+        if self.tombstones.is_empty() {
+            return Ok(());
+        }
+        self.load_spilled_edges()?;
+        let num_points = self.doc_id_mapping.len();
+        let mut deleted = BitVec::from_elem(num_points, false);
+        for (point_id, doc_id) in self.doc_id_mapping.iter().enumerate() {
+            if self.tombstones.contains(doc_id) {
+                deleted.set(point_id, true);
+            }
+        }
+
+        for layer in 0..self.layers.len() {
+            self.remove_deleted_points(layer as u8, &deleted);
+        }
+        // Top layers may only have had deleted points
+        while self
+            .layers
+            .last()
+            .is_some_and(|layer| layer.edges.is_empty())
+        {
+            self.layers.pop();
+        }
+
+        let mut assigned_ids = vec![-1; num_points];
+        let mut num_remaining_points = 0;
+        for point_id in 0..num_points {
+            if !deleted[point_id] {
+                assigned_ids[point_id] = num_remaining_points;
+                num_remaining_points += 1;
+            }
+        }
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            layer
+                .reindex(&assigned_ids)
+                .context(format!("failed to reindex layer {}", i))?;
+        }
+
+        let vector_storage_config = self.vectors.config();
+        let mut new_vector_storage =
+            Box::new(FileBackedAppendableVectorStorage::<Q::QuantizedT>::new(
+                temp_dir,
+                vector_storage_config.memory_threshold,
+                vector_storage_config.file_size,
+                vector_storage_config.num_features,
+            ));
+        let mut doc_id_mapping = Vec::with_capacity(num_remaining_points as usize);
+        for point_id in 0..num_points {
+            if !deleted[point_id] {
+                new_vector_storage.append(self.vectors.get(point_id as u32)?)?;
+                doc_id_mapping.push(self.doc_id_mapping[point_id]);
+            }
+        }
+        self.vectors = new_vector_storage;
+        self.doc_id_mapping = doc_id_mapping;
+
+        let tombstones = std::mem::take(&mut self.tombstones);
+        self.multi_vector_doc_ids
+            .retain(|doc_id, _| !tombstones.contains(doc_id));
+        for point_ids in self.multi_vector_doc_ids.values_mut() {
+            for point_id in point_ids.iter_mut() {
+                *point_id = assigned_ids[*point_id as usize] as u32;
+            }
+        }
+        self.upper_layer_vectors.remap_point_ids(|point_id| {
+            Ok((!deleted[point_id as usize]).then(|| assigned_ids[point_id as usize] as u32))
+        })?;
+        self.original_norms = std::mem::take(&mut self.original_norms)
+            .into_iter()
+            .filter(|(point_id, _)| !deleted[*point_id as usize])
+            .map(|(point_id, norm)| (assigned_ids[point_id as usize] as u32, norm))
+            .collect();
+
+        self.current_top_layer = self.layers.len().saturating_sub(1) as u8;
+        self.entry_point = match self.layers.last() {
+            Some(layer) => {
+                let mut entry_point: Vec<u32> = layer.edges.keys().copied().collect();
+                entry_point.sort();
+                entry_point
+            }
+            None => vec![],
+        };
+        self.num_in_memory_edges = self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.edges.values())
+            .map(|edges| edges.len())
+            .sum();
         Ok(())
     }
 
+    /// Removes the deleted points from `layer`. Points that had a deleted neighbor pick their
+    /// neighbors again among their remaining ones and the remaining neighbors of the deleted
+    /// ones, so that the graph stays connected.
+    fn remove_deleted_points(&mut self, layer: u8, deleted: &BitVec) {
+        let edges = &self.layers[layer as usize].edges;
+        let mut relinked_edges = HashMap::new();
+        for (point_id, point_edges) in edges.iter() {
+            if deleted[*point_id as usize]
+                || !point_edges.iter().any(|e| deleted[e.point_id as usize])
+            {
+                continue;
+            }
+            let mut candidate_ids = HashSet::new();
+            for e in point_edges {
+                if !deleted[e.point_id as usize] {
+                    candidate_ids.insert(e.point_id);
+                    continue;
+                }
+                for neighbor in edges.get(&e.point_id).into_iter().flatten() {
+                    if !deleted[neighbor.point_id as usize] {
+                        candidate_ids.insert(neighbor.point_id);
+                    }
+                }
+            }
+            candidate_ids.remove(point_id);
+
+            let mut candidates = candidate_ids
+                .into_iter()
+                .map(|candidate_id| PointAndDistance {
+                    point_id: candidate_id,
+                    distance: NotNan::new(self.distance_two_points(*point_id, candidate_id, layer))
+                        .unwrap(),
+                })
+                .collect::<Vec<_>>();
+            candidates.sort();
+            relinked_edges.insert(
+                *point_id,
+                self.select_neighbors_heuristic(&candidates, self.max_neighbors, layer),
+            );
+        }
+
+        let edges = &mut self.layers[layer as usize].edges;
+        edges.retain(|point_id, _| !deleted[*point_id as usize]);
+        edges.extend(relinked_edges);
+    }
+
     pub fn get_nodes_from_non_bottom_layer(&self) -> Vec<u32> {
         let mut nodes = HashSet::new();
         let mut current_layer = self.current_top_layer;
@@ -400,9 +1155,43 @@ impl<Q: Quantizer> HnswBuilder<Q> {
         nodes.into_iter().collect()
     }
 
-    /// Compute the distance between two points.
-    /// The vectors are quantized.
-    fn distance_two_points(&self, a: u32, b: u32) -> f32 {
+    /// Search `layer` while inserting `vector`. Layers >= 1 use exact distances when only layer 0
+    /// is quantized.
+    fn search_layer_for_insert(
+        &self,
+        context: &mut BuilderContext,
+        quantized_query: &[Q::QuantizedT],
+        vector: &[f32],
+        entry_point: u32,
+        ef: u32,
+        layer: u8,
+    ) -> Result<Vec<PointAndDistance>> {
+        if !self.quantize_layer_0 || layer == 0 {
+            return self.search_layer(context, quantized_query, entry_point, ef, layer);
+        }
+        self.search_layer_with_distance(context, entry_point, ef, layer, |graph, point_id, _| {
+            match graph.upper_layer_vectors.get(point_id) {
+                Some(point) => graph.quantizer.original_distance(vector, point),
+                None => Q::QuantizedT::distance(
+                    quantized_query,
+                    graph.get_vector(point_id),
+                    &graph.quantizer,
+                ),
+            }
+        })
+    }
+
+    /// Compute the distance between two points at `layer`.
+    /// The vectors are quantized, unless only layer 0 is quantized and `layer` >= 1.
+    fn distance_two_points(&self, a: u32, b: u32, layer: u8) -> f32 {
+        if layer > 0 {
+            if let (Some(a_vector), Some(b_vector)) = (
+                self.upper_layer_vectors.get(a),
+                self.upper_layer_vectors.get(b),
+            ) {
+                return self.quantizer.original_distance(a_vector, b_vector);
+            }
+        }
         let a_vector = self.get_vector(a);
         let b_vector = self.get_vector(b);
         Q::QuantizedT::distance(a_vector, b_vector, &self.quantizer)
@@ -412,6 +1201,17 @@ impl<Q: Quantizer> HnswBuilder<Q> {
         self.vectors.get(point_id).unwrap()
     }
 
+    /// Returns the vector of `point_id` as it was inserted. With `normalize_on_insert`, the
+    /// stored vector is scaled back by its norm, so it is exact up to rounding and quantization.
+    pub fn get_original(&self, point_id: u32) -> Option<Vec<f32>> {
+        let vector = self.vectors.get(point_id).ok()?;
+        let mut original = self.quantizer.original_vector(vector);
+        if let Some(norm) = self.original_norms.get(&point_id) {
+            original.iter_mut().for_each(|v| *v *= norm);
+        }
+        Some(original)
+    }
+
     fn get_random_layer(&self) -> u8 {
         let mut rng = rand::thread_rng();
         let random = rng.gen::<f32>();
@@ -423,6 +1223,7 @@ impl<Q: Quantizer> HnswBuilder<Q> {
         &self,
         candidates: &[PointAndDistance],
         num_neighbors: usize,
+        layer: u8,
     ) -> Vec<PointAndDistance> {
         let mut working_list = BinaryHeap::new();
         let mut return_list: Vec<PointAndDistance> = vec![];
@@ -440,7 +1241,7 @@ impl<Q: Quantizer> HnswBuilder<Q> {
             let e_id = e.point_id;
             let mut good = true;
             for x_id in return_list.iter() {
-                let distance_x_e = self.distance_two_points(e_id, (*x_id).point_id);
+                let distance_x_e = self.distance_two_points(e_id, (*x_id).point_id, layer);
                 if distance_x_e < distance_e_q {
                     good = false;
                     break;
@@ -471,7 +1272,7 @@ impl<Q: Quantizer> HnswBuilder<Q> {
         }
     }
 
-    pub fn vectors(&mut self) -> &mut Box<dyn VectorStorage<Q::QuantizedT>> {
+    pub fn vectors(&mut self) -> &mut Box<dyn VectorStorage<Q::QuantizedT> + Send + Sync> {
         &mut self.vectors
     }
 
@@ -513,12 +1314,11 @@ impl<Q: Quantizer> GraphTraversal<Q> for HnswBuilder<Q> {
         Q::QuantizedT::distance(query, point, &self.quantizer)
     }
 
-    fn get_edges_for_point(&self, point_id: u32, layer: u8) -> Option<Vec<u32>> {
-        let layer = &self.layers[layer as usize];
-        if !layer.edges.contains_key(&point_id) {
-            return None;
-        }
-        Some(layer.edges[&point_id].iter().map(|x| x.point_id).collect())
+    fn get_edges_for_point(&self, point_id: u32, layer: u8) -> Result<Option<Vec<u32>>> {
+        Ok(self
+            .edges_for_point(layer, point_id)
+            .context("Failed to read spilled edges")?
+            .map(|edges| edges.iter().map(|x| x.point_id).collect()))
     }
 
     fn print_graph(&self, _layer: u8, _predicate: impl Fn(u8, u32) -> bool) {
@@ -532,11 +1332,17 @@ impl<Q: Quantizer> GraphTraversal<Q> for HnswBuilder<Q> {
 mod tests {
     use std::fs;
 
+    use quantization::noq::noq::NoQuantizer;
     use quantization::pq::pq::ProductQuantizer;
+    use quantization::quantization::WritableQuantizer;
+    use utils::distance::dot_product::DotProductDistanceCalculator;
     use utils::distance::l2::L2DistanceCalculator;
     use utils::test_utils::generate_random_vector;
+    use utils::DistanceCalculator;
 
     use super::*;
+    use crate::hnsw::reader::HnswReader;
+    use crate::hnsw::writer::HnswWriter;
     use crate::vector::file::FileBackedAppendableVectorStorage;
 
     #[test]
@@ -612,6 +1418,22 @@ mod tests {
             entry_point: vec![0, 1],
             max_layer: 0,
             doc_id_mapping: id_provider,
+            multi_vector_doc_ids: HashMap::from([(100, vec![0, 2])]),
+            skip_vector_validation: false,
+            quantize_layer_0: false,
+            upper_layer_vectors: UpperLayerVectors::new(
+                format!("{}/upper_layer_vectors", base_directory),
+                1024,
+                4096,
+            ),
+            normalize_on_insert: false,
+            original_norms: HashMap::new(),
+            base_directory: base_directory.clone(),
+            max_graph_memory_bytes: None,
+            num_in_memory_edges: 4,
+            edge_spill: None,
+            next_point_to_spill: 0,
+            reloaded_edges: vec![],
         };
         builder.reindex(base_directory.clone()).unwrap();
 
@@ -632,6 +1454,7 @@ mod tests {
             );
         }
         assert_eq!(builder.entry_point, vec![0, 2]);
+        assert_eq!(builder.multi_vector_doc_ids[&100], vec![0, 1]);
         assert_eq!(
             builder.layers[0].edges.get(&0).unwrap(),
             &vec![PointAndDistance {
@@ -744,4 +1567,212 @@ mod tests {
 
         assert!(builder.validate());
     }
+
+    #[test]
+    fn test_compact() {
+        let temp_dir = tempdir::TempDir::new("hnsw_compact_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(8);
+        let mut builder =
+            HnswBuilder::new(10, 4, 20, 1024, 4096, 8, quantizer, base_directory.clone());
+        let datapoints: Vec<Vec<f32>> = (0..500).map(|_| generate_random_vector(8)).collect();
+        for (i, datapoint) in datapoints.iter().enumerate() {
+            builder.insert(i as u128, datapoint).unwrap();
+        }
+        for doc_id in 0..250u128 {
+            builder.delete(doc_id * 2).unwrap();
+        }
+
+        builder.compact(base_directory).unwrap();
+        assert!(builder.validate());
+        assert!(builder.tombstones.is_empty());
+        assert_eq!(builder.vectors().len(), 250);
+        assert_eq!(
+            builder.doc_id_mapping,
+            (0..250u128).map(|i| i * 2 + 1).collect::<Vec<_>>()
+        );
+        assert_eq!(builder.layers[0].edges.len(), 250);
+        for layer in builder.layers.iter() {
+            for edges in layer.edges.values() {
+                assert!(edges.iter().all(|e| e.point_id < 250));
+            }
+        }
+        assert_eq!(
+            builder.layers.last().unwrap().edges.len(),
+            builder.entry_point.len()
+        );
+    }
+
+    #[test]
+    fn test_normalize_on_insert() {
+        let dimension = 16;
+        // Centered vectors with different norms
+        let datapoints: Vec<Vec<f32>> = (0..500)
+            .map(|i| {
+                generate_random_vector(dimension)
+                    .iter()
+                    .map(|v| (v - 0.5) * (1 + i % 7) as f32)
+                    .collect()
+            })
+            .collect();
+        let temp_dir = tempdir::TempDir::new("test_normalize_on_insert").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let vector_dir = format!("{}/vectors", base_directory);
+        fs::create_dir_all(vector_dir.clone()).unwrap();
+        let quantizer = NoQuantizer::<DotProductDistanceCalculator>::new(dimension);
+        let quantizer_dir = format!("{}/quantizer", base_directory);
+        fs::create_dir_all(quantizer_dir.clone()).unwrap();
+        quantizer.write_to_directory(&quantizer_dir).unwrap();
+
+        let mut builder =
+            HnswBuilder::new(16, 4, 100, 1024, 4096, dimension, quantizer, vector_dir);
+        builder.set_normalize_on_insert(true);
+        for (i, datapoint) in datapoints.iter().enumerate() {
+            builder.insert(i as u128, datapoint).unwrap();
+        }
+        assert!(builder.insert(500, &vec![0.0; dimension]).is_err());
+
+        for (i, datapoint) in datapoints.iter().enumerate() {
+            let norm = builder
+                .get_vector(i as u32)
+                .iter()
+                .map(|v| v * v)
+                .sum::<f32>();
+            assert!((norm - 1.0).abs() < 1e-5);
+            let original = builder.get_original(i as u32).unwrap();
+            for (a, b) in original.iter().zip(datapoint.iter()) {
+                assert!((a - b).abs() < 1e-5);
+            }
+        }
+
+        let hnsw_dir = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(hnsw_dir.clone()).unwrap();
+        HnswWriter::new(hnsw_dir)
+            .write(&mut builder, false)
+            .unwrap();
+        let hnsw = HnswReader::new(base_directory)
+            .read::<NoQuantizer<DotProductDistanceCalculator>>()
+            .unwrap();
+
+        let cosine = |a: &[f32], b: &[f32]| {
+            let dot = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>();
+            let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            dot / (norm(a) * norm(b))
+        };
+        let mut num_found = 0;
+        let num_queries = 100;
+        for _ in 0..num_queries {
+            let query: Vec<f32> = generate_random_vector(dimension)
+                .iter()
+                .map(|v| (v - 0.5) * 3.0)
+                .collect();
+            let mut similarities = datapoints
+                .iter()
+                .enumerate()
+                .map(|(i, datapoint)| (NotNan::new(cosine(&query, datapoint)).unwrap(), i as u128))
+                .collect::<Vec<_>>();
+            similarities.sort_by(|a, b| b.cmp(a));
+            let expected = similarities[..10]
+                .iter()
+                .map(|(_, id)| *id)
+                .collect::<HashSet<_>>();
+            num_found += hnsw
+                .ann_search(&query, 10, 100, &mut SearchContext::new(false))
+                .unwrap()
+                .iter()
+                .filter(|result| expected.contains(&result.id))
+                .count();
+        }
+        assert!(num_found as f32 / (num_queries * 10) as f32 >= 0.95);
+    }
+
+    /// Builds an index over `datapoints` and returns its recall@10 for `queries`.
+    fn build_and_measure_recall(
+        datapoints: &[Vec<f32>],
+        queries: &[Vec<f32>],
+        parallel: bool,
+    ) -> f32 {
+        let dimension = datapoints[0].len();
+        let temp_dir = tempdir::TempDir::new("hnsw_recall_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let vector_dir = format!("{}/vectors", base_directory);
+        fs::create_dir_all(vector_dir.clone()).unwrap();
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(dimension);
+        let quantizer_dir = format!("{}/quantizer", base_directory);
+        fs::create_dir_all(quantizer_dir.clone()).unwrap();
+        quantizer.write_to_directory(&quantizer_dir).unwrap();
+
+        let mut builder =
+            HnswBuilder::new(16, 4, 100, 1024, 4096, dimension, quantizer, vector_dir);
+        if parallel {
+            let rows = datapoints
+                .iter()
+                .enumerate()
+                .map(|(i, datapoint)| (i as u64, datapoint.as_slice()))
+                .collect::<Vec<_>>();
+            builder.par_insert_batch(&rows).unwrap();
+        } else {
+            for (i, datapoint) in datapoints.iter().enumerate() {
+                builder.insert(i as u128, datapoint).unwrap();
+            }
+        }
+        assert!(builder.validate());
+        assert_eq!(builder.layers[0].edges.len(), datapoints.len());
+
+        let hnsw_dir = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(hnsw_dir.clone()).unwrap();
+        HnswWriter::new(hnsw_dir)
+            .write(&mut builder, false)
+            .unwrap();
+        let hnsw = HnswReader::new(base_directory)
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .unwrap();
+
+        let mut num_found = 0;
+        for query in queries {
+            let mut distances = datapoints
+                .iter()
+                .enumerate()
+                .map(|(i, datapoint)| {
+                    (
+                        NotNan::new(L2DistanceCalculator::calculate(query, datapoint)).unwrap(),
+                        i as u128,
+                    )
+                })
+                .collect::<Vec<_>>();
+            distances.sort();
+            let expected = distances[..10]
+                .iter()
+                .map(|(_, id)| *id)
+                .collect::<HashSet<_>>();
+            num_found += hnsw
+                .ann_search(query, 10, 50, &mut SearchContext::new(false))
+                .unwrap()
+                .iter()
+                .filter(|result| expected.contains(&result.id))
+                .count();
+        }
+        num_found as f32 / (queries.len() * 10) as f32
+    }
+
+    #[test]
+    fn test_par_insert_batch_recall() {
+        let dimension = 16;
+        // Enough points for a few parallel chunks after the sequential ones
+        let datapoints: Vec<Vec<f32>> = (0..4 * PARALLEL_INSERT_CHUNK_SIZE)
+            .map(|_| generate_random_vector(dimension))
+            .collect();
+        let queries: Vec<Vec<f32>> = (0..100)
+            .map(|_| generate_random_vector(dimension))
+            .collect();
+
+        let sequential_recall = build_and_measure_recall(&datapoints, &queries, false);
+        let parallel_recall = build_and_measure_recall(&datapoints, &queries, true);
+        assert!(
+            parallel_recall >= sequential_recall - 0.02,
+            "parallel recall {} vs sequential recall {}",
+            parallel_recall,
+            sequential_recall
+        );
+    }
 }
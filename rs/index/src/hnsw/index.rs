@@ -1,23 +1,73 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
 
-use log::debug;
+use anyhow::Result;
+use log::{debug, error};
 use memmap2::Mmap;
 use num_traits::ToPrimitive;
 use quantization::quantization::Quantizer;
 use quantization::typing::VectorOps;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use utils::distance::l2::L2DistanceCalculatorImpl::StreamingSIMD;
 
-use super::utils::GraphTraversal;
+use super::utils::{degree_histogram, GraphTraversal};
 use crate::hnsw::writer::Header;
 use crate::index::Searchable;
-use crate::utils::{IdWithScore, SearchContext};
+use crate::utils::{IdWithScore, PointAndDistance, SearchContext, SearchResult};
 use crate::vector::fixed_file::FixedFileVectorStorage;
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerStats {
+    pub num_points: usize,
+    pub num_edges: usize,
+    // (degree, number of points) pairs, by increasing degree
+    pub degree_histogram: Vec<(usize, usize)>,
+}
+
+/// Offsets of the sections of an HNSW index file, in bytes from the start of the mmap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HnswLayout {
+    pub data_offset: usize,
+    pub edges_offset: usize,
+    pub points_offset: usize,
+    pub edge_offsets_offset: usize,
+    pub level_offsets_offset: usize,
+    pub doc_id_mapping_offset: usize,
+}
+
+impl HnswLayout {
+    /// Computes the section offsets from the header, `data_offset` being where the header ends.
+    /// Sections are padded to the alignment of their elements.
+    pub fn new(header: &Header, data_offset: usize) -> Self {
+        let edges_padding = (4 - (data_offset % 4)) % 4;
+        let edges_offset = data_offset + edges_padding;
+        let points_offset = edges_offset + header.edges_len as usize;
+
+        let edge_offsets_padding = (8 - ((points_offset + header.points_len as usize) % 8)) % 8;
+        let edge_offsets_offset = points_offset + header.points_len as usize + edge_offsets_padding;
+        let level_offsets_offset = edge_offsets_offset + header.edge_offsets_len as usize;
+
+        let doc_id_mapping_padding =
+            (16 - ((level_offsets_offset + header.level_offsets_len as usize) % 16)) % 16;
+        let doc_id_mapping_offset =
+            level_offsets_offset + header.level_offsets_len as usize + doc_id_mapping_padding;
+        Self {
+            data_offset,
+            edges_offset,
+            points_offset,
+            edge_offsets_offset,
+            level_offsets_offset,
+            doc_id_mapping_offset,
+        }
+    }
+}
+
 pub struct Hnsw<Q: Quantizer> {
-    // Need this for mmap
+    // Need this for mmap. None when the index was built from bytes.
     #[allow(dead_code)]
-    backing_file: File,
+    backing_file: Option<File>,
     mmap: Mmap,
 
     pub vector_storage: FixedFileVectorStorage<Q::QuantizedT>,
@@ -30,39 +80,41 @@ pub struct Hnsw<Q: Quantizer> {
     level_offsets_offset: usize,
     doc_id_mapping_offset: usize,
 
+    // Points of documents with multiple vectors, keyed by doc id.
+    multi_vector_doc_ids: HashMap<u128, Vec<u32>>,
+
+    // Deleted doc ids, which are never returned by searches.
+    tombstones: HashSet<u128>,
+
+    // Full-precision vectors of points at layers >= 1, when only layer 0 is quantized.
+    upper_layer_vectors: HashMap<u32, Vec<f32>>,
+
     pub quantizer: Q,
 }
 
 impl<Q: Quantizer> Hnsw<Q> {
     pub fn new(
-        backing_file: File,
+        backing_file: Option<File>,
+        mmap: Mmap,
         vector_storage: FixedFileVectorStorage<Q::QuantizedT>,
         header: Header,
-        data_offset: usize,
-        edges_offset: usize,
-        points_offset: usize,
-        edge_offsets_offset: usize,
-        level_offsets_offset: usize,
-        doc_id_mapping_offset: usize,
-        base_directory: String,
+        layout: HnswLayout,
+        quantizer: Q,
     ) -> Self {
-        // Read quantizer
-        let quantizer_directory = format!("{}/quantizer", base_directory);
-
-        let quantizer = Q::read(quantizer_directory).unwrap();
-        let index_mmap = unsafe { Mmap::map(&backing_file).unwrap() };
-
         Self {
             backing_file,
-            mmap: index_mmap,
+            mmap,
             vector_storage,
             header,
-            data_offset,
-            edges_offset,
-            points_offset,
-            edge_offsets_offset,
-            level_offsets_offset,
-            doc_id_mapping_offset,
+            data_offset: layout.data_offset,
+            edges_offset: layout.edges_offset,
+            points_offset: layout.points_offset,
+            edge_offsets_offset: layout.edge_offsets_offset,
+            level_offsets_offset: layout.level_offsets_offset,
+            doc_id_mapping_offset: layout.doc_id_mapping_offset,
+            multi_vector_doc_ids: HashMap::new(),
+            tombstones: HashSet::new(),
+            upper_layer_vectors: HashMap::new(),
             quantizer,
         }
     }
@@ -81,13 +133,81 @@ impl<Q: Quantizer> Hnsw<Q> {
         k: usize,
         ef: u32,
         context: &mut SearchContext,
-    ) -> Vec<IdWithScore> {
+    ) -> Result<Vec<IdWithScore>> {
+        let (_, working_set) = self.search_bottom_layer(query, ef, context)?;
+        debug!(
+            "[ANN] number of pages accessed: {:?}",
+            context.num_pages_accessed()
+        );
+        Ok(self.to_results(working_set, k))
+    }
+
+    /// Returns the documents within `radius` of the query, closest first. The points found by
+    /// the search of the bottom layer are extended with their neighbors within `radius`, and so
+    /// on, until no new point is found or `max_results` points are collected.
+    pub fn radius_search(
+        &self,
+        query: &[f32],
+        radius: f32,
+        max_results: usize,
+        ef: u32,
+        context: &mut SearchContext,
+    ) -> Result<Vec<IdWithScore>> {
+        let (quantized_query, working_set) = self.search_bottom_layer(query, ef, context)?;
+        let mut within_radius: Vec<PointAndDistance> = working_set
+            .into_iter()
+            .filter(|x| *x.distance <= radius)
+            .take(max_results)
+            .collect();
+
+        let mut seen: HashSet<u32> = within_radius.iter().map(|x| x.point_id).collect();
+        let mut queue: VecDeque<u32> = within_radius.iter().map(|x| x.point_id).collect();
+        'expand: while let Some(point_id) = queue.pop_front() {
+            for neighbor in self.get_edges_for_point(point_id, 0)?.unwrap_or_default() {
+                if within_radius.len() >= max_results {
+                    break 'expand;
+                }
+                if !seen.insert(neighbor) {
+                    continue;
+                }
+                let distance = self.distance(&quantized_query, neighbor, context);
+                if distance <= radius {
+                    within_radius.push(PointAndDistance::new(distance, neighbor));
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        within_radius.sort();
+        Ok(self.to_results(within_radius, max_results))
+    }
+
+    /// Descends the upper layers to the closest entry point, then searches the bottom layer.
+    /// Returns the quantized query and the points found, closest first.
+    fn search_bottom_layer(
+        &self,
+        query: &[f32],
+        ef: u32,
+        context: &mut SearchContext,
+    ) -> Result<(Vec<Q::QuantizedT>, Vec<PointAndDistance>)> {
         let quantized_query = Q::QuantizedT::process_vector(query, &self.quantizer);
         let mut current_layer: i32 = self.header.num_layers as i32 - 1;
         let mut ep = self.get_entry_point_top_layer();
         let mut working_set;
         while current_layer > 0 {
-            working_set = self.search_layer(context, &quantized_query, ep, ef, current_layer as u8);
+            working_set = if self.upper_layer_vectors.is_empty() {
+                self.search_layer(context, &quantized_query, ep, ef, current_layer as u8)?
+            } else {
+                self.search_layer_with_distance(
+                    context,
+                    ep,
+                    ef,
+                    current_layer as u8,
+                    |hnsw, point_id, context| match hnsw.upper_layer_vectors.get(&point_id) {
+                        Some(point) => hnsw.quantizer.original_distance(query, point),
+                        None => hnsw.distance(&quantized_query, point_id, context),
+                    },
+                )?
+            };
             ep = working_set
                 .iter()
                 .min_by(|x, y| x.distance.cmp(&y.distance))
@@ -96,20 +216,24 @@ impl<Q: Quantizer> Hnsw<Q> {
             current_layer -= 1;
         }
 
-        working_set = self.search_layer(context, &quantized_query, ep, ef, 0);
+        working_set = self.search_layer(context, &quantized_query, ep, ef, 0)?;
         working_set.sort_by(|x, y| x.distance.cmp(&y.distance));
-        working_set.truncate(k);
+        Ok((quantized_query, working_set))
+    }
+
+    /// Maps sorted points to at most `k` results, skipping deleted documents.
+    fn to_results(&self, working_set: Vec<PointAndDistance>, k: usize) -> Vec<IdWithScore> {
         let point_ids: Vec<u32> = working_set.iter().map(|x| x.point_id).collect();
         let doc_ids = self.map_point_id_to_doc_id(&point_ids);
 
-        debug!(
-            "[ANN] number of pages accessed: {:?}",
-            context.num_pages_accessed()
-        );
-
+        // Documents with multiple vectors only keep their closest point.
+        let mut seen_doc_ids = HashSet::new();
         working_set
             .into_iter()
             .zip(doc_ids)
+            .filter(|(_, y)| !self.tombstones.contains(y))
+            .filter(|(_, y)| self.multi_vector_doc_ids.is_empty() || seen_doc_ids.insert(*y))
+            .take(k)
             .map(|(x, y)| IdWithScore {
                 id: y,
                 score: x.distance.to_f32().unwrap(),
@@ -117,6 +241,35 @@ impl<Q: Quantizer> Hnsw<Q> {
             .collect()
     }
 
+    /// Returns the points of a document inserted with multiple vectors
+    pub fn get_point_ids_for_doc(&self, doc_id: u128) -> Option<&[u32]> {
+        self.multi_vector_doc_ids.get(&doc_id).map(|x| x.as_slice())
+    }
+
+    pub fn get_multi_vector_doc_ids(&self) -> &HashMap<u128, Vec<u32>> {
+        &self.multi_vector_doc_ids
+    }
+
+    pub fn set_multi_vector_doc_ids(&mut self, multi_vector_doc_ids: HashMap<u128, Vec<u32>>) {
+        self.multi_vector_doc_ids = multi_vector_doc_ids;
+    }
+
+    pub fn get_tombstones(&self) -> &HashSet<u128> {
+        &self.tombstones
+    }
+
+    pub fn set_tombstones(&mut self, tombstones: HashSet<u128>) {
+        self.tombstones = tombstones;
+    }
+
+    pub fn get_upper_layer_vectors(&self) -> &HashMap<u32, Vec<f32>> {
+        &self.upper_layer_vectors
+    }
+
+    pub fn set_upper_layer_vectors(&mut self, upper_layer_vectors: HashMap<u32, Vec<f32>>) {
+        self.upper_layer_vectors = upper_layer_vectors;
+    }
+
     pub fn get_header(&self) -> &Header {
         &self.header
     }
@@ -125,7 +278,7 @@ impl<Q: Quantizer> Hnsw<Q> {
         self.data_offset
     }
 
-    fn get_vector(&self, point_id: u32, context: &mut SearchContext) -> &[Q::QuantizedT] {
+    fn get_vector(&self, point_id: u32, context: &mut SearchContext) -> Cow<'_, [Q::QuantizedT]> {
         self.vector_storage.get(point_id as usize, context).unwrap()
     }
 
@@ -214,6 +367,57 @@ impl<Q: Quantizer> Hnsw<Q> {
         points[rng.gen_range(0..points.len())]
     }
 
+    /// Returns the number of points and edges in each layer, starting from the bottom layer.
+    pub fn layer_stats(&self) -> Vec<LayerStats> {
+        let num_layers = self.header.num_layers as usize;
+        let level_offsets = self.get_level_offsets_slice();
+        let edge_offsets = self.get_edge_offsets_slice();
+        (0..num_layers)
+            .map(|layer| {
+                let level_idx_start = level_offsets[num_layers - 1 - layer] as usize;
+                let level_idx_end = level_offsets[num_layers - layer] as usize;
+                // The bottom layer has one extra edge offset appended at the end.
+                let num_points = if layer > 0 {
+                    level_idx_end - level_idx_start
+                } else {
+                    level_idx_end - level_idx_start - 1
+                };
+                let num_edges = (edge_offsets[level_idx_start + num_points]
+                    - edge_offsets[level_idx_start]) as usize;
+                let degrees = (level_idx_start..level_idx_start + num_points)
+                    .map(|idx| (edge_offsets[idx + 1] - edge_offsets[idx]) as usize);
+                LayerStats {
+                    num_points,
+                    num_edges,
+                    degree_histogram: degree_histogram(degrees),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a multi-line, human-readable summary of this index.
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        description.push_str("HNSW index\n");
+        description.push_str(&format!("  layers: {}\n", self.header.num_layers));
+        for (layer, stats) in self.layer_stats().iter().enumerate() {
+            let average_degree = if stats.num_points == 0 {
+                0.0
+            } else {
+                stats.num_edges as f64 / stats.num_points as f64
+            };
+            let max_degree = stats
+                .degree_histogram
+                .last()
+                .map_or(0, |(degree, _)| *degree);
+            description.push_str(&format!(
+                "  layer {}: {} points, {} edges, average degree {:.2}, max degree {}\n",
+                layer, stats.num_points, stats.num_edges, average_degree, max_degree
+            ));
+        }
+        description
+    }
+
     #[cfg(test)]
     pub fn get_doc_id_test(&self, point_ids: &[u32]) -> Vec<u128> {
         let doc_id_mapping = self.get_doc_id_mapping_slice();
@@ -276,10 +480,10 @@ impl<Q: Quantizer> GraphTraversal<Q> for Hnsw<Q> {
 
     fn distance(&self, query: &[Q::QuantizedT], point_id: u32, context: &mut SearchContext) -> f32 {
         let point = self.get_vector(point_id, context);
-        self.quantizer.distance(query, point, StreamingSIMD)
+        self.quantizer.distance(query, &point, StreamingSIMD)
     }
 
-    fn get_edges_for_point(&self, point_id: u32, layer: u8) -> Option<Vec<u32>> {
+    fn get_edges_for_point(&self, point_id: u32, layer: u8) -> Result<Option<Vec<u32>>> {
         let num_layers = self.header.num_layers as usize;
         let level_idx_start =
             self.get_level_offsets_slice()[num_layers - 1 - layer as usize] as usize;
@@ -304,7 +508,7 @@ impl<Q: Quantizer> GraphTraversal<Q> for Hnsw<Q> {
         }
 
         if idx_at_layer < 0 {
-            return None;
+            return Ok(None);
         }
 
         let idx = idx_at_layer as usize;
@@ -312,11 +516,11 @@ impl<Q: Quantizer> GraphTraversal<Q> for Hnsw<Q> {
         let end_idx_edges = self.get_edge_offsets_slice()[level_idx_start + idx + 1];
 
         if start_idx_edges == end_idx_edges {
-            return None;
+            return Ok(None);
         }
 
         let edges = &self.get_edges_slice()[start_idx_edges as usize..end_idx_edges as usize];
-        Some(edges.to_vec())
+        Ok(Some(edges.to_vec()))
     }
 
     fn print_graph(&self, layer: u8, predicate: impl Fn(u8, u32) -> bool) {
@@ -389,10 +593,27 @@ impl<Q: Quantizer> Searchable for Hnsw<Q> {
         &self,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
+        context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        match self.ann_search(query, k, ef_search, context) {
+            Ok(results) => Some(results.into()),
+            Err(e) => {
+                error!("Failed to search the HNSW index: {}", e);
+                None
+            }
+        }
+    }
+
+    fn search_radius(
+        &self,
+        query: &[f32],
+        radius: f32,
+        max_results: usize,
+        ef_search: u32,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>> {
-        Some(self.ann_search(query, k, ef_construction, context))
+    ) -> Result<Vec<IdWithScore>> {
+        self.radius_search(query, radius, max_results, ef_search, context)
     }
 }
 
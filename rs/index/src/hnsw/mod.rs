@@ -1,5 +1,6 @@
 pub mod builder;
 pub mod index;
 pub mod reader;
+pub mod spill;
 pub mod utils;
 pub mod writer;
@@ -1,14 +1,23 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use byteorder::{ByteOrder, LittleEndian};
-use memmap2::Mmap;
+use memmap2::{Mmap, MmapMut};
 use quantization::quantization::Quantizer;
 
-use crate::hnsw::index::Hnsw;
-use crate::hnsw::writer::{Header, Version};
+use crate::hnsw::index::{Hnsw, HnswLayout};
+use crate::hnsw::writer::{
+    Header, Version, MULTI_VECTOR_DOC_IDS_FILE_NAME, TOMBSTONES_FILE_NAME,
+    UPPER_LAYER_VECTORS_FILE_NAME,
+};
+use crate::vector::compressed::open_vector_storage;
 use crate::vector::fixed_file::FixedFileVectorStorage;
 
+// Version (u8), quantized dimension (u32), number of layers (u32) and five section lengths (u64)
+pub(crate) const HEADER_LEN: usize = 1 + 4 + 4 + 5 * 8;
+
 pub struct HnswReader {
     base_directory: String,
     index_offset: usize,
@@ -24,6 +33,9 @@ impl HnswReader {
         }
     }
 
+    /// Reads the index file (`hnsw/index`) and the vector file (`hnsw/vector_storage`) starting
+    /// at the given byte offsets, e.g. when they are stored inside a larger file. Same as
+    /// `IvfReader::new_with_offsets`.
     pub fn new_with_offset(
         base_directory: String,
         index_offset: usize,
@@ -36,6 +48,36 @@ impl HnswReader {
         }
     }
 
+    /// Builds the index from the content of the index and vector files, without touching the
+    /// filesystem. The quantizer is stored in its own directory, so it has to be provided.
+    /// Multi-vector doc ids, full-precision upper layer vectors and tombstones, which are stored
+    /// in separate files, are not loaded.
+    pub fn new_in_memory<Q: Quantizer>(
+        index_bytes: &[u8],
+        vector_bytes: &[u8],
+        quantizer: Q,
+    ) -> Result<Hnsw<Q>> {
+        let mut mmap = MmapMut::map_anon(index_bytes.len().max(1))?;
+        mmap[..index_bytes.len()].copy_from_slice(index_bytes);
+        let mmap = mmap.make_read_only()?;
+
+        let (header, offset) = Self::parse_header(&mmap, 0)?;
+        let vector_storage = FixedFileVectorStorage::<Q::QuantizedT>::new_from_bytes(
+            vector_bytes,
+            header.quantized_dimension as usize,
+            0,
+        )?;
+        let layout = HnswLayout::new(&header, offset);
+        Ok(Hnsw::new(
+            None,
+            mmap,
+            vector_storage,
+            header,
+            layout,
+            quantizer,
+        ))
+    }
+
     pub fn read<Q: Quantizer>(&self) -> Result<Hnsw<Q>> {
         let backing_file = File::open(format!("{}/hnsw/index", self.base_directory))?;
         let mmap = unsafe { Mmap::map(&backing_file) }?;
@@ -43,47 +85,128 @@ impl HnswReader {
         let (header, offset) = self.read_header(&mmap);
 
         let vector_storage_path = format!("{}/hnsw/vector_storage", self.base_directory);
-        let vector_storage = FixedFileVectorStorage::<Q::QuantizedT>::new_with_offset(
-            vector_storage_path,
+        let vector_storage = open_vector_storage::<Q::QuantizedT>(
+            &vector_storage_path,
             header.quantized_dimension as usize,
             self.vector_offset,
         )
         .unwrap();
-        let edges_padding = (4 - (offset % 4)) % 4;
-        let edges_offset = offset + edges_padding as usize;
-        let points_offset = edges_offset + header.edges_len as usize;
+        let layout = HnswLayout::new(&header, offset);
 
-        let edge_offsets_padding = (8 - ((points_offset + header.points_len as usize) % 8)) % 8;
-        let edge_offsets_offset =
-            points_offset + header.points_len as usize + edge_offsets_padding as usize;
-        let level_offsets_offset = edge_offsets_offset + header.edge_offsets_len as usize;
+        let quantizer_directory = format!("{}/quantizer", self.base_directory);
+        let quantizer = Q::read(quantizer_directory).unwrap();
 
-        let doc_id_mapping_padding =
-            (16 - ((level_offsets_offset + header.level_offsets_len as usize) % 16)) % 16;
-        let doc_id_mapping_offset = level_offsets_offset
-            + header.level_offsets_len as usize
-            + doc_id_mapping_padding as usize;
-
-        Ok(Hnsw::new(
-            backing_file,
+        let mut hnsw = Hnsw::new(
+            Some(backing_file),
+            mmap,
             vector_storage,
             header,
-            offset,
-            edges_offset,
-            points_offset,
-            edge_offsets_offset,
-            level_offsets_offset,
-            doc_id_mapping_offset,
-            self.base_directory.clone(),
-        ))
+            layout,
+            quantizer,
+        );
+
+        let multi_vector_doc_ids_path = format!(
+            "{}/hnsw/{}",
+            self.base_directory, MULTI_VECTOR_DOC_IDS_FILE_NAME
+        );
+        if Path::new(&multi_vector_doc_ids_path).is_file() {
+            hnsw.set_multi_vector_doc_ids(Self::read_multi_vector_doc_ids(&std::fs::read(
+                multi_vector_doc_ids_path,
+            )?)?);
+        }
+
+        let upper_layer_vectors_path = format!(
+            "{}/hnsw/{}",
+            self.base_directory, UPPER_LAYER_VECTORS_FILE_NAME
+        );
+        if Path::new(&upper_layer_vectors_path).is_file() {
+            hnsw.set_upper_layer_vectors(Self::read_upper_layer_vectors(&std::fs::read(
+                upper_layer_vectors_path,
+            )?)?);
+        }
+
+        let tombstones_path = format!("{}/hnsw/{}", self.base_directory, TOMBSTONES_FILE_NAME);
+        if Path::new(&tombstones_path).is_file() {
+            hnsw.set_tombstones(Self::read_tombstones(&std::fs::read(tombstones_path)?)?);
+        }
+        Ok(hnsw)
+    }
+
+    /// Parses the content written by `HnswWriter::write_tombstones`
+    pub fn read_tombstones(buffer: &[u8]) -> Result<HashSet<u128>> {
+        if buffer.len() % 16 != 0 {
+            return Err(anyhow!(
+                "Expected tombstones to be a multiple of 16 bytes, got {} bytes",
+                buffer.len()
+            ));
+        }
+        Ok(buffer
+            .chunks_exact(16)
+            .map(LittleEndian::read_u128)
+            .collect())
+    }
+
+    /// Parses the content written by `HnswWriter::write_upper_layer_vectors`
+    pub fn read_upper_layer_vectors(buffer: &[u8]) -> Result<HashMap<u32, Vec<f32>>> {
+        if buffer.len() < 8 {
+            return Err(anyhow!("Truncated upper layer vectors header"));
+        }
+        let num_points = LittleEndian::read_u32(buffer) as usize;
+        let dimension = LittleEndian::read_u32(&buffer[4..]) as usize;
+        let entry_len = 4 + dimension * 4;
+        if buffer.len() != 8 + num_points * entry_len {
+            return Err(anyhow!(
+                "Expected {} upper layer vectors of dimension {}, got {} bytes",
+                num_points,
+                dimension,
+                buffer.len()
+            ));
+        }
+
+        let mut result = HashMap::with_capacity(num_points);
+        for entry in buffer[8..].chunks_exact(entry_len) {
+            let mut vector = vec![0.0f32; dimension];
+            LittleEndian::read_f32_into(&entry[4..], &mut vector);
+            result.insert(LittleEndian::read_u32(entry), vector);
+        }
+        Ok(result)
+    }
+
+    /// Parses the content written by `HnswWriter::write_multi_vector_doc_ids`
+    pub fn read_multi_vector_doc_ids(buffer: &[u8]) -> Result<HashMap<u128, Vec<u32>>> {
+        let mut result = HashMap::new();
+        let mut offset = 0;
+        while offset < buffer.len() {
+            if offset + 20 > buffer.len() {
+                return Err(anyhow!("Truncated multi-vector doc id entry"));
+            }
+            let doc_id = LittleEndian::read_u128(&buffer[offset..]);
+            let num_points = LittleEndian::read_u32(&buffer[offset + 16..]) as usize;
+            offset += 20;
+            if offset + num_points * 4 > buffer.len() {
+                return Err(anyhow!("Truncated point ids for doc {}", doc_id));
+            }
+            let mut point_ids = vec![0u32; num_points];
+            LittleEndian::read_u32_into(&buffer[offset..offset + num_points * 4], &mut point_ids);
+            offset += num_points * 4;
+            result.insert(doc_id, point_ids);
+        }
+        Ok(result)
     }
 
     /// Read the header from the mmap and return the header and the offset of data page
     pub fn read_header(&self, buffer: &[u8]) -> (Header, usize) {
-        let mut offset = self.index_offset;
+        Self::parse_header(buffer, self.index_offset).unwrap()
+    }
+
+    pub(crate) fn parse_header(buffer: &[u8], offset: usize) -> Result<(Header, usize)> {
+        if buffer.len() < offset + HEADER_LEN {
+            return Err(anyhow!("Truncated HNSW index header"));
+        }
+        let mut offset = offset;
         let version = match buffer[offset] {
             0 => Version::V0,
-            default => panic!("Unknown version: {}", default),
+            default => return Err(anyhow!("Unknown version: {}", default)),
         };
 
         offset += 1;
@@ -103,7 +226,7 @@ impl HnswReader {
         let doc_id_mapping_len = LittleEndian::read_u64(&buffer[offset..]);
         offset += 8;
 
-        (
+        Ok((
             Header {
                 version,
                 quantized_dimension,
@@ -115,14 +238,16 @@ impl HnswReader {
                 doc_id_mapping_len,
             },
             offset,
-        )
+        ))
     }
 }
 
 // Test
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
     use std::fs;
+    use std::path::Path;
 
     use quantization::noq::noq::NoQuantizer;
     use quantization::pq::pq::{ProductQuantizer, ProductQuantizerConfig};
@@ -130,10 +255,13 @@ mod tests {
     use quantization::quantization::WritableQuantizer;
     use utils::distance::l2::L2DistanceCalculator;
     use utils::test_utils::generate_random_vector;
+    use utils::DistanceCalculator;
 
     use super::*;
     use crate::hnsw::builder::HnswBuilder;
     use crate::hnsw::writer::HnswWriter;
+    use crate::index::Searchable;
+    use crate::utils::SearchContext;
 
     #[test]
     fn test_read_header() {
@@ -154,6 +282,7 @@ mod tests {
         let pq_builder_config = ProductQuantizerBuilderConfig {
             max_iteration: 1000,
             batch_size: 4,
+            use_annealing: false,
         };
 
         // Train a product quantizer
@@ -220,4 +349,364 @@ mod tests {
         assert_eq!(49, hnsw.get_data_offset());
         assert_eq!(128, hnsw.get_header().quantized_dimension);
     }
+
+    #[test]
+    fn test_new_in_memory() {
+        let temp_dir = tempdir::TempDir::new("test_new_in_memory").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let vector_dir = format!("{}/vectors", base_directory);
+        fs::create_dir_all(vector_dir.clone()).unwrap();
+        let datapoints: Vec<Vec<f32>> = (0..500).map(|_| generate_random_vector(8)).collect();
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(8);
+        let quantizer_dir = format!("{}/quantizer", base_directory);
+        fs::create_dir_all(quantizer_dir.clone()).unwrap();
+        assert!(quantizer.write_to_directory(&quantizer_dir).is_ok());
+
+        let mut hnsw_builder = HnswBuilder::new(10, 4, 20, 1024, 4096, 8, quantizer, vector_dir);
+        for (i, datapoint) in datapoints.iter().enumerate() {
+            hnsw_builder.insert(i as u128, datapoint).unwrap();
+        }
+        let hnsw_dir = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(hnsw_dir.clone()).unwrap();
+        let writer = HnswWriter::new(hnsw_dir.clone());
+        assert!(writer.write(&mut hnsw_builder, false).is_ok());
+
+        let on_disk = HnswReader::new(base_directory.clone())
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .unwrap();
+        let in_memory = HnswReader::new_in_memory(
+            &fs::read(format!("{}/index", hnsw_dir)).unwrap(),
+            &fs::read(format!("{}/vector_storage", hnsw_dir)).unwrap(),
+            NoQuantizer::<L2DistanceCalculator>::new(8),
+        )
+        .unwrap();
+        assert_eq!(in_memory.get_data_offset(), on_disk.get_data_offset());
+        assert_eq!(in_memory.layer_stats(), on_disk.layer_stats());
+
+        let query = generate_random_vector(8);
+        let ids = |results: Vec<crate::utils::IdWithScore>| {
+            results.into_iter().map(|r| r.id).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(in_memory
+                .ann_search(&query, 10, 50, &mut SearchContext::new(false))
+                .unwrap()),
+            ids(on_disk
+                .ann_search(&query, 10, 50, &mut SearchContext::new(false))
+                .unwrap())
+        );
+
+        assert!(HnswReader::new_in_memory(
+            &[0u8; 10],
+            &[0u8; 8],
+            NoQuantizer::<L2DistanceCalculator>::new(8),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_read_multi_vector_doc_ids() {
+        let temp_dir = tempdir::TempDir::new("hnsw_multi_vector_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let vector_dir = format!("{}/vectors", base_directory);
+        fs::create_dir_all(vector_dir.clone()).unwrap();
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(8);
+        let quantizer_dir = format!("{}/quantizer", base_directory);
+        fs::create_dir_all(quantizer_dir.clone()).unwrap();
+        assert!(quantizer.write_to_directory(&quantizer_dir).is_ok());
+
+        let mut hnsw_builder = HnswBuilder::new(10, 8, 20, 1024, 4096, 8, quantizer, vector_dir);
+        let documents: Vec<Vec<Vec<f32>>> = (0..100)
+            .map(|_| (0..3).map(|_| generate_random_vector(8)).collect())
+            .collect();
+        for (doc_id, vectors) in documents.iter().enumerate() {
+            let vectors: Vec<&[f32]> = vectors.iter().map(|v| v.as_slice()).collect();
+            hnsw_builder.insert_multi(doc_id as u128, &vectors).unwrap();
+        }
+        assert!(hnsw_builder.insert_multi(100, &[&[f32::NAN; 8]]).is_err());
+        assert!(!hnsw_builder.multi_vector_doc_ids.contains_key(&100));
+
+        let hnsw_dir = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(hnsw_dir.clone()).unwrap();
+        let writer = HnswWriter::new(hnsw_dir);
+        assert!(writer.write(&mut hnsw_builder, true).is_ok());
+
+        let reader = HnswReader::new(base_directory.clone());
+        let hnsw = reader.read::<NoQuantizer<L2DistanceCalculator>>().unwrap();
+        assert_eq!(hnsw.get_multi_vector_doc_ids().len(), 100);
+        let doc_id_mapping = hnsw.get_doc_id_mapping_slice();
+        for doc_id in 0..100u128 {
+            let point_ids = hnsw.get_point_ids_for_doc(doc_id).unwrap();
+            assert_eq!(point_ids.len(), 3);
+            for point_id in point_ids {
+                assert_eq!(doc_id_mapping[*point_id as usize], doc_id);
+            }
+        }
+
+        let mut context = SearchContext::new(false);
+        let results = hnsw
+            .ann_search(&documents[42][1], 10, 100, &mut context)
+            .unwrap();
+        assert_eq!(results.len(), 10);
+        assert_eq!(results[0].id, 42);
+        let unique: HashSet<u128> = results.iter().map(|x| x.id).collect();
+        assert_eq!(unique.len(), results.len());
+    }
+
+    #[test]
+    fn test_read_tombstones_and_compact() {
+        let temp_dir = tempdir::TempDir::new("hnsw_tombstones_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let vector_dir = format!("{}/vectors", base_directory);
+        fs::create_dir_all(vector_dir.clone()).unwrap();
+        let datapoints: Vec<Vec<f32>> = (0..1000).map(|_| generate_random_vector(8)).collect();
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(8);
+        let quantizer_dir = format!("{}/quantizer", base_directory);
+        fs::create_dir_all(quantizer_dir.clone()).unwrap();
+        assert!(quantizer.write_to_directory(&quantizer_dir).is_ok());
+
+        let mut hnsw_builder = HnswBuilder::new(10, 4, 20, 1024, 4096, 8, quantizer, vector_dir);
+        for (i, datapoint) in datapoints.iter().enumerate() {
+            hnsw_builder.insert(i as u128, datapoint).unwrap();
+        }
+        // Delete every even doc id
+        for doc_id in (0..1000u128).step_by(2) {
+            hnsw_builder.delete(doc_id).unwrap();
+        }
+        assert!(hnsw_builder.delete(1000).is_err());
+
+        let hnsw_dir = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(hnsw_dir.clone()).unwrap();
+        let writer = HnswWriter::new(hnsw_dir.clone());
+        assert!(writer
+            .write_with_tombstones(&mut hnsw_builder, false)
+            .is_ok());
+        let index_len = fs::metadata(format!("{}/index", hnsw_dir)).unwrap().len();
+        let vectors_len = fs::metadata(format!("{}/vector_storage", hnsw_dir))
+            .unwrap()
+            .len();
+
+        let assert_no_deleted_results = |hnsw: &Hnsw<NoQuantizer<L2DistanceCalculator>>| {
+            let mut context = SearchContext::new(false);
+            for doc_id in (0..1000).step_by(50) {
+                let results = hnsw
+                    .ann_search(&datapoints[doc_id], 10, 100, &mut context)
+                    .unwrap();
+                assert!(!results.is_empty());
+                assert!(results.iter().all(|result| result.id % 2 == 1));
+            }
+        };
+        let hnsw = HnswReader::new(base_directory.clone())
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .unwrap();
+        assert_eq!(hnsw.get_tombstones().len(), 500);
+        assert_no_deleted_results(&hnsw);
+        drop(hnsw);
+
+        assert!(writer.compact(&mut hnsw_builder, false).is_ok());
+        assert!(hnsw_builder.tombstones.is_empty());
+        assert_eq!(hnsw_builder.doc_id_mapping.len(), 500);
+        assert!(!Path::new(&format!("{}/{}", hnsw_dir, TOMBSTONES_FILE_NAME)).exists());
+        assert!(fs::metadata(format!("{}/index", hnsw_dir)).unwrap().len() < index_len);
+        assert!(
+            fs::metadata(format!("{}/vector_storage", hnsw_dir))
+                .unwrap()
+                .len()
+                < vectors_len
+        );
+
+        let hnsw = HnswReader::new(base_directory.clone())
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .unwrap();
+        assert!(hnsw.get_tombstones().is_empty());
+        assert_no_deleted_results(&hnsw);
+        let mut context = SearchContext::new(false);
+        let results = hnsw
+            .ann_search(&datapoints[501], 10, 100, &mut context)
+            .unwrap();
+        assert_eq!(results[0].id, 501);
+    }
+
+    #[test]
+    fn test_radius_search() {
+        let temp_dir = tempdir::TempDir::new("hnsw_radius_search_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let vector_dir = format!("{}/vectors", base_directory);
+        fs::create_dir_all(vector_dir.clone()).unwrap();
+        let datapoints: Vec<Vec<f32>> = (0..1000).map(|_| generate_random_vector(8)).collect();
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(8);
+        let quantizer_dir = format!("{}/quantizer", base_directory);
+        fs::create_dir_all(quantizer_dir.clone()).unwrap();
+        assert!(quantizer.write_to_directory(&quantizer_dir).is_ok());
+
+        let mut hnsw_builder = HnswBuilder::new(10, 4, 20, 1024, 4096, 8, quantizer, vector_dir);
+        for (i, datapoint) in datapoints.iter().enumerate() {
+            hnsw_builder.insert(i as u128, datapoint).unwrap();
+        }
+        let hnsw_dir = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(hnsw_dir.clone()).unwrap();
+        let writer = HnswWriter::new(hnsw_dir);
+        assert!(writer.write(&mut hnsw_builder, false).is_ok());
+        let hnsw = HnswReader::new(base_directory.clone())
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .unwrap();
+
+        // Pick a radius that covers the 50 closest points, more than ef
+        let query = &datapoints[0];
+        let mut distances: Vec<(u128, f32)> = datapoints
+            .iter()
+            .enumerate()
+            .map(|(i, point)| (i as u128, L2DistanceCalculator::calculate(query, point)))
+            .collect();
+        distances.sort_by(|x, y| x.1.total_cmp(&y.1));
+        let radius = distances[49].1;
+        let expected: HashSet<u128> = distances[..50].iter().map(|x| x.0).collect();
+
+        let mut context = SearchContext::new(false);
+        let results = hnsw
+            .search_radius(query, radius, usize::MAX, 20, &mut context)
+            .unwrap();
+        assert!(results.iter().all(|result| result.score <= radius));
+        assert!(results.windows(2).all(|x| x[0].score <= x[1].score));
+        let found = results
+            .iter()
+            .filter(|result| expected.contains(&result.id))
+            .count();
+        assert!(found >= 45, "radius search found {} of 50 points", found);
+
+        let results = hnsw
+            .search_radius(query, radius, 10, 20, &mut context)
+            .unwrap();
+        assert_eq!(results.len(), 10);
+        assert_eq!(results[0].id, 0);
+    }
+
+    #[test]
+    fn test_read_quantize_layer_0() {
+        let temp_dir = tempdir::TempDir::new("hnsw_quantize_layer_0_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let datapoints: Vec<Vec<f32>> = (0..2000).map(|_| generate_random_vector(16)).collect();
+
+        let pq_dir = format!("{}/quantizer", base_directory);
+        fs::create_dir_all(pq_dir.clone()).unwrap();
+        let mut pq_builder = ProductQuantizerBuilder::<L2DistanceCalculator>::new(
+            ProductQuantizerConfig {
+                dimension: 16,
+                subvector_dimension: 4,
+                num_bits: 4,
+            },
+            ProductQuantizerBuilderConfig {
+                max_iteration: 100,
+                batch_size: 16,
+                use_annealing: false,
+            },
+        );
+        for datapoint in datapoints.iter().take(500) {
+            pq_builder.add(datapoint.clone());
+        }
+        let pq = pq_builder.build(base_directory.clone()).unwrap();
+        assert!(pq.write_to_directory(&pq_dir).is_ok());
+
+        let vector_dir = format!("{}/vectors", base_directory);
+        fs::create_dir_all(vector_dir.clone()).unwrap();
+        let mut hnsw_builder = HnswBuilder::new(8, 4, 50, 1024, 4096, 4, pq, vector_dir);
+        hnsw_builder.set_quantize_layer_0(true);
+        for (i, datapoint) in datapoints.iter().enumerate() {
+            hnsw_builder.insert(i as u128, datapoint).unwrap();
+        }
+        let upper_layer_points: HashSet<u32> = hnsw_builder
+            .get_nodes_from_non_bottom_layer()
+            .into_iter()
+            .collect();
+        assert!(!upper_layer_points.is_empty());
+
+        let hnsw_dir = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(hnsw_dir.clone()).unwrap();
+        let writer = HnswWriter::new(hnsw_dir);
+        assert!(writer.write(&mut hnsw_builder, true).is_ok());
+
+        let reader = HnswReader::new(base_directory.clone());
+        let hnsw = reader
+            .read::<ProductQuantizer<L2DistanceCalculator>>()
+            .unwrap();
+        let upper_layer_vectors = hnsw.get_upper_layer_vectors();
+        assert_eq!(upper_layer_vectors.len(), upper_layer_points.len());
+        let doc_id_mapping = hnsw.get_doc_id_mapping_slice();
+        for (point_id, vector) in upper_layer_vectors {
+            assert_eq!(
+                vector,
+                &datapoints[doc_id_mapping[*point_id as usize] as usize]
+            );
+        }
+
+        let mut context = SearchContext::new(false);
+        let results = hnsw
+            .ann_search(&datapoints[7], 10, 50, &mut context)
+            .unwrap();
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().any(|x| x.id == 7));
+    }
+
+    #[test]
+    fn test_read_spilled_graph() {
+        let temp_dir = tempdir::TempDir::new("hnsw_spilled_graph_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let vector_dir = format!("{}/vectors", base_directory);
+        fs::create_dir_all(vector_dir.clone()).unwrap();
+        let datapoints: Vec<Vec<f32>> = (0..2000).map(|_| generate_random_vector(16)).collect();
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(16);
+        let quantizer_dir = format!("{}/quantizer", base_directory);
+        fs::create_dir_all(quantizer_dir.clone()).unwrap();
+        assert!(quantizer.write_to_directory(&quantizer_dir).is_ok());
+
+        let max_graph_memory_bytes = 16 * 1024;
+        let mut hnsw_builder =
+            HnswBuilder::new(10, 4, 50, 1024, 4096, 16, quantizer, vector_dir.clone());
+        hnsw_builder.set_max_graph_memory_bytes(Some(max_graph_memory_bytes));
+        for (i, datapoint) in datapoints.iter().enumerate() {
+            hnsw_builder.insert(i as u128, datapoint).unwrap();
+        }
+        assert!(hnsw_builder.num_spilled_edge_lists() > 0);
+        assert!(hnsw_builder.graph_memory_bytes() <= max_graph_memory_bytes);
+        assert!(Path::new(&format!("{}/graph_spill", vector_dir)).exists());
+        let degree_histograms = (0..hnsw_builder.layers.len())
+            .map(|layer| hnsw_builder.degree_histogram(layer))
+            .collect::<Vec<_>>();
+
+        // Without reindexing, the writer reads the spilled edges without loading them back
+        let hnsw_dir = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(hnsw_dir.clone()).unwrap();
+        let writer = HnswWriter::new(hnsw_dir);
+        assert!(writer.write(&mut hnsw_builder, false).is_ok());
+        assert!(hnsw_builder.num_spilled_edge_lists() > 0);
+
+        let reader = HnswReader::new(base_directory.clone());
+        let hnsw = reader.read::<NoQuantizer<L2DistanceCalculator>>().unwrap();
+        let layer_stats = hnsw.layer_stats();
+        assert_eq!(layer_stats.len(), degree_histograms.len());
+        for (stats, degree_histogram) in layer_stats.iter().zip(degree_histograms.iter()) {
+            assert_eq!(&stats.degree_histogram, degree_histogram);
+            let num_points: usize = degree_histogram.iter().map(|(_, count)| count).sum();
+            assert_eq!(num_points, stats.num_points);
+        }
+        assert_eq!(layer_stats[0].num_points, 2000);
+        for i in [0, 7, 1999] {
+            let mut context = SearchContext::new(false);
+            let results = hnsw
+                .ann_search(&datapoints[i], 10, 50, &mut context)
+                .unwrap();
+            assert_eq!(results.len(), 10);
+            assert_eq!(results[0].id, i as u128);
+        }
+
+        hnsw_builder.load_spilled_edges().unwrap();
+        assert_eq!(hnsw_builder.num_spilled_edge_lists(), 0);
+        drop(hnsw_builder);
+        assert!(!Path::new(&format!("{}/graph_spill", vector_dir)).exists());
+    }
 }
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::os::unix::fs::FileExt;
+
+use anyhow::Result;
+use ordered_float::NotNan;
+
+use crate::utils::PointAndDistance;
+
+// Each edge is stored as its point id (u32) followed by its distance (f32)
+const EDGE_SIZE: usize = 8;
+
+// The file is not compacted while it holds less stale data than this
+const MIN_COMPACTION_BYTES: u64 = 1 << 20;
+
+/// Neighbor lists moved out of memory while building a graph. Lists are appended to a single
+/// file and read back on demand. A list that is spilled again after being taken back is
+/// appended anew, and the file is compacted once most of it holds stale copies.
+pub struct EdgeSpill {
+    path: String,
+    file: File,
+    file_len: u64,
+    // (layer, point id) -> (offset in the file, number of edges)
+    offsets: HashMap<(u8, u32), (u64, usize)>,
+    // Bytes of the file taken by lists that were taken back or spilled again
+    stale_bytes: u64,
+}
+
+impl EdgeSpill {
+    pub fn new(path: String) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            file,
+            file_len: 0,
+            offsets: HashMap::new(),
+            stale_bytes: 0,
+        })
+    }
+
+    pub fn contains(&self, layer: u8, point_id: u32) -> bool {
+        self.offsets.contains_key(&(layer, point_id))
+    }
+
+    /// Number of edges spilled for `point_id` at `layer`, without reading them.
+    pub fn num_edges(&self, layer: u8, point_id: u32) -> Option<usize> {
+        self.offsets
+            .get(&(layer, point_id))
+            .map(|(_, num_edges)| *num_edges)
+    }
+
+    pub fn num_spilled(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Appends the edges of `point_id` at `layer` to the spill file.
+    pub fn spill(&mut self, layer: u8, point_id: u32, edges: &[PointAndDistance]) -> Result<()> {
+        let mut writer = BufWriter::new(&mut self.file);
+        for edge in edges {
+            writer.write_all(&edge.point_id.to_le_bytes())?;
+            writer.write_all(&edge.distance.into_inner().to_le_bytes())?;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        if let Some((_, num_edges)) = self
+            .offsets
+            .insert((layer, point_id), (self.file_len, edges.len()))
+        {
+            self.stale_bytes += (num_edges * EDGE_SIZE) as u64;
+        }
+        self.file_len += (edges.len() * EDGE_SIZE) as u64;
+        if self.stale_bytes >= MIN_COMPACTION_BYTES && self.stale_bytes * 2 > self.file_len {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the spilled lists to a new file, without the stale copies.
+    pub fn compact(&mut self) -> Result<()> {
+        let compacted_path = format!("{}.compact", self.path);
+        let mut compacted_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&compacted_path)?;
+        let mut entries = self.offsets.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(_, (offset, _))| *offset);
+
+        let mut offsets = HashMap::with_capacity(entries.len());
+        let mut file_len = 0;
+        let mut writer = BufWriter::new(&mut compacted_file);
+        for (key, &(offset, num_edges)) in entries {
+            let mut buffer = vec![0u8; num_edges * EDGE_SIZE];
+            self.file.read_exact_at(&mut buffer, offset)?;
+            writer.write_all(&buffer)?;
+            offsets.insert(*key, (file_len, num_edges));
+            file_len += buffer.len() as u64;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        std::fs::rename(&compacted_path, &self.path)?;
+        self.file = compacted_file;
+        self.file_len = file_len;
+        self.offsets = offsets;
+        self.stale_bytes = 0;
+        Ok(())
+    }
+
+    /// Reads the edges of `point_id` at `layer`, or None if they were not spilled.
+    pub fn get(&self, layer: u8, point_id: u32) -> Result<Option<Vec<PointAndDistance>>> {
+        let Some(&(offset, num_edges)) = self.offsets.get(&(layer, point_id)) else {
+            return Ok(None);
+        };
+        let mut buffer = vec![0u8; num_edges * EDGE_SIZE];
+        self.file.read_exact_at(&mut buffer, offset)?;
+        let edges = buffer
+            .chunks_exact(EDGE_SIZE)
+            .map(|chunk| {
+                Ok(PointAndDistance {
+                    point_id: u32::from_le_bytes(chunk[0..4].try_into()?),
+                    distance: NotNan::new(f32::from_le_bytes(chunk[4..8].try_into()?))?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(edges))
+    }
+
+    /// Same as `get`, but the edges are no longer considered spilled.
+    pub fn take(&mut self, layer: u8, point_id: u32) -> Result<Option<Vec<PointAndDistance>>> {
+        let edges = self.get(layer, point_id)?;
+        if let Some((_, num_edges)) = self.offsets.remove(&(layer, point_id)) {
+            self.stale_bytes += (num_edges * EDGE_SIZE) as u64;
+        }
+        Ok(edges)
+    }
+}
+
+impl Drop for EdgeSpill {
+    fn drop(&mut self) {
+        // Ok to ignore errors, the spill file is only a temporary file
+        std::fs::remove_file(&self.path).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn edge(point_id: u32, distance: f32) -> PointAndDistance {
+        PointAndDistance {
+            point_id,
+            distance: NotNan::new(distance).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_edge_spill() {
+        let temp_dir = TempDir::new("test_edge_spill").unwrap();
+        let path = format!("{}/spill", temp_dir.path().to_str().unwrap());
+        let mut spill = EdgeSpill::new(path.clone()).unwrap();
+
+        spill.spill(0, 1, &[edge(2, 0.5), edge(3, 1.5)]).unwrap();
+        spill.spill(1, 1, &[edge(4, 2.0)]).unwrap();
+        spill.spill(0, 5, &[]).unwrap();
+        assert_eq!(spill.num_spilled(), 3);
+        assert_eq!(spill.num_edges(0, 1), Some(2));
+        assert_eq!(spill.num_edges(0, 2), None);
+
+        assert_eq!(
+            spill.get(0, 1).unwrap(),
+            Some(vec![edge(2, 0.5), edge(3, 1.5)])
+        );
+        assert_eq!(spill.get(0, 5).unwrap(), Some(vec![]));
+        assert_eq!(spill.get(0, 2).unwrap(), None);
+
+        assert_eq!(spill.take(1, 1).unwrap(), Some(vec![edge(4, 2.0)]));
+        assert!(!spill.contains(1, 1));
+        assert!(spill.contains(0, 1));
+
+        // Spilling again appends a new copy
+        spill.spill(1, 1, &[edge(6, 3.0)]).unwrap();
+        assert_eq!(spill.get(1, 1).unwrap(), Some(vec![edge(6, 3.0)]));
+
+        drop(spill);
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_edge_spill_compact() {
+        let temp_dir = TempDir::new("test_edge_spill_compact").unwrap();
+        let path = format!("{}/spill", temp_dir.path().to_str().unwrap());
+        let mut spill = EdgeSpill::new(path.clone()).unwrap();
+        let file_len = || std::fs::metadata(&path).unwrap().len();
+
+        for point_id in 0..10 {
+            spill
+                .spill(0, point_id, &[edge(point_id + 1, 1.0)])
+                .unwrap();
+        }
+        for point_id in 0..5 {
+            let edges = spill.take(0, point_id).unwrap().unwrap();
+            spill.spill(0, point_id, &edges).unwrap();
+        }
+        spill.take(0, 9).unwrap();
+        assert_eq!(file_len(), 15 * EDGE_SIZE as u64);
+
+        spill.compact().unwrap();
+        assert_eq!(file_len(), 9 * EDGE_SIZE as u64);
+        assert_eq!(spill.num_spilled(), 9);
+        for point_id in 0..9 {
+            assert_eq!(
+                spill.get(0, point_id).unwrap(),
+                Some(vec![edge(point_id + 1, 1.0)])
+            );
+        }
+        assert_eq!(spill.get(0, 9).unwrap(), None);
+
+        // Spilling keeps appending after the compacted lists
+        spill.spill(1, 0, &[edge(7, 2.0)]).unwrap();
+        assert_eq!(spill.get(1, 0).unwrap(), Some(vec![edge(7, 2.0)]));
+        assert_eq!(spill.get(0, 8).unwrap(), Some(vec![edge(9, 1.0)]));
+    }
+}
@@ -1,10 +1,41 @@
-use std::collections::BinaryHeap;
+use std::collections::{BTreeMap, BinaryHeap};
 
+use anyhow::Result;
 use bit_vec::BitVec;
 use ordered_float::NotNan;
 use quantization::quantization::Quantizer;
 
 use crate::utils::{PointAndDistance, TraversalContext};
+
+// Points with more than this many times the average degree of their layer are hub nodes
+const HUB_DEGREE_FACTOR: f64 = 3.0;
+
+/// Counts the points of each degree, given the degree of every point of a layer. Returns
+/// (degree, number of points) pairs, by increasing degree.
+pub fn degree_histogram(degrees: impl IntoIterator<Item = usize>) -> Vec<(usize, usize)> {
+    let mut histogram = BTreeMap::new();
+    for degree in degrees {
+        *histogram.entry(degree).or_insert(0) += 1;
+    }
+    histogram.into_iter().collect()
+}
+
+/// Returns the points whose degree is more than `HUB_DEGREE_FACTOR` times the average degree of
+/// the layer, given the (point id, degree) of every point of the layer. Greedy searches keep
+/// going back through such hubs, which slows them down.
+pub fn hub_nodes(degrees: &[(u32, usize)]) -> Vec<u32> {
+    if degrees.is_empty() {
+        return vec![];
+    }
+    let average_degree =
+        degrees.iter().map(|(_, degree)| *degree).sum::<usize>() as f64 / degrees.len() as f64;
+    degrees
+        .iter()
+        .filter(|(_, degree)| *degree as f64 > HUB_DEGREE_FACTOR * average_degree)
+        .map(|(point_id, _)| *point_id)
+        .collect()
+}
+
 pub struct BuilderContext {
     visited: BitVec,
 }
@@ -31,6 +62,10 @@ impl TraversalContext for BuilderContext {
     }
 
     fn record_pages(&mut self, _page_id: String) {}
+
+    fn is_timed_out(&mut self) -> bool {
+        false
+    }
 }
 
 /// Move the traversal logic out, since it's used in both indexing and query path
@@ -42,16 +77,35 @@ pub trait GraphTraversal<Q: Quantizer> {
         -> f32;
 
     /// Get the edges for a point
-    fn get_edges_for_point(&self, point_id: u32, layer: u8) -> Option<Vec<u32>>;
+    fn get_edges_for_point(&self, point_id: u32, layer: u8) -> Result<Option<Vec<u32>>>;
 
     fn search_layer(
         &self,
         context: &mut Self::ContextT,
         query: &[Q::QuantizedT],
         entry_point: u32,
-        ef_construction: u32,
+        ef: u32,
         layer: u8,
-    ) -> Vec<PointAndDistance> {
+    ) -> Result<Vec<PointAndDistance>> {
+        self.search_layer_with_distance(
+            context,
+            entry_point,
+            ef,
+            layer,
+            |graph, point_id, context| graph.distance(query, point_id, context),
+        )
+    }
+
+    /// Same as `search_layer`, with `distance_to_query` computing the distance between the query and a
+    /// point
+    fn search_layer_with_distance(
+        &self,
+        context: &mut Self::ContextT,
+        entry_point: u32,
+        ef: u32,
+        layer: u8,
+        distance_to_query: impl Fn(&Self, u32, &mut Self::ContextT) -> f32,
+    ) -> Result<Vec<PointAndDistance>> {
         // Mark the entry point as visited so that we don't visit it again
         context.set_visited(entry_point);
 
@@ -62,14 +116,17 @@ pub trait GraphTraversal<Q: Quantizer> {
 
         candidates.push(PointAndDistance {
             point_id: entry_point,
-            distance: NotNan::new(-self.distance(query, entry_point, context)).unwrap(),
+            distance: NotNan::new(-distance_to_query(self, entry_point, context)).unwrap(),
         });
         working_list.push(PointAndDistance {
             point_id: entry_point,
-            distance: NotNan::new(self.distance(query, entry_point, context)).unwrap(),
+            distance: NotNan::new(distance_to_query(self, entry_point, context)).unwrap(),
         });
 
         while !candidates.is_empty() {
+            if context.is_timed_out() {
+                break;
+            }
             let point_and_distance = candidates.pop().unwrap();
             let point_id = point_and_distance.point_id as u32;
             let distance: f32 = -*point_and_distance.distance;
@@ -80,20 +137,19 @@ pub trait GraphTraversal<Q: Quantizer> {
                 break;
             }
 
-            let edges = self.get_edges_for_point(point_id, layer);
-            if edges.is_none() {
+            let Some(edges) = self.get_edges_for_point(point_id, layer)? else {
                 continue;
-            }
+            };
 
-            for e in edges.unwrap().iter() {
+            for e in edges.iter() {
                 if context.visited(*e) {
                     continue;
                 }
                 context.set_visited(*e);
                 furthest_element_from_working_list = working_list.peek().unwrap();
-                let distance_e_q = self.distance(query, *e, context);
+                let distance_e_q = distance_to_query(self, *e, context);
                 if distance_e_q < *furthest_element_from_working_list.distance
-                    || working_list.len() < ef_construction as usize
+                    || working_list.len() < ef as usize
                 {
                     candidates.push(PointAndDistance {
                         point_id: *e,
@@ -103,7 +159,7 @@ pub trait GraphTraversal<Q: Quantizer> {
                         point_id: *e,
                         distance: NotNan::new(distance_e_q).unwrap(),
                     });
-                    if working_list.len() > ef_construction as usize {
+                    if working_list.len() > ef as usize {
                         working_list.pop();
                     }
                 }
@@ -114,9 +170,27 @@ pub trait GraphTraversal<Q: Quantizer> {
         // whether to drop the distance or not
         let mut result: Vec<PointAndDistance> = working_list.into_iter().collect();
         result.sort();
-        result
+        Ok(result)
     }
 
     /// Print the graph for debugging purposes
     fn print_graph(&self, layer: u8, predicate: impl Fn(u8, u32) -> bool);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degree_histogram_and_hub_nodes() {
+        let degrees = vec![(0, 2), (1, 3), (2, 2), (3, 0), (4, 2), (5, 2), (6, 20)];
+        assert_eq!(
+            degree_histogram(degrees.iter().map(|(_, degree)| *degree)),
+            vec![(0, 1), (2, 4), (3, 1), (20, 1)]
+        );
+        // The average degree is 31 / 7
+        assert_eq!(hub_nodes(&degrees), vec![6]);
+        assert!(hub_nodes(&degrees[..6]).is_empty());
+        assert!(hub_nodes(&[]).is_empty());
+    }
+}
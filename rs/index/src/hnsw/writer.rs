@@ -1,13 +1,28 @@
+use std::cmp::min;
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 
 use anyhow::{Context, Result};
-use log::debug;
+use log::{debug, warn};
 use quantization::quantization::Quantizer;
 use utils::io::{append_file_to_writer, wrap_write};
 
 use crate::hnsw::builder::HnswBuilder;
 
+// Side-car file holding the points of documents inserted with multiple vectors.
+pub const MULTI_VECTOR_DOC_IDS_FILE_NAME: &str = "multi_vector_doc_ids";
+
+// Full-precision vectors of the points at layers >= 1, when only layer 0 is quantized. The
+// quantized vectors of all points are in `vector_storage`.
+pub const UPPER_LAYER_VECTORS_FILE_NAME: &str = "upper_layer_vectors";
+
+// Side-car file holding the doc ids deleted from the builder, when written with
+// `write_with_tombstones`.
+pub const TOMBSTONES_FILE_NAME: &str = "tombstones";
+
+// Hub nodes logged per layer when writing, the count is always logged.
+const MAX_LOGGED_HUB_NODES: usize = 10;
+
 pub struct HnswWriter<Q: Quantizer> {
     base_directory: String,
 
@@ -40,6 +55,9 @@ impl<Q: Quantizer> HnswWriter<Q> {
         }
     }
 
+    /// Writes the graph and vectors of the builder. Documents deleted from the builder are
+    /// written as any other, use `write_with_tombstones` or `compact` to leave them out of
+    /// search results.
     pub fn write(&self, index_builder: &mut HnswBuilder<Q>, reindex: bool) -> Result<()> {
         if reindex {
             let temp_dir = format!("{}/temp", self.base_directory);
@@ -52,6 +70,19 @@ impl<Q: Quantizer> HnswWriter<Q> {
             debug!("Finish reindexing");
         }
 
+        for layer in 0..index_builder.layers.len() {
+            let hubs = index_builder.hub_nodes(layer);
+            if !hubs.is_empty() {
+                warn!(
+                    "Layer {} has {} hub nodes with more than 3x the average degree, \
+                     which slows down searches: {:?}",
+                    layer,
+                    hubs.len(),
+                    &hubs[..min(hubs.len(), MAX_LOGGED_HUB_NODES)]
+                );
+            }
+        }
+
         let non_bottom_layer_nodes = index_builder.get_nodes_from_non_bottom_layer();
         // Doc_id mapping writer
         let doc_id_mapping_path = format!("{}/doc_id_mapping", self.base_directory);
@@ -108,8 +139,10 @@ impl<Q: Quantizer> HnswWriter<Q> {
                     if layer.edges.contains_key(point_id) {
                         // Edge offsets will be the starting index of edges for this point
 
-                        let edges_for_point = &layer.edges[point_id];
-                        for edge in edges_for_point {
+                        let edges_for_point = index_builder
+                            .edges_for_point(current_layer as u8, *point_id)?
+                            .unwrap_or_default();
+                        for edge in edges_for_point.iter() {
                             edges.push(edge.point_id);
                         }
 
@@ -126,8 +159,10 @@ impl<Q: Quantizer> HnswWriter<Q> {
                 for point_id in 0..=max_point_id {
                     edge_offsets.push(num_edges);
                     if layer.edges.contains_key(&point_id) {
-                        let edges_for_point = &layer.edges[&point_id];
-                        for edge in edges_for_point {
+                        let edges_for_point = index_builder
+                            .edges_for_point(current_layer as u8, point_id)?
+                            .unwrap_or_default();
+                        for edge in edges_for_point.iter() {
                             edges.push(edge.point_id);
                         }
                         num_edges += edges_for_point.len();
@@ -200,6 +235,105 @@ impl<Q: Quantizer> HnswWriter<Q> {
         fs::remove_file(format!("{}/level_offsets", self.base_directory)).unwrap_or_default();
         fs::remove_file(format!("{}/doc_id_mapping", self.base_directory)).unwrap_or_default();
 
+        self.write_multi_vector_doc_ids(index_builder)?;
+        self.write_upper_layer_vectors(index_builder)?;
+        fs::remove_file(format!("{}/{}", self.base_directory, TOMBSTONES_FILE_NAME))
+            .unwrap_or_default();
+        Ok(())
+    }
+
+    /// Same as `write`, and also writes the doc ids deleted from the builder, so that searches
+    /// on the written index skip them. Their points are still in the graph.
+    pub fn write_with_tombstones(
+        &self,
+        index_builder: &mut HnswBuilder<Q>,
+        reindex: bool,
+    ) -> Result<()> {
+        self.write(index_builder, reindex)?;
+        self.write_tombstones(index_builder)
+    }
+
+    /// Removes the points of deleted documents from the builder, then writes it. Unlike
+    /// `write_with_tombstones`, their vectors and edges are not in the written index anymore.
+    pub fn compact(&self, index_builder: &mut HnswBuilder<Q>, reindex: bool) -> Result<()> {
+        let temp_dir = format!("{}/compact_temp", self.base_directory);
+        fs::create_dir_all(&temp_dir).context("failed to create compaction temp directory")?;
+        index_builder
+            .compact(temp_dir)
+            .context("failed to compact during write")?;
+        self.write(index_builder, reindex)
+    }
+
+    /// Writes the deleted doc ids, sorted, as `doc_id (u128)...`. Nothing is written when no
+    /// document was deleted.
+    fn write_tombstones(&self, index_builder: &HnswBuilder<Q>) -> Result<()> {
+        let path = format!("{}/{}", self.base_directory, TOMBSTONES_FILE_NAME);
+        if index_builder.tombstones.is_empty() {
+            fs::remove_file(path).unwrap_or_default();
+            return Ok(());
+        }
+
+        let mut doc_ids: Vec<&u128> = index_builder.tombstones.iter().collect();
+        doc_ids.sort();
+        let mut file = File::create(path)?;
+        let mut writer = BufWriter::new(&mut file);
+        for doc_id in doc_ids {
+            wrap_write(&mut writer, &doc_id.to_le_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the full-precision vectors of upper layer points, sorted by point id, as
+    /// `num_points (u32) | dimension (u32) | (point_id (u32) | vector (f32 * dimension))...`.
+    /// Nothing is written when all layers are quantized.
+    fn write_upper_layer_vectors(&self, index_builder: &HnswBuilder<Q>) -> Result<()> {
+        let path = format!("{}/{}", self.base_directory, UPPER_LAYER_VECTORS_FILE_NAME);
+        if index_builder.upper_layer_vectors.is_empty() {
+            fs::remove_file(path).unwrap_or_default();
+            return Ok(());
+        }
+
+        let vectors = index_builder.upper_layer_vectors.iter_sorted();
+        let dimension = vectors[0].1.len();
+
+        let mut file = File::create(path)?;
+        let mut writer = BufWriter::new(&mut file);
+        wrap_write(&mut writer, &(vectors.len() as u32).to_le_bytes())?;
+        wrap_write(&mut writer, &(dimension as u32).to_le_bytes())?;
+        for (point_id, vector) in vectors {
+            wrap_write(&mut writer, &point_id.to_le_bytes())?;
+            for value in vector {
+                wrap_write(&mut writer, &value.to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the points of every multi-vector document, sorted by doc id, as
+    /// `doc_id (u128) | num_points (u32) | point_ids (u32)...`. Nothing is written when all
+    /// documents have a single vector.
+    fn write_multi_vector_doc_ids(&self, index_builder: &HnswBuilder<Q>) -> Result<()> {
+        let path = format!("{}/{}", self.base_directory, MULTI_VECTOR_DOC_IDS_FILE_NAME);
+        if index_builder.multi_vector_doc_ids.is_empty() {
+            fs::remove_file(path).unwrap_or_default();
+            return Ok(());
+        }
+
+        let mut file = File::create(path)?;
+        let mut writer = BufWriter::new(&mut file);
+        let mut doc_ids: Vec<&u128> = index_builder.multi_vector_doc_ids.keys().collect();
+        doc_ids.sort();
+        for doc_id in doc_ids {
+            let point_ids = &index_builder.multi_vector_doc_ids[doc_id];
+            wrap_write(&mut writer, &doc_id.to_le_bytes())?;
+            wrap_write(&mut writer, &(point_ids.len() as u32).to_le_bytes())?;
+            for point_id in point_ids {
+                wrap_write(&mut writer, &point_id.to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
         Ok(())
     }
 
@@ -520,6 +654,7 @@ mod tests {
         let pq_builder_config = ProductQuantizerBuilderConfig {
             max_iteration: 1000,
             batch_size: 4,
+            use_annealing: false,
         };
 
         // Train a product quantizer
@@ -580,17 +715,17 @@ mod tests {
             .read::<ProductQuantizer<L2DistanceCalculator>>()
             .unwrap();
         {
-            let egdes = hnsw.get_edges_for_point(1, 2);
+            let egdes = hnsw.get_edges_for_point(1, 2).unwrap();
             assert!(egdes.is_none());
         }
         {
-            let edges = hnsw.get_edges_for_point(1, 1).unwrap();
+            let edges = hnsw.get_edges_for_point(1, 1).unwrap().unwrap();
             assert_eq!(edges.len(), 2);
             assert!(edges.contains(&4));
             assert!(edges.contains(&5));
         }
         {
-            let edges = hnsw.get_edges_for_point(0, 0).unwrap();
+            let edges = hnsw.get_edges_for_point(0, 0).unwrap().unwrap();
             assert_eq!(edges.len(), 2);
             assert!(edges.contains(&1));
             assert!(edges.contains(&2));
@@ -1,15 +1,25 @@
-use crate::utils::{IdWithScore, SearchContext};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::utils::{IdWithScore, SearchContext, SearchResult};
+
+/// Predicate on doc ids. Only documents for which it returns true are returned by
+/// `Searchable::search_with_filter`.
+pub type DocIdFilter = Arc<dyn Fn(u128) -> bool + Send + Sync>;
 
 /// Main trait for index
 pub trait Searchable {
-    /// Search for the nearest neighbors of a query vector
+    /// Search for the nearest neighbors of a query vector. `ef_search` is the query-time effort:
+    /// the size of the candidate list for HNSW, or the number of probed clusters for IVF. Higher
+    /// values are slower, but get closer to an exhaustive search.
     fn search(
         &self,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>>;
+    ) -> Option<SearchResult>;
 
     #[allow(unused_variables)]
     fn search_with_id(
@@ -17,12 +27,60 @@ pub trait Searchable {
         id: u128,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>> {
+    ) -> Option<SearchResult> {
         // This is a default implementation. In MultiSpann, we will override this function.
-        self.search(query, k, ef_construction, context)
+        self.search(query, k, ef_search, context)
+    }
+
+    /// Search for the nearest neighbors of a query vector among the documents accepted by
+    /// `filter`.
+    fn search_with_filter(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        filter: &DocIdFilter,
+        context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        // This is a default implementation, which filters the unfiltered top k and may therefore
+        // return fewer than k results. Indexes should override it to filter while searching.
+        let results = self.search(query, k, ef_search, context)?;
+        Some(results.0.into_iter().filter(|x| filter(x.id)).collect())
     }
+
+    /// Search for the documents whose distance to the query is at most `radius`, closest first.
+    /// At most `max_results` documents are returned, so that a large radius doesn't collect the
+    /// whole index. Indexes without radius search return an error.
+    #[allow(unused_variables)]
+    fn search_radius(
+        &self,
+        query: &[f32],
+        radius: f32,
+        max_results: usize,
+        ef_search: u32,
+        context: &mut SearchContext,
+    ) -> Result<Vec<IdWithScore>> {
+        Err(anyhow!("radius search not supported"))
+    }
+}
+
+/// Search restricted to the documents accepted by a predicate. Unlike `DocIdFilter`, the
+/// predicate is borrowed for the duration of the search, so it can capture local state such as
+/// an attribute lookup table.
+pub trait FilteredSearch {
+    /// Search for the `k` nearest neighbors of a query vector among the documents for which
+    /// `filter` returns true. The filter is applied before scoring, so `k` results are returned
+    /// as long as enough documents match.
+    fn search_filtered(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        context: &mut SearchContext,
+        filter: &dyn Fn(u128) -> bool,
+    ) -> Option<Vec<IdWithScore>>;
 }
 
 pub type BoxedSearchable = Box<dyn Searchable + Send + Sync>;
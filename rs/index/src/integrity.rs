@@ -0,0 +1,892 @@
+use std::fmt;
+use std::fs::File;
+use std::mem::size_of;
+use std::path::Path;
+
+use anyhow::Result;
+use bit_vec::BitVec;
+use byteorder::{ByteOrder, LittleEndian};
+use compression::compression::IntSeqDecoder;
+use memmap2::Mmap;
+
+use crate::hnsw::index::HnswLayout;
+use crate::hnsw::reader::{HnswReader, HEADER_LEN as HNSW_HEADER_LEN};
+use crate::ivf::index::ivf_files_directory;
+use crate::posting_list::combined_file::{
+    FixedIndexFile, Header, SectionEntry, Version, SECTION_NAMES,
+};
+use crate::vector::compressed::{compressed_vector_file_path, CompressedVectorStorage};
+
+/// Inconsistency found in the files of an index by `IndexIntegrityChecker`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityViolation {
+    /// The first byte of the index file is not a known format version.
+    UnknownVersion {
+        file: String,
+        version: u8,
+    },
+    InvalidHeader {
+        file: String,
+        reason: String,
+    },
+    /// The section ends after the end of its file.
+    Truncated {
+        section: String,
+        end: u64,
+        file_len: u64,
+    },
+    /// The section length is not a multiple of the size of its elements.
+    InvalidSectionLength {
+        section: String,
+        len: u64,
+    },
+    /// A count stored in a section doesn't match the header.
+    CountMismatch {
+        section: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// The size of the vector file doesn't match its number of vectors and their dimension.
+    VectorFileSize {
+        num_vectors: u64,
+        dimension: u32,
+        file_len: u64,
+    },
+    ChecksumMismatch {
+        section: String,
+    },
+    /// Offset and length of the posting list, from the start of the posting lists.
+    PostingListOutOfBounds {
+        centroid: usize,
+        offset: u64,
+        len: u64,
+    },
+    UndecodablePostingList {
+        centroid: usize,
+    },
+    /// `index` is the position of the point id in its section. Only the first point out of
+    /// range of each posting list or section is reported.
+    PointOutOfRange {
+        section: String,
+        index: usize,
+        point_id: u64,
+    },
+    /// Points that are in no posting list, so searches never return them.
+    UnassignedPoints {
+        count: usize,
+    },
+    NonFiniteCentroid {
+        centroid: usize,
+    },
+    /// The offset at `index` is smaller than the previous one.
+    UnsortedOffsets {
+        section: String,
+        index: usize,
+    },
+}
+
+impl fmt::Display for IntegrityViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownVersion { file, version } => {
+                write!(f, "{}: unknown version {}", file, version)
+            }
+            Self::InvalidHeader { file, reason } => {
+                write!(f, "{}: invalid header: {}", file, reason)
+            }
+            Self::Truncated {
+                section,
+                end,
+                file_len,
+            } => write!(
+                f,
+                "{}: ends at byte {} of a {} byte file",
+                section, end, file_len
+            ),
+            Self::InvalidSectionLength { section, len } => {
+                write!(f, "{}: invalid length of {} bytes", section, len)
+            }
+            Self::CountMismatch {
+                section,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: expected {} entries, found {}",
+                section, expected, actual
+            ),
+            Self::VectorFileSize {
+                num_vectors,
+                dimension,
+                file_len,
+            } => write!(
+                f,
+                "vectors: {} bytes for {} vectors of dimension {}",
+                file_len, num_vectors, dimension
+            ),
+            Self::ChecksumMismatch { section } => write!(f, "{}: checksum mismatch", section),
+            Self::PostingListOutOfBounds {
+                centroid,
+                offset,
+                len,
+            } => write!(
+                f,
+                "posting list {}: {} bytes at offset {} are out of bounds",
+                centroid, len, offset
+            ),
+            Self::UndecodablePostingList { centroid } => {
+                write!(f, "posting list {}: failed to decode", centroid)
+            }
+            Self::PointOutOfRange {
+                section,
+                index,
+                point_id,
+            } => write!(
+                f,
+                "{}: point {} at position {} is out of range",
+                section, point_id, index
+            ),
+            Self::UnassignedPoints { count } => {
+                write!(f, "{} points are in no posting list", count)
+            }
+            Self::NonFiniteCentroid { centroid } => {
+                write!(f, "centroid {}: NaN or infinite value", centroid)
+            }
+            Self::UnsortedOffsets { section, index } => {
+                write!(
+                    f,
+                    "{}: offset {} is smaller than the previous one",
+                    section, index
+                )
+            }
+        }
+    }
+}
+
+/// Violations found by `IndexIntegrityChecker`. An index without violations is consistent,
+/// not necessarily correct: e.g. a corrupted vector value goes unnoticed.
+#[derive(Debug, Default)]
+pub struct IntegrityReport {
+    pub violations: Vec<IntegrityViolation>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    fn add(&mut self, violation: IntegrityViolation) {
+        self.violations.push(violation);
+    }
+
+    /// Reports the section if it ends after `file_len`. Returns whether it fits.
+    fn check_bounds(&mut self, section: &str, offset: usize, len: u64, file_len: usize) -> bool {
+        let end = (offset as u64).saturating_add(len);
+        if end > file_len as u64 {
+            self.add(IntegrityViolation::Truncated {
+                section: section.to_string(),
+                end,
+                file_len: file_len as u64,
+            });
+            return false;
+        }
+        true
+    }
+}
+
+/// Checks the consistency of index files before they are read, since the readers trust the
+/// headers and corrupted files lead to wrong results or panics. Errors are only returned when
+/// files can't be read, violations are listed in the returned `IntegrityReport`.
+pub struct IndexIntegrityChecker {}
+
+impl IndexIntegrityChecker {
+    /// Checks the `index` and `vectors` files written by `IvfWriter` in `base_directory`, or the
+    /// current ones after `Ivf::flush`.
+    /// Posting lists are decoded with `D`, which must match the encoder of the index. A point
+    /// may be in several posting lists, but every point must be in at least one.
+    pub fn check_ivf<D: IntSeqDecoder<Item = u64>>(
+        base_directory: &str,
+    ) -> Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        let files_directory = ivf_files_directory(base_directory)?;
+        let index = map_file(&format!("{}/index", files_directory))?;
+        let Some((header, data_offset)) = Self::check_ivf_header(&index, &mut report) else {
+            return Ok(report);
+        };
+
+        let lens = [
+            header.doc_id_mapping_len,
+            header.centroids_len,
+            header.posting_lists_and_metadata_len,
+        ];
+        // Huge lengths would overflow the offsets of the following sections
+        let mut in_bounds = true;
+        for (name, len) in SECTION_NAMES.iter().zip(lens) {
+            in_bounds &= report.check_bounds(name, data_offset, len, index.len());
+        }
+        if !in_bounds {
+            return Ok(report);
+        }
+        let offsets = FixedIndexFile::section_offsets(&header, data_offset);
+        for ((name, offset), len) in SECTION_NAMES.iter().zip(offsets).zip(lens) {
+            in_bounds &= report.check_bounds(name, offset, len, index.len());
+        }
+        if !in_bounds {
+            return Ok(report);
+        }
+        let [doc_id_mapping_offset, centroid_offset, posting_lists_offset] = offsets;
+
+        for (name, section) in SECTION_NAMES.iter().zip(&header.sections) {
+            let start = section.offset as usize;
+            let in_bounds = report.check_bounds(name, start, section.len, index.len());
+            if in_bounds
+                && crc32fast::hash(&index[start..start + section.len as usize]) != section.crc32
+            {
+                report.add(IntegrityViolation::ChecksumMismatch {
+                    section: name.to_string(),
+                });
+            }
+        }
+
+        let doc_id_mapping = &index[doc_id_mapping_offset..][..header.doc_id_mapping_len as usize];
+        Self::check_count(
+            "doc_id_mapping",
+            doc_id_mapping,
+            size_of::<u128>(),
+            size_of::<u128>(),
+            header.num_vectors,
+            &mut report,
+        );
+
+        let centroids = &index[centroid_offset..][..header.centroids_len as usize];
+        let centroid_len = header.num_features as usize * size_of::<f32>();
+        let centroids_ok = Self::check_count(
+            "centroids",
+            centroids,
+            size_of::<u64>(),
+            centroid_len,
+            header.num_clusters as u64,
+            &mut report,
+        );
+        if centroids_ok && centroid_len > 0 {
+            for (centroid, bytes) in centroids[size_of::<u64>()..]
+                .chunks_exact(centroid_len)
+                .enumerate()
+            {
+                let finite = bytes
+                    .chunks_exact(size_of::<f32>())
+                    .all(|value| LittleEndian::read_f32(value).is_finite());
+                if !finite {
+                    report.add(IntegrityViolation::NonFiniteCentroid { centroid });
+                }
+            }
+        }
+
+        // Points are counted from the doc id mapping rather than the header, so that a corrupted
+        // count can't size the allocations below beyond the file
+        let num_points =
+            (doc_id_mapping.len().saturating_sub(size_of::<u128>()) / size_of::<u128>()) as u64;
+        let posting_lists =
+            &index[posting_lists_offset..][..header.posting_lists_and_metadata_len as usize];
+        Self::check_posting_lists::<D>(posting_lists, &header, num_points, &mut report);
+
+        Self::check_vectors(
+            &format!("{}/vectors", files_directory),
+            header.num_vectors,
+            header.quantized_dimension,
+            &mut report,
+        )?;
+        Ok(report)
+    }
+
+    /// Checks the `hnsw/index` and `hnsw/vector_storage` files written by `HnswWriter` in
+    /// `base_directory`, which is laid out as `HnswReader` expects.
+    pub fn check_hnsw(base_directory: &str) -> Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        let index = map_file(&format!("{}/hnsw/index", base_directory))?;
+        if index.len() < HNSW_HEADER_LEN {
+            report.check_bounds("header", 0, HNSW_HEADER_LEN as u64, index.len());
+            return Ok(report);
+        }
+        if index[0] != 0 {
+            report.add(IntegrityViolation::UnknownVersion {
+                file: "hnsw/index".to_string(),
+                version: index[0],
+            });
+            return Ok(report);
+        }
+        let (header, data_offset) = match HnswReader::parse_header(&index, 0) {
+            Ok(header) => header,
+            Err(e) => {
+                report.add(IntegrityViolation::InvalidHeader {
+                    file: "hnsw/index".to_string(),
+                    reason: e.to_string(),
+                });
+                return Ok(report);
+            }
+        };
+
+        // Huge lengths would overflow the offsets of the following sections
+        let lens = [
+            header.edges_len,
+            header.points_len,
+            header.edge_offsets_len,
+            header.level_offsets_len,
+            header.doc_id_mapping_len,
+        ];
+        if lens.iter().any(|len| *len > index.len() as u64) {
+            report.check_bounds("sections", data_offset, lens.iter().sum(), index.len());
+            return Ok(report);
+        }
+        let layout = HnswLayout::new(&header, data_offset);
+        let sections = [
+            (
+                "edges",
+                layout.edges_offset,
+                header.edges_len,
+                size_of::<u32>(),
+            ),
+            (
+                "points",
+                layout.points_offset,
+                header.points_len,
+                size_of::<u32>(),
+            ),
+            (
+                "edge_offsets",
+                layout.edge_offsets_offset,
+                header.edge_offsets_len,
+                size_of::<u64>(),
+            ),
+            (
+                "level_offsets",
+                layout.level_offsets_offset,
+                header.level_offsets_len,
+                size_of::<u64>(),
+            ),
+            (
+                "doc_id_mapping",
+                layout.doc_id_mapping_offset,
+                header.doc_id_mapping_len,
+                size_of::<u128>(),
+            ),
+        ];
+        let mut valid = true;
+        for (name, offset, len, element_len) in sections {
+            valid &= report.check_bounds(name, offset, len, index.len());
+            if len % element_len as u64 != 0 {
+                report.add(IntegrityViolation::InvalidSectionLength {
+                    section: name.to_string(),
+                    len,
+                });
+                valid = false;
+            }
+        }
+        if !valid {
+            return Ok(report);
+        }
+        let section = |offset: usize, len: u64| &index[offset..offset + len as usize];
+        let edges = read_u32s(section(layout.edges_offset, header.edges_len));
+        let points = read_u32s(section(layout.points_offset, header.points_len));
+        let edge_offsets = read_u64s(section(layout.edge_offsets_offset, header.edge_offsets_len));
+        let level_offsets = read_u64s(section(
+            layout.level_offsets_offset,
+            header.level_offsets_len,
+        ));
+        let num_points = header.doc_id_mapping_len / size_of::<u128>() as u64;
+
+        for (name, point_ids) in [("edges", &edges), ("points", &points)] {
+            if let Some(index) = point_ids.iter().position(|id| *id as u64 >= num_points) {
+                report.add(IntegrityViolation::PointOutOfRange {
+                    section: name.to_string(),
+                    index,
+                    point_id: point_ids[index] as u64,
+                });
+            }
+        }
+
+        // Both offsets end with the total count of what they point to
+        let offsets = [
+            ("edge_offsets", &edge_offsets, edges.len()),
+            ("level_offsets", &level_offsets, edge_offsets.len()),
+        ];
+        for (name, offsets, total) in offsets {
+            if let Some(index) = (1..offsets.len()).find(|i| offsets[*i] < offsets[*i - 1]) {
+                report.add(IntegrityViolation::UnsortedOffsets {
+                    section: name.to_string(),
+                    index,
+                });
+            }
+            let last = offsets.last().copied().unwrap_or(0);
+            if last != total as u64 {
+                report.add(IntegrityViolation::CountMismatch {
+                    section: name.to_string(),
+                    expected: total as u64,
+                    actual: last,
+                });
+            }
+        }
+        if level_offsets.len() != header.num_layers as usize + 1 {
+            report.add(IntegrityViolation::CountMismatch {
+                section: "layers".to_string(),
+                expected: header.num_layers as u64,
+                actual: level_offsets.len().saturating_sub(1) as u64,
+            });
+        }
+
+        Self::check_vectors(
+            &format!("{}/hnsw/vector_storage", base_directory),
+            num_points,
+            header.quantized_dimension,
+            &mut report,
+        )?;
+        Ok(report)
+    }
+
+    /// Parses the header of an IVF index file, reporting why it can't be parsed.
+    fn check_ivf_header(index: &[u8], report: &mut IntegrityReport) -> Option<(Header, usize)> {
+        let version = match index.first() {
+            Some(0) => Version::V0,
+            Some(1) => Version::V1,
+            Some(version) => {
+                report.add(IntegrityViolation::UnknownVersion {
+                    file: "index".to_string(),
+                    version: *version,
+                });
+                return None;
+            }
+            None => {
+                report.check_bounds("header", 0, 1, 0);
+                return None;
+            }
+        };
+        // The header length only depends on the version
+        let sections = match version {
+            Version::V0 => vec![],
+            Version::V1 => vec![
+                SectionEntry {
+                    offset: 0,
+                    len: 0,
+                    crc32: 0,
+                };
+                SECTION_NAMES.len()
+            ],
+        };
+        let header_len = Header {
+            version,
+            num_features: 0,
+            quantized_dimension: 0,
+            num_clusters: 0,
+            num_vectors: 0,
+            doc_id_mapping_len: 0,
+            centroids_len: 0,
+            posting_lists_and_metadata_len: 0,
+            sections,
+        }
+        .to_bytes()
+        .len();
+        if !report.check_bounds("header", 0, header_len as u64, index.len()) {
+            return None;
+        }
+        match FixedIndexFile::read_header(index, 0) {
+            Ok(header) => Some(header),
+            Err(e) => {
+                report.add(IntegrityViolation::InvalidHeader {
+                    file: "index".to_string(),
+                    reason: e.to_string(),
+                });
+                None
+            }
+        }
+    }
+
+    /// Checks a section made of its number of elements, stored in `count_len` bytes, followed
+    /// by elements of `element_len` bytes. Returns whether it holds `expected` elements.
+    fn check_count(
+        section: &str,
+        bytes: &[u8],
+        count_len: usize,
+        element_len: usize,
+        expected: u64,
+        report: &mut IntegrityReport,
+    ) -> bool {
+        if bytes.len() < count_len {
+            report.add(IntegrityViolation::InvalidSectionLength {
+                section: section.to_string(),
+                len: bytes.len() as u64,
+            });
+            return false;
+        }
+        let count = LittleEndian::read_uint128(bytes, count_len) as u64;
+        if count != expected {
+            report.add(IntegrityViolation::CountMismatch {
+                section: section.to_string(),
+                expected,
+                actual: count,
+            });
+            return false;
+        }
+        if bytes.len() as u64 != count_len as u64 + expected * element_len as u64 {
+            report.add(IntegrityViolation::InvalidSectionLength {
+                section: section.to_string(),
+                len: bytes.len() as u64,
+            });
+            return false;
+        }
+        true
+    }
+
+    /// Checks the posting list metadata and decodes every posting list, whose point ids must be
+    /// smaller than `num_points`.
+    fn check_posting_lists<D: IntSeqDecoder<Item = u64>>(
+        bytes: &[u8],
+        header: &Header,
+        num_points: u64,
+        report: &mut IntegrityReport,
+    ) {
+        // Number of posting lists, then a (length, offset) pair per posting list
+        let num_clusters = header.num_clusters as usize;
+        let metadata_len = size_of::<u64>() * (1 + 2 * num_clusters);
+        if bytes.len() < metadata_len {
+            report.add(IntegrityViolation::InvalidSectionLength {
+                section: "posting_lists".to_string(),
+                len: bytes.len() as u64,
+            });
+            return;
+        }
+        let num_posting_lists = LittleEndian::read_u64(bytes);
+        if num_posting_lists != num_clusters as u64 {
+            report.add(IntegrityViolation::CountMismatch {
+                section: "posting_lists".to_string(),
+                expected: num_clusters as u64,
+                actual: num_posting_lists,
+            });
+            return;
+        }
+
+        let metadata = read_u64s(&bytes[size_of::<u64>()..metadata_len]);
+        let data = &bytes[metadata_len..];
+        let mut assigned = BitVec::from_elem(num_points as usize, false);
+        for (centroid, entry) in metadata.chunks_exact(2).enumerate() {
+            let (len, offset) = (entry[0], entry[1]);
+            match offset.checked_add(len) {
+                Some(end) if end <= data.len() as u64 => {}
+                _ => {
+                    report.add(IntegrityViolation::PostingListOutOfBounds {
+                        centroid,
+                        offset,
+                        len,
+                    });
+                    continue;
+                }
+            }
+            let byte_slice = &data[offset as usize..(offset + len) as usize];
+            let Ok(decoder) = D::new_decoder(byte_slice) else {
+                report.add(IntegrityViolation::UndecodablePostingList { centroid });
+                continue;
+            };
+            let mut reported = false;
+            for (index, point_id) in decoder.get_iterator(byte_slice).enumerate() {
+                if point_id < num_points {
+                    assigned.set(point_id as usize, true);
+                } else if !reported {
+                    report.add(IntegrityViolation::PointOutOfRange {
+                        section: format!("posting list {}", centroid),
+                        index,
+                        point_id,
+                    });
+                    reported = true;
+                }
+            }
+        }
+
+        let num_unassigned = assigned.iter().filter(|assigned| !assigned).count();
+        if num_unassigned > 0 {
+            report.add(IntegrityViolation::UnassignedPoints {
+                count: num_unassigned,
+            });
+        }
+    }
+
+    /// Checks the vector file at `path`, or its compressed version when it exists, as
+    /// `open_vector_storage` prefers it. Vectors are made of 1, 2, 4 or 8 byte values,
+    /// depending on the quantizer.
+    fn check_vectors(
+        path: &str,
+        num_vectors: u64,
+        dimension: u32,
+        report: &mut IntegrityReport,
+    ) -> Result<()> {
+        let compressed_path = compressed_vector_file_path(path);
+        if Path::new(&compressed_path).is_file() {
+            match CompressedVectorStorage::<u8>::new(compressed_path.clone(), 0) {
+                Ok(storage) if storage.num_vectors as u64 != num_vectors => {
+                    report.add(IntegrityViolation::CountMismatch {
+                        section: "vectors".to_string(),
+                        expected: num_vectors,
+                        actual: storage.num_vectors as u64,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => report.add(IntegrityViolation::InvalidHeader {
+                    file: compressed_path
+                        .rsplit('/')
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    reason: e.to_string(),
+                }),
+            }
+            return Ok(());
+        }
+
+        let vectors = map_file(path)?;
+        if !report.check_bounds("vectors", 0, size_of::<u64>() as u64, vectors.len()) {
+            return Ok(());
+        }
+        let count = LittleEndian::read_u64(&vectors);
+        if count != num_vectors {
+            report.add(IntegrityViolation::CountMismatch {
+                section: "vectors".to_string(),
+                expected: num_vectors,
+                actual: count,
+            });
+            return Ok(());
+        }
+        let data_len = (vectors.len() - size_of::<u64>()) as u64;
+        let num_values = num_vectors * dimension as u64;
+        let valid = match num_values {
+            0 => data_len == 0,
+            _ => data_len % num_values == 0 && [1, 2, 4, 8].contains(&(data_len / num_values)),
+        };
+        if !valid {
+            report.add(IntegrityViolation::VectorFileSize {
+                num_vectors,
+                dimension,
+                file_len: vectors.len() as u64,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn map_file(path: &str) -> Result<Mmap> {
+    let file = File::open(path)?;
+    Ok(unsafe { Mmap::map(&file) }?)
+}
+
+fn read_u32s(bytes: &[u8]) -> Vec<u32> {
+    let mut values = vec![0u32; bytes.len() / size_of::<u32>()];
+    LittleEndian::read_u32_into(bytes, &mut values);
+    values
+}
+
+fn read_u64s(bytes: &[u8]) -> Vec<u64> {
+    let mut values = vec![0u64; bytes.len() / size_of::<u64>()];
+    LittleEndian::read_u64_into(bytes, &mut values);
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use compression::noc::noc::{PlainDecoder, PlainEncoder};
+    use quantization::noq::noq::NoQuantizer;
+    use tempdir::TempDir;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::kmeans_builder::kmeans_builder::KMeansVariant;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::hnsw::builder::HnswBuilder;
+    use crate::hnsw::writer::HnswWriter;
+    use crate::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+    use crate::ivf::writer::IvfWriter;
+
+    fn write_ivf(base_directory: &str, version: Version) {
+        let num_features = 4;
+        let mut writer = IvfWriter::<_, PlainEncoder, L2DistanceCalculator>::new(
+            base_directory.to_string(),
+            NoQuantizer::<L2DistanceCalculator>::new(num_features),
+        );
+        writer.set_version(version);
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters: 10,
+            num_data_points_for_clustering: 1000,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: base_directory.to_string(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .unwrap();
+        for i in 0..1000 {
+            builder
+                .add_vector(i as u128, &generate_random_vector(num_features))
+                .unwrap();
+        }
+        builder.build().unwrap();
+        writer.write(&mut builder, false).unwrap();
+    }
+
+    #[test]
+    fn test_check_ivf() {
+        let temp_dir = TempDir::new("test_check_ivf").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        write_ivf(&base_directory, Version::V0);
+        let report = IndexIntegrityChecker::check_ivf::<PlainDecoder>(&base_directory).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+
+        // Point the offset of the first posting list far past the end of the file
+        let index_path = format!("{}/index", base_directory);
+        let mut bytes = fs::read(&index_path).unwrap();
+        let (header, data_offset) = FixedIndexFile::read_header(&bytes, 0).unwrap();
+        let posting_lists_offset = FixedIndexFile::section_offsets(&header, data_offset)[2];
+        let offset = posting_lists_offset + 3 * size_of::<u64>() - 1;
+        let original_byte = bytes[offset];
+        bytes[offset] = 0x7f;
+        fs::write(&index_path, &bytes).unwrap();
+        let report = IndexIntegrityChecker::check_ivf::<PlainDecoder>(&base_directory).unwrap();
+        assert!(matches!(
+            report.violations[..],
+            [
+                IntegrityViolation::PostingListOutOfBounds { centroid: 0, .. },
+                IntegrityViolation::UnassignedPoints { .. }
+            ]
+        ));
+
+        bytes[offset] = original_byte;
+        bytes[0] = 7;
+        fs::write(&index_path, &bytes).unwrap();
+        let report = IndexIntegrityChecker::check_ivf::<PlainDecoder>(&base_directory).unwrap();
+        assert_eq!(
+            report.violations,
+            vec![IntegrityViolation::UnknownVersion {
+                file: "index".to_string(),
+                version: 7
+            }]
+        );
+
+        // Drop the last vector
+        bytes[0] = 0;
+        fs::write(&index_path, &bytes).unwrap();
+        let vectors_path = format!("{}/vectors", base_directory);
+        let mut vectors = fs::read(&vectors_path).unwrap();
+        vectors.truncate(vectors.len() - 4 * size_of::<f32>());
+        fs::write(&vectors_path, &vectors).unwrap();
+        let report = IndexIntegrityChecker::check_ivf::<PlainDecoder>(&base_directory).unwrap();
+        assert_eq!(report.violations.len(), 1);
+        assert!(matches!(
+            report.violations[0],
+            IntegrityViolation::VectorFileSize {
+                num_vectors: 1000,
+                ..
+            }
+        ));
+
+        // A corrupted vector count is reported instead of sizing the posting list check
+        let num_vectors_offset = 1 + 3 * size_of::<u32>();
+        bytes[num_vectors_offset..num_vectors_offset + size_of::<u64>()]
+            .copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&index_path, &bytes).unwrap();
+        let report = IndexIntegrityChecker::check_ivf::<PlainDecoder>(&base_directory).unwrap();
+        assert_eq!(
+            report.violations[0],
+            IntegrityViolation::CountMismatch {
+                section: "doc_id_mapping".to_string(),
+                expected: u64::MAX,
+                actual: 1000,
+            }
+        );
+        assert!(!report
+            .violations
+            .iter()
+            .any(|violation| matches!(violation, IntegrityViolation::UnassignedPoints { .. })));
+    }
+
+    #[test]
+    fn test_check_ivf_checksums() {
+        let temp_dir = TempDir::new("test_check_ivf_checksums").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        write_ivf(&base_directory, Version::V1);
+        let report = IndexIntegrityChecker::check_ivf::<PlainDecoder>(&base_directory).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+
+        // Make the first value of the first centroid NaN
+        let index_path = format!("{}/index", base_directory);
+        let mut bytes = fs::read(&index_path).unwrap();
+        let (header, data_offset) = FixedIndexFile::read_header(&bytes, 0).unwrap();
+        let centroid_offset = FixedIndexFile::section_offsets(&header, data_offset)[1];
+        let value_offset = centroid_offset + size_of::<u64>();
+        bytes[value_offset..value_offset + size_of::<f32>()]
+            .copy_from_slice(&f32::NAN.to_le_bytes());
+        fs::write(&index_path, &bytes).unwrap();
+        let report = IndexIntegrityChecker::check_ivf::<PlainDecoder>(&base_directory).unwrap();
+        assert_eq!(
+            report.violations,
+            vec![
+                IntegrityViolation::ChecksumMismatch {
+                    section: "centroids".to_string()
+                },
+                IntegrityViolation::NonFiniteCentroid { centroid: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_hnsw() {
+        let temp_dir = TempDir::new("test_check_hnsw").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let vector_directory = format!("{}/vectors", base_directory);
+        fs::create_dir_all(&vector_directory).unwrap();
+        let mut builder = HnswBuilder::<NoQuantizer<L2DistanceCalculator>>::new(
+            10,
+            4,
+            20,
+            1024,
+            4096,
+            4,
+            NoQuantizer::<L2DistanceCalculator>::new(4),
+            vector_directory,
+        );
+        for i in 0..500 {
+            builder
+                .insert(i as u128, &generate_random_vector(4))
+                .unwrap();
+        }
+        let hnsw_directory = format!("{}/hnsw", base_directory);
+        fs::create_dir_all(&hnsw_directory).unwrap();
+        HnswWriter::new(hnsw_directory.clone())
+            .write(&mut builder, false)
+            .unwrap();
+        let report = IndexIntegrityChecker::check_hnsw(&base_directory).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+
+        // Make the first edge point to a point that doesn't exist
+        let index_path = format!("{}/index", hnsw_directory);
+        let mut bytes = fs::read(&index_path).unwrap();
+        let (header, data_offset) = HnswReader::parse_header(&bytes, 0).unwrap();
+        let edges_offset = HnswLayout::new(&header, data_offset).edges_offset;
+        bytes[edges_offset..edges_offset + size_of::<u32>()]
+            .copy_from_slice(&1000u32.to_le_bytes());
+        fs::write(&index_path, &bytes).unwrap();
+        let report = IndexIntegrityChecker::check_hnsw(&base_directory).unwrap();
+        assert_eq!(
+            report.violations,
+            vec![IntegrityViolation::PointOutOfRange {
+                section: "edges".to_string(),
+                index: 0,
+                point_id: 1000,
+            }]
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::mem::size_of;
+
+use anyhow::{anyhow, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use compression::compression::IntSeqDecoder;
+use config::enums::DistanceType;
+use quantization::quantization::Quantizer;
+use utils::DistanceCalculator;
+
+use crate::flat::index::FlatIndex;
+use crate::ivf::index::Ivf;
+use crate::utils::SearchContext;
+use crate::vector::fixed_file::FixedFileVectorStorage;
+
+// FAISS files start with a fourcc identifying the type of the index, there is no other magic.
+const FLAT_L2_FOURCC: &[u8; 4] = b"IxF2";
+const FLAT_IP_FOURCC: &[u8; 4] = b"IxFI";
+const IVF_FLAT_FOURCC: &[u8; 4] = b"IwFl";
+// Inverted lists stored as arrays, with the size of every list
+const ARRAY_INVERTED_LISTS_FOURCC: &[u8; 4] = b"ilar";
+const FULL_LIST_SIZES_FOURCC: &[u8; 4] = b"full";
+
+// Values of `faiss::MetricType`
+const METRIC_INNER_PRODUCT: i32 = 0;
+const METRIC_L2: i32 = 1;
+
+// Unused fields of the index header, always written as 2^20 by FAISS
+const HEADER_DUMMY: i64 = 1 << 20;
+
+/// Converts indexes to and from the binary format of FAISS, as written by `faiss.write_index`
+/// and read by `faiss.read_index`. All values are little-endian.
+pub struct FaissExporter {}
+
+impl FaissExporter {
+    /// Writes `vectors` as an `IndexFlatL2`.
+    pub fn export_flat(vectors: &FixedFileVectorStorage<f32>, output_path: &str) -> Result<()> {
+        let num_features = vectors.num_features();
+        let mut context = SearchContext::new(false);
+        let vectors = (0..vectors.num_vectors)
+            .map(|i| {
+                vectors
+                    .get(i, &mut context)
+                    .map(|vector| vector.to_vec())
+                    .ok_or_else(|| anyhow!("Failed to read vector {}", i))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut file = File::create(output_path)?;
+        let mut writer = BufWriter::new(&mut file);
+        write_flat(&mut writer, METRIC_L2, num_features, &vectors)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes `ivf` as an `IndexIVFFlat`, with its centroids as an `IndexFlat` quantizer. Doc
+    /// ids become the FAISS ids, so they must fit in an i64. Deleted points are left out, and
+    /// quantized vectors are reconstructed by the quantizer, so they are approximate.
+    ///
+    /// `distance_type` is the one the index was built with. Cosine indexes hold normalized
+    /// vectors, so they are exported with the inner product metric like dot product ones.
+    pub fn export_ivf_flat<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>>(
+        ivf: &Ivf<Q, DC, D>,
+        distance_type: DistanceType,
+        output_path: &str,
+    ) -> Result<()> {
+        let metric = faiss_metric(distance_type);
+        let num_features = ivf.index_storage.header().num_features as usize;
+        let centroids = (0..ivf.num_clusters)
+            .map(|i| Ok(ivf.index_storage.get_centroid(i)?.to_vec()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut context = SearchContext::new(false);
+        let mut lists: Vec<(Vec<i64>, Vec<f32>)> = Vec::with_capacity(ivf.num_clusters);
+        for centroid in 0..ivf.num_clusters {
+            let mut ids = vec![];
+            let mut codes = vec![];
+            for point_id in ivf.get_posting_list(centroid)? {
+                if ivf.is_deleted(point_id) {
+                    continue;
+                }
+                let doc_id = ivf.get_doc_id(point_id as usize)?;
+                ids.push(
+                    i64::try_from(doc_id)
+                        .map_err(|_| anyhow!("Doc id {} doesn't fit in a FAISS id", doc_id))?,
+                );
+                let vector = ivf
+                    .get_quantized_vector(point_id as usize, &mut context)
+                    .ok_or_else(|| anyhow!("Failed to read vector of point {}", point_id))?;
+                codes.extend(ivf.quantizer.original_vector(&vector));
+            }
+            lists.push((ids, codes));
+        }
+        let num_vectors: usize = lists.iter().map(|(ids, _)| ids.len()).sum();
+
+        let mut file = File::create(output_path)?;
+        let mut writer = BufWriter::new(&mut file);
+        writer.write_all(IVF_FLAT_FOURCC)?;
+        write_header(&mut writer, num_features, num_vectors, metric)?;
+        // Number of lists, and the default number of probed lists
+        writer.write_all(&(ivf.num_clusters as u64).to_le_bytes())?;
+        writer.write_all(&1u64.to_le_bytes())?;
+        write_flat(&mut writer, metric, num_features, &centroids)?;
+        // No direct map: its type, then an empty array
+        writer.write_all(&[0])?;
+        writer.write_all(&0u64.to_le_bytes())?;
+
+        // Inverted lists: their sizes, then the vectors and ids of each non-empty list
+        writer.write_all(ARRAY_INVERTED_LISTS_FOURCC)?;
+        writer.write_all(&(ivf.num_clusters as u64).to_le_bytes())?;
+        writer.write_all(&((num_features * size_of::<f32>()) as u64).to_le_bytes())?;
+        writer.write_all(FULL_LIST_SIZES_FOURCC)?;
+        writer.write_all(&(lists.len() as u64).to_le_bytes())?;
+        for (ids, _) in &lists {
+            writer.write_all(&(ids.len() as u64).to_le_bytes())?;
+        }
+        for (ids, codes) in &lists {
+            for value in codes {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            for id in ids {
+                writer.write_all(&id.to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads an `IndexFlatL2` or `IndexFlatIP`. FAISS flat indexes have no ids, so doc ids are
+    /// the positions of the vectors. The metric of the file is ignored, searches use `D`.
+    pub fn import_from_faiss<D: DistanceCalculator>(path: &str) -> Result<FlatIndex<D>> {
+        let bytes = std::fs::read(path)?;
+        let mut reader = ByteReader::new(&bytes);
+        let fourcc = reader.take(4)?;
+        if fourcc != FLAT_L2_FOURCC && fourcc != FLAT_IP_FOURCC {
+            return Err(anyhow!(
+                "Unsupported FAISS index type {}, expected a flat index",
+                String::from_utf8_lossy(fourcc)
+            ));
+        }
+        let num_features = usize::try_from(reader.read_i32()?)?;
+        let num_vectors = usize::try_from(reader.read_i64()?)?;
+        reader.take(2 * size_of::<i64>() + 1)?;
+        let metric = reader.read_i32()?;
+        if metric != METRIC_L2 && metric != METRIC_INNER_PRODUCT {
+            return Err(anyhow!("Unsupported FAISS metric {}", metric));
+        }
+        let num_values = reader.read_i64()?;
+        if Some(num_values as u64) != (num_vectors as u64).checked_mul(num_features as u64) {
+            return Err(anyhow!(
+                "Expected {} vectors of dimension {}, found {} values",
+                num_vectors,
+                num_features,
+                num_values
+            ));
+        }
+
+        // The values are laid out as in a vector file, after the number of vectors
+        let mut vector_bytes = (num_vectors as u64).to_le_bytes().to_vec();
+        let values_len = (num_values as usize)
+            .checked_mul(size_of::<f32>())
+            .ok_or_else(|| anyhow!("Too many values: {}", num_values))?;
+        vector_bytes.extend_from_slice(reader.take(values_len)?);
+        let storage =
+            FixedFileVectorStorage::<f32>::new_from_bytes(&vector_bytes, num_features, 0)?;
+        FlatIndex::new(storage, (0..num_vectors as u128).collect())
+    }
+}
+
+fn faiss_metric(distance_type: DistanceType) -> i32 {
+    match distance_type {
+        DistanceType::L2 => METRIC_L2,
+        DistanceType::DotProduct | DistanceType::Cosine => METRIC_INNER_PRODUCT,
+    }
+}
+
+/// Writes the header shared by all FAISS indexes. Indexes are always trained.
+fn write_header(
+    writer: &mut impl Write,
+    num_features: usize,
+    num_vectors: usize,
+    metric: i32,
+) -> Result<()> {
+    writer.write_all(&(num_features as i32).to_le_bytes())?;
+    writer.write_all(&(num_vectors as i64).to_le_bytes())?;
+    writer.write_all(&HEADER_DUMMY.to_le_bytes())?;
+    writer.write_all(&HEADER_DUMMY.to_le_bytes())?;
+    writer.write_all(&[1])?;
+    writer.write_all(&metric.to_le_bytes())?;
+    Ok(())
+}
+
+/// Writes an `IndexFlat`: the header, then the number of values and the values.
+fn write_flat(
+    writer: &mut impl Write,
+    metric: i32,
+    num_features: usize,
+    vectors: &[Vec<f32>],
+) -> Result<()> {
+    let fourcc = match metric {
+        METRIC_INNER_PRODUCT => FLAT_IP_FOURCC,
+        _ => FLAT_L2_FOURCC,
+    };
+    writer.write_all(fourcc)?;
+    write_header(writer, num_features, vectors.len(), metric)?;
+    writer.write_all(&((vectors.len() * num_features) as u64).to_le_bytes())?;
+    for value in vectors.iter().flatten() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads values from the start of a buffer, failing when it is too short.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.offset < len {
+            return Err(anyhow!(
+                "Truncated FAISS index: {} bytes missing at offset {}",
+                len - (self.bytes.len() - self.offset),
+                self.offset
+            ));
+        }
+        let slice = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(slice)
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(LittleEndian::read_i32(self.take(size_of::<i32>())?))
+    }
+
+    fn read_i64(&mut self) -> Result<i64> {
+        Ok(LittleEndian::read_i64(self.take(size_of::<i64>())?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use compression::noc::noc::{PlainDecoder, PlainEncoder};
+    use quantization::noq::noq::NoQuantizer;
+    use tempdir::TempDir;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::kmeans_builder::kmeans_builder::KMeansVariant;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::index::Searchable;
+    use crate::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+    use crate::ivf::writer::IvfWriter;
+    use crate::posting_list::combined_file::FixedIndexFile;
+
+    const NUM_FEATURES: usize = 4;
+
+    fn vector_file_bytes(vectors: &[Vec<f32>]) -> Vec<u8> {
+        let mut bytes = (vectors.len() as u64).to_le_bytes().to_vec();
+        for value in vectors.iter().flatten() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Checks the header of a flat index and returns the bytes that follow it.
+    fn check_flat_header<'a>(
+        reader: &mut ByteReader<'a>,
+        fourcc: &[u8; 4],
+        num_vectors: usize,
+        metric: i32,
+    ) -> &'a [u8] {
+        assert_eq!(reader.take(4).unwrap(), fourcc);
+        assert_eq!(reader.read_i32().unwrap(), NUM_FEATURES as i32);
+        assert_eq!(reader.read_i64().unwrap(), num_vectors as i64);
+        assert_eq!(reader.read_i64().unwrap(), 1 << 20);
+        assert_eq!(reader.read_i64().unwrap(), 1 << 20);
+        assert_eq!(reader.take(1).unwrap(), [1]);
+        assert_eq!(reader.read_i32().unwrap(), metric);
+        assert_eq!(
+            reader.read_i64().unwrap(),
+            (num_vectors * NUM_FEATURES) as i64
+        );
+        reader
+            .take(num_vectors * NUM_FEATURES * size_of::<f32>())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_export_flat() {
+        let temp_dir = TempDir::new("test_export_flat").unwrap();
+        let output_path = format!("{}/flat.index", temp_dir.path().to_str().unwrap());
+        let vectors: Vec<Vec<f32>> = (0..100)
+            .map(|_| generate_random_vector(NUM_FEATURES))
+            .collect();
+        let vector_bytes = vector_file_bytes(&vectors);
+        let storage =
+            FixedFileVectorStorage::<f32>::new_from_bytes(&vector_bytes, NUM_FEATURES, 0).unwrap();
+        FaissExporter::export_flat(&storage, &output_path).unwrap();
+
+        let bytes = std::fs::read(&output_path).unwrap();
+        // fourcc, header of 33 bytes, number of values
+        assert_eq!(bytes.len(), 4 + 33 + 8 + 100 * NUM_FEATURES * 4);
+        assert_eq!(&bytes[..4], b"IxF2");
+        let mut reader = ByteReader::new(&bytes);
+        let values = check_flat_header(&mut reader, FLAT_L2_FOURCC, 100, METRIC_L2);
+        assert_eq!(values, &vector_bytes[8..]);
+
+        let flat = FaissExporter::import_from_faiss::<L2DistanceCalculator>(&output_path).unwrap();
+        assert_eq!(flat.num_vectors(), 100);
+        let mut context = SearchContext::new(false);
+        let results = flat.search(&vectors[42], 1, 0, &mut context).unwrap();
+        assert_eq!(results[0].id, 42);
+
+        std::fs::write(&output_path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(FaissExporter::import_from_faiss::<L2DistanceCalculator>(&output_path).is_err());
+    }
+
+    #[test]
+    fn test_export_ivf_flat() {
+        let temp_dir = TempDir::new("test_export_ivf_flat").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let num_clusters = 4;
+        let vectors: Vec<Vec<f32>> = (0..100)
+            .map(|_| generate_random_vector(NUM_FEATURES))
+            .collect();
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters,
+            num_data_points_for_clustering: 100,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: base_directory.clone(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features: NUM_FEATURES,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .unwrap();
+        for (i, vector) in vectors.iter().enumerate() {
+            builder.add_vector(1000 + i as u128, vector).unwrap();
+        }
+        builder.build().unwrap();
+        IvfWriter::<_, PlainEncoder, L2DistanceCalculator>::new(
+            base_directory.clone(),
+            NoQuantizer::<L2DistanceCalculator>::new(NUM_FEATURES),
+        )
+        .write(&mut builder, false)
+        .unwrap();
+        let mut ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> = Ivf::new(
+            FixedFileVectorStorage::<f32>::new(format!("{}/vectors", base_directory), NUM_FEATURES)
+                .unwrap(),
+            FixedIndexFile::new(format!("{}/index", base_directory)).unwrap(),
+            num_clusters,
+            NoQuantizer::<L2DistanceCalculator>::new(NUM_FEATURES),
+        );
+        ivf.delete(1099).unwrap();
+
+        let output_path = format!("{}/ivf.index", base_directory);
+        FaissExporter::export_ivf_flat(&ivf, DistanceType::L2, &output_path).unwrap();
+        let bytes = std::fs::read(&output_path).unwrap();
+        let mut reader = ByteReader::new(&bytes);
+
+        assert_eq!(reader.take(4).unwrap(), b"IwFl");
+        assert_eq!(reader.read_i32().unwrap(), NUM_FEATURES as i32);
+        assert_eq!(reader.read_i64().unwrap(), 99);
+        reader.take(2 * 8 + 1).unwrap();
+        assert_eq!(reader.read_i32().unwrap(), METRIC_L2);
+        assert_eq!(reader.read_i64().unwrap(), num_clusters as i64);
+        assert_eq!(reader.read_i64().unwrap(), 1);
+
+        let centroids = check_flat_header(&mut reader, FLAT_L2_FOURCC, num_clusters, METRIC_L2);
+        for i in 0..num_clusters {
+            let centroid = ivf.index_storage.get_centroid(i).unwrap();
+            let expected: Vec<u8> = centroid.iter().flat_map(|v| v.to_le_bytes()).collect();
+            assert_eq!(centroids[i * 16..(i + 1) * 16], expected[..]);
+        }
+        // Direct map
+        assert_eq!(reader.take(1).unwrap(), [0]);
+        assert_eq!(reader.read_i64().unwrap(), 0);
+
+        assert_eq!(reader.take(4).unwrap(), b"ilar");
+        assert_eq!(reader.read_i64().unwrap(), num_clusters as i64);
+        assert_eq!(reader.read_i64().unwrap(), 16);
+        assert_eq!(reader.take(4).unwrap(), b"full");
+        assert_eq!(reader.read_i64().unwrap(), num_clusters as i64);
+        let sizes: Vec<usize> = (0..num_clusters)
+            .map(|_| reader.read_i64().unwrap() as usize)
+            .collect();
+        assert_eq!(sizes.iter().sum::<usize>(), 99);
+
+        let mut exported_ids = vec![];
+        for size in sizes {
+            let codes = reader.take(size * 16).unwrap();
+            for (j, code) in codes.chunks_exact(16).enumerate() {
+                let id = LittleEndian::read_i64(&bytes[reader.offset + j * 8..]);
+                let expected: Vec<u8> = vectors[id as usize - 1000]
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect();
+                assert_eq!(code, expected);
+                exported_ids.push(id);
+            }
+            reader.take(size * 8).unwrap();
+        }
+        assert_eq!(reader.offset, bytes.len());
+        exported_ids.sort();
+        assert_eq!(exported_ids, (1000..1099).collect::<Vec<i64>>());
+
+        // The metric comes from the given distance type, not from the calculator
+        FaissExporter::export_ivf_flat(&ivf, DistanceType::Cosine, &output_path).unwrap();
+        let bytes = std::fs::read(&output_path).unwrap();
+        let mut reader = ByteReader::new(&bytes);
+        reader.take(4 + 4 + 8 + 2 * 8 + 1).unwrap();
+        assert_eq!(reader.read_i32().unwrap(), METRIC_INNER_PRODUCT);
+    }
+}
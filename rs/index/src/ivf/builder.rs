@@ -9,19 +9,40 @@ use atomic_refcell::AtomicRefCell;
 use log::debug;
 use rand::seq::SliceRandom;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 use sorted_vec::SortedVec;
 use utils::distance::l2::L2DistanceCalculator;
-use utils::kmeans_builder::kmeans_builder::{KMeansBuilder, KMeansVariant};
+use utils::io::fvecs::FvecsReader;
+use utils::kmeans_builder::kmeans_builder::{rebalance_assignments, KMeansBuilder, KMeansVariant};
+use utils::validation::validate_vector;
 use utils::{ceil_div, CalculateSquared, DistanceCalculator};
 
+use super::checkpoint::IvfBuildCheckpoint;
 use crate::posting_list::file::FileBackedAppendablePostingListStorage;
 use crate::posting_list::PostingListStorage;
 use crate::utils::PointAndDistance;
 use crate::vector::file::FileBackedAppendableVectorStorage;
 use crate::vector::VectorStorage;
 
+pub const DEFAULT_CHECKPOINT_INTERVAL: usize = 10;
+
+/// Configuration of an `IvfBuilder`.
+///
+/// By default (`allow_online_insertion: false`) the builder is meant for bulk builds: added
+/// vectors are buffered and appended to the vector storage `batch_size` at a time, and posting
+/// lists are only computed once, by `build`. Vectors added after `build` are stored but not
+/// assigned to any cluster.
+///
+/// With `allow_online_insertion: true`, vectors are appended to the storage as soon as they are
+/// added, and vectors added after `build` are assigned to their nearest centroids (with the same
+/// `max_clusters_per_vector` and `distance_threshold` rules as `build`). The centroids are not
+/// retrained, so the clusters drift as more vectors are inserted. Posting lists can only be
+/// appended to as a whole, so they are rewritten on the next `flush`, which makes this mode
+/// slower for bulk loads.
+#[derive(Serialize, Deserialize)]
 pub struct IvfBuilderConfig {
     pub max_iteration: usize,
+    // Number of added vectors buffered before they are appended to the vector storage
     pub batch_size: usize,
     pub num_clusters: usize,
     pub num_data_points_for_clustering: usize,
@@ -35,9 +56,17 @@ pub struct IvfBuilderConfig {
     pub file_size: usize,
     pub num_features: usize,
 
-    // Parameters for clustering.
+    // Parameters for clustering. See `KMeansBuilder` for how `tolerance` is used by each variant.
     pub tolerance: f32,
+    pub kmeans_variant: KMeansVariant,
     pub max_posting_list_size: usize,
+
+    // Centroids to use instead of training KMeans, in FVECS format. Building several indexes
+    // with the same centroids (e.g. over shards of a dataset) gives them the same clusters.
+    pub initial_centroids_fvecs_path: Option<String>,
+
+    // Assign vectors added after `build` to the existing clusters
+    pub allow_online_insertion: bool,
 }
 
 pub struct IvfBuilder<D: DistanceCalculator + CalculateSquared + Send + Sync> {
@@ -46,6 +75,21 @@ pub struct IvfBuilder<D: DistanceCalculator + CalculateSquared + Send + Sync> {
     centroids: AtomicRefCell<Box<dyn VectorStorage<f32> + Send + Sync>>,
     posting_lists: Box<dyn for<'a> PostingListStorage<'a>>,
     doc_id_mapping: Vec<u128>,
+    // Vectors added since the last flush, with their doc ids
+    pending_vectors: Vec<(u128, Vec<f32>)>,
+    // Whether posting lists were built, so that new vectors are inserted online
+    built: bool,
+    // Vectors inserted after `build` and the posting lists they belong to, not yet written to
+    // the posting list storage
+    pending_insertions: Vec<(u64, Vec<u64>)>,
+
+    // Skip the NaN/Inf check on added vectors
+    skip_vector_validation: bool,
+
+    // Number of posting list splits between two checkpoints of `build_centroids`
+    checkpoint_interval: usize,
+    // Checkpoint to continue `build_centroids` from, see `resume_from_checkpoint`
+    checkpoint: Option<IvfBuildCheckpoint>,
     _marker: PhantomData<D>,
 }
 
@@ -138,6 +182,17 @@ impl PartialEq for PostingListWithStoppingPoints {
 
 impl Eq for PostingListWithStoppingPoints {}
 
+/// `k * sum(size^2) / (sum(size))^2` for `k` clusters. It is 1.0 when all clusters have the same
+/// size, and grows as points concentrate in fewer clusters, up to `k`.
+pub fn imbalance_coefficient(cluster_sizes: &[usize]) -> f32 {
+    let total: usize = cluster_sizes.iter().sum();
+    if total == 0 {
+        return 1.0;
+    }
+    let sum_of_squares: f64 = cluster_sizes.iter().map(|&x| (x as f64) * (x as f64)).sum();
+    (cluster_sizes.len() as f64 * sum_of_squares / (total as f64 * total as f64)) as f32
+}
+
 impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
     /// Create a new IvfBuilder
     pub fn new(config: IvfBuilderConfig) -> Result<Self> {
@@ -166,6 +221,26 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
                 config.num_features,
             )));
 
+        if let Some(path) = &config.initial_centroids_fvecs_path {
+            let initial_centroids = FvecsReader::read(path)?;
+            if initial_centroids.is_empty() {
+                return Err(anyhow!("No centroids in {}", path));
+            }
+            for (i, centroid) in initial_centroids.iter().enumerate() {
+                if centroid.len() != config.num_features {
+                    return Err(anyhow!(
+                        "Centroid {} in {} has dimension {}, expected {}",
+                        i,
+                        path,
+                        centroid.len(),
+                        config.num_features
+                    ));
+                }
+                centroids.borrow_mut().append(centroid)?;
+            }
+            debug!("Loaded {} centroids from {}", initial_centroids.len(), path);
+        }
+
         let posting_lists_path = format!("{}/builder_posting_list_storage", config.base_directory);
         create_dir(&posting_lists_path)?;
 
@@ -181,10 +256,49 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
             centroids,
             posting_lists,
             doc_id_mapping: Vec::new(),
+            pending_vectors: Vec::new(),
+            built: false,
+            pending_insertions: Vec::new(),
+            skip_vector_validation: false,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            checkpoint: None,
             _marker: PhantomData,
         })
     }
 
+    /// Creates a builder that continues the build checkpointed in `config.base_directory`.
+    /// Vectors are not part of the checkpoint: the same vectors must be added again, in the same
+    /// order, before calling `build`, which then skips the posting list splits that were done.
+    pub fn resume_from_checkpoint(config: IvfBuilderConfig) -> Result<Self> {
+        let checkpoint = IvfBuildCheckpoint::read(&config.base_directory)?;
+        if checkpoint.config != serde_yaml::to_string(&config)? {
+            return Err(anyhow!(
+                "Checkpoint in {} was written with a different config",
+                config.base_directory
+            ));
+        }
+        debug!("Resuming the build from iteration {}", checkpoint.iteration);
+
+        // Storages of the interrupted build
+        for storage in [
+            "builder_vector_storage",
+            "builder_centroid_storage",
+            "builder_posting_list_storage",
+        ] {
+            if let Err(err) =
+                std::fs::remove_dir_all(format!("{}/{}", config.base_directory, storage))
+            {
+                if err.kind() != ErrorKind::NotFound {
+                    return Err(err.into());
+                }
+            }
+        }
+
+        let mut builder = Self::new(config)?;
+        builder.checkpoint = Some(checkpoint);
+        Ok(builder)
+    }
+
     pub fn config(&self) -> &IvfBuilderConfig {
         &self.config
     }
@@ -209,13 +323,97 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
         &mut *self.posting_lists
     }
 
-    /// Add a new vector to the dataset for training
+    /// Disables the NaN/Inf check in `add_vector`, for inputs that are known to be valid.
+    pub fn set_skip_vector_validation(&mut self, skip_vector_validation: bool) {
+        self.skip_vector_validation = skip_vector_validation;
+    }
+
+    /// Sets how many posting lists `build` splits between two checkpoints. 0 disables
+    /// checkpoints.
+    pub fn set_checkpoint_interval(&mut self, checkpoint_interval: usize) {
+        self.checkpoint_interval = checkpoint_interval;
+    }
+
+    /// Add a new vector to the dataset for training. Vectors are buffered and appended to the
+    /// vector storage every `batch_size` vectors, see `flush`. With online insertion enabled,
+    /// the vector is appended right away, and assigned to its clusters if `build` was called.
     pub fn add_vector(&mut self, doc_id: u128, data: &[f32]) -> Result<()> {
-        self.vectors.borrow_mut().append(&data)?;
-        self.generate_id(doc_id)?;
+        if !self.skip_vector_validation {
+            validate_vector(data, "IvfBuilder::add_vector")?;
+        }
+        // Check the length here so that a bad vector doesn't fail a later flush halfway
+        if data.len() != self.config.num_features {
+            return Err(anyhow!(
+                "vector length mismatch: expected {}, got {}",
+                self.config.num_features,
+                data.len()
+            ));
+        }
+        if self.config.allow_online_insertion {
+            return self.insert_vector(doc_id, data);
+        }
+        self.pending_vectors.push((doc_id, data.to_vec()));
+        if self.pending_vectors.len() >= max(self.config.batch_size, 1) {
+            self.flush()?;
+        }
         Ok(())
     }
 
+    /// Appends the buffered vectors to the vector storage. Building, reindexing and suggesting
+    /// the number of clusters flush first, so this only needs to be called to read the vectors
+    /// or doc id mapping before that.
+    pub fn flush(&mut self) -> Result<()> {
+        self.flush_insertions()?;
+        if self.pending_vectors.is_empty() {
+            return Ok(());
+        }
+        let pending_vectors = std::mem::take(&mut self.pending_vectors);
+        {
+            let mut vectors = self.vectors.borrow_mut();
+            for (_, data) in pending_vectors.iter() {
+                vectors.append(data)?;
+            }
+        }
+        for (doc_id, _) in pending_vectors {
+            self.generate_id(doc_id)?;
+        }
+        Ok(())
+    }
+
+    fn insert_vector(&mut self, doc_id: u128, data: &[f32]) -> Result<()> {
+        self.vectors.borrow_mut().append(data)?;
+        let point_id = self.generate_id(doc_id)?;
+        if self.built {
+            let posting_list_ids =
+                Self::assign_to_centroids(data, self.centroids.borrow().as_ref(), &self.config)?;
+            self.pending_insertions
+                .push((point_id as u64, posting_list_ids));
+        }
+        Ok(())
+    }
+
+    /// Rewrites the posting lists with the vectors inserted since the last flush.
+    fn flush_insertions(&mut self) -> Result<()> {
+        if self.pending_insertions.is_empty() {
+            return Ok(());
+        }
+        let mut posting_lists = Vec::with_capacity(self.posting_lists.len());
+        for i in 0..self.posting_lists.len() {
+            posting_lists.push(
+                self.posting_lists
+                    .get(i as u32)?
+                    .iter()
+                    .collect::<Vec<u64>>(),
+            );
+        }
+        for (point_id, posting_list_ids) in std::mem::take(&mut self.pending_insertions) {
+            for posting_list_id in posting_list_ids {
+                posting_lists[posting_list_id as usize].push(point_id);
+            }
+        }
+        self.write_posting_lists(posting_lists)
+    }
+
     /// Add a new centroid
     pub fn add_centroid(&self, centroid: &[f32]) -> Result<()> {
         self.centroids.borrow_mut().append(centroid)?;
@@ -239,13 +437,13 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
         flattened_centroids: &[f32],
         dimension: usize,
     ) -> usize {
-        let mut max_distance = std::f32::MIN;
+        let mut min_distance = std::f32::MAX;
         let mut centroid_index = 0;
         for i in 0..flattened_centroids.len() / dimension {
             let centroid = &flattened_centroids[i * dimension..(i + 1) * dimension];
             let dist = D::calculate(&vector, &centroid);
-            if dist > max_distance {
-                max_distance = dist;
+            if dist < min_distance {
+                min_distance = dist;
                 centroid_index = i;
             }
         }
@@ -274,40 +472,29 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
 
     pub fn build_posting_lists(&mut self) -> Result<()> {
         debug!("Building posting lists");
+        self.flush()?;
 
         let mut posting_lists: Vec<Vec<u64>> =
             vec![Vec::with_capacity(0); self.centroids.borrow().len()];
         // Assign vectors to nearest centroids
         // self.assign_docs_to_cluster(doc_ids, flattened_centroids)
 
+        if let KMeansVariant::Balanced { slack } = self.config.kmeans_variant {
+            let posting_lists = self.balanced_posting_lists(slack)?;
+            return self.write_posting_lists(posting_lists);
+        }
+
         let doc_ids = (0..self.vectors.borrow().len()).collect::<Vec<usize>>();
         // let vector_clone = self.vectors.clone();
-        let max_clusters_per_vector = self.config.max_clusters_per_vector;
         let posting_list_per_doc = doc_ids
             .par_iter()
             .map(|doc_id| {
-                let nearest_centroids = Self::find_nearest_centroids(
+                Self::assign_to_centroids(
                     self.vectors.borrow().get(*doc_id as u32).unwrap(),
                     self.centroids.borrow().as_ref(),
-                    max_clusters_per_vector,
+                    &self.config,
                 )
-                .expect("Nearest centroids should not be None");
-                // Find the nearest distance, ensuring that NaN values are treated as greater than any
-                // other value
-                let nearest_distance = nearest_centroids
-                    .iter()
-                    .map(|pad| pad.distance.into_inner())
-                    .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Greater))
-                    .expect("nearest_distance should not be None");
-                let mut accepted_centroid_ids = vec![];
-                for centroid_and_distance in nearest_centroids.iter() {
-                    if (centroid_and_distance.distance - nearest_distance).abs()
-                        <= nearest_distance * self.config.distance_threshold
-                    {
-                        accepted_centroid_ids.push(centroid_and_distance.point_id as u64);
-                    }
-                }
-                accepted_centroid_ids
+                .expect("Nearest centroids should not be None")
             })
             .collect::<Vec<Vec<u64>>>();
 
@@ -320,6 +507,73 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
                 });
             });
 
+        self.write_posting_lists(posting_lists)
+    }
+
+    /// Assigns every vector to its nearest centroid, then moves vectors between posting lists
+    /// until their sizes are within `slack` of the mean, see `rebalance_assignments`. Vectors
+    /// belong to a single posting list, regardless of `max_clusters_per_vector`.
+    fn balanced_posting_lists(&self, slack: f32) -> Result<Vec<Vec<u64>>> {
+        let vectors = self.vectors.borrow();
+        let centroids = self.centroids.borrow();
+        let num_features = self.config.num_features;
+        let mut flattened_centroids = Vec::with_capacity(centroids.len() * num_features);
+        for i in 0..centroids.len() {
+            flattened_centroids.extend_from_slice(centroids.get(i as u32)?);
+        }
+        let points = (0..vectors.len())
+            .map(|doc_id| vectors.get(doc_id as u32))
+            .collect::<Result<Vec<&[f32]>>>()?;
+
+        let mut assignments = points
+            .par_iter()
+            .map(|point| {
+                Self::find_nearest_centroid_inmemory(point, &flattened_centroids, num_features)
+            })
+            .collect::<Vec<usize>>();
+        rebalance_assignments::<D>(
+            &points,
+            &flattened_centroids,
+            num_features,
+            &mut assignments,
+            slack,
+        );
+
+        let mut posting_lists: Vec<Vec<u64>> = vec![Vec::new(); centroids.len()];
+        for (doc_id, centroid_id) in assignments.into_iter().enumerate() {
+            posting_lists[centroid_id].push(doc_id as u64);
+        }
+        Ok(posting_lists)
+    }
+
+    /// Returns the ids of the posting lists `vector` belongs to: its nearest centroid, plus the
+    /// other of its `max_clusters_per_vector` nearest centroids within `distance_threshold`.
+    fn assign_to_centroids(
+        vector: &[f32],
+        centroids: &dyn VectorStorage<f32>,
+        config: &IvfBuilderConfig,
+    ) -> Result<Vec<u64>> {
+        let nearest_centroids =
+            Self::find_nearest_centroids(vector, centroids, config.max_clusters_per_vector)?;
+        // Find the nearest distance, ensuring that NaN values are treated as greater than any
+        // other value
+        let nearest_distance = nearest_centroids
+            .iter()
+            .map(|pad| pad.distance.into_inner())
+            .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Greater))
+            .ok_or(anyhow!("No centroid to assign the vector to"))?;
+        let mut accepted_centroid_ids = vec![];
+        for centroid_and_distance in nearest_centroids.iter() {
+            if (centroid_and_distance.distance - nearest_distance).abs()
+                <= nearest_distance * config.distance_threshold
+            {
+                accepted_centroid_ids.push(centroid_and_distance.point_id as u64);
+            }
+        }
+        Ok(accepted_centroid_ids)
+    }
+
+    fn write_posting_lists(&mut self, posting_lists: Vec<Vec<u64>>) -> Result<()> {
         let posting_list_storage_location = format!(
             "{}/builder_posting_list_storage",
             self.config.base_directory
@@ -415,7 +669,7 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
             self.config.max_iteration,
             self.config.tolerance,
             self.config.num_features,
-            KMeansVariant::Lloyd,
+            self.config.kmeans_variant,
         );
 
         let flattened_dataset =
@@ -441,6 +695,34 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
 
     pub fn build_centroids(&mut self) -> Result<()> {
         debug!("Building centroids");
+        self.flush()?;
+        self.build_centroids_until(None)?;
+        Ok(())
+    }
+
+    /// Posting lists of the first k-means pass, or of the checkpoint to resume from, with the
+    /// number of posting lists split so far.
+    fn initial_posting_list_infos(&mut self) -> Result<(Vec<PostingListInfo>, usize)> {
+        if let Some(checkpoint) = self.checkpoint.take() {
+            let num_vectors = self.vectors.borrow().len();
+            if checkpoint.num_vectors != num_vectors {
+                return Err(anyhow!(
+                    "Checkpoint has {} vectors, but {} were added",
+                    checkpoint.num_vectors,
+                    num_vectors
+                ));
+            }
+            let centroids = checkpoint.centroids(self.config.num_features)?;
+            let posting_list_infos = centroids
+                .into_iter()
+                .zip(checkpoint.posting_lists)
+                .map(|(centroid, posting_list)| PostingListInfo {
+                    centroid,
+                    posting_list,
+                })
+                .collect();
+            return Ok((posting_list_infos, checkpoint.iteration));
+        }
 
         // First pass to get the initial centroids
         let num_clusters = self.compute_actual_num_clusters(
@@ -453,7 +735,7 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
             self.config.max_iteration,
             self.config.tolerance,
             self.config.num_features,
-            KMeansVariant::Lloyd,
+            self.config.kmeans_variant,
         );
 
         // Sample the dataset to build the first set of centroids
@@ -476,6 +758,28 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
 
         let result = kmeans.fit(flattened_dataset)?;
         let posting_list_infos = self.assign_docs_to_cluster(indices, result.centroids.as_ref())?;
+        Ok((posting_list_infos, 0))
+    }
+
+    fn write_checkpoint(&self, heap: &BinaryHeap<PostingListInfo>, iteration: usize) -> Result<()> {
+        let (centroids, posting_lists): (Vec<Vec<f32>>, Vec<Vec<usize>>) = heap
+            .iter()
+            .map(|info| (info.centroid.clone(), info.posting_list.clone()))
+            .unzip();
+        IvfBuildCheckpoint::new(
+            serde_yaml::to_string(&self.config)?,
+            iteration,
+            self.vectors.borrow().len(),
+            &centroids,
+            posting_lists,
+        )
+        .write(&self.config.base_directory)
+    }
+
+    /// Builds the centroids, stopping after `stop_at_iteration` posting list splits if set.
+    /// Returns whether the centroids were built.
+    fn build_centroids_until(&mut self, stop_at_iteration: Option<usize>) -> Result<bool> {
+        let (posting_list_infos, mut num_iter) = self.initial_posting_list_infos()?;
 
         // Repeatedly run kmeans on the longest posting list until no posting list is longer
         // than max_posting_list_size
@@ -484,7 +788,6 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
             heap.push(posting_list_info);
         }
 
-        let mut num_iter = 0 as usize;
         while heap.len() > 0 {
             match heap.peek() {
                 None => break,
@@ -507,6 +810,13 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
             for posting_list_info in new_posting_list_infos {
                 heap.push(posting_list_info);
             }
+
+            if self.checkpoint_interval > 0 && num_iter % self.checkpoint_interval == 0 {
+                self.write_checkpoint(&heap, num_iter)?;
+            }
+            if stop_at_iteration == Some(num_iter) {
+                return Ok(false);
+            }
         }
         debug!("Number of iterations to cluster: {}", num_iter);
 
@@ -520,12 +830,131 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
             self.add_centroid(&posting_list_info.centroid)?;
         }
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Suggests a number of clusters for the added vectors. K-means runs on a 1% sample (at least
+    /// `num_data_points_for_clustering` points) with a doubling number of clusters, then binary
+    /// searches between the last count whose imbalance coefficient is within `target_imbalance`
+    /// and the first one above it. Returns the largest such count, up to `max_clusters`.
+    pub fn suggest_num_clusters(
+        &mut self,
+        target_imbalance: f32,
+        max_clusters: usize,
+    ) -> Result<usize> {
+        if target_imbalance < 1.0 {
+            return Err(anyhow!(
+                "Target imbalance needs to be at least 1.0, got {}",
+                target_imbalance
+            ));
+        }
+        self.flush()?;
+        let num_vectors = self.vectors.borrow().len();
+        let sample_size = max(
+            num_vectors / 100,
+            self.config.num_data_points_for_clustering,
+        )
+        .min(num_vectors);
+        let max_clusters = max_clusters.min(sample_size);
+        if max_clusters <= 1 {
+            return Ok(1);
+        }
+
+        let doc_ids: Vec<usize> = (0..num_vectors).collect();
+        let sample = self.get_sample_dataset_from_doc_ids(&doc_ids, sample_size)?;
+        let sample_imbalance = |num_clusters: usize| -> Result<f32> {
+            let kmeans = KMeansBuilder::<D>::new(
+                num_clusters,
+                self.config.max_iteration,
+                self.config.tolerance,
+                self.config.num_features,
+                self.config.kmeans_variant,
+            );
+            let result = kmeans.fit(sample.clone())?;
+            let mut cluster_sizes = vec![0; num_clusters];
+            for assignment in result.assignments {
+                cluster_sizes[assignment] += 1;
+            }
+            let imbalance = imbalance_coefficient(&cluster_sizes);
+            debug!(
+                "Imbalance coefficient with {} clusters: {}",
+                num_clusters, imbalance
+            );
+            Ok(imbalance)
+        };
+
+        // A single cluster is always balanced.
+        let mut good = 1;
+        let mut bad = None;
+        while good < max_clusters {
+            let num_clusters = min(good * 2, max_clusters);
+            if sample_imbalance(num_clusters)? <= target_imbalance {
+                good = num_clusters;
+            } else {
+                bad = Some(num_clusters);
+                break;
+            }
+        }
+        if let Some(mut bad) = bad {
+            while bad - good > 1 {
+                let num_clusters = good + (bad - good) / 2;
+                if sample_imbalance(num_clusters)? <= target_imbalance {
+                    good = num_clusters;
+                } else {
+                    bad = num_clusters;
+                }
+            }
+        }
+        Ok(good)
+    }
+
+    pub fn set_num_clusters(&mut self, num_clusters: usize) {
+        self.config.num_clusters = num_clusters;
+    }
+
+    fn posting_list_sizes(&self) -> Result<Vec<usize>> {
+        let mut posting_list_sizes = Vec::with_capacity(self.posting_lists.len());
+        for i in 0..self.posting_lists.len() {
+            posting_list_sizes.push(self.posting_lists.get(i as u32)?.iter().count());
+        }
+        Ok(posting_list_sizes)
+    }
+
+    /// Imbalance coefficient of the built posting lists, see `imbalance_coefficient`.
+    pub fn imbalance_coefficient(&self) -> Result<f32> {
+        Ok(imbalance_coefficient(&self.posting_list_sizes()?))
+    }
+
+    /// Mean, standard deviation, min and max of the sizes of the built posting lists.
+    pub fn cluster_size_stats(&self) -> Result<(f32, f32, usize, usize)> {
+        let posting_list_sizes = self.posting_list_sizes()?;
+        if posting_list_sizes.is_empty() {
+            return Ok((0.0, 0.0, 0, 0));
+        }
+        let num_posting_lists = posting_list_sizes.len() as f32;
+        let mean = posting_list_sizes.iter().sum::<usize>() as f32 / num_posting_lists;
+        let variance = posting_list_sizes
+            .iter()
+            .map(|size| (*size as f32 - mean) * (*size as f32 - mean))
+            .sum::<f32>()
+            / num_posting_lists;
+        Ok((
+            mean,
+            variance.sqrt(),
+            *posting_list_sizes.iter().min().unwrap(),
+            *posting_list_sizes.iter().max().unwrap(),
+        ))
     }
 
     pub fn build(&mut self) -> Result<()> {
-        self.build_centroids()?;
+        self.flush()?;
+        // Centroids loaded in `new` are used as is
+        if self.config.initial_centroids_fvecs_path.is_none() {
+            self.build_centroids()?;
+        }
         self.build_posting_lists()?;
+        self.built = true;
+        IvfBuildCheckpoint::remove(&self.config.base_directory)?;
 
         Ok(())
     }
@@ -633,6 +1062,7 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> IvfBuilder<D> {
 
     /// Assign new ids to the vectors
     fn get_reassigned_ids(&mut self) -> Result<Vec<i32>> {
+        self.flush()?;
         let vector_length = self.vectors.borrow().len();
         let mut assigned_ids = vec![-1; vector_length];
 
@@ -767,6 +1197,27 @@ mod tests {
         count
     }
 
+    #[test]
+    fn test_find_nearest_centroid_inmemory() {
+        let flattened_centroids = [0.0, 0.0, 10.0, 10.0, 4.0, 4.0];
+        assert_eq!(
+            IvfBuilder::<L2DistanceCalculator>::find_nearest_centroid_inmemory(
+                &[3.0, 3.5],
+                &flattened_centroids,
+                2
+            ),
+            2
+        );
+        assert_eq!(
+            IvfBuilder::<L2DistanceCalculator>::find_nearest_centroid_inmemory(
+                &[-1.0, 0.5],
+                &flattened_centroids,
+                2
+            ),
+            0
+        );
+    }
+
     #[test]
     fn test_build_posting_lists() {
         env_logger::init();
@@ -796,7 +1247,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
         // Generate 1000 vectors of f32, dimension 4
@@ -860,7 +1314,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -898,7 +1355,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -954,7 +1414,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -1021,7 +1484,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -1094,7 +1560,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -1167,7 +1636,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -1262,7 +1734,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -1340,7 +1815,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -1388,6 +1866,475 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ivf_builder_add_invalid_vector() {
+        let temp_dir = tempdir::TempDir::new("ivf_builder_add_invalid_vector_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_features = 4;
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters: 1,
+            num_data_points_for_clustering: 10,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory,
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .expect("Failed to create builder");
+
+        assert!(builder.add_vector(0, &[1.0, f32::NAN, 3.0, 4.0]).is_err());
+        assert!(builder
+            .add_vector(0, &[1.0, 2.0, f32::INFINITY, 4.0])
+            .is_err());
+        assert_eq!(builder.vectors.borrow().len(), 0);
+        assert!(builder.doc_id_mapping.is_empty());
+
+        builder.set_skip_vector_validation(true);
+        assert!(builder.add_vector(0, &[1.0, f32::NAN, 3.0, 4.0]).is_ok());
+        builder.flush().unwrap();
+        assert_eq!(builder.vectors.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_ivf_builder_flush() {
+        let temp_dir = tempdir::TempDir::new("ivf_builder_flush_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_features = 2;
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters: 2,
+            num_data_points_for_clustering: 10,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory,
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .expect("Failed to create builder");
+
+        // Vectors are appended once a full batch is buffered
+        for i in 0..3 {
+            builder.add_vector(i, &[i as f32, 0.0]).unwrap();
+        }
+        assert_eq!(builder.vectors.borrow().len(), 0);
+        assert!(builder.doc_id_mapping.is_empty());
+        builder.add_vector(3, &[3.0, 0.0]).unwrap();
+        assert_eq!(builder.vectors.borrow().len(), 4);
+        assert_eq!(builder.doc_id_mapping, vec![0, 1, 2, 3]);
+
+        // Mismatched lengths are rejected before they are buffered
+        assert!(builder.add_vector(4, &[4.0]).is_err());
+
+        builder.add_vector(4, &[4.0, 0.0]).unwrap();
+        builder.flush().unwrap();
+        assert_eq!(builder.vectors.borrow().len(), 5);
+        assert_eq!(builder.vectors.borrow().get(4).unwrap(), &[4.0, 0.0]);
+        assert_eq!(builder.doc_id_mapping, vec![0, 1, 2, 3, 4]);
+
+        // Building flushes the vectors added since the last flush
+        builder.add_vector(5, &[5.0, 0.0]).unwrap();
+        builder.build().unwrap();
+        assert_eq!(builder.vectors.borrow().len(), 6);
+        assert_eq!(builder.doc_id_mapping.len(), 6);
+        let num_assigned: usize = (0..builder.posting_lists.len())
+            .map(|i| builder.posting_lists.get(i as u32).unwrap().iter().count())
+            .sum();
+        assert_eq!(num_assigned, 6);
+    }
+
+    #[test]
+    fn test_ivf_builder_online_insertion() {
+        let temp_dir = tempdir::TempDir::new("ivf_builder_online_insertion_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let centroids_path = format!("{}/centroids.fvecs", base_directory);
+        let mut bytes = vec![];
+        for centroid in [[0.0f32, 0.0], [100.0, 100.0]] {
+            bytes.extend_from_slice(&2i32.to_le_bytes());
+            for value in centroid {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        std::fs::write(&centroids_path, bytes).unwrap();
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters: 2,
+            num_data_points_for_clustering: 10,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: format!("{}/builder", base_directory),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features: 2,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: Some(centroids_path),
+            allow_online_insertion: true,
+        })
+        .expect("Failed to create builder");
+
+        // Vectors are appended without batching
+        builder.add_vector(10, &[1.0, 1.0]).unwrap();
+        assert_eq!(builder.vectors.borrow().len(), 1);
+        builder.add_vector(11, &[99.0, 99.0]).unwrap();
+        builder.build().unwrap();
+        let posting_list = |builder: &IvfBuilder<L2DistanceCalculator>, i: u32| {
+            builder
+                .posting_lists
+                .get(i)
+                .unwrap()
+                .iter()
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(posting_list(&builder, 0), vec![0]);
+        assert_eq!(posting_list(&builder, 1), vec![1]);
+
+        // Vectors inserted after building go to their nearest cluster on the next flush
+        builder.add_vector(12, &[101.0, 100.0]).unwrap();
+        builder.add_vector(13, &[-1.0, 0.0]).unwrap();
+        assert_eq!(builder.doc_id_mapping, vec![10, 11, 12, 13]);
+        assert_eq!(posting_list(&builder, 1), vec![1]);
+        builder.flush().unwrap();
+        assert_eq!(posting_list(&builder, 0), vec![0, 3]);
+        assert_eq!(posting_list(&builder, 1), vec![1, 2]);
+        assert_eq!(builder.vectors.borrow().get(2).unwrap(), &[101.0, 100.0]);
+    }
+
+    #[test]
+    fn test_ivf_builder_initial_centroids() {
+        let temp_dir = tempdir::TempDir::new("ivf_builder_initial_centroids_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_features = 2;
+        let centroids_path = format!("{}/centroids.fvecs", base_directory);
+        let write_fvecs = |centroids: &[Vec<f32>]| {
+            let mut bytes = vec![];
+            for centroid in centroids {
+                bytes.extend_from_slice(&(centroid.len() as i32).to_le_bytes());
+                for value in centroid {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            std::fs::write(&centroids_path, bytes).expect("Failed to write centroids");
+        };
+        let config = |directory: &str| IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            // Ignored when the centroids are given
+            num_clusters: 10,
+            num_data_points_for_clustering: 10,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: format!("{}/{}", base_directory, directory),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: Some(centroids_path.clone()),
+            allow_online_insertion: false,
+        };
+
+        let centroids = vec![vec![0.0, 0.0], vec![10.0, 10.0], vec![20.0, 20.0]];
+        write_fvecs(&centroids);
+
+        // Two shards built with the same centroids
+        for (shard, offset) in [("shard_0", 0.0), ("shard_1", 1.0)] {
+            let mut builder: IvfBuilder<L2DistanceCalculator> =
+                IvfBuilder::new(config(shard)).expect("Failed to create builder");
+            assert_eq!(builder.centroids.borrow().len(), 3);
+            for i in 0..6 {
+                let v = (i / 2) as f32 * 10.0 + offset;
+                builder.add_vector(i, &[v, v]).unwrap();
+            }
+            builder.build().expect("Failed to build");
+
+            for (i, centroid) in centroids.iter().enumerate() {
+                assert_eq!(
+                    builder.centroids.borrow().get(i as u32).unwrap(),
+                    centroid.as_slice()
+                );
+                let posting_list: Vec<u64> = builder
+                    .posting_lists
+                    .get(i as u32)
+                    .unwrap()
+                    .iter()
+                    .collect();
+                assert_eq!(posting_list, vec![2 * i as u64, 2 * i as u64 + 1]);
+            }
+        }
+
+        write_fvecs(&[vec![0.0, 0.0, 0.0]]);
+        assert!(IvfBuilder::<L2DistanceCalculator>::new(config("wrong_dimension")).is_err());
+        write_fvecs(&[]);
+        assert!(IvfBuilder::<L2DistanceCalculator>::new(config("empty")).is_err());
+    }
+
+    #[test]
+    fn test_ivf_builder_resume_from_checkpoint() {
+        let temp_dir = tempdir::TempDir::new("ivf_builder_resume_from_checkpoint_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_features = 4;
+        let num_groups = 40;
+        let group_size = 10;
+        let config = |directory: &str| IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters: 2,
+            num_data_points_for_clustering: 1000,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: format!("{}/{}", base_directory, directory),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            // Two groups never fit in a posting list, so the longest posting lists are split
+            // until every group has its own
+            max_posting_list_size: 2 * group_size - 1,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        };
+
+        // Groups of identical vectors always end up in the same posting list, which makes the
+        // result of the build deterministic
+        let groups: Vec<Vec<f32>> = (0..num_groups)
+            .map(|_| generate_random_vector(num_features))
+            .collect();
+        let add_vectors = |builder: &mut IvfBuilder<L2DistanceCalculator>| {
+            for (group, vector) in groups.iter().enumerate() {
+                for i in 0..group_size {
+                    builder
+                        .add_vector((group * group_size + i) as u128, vector)
+                        .expect("Vector should be added");
+                }
+            }
+        };
+        let sorted_posting_lists = |builder: &IvfBuilder<L2DistanceCalculator>| {
+            let mut posting_lists: Vec<Vec<u64>> = (0..builder.posting_lists.len())
+                .map(|i| {
+                    let mut posting_list: Vec<u64> = builder
+                        .posting_lists
+                        .get(i as u32)
+                        .unwrap()
+                        .iter()
+                        .collect();
+                    posting_list.sort();
+                    posting_list
+                })
+                .collect();
+            posting_lists.sort();
+            posting_lists
+        };
+        let expected: Vec<Vec<u64>> = (0..num_groups as u64)
+            .map(|group| {
+                (group * group_size as u64..(group + 1) * group_size as u64).collect::<Vec<_>>()
+            })
+            .collect();
+
+        let mut builder: IvfBuilder<L2DistanceCalculator> =
+            IvfBuilder::new(config("uninterrupted")).expect("Failed to create builder");
+        builder.set_checkpoint_interval(1);
+        add_vectors(&mut builder);
+        builder.build().expect("Failed to build");
+        assert_eq!(sorted_posting_lists(&builder), expected);
+        // The checkpoint is removed once the build is done
+        assert!(!PathBuf::from(IvfBuildCheckpoint::path(&builder.config.base_directory)).exists());
+
+        // Simulate a crash after 5 posting list splits
+        let mut builder: IvfBuilder<L2DistanceCalculator> =
+            IvfBuilder::new(config("interrupted")).expect("Failed to create builder");
+        builder.set_checkpoint_interval(1);
+        add_vectors(&mut builder);
+        builder.flush().expect("Failed to flush");
+        assert!(!builder.build_centroids_until(Some(5)).unwrap());
+        drop(builder);
+
+        let checkpoint = IvfBuildCheckpoint::read(&config("interrupted").base_directory).unwrap();
+        assert_eq!(checkpoint.iteration, 5);
+        assert_eq!(checkpoint.num_vectors, num_groups * group_size);
+
+        let mut other_config = config("interrupted");
+        other_config.max_posting_list_size = group_size;
+        assert!(IvfBuilder::<L2DistanceCalculator>::resume_from_checkpoint(other_config).is_err());
+
+        let mut builder: IvfBuilder<L2DistanceCalculator> =
+            IvfBuilder::resume_from_checkpoint(config("interrupted"))
+                .expect("Failed to resume builder");
+        add_vectors(&mut builder);
+        builder.build().expect("Failed to build");
+        assert_eq!(sorted_posting_lists(&builder), expected);
+        assert_eq!(builder.centroids.borrow().len(), num_groups);
+        assert!(!PathBuf::from(IvfBuildCheckpoint::path(&builder.config.base_directory)).exists());
+    }
+
+    #[test]
+    fn test_imbalance_coefficient() {
+        assert_eq!(imbalance_coefficient(&[10, 10, 10]), 1.0);
+        assert_eq!(imbalance_coefficient(&[30, 0, 0]), 3.0);
+        assert_eq!(imbalance_coefficient(&[30, 10]), 1.25);
+        assert_eq!(imbalance_coefficient(&[]), 1.0);
+    }
+
+    #[test]
+    fn test_suggest_num_clusters() {
+        let temp_dir = tempdir::TempDir::new("ivf_builder_suggest_num_clusters_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_vectors = 1000;
+        let num_features = 4;
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 100,
+            batch_size: 4,
+            num_clusters: 1,
+            num_data_points_for_clustering: num_vectors,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory,
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .expect("Failed to create builder");
+        for i in 0..num_vectors {
+            builder
+                .add_vector(i as u128, &generate_random_vector(num_features))
+                .expect("Vector should be added");
+        }
+
+        assert!(builder.suggest_num_clusters(0.5, 64).is_err());
+        assert_eq!(builder.suggest_num_clusters(f32::MAX, 48).unwrap(), 48);
+        // Never more clusters than sampled points.
+        assert_eq!(builder.suggest_num_clusters(f32::MAX, 5000).unwrap(), 1000);
+
+        let num_clusters = builder.suggest_num_clusters(1.5, 64).unwrap();
+        assert!((1..=64).contains(&num_clusters));
+        builder.set_num_clusters(num_clusters);
+        builder.build().expect("Failed to build");
+        assert_eq!(
+            builder.posting_lists.len(),
+            builder.centroids.borrow().len()
+        );
+        assert!(builder.imbalance_coefficient().unwrap() >= 1.0);
+    }
+
+    #[test]
+    fn test_ivf_builder_balanced_kmeans() {
+        let temp_dir = tempdir::TempDir::new("ivf_builder_balanced_kmeans_test")
+            .expect("Failed to create temporary directory");
+        let num_features = 4;
+        // 1100 copies of the same vector, and 9 blobs of 100 vectors
+        let mut vectors = vec![vec![0.0; num_features]; 1100];
+        for blob in 1..10 {
+            for _ in 0..100 {
+                let vector = generate_random_vector(num_features)
+                    .iter()
+                    .map(|v| v + blob as f32 * 10.0)
+                    .collect::<Vec<f32>>();
+                vectors.push(vector);
+            }
+        }
+
+        let build = |name: &str, kmeans_variant: KMeansVariant| {
+            let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+                max_iteration: 100,
+                batch_size: 4,
+                num_clusters: 10,
+                num_data_points_for_clustering: vectors.len(),
+                max_clusters_per_vector: 1,
+                distance_threshold: 0.1,
+                base_directory: format!("{}/{}", temp_dir.path().to_str().unwrap(), name),
+                memory_size: 1024,
+                file_size: 4096,
+                num_features,
+                tolerance: 0.0,
+                kmeans_variant,
+                max_posting_list_size: usize::MAX,
+                initial_centroids_fvecs_path: None,
+                allow_online_insertion: false,
+            })
+            .expect("Failed to create builder");
+            for (i, vector) in vectors.iter().enumerate() {
+                builder
+                    .add_vector(i as u128, vector)
+                    .expect("Vector should be added");
+            }
+            builder.build().expect("Failed to build");
+            builder.cluster_size_stats().unwrap()
+        };
+
+        // The copies of the same vector can only be in one cluster
+        let (mean, _, _, max_size) = build("lloyd", KMeansVariant::Lloyd);
+        assert!(max_size as f32 > 1.2 * mean);
+
+        let (mean, stddev, min_size, max_size) =
+            build("balanced", KMeansVariant::Balanced { slack: 0.2 });
+        assert!(
+            min_size as f32 >= 0.8 * mean,
+            "{} < 0.8 * {}",
+            min_size,
+            mean
+        );
+        assert!(
+            max_size as f32 <= 1.2 * mean,
+            "{} > 1.2 * {}",
+            max_size,
+            mean
+        );
+        assert!(stddev <= 0.2 * mean);
+    }
+
     #[test]
     fn test_ivf_builder() {
         let temp_dir = tempdir::TempDir::new("ivf_builder_test")
@@ -1415,7 +2362,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: balance_factor,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
         // Generate 1000 vectors of f32, dimension 4
@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor, ErrorKind, Read, Write};
+
+use anyhow::{anyhow, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+/// State of `IvfBuilder::build_centroids`, saved while the longest posting lists are split so
+/// that a crashed build doesn't start over. See `IvfBuilder::resume_from_checkpoint`.
+///
+/// The checkpoint is stored in a little-endian binary file:
+/// config length (u64), config, iteration (u64), number of vectors (u64), centroids length in
+/// bytes (u64), centroids, number of posting lists (u64), then for every posting list its length
+/// (u64) followed by its point ids (u32).
+#[derive(Debug)]
+pub struct IvfBuildCheckpoint {
+    // YAML of the builder config, to refuse resuming a different build
+    pub config: String,
+    // Number of posting lists split so far
+    pub iteration: usize,
+    pub num_vectors: usize,
+    // Centroid of every posting list, as little-endian f32
+    pub centroids: Vec<u8>,
+    // Point ids of every posting list, in the same order as the centroids
+    pub posting_lists: Vec<Vec<usize>>,
+}
+
+impl IvfBuildCheckpoint {
+    pub fn path(base_directory: &str) -> String {
+        format!("{}/checkpoint", base_directory)
+    }
+
+    pub fn new(
+        config: String,
+        iteration: usize,
+        num_vectors: usize,
+        centroids: &[Vec<f32>],
+        posting_lists: Vec<Vec<usize>>,
+    ) -> Self {
+        Self {
+            config,
+            iteration,
+            num_vectors,
+            centroids: centroids
+                .iter()
+                .flatten()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+            posting_lists,
+        }
+    }
+
+    pub fn centroids(&self, num_features: usize) -> Result<Vec<Vec<f32>>> {
+        let bytes_per_centroid = num_features * std::mem::size_of::<f32>();
+        if self.centroids.len() != self.posting_lists.len() * bytes_per_centroid {
+            return Err(anyhow!(
+                "Checkpoint has {} bytes of centroids for {} posting lists of dimension {}",
+                self.centroids.len(),
+                self.posting_lists.len(),
+                num_features
+            ));
+        }
+        Ok(self
+            .centroids
+            .chunks_exact(bytes_per_centroid)
+            .map(|centroid| {
+                centroid
+                    .chunks_exact(std::mem::size_of::<f32>())
+                    .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect()
+            })
+            .collect())
+    }
+
+    pub fn read(base_directory: &str) -> Result<Self> {
+        let mut bytes = vec![];
+        File::open(Self::path(base_directory))?.read_to_end(&mut bytes)?;
+        let mut cursor = Cursor::new(bytes.as_slice());
+
+        let config = String::from_utf8(Self::read_bytes(&mut cursor)?)?;
+        let iteration = cursor.read_u64::<LittleEndian>()? as usize;
+        let num_vectors = cursor.read_u64::<LittleEndian>()? as usize;
+        let centroids = Self::read_bytes(&mut cursor)?;
+        let num_posting_lists = cursor.read_u64::<LittleEndian>()?;
+        // Every posting list takes at least its length
+        Self::check_remaining(&cursor, num_posting_lists, std::mem::size_of::<u64>())?;
+        let mut posting_lists = Vec::with_capacity(num_posting_lists as usize);
+        for _ in 0..num_posting_lists {
+            let len = cursor.read_u64::<LittleEndian>()?;
+            Self::check_remaining(&cursor, len, std::mem::size_of::<u32>())?;
+            let mut posting_list = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                posting_list.push(cursor.read_u32::<LittleEndian>()? as usize);
+            }
+            posting_lists.push(posting_list);
+        }
+        if (cursor.position() as usize) != bytes.len() {
+            return Err(anyhow!(
+                "Checkpoint has {} trailing bytes",
+                bytes.len() - cursor.position() as usize
+            ));
+        }
+
+        Ok(Self {
+            config,
+            iteration,
+            num_vectors,
+            centroids,
+            posting_lists,
+        })
+    }
+
+    /// Writes to a temporary file first, so that a crash while writing keeps the previous
+    /// checkpoint.
+    pub fn write(&self, base_directory: &str) -> Result<()> {
+        let path = Self::path(base_directory);
+        let tmp_path = format!("{}.tmp", path);
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+        writer.write_u64::<LittleEndian>(self.config.len() as u64)?;
+        writer.write_all(self.config.as_bytes())?;
+        writer.write_u64::<LittleEndian>(self.iteration as u64)?;
+        writer.write_u64::<LittleEndian>(self.num_vectors as u64)?;
+        writer.write_u64::<LittleEndian>(self.centroids.len() as u64)?;
+        writer.write_all(&self.centroids)?;
+        writer.write_u64::<LittleEndian>(self.posting_lists.len() as u64)?;
+        for posting_list in &self.posting_lists {
+            writer.write_u64::<LittleEndian>(posting_list.len() as u64)?;
+            for &point_id in posting_list {
+                let point_id = u32::try_from(point_id)
+                    .map_err(|_| anyhow!("Point id {} doesn't fit in u32", point_id))?;
+                writer.write_u32::<LittleEndian>(point_id)?;
+            }
+        }
+
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn remove(base_directory: &str) -> Result<()> {
+        match std::fs::remove_file(Self::path(base_directory)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+    /// Reads a u64 length followed by that many bytes.
+    fn read_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+        let len = cursor.read_u64::<LittleEndian>()?;
+        Self::check_remaining(cursor, len, 1)?;
+        let mut bytes = vec![0; len as usize];
+        cursor.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Checks that `count` items of `item_size` bytes fit in what is left of the checkpoint,
+    /// before allocating for them.
+    fn check_remaining(cursor: &Cursor<&[u8]>, count: u64, item_size: usize) -> Result<()> {
+        let remaining = cursor.get_ref().len() as u64 - cursor.position();
+        if count.saturating_mul(item_size as u64) > remaining {
+            return Err(anyhow!(
+                "Checkpoint is truncated: {} items of {} bytes, {} bytes left",
+                count,
+                item_size,
+                remaining
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let temp_dir = tempdir::TempDir::new("ivf_checkpoint_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let centroids = vec![vec![1.0, -2.5], vec![0.125, 3.0]];
+        let checkpoint = IvfBuildCheckpoint::new(
+            "num_features: 2\n".to_string(),
+            5,
+            3,
+            &centroids,
+            vec![vec![0, 2], vec![1]],
+        );
+        checkpoint.write(base_directory).unwrap();
+
+        let read = IvfBuildCheckpoint::read(base_directory).unwrap();
+        assert_eq!(read.config, "num_features: 2\n");
+        assert_eq!(read.iteration, 5);
+        assert_eq!(read.num_vectors, 3);
+        assert_eq!(read.centroids(2).unwrap(), centroids);
+        assert_eq!(read.posting_lists, vec![vec![0, 2], vec![1]]);
+        assert!(read.centroids(3).is_err());
+
+        IvfBuildCheckpoint::remove(base_directory).unwrap();
+        assert!(IvfBuildCheckpoint::read(base_directory).is_err());
+        // Removing a missing checkpoint is fine
+        assert!(IvfBuildCheckpoint::remove(base_directory).is_ok());
+    }
+
+    #[test]
+    fn test_checkpoint_truncated() {
+        let temp_dir = tempdir::TempDir::new("ivf_checkpoint_truncated_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        IvfBuildCheckpoint::new(
+            "num_features: 2\n".to_string(),
+            1,
+            3,
+            &[vec![1.0, 2.0], vec![3.0, 4.0]],
+            vec![vec![0, 2], vec![1]],
+        )
+        .write(base_directory)
+        .unwrap();
+
+        let path = IvfBuildCheckpoint::path(base_directory);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(IvfBuildCheckpoint::read(base_directory).is_err());
+
+        // A huge length must not be allocated before checking it against the file size
+        let mut bytes = bytes.clone();
+        bytes[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(IvfBuildCheckpoint::read(base_directory).is_err());
+    }
+}
@@ -1,21 +1,88 @@
-use std::collections::BinaryHeap;
+use std::borrow::Cow;
+use std::cmp::min;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{create_dir_all, read_to_string, remove_dir_all, remove_file, rename, File};
+use std::io::{BufWriter, Write};
 use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use bit_vec::BitVec;
 use compression::compression::IntSeqDecoder;
+use log::{error, info};
+use num_traits::ToBytes;
+use quantization::pq::pq::AdcTable;
 use quantization::quantization::Quantizer;
 use quantization::typing::VectorOps;
 use utils::distance::l2::L2DistanceCalculatorImpl::StreamingSIMD;
+use utils::io::wrap_write;
 use utils::DistanceCalculator;
 
-use crate::index::Searchable;
-use crate::posting_list::combined_file::FixedIndexFile;
-use crate::utils::{IdWithScore, PointAndDistance, SearchContext};
+use crate::index::{DocIdFilter, FilteredSearch, Searchable};
+use crate::ivf::writer::{combine_files, compute_sections, write_posting_lists_and_metadata};
+use crate::posting_list::combined_file::{FixedIndexFile, Header, Version};
+use crate::utils::{
+    CachePolicy, IdWithScore, PointAndDistance, SearchContext, SearchResult, VectorCache,
+};
+use crate::vector::compressed::compressed_vector_file_path;
 use crate::vector::fixed_file::FixedFileVectorStorage;
+use crate::vector::VectorReader;
 
-pub struct Ivf<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> {
-    // The dataset.
-    pub vector_storage: FixedFileVectorStorage<Q::QuantizedT>,
+// Side-car file holding the mask of deleted points, written by `Ivf::write_deleted_points`.
+pub const DELETED_POINTS_FILE_NAME: &str = "deleted_points";
+
+// Names the directory holding the current index and vector files, once `Ivf::flush` has
+// written new ones. Without it, the files are directly in the base directory.
+pub const CURRENT_FILE_NAME: &str = "CURRENT";
+
+// Each `Ivf::flush` writes its files to a new `version_<N>` directory.
+const VERSION_DIRECTORY_PREFIX: &str = "version_";
+
+/// Returns the directory holding the current index and vector files of the index in
+/// `base_directory`.
+pub fn ivf_files_directory(base_directory: &str) -> Result<String> {
+    match current_version_directory(base_directory)? {
+        Some(name) => Ok(format!("{}/{}", base_directory, name)),
+        None => Ok(base_directory.to_string()),
+    }
+}
+
+fn current_version_directory(base_directory: &str) -> Result<Option<String>> {
+    let current_path = format!("{}/{}", base_directory, CURRENT_FILE_NAME);
+    if !Path::new(&current_path).is_file() {
+        return Ok(None);
+    }
+    let name = read_to_string(&current_path)?.trim().to_string();
+    if !name.starts_with(VERSION_DIRECTORY_PREFIX) || name.contains('/') {
+        return Err(anyhow!(
+            "Invalid version directory {:?} in {}",
+            name,
+            current_path
+        ));
+    }
+    Ok(Some(name))
+}
+
+fn sync_file(path: &str) -> Result<()> {
+    File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+/// IVF index over the vectors of `S`, which are read from a local file by default. Other
+/// backends, e.g. object stores, provide their own `VectorReader`.
+pub struct Ivf<
+    Q: Quantizer,
+    DC: DistanceCalculator,
+    D: IntSeqDecoder<Item = u64>,
+    S: VectorReader<Q::QuantizedT> = FixedFileVectorStorage<<Q as Quantizer>::QuantizedT>,
+> {
+    // The dataset
+    pub vector_storage: S,
+
+    // Vectors read while scanning posting lists, shared by all searches. Used by the searches
+    // whose context has no vector cache of its own, see `set_vector_cache_capacity`.
+    vector_cache: Option<Mutex<VectorCache>>,
 
     // Each cluster is represented by a centroid vector.
     // This stores the list of centroids, along with a posting list
@@ -30,27 +97,61 @@ pub struct Ivf<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64
 
     pub quantizer: Q,
 
+    // Points of deleted documents, indexed by point id. They are skipped when scanning posting
+    // lists.
+    deleted_points: BitVec,
+    // Point ids of every doc id. Built by the first `delete`, and kept up to date by `insert`.
+    doc_point_ids: Option<HashMap<u128, Vec<u64>>>,
+
+    // Points inserted since the last `flush`, which are not in the files yet. Their point ids
+    // follow the ones of `vector_storage`, in insertion order.
+    delta_vectors: Vec<Vec<Q::QuantizedT>>,
+    delta_doc_ids: Vec<u128>,
+    // Point ids of the inserted points, per centroid. They are scanned after the posting list
+    // of the same centroid.
+    delta_posting_lists: Vec<Vec<u64>>,
+
+    // Directory the index files were read from, where `flush` writes new ones. None if they
+    // weren't read from a directory, e.g. from memory or at an offset of a larger file.
+    base_directory: Option<String>,
+
     _distance_calculator_marker: PhantomData<DC>,
     _decoder_marker: PhantomData<D>,
 }
 
-impl<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> Ivf<Q, DC, D> {
+impl<
+        Q: Quantizer,
+        DC: DistanceCalculator,
+        D: IntSeqDecoder<Item = u64>,
+        S: VectorReader<Q::QuantizedT>,
+    > Ivf<Q, DC, D, S>
+{
     pub fn new(
-        vector_storage: FixedFileVectorStorage<Q::QuantizedT>,
+        vector_storage: S,
         index_storage: FixedIndexFile,
         num_clusters: usize,
         quantizer: Q,
     ) -> Self {
+        let deleted_points = BitVec::from_elem(vector_storage.num_vectors(), false);
         Self {
             vector_storage,
+            vector_cache: None,
             index_storage,
             num_clusters,
             quantizer,
+            deleted_points,
+            doc_point_ids: None,
+            delta_vectors: vec![],
+            delta_doc_ids: vec![],
+            delta_posting_lists: vec![vec![]; num_clusters],
+            base_directory: None,
             _distance_calculator_marker: PhantomData,
             _decoder_marker: PhantomData,
         }
     }
 
+    /// Returns the `num_probes` centroids nearest to `vector`, nearest first. Fewer are returned
+    /// if the index has fewer centroids, and none if it has none.
     pub fn find_nearest_centroids(
         vector: &Vec<f32>,
         index_storage: &FixedIndexFile,
@@ -64,6 +165,10 @@ impl<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> Ivf<Q,
             let dist = DC::calculate(&vector, &centroid);
             distances.push((i as usize, dist));
         }
+        let num_probes = min(num_probes, distances.len());
+        if num_probes == 0 {
+            return Ok(vec![]);
+        }
         distances.select_nth_unstable_by(num_probes - 1, |a, b| a.1.total_cmp(&b.1));
         let mut nearest_centroids: Vec<(usize, f32)> =
             distances.into_iter().take(num_probes).collect();
@@ -71,45 +176,384 @@ impl<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> Ivf<Q,
         Ok(nearest_centroids.into_iter().map(|(idx, _)| idx).collect())
     }
 
+    /// Caches the last `capacity` vectors read by searches, shared by all searches. None
+    /// disables the cache.
+    pub fn set_vector_cache_capacity(&mut self, capacity: Option<usize>) {
+        self.vector_cache = capacity
+            .and_then(|capacity| VectorCache::new(CachePolicy::LRU(capacity)))
+            .map(Mutex::new);
+    }
+
+    /// Sets the directory the index files were read from, so that `flush` can write new ones.
+    pub fn set_base_directory(&mut self, base_directory: String) {
+        self.base_directory = Some(base_directory);
+    }
+
+    /// Number of points, including the ones inserted since the last `flush`.
+    pub fn num_vectors(&self) -> usize {
+        self.vector_storage.num_vectors() + self.delta_doc_ids.len()
+    }
+
+    /// Adds `vector` to the posting list of its nearest centroid, without rebuilding the index.
+    /// The point is kept in memory, and is visible to searches right away. `flush` writes it
+    /// to the index files.
+    pub fn insert(&mut self, doc_id: u128, vector: &[f32]) -> Result<()> {
+        let num_features = self.index_storage.header().num_features as usize;
+        if vector.len() != num_features {
+            return Err(anyhow!(
+                "Vector has {} dimensions, expected {}",
+                vector.len(),
+                num_features
+            ));
+        }
+        let centroid = Self::find_nearest_centroids(&vector.to_vec(), &self.index_storage, 1)?
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("Cannot insert into an index without centroids"))?;
+
+        let point_id = self.num_vectors() as u64;
+        self.delta_vectors
+            .push(Q::QuantizedT::process_vector(vector, &self.quantizer));
+        self.delta_doc_ids.push(doc_id);
+        self.delta_posting_lists[centroid].push(point_id);
+        self.deleted_points.push(false);
+        if let Some(doc_point_ids) = &mut self.doc_point_ids {
+            doc_point_ids.entry(doc_id).or_default().push(point_id);
+        }
+        Ok(())
+    }
+
+    /// Returns the point ids of the posting list of `centroid`, including the points inserted
+    /// since the last `flush`.
+    pub(crate) fn get_posting_list(&self, centroid: usize) -> Result<Vec<u64>> {
+        let byte_slice = self.index_storage.get_posting_list(centroid)?;
+        let mut posting_list: Vec<u64> = D::new_decoder(byte_slice)?
+            .get_iterator(byte_slice)
+            .collect();
+        posting_list.extend_from_slice(&self.delta_posting_lists[centroid]);
+        Ok(posting_list)
+    }
+
+    /// Returns the doc id of `point_id`, which may have been inserted since the last `flush`.
+    pub(crate) fn get_doc_id(&self, point_id: usize) -> Result<u128> {
+        let num_stored = self.vector_storage.num_vectors();
+        if point_id < num_stored {
+            return self.index_storage.get_doc_id(point_id);
+        }
+        self.delta_doc_ids
+            .get(point_id - num_stored)
+            .copied()
+            .ok_or_else(|| anyhow!("Point {} is not in the index", point_id))
+    }
+
+    pub(crate) fn get_quantized_vector<'a>(
+        &'a self,
+        point_id: usize,
+        context: &mut SearchContext,
+    ) -> Option<Cow<'a, [Q::QuantizedT]>> {
+        let num_stored = self.vector_storage.num_vectors();
+        if point_id < num_stored {
+            return self.vector_storage.get(point_id, context);
+        }
+        self.delta_vectors
+            .get(point_id - num_stored)
+            .map(|vector| Cow::Borrowed(vector.as_slice()))
+    }
+
+    /// Marks all points of `doc_id` as deleted, so that searches don't return them.
+    pub fn delete(&mut self, doc_id: u128) -> Result<()> {
+        if self.doc_point_ids.is_none() {
+            let mut doc_point_ids: HashMap<u128, Vec<u64>> = HashMap::new();
+            for point_id in 0..self.num_vectors() {
+                doc_point_ids
+                    .entry(self.get_doc_id(point_id)?)
+                    .or_default()
+                    .push(point_id as u64);
+            }
+            self.doc_point_ids = Some(doc_point_ids);
+        }
+
+        let point_ids = self
+            .doc_point_ids
+            .as_ref()
+            .and_then(|doc_point_ids| doc_point_ids.get(&doc_id))
+            .ok_or_else(|| anyhow!("Doc id {} is not in the index", doc_id))?;
+        for point_id in point_ids {
+            self.deleted_points.set(*point_id as usize, true);
+        }
+        Ok(())
+    }
+
+    pub fn is_deleted(&self, point_id: u64) -> bool {
+        self.deleted_points.get(point_id as usize).unwrap_or(false)
+    }
+
+    /// Returns the doc id and vector of every point that is not deleted, in point order.
+    /// Quantized vectors are reconstructed by the quantizer, so they are approximate.
+    pub fn documents(&self) -> Result<Vec<(u128, Vec<f32>)>> {
+        let mut context = SearchContext::new(false);
+        (0..self.num_vectors())
+            .filter(|point_id| !self.is_deleted(*point_id as u64))
+            .map(|point_id| {
+                let doc_id = self.get_doc_id(point_id)?;
+                let vector = self
+                    .get_quantized_vector(point_id, &mut context)
+                    .ok_or_else(|| anyhow!("Failed to read vector of point {}", point_id))?;
+                Ok((doc_id, self.quantizer.original_vector(&vector)))
+            })
+            .collect()
+    }
+
+    pub fn get_deleted_points(&self) -> &BitVec {
+        &self.deleted_points
+    }
+
+    /// Replaces the mask of deleted points. It is resized to the number of vectors.
+    pub fn set_deleted_points(&mut self, mut deleted_points: BitVec) {
+        let num_vectors = self.num_vectors();
+        deleted_points.grow(num_vectors.saturating_sub(deleted_points.len()), false);
+        deleted_points.truncate(num_vectors);
+        self.deleted_points = deleted_points;
+    }
+
+    /// Writes the mask of deleted points to `DELETED_POINTS_FILE_NAME` in `base_directory`,
+    /// where `IvfReader` loads it from. Nothing is written when no point is deleted.
+    pub fn write_deleted_points(&self, base_directory: &str) -> Result<()> {
+        let path = format!("{}/{}", base_directory, DELETED_POINTS_FILE_NAME);
+        if self.deleted_points.none() {
+            std::fs::remove_file(path).unwrap_or_default();
+            return Ok(());
+        }
+        std::fs::write(path, self.deleted_points.to_bytes())?;
+        Ok(())
+    }
+
+    /// Returns a multi-line, human-readable summary of this index.
+    pub fn describe(&self) -> String {
+        let header = self.index_storage.header();
+        let posting_list_sizes = self.posting_list_sizes();
+        let average_posting_list_size = if posting_list_sizes.is_empty() {
+            0.0
+        } else {
+            posting_list_sizes.iter().sum::<usize>() as f64 / posting_list_sizes.len() as f64
+        };
+
+        let mut description = String::new();
+        description.push_str("IVF index\n");
+        description.push_str(&format!("  clusters: {}\n", self.num_clusters));
+        description.push_str(&format!("  vectors: {}\n", self.num_vectors()));
+        description.push_str(&format!(
+            "  dimension: {} (quantized: {})\n",
+            header.num_features, header.quantized_dimension
+        ));
+        description.push_str(&format!("  encoding: {:?}\n", D::encoding_type()));
+        description.push_str(&format!(
+            "  vector storage size: {} bytes\n",
+            self.vector_storage.size_in_bytes()
+        ));
+        description.push_str(&format!(
+            "  index storage size: {} bytes\n",
+            self.index_storage.size_in_bytes()
+        ));
+        description.push_str(&format!(
+            "  average posting list size: {:.2}\n",
+            average_posting_list_size
+        ));
+        description.push_str(&format!(
+            "  imbalance coefficient: {:.4}\n",
+            imbalance_coefficient(&posting_list_sizes)
+        ));
+        description
+    }
+
+    /// Returns the number of points in each posting list.
+    fn posting_list_sizes(&self) -> Vec<usize> {
+        (0..self.num_clusters)
+            .map(
+                |centroid| match self.index_storage.get_posting_list(centroid) {
+                    Ok(byte_slice) => match D::new_decoder(byte_slice) {
+                        Ok(decoder) => decoder.get_iterator(byte_slice).count(),
+                        Err(_) => 0,
+                    },
+                    Err(_) => 0,
+                },
+            )
+            .collect()
+    }
+
+    /// Returns true if the point passes `filter`. Points whose doc id can't be read are skipped.
+    fn accepts(&self, point_id: u64, filter: Option<&dyn Fn(u128) -> bool>) -> bool {
+        match filter {
+            Some(filter) => match self.get_doc_id(point_id as usize) {
+                Ok(doc_id) => filter(doc_id),
+                Err(_) => false,
+            },
+            None => true,
+        }
+    }
+
     fn scan_posting_list(
         &self,
         centroid: usize,
         query: &[f32],
+        adc_table: Option<&AdcTable>,
+        filter: Option<&dyn Fn(u128) -> bool>,
+        context: &mut SearchContext,
+    ) -> Vec<PointAndDistance> {
+        let mut results =
+            self.scan_stored_posting_list(centroid, query, adc_table, filter, context);
+        results.extend(self.scan_delta_posting_list(centroid, query, adc_table, filter));
+        results
+    }
+
+    /// Scores the points inserted into the posting list of `centroid` since the last `flush`.
+    fn scan_delta_posting_list(
+        &self,
+        centroid: usize,
+        query: &[f32],
+        adc_table: Option<&AdcTable>,
+        filter: Option<&dyn Fn(u128) -> bool>,
+    ) -> Vec<PointAndDistance> {
+        let point_ids = match self.delta_posting_lists.get(centroid) {
+            Some(point_ids) if !point_ids.is_empty() => point_ids,
+            _ => return vec![],
+        };
+        let quantized_query = match adc_table {
+            Some(_) => vec![],
+            None => Q::QuantizedT::process_vector(query, &self.quantizer),
+        };
+        let num_stored = self.vector_storage.num_vectors() as u64;
+        let results = point_ids
+            .iter()
+            .filter(|idx| !self.is_deleted(**idx) && self.accepts(**idx, filter))
+            .map(|idx| -> Result<PointAndDistance> {
+                let vector = &self.delta_vectors[(idx - num_stored) as usize];
+                let distance = match adc_table {
+                    Some(adc_table) => self.quantizer.distance_with_adc_table(adc_table, vector)?,
+                    None => self
+                        .quantizer
+                        .distance(&quantized_query, vector, StreamingSIMD),
+                };
+                Ok(PointAndDistance::new(distance, *idx as u32))
+            })
+            .collect::<Result<Vec<_>>>();
+        results.unwrap_or_else(|e| {
+            error!("Error scoring inserted points of posting list {centroid}: {e}");
+            vec![]
+        })
+    }
+
+    fn scan_stored_posting_list(
+        &self,
+        centroid: usize,
+        query: &[f32],
+        adc_table: Option<&AdcTable>,
+        filter: Option<&dyn Fn(u128) -> bool>,
         context: &mut SearchContext,
     ) -> Vec<PointAndDistance> {
         if let Ok(byte_slice) = self.index_storage.get_posting_list(centroid) {
-            let quantized_query = Q::QuantizedT::process_vector(query, &self.quantizer);
-            let mut results: Vec<PointAndDistance> = Vec::new();
-            let decoder =
-                D::new_decoder(byte_slice).expect("Failed to create posting list decoder");
-            for idx in decoder.get_iterator(byte_slice) {
-                match self.vector_storage.get(idx as usize, context) {
-                    Some(vector) => {
-                        let distance =
-                            self.quantizer
-                                .distance(&quantized_query, vector, StreamingSIMD);
-                        results.push(PointAndDistance::new(distance, idx as u32));
+            let quantized_query = match adc_table {
+                Some(_) => vec![],
+                None => Q::QuantizedT::process_vector(query, &self.quantizer),
+            };
+            // Collect the points to scan first, so that their vectors are read in one batch
+            let num_vectors = self.vector_storage.num_vectors() as u64;
+            let mut point_ids: Vec<u32> = Vec::new();
+            let mut collect_point = |idx: u64| {
+                if idx < num_vectors && !self.is_deleted(idx) && self.accepts(idx, filter) {
+                    point_ids.push(idx as u32);
+                }
+            };
+
+            match context.posting_list_cache.as_mut() {
+                Some(cache) => {
+                    let key = self.index_storage.posting_list_id(centroid);
+                    let cached_point_ids = match cache.get(&key) {
+                        Some(point_ids) => point_ids,
+                        None => {
+                            let decoder = D::new_decoder(byte_slice)
+                                .expect("Failed to create posting list decoder");
+                            let point_ids = Arc::new(decoder.get_iterator(byte_slice).collect());
+                            cache.insert(key, Arc::clone(&point_ids));
+                            point_ids
+                        }
+                    };
+                    for idx in cached_point_ids.iter() {
+                        collect_point(*idx);
+                    }
+                }
+                None => {
+                    let decoder =
+                        D::new_decoder(byte_slice).expect("Failed to create posting list decoder");
+                    for idx in decoder.get_iterator(byte_slice) {
+                        collect_point(idx);
                     }
-                    None => {}
                 }
             }
-            results
+
+            self.vector_storage.record_pages(&point_ids, context);
+            let score = |vector: &[Q::QuantizedT], idx: u32| -> Result<PointAndDistance> {
+                let distance = match adc_table {
+                    Some(adc_table) => self.quantizer.distance_with_adc_table(adc_table, vector)?,
+                    None => self
+                        .quantizer
+                        .distance(&quantized_query, vector, StreamingSIMD),
+                };
+                Ok(PointAndDistance::new(distance, idx))
+            };
+            // The vector cache of the context takes precedence over the one of the index
+            let vectors = match (&context.vector_cache, &self.vector_cache) {
+                (None, Some(vector_cache)) => self
+                    .vector_storage
+                    .read_batch_with_shared_cache(&point_ids, vector_cache),
+                _ => self
+                    .vector_storage
+                    .read_batch_with_context(&point_ids, context),
+            };
+            let num_features = self.vector_storage.num_features();
+            let results = vectors.and_then(|vectors| {
+                point_ids
+                    .into_iter()
+                    .zip(vectors.chunks_exact(num_features))
+                    .map(|(idx, vector)| score(vector, idx))
+                    .collect::<Result<Vec<_>>>()
+            });
+            results.unwrap_or_else(|e| {
+                error!("Error scoring posting list {centroid}: {e}");
+                vec![]
+            })
         } else {
             vec![]
         }
     }
 
+    /// Points farther than `max_distance` never enter the heap, as if it was seeded with
+    /// sentinels at `max_distance`. Also returns the number of vectors scored across all probed
+    /// posting lists. Once the context times out, the remaining posting lists are skipped.
     fn search_with_centroids(
         &self,
         query: &[f32],
         nearest_centroid_ids: Vec<usize>,
         k: usize,
+        max_distance: Option<f32>,
+        filter: Option<&dyn Fn(u128) -> bool>,
         context: &mut SearchContext,
-    ) -> Vec<PointAndDistance> {
-        let mut heap = BinaryHeap::with_capacity(k);
+    ) -> (Vec<PointAndDistance>, u64) {
+        // `k` can be huge for radius searches, don't reserve more than the index holds.
+        let mut heap = BinaryHeap::with_capacity(min(k, self.num_vectors()));
+        let mut num_scanned = 0;
+        let adc_table = context.get_or_compute_adc_table(query, &self.quantizer);
         for &centroid in &nearest_centroid_ids {
-            let results = self.scan_posting_list(centroid, query, context);
+            if context.is_timed_out() {
+                break;
+            }
+            let results =
+                self.scan_posting_list(centroid, query, adc_table.as_deref(), filter, context);
+            num_scanned += results.len() as u64;
             for id_with_score in results {
+                if max_distance.is_some_and(|max_distance| *id_with_score.distance > max_distance) {
+                    continue;
+                }
                 if heap.len() < k {
                     heap.push(id_with_score);
                 } else if let Some(max) = heap.peek() {
@@ -124,14 +568,14 @@ impl<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> Ivf<Q,
         // Convert heap to a sorted vector in ascending order.
         let mut results: Vec<PointAndDistance> = heap.into_vec();
         results.sort();
-        results
+        (results, num_scanned)
     }
 
     fn map_point_id_to_doc_id(&self, point_ids: &[PointAndDistance]) -> Vec<IdWithScore> {
         point_ids
             .iter()
             .map(|x| IdWithScore {
-                id: self.index_storage.get_doc_id(x.point_id as usize).unwrap(),
+                id: self.get_doc_id(x.point_id as usize).unwrap(),
                 score: *x.distance,
             })
             .collect()
@@ -142,34 +586,225 @@ impl<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> Ivf<Q,
         query: &[f32],
         nearest_centroid_ids: Vec<usize>,
         k: usize,
+        filter: Option<&dyn Fn(u128) -> bool>,
         context: &mut SearchContext,
     ) -> Vec<IdWithScore> {
-        let point_ids = self.search_with_centroids(query, nearest_centroid_ids, k, context);
+        let (point_ids, num_scanned) =
+            self.search_with_centroids(query, nearest_centroid_ids, k, None, filter, context);
+        context.stats.num_candidates_scanned += num_scanned;
         let doc_ids = self.map_point_id_to_doc_id(&point_ids);
         doc_ids
     }
+
+    /// Same as `search`, but results with a score above `max_distance` are dropped, even if
+    /// fewer than `k` results remain.
+    pub fn search_threshold(
+        &self,
+        query: &[f32],
+        k: usize,
+        nprobes: u32,
+        max_distance: f32,
+        context: &mut SearchContext,
+    ) -> Option<Vec<IdWithScore>> {
+        self.search_impl(query, k, nprobes, Some(max_distance), None, context)
+            .map(|result| result.0)
+    }
+}
+
+impl<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> Ivf<Q, DC, D> {
+    /// Writes the points inserted since the last `flush` to new index and vector files in the
+    /// directory the index was read from, and reopens them. The files are written to a new
+    /// version directory, which is published by renaming the `CURRENT` file over the old one, so
+    /// readers see either the old files or the new ones. The old files are removed afterwards.
+    ///
+    /// Posting lists are rewritten with the encoder of `D`. Fails if the index wasn't read from a
+    /// directory, see `set_base_directory`.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.delta_doc_ids.is_empty() {
+            return Ok(());
+        }
+        let base_directory = self
+            .base_directory
+            .clone()
+            .ok_or_else(|| anyhow!("Index was not read from a directory, it can't be flushed"))?;
+        let base_directory = base_directory.as_str();
+        let old_version = current_version_directory(base_directory)?;
+        let version = match &old_version {
+            Some(name) => {
+                name[VERSION_DIRECTORY_PREFIX.len()..]
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid version directory {}", name))?
+                    + 1
+            }
+            None => 0,
+        };
+        let version_name = format!("{}{}", VERSION_DIRECTORY_PREFIX, version);
+        let flush_directory = format!("{}/{}", base_directory, version_name);
+        // Leftover of a flush that failed before being published
+        if Path::new(&flush_directory).exists() {
+            remove_dir_all(&flush_directory)?;
+        }
+        create_dir_all(&flush_directory)?;
+
+        let num_vectors = self.num_vectors();
+        let mut context = SearchContext::new(false);
+        let mut file = File::create(format!("{}/vectors", flush_directory))?;
+        let mut writer = BufWriter::new(&mut file);
+        wrap_write(&mut writer, &(num_vectors as u64).to_le_bytes())?;
+        for point_id in 0..num_vectors {
+            let vector = self
+                .get_quantized_vector(point_id, &mut context)
+                .ok_or_else(|| anyhow!("Failed to read vector of point {}", point_id))?;
+            for value in vector.iter() {
+                wrap_write(&mut writer, value.to_le_bytes().as_ref())?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+
+        let mut file = File::create(format!("{}/doc_id_mapping", flush_directory))?;
+        let mut writer = BufWriter::new(&mut file);
+        let mut doc_id_mapping_len = wrap_write(&mut writer, &(num_vectors as u128).to_le_bytes())?;
+        for point_id in 0..num_vectors {
+            doc_id_mapping_len +=
+                wrap_write(&mut writer, &self.get_doc_id(point_id)?.to_le_bytes())?;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        let mut file = File::create(format!("{}/centroids", flush_directory))?;
+        let mut writer = BufWriter::new(&mut file);
+        let mut centroids_len = wrap_write(&mut writer, &(self.num_clusters as u64).to_le_bytes())?;
+        for centroid in 0..self.num_clusters {
+            for value in self.index_storage.get_centroid(centroid)? {
+                centroids_len += wrap_write(&mut writer, &value.to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+
+        let posting_lists_and_metadata_len = write_posting_lists_and_metadata::<D::Encoder>(
+            &flush_directory,
+            self.num_clusters,
+            |i| self.get_posting_list(i),
+        )?;
+
+        let old_header = self.index_storage.header();
+        let mut header = Header {
+            version: old_header.version,
+            num_features: old_header.num_features,
+            quantized_dimension: old_header.quantized_dimension,
+            num_clusters: old_header.num_clusters,
+            num_vectors: num_vectors as u64,
+            doc_id_mapping_len: doc_id_mapping_len as u64,
+            centroids_len: centroids_len as u64,
+            posting_lists_and_metadata_len: posting_lists_and_metadata_len as u64,
+            sections: vec![],
+        };
+        if header.version == Version::V1 {
+            header.sections = compute_sections(&flush_directory, &header)
+                .context("Failed to compute section checksums")?;
+        }
+        combine_files(&flush_directory, &header)?;
+
+        let vectors_path = format!("{}/vectors", flush_directory);
+        let index_path = format!("{}/index", flush_directory);
+        sync_file(&vectors_path)?;
+        sync_file(&index_path)?;
+        File::open(&flush_directory)?.sync_all()?;
+
+        let current_path = format!("{}/{}", base_directory, CURRENT_FILE_NAME);
+        let tmp_current_path = format!("{}.tmp", current_path);
+        {
+            let mut tmp_file = File::create(&tmp_current_path)?;
+            tmp_file.write_all(version_name.as_bytes())?;
+            tmp_file.sync_all()?;
+        }
+        rename(&tmp_current_path, &current_path)?;
+        File::open(base_directory)?.sync_all()?;
+
+        self.index_storage = FixedIndexFile::new(index_path)?;
+        self.vector_storage =
+            FixedFileVectorStorage::new(vectors_path, header.quantized_dimension as usize)?;
+        if let Some(vector_cache) = &self.vector_cache {
+            vector_cache.lock().unwrap().clear();
+        }
+        self.delta_vectors.clear();
+        self.delta_doc_ids.clear();
+        self.delta_posting_lists
+            .iter_mut()
+            .for_each(|list| list.clear());
+
+        // The old files are not referenced anymore. Failing to remove them only leaks space.
+        let removed = match old_version {
+            Some(name) => remove_dir_all(format!("{}/{}", base_directory, name)),
+            None => {
+                let old_vectors_path = format!("{}/vectors", base_directory);
+                [
+                    compressed_vector_file_path(&old_vectors_path),
+                    old_vectors_path,
+                    format!("{}/index", base_directory),
+                ]
+                .iter()
+                .filter(|path| Path::new(path).is_file())
+                .try_for_each(remove_file)
+            }
+        };
+        if let Err(e) = removed {
+            error!(
+                "Failed to remove the old files of {}: {}",
+                base_directory, e
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Imbalance coefficient of the given cluster sizes: `k * sum(n_i^2) / (sum(n_i))^2`.
+/// It is 1.0 for perfectly balanced clusters and grows as clusters become skewed.
+fn imbalance_coefficient(sizes: &[usize]) -> f64 {
+    let total: usize = sizes.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let sum_of_squares: f64 = sizes.iter().map(|&size| (size * size) as f64).sum();
+    sizes.len() as f64 * sum_of_squares / (total as f64 * total as f64)
 }
 
-impl<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> Searchable
-    for Ivf<Q, DC, D>
+impl<
+        Q: Quantizer,
+        DC: DistanceCalculator,
+        D: IntSeqDecoder<Item = u64>,
+        S: VectorReader<Q::QuantizedT>,
+    > Ivf<Q, DC, D, S>
 {
-    fn search(
+    fn search_impl(
         &self,
         query: &[f32],
         k: usize,
-        ef_construction: u32, // Number of probed centroids
+        ef_search: u32, // Number of probed centroids
+        max_distance: Option<f32>,
+        filter: Option<&dyn Fn(u128) -> bool>,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>> {
-        // Find the nearest centroids to the query.
-        if let Ok(nearest_centroids) = Self::find_nearest_centroids(
-            &query.to_vec(),
-            &self.index_storage,
-            ef_construction as usize,
-        ) {
+    ) -> Option<SearchResult> {
+        // Find the nearest centroids to the query. At least one and at most all clusters are
+        // probed.
+        let num_probes = (ef_search as usize).clamp(1, self.num_clusters.max(1));
+        if let Ok(nearest_centroids) =
+            Self::find_nearest_centroids(&query.to_vec(), &self.index_storage, num_probes)
+        {
             // Search in the posting lists of the nearest centroids.
-            let point_ids = self.search_with_centroids(query, nearest_centroids, k, context);
+            let (point_ids, num_scanned) = self.search_with_centroids(
+                query,
+                nearest_centroids,
+                k,
+                max_distance,
+                filter,
+                context,
+            );
+            context.stats.num_candidates_scanned += num_scanned;
             let doc_ids = self.map_point_id_to_doc_id(&point_ids);
-            Some(doc_ids)
+            Some(doc_ids.into())
         } else {
             println!("Error finding nearest centroids");
             return None;
@@ -177,20 +812,104 @@ impl<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>> Searcha
     }
 }
 
+impl<
+        Q: Quantizer,
+        DC: DistanceCalculator,
+        D: IntSeqDecoder<Item = u64>,
+        S: VectorReader<Q::QuantizedT>,
+    > Searchable for Ivf<Q, DC, D, S>
+{
+    fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32, // Number of probed centroids
+        context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        let results = self.search_impl(query, k, ef_search, None, None, context)?;
+        if let Some(sampler) = context.recall_sampler.as_ref() {
+            if sampler.should_sample() {
+                let doc_ids = results.0.iter().map(|x| x.id).collect::<Vec<_>>();
+                let recall = sampler.record(query, k, &doc_ids);
+                info!(
+                    "Sampled search recall: {}, mean recall: {}, candidates scanned so far: {}",
+                    recall,
+                    sampler.mean_recall().unwrap_or(recall),
+                    context.stats.num_candidates_scanned
+                );
+            }
+        }
+        Some(results)
+    }
+
+    fn search_with_filter(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        filter: &DocIdFilter,
+        context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        self.search_impl(query, k, ef_search, None, Some(&**filter), context)
+    }
+
+    fn search_radius(
+        &self,
+        query: &[f32],
+        radius: f32,
+        max_results: usize,
+        ef_search: u32, // Number of probed centroids
+        context: &mut SearchContext,
+    ) -> Result<Vec<IdWithScore>> {
+        self.search_impl(query, max_results, ef_search, Some(radius), None, context)
+            .map(|result| result.0)
+            .ok_or(anyhow!("Error finding nearest centroids"))
+    }
+}
+
+impl<
+        Q: Quantizer,
+        DC: DistanceCalculator,
+        D: IntSeqDecoder<Item = u64>,
+        S: VectorReader<Q::QuantizedT>,
+    > FilteredSearch for Ivf<Q, DC, D, S>
+{
+    fn search_filtered(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32, // Number of probed centroids
+        context: &mut SearchContext,
+        filter: &dyn Fn(u128) -> bool,
+    ) -> Option<Vec<IdWithScore>> {
+        self.search_impl(query, k, ef_search, None, Some(filter), context)
+            .map(|result| result.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs::File;
     use std::io::Write;
+    use std::time::{Duration, Instant};
 
     use anyhow::anyhow;
     use compression::noc::noc::PlainDecoder;
     use num_traits::ops::bytes::ToBytes;
     use quantization::noq::noq::NoQuantizer;
     use quantization::pq::pq::ProductQuantizer;
+    use rand::Rng;
+    use utils::distance::hamming::{num_words, pack_bits, HammingDistanceCalculator};
     use utils::distance::l2::L2DistanceCalculator;
+    use utils::evaluation::recall_at_k;
     use utils::mem::{transmute_slice_to_u8, transmute_u8_to_slice};
+    use utils::test_utils::generate_random_vector;
+    use utils::BinaryDistanceCalculator;
 
     use super::*;
+    use crate::flat::index::{compute_recall_at_k, generate_ground_truth, FlatIndex};
+    use crate::utils::CachePolicy;
+    use crate::vector::bit_packed::BitPackedVectorStorage;
 
     fn create_fixed_file_vector_storage<T: ToBytes>(
         file_path: &String,
@@ -367,6 +1086,66 @@ mod tests {
         assert!(cluster_1.contains(&2));
     }
 
+    #[test]
+    fn test_ivf_describe() {
+        let temp_dir = tempdir::TempDir::new("ivf_describe_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let file_path = format!("{}/vectors", base_dir);
+        let dataset: Vec<Vec<f32>> = vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![7.0, 8.0, 9.0],
+        ];
+        assert!(create_fixed_file_vector_storage(&file_path, &dataset).is_ok());
+        let storage = FixedFileVectorStorage::<f32>::new(file_path, 3)
+            .expect("FixedFileVectorStorage should be created");
+
+        let file_path = format!("{}/index", base_dir);
+        let doc_id_mapping = vec![100u128, 101, 102];
+        let centroids = vec![vec![1.5, 2.5, 3.5], vec![5.5, 6.5, 7.5]];
+        let posting_lists = vec![vec![0], vec![1, 2]];
+        assert!(create_fixed_file_index_storage(
+            &file_path,
+            &doc_id_mapping,
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let index_storage =
+            FixedIndexFile::new(file_path).expect("FixedIndexFile should be created");
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(3);
+        let mut ivf =
+            Ivf::<_, L2DistanceCalculator, PlainDecoder>::new(storage, index_storage, 2, quantizer);
+
+        let description = ivf.describe();
+        assert!(description.contains("clusters: 2"));
+        assert!(description.contains("vectors: 3"));
+        assert!(description.contains("dimension: 3"));
+        assert!(description.contains("encoding: PlainEncoding"));
+        assert!(description.contains("vector storage size: 44 bytes"));
+        assert!(description.contains("average posting list size: 1.50"));
+        // 2 * (1 + 4) / 9
+        assert!(description.contains("imbalance coefficient: 1.1111"));
+
+        // Inserted points are counted before they are flushed
+        ivf.insert(103, &[1.0, 2.0, 3.0])
+            .expect("Vector should be inserted");
+        assert!(ivf.describe().contains("vectors: 4"));
+    }
+
+    #[test]
+    fn test_imbalance_coefficient() {
+        assert_eq!(imbalance_coefficient(&[]), 0.0);
+        assert_eq!(imbalance_coefficient(&[5, 5, 5]), 1.0);
+        assert_eq!(imbalance_coefficient(&[10, 0]), 2.0);
+    }
+
     #[test]
     fn test_find_nearest_centroids() {
         let temp_dir = tempdir::TempDir::new("find_nearest_centroids_test")
@@ -406,6 +1185,14 @@ mod tests {
 
         assert_eq!(nearest[0], 1);
         assert_eq!(nearest[1], 0);
+
+        // At most all centroids are returned
+        type TestIvf = Ivf<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, PlainDecoder>;
+        let nearest = TestIvf::find_nearest_centroids(&vector, &index_storage, 5).unwrap();
+        assert_eq!(nearest, vec![1, 0, 2]);
+        assert!(TestIvf::find_nearest_centroids(&vector, &index_storage, 0)
+            .unwrap()
+            .is_empty());
     }
 
     #[test]
@@ -466,82 +1253,894 @@ mod tests {
         assert_eq!(results[0].id, 103); // Closest to [2.0, 3.0, 4.0]
         assert_eq!(results[1].id, 100); // Second closest to [2.0, 3.0, 4.0]
         assert!(results[0].score < results[1].score);
+
+        // Only odd doc ids, so 103 is still found but 100 is skipped in favor of 101
+        let filter: DocIdFilter = Arc::new(|doc_id| doc_id % 2 == 1);
+        let results = ivf
+            .search_with_filter(&query, k, num_probes, &filter, &mut context)
+            .expect("IVF search should return a result");
+        assert_eq!(results.len(), k);
+        assert_eq!(results[0].id, 103);
+        assert_eq!(results[1].id, 101);
+
+        // A borrowed predicate works the same way
+        let accepted = |doc_id: u128| doc_id % 2 == 0;
+        let results = ivf
+            .search_filtered(&query, k, num_probes, &mut context, &accepted)
+            .expect("IVF search should return a result");
+        assert_eq!(results.len(), k);
+        assert!(results.iter().all(|result| accepted(result.id)));
+        assert_eq!(results[0].id, 100);
+        assert_eq!(results[1].id, 102);
     }
 
     #[test]
-    fn test_ivf_search_with_pq() {
-        let temp_dir = tempdir::TempDir::new("ivf_search_with_pq_test")
-            .expect("Failed to create temporary directory");
+    fn test_ivf_delete() {
+        let temp_dir =
+            tempdir::TempDir::new("ivf_delete_test").expect("Failed to create temporary directory");
         let base_dir = temp_dir
             .path()
             .to_str()
             .expect("Failed to convert temporary directory path to string")
             .to_string();
 
-        let file_path = format!("{}/vectors", base_dir);
-        let dataset = vec![
+        let vectors_path = format!("{}/vectors", base_dir);
+        let dataset: Vec<Vec<f32>> = vec![
             vec![1.0, 2.0, 3.0],
             vec![4.0, 5.0, 6.0],
             vec![7.0, 8.0, 9.0],
             vec![2.0, 3.0, 4.0],
         ];
-        let num_features = 3;
-        let subvector_dimension = 1;
-
-        let codebook = vec![1.5, 5.5, 2.5, 6.5, 3.5, 7.5];
-        let quantizer = ProductQuantizer::<L2DistanceCalculator>::new(
-            num_features,
-            1,
-            subvector_dimension,
-            codebook,
-            base_dir.clone(),
-        )
-        .expect("Can't create product quantizer");
-        let quantized_dataset: Vec<Vec<u8>> = dataset
-            .iter()
-            .map(|x| f32::process_vector(x, &quantizer))
-            .collect();
-
-        assert!(create_fixed_file_vector_storage(&file_path, &quantized_dataset).is_ok());
-        let storage = FixedFileVectorStorage::<u8>::new(
-            file_path,
-            num_features / subvector_dimension as usize,
-        )
-        .expect("FixedFileVectorStorage should be created");
-
-        let file_path = format!("{}/index", base_dir);
+        assert!(create_fixed_file_vector_storage(&vectors_path, &dataset).is_ok());
+        let index_path = format!("{}/index", base_dir);
         let doc_id_mapping = vec![100, 101, 102, 103];
         let centroids = vec![vec![1.5, 2.5, 3.5], vec![5.5, 6.5, 7.5]];
         let posting_lists = vec![vec![0, 3], vec![1, 2]];
         assert!(create_fixed_file_index_storage(
-            &file_path,
+            &index_path,
             &doc_id_mapping,
             &centroids,
             &posting_lists
         )
         .is_ok());
-        let index_storage =
-            FixedIndexFile::new(file_path).expect("FixedIndexFile should be created");
-
-        let num_clusters = 2;
-        let num_probes = 2;
+        let new_ivf = || -> Ivf<_, L2DistanceCalculator, PlainDecoder> {
+            Ivf::new(
+                FixedFileVectorStorage::<f32>::new(vectors_path.clone(), 3)
+                    .expect("FixedFileVectorStorage should be created"),
+                FixedIndexFile::new(index_path.clone()).expect("FixedIndexFile should be created"),
+                2,
+                NoQuantizer::<L2DistanceCalculator>::new(3),
+            )
+        };
 
-        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
-            Ivf::new(storage, index_storage, num_clusters, quantizer);
+        let mut ivf = new_ivf();
+        assert!(ivf.delete(103).is_ok());
+        assert!(ivf.delete(104).is_err());
+        assert!(ivf.is_deleted(3));
 
         let query = vec![2.0, 3.0, 4.0];
-        let k = 2;
         let mut context = SearchContext::new(false);
-
         let results = ivf
-            .search(&query, k, num_probes, &mut context)
+            .search(&query, 2, 2, &mut context)
             .expect("IVF search should return a result");
-
-        assert_eq!(results.len(), k);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].id, 100);
+        assert_eq!(results[1].id, 101);
+
+        // The mask survives a round trip through its side-car file
+        ivf.write_deleted_points(&base_dir)
+            .expect("Deleted points should be written");
+        let mut reopened_ivf = new_ivf();
+        reopened_ivf.set_deleted_points(BitVec::from_bytes(
+            &std::fs::read(format!("{}/{}", base_dir, DELETED_POINTS_FILE_NAME)).unwrap(),
+        ));
+        assert_eq!(reopened_ivf.get_deleted_points(), ivf.get_deleted_points());
+        let results = reopened_ivf
+            .search(&query, 4, 2, &mut context)
+            .expect("IVF search should return a result");
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|result| result.id != 103));
+    }
+
+    #[test]
+    fn test_ivf_insert_and_flush() {
+        let temp_dir = tempdir::TempDir::new("ivf_insert_and_flush_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let num_features = 4;
+        let dataset: Vec<Vec<f32>> = (0..100)
+            .map(|_| generate_random_vector(num_features))
+            .collect();
+        let centroids: Vec<Vec<f32>> = dataset[..4].to_vec();
+        let mut posting_lists = vec![vec![]; centroids.len()];
+        for (point_id, vector) in dataset.iter().enumerate() {
+            let nearest = (0..centroids.len())
+                .min_by(|a, b| {
+                    L2DistanceCalculator::calculate(vector, &centroids[*a])
+                        .total_cmp(&L2DistanceCalculator::calculate(vector, &centroids[*b]))
+                })
+                .unwrap();
+            posting_lists[nearest].push(point_id as u64);
+        }
+        let vectors_path = format!("{}/vectors", base_dir);
+        assert!(create_fixed_file_vector_storage(&vectors_path, &dataset).is_ok());
+        let index_path = format!("{}/index", base_dir);
+        let doc_id_mapping: Vec<u128> = (0..100).collect();
+        assert!(create_fixed_file_index_storage(
+            &index_path,
+            &doc_id_mapping,
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let new_ivf = || -> Ivf<_, L2DistanceCalculator, PlainDecoder> {
+            let files_directory =
+                ivf_files_directory(&base_dir).expect("Files directory should be resolved");
+            Ivf::new(
+                FixedFileVectorStorage::<f32>::new(
+                    format!("{}/vectors", files_directory),
+                    num_features,
+                )
+                .expect("FixedFileVectorStorage should be created"),
+                FixedIndexFile::new(format!("{}/index", files_directory))
+                    .expect("FixedIndexFile should be created"),
+                centroids.len(),
+                NoQuantizer::<L2DistanceCalculator>::new(num_features),
+            )
+        };
+
+        let mut ivf = new_ivf();
+        ivf.insert(999, &dataset[0])
+            .expect("Vector should be inserted");
+        assert!(ivf.flush().is_err());
+        ivf = new_ivf();
+        ivf.set_base_directory(base_dir.clone());
+        let inserted: Vec<Vec<f32>> = (0..100)
+            .map(|_| generate_random_vector(num_features))
+            .collect();
+        for (i, vector) in inserted.iter().enumerate() {
+            ivf.insert(1000 + i as u128, vector)
+                .expect("Vector should be inserted");
+        }
+        assert!(ivf.insert(2000, &[1.0, 2.0]).is_err());
+        assert_eq!(ivf.num_vectors(), 200);
+
+        let mut context = SearchContext::new(false);
+        let assert_inserted_found =
+            |ivf: &Ivf<_, L2DistanceCalculator, PlainDecoder>, context: &mut SearchContext| {
+                for (i, vector) in inserted.iter().enumerate() {
+                    let results = ivf
+                        .search(vector, 1, centroids.len() as u32, context)
+                        .expect("IVF search should return a result");
+                    assert_eq!(results[0].id, 1000 + i as u128);
+                }
+            };
+        assert_inserted_found(&ivf, &mut context);
+
+        // Inserted points can be deleted before they are flushed
+        assert!(ivf.delete(1000).is_ok());
+        let results = ivf
+            .search(&inserted[0], 1, centroids.len() as u32, &mut context)
+            .expect("IVF search should return a result");
+        assert_ne!(results[0].id, 1000);
+
+        ivf.flush().expect("Inserted vectors should be flushed");
+        assert_eq!(ivf.vector_storage.num_vectors, 200);
+        assert_eq!(ivf.index_storage.header().num_vectors, 200);
+        assert!(ivf.is_deleted(100));
+        // The new files replace the old ones
+        assert_eq!(
+            ivf_files_directory(&base_dir).unwrap(),
+            format!("{}/version_0", base_dir)
+        );
+        assert!(!Path::new(&vectors_path).exists());
+        assert!(!Path::new(&index_path).exists());
+
+        let mut reopened_ivf = new_ivf();
+        assert_eq!(reopened_ivf.num_vectors(), 200);
+        assert_eq!(
+            reopened_ivf.documents().unwrap()[150],
+            (1050, inserted[50].clone())
+        );
+        // The stored points are unchanged
+        let results = reopened_ivf
+            .search(&dataset[7], 1, centroids.len() as u32, &mut context)
+            .expect("IVF search should return a result");
+        assert_eq!(results[0].id, 7);
+        ivf.set_deleted_points(BitVec::from_elem(200, false));
+        reopened_ivf.set_deleted_points(BitVec::from_elem(200, false));
+        assert_inserted_found(&ivf, &mut context);
+        assert_inserted_found(&reopened_ivf, &mut context);
+
+        // A second flush publishes a new version and removes the previous one
+        ivf.insert(3000, &dataset[0])
+            .expect("Vector should be inserted");
+        ivf.flush().expect("Inserted vector should be flushed");
+        assert_eq!(
+            ivf_files_directory(&base_dir).unwrap(),
+            format!("{}/version_1", base_dir)
+        );
+        assert!(!Path::new(&format!("{}/version_0", base_dir)).exists());
+        assert_eq!(new_ivf().num_vectors(), 201);
+
+        // Points inserted after the first delete can be deleted too
+        ivf.insert(4000, &dataset[1])
+            .expect("Vector should be inserted");
+        assert!(ivf.delete(4000).is_ok());
+        assert!(ivf.is_deleted(201));
+        assert!(ivf.delete(3000).is_ok());
+        assert!(ivf.is_deleted(200));
+        assert!(ivf.delete(5000).is_err());
+    }
+
+    #[test]
+    fn test_ivf_search_threshold() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_threshold_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir.path().to_str().unwrap().to_string();
+
+        let file_path = format!("{}/vectors", base_dir);
+        let dataset: Vec<Vec<f32>> = vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![7.0, 8.0, 9.0],
+            vec![2.0, 3.0, 4.0],
+        ];
+        assert!(create_fixed_file_vector_storage(&file_path, &dataset).is_ok());
+        let num_features = 3;
+        let storage = FixedFileVectorStorage::<f32>::new(file_path, num_features).unwrap();
+
+        let file_path = format!("{}/index", base_dir);
+        let centroids = vec![vec![1.5, 2.5, 3.5], vec![5.5, 6.5, 7.5]];
+        let posting_lists = vec![vec![0, 3], vec![1, 2]];
+        assert!(create_fixed_file_index_storage(
+            &file_path,
+            &vec![100, 101, 102, 103],
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let index_storage = FixedIndexFile::new(file_path).unwrap();
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, 2, quantizer);
+
+        let query = vec![2.0, 3.0, 4.0];
+        let mut context = SearchContext::new(false);
+        let all_results = ivf.search(&query, 4, 2, &mut context).unwrap();
+        assert_eq!(all_results.len(), 4);
+
+        // Results at exactly the threshold are kept
+        let max_distance = all_results[1].score;
+        let results = ivf
+            .search_threshold(&query, 4, 2, max_distance, &mut context)
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![103, 100]
+        );
+
+        // k still applies below the threshold
+        let results = ivf
+            .search_threshold(&query, 1, 2, f32::MAX, &mut context)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, 103);
+
+        let results = ivf
+            .search_threshold(&query, 4, 2, -1.0, &mut context)
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_ivf_search_radius() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_radius_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir.path().to_str().unwrap().to_string();
+
+        let num_features = 4;
+        let mut rng = rand::thread_rng();
+        let dataset: Vec<Vec<f32>> = (0..200)
+            .map(|_| (0..num_features).map(|_| rng.gen::<f32>()).collect())
+            .collect();
+        let file_path = format!("{}/vectors", base_dir);
+        assert!(create_fixed_file_vector_storage(&file_path, &dataset).is_ok());
+        let storage = FixedFileVectorStorage::<f32>::new(file_path, num_features).unwrap();
+
+        // Split the points by their first coordinate
+        let centroids = vec![vec![0.25; num_features], vec![0.75; num_features]];
+        let mut posting_lists = vec![vec![], vec![]];
+        for (i, point) in dataset.iter().enumerate() {
+            posting_lists[(point[0] >= 0.5) as usize].push(i as u64);
+        }
+        let doc_ids: Vec<u128> = (0..dataset.len() as u128).map(|i| i + 1000).collect();
+        let file_path = format!("{}/index", base_dir);
+        assert!(
+            create_fixed_file_index_storage(&file_path, &doc_ids, &centroids, &posting_lists)
+                .is_ok()
+        );
+        let index_storage = FixedIndexFile::new(file_path).unwrap();
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, 2, quantizer);
+
+        let query = vec![0.5; num_features];
+        let radius = 0.1;
+        let mut expected: Vec<(u128, f32)> = dataset
+            .iter()
+            .enumerate()
+            .map(|(i, point)| (doc_ids[i], L2DistanceCalculator::calculate(&query, point)))
+            .filter(|(_, distance)| *distance <= radius)
+            .collect();
+        expected.sort_by(|x, y| x.1.total_cmp(&y.1));
+
+        // Probing every cluster makes the radius search exact
+        let mut context = SearchContext::new(false);
+        let results = ivf
+            .search_radius(&query, radius, usize::MAX, 2, &mut context)
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|x| x.id).collect::<Vec<_>>(),
+            expected.iter().map(|x| x.0).collect::<Vec<_>>()
+        );
+
+        let max_results = expected.len() / 2;
+        let results = ivf
+            .search_radius(&query, radius, max_results, 2, &mut context)
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|x| x.id).collect::<Vec<_>>(),
+            expected[..max_results]
+                .iter()
+                .map(|x| x.0)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Writes `dataset` as an IVF whose clusters are slabs along the first coordinate, which
+    /// needs to be in [0, 1), and as a flat index for the ground truth.
+    fn slab_ivf_and_flat_index(
+        base_dir: &str,
+        dataset: &Vec<Vec<f32>>,
+        num_clusters: usize,
+    ) -> (
+        Ivf<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, PlainDecoder>,
+        FlatIndex<L2DistanceCalculator>,
+    ) {
+        let num_features = dataset[0].len();
+        let file_path = format!("{}/vectors", base_dir);
+        assert!(create_fixed_file_vector_storage(&file_path, dataset).is_ok());
+        let storage = FixedFileVectorStorage::<f32>::new(file_path.clone(), num_features).unwrap();
+        let flat_storage = FixedFileVectorStorage::<f32>::new(file_path, num_features).unwrap();
+
+        // Split the points in slabs along their first coordinate
+        let centroids: Vec<Vec<f32>> = (0..num_clusters)
+            .map(|i| {
+                let mut centroid = vec![0.5; num_features];
+                centroid[0] = (i as f32 + 0.5) / num_clusters as f32;
+                centroid
+            })
+            .collect();
+        let mut posting_lists = vec![vec![]; num_clusters];
+        for (i, point) in dataset.iter().enumerate() {
+            let slab = ((point[0] * num_clusters as f32) as usize).min(num_clusters - 1);
+            posting_lists[slab].push(i as u64);
+        }
+        let doc_ids: Vec<u128> = (0..dataset.len() as u128).collect();
+        let file_path = format!("{}/index", base_dir);
+        assert!(
+            create_fixed_file_index_storage(&file_path, &doc_ids, &centroids, &posting_lists)
+                .is_ok()
+        );
+        let index_storage = FixedIndexFile::new(file_path).unwrap();
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        (
+            Ivf::new(storage, index_storage, num_clusters, quantizer),
+            FlatIndex::<L2DistanceCalculator>::new(flat_storage, doc_ids).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_ivf_ef_search_recall() {
+        let temp_dir = tempdir::TempDir::new("ivf_ef_search_recall_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir.path().to_str().unwrap().to_string();
+
+        let num_features = 4;
+        let num_clusters = 8;
+        let mut rng = rand::thread_rng();
+        let dataset: Vec<Vec<f32>> = (0..400)
+            .map(|_| (0..num_features).map(|_| rng.gen::<f32>()).collect())
+            .collect();
+        let (ivf, flat) = slab_ivf_and_flat_index(&base_dir, &dataset, num_clusters);
+
+        let k = 10;
+        let queries: Vec<Vec<f32>> = (0..50)
+            .map(|_| (0..num_features).map(|_| rng.gen::<f32>()).collect())
+            .collect();
+        // Mean and variance of the recall of each query
+        let recall_stats = |ef_search: u32| {
+            let mut context = SearchContext::new(false);
+            let recalls: Vec<f64> = queries
+                .iter()
+                .map(|query| {
+                    let ground_truth: Vec<u128> = flat
+                        .exact_search(query, k, None, &mut context)
+                        .iter()
+                        .map(|x| x.id)
+                        .collect();
+                    let results: Vec<u128> = ivf
+                        .search(query, k, ef_search, &mut context)
+                        .unwrap()
+                        .iter()
+                        .map(|x| x.id)
+                        .collect();
+                    recall_at_k(&ground_truth, &results, k)
+                })
+                .collect();
+            let mean = recalls.iter().sum::<f64>() / recalls.len() as f64;
+            let variance =
+                recalls.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / recalls.len() as f64;
+            (mean, variance)
+        };
+
+        // Probing a single cluster misses the neighbors across slab boundaries
+        let (low_mean, low_variance) = recall_stats(1);
+        assert!(low_mean < 1.0);
+        assert!(low_variance > 0.0);
+
+        // Probing every cluster is a brute-force search
+        let (high_mean, high_variance) = recall_stats(num_clusters as u32);
+        assert_eq!(high_mean, 1.0);
+        assert_eq!(high_variance, 0.0);
+    }
+
+    #[test]
+    fn test_ivf_recall_against_flat_index() {
+        let temp_dir = tempdir::TempDir::new("ivf_recall_against_flat_index_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir.path().to_str().unwrap().to_string();
+
+        let num_features = 4;
+        let num_clusters = 16;
+        let dataset: Vec<Vec<f32>> = (0..10000)
+            .map(|_| generate_random_vector(num_features))
+            .collect();
+        let (ivf, flat) = slab_ivf_and_flat_index(&base_dir, &dataset, num_clusters);
+
+        let k = 10;
+        let queries: Vec<Vec<f32>> = (0..100)
+            .map(|_| generate_random_vector(num_features))
+            .collect();
+        let mut context = SearchContext::new(false);
+        let exact = generate_ground_truth(&flat, &queries, k, &mut context);
+        let approximate: Vec<Vec<IdWithScore>> = queries
+            .iter()
+            .map(|query| ivf.search(query, k, 5, &mut context).unwrap().0)
+            .collect();
+        assert!(compute_recall_at_k(&exact, &approximate, k) > 0.7);
+    }
+
+    #[test]
+    fn test_ivf_search_with_cache() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_with_cache_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let file_path = format!("{}/vectors", base_dir);
+        let dataset: Vec<Vec<f32>> = vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![7.0, 8.0, 9.0],
+            vec![2.0, 3.0, 4.0],
+        ];
+        assert!(create_fixed_file_vector_storage(&file_path, &dataset).is_ok());
+        let num_features = 3;
+        let storage = FixedFileVectorStorage::<f32>::new(file_path, num_features)
+            .expect("FixedFileVectorStorage should be created");
+
+        let file_path = format!("{}/index", base_dir);
+        let doc_id_mapping = vec![100, 101, 102, 103];
+        let centroids = vec![vec![1.5, 2.5, 3.5], vec![5.5, 6.5, 7.5]];
+        let posting_lists = vec![vec![0, 3], vec![1, 2]];
+        assert!(create_fixed_file_index_storage(
+            &file_path,
+            &doc_id_mapping,
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let index_storage =
+            FixedIndexFile::new(file_path).expect("FixedIndexFile should be created");
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, 2, quantizer);
+
+        let query = vec![2.0, 3.0, 4.0];
+        let mut context = SearchContext::new_with_cache_size(1024);
+        for _ in 0..3 {
+            let results = ivf
+                .search(&query, 2, 1, &mut context)
+                .expect("IVF search should return a result");
+            assert_eq!(results[0].id, 103);
+            assert_eq!(results[1].id, 100);
+        }
+
+        // Only the first search has to decode the probed posting list
+        assert_eq!(context.cache_misses(), 1);
+        assert_eq!(context.cache_hits(), 2);
+    }
+
+    #[test]
+    fn test_ivf_search_with_vector_cache() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_with_vector_cache_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let file_path = format!("{}/vectors", base_dir);
+        let dataset: Vec<Vec<f32>> = vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![7.0, 8.0, 9.0],
+            vec![2.0, 3.0, 4.0],
+        ];
+        assert!(create_fixed_file_vector_storage(&file_path, &dataset).is_ok());
+        let num_features = 3;
+        let storage = FixedFileVectorStorage::<f32>::new(file_path, num_features)
+            .expect("FixedFileVectorStorage should be created");
+
+        // Vector 3 belongs to both clusters
+        let file_path = format!("{}/index", base_dir);
+        let doc_id_mapping = vec![100, 101, 102, 103];
+        let centroids = vec![vec![1.5, 2.5, 3.5], vec![5.5, 6.5, 7.5]];
+        let posting_lists = vec![vec![0, 3], vec![1, 2, 3]];
+        assert!(create_fixed_file_index_storage(
+            &file_path,
+            &doc_id_mapping,
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let index_storage =
+            FixedIndexFile::new(file_path).expect("FixedIndexFile should be created");
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, 2, quantizer);
+
+        let query = vec![2.0, 3.0, 4.0];
+        let mut context = SearchContext::with_cache(CachePolicy::LRU(16));
+        let results = ivf
+            .search(&query, 1, 2, &mut context)
+            .expect("IVF search should return a result");
+        assert_eq!(results[0].id, 103);
+        // The second probed cluster reuses vector 3
+        assert_eq!(context.cache_miss_count(), 4);
+        assert_eq!(context.cache_hit_count(), 1);
+        // Vector 3 is scored once per probed cluster
+        assert_eq!(context.stats().num_candidates_scanned, 5);
+
+        let results = ivf
+            .search(&query, 1, 2, &mut context)
+            .expect("IVF search should return a result");
+        assert_eq!(results[0].id, 103);
+        assert_eq!(context.cache_miss_count(), 4);
+        assert_eq!(context.cache_hit_count(), 6);
+        assert_eq!(context.stats().num_candidates_scanned, 10);
+
+        // Without a cache, nothing is counted
+        let mut context = SearchContext::new(false);
+        ivf.search(&query, 1, 2, &mut context).unwrap();
+        assert_eq!(context.cache_hit_count() + context.cache_miss_count(), 0);
+    }
+
+    #[test]
+    fn test_ivf_search_timeout() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_timeout_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        // 100 posting lists of 1000 vectors
+        let num_vectors = 100_000;
+        let num_clusters = 100;
+        let num_features = 4;
+        let file_path = format!("{}/vectors", base_dir);
+        let dataset: Vec<Vec<f32>> = (0..num_vectors)
+            .map(|i| vec![(i % num_clusters) as f32; num_features])
+            .collect();
+        assert!(create_fixed_file_vector_storage(&file_path, &dataset).is_ok());
+        let storage = FixedFileVectorStorage::<f32>::new(file_path, num_features)
+            .expect("FixedFileVectorStorage should be created");
+
+        let file_path = format!("{}/index", base_dir);
+        let doc_id_mapping: Vec<u128> = (0..num_vectors as u128).collect();
+        let centroids: Vec<Vec<f32>> = (0..num_clusters)
+            .map(|i| vec![i as f32; num_features])
+            .collect();
+        let posting_lists: Vec<Vec<u64>> = (0..num_clusters as u64)
+            .map(|i| (i..num_vectors as u64).step_by(num_clusters).collect())
+            .collect();
+        assert!(create_fixed_file_index_storage(
+            &file_path,
+            &doc_id_mapping,
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let index_storage =
+            FixedIndexFile::new(file_path).expect("FixedIndexFile should be created");
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, num_clusters, quantizer);
+        let query = vec![0.0; num_features];
+
+        let mut context = SearchContext::new(false);
+        context.set_timeout(Duration::from_nanos(1));
+        let start = Instant::now();
+        let results = ivf
+            .search(&query, 10, num_clusters as u32, &mut context)
+            .expect("IVF search should return a result");
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(context.timed_out);
+        // The deadline passed before the first posting list was scanned
+        assert!(results.is_empty());
+        assert_eq!(context.stats().num_candidates_scanned, 0);
+
+        let mut context = SearchContext::new(false);
+        context.set_timeout(Duration::from_secs(3600));
+        let results = ivf
+            .search(&query, 10, num_clusters as u32, &mut context)
+            .expect("IVF search should return a result");
+        assert!(!context.timed_out);
+        assert_eq!(results.len(), 10);
+        assert_eq!(context.stats().num_candidates_scanned, num_vectors as u64);
+    }
+
+    #[test]
+    fn test_ivf_search_with_lru_vector_cache() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_with_lru_vector_cache_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let file_path = format!("{}/vectors", base_dir);
+        let dataset: Vec<Vec<f32>> = vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![7.0, 8.0, 9.0],
+            vec![2.0, 3.0, 4.0],
+        ];
+        assert!(create_fixed_file_vector_storage(&file_path, &dataset).is_ok());
+        let num_features = 3;
+        let storage = FixedFileVectorStorage::<f32>::new(file_path, num_features)
+            .expect("FixedFileVectorStorage should be created");
+
+        let file_path = format!("{}/index", base_dir);
+        let doc_id_mapping = vec![100, 101, 102, 103];
+        let centroids = vec![vec![1.5, 2.5, 3.5], vec![5.5, 6.5, 7.5]];
+        let posting_lists = vec![vec![0, 3], vec![1, 2]];
+        assert!(create_fixed_file_index_storage(
+            &file_path,
+            &doc_id_mapping,
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let index_storage =
+            FixedIndexFile::new(file_path).expect("FixedIndexFile should be created");
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let mut ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, 2, quantizer);
+        let query = vec![2.0, 3.0, 4.0];
+        let ids_and_scores = |results: Vec<IdWithScore>| {
+            results
+                .into_iter()
+                .map(|result| (result.id, result.score))
+                .collect::<Vec<_>>()
+        };
+        let expected = ids_and_scores(
+            ivf.search(&query, 2, 2, &mut SearchContext::new(false))
+                .expect("IVF search should return a result"),
+        );
+        assert_eq!(
+            expected.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![103, 100]
+        );
+        assert!(ivf.vector_cache.is_none());
+
+        ivf.set_vector_cache_capacity(Some(3));
+        for _ in 0..2 {
+            let results = ivf
+                .search(&query, 2, 2, &mut SearchContext::new(false))
+                .expect("IVF search should return a result");
+            assert_eq!(ids_and_scores(results), expected);
+        }
+        // Only the last 3 of the 4 scanned vectors are kept, across searches
+        assert_eq!(ivf.vector_cache.as_ref().unwrap().lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_ivf_search_recall_sampling() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_recall_sampling_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let file_path = format!("{}/vectors", base_dir);
+        let dataset: Vec<Vec<f32>> = vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![7.0, 8.0, 9.0],
+            vec![2.0, 3.0, 4.0],
+        ];
+        assert!(create_fixed_file_vector_storage(&file_path, &dataset).is_ok());
+        let num_features = 3;
+        let storage = FixedFileVectorStorage::<f32>::new(file_path, num_features)
+            .expect("FixedFileVectorStorage should be created");
+
+        let file_path = format!("{}/index", base_dir);
+        let doc_id_mapping = vec![100, 101, 102, 103];
+        let centroids = vec![vec![1.5, 2.5, 3.5], vec![5.5, 6.5, 7.5]];
+        let posting_lists = vec![vec![0, 3], vec![1, 2]];
+        assert!(create_fixed_file_index_storage(
+            &file_path,
+            &doc_id_mapping,
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let index_storage =
+            FixedIndexFile::new(file_path).expect("FixedIndexFile should be created");
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, 2, quantizer);
+
+        // Exact results for the query below are 103 then 100
+        let query = vec![2.0, 3.0, 4.0];
+        let mut context = SearchContext::new(false);
+        assert_eq!(context.mean_recall(), None);
+        context.enable_recall_sampling(1.0, Arc::new(|_, _| vec![103, 100]));
+
+        // Probing both clusters finds the exact results
+        ivf.search(&query, 2, 2, &mut context).unwrap();
+        assert_eq!(context.mean_recall(), Some(1.0));
+
+        // Enabling again starts a new mean. Probing one cluster finds the same results, but the
+        // ground truth now differs on one id
+        context.enable_recall_sampling(1.0, Arc::new(|_, _| vec![103, 101]));
+        ivf.search(&query, 2, 1, &mut context).unwrap();
+        // {103, 100} vs {103, 101}: 1 common id out of 3
+        assert_eq!(context.mean_recall(), Some(1.0 / 3.0));
+
+        // Clones share the running mean
+        let sampler = context.recall_sampler.clone().unwrap();
+        let mut other_context = SearchContext::new(false);
+        other_context.recall_sampler = Some(sampler.clone());
+        ivf.search(&query, 2, 2, &mut other_context).unwrap();
+        assert_eq!(sampler.num_samples(), 2);
+        assert_eq!(context.mean_recall(), Some(1.0 / 3.0));
+
+        // Nothing is sampled at a rate of 0
+        context.enable_recall_sampling(0.0, Arc::new(|_, _| vec![]));
+        ivf.search(&query, 2, 2, &mut context).unwrap();
+        assert_eq!(context.mean_recall(), None);
+    }
+
+    #[test]
+    fn test_ivf_search_with_pq() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_with_pq_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let file_path = format!("{}/vectors", base_dir);
+        let dataset = vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![7.0, 8.0, 9.0],
+            vec![2.0, 3.0, 4.0],
+        ];
+        let num_features = 3;
+        let subvector_dimension = 1;
+
+        let codebook = vec![1.5, 5.5, 2.5, 6.5, 3.5, 7.5];
+        let quantizer = ProductQuantizer::<L2DistanceCalculator>::new(
+            num_features,
+            1,
+            subvector_dimension,
+            codebook,
+            base_dir.clone(),
+        )
+        .expect("Can't create product quantizer");
+        let quantized_dataset: Vec<Vec<u8>> = dataset
+            .iter()
+            .map(|x| f32::process_vector(x, &quantizer))
+            .collect();
+
+        assert!(create_fixed_file_vector_storage(&file_path, &quantized_dataset).is_ok());
+        let storage = FixedFileVectorStorage::<u8>::new(
+            file_path,
+            num_features / subvector_dimension as usize,
+        )
+        .expect("FixedFileVectorStorage should be created");
+
+        let file_path = format!("{}/index", base_dir);
+        let doc_id_mapping = vec![100, 101, 102, 103];
+        let centroids = vec![vec![1.5, 2.5, 3.5], vec![5.5, 6.5, 7.5]];
+        let posting_lists = vec![vec![0, 3], vec![1, 2]];
+        assert!(create_fixed_file_index_storage(
+            &file_path,
+            &doc_id_mapping,
+            &centroids,
+            &posting_lists
+        )
+        .is_ok());
+        let index_storage =
+            FixedIndexFile::new(file_path).expect("FixedIndexFile should be created");
+
+        let num_clusters = 2;
+        let num_probes = 2;
+
+        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, num_clusters, quantizer);
+
+        let query = vec![2.0, 3.0, 4.0];
+        let k = 2;
+        let mut context = SearchContext::new(false);
+
+        let results = ivf
+            .search(&query, k, num_probes, &mut context)
+            .expect("IVF search should return a result");
+
+        assert_eq!(results.len(), k);
         // This demonstrates the accuracy loss due to quantization
         assert!(results[0].score == results[1].score);
         assert_eq!(results[0].id + results[1].id, 203);
         assert_eq!(results[0].id.abs_diff(results[1].id), 3);
+
+        // Repeating the query reuses the ADC table of the first search
+        let mut context = SearchContext::new_with_pq_table_cache();
+        let first_results = ivf
+            .search(&query, k, num_probes, &mut context)
+            .expect("IVF search should return a result");
+        let second_results = ivf
+            .search(&query, k, num_probes, &mut context)
+            .expect("IVF search should return a result");
+        assert_eq!(first_results.into_scores(), second_results.into_scores());
+        let cache = context.pq_table_cache.as_ref().unwrap();
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.misses(), 1);
     }
 
     #[test]
@@ -593,4 +2192,125 @@ mod tests {
         assert_eq!(results.len(), 1); // Only one result available
         assert_eq!(results[0].id, 100);
     }
+
+    // Thresholds each dimension at 0.5, and compares the packed vectors by Hamming distance
+    struct BitQuantizer {
+        num_bits: usize,
+    }
+
+    impl Quantizer for BitQuantizer {
+        type QuantizedT = u64;
+
+        fn quantize(&self, value: &[f32]) -> Vec<u64> {
+            pack_bits(&value.iter().map(|&x| x > 0.5).collect::<Vec<_>>())
+        }
+
+        fn quantized_dimension(&self) -> usize {
+            num_words(self.num_bits)
+        }
+
+        fn original_vector(&self, quantized_vector: &[u64]) -> Vec<f32> {
+            (0..self.num_bits)
+                .map(|i| ((quantized_vector[i / 64] >> (i % 64)) & 1) as f32)
+                .collect()
+        }
+
+        fn distance(
+            &self,
+            query: &[u64],
+            point: &[u64],
+            _implem: utils::distance::l2::L2DistanceCalculatorImpl,
+        ) -> f32 {
+            HammingDistanceCalculator::calculate(query, point) as f32
+        }
+
+        fn read(_dir: String) -> Result<Self> {
+            Err(anyhow!("BitQuantizer is only used in tests"))
+        }
+    }
+
+    #[test]
+    fn test_ivf_search_binary_vectors() {
+        let temp_dir = tempdir::TempDir::new("ivf_search_binary_vectors_test")
+            .expect("Failed to create temporary directory");
+        let base_dir = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let num_bits = 64;
+        let num_vectors = 1000;
+        let mut rng = rand::thread_rng();
+        let dataset: Vec<Vec<bool>> = (0..num_vectors)
+            .map(|_| (0..num_bits).map(|_| rng.gen()).collect())
+            .collect();
+        let packed: Vec<Vec<u64>> = dataset.iter().map(|bits| pack_bits(bits)).collect();
+
+        let file_path = format!("{}/vectors", base_dir);
+        let mut file = File::create(&file_path).unwrap();
+        let mut writer = std::io::BufWriter::new(&mut file);
+        BitPackedVectorStorage::write_vectors(&mut writer, num_bits, &packed).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let storage = BitPackedVectorStorage::new(file_path, num_bits)
+            .unwrap()
+            .into_inner();
+
+        // Centroids are the first vectors as 0/1 floats, for which the squared L2 distance is
+        // the Hamming distance. Each vector goes to its nearest centroid.
+        let num_clusters = 8;
+        let centroids: Vec<Vec<f32>> = dataset[..num_clusters]
+            .iter()
+            .map(|bits| bits.iter().map(|&bit| bit as u8 as f32).collect())
+            .collect();
+        let mut posting_lists: Vec<Vec<u64>> = vec![vec![]; num_clusters];
+        for (point_id, vector) in packed.iter().enumerate() {
+            let nearest = (0..num_clusters)
+                .min_by_key(|&c| HammingDistanceCalculator::calculate(vector, &packed[c]))
+                .unwrap();
+            posting_lists[nearest].push(point_id as u64);
+        }
+        let doc_id_mapping: Vec<u128> = (0..num_vectors as u128).map(|id| id + 1000).collect();
+        let file_path = format!("{}/index", base_dir);
+        create_fixed_file_index_storage(&file_path, &doc_id_mapping, &centroids, &posting_lists)
+            .unwrap();
+        let index_storage = FixedIndexFile::new(file_path).unwrap();
+
+        let quantizer = BitQuantizer { num_bits };
+        let ivf: Ivf<_, L2DistanceCalculator, PlainDecoder> =
+            Ivf::new(storage, index_storage, num_clusters, quantizer);
+
+        for _ in 0..10 {
+            let query: Vec<f32> = (0..num_bits)
+                .map(|_| rng.gen::<bool>() as u8 as f32)
+                .collect();
+            let packed_query = ivf.quantizer.quantize(&query);
+            let mut expected: Vec<u32> = packed
+                .iter()
+                .map(|vector| HammingDistanceCalculator::calculate(&packed_query, vector))
+                .collect();
+            expected.sort();
+
+            // Probing every cluster is exhaustive, so the distances match brute force
+            let k = 10;
+            let results = ivf
+                .search(
+                    &query,
+                    k,
+                    num_clusters as u32,
+                    &mut SearchContext::new(false),
+                )
+                .unwrap();
+            assert_eq!(results.len(), k);
+            for (result, expected_distance) in results.iter().zip(expected.iter()) {
+                assert_eq!(result.score, *expected_distance as f32);
+                let point_id = (result.id - 1000) as usize;
+                assert_eq!(
+                    HammingDistanceCalculator::calculate(&packed_query, &packed[point_id]),
+                    *expected_distance
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,261 @@
+use std::cmp::{max, min};
+use std::marker::PhantomData;
+
+use anyhow::{anyhow, Context, Result};
+use compression::compression::IntSeqEncoder;
+use log::debug;
+use quantization::quantization::Quantizer;
+use utils::kmeans_builder::kmeans_builder::KMeansBuilder;
+use utils::{CalculateSquared, DistanceCalculator};
+
+use crate::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+use crate::ivf::writer::IvfWriter;
+
+/// Merges two built IVF indexes, e.g. built over separate shards of a dataset, into a single
+/// index.
+///
+/// The centroids of both indexes are clustered again with a single K-means pass, and the vectors
+/// of both indexes are assigned to the resulting clusters. Builders hold unquantized vectors, so
+/// the merged index is quantized by the quantizer of the merger when it is written.
+pub struct IvfMerger<Q, E, D>
+where
+    Q: Quantizer,
+    E: IntSeqEncoder,
+    D: DistanceCalculator + CalculateSquared + Send + Sync,
+{
+    quantizer: Q,
+    // Number of clusters of the merged index, the largest of both indexes if None
+    num_clusters: Option<usize>,
+    _encoder_marker: PhantomData<E>,
+    _distance_calculator_marker: PhantomData<D>,
+}
+
+impl<Q, E, D> IvfMerger<Q, E, D>
+where
+    Q: Quantizer,
+    E: IntSeqEncoder + 'static,
+    D: DistanceCalculator + CalculateSquared + Send + Sync,
+{
+    pub fn new(quantizer: Q) -> Self {
+        Self {
+            quantizer,
+            num_clusters: None,
+            _encoder_marker: PhantomData,
+            _distance_calculator_marker: PhantomData,
+        }
+    }
+
+    /// Sets the number of clusters of the merged index. It is capped by the total number of
+    /// centroids of both indexes.
+    pub fn with_num_clusters(mut self, num_clusters: usize) -> Self {
+        self.num_clusters = Some(num_clusters);
+        self
+    }
+
+    /// Writes the merged index of `lhs` and `rhs` to `output_path`. Both builders must have been
+    /// built. The parameters of the merged builder, such as `max_clusters_per_vector`, are taken
+    /// from `lhs`.
+    pub fn merge(
+        self,
+        lhs: &mut IvfBuilder<D>,
+        rhs: &mut IvfBuilder<D>,
+        output_path: &str,
+    ) -> Result<()> {
+        let num_features = lhs.config().num_features;
+        if rhs.config().num_features != num_features {
+            return Err(anyhow!(
+                "Cannot merge indexes of dimension {} and {}",
+                num_features,
+                rhs.config().num_features
+            ));
+        }
+        lhs.flush()?;
+        rhs.flush()?;
+
+        let mut flattened_centroids = vec![];
+        for builder in [&*lhs, &*rhs] {
+            let centroids = builder.centroids().borrow();
+            if centroids.len() == 0 {
+                return Err(anyhow!("Indexes must be built before they are merged"));
+            }
+            for i in 0..centroids.len() {
+                flattened_centroids.extend_from_slice(centroids.get(i as u32)?);
+            }
+        }
+        let num_centroids = flattened_centroids.len() / num_features;
+        let num_clusters = min(
+            self.num_clusters
+                .unwrap_or(max(lhs.config().num_clusters, rhs.config().num_clusters)),
+            num_centroids,
+        );
+        debug!(
+            "Clustering {} centroids into {} clusters",
+            num_centroids, num_clusters
+        );
+        let kmeans = KMeansBuilder::<D>::new(
+            num_clusters,
+            lhs.config().max_iteration,
+            lhs.config().tolerance,
+            num_features,
+            lhs.config().kmeans_variant,
+        );
+        let result = kmeans.fit(flattened_centroids)?;
+
+        let builder_directory = format!("{}/merger_builder", output_path);
+        let config = lhs.config();
+        let mut merged = IvfBuilder::<D>::new(IvfBuilderConfig {
+            max_iteration: config.max_iteration,
+            batch_size: config.batch_size,
+            num_clusters,
+            num_data_points_for_clustering: config.num_data_points_for_clustering,
+            max_clusters_per_vector: config.max_clusters_per_vector,
+            distance_threshold: config.distance_threshold,
+            base_directory: builder_directory.clone(),
+            memory_size: config.memory_size,
+            file_size: config.file_size,
+            num_features,
+            tolerance: config.tolerance,
+            kmeans_variant: config.kmeans_variant,
+            max_posting_list_size: config.max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })?;
+        // Vectors were validated when they were added to `lhs` and `rhs`
+        merged.set_skip_vector_validation(true);
+
+        for centroid in result.centroids.chunks_exact(num_features) {
+            merged.add_centroid(centroid)?;
+        }
+        for builder in [&*lhs, &*rhs] {
+            let vectors = builder.vectors().borrow();
+            for (i, doc_id) in builder.doc_id_mapping().iter().enumerate() {
+                merged.add_vector(*doc_id, vectors.get(i as u32)?)?;
+            }
+        }
+        merged.build_posting_lists()?;
+        debug!(
+            "Merged {} vectors into {} clusters",
+            merged.doc_id_mapping().len(),
+            num_clusters
+        );
+
+        IvfWriter::<Q, E, D>::new(output_path.to_string(), self.quantizer)
+            .write(&mut merged, false)
+            .context("Failed to write the merged index")?;
+        merged.cleanup()?;
+        std::fs::remove_dir_all(&builder_directory)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use compression::elias_fano::ef::{EliasFano, EliasFanoDecoder};
+    use quantization::noq::noq::NoQuantizer;
+    use quantization::quantization::WritableQuantizer;
+    use tempdir::TempDir;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::kmeans_builder::kmeans_builder::KMeansVariant;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::index::Searchable;
+    use crate::ivf::reader::IvfReader;
+    use crate::utils::SearchContext;
+
+    fn builder_config(base_directory: String, num_clusters: usize) -> IvfBuilderConfig {
+        IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters,
+            num_data_points_for_clustering: 1000,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory,
+            memory_size: 1024,
+            file_size: 4096,
+            num_features: 4,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        }
+    }
+
+    #[test]
+    fn test_ivf_merger() {
+        let temp_dir = TempDir::new("test_ivf_merger").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let num_features = 4;
+        let vectors = (0..2000)
+            .map(|_| generate_random_vector(num_features))
+            .collect::<Vec<_>>();
+
+        // Build one index over each half of the dataset
+        let build_shard = |shard: usize, num_clusters: usize| {
+            let mut builder = IvfBuilder::<L2DistanceCalculator>::new(builder_config(
+                format!("{}/shard_{}", base_directory, shard),
+                num_clusters,
+            ))
+            .unwrap();
+            for doc_id in (shard * 1000)..(shard + 1) * 1000 {
+                builder
+                    .add_vector(doc_id as u128, &vectors[doc_id])
+                    .unwrap();
+            }
+            builder.build().unwrap();
+            builder
+        };
+        let mut lhs = build_shard(0, 8);
+        let mut rhs = build_shard(1, 10);
+
+        let output_path = format!("{}/merged", base_directory);
+        std::fs::create_dir_all(&output_path).unwrap();
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let quantizer_directory = format!("{}/quantizer", output_path);
+        std::fs::create_dir_all(&quantizer_directory).unwrap();
+        quantizer.write_to_directory(&quantizer_directory).unwrap();
+        IvfMerger::<_, EliasFano, L2DistanceCalculator>::new(quantizer)
+            .merge(&mut lhs, &mut rhs, &output_path)
+            .unwrap();
+        assert!(!std::path::Path::new(&format!("{}/merger_builder", output_path)).exists());
+
+        let index = IvfReader::new(output_path)
+            .read::<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, EliasFanoDecoder>()
+            .unwrap();
+        assert_eq!(index.num_vectors(), 2000);
+        // The number of clusters defaults to the largest of both indexes
+        assert_eq!(index.num_clusters, 10);
+
+        // Every vector is its own nearest neighbor when all clusters are probed
+        let mut context = SearchContext::new(false);
+        for (doc_id, vector) in vectors.iter().enumerate() {
+            let results = index.search(vector, 1, 10, &mut context).unwrap();
+            assert_eq!(results[0].id, doc_id as u128);
+        }
+    }
+
+    #[test]
+    fn test_ivf_merger_dimension_mismatch() {
+        let temp_dir = TempDir::new("test_ivf_merger_dimension_mismatch").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let mut lhs = IvfBuilder::<L2DistanceCalculator>::new(builder_config(
+            format!("{}/lhs", base_directory),
+            2,
+        ))
+        .unwrap();
+        let mut rhs = IvfBuilder::<L2DistanceCalculator>::new(IvfBuilderConfig {
+            num_features: 8,
+            ..builder_config(format!("{}/rhs", base_directory), 2)
+        })
+        .unwrap();
+
+        let merger = IvfMerger::<_, EliasFano, L2DistanceCalculator>::new(NoQuantizer::<
+            L2DistanceCalculator,
+        >::new(4));
+        assert!(merger
+            .merge(&mut lhs, &mut rhs, &format!("{}/merged", base_directory))
+            .is_err());
+    }
+}
@@ -1,4 +1,6 @@
 pub mod builder;
+mod checkpoint;
 pub mod index;
+pub mod merger;
 pub mod reader;
 pub mod writer;
@@ -1,24 +1,31 @@
+use std::path::Path;
+
 use anyhow::Result;
+use bit_vec::BitVec;
 use compression::compression::IntSeqDecoder;
 use quantization::quantization::Quantizer;
 use utils::DistanceCalculator;
 
-use crate::ivf::index::Ivf;
+use crate::ivf::index::{ivf_files_directory, Ivf, DELETED_POINTS_FILE_NAME};
 use crate::posting_list::combined_file::FixedIndexFile;
+use crate::vector::compressed::open_vector_storage;
 use crate::vector::fixed_file::FixedFileVectorStorage;
 
 pub struct IvfReader {
     base_directory: String,
     index_offset: usize,
     vector_offset: usize,
+    vector_cache_capacity: Option<usize>,
 }
 
 impl IvfReader {
     pub fn new(base_directory: String) -> Self {
-        Self::new_with_offset(base_directory, 0, 0)
+        Self::new_with_offsets(base_directory, 0, 0)
     }
 
-    pub fn new_with_offset(
+    /// Reads the index and vector files starting at the given byte offsets, e.g. when they are
+    /// stored inside a larger file. Same as `SpannReader::new_with_offsets`.
+    pub fn new_with_offsets(
         base_directory: String,
         index_offset: usize,
         vector_offset: usize,
@@ -27,20 +34,60 @@ impl IvfReader {
             base_directory,
             index_offset,
             vector_offset,
+            vector_cache_capacity: None,
         }
     }
 
+    /// Sets the capacity of the vector cache of the index, see `Ivf::set_vector_cache_capacity`.
+    /// Defaults to None, which disables the cache.
+    pub fn with_vector_cache_capacity(mut self, vector_cache_capacity: Option<usize>) -> Self {
+        self.vector_cache_capacity = vector_cache_capacity;
+        self
+    }
+
+    /// Same as `new_with_offsets`.
+    pub fn new_with_offset(
+        base_directory: String,
+        index_offset: usize,
+        vector_offset: usize,
+    ) -> Self {
+        Self::new_with_offsets(base_directory, index_offset, vector_offset)
+    }
+
+    /// Builds the index from the content of the index and vector files, without touching the
+    /// filesystem. The quantizer is not part of either file, so it has to be provided.
+    pub fn new_in_memory<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>>(
+        index_bytes: &[u8],
+        vector_bytes: &[u8],
+        quantizer: Q,
+    ) -> Result<Ivf<Q, DC, D>> {
+        let index_storage = FixedIndexFile::new_from_bytes("memory".to_string(), index_bytes, 0)?;
+        let vector_storage = FixedFileVectorStorage::<Q::QuantizedT>::new_from_bytes(
+            vector_bytes,
+            index_storage.header().quantized_dimension as usize,
+            0,
+        )?;
+        let num_clusters = index_storage.header().num_clusters as usize;
+        Ok(Ivf::<_, DC, D>::new(
+            vector_storage,
+            index_storage,
+            num_clusters,
+            quantizer,
+        ))
+    }
+
     pub fn read<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>>(
         &self,
     ) -> Result<Ivf<Q, DC, D>> {
+        let files_directory = ivf_files_directory(&self.base_directory)?;
         let index_storage = FixedIndexFile::new_with_offset(
-            format!("{}/index", self.base_directory),
+            format!("{}/index", files_directory),
             self.index_offset,
         )?;
 
-        let vector_storage_path = format!("{}/vectors", self.base_directory);
-        let vector_storage = FixedFileVectorStorage::<Q::QuantizedT>::new_with_offset(
-            vector_storage_path,
+        let vector_storage_path = format!("{}/vectors", files_directory);
+        let vector_storage = open_vector_storage::<Q::QuantizedT>(
+            &vector_storage_path,
             index_storage.header().quantized_dimension as usize,
             self.vector_offset,
         )?;
@@ -51,12 +98,17 @@ impl IvfReader {
         let quantizer_directory = format!("{}/quantizer", self.base_directory);
         let quantizer = Q::read(quantizer_directory).unwrap();
 
-        Ok(Ivf::<_, DC, D>::new(
-            vector_storage,
-            index_storage,
-            num_clusters,
-            quantizer,
-        ))
+        let mut ivf = Ivf::<_, DC, D>::new(vector_storage, index_storage, num_clusters, quantizer);
+        ivf.set_vector_cache_capacity(self.vector_cache_capacity);
+        // Flushing rewrites whole files, so it's not supported at an offset of a larger file
+        if self.index_offset == 0 && self.vector_offset == 0 {
+            ivf.set_base_directory(self.base_directory.clone());
+        }
+        let deleted_points_path = format!("{}/{}", self.base_directory, DELETED_POINTS_FILE_NAME);
+        if Path::new(&deleted_points_path).is_file() {
+            ivf.set_deleted_points(BitVec::from_bytes(&std::fs::read(deleted_points_path)?));
+        }
+        Ok(ivf)
     }
 }
 
@@ -64,12 +116,17 @@ impl IvfReader {
 mod tests {
     use std::fs;
 
+    use compression::adaptive::adaptive::{
+        AdaptiveDecoder, AdaptiveEncoder, ADAPTIVE_MIN_ELIAS_FANO_LEN,
+    };
     use compression::elias_fano::ef::{EliasFano, EliasFanoDecoder};
     use compression::noc::noc::{PlainDecoder, PlainEncoder};
+    use compression::roaring::roaring::{RoaringDecoder, RoaringEncoder};
     use quantization::noq::noq::NoQuantizer;
     use quantization::quantization::WritableQuantizer;
     use tempdir::TempDir;
     use utils::distance::l2::L2DistanceCalculator;
+    use utils::kmeans_builder::kmeans_builder::KMeansVariant;
     use utils::mem::transmute_u8_to_slice;
     use utils::test_utils::generate_random_vector;
 
@@ -113,7 +170,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
         // Generate 1000 vectors of f32, dimension 4
@@ -270,7 +330,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -347,7 +410,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
         // Generate 1000 vectors of f32, dimension 4
@@ -492,7 +558,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: 10,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
         // Generate 1000 vectors of f32, dimension 4
@@ -523,4 +592,250 @@ mod tests {
             assert!(posting_list.len() <= 30);
         }
     }
+
+    #[test]
+    fn test_ivf_reader_new_in_memory() {
+        let temp_dir = TempDir::new("test_ivf_reader_new_in_memory")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_vectors = 500;
+        let num_features = 4;
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let quantizer_directory = format!("{}/quantizer", base_directory);
+        std::fs::create_dir_all(&quantizer_directory)
+            .expect("Failed to create quantizer directory");
+        assert!(quantizer.write_to_directory(&quantizer_directory).is_ok());
+        let writer = IvfWriter::<_, PlainEncoder, L2DistanceCalculator>::new(
+            base_directory.clone(),
+            quantizer,
+        );
+
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters: 5,
+            num_data_points_for_clustering: num_vectors,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: base_directory.clone(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .expect("Failed to create builder");
+        for i in 0..num_vectors {
+            builder
+                .add_vector(i as u128, &generate_random_vector(num_features))
+                .expect("Vector should be added");
+        }
+        assert!(builder.build().is_ok());
+        assert!(writer.write(&mut builder, false).is_ok());
+
+        let on_disk = IvfReader::new(base_directory.clone())
+            .read::<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, PlainDecoder>()
+            .expect("Failed to read index file");
+
+        let index_bytes = fs::read(format!("{}/index", base_directory)).unwrap();
+        let vector_bytes = fs::read(format!("{}/vectors", base_directory)).unwrap();
+        let in_memory = IvfReader::new_in_memory::<_, L2DistanceCalculator, PlainDecoder>(
+            &index_bytes,
+            &vector_bytes,
+            NoQuantizer::<L2DistanceCalculator>::new(num_features),
+        )
+        .expect("Failed to build index from bytes");
+        assert_eq!(in_memory.num_clusters, on_disk.num_clusters);
+
+        let query = generate_random_vector(num_features);
+        let expected = on_disk
+            .search(&query, 10, 5, &mut SearchContext::new(false))
+            .expect("Search should return results")
+            .into_doc_ids();
+        let results = in_memory
+            .search(&query, 10, 5, &mut SearchContext::new(false))
+            .expect("Search should return results")
+            .into_doc_ids();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_ivf_reader_adaptive_encoding() {
+        let temp_dir = TempDir::new("test_ivf_reader_adaptive_encoding")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_clusters = 8;
+        let num_vectors = 600;
+        let num_features = 4;
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let quantizer_directory = format!("{}/quantizer", base_directory);
+        std::fs::create_dir_all(&quantizer_directory)
+            .expect("Failed to create quantizer directory");
+        assert!(quantizer.write_to_directory(&quantizer_directory).is_ok());
+        let writer = IvfWriter::<_, AdaptiveEncoder, L2DistanceCalculator>::new(
+            base_directory.clone(),
+            quantizer,
+        );
+
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters,
+            num_data_points_for_clustering: num_vectors,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: base_directory.clone(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .expect("Failed to create builder");
+        for i in 0..num_vectors {
+            builder
+                .add_vector(i as u128, &generate_random_vector(num_features))
+                .expect("Vector should be added");
+        }
+        assert!(builder.build().is_ok());
+        assert!(writer.write(&mut builder, false).is_ok());
+
+        let index = IvfReader::new(base_directory.clone())
+            .read::<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, AdaptiveDecoder>()
+            .expect("Failed to read index file");
+        for i in 0..num_clusters {
+            let ref_posting_list = builder
+                .posting_lists_mut()
+                .get(i as u32)
+                .expect("Failed to read posting list")
+                .iter()
+                .collect::<Vec<u64>>();
+            let byte_slice = index
+                .index_storage
+                .get_posting_list(i)
+                .expect("Failed to read posting list from FixedIndexFile");
+            // Each posting list starts with the tag of its encoding
+            let tag = transmute_u8_to_slice::<u64>(byte_slice)[0];
+            let expected_tag = if ref_posting_list.len() < ADAPTIVE_MIN_ELIAS_FANO_LEN {
+                0
+            } else {
+                1
+            };
+            assert_eq!(tag, expected_tag);
+
+            let decoder = AdaptiveDecoder::new_decoder(byte_slice)
+                .expect("Failed to create posting list decoder");
+            assert_eq!(
+                decoder.get_iterator(byte_slice).collect::<Vec<u64>>(),
+                ref_posting_list
+            );
+        }
+
+        let query = generate_random_vector(num_features);
+        let results = index
+            .search(
+                &query,
+                5,
+                num_clusters as u32,
+                &mut SearchContext::new(false),
+            )
+            .expect("Search should return results");
+        assert_eq!(results.0.len(), 5);
+    }
+
+    #[test]
+    fn test_ivf_reader_roaring_encoding() {
+        let temp_dir = TempDir::new("test_ivf_reader_roaring_encoding")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_clusters = 4;
+        let num_vectors = 200;
+        let num_features = 4;
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let quantizer_directory = format!("{}/quantizer", base_directory);
+        std::fs::create_dir_all(&quantizer_directory)
+            .expect("Failed to create quantizer directory");
+        assert!(quantizer.write_to_directory(&quantizer_directory).is_ok());
+        let writer = IvfWriter::<_, RoaringEncoder, L2DistanceCalculator>::new(
+            base_directory.clone(),
+            quantizer,
+        );
+
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters,
+            num_data_points_for_clustering: num_vectors,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: base_directory.clone(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .expect("Failed to create builder");
+        for i in 0..num_vectors {
+            builder
+                .add_vector(i as u128, &generate_random_vector(num_features))
+                .expect("Vector should be added");
+        }
+        assert!(builder.build().is_ok());
+        assert!(writer.write(&mut builder, false).is_ok());
+
+        let index = IvfReader::new(base_directory.clone())
+            .read::<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, RoaringDecoder>()
+            .expect("Failed to read index file");
+        for i in 0..num_clusters {
+            let ref_posting_list = builder
+                .posting_lists_mut()
+                .get(i as u32)
+                .expect("Failed to read posting list")
+                .iter()
+                .collect::<Vec<u64>>();
+            let byte_slice = index
+                .index_storage
+                .get_posting_list(i)
+                .expect("Failed to read posting list from FixedIndexFile");
+            let decoder = RoaringDecoder::new_decoder(byte_slice)
+                .expect("Failed to create posting list decoder");
+            assert_eq!(
+                decoder.get_iterator(byte_slice).collect::<Vec<u64>>(),
+                ref_posting_list
+            );
+        }
+
+        let query = generate_random_vector(num_features);
+        let results = index
+            .search(
+                &query,
+                5,
+                num_clusters as u32,
+                &mut SearchContext::new(false),
+            )
+            .expect("Search should return results");
+        assert_eq!(results.0.len(), 5);
+    }
 }
@@ -1,6 +1,6 @@
 use std::cmp::min;
 use std::fs::{create_dir_all, remove_dir_all, remove_file, File};
-use std::io::{BufWriter, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::marker::PhantomData;
 
 use anyhow::{anyhow, Context, Result};
@@ -13,7 +13,7 @@ use utils::io::{append_file_to_writer, wrap_write, write_pad};
 use utils::{CalculateSquared, DistanceCalculator};
 
 use crate::ivf::builder::IvfBuilder;
-use crate::posting_list::combined_file::{Header, Version};
+use crate::posting_list::combined_file::{FixedIndexFile, Header, SectionEntry, Version};
 
 pub struct IvfWriter<Q, E, D>
 where
@@ -23,6 +23,8 @@ where
 {
     base_directory: String,
     quantizer: Q,
+    // Format version of the index file
+    version: Version,
     _encoder_marker: PhantomData<E>,
     _distance_calculator_marker: PhantomData<D>,
 }
@@ -37,12 +39,20 @@ where
         Self {
             base_directory,
             quantizer,
+            version: Version::V0,
             _encoder_marker: PhantomData,
             _distance_calculator_marker: PhantomData,
         }
     }
 
+    /// Sets the format version of the index file. `Version::V1` adds a CRC32 per section, checked
+    /// by `FixedIndexFile::new_verified`.
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
     pub fn write(&self, ivf_builder: &mut IvfBuilder<D>, reindex: bool) -> Result<()> {
+        ivf_builder.flush()?;
         if reindex {
             // Reindex the vectors for efficient lookup
             ivf_builder
@@ -106,8 +116,8 @@ where
             .context("Failed to write posting lists and metadata")?;
         debug!("Finish writing posting_lists_and_metadata");
 
-        let header: Header = Header {
-            version: Version::V0,
+        let mut header: Header = Header {
+            version: self.version,
             num_features: num_features as u32,
             quantized_dimension: self.quantizer.quantized_dimension() as u32,
             num_clusters: num_clusters as u32,
@@ -115,7 +125,13 @@ where
             doc_id_mapping_len: doc_id_mapping_len as u64,
             centroids_len: centroids_len as u64,
             posting_lists_and_metadata_len: posting_lists_and_metadata_len as u64,
+            sections: vec![],
         };
+        if self.version == Version::V1 {
+            header.sections = self
+                .compute_sections(&header)
+                .context("Failed to compute section checksums")?;
+        }
 
         self.combine_files(&header)?;
         debug!("Finish combining files");
@@ -180,109 +196,163 @@ where
     }
 
     fn write_posting_lists_and_metadata(&self, ivf_builder: &mut IvfBuilder<D>) -> Result<usize> {
-        let metadata_path = format!("{}/posting_list_metadata", self.base_directory);
-        let mut metadata_file = File::create(metadata_path)?;
-        let mut metadata_writer = BufWriter::new(&mut metadata_file);
-
-        let posting_list_path = format!("{}/posting_lists", self.base_directory);
-        let mut posting_list_file = File::create(posting_list_path)?;
-        let mut posting_list_writer = BufWriter::new(&mut posting_list_file);
-
-        let mut metadata_bytes_written = 0;
-        let mut posting_list_bytes_written = 0;
-
-        let num_posting_lists = ivf_builder.posting_lists().len();
-        // First write the total number of posting lists
-        metadata_bytes_written +=
-            wrap_write(&mut metadata_writer, &num_posting_lists.to_le_bytes())?;
-        for i in 0..num_posting_lists {
-            let posting_list = ivf_builder.posting_lists().get(i as u32)?;
-            let mut encoder = E::new_encoder(
-                posting_list.last().unwrap_or(0) as usize,
-                posting_list.elem_count,
-            );
-            // Encode to get the length of the encoded data
-            for val in posting_list.iter() {
-                encoder.encode_value(&val)?;
-            }
-            // Write the length of the encoded posting list
-            metadata_bytes_written +=
-                wrap_write(&mut metadata_writer, &encoder.len().to_le_bytes())?;
-            // Write the offset to the current posting list
-            metadata_bytes_written += wrap_write(
-                &mut metadata_writer,
-                &((posting_list_bytes_written as u64).to_le_bytes()),
-            )?;
-            // Now write the posting list itself
-            posting_list_bytes_written += encoder.write(&mut posting_list_writer)?;
-        }
+        let posting_lists = ivf_builder.posting_lists();
+        write_posting_lists_and_metadata::<E>(&self.base_directory, posting_lists.len(), |i| {
+            Ok(posting_lists.get(i as u32)?.iter().collect())
+        })
+    }
 
-        let expected_bytes_written =
-            std::mem::size_of::<u64>() * 2 * num_posting_lists + std::mem::size_of::<u64>();
-        if metadata_bytes_written != expected_bytes_written {
-            return Err(anyhow!(
-                "Expected to write {} bytes of posting list metadata, but wrote {}",
-                expected_bytes_written,
-                metadata_bytes_written,
-            ));
+    fn compute_sections(&self, header: &Header) -> Result<Vec<SectionEntry>> {
+        compute_sections(&self.base_directory, header)
+    }
+
+    fn combine_files(&self, header: &Header) -> Result<usize> {
+        combine_files(&self.base_directory, header)
+    }
+}
+
+/// Encodes the posting lists returned by `posting_list` for ids `0..num_posting_lists` with `E`,
+/// and writes them to the posting list files of `base_directory`.
+pub(crate) fn write_posting_lists_and_metadata<E: IntSeqEncoder>(
+    base_directory: &str,
+    num_posting_lists: usize,
+    mut posting_list: impl FnMut(usize) -> Result<Vec<u64>>,
+) -> Result<usize> {
+    let metadata_path = format!("{}/posting_list_metadata", base_directory);
+    let mut metadata_file = File::create(metadata_path)?;
+    let mut metadata_writer = BufWriter::new(&mut metadata_file);
+
+    let posting_list_path = format!("{}/posting_lists", base_directory);
+    let mut posting_list_file = File::create(posting_list_path)?;
+    let mut posting_list_writer = BufWriter::new(&mut posting_list_file);
+
+    let mut metadata_bytes_written = 0;
+    let mut posting_list_bytes_written = 0;
+
+    // First write the total number of posting lists
+    metadata_bytes_written += wrap_write(&mut metadata_writer, &num_posting_lists.to_le_bytes())?;
+    for i in 0..num_posting_lists {
+        let posting_list = posting_list(i)?;
+        let mut encoder = E::new_encoder(
+            posting_list.last().copied().unwrap_or(0) as usize,
+            posting_list.len(),
+        );
+        // Encode to get the length of the encoded data
+        for val in posting_list.iter() {
+            encoder.encode_value(val)?;
         }
-        Ok(metadata_bytes_written + posting_list_bytes_written)
+        // Write the length of the encoded posting list
+        metadata_bytes_written += wrap_write(&mut metadata_writer, &encoder.len().to_le_bytes())?;
+        // Write the offset to the current posting list
+        metadata_bytes_written += wrap_write(
+            &mut metadata_writer,
+            &((posting_list_bytes_written as u64).to_le_bytes()),
+        )?;
+        // Now write the posting list itself
+        posting_list_bytes_written += encoder.write(&mut posting_list_writer)?;
     }
 
-    fn write_header(&self, header: &Header, writer: &mut BufWriter<&mut File>) -> Result<usize> {
-        let version_value: u8 = match header.version {
-            Version::V0 => 0,
-        };
-        let mut written = 0;
-        written += wrap_write(writer, &version_value.to_le_bytes())?;
-        written += wrap_write(writer, &header.num_features.to_le_bytes())?;
-        written += wrap_write(writer, &header.quantized_dimension.to_le_bytes())?;
-        written += wrap_write(writer, &header.num_clusters.to_le_bytes())?;
-        written += wrap_write(writer, &header.num_vectors.to_le_bytes())?;
-        written += wrap_write(writer, &header.doc_id_mapping_len.to_le_bytes())?;
-        written += wrap_write(writer, &header.centroids_len.to_le_bytes())?;
-        written += wrap_write(writer, &header.posting_lists_and_metadata_len.to_le_bytes())?;
-        Ok(written)
+    let expected_bytes_written =
+        std::mem::size_of::<u64>() * 2 * num_posting_lists + std::mem::size_of::<u64>();
+    if metadata_bytes_written != expected_bytes_written {
+        return Err(anyhow!(
+            "Expected to write {} bytes of posting list metadata, but wrote {}",
+            expected_bytes_written,
+            metadata_bytes_written,
+        ));
     }
+    Ok(metadata_bytes_written + posting_list_bytes_written)
+}
 
-    /// Combine all individual files into one final index file. Keep vectors file separate.
-    fn combine_files(&self, header: &Header) -> Result<usize> {
-        let doc_id_mapping_path = format!("{}/doc_id_mapping", self.base_directory);
-        let centroids_path = format!("{}/centroids", self.base_directory);
-        let posting_list_metadata_path = format!("{}/posting_list_metadata", self.base_directory);
-        let posting_lists_path = format!("{}/posting_lists", self.base_directory);
+/// Computes the section directory of a V1 index file from the files of `base_directory` that
+/// will be combined into it.
+pub(crate) fn compute_sections(base_directory: &str, header: &Header) -> Result<Vec<SectionEntry>> {
+    // The header length only depends on the number of sections, not on their content.
+    let placeholder = Header {
+        sections: vec![
+            SectionEntry {
+                offset: 0,
+                len: 0,
+                crc32: 0,
+            };
+            3
+        ],
+        ..*header
+    };
+    let data_offset = FixedIndexFile::data_offset(placeholder.to_bytes().len());
+    let offsets = FixedIndexFile::section_offsets(header, data_offset);
+    let lens = [
+        header.doc_id_mapping_len,
+        header.centroids_len,
+        header.posting_lists_and_metadata_len,
+    ];
+    let files: [&[&str]; 3] = [
+        &["doc_id_mapping"],
+        &["centroids"],
+        &["posting_list_metadata", "posting_lists"],
+    ];
+
+    let mut sections = vec![];
+    for ((offset, len), file_names) in offsets.iter().zip(lens).zip(files) {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buffer = vec![0u8; 1 << 16];
+        for file_name in file_names {
+            let file = File::open(format!("{}/{}", base_directory, file_name))?;
+            let mut reader = BufReader::new(file);
+            loop {
+                let read = reader.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+            }
+        }
+        sections.push(SectionEntry {
+            offset: *offset as u64,
+            len,
+            crc32: hasher.finalize(),
+        });
+    }
+    Ok(sections)
+}
 
-        let combined_path = format!("{}/index", self.base_directory);
-        let mut combined_file = File::create(combined_path)?;
-        let mut combined_buffer_writer = BufWriter::new(&mut combined_file);
+/// Combine all individual files of `base_directory` into one final index file. Keep vectors
+/// file separate.
+pub(crate) fn combine_files(base_directory: &str, header: &Header) -> Result<usize> {
+    let doc_id_mapping_path = format!("{}/doc_id_mapping", base_directory);
+    let centroids_path = format!("{}/centroids", base_directory);
+    let posting_list_metadata_path = format!("{}/posting_list_metadata", base_directory);
+    let posting_lists_path = format!("{}/posting_lists", base_directory);
 
-        let mut written = self
-            .write_header(header, &mut combined_buffer_writer)
-            .context("Failed to write header")?;
+    let combined_path = format!("{}/index", base_directory);
+    let mut combined_file = File::create(combined_path)?;
+    let mut combined_buffer_writer = BufWriter::new(&mut combined_file);
 
-        // Compute padding for alignment to 8 bytes
-        written += write_pad(written, &mut combined_buffer_writer, 16)?;
-        written += append_file_to_writer(&doc_id_mapping_path, &mut combined_buffer_writer)?;
+    let mut written = wrap_write(&mut combined_buffer_writer, &header.to_bytes())
+        .context("Failed to write header")?;
 
-        // No need for padding, doc_id_mapping is always 8-byte aligned
-        written += append_file_to_writer(&centroids_path, &mut combined_buffer_writer)?;
+    // Compute padding for alignment to 8 bytes
+    written += write_pad(written, &mut combined_buffer_writer, 16)?;
+    written += append_file_to_writer(&doc_id_mapping_path, &mut combined_buffer_writer)?;
 
-        // Pad again in case num_features and num_clusters are both odd
-        written += write_pad(written, &mut combined_buffer_writer, 8)?;
-        written += append_file_to_writer(&posting_list_metadata_path, &mut combined_buffer_writer)?;
-        written += append_file_to_writer(&posting_lists_path, &mut combined_buffer_writer)?;
+    // No need for padding, doc_id_mapping is always 8-byte aligned
+    written += append_file_to_writer(&centroids_path, &mut combined_buffer_writer)?;
 
-        combined_buffer_writer
-            .flush()
-            .context("Failed to flush combined buffer")?;
+    // Pad again in case num_features and num_clusters are both odd
+    written += write_pad(written, &mut combined_buffer_writer, 8)?;
+    written += append_file_to_writer(&posting_list_metadata_path, &mut combined_buffer_writer)?;
+    written += append_file_to_writer(&posting_lists_path, &mut combined_buffer_writer)?;
 
-        remove_file(format!("{}/doc_id_mapping", self.base_directory))?;
-        remove_file(format!("{}/centroids", self.base_directory))?;
-        remove_file(format!("{}/posting_list_metadata", self.base_directory))?;
-        remove_file(format!("{}/posting_lists", self.base_directory))?;
+    combined_buffer_writer
+        .flush()
+        .context("Failed to flush combined buffer")?;
 
-        Ok(written)
-    }
+    remove_file(format!("{}/doc_id_mapping", base_directory))?;
+    remove_file(format!("{}/centroids", base_directory))?;
+    remove_file(format!("{}/posting_list_metadata", base_directory))?;
+    remove_file(format!("{}/posting_lists", base_directory))?;
+
+    Ok(written)
 }
 
 // Test
@@ -299,10 +369,12 @@ mod tests {
     use quantization::pq::pq::ProductQuantizer;
     use tempdir::TempDir;
     use utils::distance::l2::L2DistanceCalculator;
+    use utils::kmeans_builder::kmeans_builder::KMeansVariant;
     use utils::test_utils::generate_random_vector;
 
     use super::*;
     use crate::ivf::builder::IvfBuilderConfig;
+    use crate::posting_list::combined_file::SectionCorrupted;
 
     fn create_test_file(base_directory: &str, name: &str, content: &[u8]) -> Result<()> {
         let path = format!("{}/{}", base_directory, name);
@@ -346,6 +418,7 @@ mod tests {
             doc_id_mapping_len: 4,
             centroids_len: 4,
             posting_lists_and_metadata_len: 4,
+            sections: vec![],
         };
 
         // Call combine_files
@@ -481,7 +554,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -491,6 +567,7 @@ mod tests {
         ivf_builder
             .add_vector(1, &vec![4.0, 5.0, 6.0])
             .expect("Vector should be added");
+        ivf_builder.flush().expect("Vectors should be flushed");
 
         // Act
         let result = ivf_writer.quantize_and_write_vectors(&ivf_builder);
@@ -570,7 +647,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
 
@@ -629,6 +709,99 @@ mod tests {
         assert_eq!(posting_lists_content.len(), 8 * 6);
     }
 
+    #[test]
+    fn test_ivf_writer_write_v1() {
+        let temp_dir =
+            TempDir::new("test_ivf_writer_write_v1").expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let num_clusters = 10;
+        let num_vectors = 100;
+        let num_features = 4;
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let mut writer = IvfWriter::<_, PlainEncoder, L2DistanceCalculator>::new(
+            base_directory.clone(),
+            quantizer,
+        );
+        writer.set_version(Version::V1);
+
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters,
+            num_data_points_for_clustering: num_vectors,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: base_directory.clone(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .expect("Failed to create builder");
+        for i in 0..num_vectors {
+            builder
+                .add_vector(i as u128, &generate_random_vector(num_features))
+                .expect("Vector should be added");
+        }
+        assert!(builder.build().is_ok());
+        assert!(writer.write(&mut builder, false).is_ok());
+
+        let index_path = format!("{}/index", base_directory);
+        let index_file = FixedIndexFile::new_verified(index_path.clone())
+            .expect("Freshly written index should pass verification");
+        let header = index_file.header();
+        assert_eq!(header.version, Version::V1);
+        assert_eq!(header.sections.len(), 3);
+        assert_eq!(header.sections[1].len, header.centroids_len);
+        assert_eq!(index_file.get_doc_id(42).unwrap(), 42);
+
+        // Flip a byte of the first centroid
+        let mut content = fs::read(&index_path).unwrap();
+        let centroids_offset = header.sections[1].offset as usize;
+        content[centroids_offset + 8] ^= 0xff;
+        fs::write(&index_path, &content).unwrap();
+
+        let error = match FixedIndexFile::new_verified(index_path.clone()) {
+            Ok(_) => panic!("Corrupted index should fail verification"),
+            Err(error) => error,
+        };
+        assert_eq!(
+            error.downcast_ref::<SectionCorrupted>(),
+            Some(&SectionCorrupted {
+                section: "centroids".to_string(),
+                offset: centroids_offset as u64,
+            })
+        );
+
+        // Other sections are still readable without verification
+        let index_file = FixedIndexFile::new(index_path.clone()).unwrap();
+        assert_eq!(index_file.get_doc_id(42).unwrap(), 42);
+
+        // A section offset that overflows is reported as corrupted instead of panicking.
+        // Entries start after the fixed header fields and the section count.
+        content[centroids_offset + 8] ^= 0xff;
+        let posting_lists_entry = 1 + 12 + 32 + 4 + 2 * 20;
+        content[posting_lists_entry..posting_lists_entry + 8]
+            .copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&index_path, &content).unwrap();
+        let error = match FixedIndexFile::new_verified(index_path) {
+            Ok(_) => panic!("Overflowing section should fail verification"),
+            Err(error) => error,
+        };
+        assert_eq!(
+            error.downcast_ref::<SectionCorrupted>().unwrap().section,
+            "posting_lists"
+        );
+    }
+
     #[test]
     fn test_ivf_writer_write() {
         let temp_dir =
@@ -660,7 +833,10 @@ mod tests {
             file_size,
             num_features,
             tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })
         .expect("Failed to create builder");
         // Generate 1000 vectors of f32, dimension 4
@@ -1,9 +1,14 @@
 #![feature(auto_traits)]
 
 pub mod collection;
+pub mod diff;
+pub mod flat;
 pub mod hnsw;
 pub mod index;
+pub mod integrity;
+pub mod interop;
 pub mod ivf;
+pub mod mock;
 pub mod multi_spann;
 pub mod posting_list;
 pub mod segment;
@@ -11,3 +16,4 @@ pub mod spann;
 pub mod traverse_state;
 pub mod utils;
 pub mod vector;
+pub mod wal;
@@ -0,0 +1,173 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::collection::SegmentSearchable;
+use crate::index::{DocIdFilter, Searchable};
+use crate::multi_spann::user_stats::{GlobalStats, UserStats};
+use crate::segment::Segment;
+use crate::utils::{IdWithScore, SearchContext, SearchResult};
+
+#[derive(Default)]
+struct MockState {
+    call_count: usize,
+    last_query: Option<Vec<f32>>,
+}
+
+/// A `Searchable` returning canned results, for unit testing code that depends on an index
+/// (e.g. collection logic or gRPC handlers) without building a real one.
+/// It can also be used as a segment: insertions and removals are accepted and ignored.
+///
+/// Clones share the same interaction history, so a clone can be kept for assertions after the
+/// mock itself has been handed over.
+#[derive(Clone)]
+pub struct MockSearchable {
+    results: Option<Vec<IdWithScore>>,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockSearchable {
+    /// Always returns `results` (truncated to `k`)
+    pub fn returning(results: Vec<IdWithScore>) -> Self {
+        Self {
+            results: Some(results),
+            state: Arc::new(Mutex::new(MockState::default())),
+        }
+    }
+
+    /// Always returns `None`
+    pub fn failing() -> Self {
+        Self {
+            results: None,
+            state: Arc::new(Mutex::new(MockState::default())),
+        }
+    }
+
+    /// Number of searches performed on this mock
+    pub fn call_count(&self) -> usize {
+        self.state.lock().unwrap().call_count
+    }
+
+    /// Query of the latest search performed on this mock
+    pub fn last_query(&self) -> Option<Vec<f32>> {
+        self.state.lock().unwrap().last_query.clone()
+    }
+}
+
+impl Searchable for MockSearchable {
+    fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        _ef_search: u32,
+        _context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        let mut state = self.state.lock().unwrap();
+        state.call_count += 1;
+        state.last_query = Some(query.to_vec());
+
+        self.results
+            .as_ref()
+            .map(|results| results.iter().take(k).cloned().collect())
+    }
+
+    /// Returns the canned results accepted by `filter` (truncated to `k`)
+    fn search_with_filter(
+        &self,
+        query: &[f32],
+        k: usize,
+        _ef_search: u32,
+        filter: &DocIdFilter,
+        _context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        let mut state = self.state.lock().unwrap();
+        state.call_count += 1;
+        state.last_query = Some(query.to_vec());
+
+        self.results.as_ref().map(|results| {
+            results
+                .iter()
+                .filter(|x| filter(x.id))
+                .take(k)
+                .cloned()
+                .collect()
+        })
+    }
+}
+
+impl Segment for MockSearchable {
+    fn insert(&mut self, _doc_id: u64, _data: &[f32]) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove(&mut self, _doc_id: u64) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn may_contains(&self, _doc_id: u64) -> bool {
+        false
+    }
+
+    fn describe(&self) -> String {
+        "Mock index\n".to_string()
+    }
+
+    /// One vector per canned result.
+    fn num_vectors(&self) -> u64 {
+        self.results
+            .as_ref()
+            .map_or(0, |results| results.len() as u64)
+    }
+
+    fn size_in_bytes(&self) -> u64 {
+        0
+    }
+
+    fn user_stats(&self, _user_id: u128) -> Option<UserStats> {
+        None
+    }
+
+    fn global_stats(&self) -> GlobalStats {
+        GlobalStats::default()
+    }
+}
+
+impl SegmentSearchable for MockSearchable {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_searchable_returning() {
+        let mock = MockSearchable::returning(vec![
+            IdWithScore { id: 1, score: 0.1 },
+            IdWithScore { id: 2, score: 0.2 },
+        ]);
+        assert_eq!(mock.call_count(), 0);
+        assert!(mock.last_query().is_none());
+
+        let mut context = SearchContext::new(false);
+        let results = mock.search(&[1.0, 2.0], 1, 10, &mut context).unwrap();
+        assert_eq!(results.into_doc_ids(), vec![1]);
+
+        let results = mock.search_with_id(0, &[3.0], 5, 10, &mut context).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(mock.call_count(), 2);
+        assert_eq!(mock.last_query(), Some(vec![3.0]));
+    }
+
+    #[test]
+    fn test_mock_searchable_failing() {
+        let mock = MockSearchable::failing();
+        let mut context = SearchContext::new(false);
+        assert!(mock.search(&[1.0], 1, 10, &mut context).is_none());
+        assert_eq!(mock.call_count(), 1);
+
+        // Clones share the interaction history
+        let clone = mock.clone();
+        assert!(clone.search(&[2.0], 1, 10, &mut context).is_none());
+        assert_eq!(mock.call_count(), 2);
+        assert_eq!(mock.last_query(), Some(vec![2.0]));
+    }
+}
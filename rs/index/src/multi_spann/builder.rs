@@ -1,10 +1,15 @@
+use std::collections::HashMap;
 use std::sync::RwLock;
 
 use anyhow::Result;
 use config::collection::CollectionConfig;
+use dashmap::mapref::one::RefMut;
 use dashmap::DashMap;
-use log::debug;
+use log::{debug, warn};
+use quantization::quantization::Quantizer;
+use utils::validation::validate_vector;
 
+use crate::multi_spann::index::MultiSpannIndex;
 use crate::spann::builder::{SpannBuilder, SpannBuilderConfig};
 
 pub struct MultiSpannBuilder {
@@ -22,8 +27,28 @@ impl MultiSpannBuilder {
         })
     }
 
-    pub fn insert(&self, user_id: u128, doc_id: u128, data: &[f32]) -> Result<()> {
-        let spann_builder = self.inner_builders.entry(user_id).or_insert_with(|| {
+    /// Creates a builder holding the documents of every user of both segments, so that they can
+    /// be built into a single segment. Deleted documents are dropped, and quantized vectors are
+    /// replaced by their reconstruction.
+    pub fn merge_from<Q: Quantizer>(
+        seg_a: &MultiSpannIndex<Q>,
+        seg_b: &MultiSpannIndex<Q>,
+        config: CollectionConfig,
+        base_directory: String,
+    ) -> Result<Self> {
+        let builder = Self::new(config, base_directory)?;
+        for segment in [seg_a, seg_b] {
+            for user_id in segment.user_ids() {
+                for (doc_id, data) in segment.user_documents(user_id)? {
+                    builder.insert(user_id, doc_id, &data)?;
+                }
+            }
+        }
+        Ok(builder)
+    }
+
+    fn builder_for_user(&self, user_id: u128) -> RefMut<'_, u128, RwLock<SpannBuilder>> {
+        self.inner_builders.entry(user_id).or_insert_with(|| {
             let user_directory = format!("{}/{}", self.base_directory, user_id);
             RwLock::new(
                 SpannBuilder::new(SpannBuilderConfig::from_collection_config(
@@ -32,11 +57,54 @@ impl MultiSpannBuilder {
                 ))
                 .unwrap(),
             )
-        });
+        })
+    }
+
+    pub fn insert(&self, user_id: u128, doc_id: u128, data: &[f32]) -> Result<()> {
+        let spann_builder = self.builder_for_user(user_id);
         spann_builder.write().unwrap().add(doc_id, data)?;
         Ok(())
     }
 
+    /// Inserts (user id, doc id, vector) records, in order for each user. Records are grouped by
+    /// user so that each user's builder is looked up and locked once for the whole batch.
+    /// Records with a vector of the wrong dimension or with NaN/Inf components are skipped,
+    /// other errors are returned. Returns the number of inserted records.
+    pub fn insert_batch(&self, records: &[(u128, u128, &[f32])]) -> Result<usize> {
+        let mut records_per_user: HashMap<u128, Vec<(u128, &[f32])>> = HashMap::new();
+        for (user_id, doc_id, data) in records {
+            if data.len() != self.config.num_features {
+                warn!(
+                    "Skipping doc {} of user {}: expected {} features, got {}",
+                    doc_id,
+                    user_id,
+                    self.config.num_features,
+                    data.len()
+                );
+                continue;
+            }
+            if let Err(e) = validate_vector(data, "MultiSpannBuilder::insert_batch") {
+                warn!("Skipping doc {} of user {}: {}", doc_id, user_id, e);
+                continue;
+            }
+            records_per_user
+                .entry(*user_id)
+                .or_default()
+                .push((*doc_id, *data));
+        }
+
+        let mut num_inserted = 0;
+        for (user_id, user_records) in records_per_user {
+            let spann_builder = self.builder_for_user(user_id);
+            let mut spann_builder = spann_builder.write().unwrap();
+            for (doc_id, data) in user_records {
+                spann_builder.add(doc_id, data)?;
+                num_inserted += 1;
+            }
+        }
+        Ok(num_inserted)
+    }
+
     pub fn build(&self) -> Result<()> {
         for entry in self.inner_builders.iter() {
             debug!("Building segment for user {}", entry.key());
@@ -199,4 +267,43 @@ mod tests {
         // The builders should be removed from multi_builder
         assert!(multi_builder.user_ids().is_empty());
     }
+
+    #[test]
+    fn test_multi_spann_builder_insert_batch() {
+        let temp_dir = TempDir::new("test_multi_spann_builder_insert_batch").unwrap();
+        let base_directory: String = temp_dir.path().to_str().unwrap().to_string();
+
+        let multi_builder =
+            MultiSpannBuilder::new(CollectionConfig::default_test_config(), base_directory)
+                .expect("Failed to create builder");
+
+        let data_1 = [1.0, 2.0, 3.0, 4.0];
+        let data_2 = [5.0, 6.0, 7.0, 8.0];
+        let data_3 = [9.0, 10.0, 11.0, 12.0];
+        let nan = [f32::NAN, 0.0, 0.0, 0.0];
+        let too_short = [1.0, 2.0];
+        let records: Vec<(u128, u128, &[f32])> = vec![
+            (1, 101, &data_1),
+            (2, 201, &data_2),
+            (1, 102, &nan),
+            (1, 103, &data_3),
+            (2, 202, &too_short),
+        ];
+        assert_eq!(multi_builder.insert_batch(&records).unwrap(), 3);
+
+        let mut user_ids = multi_builder.user_ids();
+        user_ids.sort();
+        assert_eq!(user_ids, vec![1, 2]);
+        assert!(multi_builder.build().is_ok());
+
+        // Vectors of a user keep their order
+        let builder = multi_builder.take_builder_for_user(1).unwrap();
+        assert_eq!(builder.ivf_builder.doc_id_mapping(), &[101, 103]);
+        assert_eq!(
+            builder.ivf_builder.vectors().borrow().get(1).unwrap(),
+            &data_3
+        );
+        let builder = multi_builder.take_builder_for_user(2).unwrap();
+        assert_eq!(builder.ivf_builder.doc_id_mapping(), &[201]);
+    }
 }
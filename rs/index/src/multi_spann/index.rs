@@ -1,16 +1,21 @@
+use std::fs::File;
+use std::os::unix::fs::FileExt;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use config::enums::DistanceType;
 use dashmap::DashMap;
 use memmap2::Mmap;
 use odht::HashTableOwned;
 use quantization::quantization::Quantizer;
+use utils::io::directory_size;
 
 use super::user_index_info::HashConfig;
-use crate::index::Searchable;
+use super::user_stats::{now_epoch_ms, GlobalStats, UserStats};
+use crate::index::{DocIdFilter, FilteredSearch, Searchable};
 use crate::spann::index::Spann;
 use crate::spann::reader::SpannReader;
-use crate::utils::{IdWithScore, SearchContext};
+use crate::utils::{SearchContext, SearchResult};
 
 pub struct MultiSpannIndex<Q: Quantizer> {
     base_directory: String,
@@ -18,18 +23,207 @@ pub struct MultiSpannIndex<Q: Quantizer> {
     #[allow(dead_code)]
     user_index_info_mmap: Mmap,
     user_index_infos: HashTableOwned<HashConfig>,
+    // Access statistics of the users that have been queried
+    user_stats: Arc<DashMap<u128, UserStats>>,
+    // Distance metric the per-user indexes are read with
+    distance_metric: DistanceType,
+    // Capacity of the vector cache of the per-user indexes
+    vector_cache_capacity: Option<usize>,
 }
 
 impl<Q: Quantizer> MultiSpannIndex<Q> {
-    pub fn new(base_directory: String, user_index_info_mmap: Mmap) -> Result<Self> {
+    pub fn new(
+        base_directory: String,
+        user_index_info_mmap: Mmap,
+        distance_metric: DistanceType,
+    ) -> Result<Self> {
         let user_index_infos = HashTableOwned::from_raw_bytes(&user_index_info_mmap).unwrap();
         Ok(Self {
             base_directory,
             user_to_spann: DashMap::new(),
             user_index_info_mmap,
             user_index_infos,
+            user_stats: Arc::new(DashMap::new()),
+            distance_metric,
+            vector_cache_capacity: None,
         })
     }
+
+    /// Sets the capacity of the vector cache of the per-user indexes loaded from now on.
+    pub fn with_vector_cache_capacity(mut self, vector_cache_capacity: Option<usize>) -> Self {
+        self.vector_cache_capacity = vector_cache_capacity;
+        self
+    }
+
+    /// Returns the SPANN index of the given user, reading it from disk if it is not loaded yet.
+    fn get_or_load_index(&self, id: u128) -> Option<Arc<Spann<Q>>> {
+        if let Some(index) = self.user_to_spann.get(&id) {
+            return Some(index.clone());
+        }
+
+        // Fetch the index from the mmap
+        let index_info = self.user_index_infos.get(&id)?;
+        let reader = SpannReader::new_with_offsets(
+            self.base_directory.clone(),
+            index_info.centroid_index_offset as usize,
+            index_info.centroid_vector_offset as usize,
+            index_info.ivf_index_offset as usize,
+            index_info.ivf_vectors_offset as usize,
+        )
+        .with_distance_metric(self.distance_metric.clone())
+        .with_vector_cache_capacity(self.vector_cache_capacity);
+        match reader.read::<Q>() {
+            Ok(index) => {
+                let index = Arc::new(index);
+                self.user_to_spann.insert(id, index.clone());
+                Some(index)
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Returns the SPANN index of the given user and records the query in the user's statistics.
+    fn get_index_for_query(&self, id: u128) -> Option<Arc<Spann<Q>>> {
+        let cache_hit = self.user_to_spann.contains_key(&id);
+        let index = self.get_or_load_index(id)?;
+        self.user_stats
+            .entry(id)
+            .or_default()
+            .record_query(cache_hit, now_epoch_ms());
+        Some(index)
+    }
+
+    /// Same as `search_with_id`, but only returns the documents of the user accepted by
+    /// `filter`.
+    pub fn search_with_id_filtered(
+        &self,
+        id: u128,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        context: &mut SearchContext,
+        filter: &dyn Fn(u128) -> bool,
+    ) -> Option<SearchResult> {
+        let index = self.get_index_for_query(id)?;
+        index
+            .search_filtered(query, k, ef_search, context, filter)
+            .map(SearchResult::new)
+    }
+
+    /// Returns the ids of all users with an index in this segment, sorted.
+    pub fn user_ids(&self) -> Vec<u128> {
+        let mut user_ids: Vec<u128> = self.user_index_infos.iter().map(|(id, _)| id).collect();
+        user_ids.sort();
+        user_ids
+    }
+
+    /// Returns the doc id and vector of every document of the given user that is not deleted.
+    pub fn user_documents(&self, user_id: u128) -> Result<Vec<(u128, Vec<f32>)>> {
+        let index = self
+            .get_or_load_index(user_id)
+            .ok_or_else(|| anyhow!("Failed to read the index of user {}", user_id))?;
+        index.get_posting_lists().documents()
+    }
+
+    /// Returns the access statistics of the given user, or None if it was never queried.
+    pub fn user_stats(&self, user_id: u128) -> Option<UserStats> {
+        self.user_stats.get(&user_id).map(|stats| *stats)
+    }
+
+    /// Returns the access statistics summed over all users.
+    pub fn global_stats(&self) -> GlobalStats {
+        let mut global_stats = GlobalStats::default();
+        for stats in self.user_stats.iter() {
+            global_stats.add_user(stats.value());
+        }
+        global_stats
+    }
+
+    /// Unloads the least recently queried indexes until at most `max_loaded_users` remain in
+    /// memory. They are read from disk again on their next query. Returns the number of
+    /// unloaded indexes.
+    pub fn evict_least_recently_used(&self, max_loaded_users: usize) -> usize {
+        let mut loaded: Vec<(u64, u128)> = self
+            .user_to_spann
+            .iter()
+            .map(|entry| {
+                let user_id = *entry.key();
+                let last_accessed = self
+                    .user_stats
+                    .get(&user_id)
+                    .map_or(0, |stats| stats.last_accessed_epoch_ms);
+                (last_accessed, user_id)
+            })
+            .collect();
+        if loaded.len() <= max_loaded_users {
+            return 0;
+        }
+
+        loaded.sort();
+        let num_evicted = loaded.len() - max_loaded_users;
+        for (_, user_id) in loaded.into_iter().take(num_evicted) {
+            self.user_to_spann.remove(&user_id);
+        }
+        num_evicted
+    }
+
+    /// Returns a multi-line, human-readable summary of the SPANN index of every user.
+    pub fn describe(&self) -> String {
+        let user_ids = self.user_ids();
+        let mut description = format!("Multi-SPANN index with {} users\n", user_ids.len());
+        for user_id in user_ids {
+            description.push_str(&format!("User {}: ", user_id));
+            match self.get_or_load_index(user_id) {
+                Some(index) => description.push_str(&index.describe()),
+                None => description.push_str("failed to read index\n"),
+            }
+        }
+        description
+    }
+
+    /// Returns the total number of vectors of all users, without loading their indexes. The
+    /// vectors of every user start with their count, at the offset kept in their index info.
+    /// Users whose count can't be read are skipped.
+    pub fn num_vectors(&self) -> u64 {
+        let vectors_path = format!("{}/ivf/vectors", self.base_directory);
+        let vectors_file = match File::open(&vectors_path) {
+            Ok(file) => file,
+            Err(_) => return 0,
+        };
+        self.user_index_infos
+            .iter()
+            .filter_map(|(_, index_info)| {
+                let mut count = [0u8; 8];
+                vectors_file
+                    .read_exact_at(&mut count, index_info.ivf_vectors_offset)
+                    .ok()?;
+                Some(u64::from_le_bytes(count))
+            })
+            .sum()
+    }
+
+    /// Loads the indexes of the users in id order, and reads their vectors until `num_vectors`
+    /// vectors are in the page cache. Returns the number of vectors read.
+    pub fn prefetch(&self, num_vectors: usize) -> usize {
+        let mut num_read = 0;
+        for user_id in self.user_ids() {
+            if num_read >= num_vectors {
+                break;
+            }
+            if let Some(index) = self.get_or_load_index(user_id) {
+                num_read += index
+                    .get_posting_lists()
+                    .vector_storage
+                    .prefetch(num_vectors - num_read);
+            }
+        }
+        num_read
+    }
+
+    /// Returns the size of the index files on disk, in bytes.
+    pub fn size_in_bytes(&self) -> u64 {
+        directory_size(&self.base_directory).unwrap_or(0)
+    }
 }
 
 impl<Q: Quantizer> Searchable for MultiSpannIndex<Q> {
@@ -37,10 +231,10 @@ impl<Q: Quantizer> Searchable for MultiSpannIndex<Q> {
         &self,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>> {
-        self.search_with_id(0, query, k, ef_construction, context)
+    ) -> Option<SearchResult> {
+        self.search_with_id(0, query, k, ef_search, context)
     }
 
     fn search_with_id(
@@ -48,39 +242,22 @@ impl<Q: Quantizer> Searchable for MultiSpannIndex<Q> {
         id: u128,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
         context: &mut SearchContext,
-    ) -> Option<Vec<IdWithScore>> {
-        let index = self.user_to_spann.get(&id);
-        if index.is_none() {
-            // Fetch the index from the mmap
-            let index_info = self.user_index_infos.get(&id);
-            if index_info.is_none() {
-                return None;
-            }
-
-            let index_info = index_info.unwrap();
-            let reader = SpannReader::new_with_offsets(
-                self.base_directory.clone(),
-                index_info.centroid_index_offset as usize,
-                index_info.centroid_vector_offset as usize,
-                index_info.ivf_index_offset as usize,
-                index_info.ivf_vectors_offset as usize,
-            );
-            match reader.read::<Q>() {
-                Ok(index) => {
-                    let index = Arc::new(index);
-                    self.user_to_spann.insert(id, index.clone());
-                    return index.search(query, k, ef_construction, context);
-                }
-                Err(_) => {
-                    return None;
-                }
-            }
-        }
+    ) -> Option<SearchResult> {
+        let index = self.get_index_for_query(id)?;
+        index.search(query, k, ef_search, context)
+    }
 
-        let index = index.unwrap().clone();
-        index.search(query, k, ef_construction, context)
+    fn search_with_filter(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        filter: &DocIdFilter,
+        context: &mut SearchContext,
+    ) -> Option<SearchResult> {
+        self.search_with_id_filtered(0, query, k, ef_search, context, &**filter)
     }
 }
 
@@ -147,5 +324,97 @@ mod tests {
         assert_eq!(results[0].id, num_vectors);
         assert_eq!(results[1].id, 3);
         assert_eq!(results[2].id, 2);
+
+        let results = multi_spann_index
+            .search_with_id_filtered(0, &query, k, num_probes, &mut context, &|doc_id| {
+                doc_id % 2 == 0
+            })
+            .expect("Failed to search with Multi-SPANN index");
+        assert_eq!(results.len(), k);
+        assert!(results.iter().all(|result| result.id % 2 == 0));
+        assert_eq!(results[0].id, num_vectors);
+
+        let description = multi_spann_index.describe();
+        assert!(description.starts_with("Multi-SPANN index with 1 users"));
+        assert!(description.contains("User 0: SPANN index"));
+        assert!(description.contains("vectors: 1001"));
+    }
+
+    #[test]
+    fn test_multi_spann_user_stats() {
+        let temp_dir = tempdir::TempDir::new("multi_spann_user_stats_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let num_features = 4;
+        let mut spann_builder_config = CollectionConfig::default_test_config();
+        spann_builder_config.num_features = num_features;
+        let mut multi_spann_builder =
+            MultiSpannBuilder::new(spann_builder_config, base_directory.clone())
+                .expect("Failed to create Multi-SPANN builder");
+        for user_id in 0..3u128 {
+            for i in 0..10 {
+                let v = (user_id * 100 + i) as f32;
+                assert!(multi_spann_builder
+                    .insert(user_id, i, &[v, v, v, v])
+                    .is_ok());
+            }
+        }
+        assert!(multi_spann_builder.build().is_ok());
+        let multi_spann_writer = MultiSpannWriter::new(base_directory.clone());
+        assert!(multi_spann_writer.write(&mut multi_spann_builder).is_ok());
+        let multi_spann_index = MultiSpannReader::new(base_directory)
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .expect("Failed to read Multi-SPANN index");
+        // Counting vectors doesn't load any index
+        assert_eq!(multi_spann_index.num_vectors(), 30);
+        assert!(multi_spann_index.user_to_spann.is_empty());
+
+        let query = [1.0, 1.0, 1.0, 1.0];
+        let mut context = SearchContext::new(false);
+        let mut search = |user_id: u128| {
+            assert!(multi_spann_index
+                .search_with_id(user_id, &query, 3, 2, &mut context)
+                .is_some());
+            // Keep the access times of the users apart
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        };
+        search(0);
+        search(1);
+        search(2);
+        search(0);
+        search(0);
+
+        let user_0 = multi_spann_index.user_stats(0).unwrap();
+        assert_eq!(user_0.query_count, 3);
+        assert_eq!(user_0.cache_misses, 1);
+        assert_eq!(user_0.cache_hits, 2);
+        let user_1 = multi_spann_index.user_stats(1).unwrap();
+        assert_eq!(user_1.query_count, 1);
+        assert!(user_1.last_accessed_epoch_ms <= user_0.last_accessed_epoch_ms);
+        assert!(multi_spann_index.user_stats(3).is_none());
+
+        let global_stats = multi_spann_index.global_stats();
+        assert_eq!(global_stats.num_users, 3);
+        assert_eq!(global_stats.query_count, 5);
+        assert_eq!(global_stats.cache_hits, 2);
+        assert_eq!(global_stats.cache_misses, 3);
+
+        // User 1 is the least recently queried, so it is unloaded first
+        assert_eq!(multi_spann_index.evict_least_recently_used(3), 0);
+        assert_eq!(multi_spann_index.evict_least_recently_used(2), 1);
+        assert!(!multi_spann_index.user_to_spann.contains_key(&1));
+        assert_eq!(multi_spann_index.evict_least_recently_used(1), 1);
+        assert!(multi_spann_index.user_to_spann.contains_key(&0));
+
+        // An evicted user is read from disk again on its next query
+        assert!(multi_spann_index
+            .search_with_id(1, &query, 3, 2, &mut context)
+            .is_some());
+        assert_eq!(multi_spann_index.user_stats(1).unwrap().cache_misses, 2);
     }
 }
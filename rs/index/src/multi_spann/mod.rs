@@ -2,4 +2,5 @@ pub mod builder;
 pub mod index;
 pub mod reader;
 pub mod user_index_info;
+pub mod user_stats;
 pub mod writer;
@@ -1,4 +1,5 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use config::enums::DistanceType;
 use memmap2::Mmap;
 use quantization::quantization::Quantizer;
 
@@ -6,21 +7,53 @@ use crate::multi_spann::index::MultiSpannIndex;
 
 pub struct MultiSpannReader {
     base_directory: String,
+    distance_metric: DistanceType,
+    vector_cache_capacity: Option<usize>,
 }
 
 impl MultiSpannReader {
     pub fn new(base_directory: String) -> Self {
-        Self { base_directory }
+        Self {
+            base_directory,
+            distance_metric: DistanceType::L2,
+            vector_cache_capacity: None,
+        }
+    }
+
+    /// Sets the distance metric the per-user indexes are searched with. Defaults to L2.
+    pub fn with_distance_metric(mut self, distance_metric: DistanceType) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    /// Sets the capacity of the vector cache of every per-user index. Defaults to None, which
+    /// disables the cache.
+    pub fn with_vector_cache_capacity(mut self, vector_cache_capacity: Option<usize>) -> Self {
+        self.vector_cache_capacity = vector_cache_capacity;
+        self
     }
 
     pub fn read<Q: Quantizer>(&self) -> Result<MultiSpannIndex<Q>> {
+        // Per-user indexes are loaded lazily, so check the metric before they are needed
+        if self.distance_metric != DistanceType::L2 {
+            return Err(anyhow!(
+                "{:?} distance is not supported for SPANN indexes",
+                self.distance_metric
+            ));
+        }
+
         let user_index_info_file_path = format!("{}/user_index_info", self.base_directory);
         let user_index_info_file = std::fs::OpenOptions::new()
             .read(true)
             .open(user_index_info_file_path)?;
 
         let user_index_info_mmap = unsafe { Mmap::map(&user_index_info_file)? };
-        MultiSpannIndex::<Q>::new(self.base_directory.clone(), user_index_info_mmap)
+        Ok(MultiSpannIndex::<Q>::new(
+            self.base_directory.clone(),
+            user_index_info_mmap,
+            self.distance_metric.clone(),
+        )?
+        .with_vector_cache_capacity(self.vector_cache_capacity))
     }
 }
 
@@ -54,6 +87,12 @@ mod tests {
         let multi_spann_writer = MultiSpannWriter::new(base_directory.clone());
         multi_spann_writer.write(&mut multi_spann_builder)?;
 
+        // Only L2 is supported so far
+        assert!(MultiSpannReader::new(base_directory.clone())
+            .with_distance_metric(DistanceType::DotProduct)
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .is_err());
+
         let multi_spann_reader = MultiSpannReader::new(base_directory);
         let multi_spann_index = multi_spann_reader.read::<NoQuantizer<L2DistanceCalculator>>()?;
 
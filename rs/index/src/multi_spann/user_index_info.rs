@@ -1,6 +1,6 @@
 use odht::{Config, FxHashFn};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct UserIndexInfo {
     pub user_id: u128,
     pub centroid_vector_offset: u64,
@@ -0,0 +1,112 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Access statistics of a single user of a `MultiSpannIndex`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UserStats {
+    pub query_count: u64,
+    // Time of the last query, in milliseconds since the Unix epoch
+    pub last_accessed_epoch_ms: u64,
+    // Queries served by an index that was already loaded
+    pub cache_hits: u64,
+    // Queries that had to read the index of the user from disk first
+    pub cache_misses: u64,
+}
+
+impl UserStats {
+    /// Records a query made at `epoch_ms`.
+    pub fn record_query(&mut self, cache_hit: bool, epoch_ms: u64) {
+        self.query_count += 1;
+        self.last_accessed_epoch_ms = self.last_accessed_epoch_ms.max(epoch_ms);
+        if cache_hit {
+            self.cache_hits += 1;
+        } else {
+            self.cache_misses += 1;
+        }
+    }
+
+    /// Combines the statistics of the same user from another index, e.g. another segment.
+    pub fn merge(&mut self, other: &UserStats) {
+        self.query_count += other.query_count;
+        self.last_accessed_epoch_ms = self
+            .last_accessed_epoch_ms
+            .max(other.last_accessed_epoch_ms);
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+    }
+}
+
+/// Access statistics summed over all users of a `MultiSpannIndex`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GlobalStats {
+    // Users that have been queried at least once
+    pub num_users: u64,
+    pub query_count: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+impl GlobalStats {
+    pub fn add_user(&mut self, user_stats: &UserStats) {
+        self.num_users += 1;
+        self.query_count += user_stats.query_count;
+        self.cache_hits += user_stats.cache_hits;
+        self.cache_misses += user_stats.cache_misses;
+    }
+
+    /// Combines the statistics of another index, e.g. another segment. Users of both indexes
+    /// are counted twice.
+    pub fn merge(&mut self, other: &GlobalStats) {
+        self.num_users += other.num_users;
+        self.query_count += other.query_count;
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+    }
+}
+
+pub fn now_epoch_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_stats() {
+        let mut stats = UserStats::default();
+        stats.record_query(false, 100);
+        stats.record_query(true, 200);
+        stats.record_query(true, 150);
+        assert_eq!(
+            stats,
+            UserStats {
+                query_count: 3,
+                last_accessed_epoch_ms: 200,
+                cache_hits: 2,
+                cache_misses: 1,
+            }
+        );
+
+        let mut other = UserStats::default();
+        other.record_query(false, 300);
+        stats.merge(&other);
+        assert_eq!(stats.query_count, 4);
+        assert_eq!(stats.last_accessed_epoch_ms, 300);
+        assert_eq!(stats.cache_misses, 2);
+
+        let mut global_stats = GlobalStats::default();
+        global_stats.add_user(&stats);
+        global_stats.add_user(&other);
+        assert_eq!(
+            global_stats,
+            GlobalStats {
+                num_users: 2,
+                query_count: 5,
+                cache_hits: 2,
+                cache_misses: 3,
+            }
+        );
+    }
+}
@@ -1,8 +1,8 @@
-use std::fs::{self, File};
+use std::fs::{self, File, OpenOptions};
 use std::io::{BufWriter, Write};
 use std::path::Path;
 
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Ok, Result};
 use config::collection::CollectionConfig;
 use config::enums::QuantizerType;
 use odht::HashTableOwned;
@@ -52,6 +52,16 @@ impl MultiSpannWriter {
                 let ivf_quantizer = NoQuantizer::<L2DistanceCalculator>::new(config.num_features);
                 ivf_quantizer.write_to_directory(&ivf_quantizer_directory)?;
             }
+            QuantizerType::ResidualQuantizer
+            | QuantizerType::Sq4
+            | QuantizerType::ScalarQuantizer
+            | QuantizerType::BinaryQuantizer
+            | QuantizerType::RandomProjection { .. } => {
+                return Err(anyhow::anyhow!(
+                    "{:?} is not supported for collections",
+                    config.quantization_type
+                ));
+            }
         };
         Ok(())
     }
@@ -73,16 +83,7 @@ impl MultiSpannWriter {
         for user_id in user_ids.iter() {
             user_index_infos.push(UserIndexInfo {
                 user_id: *user_id,
-                centroid_vector_offset: 0,
-                centroid_vector_len: 0,
-                centroid_index_offset: 0,
-                centroid_index_len: 0,
-                ivf_vectors_offset: 0,
-                ivf_vectors_len: 0,
-                ivf_index_offset: 0,
-                ivf_index_len: 0,
-                ivf_pq_codebook_offset: 0,
-                ivf_pq_codebook_len: 0,
+                ..Default::default()
             });
         }
 
@@ -105,115 +106,15 @@ impl MultiSpannWriter {
         // IVF quantizer
         Self::write_common_ivf_quantizer(&ivf_directory, &multi_spann.config())?;
 
-        // Centroids index
-        let mut hnsw_index_file = File::create(format!("{}/index", hnsw_directory))?;
-        let mut hnsw_index_buffer_writer = BufWriter::new(&mut hnsw_index_file);
-
-        // Centroids vector file
-        let mut centroids_vector_file = File::create(format!("{}/vector_storage", hnsw_directory))?;
-        let mut centroids_vector_buffer_writer = BufWriter::new(&mut centroids_vector_file);
-
-        // IVF index file
-        let mut ivf_index_file = File::create(format!("{}/index", ivf_directory))?;
-        let mut ivf_index_buffer_writer = BufWriter::new(&mut ivf_index_file);
-
-        // IVF vectors file
-        let mut ivf_vectors_file = File::create(format!("{}/vectors", ivf_directory))?;
-        let mut ivf_vectors_buffer_writer = BufWriter::new(&mut ivf_vectors_file);
-
-        // IVF product quantizer codebook file
-        let ivf_pq_codebook_path = format!("{}/quantizer/{}", ivf_directory, CODEBOOK_NAME);
-        let mut ivf_pq_codebook_file = File::create(&ivf_pq_codebook_path)?;
-        let mut ivf_pq_codebook_buffer_writer = BufWriter::new(&mut ivf_pq_codebook_file);
-
-        let mut centroids_index_written: u64 = 0;
-        let mut centroids_vector_written: u64 = 0;
-        let mut ivf_index_written: u64 = 0;
-        let mut ivf_vectors_written: u64 = 0;
-        let mut ivf_pq_codebook_written: u64 = 0;
-
+        let with_pq = multi_spann.config().quantization_type == QuantizerType::ProductQuantizer;
+        let mut combined_files = CombinedFiles::create(&base_directory, with_pq)?;
         for (idx, user_id) in user_ids.iter().enumerate() {
             let user_id_base_directory = format!("{}/{}", base_directory, *user_id);
-            let user_id_index_file_path =
-                format!("{}/centroids/hnsw/index", user_id_base_directory);
-            let user_index_info = &mut user_index_infos[idx];
-
-            // Centroids index
-            centroids_index_written += write_pad(
-                centroids_index_written as usize,
-                &mut hnsw_index_buffer_writer,
-                16,
-            )? as u64;
-            user_index_info.centroid_index_offset = centroids_index_written;
-            centroids_index_written +=
-                append_file_to_writer(&user_id_index_file_path, &mut hnsw_index_buffer_writer)?
-                    as u64;
-            user_index_info.centroid_index_len =
-                centroids_index_written - user_index_info.centroid_index_offset;
-
-            // Centroids vector
-            centroids_vector_written += write_pad(
-                centroids_vector_written as usize,
-                &mut centroids_vector_buffer_writer,
-                8,
-            )? as u64;
-            user_index_info.centroid_vector_offset = centroids_vector_written;
-            centroids_vector_written += append_file_to_writer(
-                &format!("{}/centroids/hnsw/vector_storage", user_id_base_directory),
-                &mut centroids_vector_buffer_writer,
-            )? as u64;
-            user_index_info.centroid_vector_len =
-                centroids_vector_written - user_index_info.centroid_vector_offset;
-
-            // IVF index
-            ivf_index_written +=
-                write_pad(ivf_index_written as usize, &mut ivf_index_buffer_writer, 16)? as u64;
-            user_index_info.ivf_index_offset = ivf_index_written;
-            ivf_index_written += append_file_to_writer(
-                &format!("{}/ivf/index", user_id_base_directory),
-                &mut ivf_index_buffer_writer,
-            )? as u64;
-            user_index_info.ivf_index_len = ivf_index_written - user_index_info.ivf_index_offset;
-
-            // IVF vectors
-            ivf_vectors_written += write_pad(
-                ivf_vectors_written as usize,
-                &mut ivf_vectors_buffer_writer,
-                8,
-            )? as u64;
-            user_index_info.ivf_vectors_offset = ivf_vectors_written;
-            ivf_vectors_written += append_file_to_writer(
-                &format!("{}/ivf/vectors", user_id_base_directory),
-                &mut ivf_vectors_buffer_writer,
-            )? as u64;
-            user_index_info.ivf_vectors_len =
-                ivf_vectors_written - user_index_info.ivf_vectors_offset;
-
-            // IVF product quantizer codebooks
-            if multi_spann.config().quantization_type == QuantizerType::ProductQuantizer {
-                ivf_pq_codebook_written += write_pad(
-                    ivf_pq_codebook_written as usize,
-                    &mut ivf_pq_codebook_buffer_writer,
-                    8,
-                )? as u64;
-                user_index_info.ivf_pq_codebook_offset = ivf_pq_codebook_written;
-                ivf_pq_codebook_written += append_file_to_writer(
-                    &format!("{}/ivf/quantizer/codebook", user_id_base_directory),
-                    &mut ivf_pq_codebook_buffer_writer,
-                )? as u64;
-                user_index_info.ivf_pq_codebook_len =
-                    ivf_pq_codebook_written - user_index_info.ivf_pq_codebook_offset;
-            }
+            combined_files.append_user(&user_id_base_directory, &mut user_index_infos[idx])?;
         }
 
         // Write user index infos
-        let mut hash_table = HashTableOwned::<HashConfig>::with_capacity(user_ids.len(), 90);
-        for user_index_info in user_index_infos.iter() {
-            hash_table.insert(&user_index_info.user_id, &user_index_info);
-        }
-        let serialized = hash_table.raw_bytes();
-        let user_index_info_file = format!("{}/user_index_info", base_directory);
-        fs::write(&user_index_info_file, serialized)?;
+        Self::write_user_index_infos(&base_directory, &user_index_infos)?;
 
         // Cleanup the user directories
         for user_id in user_ids.iter() {
@@ -222,12 +123,190 @@ impl MultiSpannWriter {
             // It's ok to fail for some reason
             std::fs::remove_dir_all(user_id_base_directory).unwrap_or_default();
         }
+        Ok(user_index_infos)
+    }
 
-        // Cleanup the codebook if no quantization for IVF
-        if multi_spann.config().quantization_type != QuantizerType::ProductQuantizer {
-            std::fs::remove_file(ivf_pq_codebook_path).unwrap_or_default();
+    /// Appends the index of `user_id` to the index previously written by `write` in the same
+    /// directory, without rewriting the other users' data. The user must not be in the index yet.
+    pub fn append_user(&self, multi_spann: &mut MultiSpannBuilder, user_id: u128) -> Result<()> {
+        let base_directory = self.base_directory.clone();
+        let mut user_index_infos = Self::read_user_index_infos(&base_directory)?;
+        if user_index_infos.iter().any(|info| info.user_id == user_id) {
+            return Err(anyhow!("User {} is already in the index", user_id));
         }
-        Ok(user_index_infos)
+        let mut spann_builder = multi_spann
+            .take_builder_for_user(user_id)
+            .ok_or_else(|| anyhow!("No builder for user {}", user_id))?;
+
+        let user_id_base_directory = format!("{}/{}", base_directory, user_id);
+        SpannWriter::new(user_id_base_directory.clone()).write(&mut spann_builder)?;
+
+        let mut user_index_info = UserIndexInfo {
+            user_id,
+            ..Default::default()
+        };
+        let with_pq = multi_spann.config().quantization_type == QuantizerType::ProductQuantizer;
+        let mut combined_files = CombinedFiles::open_for_append(&base_directory, with_pq)?;
+        combined_files.append_user(&user_id_base_directory, &mut user_index_info)?;
+
+        user_index_infos.push(user_index_info);
+        Self::write_user_index_infos(&base_directory, &user_index_infos)?;
+
+        // It's ok to fail for some reason
+        std::fs::remove_dir_all(user_id_base_directory).unwrap_or_default();
+        Ok(())
+    }
+
+    fn read_user_index_infos(base_directory: &str) -> Result<Vec<UserIndexInfo>> {
+        let bytes = fs::read(format!("{}/user_index_info", base_directory))?;
+        let hash_table = HashTableOwned::<HashConfig>::from_raw_bytes(&bytes)
+            .map_err(|e| anyhow!("Invalid user index info file: {}", e))?;
+        Ok(hash_table.iter().map(|(_, info)| info).collect())
+    }
+
+    fn write_user_index_infos(
+        base_directory: &str,
+        user_index_infos: &[UserIndexInfo],
+    ) -> Result<()> {
+        let mut hash_table =
+            HashTableOwned::<HashConfig>::with_capacity(user_index_infos.len(), 90);
+        for user_index_info in user_index_infos.iter() {
+            hash_table.insert(&user_index_info.user_id, user_index_info);
+        }
+        let serialized = hash_table.raw_bytes();
+        let user_index_info_file = format!("{}/user_index_info", base_directory);
+        // Write to a temporary file and rename it, so that a crash keeps the previous infos
+        let tmp_user_index_info_file = format!("{}.tmp", user_index_info_file);
+        {
+            let mut tmp_file = File::create(&tmp_user_index_info_file)?;
+            tmp_file.write_all(serialized)?;
+            tmp_file.sync_all()?;
+        }
+        fs::rename(&tmp_user_index_info_file, &user_index_info_file)?;
+        File::open(base_directory)?.sync_all()?;
+        Ok(())
+    }
+}
+
+// A file that the files of all users are concatenated into.
+struct CombinedFile {
+    file: File,
+    // Number of bytes in the file so far
+    written: u64,
+}
+
+impl CombinedFile {
+    fn create(path: &str) -> Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+            written: 0,
+        })
+    }
+
+    fn open_for_append(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self { file, written })
+    }
+
+    /// Pads the file to `alignment`, then appends the file at `path` to it. Returns the offset and
+    /// length of the appended data.
+    fn append(&mut self, path: &str, alignment: usize) -> Result<(u64, u64)> {
+        let mut writer = BufWriter::new(&mut self.file);
+        self.written += write_pad(self.written as usize, &mut writer, alignment)? as u64;
+        let offset = self.written;
+        self.written += append_file_to_writer(path, &mut writer)? as u64;
+        writer.flush()?;
+        Ok((offset, self.written - offset))
+    }
+}
+
+// The files shared by all users of a multi-user index.
+struct CombinedFiles {
+    centroids_index: CombinedFile,
+    centroids_vectors: CombinedFile,
+    ivf_index: CombinedFile,
+    ivf_vectors: CombinedFile,
+    // Only when the IVF uses product quantization
+    ivf_pq_codebook: Option<CombinedFile>,
+}
+
+impl CombinedFiles {
+    fn open(
+        base_directory: &str,
+        with_pq: bool,
+        open_file: fn(&str) -> Result<CombinedFile>,
+    ) -> Result<Self> {
+        Ok(Self {
+            centroids_index: open_file(&format!("{}/centroids/hnsw/index", base_directory))?,
+            centroids_vectors: open_file(&format!(
+                "{}/centroids/hnsw/vector_storage",
+                base_directory
+            ))?,
+            ivf_index: open_file(&format!("{}/ivf/index", base_directory))?,
+            ivf_vectors: open_file(&format!("{}/ivf/vectors", base_directory))?,
+            ivf_pq_codebook: if with_pq {
+                Some(open_file(&format!(
+                    "{}/ivf/quantizer/{}",
+                    base_directory, CODEBOOK_NAME
+                ))?)
+            } else {
+                None
+            },
+        })
+    }
+
+    fn create(base_directory: &str, with_pq: bool) -> Result<Self> {
+        Self::open(base_directory, with_pq, CombinedFile::create)
+    }
+
+    fn open_for_append(base_directory: &str, with_pq: bool) -> Result<Self> {
+        Self::open(base_directory, with_pq, CombinedFile::open_for_append)
+    }
+
+    /// Appends the files of the SPANN index written in `user_id_base_directory`, and fills the
+    /// offsets and lengths of `user_index_info`.
+    fn append_user(
+        &mut self,
+        user_id_base_directory: &str,
+        user_index_info: &mut UserIndexInfo,
+    ) -> Result<()> {
+        (
+            user_index_info.centroid_index_offset,
+            user_index_info.centroid_index_len,
+        ) = self.centroids_index.append(
+            &format!("{}/centroids/hnsw/index", user_id_base_directory),
+            16,
+        )?;
+        (
+            user_index_info.centroid_vector_offset,
+            user_index_info.centroid_vector_len,
+        ) = self.centroids_vectors.append(
+            &format!("{}/centroids/hnsw/vector_storage", user_id_base_directory),
+            8,
+        )?;
+        (
+            user_index_info.ivf_index_offset,
+            user_index_info.ivf_index_len,
+        ) = self
+            .ivf_index
+            .append(&format!("{}/ivf/index", user_id_base_directory), 16)?;
+        (
+            user_index_info.ivf_vectors_offset,
+            user_index_info.ivf_vectors_len,
+        ) = self
+            .ivf_vectors
+            .append(&format!("{}/ivf/vectors", user_id_base_directory), 8)?;
+        if let Some(ivf_pq_codebook) = self.ivf_pq_codebook.as_mut() {
+            (
+                user_index_info.ivf_pq_codebook_offset,
+                user_index_info.ivf_pq_codebook_len,
+            ) = ivf_pq_codebook.append(
+                &format!("{}/ivf/quantizer/{}", user_id_base_directory, CODEBOOK_NAME),
+                8,
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -240,6 +319,9 @@ mod tests {
     use utils::test_utils::generate_random_vector;
 
     use super::*;
+    use crate::index::Searchable;
+    use crate::multi_spann::reader::MultiSpannReader;
+    use crate::utils::SearchContext;
 
     #[test]
     fn test_write() {
@@ -346,4 +428,58 @@ mod tests {
         assert!(PathBuf::from(&ivf_quantizer_codebook_path).exists());
         assert!(PathBuf::from(&ivf_quantizer_config_path).exists());
     }
+
+    #[test]
+    fn test_append_user() -> Result<()> {
+        let temp_dir = TempDir::new("test_append_user")?;
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let mut collection_config = CollectionConfig::default_test_config();
+        collection_config.num_features = 4;
+
+        let mut builder =
+            MultiSpannBuilder::new(collection_config.clone(), base_directory.clone())?;
+        builder.insert(0, 1, &[1.0, 2.0, 3.0, 4.0])?;
+        builder.insert(0, 2, &[5.0, 6.0, 7.0, 8.0])?;
+        builder.insert(1, 3, &[9.0, 10.0, 11.0, 12.0])?;
+        builder.build()?;
+        let multi_spann_writer = MultiSpannWriter::new(base_directory.clone());
+        let user_index_infos = multi_spann_writer.write(&mut builder)?;
+
+        let mut new_builder = MultiSpannBuilder::new(collection_config, base_directory.clone())?;
+        new_builder.insert(2, 4, &[13.0, 14.0, 15.0, 16.0])?;
+        new_builder.insert(0, 5, &[1.0, 2.0, 3.0, 4.0])?;
+        new_builder.build()?;
+        multi_spann_writer.append_user(&mut new_builder, 2)?;
+        assert!(!PathBuf::from(format!("{}/2", base_directory)).exists());
+        assert!(!PathBuf::from(format!("{}/user_index_info.tmp", base_directory)).exists());
+
+        // Existing users are kept
+        assert!(multi_spann_writer.append_user(&mut new_builder, 0).is_err());
+        // Users without data can't be appended
+        assert!(multi_spann_writer.append_user(&mut new_builder, 3).is_err());
+
+        let mut appended_infos = MultiSpannWriter::read_user_index_infos(&base_directory)?;
+        appended_infos.sort_by_key(|info| info.user_id);
+        assert_eq!(appended_infos.len(), 3);
+        // Data of the existing users did not move
+        for (info, appended_info) in user_index_infos.iter().zip(appended_infos.iter()) {
+            assert_eq!(info.user_id, appended_info.user_id);
+            assert_eq!(info.ivf_index_offset, appended_info.ivf_index_offset);
+            assert_eq!(
+                info.centroid_index_offset,
+                appended_info.centroid_index_offset
+            );
+        }
+
+        let multi_spann_index =
+            MultiSpannReader::new(base_directory).read::<NoQuantizer<L2DistanceCalculator>>()?;
+        let query = [1.0, 2.0, 3.0, 4.0];
+        for (user_id, expected) in [(0, 1), (1, 3), (2, 4)] {
+            let result = multi_spann_index
+                .search_with_id(user_id, &query, 3, 100, &mut SearchContext::new(false))
+                .unwrap();
+            assert_eq!(result[0].id, expected);
+        }
+        Ok(())
+    }
 }
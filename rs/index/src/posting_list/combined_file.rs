@@ -1,15 +1,32 @@
+use std::fmt;
 use std::mem::size_of;
 
 use anyhow::{anyhow, Result};
 use byteorder::{ByteOrder, LittleEndian};
-use memmap2::Mmap;
+use memmap2::{Mmap, MmapMut};
 use utils::mem::transmute_u8_to_slice;
 
 const PL_METADATA_LEN: usize = 2;
 
-#[derive(PartialEq, Debug)]
+// Names of the sections of an index file, in the order they are stored.
+pub const SECTION_NAMES: [&str; 3] = ["doc_id_mapping", "centroids", "posting_lists"];
+
+// Size of a section directory entry: offset (u64), length (u64) and CRC32 (u32).
+const SECTION_ENTRY_LEN: usize = 20;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Version {
     V0,
+    // V0 followed by a section directory with the CRC32 of every section
+    V1,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionEntry {
+    // Offset of the section from the start of the index, in bytes
+    pub offset: u64,
+    pub len: u64,
+    pub crc32: u32,
 }
 
 #[derive(Debug)]
@@ -22,9 +39,61 @@ pub struct Header {
     pub doc_id_mapping_len: u64,
     pub centroids_len: u64,
     pub posting_lists_and_metadata_len: u64,
+    // One entry per section in `SECTION_NAMES` order. Empty for V0, which has no checksums.
+    pub sections: Vec<SectionEntry>,
 }
 
+impl Header {
+    /// Serializes the header, without the padding that follows it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let version: u8 = match self.version {
+            Version::V0 => 0,
+            Version::V1 => 1,
+        };
+        let mut bytes = vec![version];
+        bytes.extend_from_slice(&self.num_features.to_le_bytes());
+        bytes.extend_from_slice(&self.quantized_dimension.to_le_bytes());
+        bytes.extend_from_slice(&self.num_clusters.to_le_bytes());
+        bytes.extend_from_slice(&self.num_vectors.to_le_bytes());
+        bytes.extend_from_slice(&self.doc_id_mapping_len.to_le_bytes());
+        bytes.extend_from_slice(&self.centroids_len.to_le_bytes());
+        bytes.extend_from_slice(&self.posting_lists_and_metadata_len.to_le_bytes());
+        if self.version == Version::V1 {
+            bytes.extend_from_slice(&(self.sections.len() as u32).to_le_bytes());
+            for section in &self.sections {
+                bytes.extend_from_slice(&section.offset.to_le_bytes());
+                bytes.extend_from_slice(&section.len.to_le_bytes());
+                bytes.extend_from_slice(&section.crc32.to_le_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+/// Error returned by `FixedIndexFile::new_verified` when the content of a section doesn't match
+/// its checksum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionCorrupted {
+    pub section: String,
+    // Offset of the section from the start of the index, in bytes
+    pub offset: u64,
+}
+
+impl fmt::Display for SectionCorrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Section {} at offset {} is corrupted: checksum mismatch",
+            self.section, self.offset
+        )
+    }
+}
+
+impl std::error::Error for SectionCorrupted {}
+
 pub struct FixedIndexFile {
+    file_path: String,
+    offset: usize,
     mmap: Mmap,
     header: Header,
     doc_id_mapping_offset: usize,
@@ -38,17 +107,63 @@ impl FixedIndexFile {
             .read(true)
             .open(file_path.clone())?;
         let mmap = unsafe { Mmap::map(&file) }?;
-        let (header, doc_id_mapping_offset) = Self::read_header(&mmap, offset)?;
+        Self::new_with_mmap(file_path, mmap, offset)
+    }
 
-        let centroid_offset = Self::align_to_next_boundary(
-            doc_id_mapping_offset + header.doc_id_mapping_len as usize,
-            8,
-        );
+    pub fn new(file_path: String) -> Result<Self> {
+        Self::new_with_offset(file_path, 0)
+    }
+
+    /// Like `new`, but also checks every section against its CRC32 and returns a
+    /// `SectionCorrupted` error on mismatch. V0 files have no checksums and are not checked.
+    pub fn new_verified(file_path: String) -> Result<Self> {
+        let index_file = Self::new(file_path)?;
+        index_file.verify_sections()?;
+        Ok(index_file)
+    }
+
+    /// Checks every section against the CRC32 recorded in the header.
+    pub fn verify_sections(&self) -> Result<()> {
+        for (name, section) in SECTION_NAMES.iter().zip(&self.header.sections) {
+            // Offsets and lengths come from the header, so they may be arbitrary
+            let range = (self.offset as u64)
+                .checked_add(section.offset)
+                .and_then(|start| Some((start, start.checked_add(section.len)?)));
+            let corrupted = match range {
+                Some((start, end)) if end <= self.mmap.len() as u64 => {
+                    crc32fast::hash(&self.mmap[start as usize..end as usize]) != section.crc32
+                }
+                _ => true,
+            };
+            if corrupted {
+                return Err(SectionCorrupted {
+                    section: name.to_string(),
+                    offset: section.offset,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the index file from bytes that are already in memory, e.g. downloaded from
+    /// remote storage. `name` is only used to identify the file, e.g. in posting list ids.
+    pub fn new_from_bytes(name: String, bytes: &[u8], offset: usize) -> Result<Self> {
+        let mut mmap = MmapMut::map_anon(bytes.len().max(1))?;
+        mmap[..bytes.len()].copy_from_slice(bytes);
+        Self::new_with_mmap(name, mmap.make_read_only()?, offset)
+    }
+
+    fn new_with_mmap(file_path: String, mmap: Mmap, offset: usize) -> Result<Self> {
+        let (header, data_offset) = Self::read_header(&mmap, offset)?;
+        let [doc_id_mapping_offset, centroid_offset, posting_lists_offset] =
+            Self::section_offsets(&header, data_offset);
 
-        let posting_list_metadata_offset =
-            Self::align_to_next_boundary(centroid_offset + header.centroids_len as usize, 8)
-                + size_of::<u64>(); // FileBackedAppendablePostingListStorage's first u64 encodes num_clusters
+        // FileBackedAppendablePostingListStorage's first u64 encodes num_clusters
+        let posting_list_metadata_offset = posting_lists_offset + size_of::<u64>();
         Ok(Self {
+            file_path,
+            offset,
             mmap,
             header,
             doc_id_mapping_offset,
@@ -57,15 +172,12 @@ impl FixedIndexFile {
         })
     }
 
-    pub fn new(file_path: String) -> Result<Self> {
-        Self::new_with_offset(file_path, 0)
-    }
-
     /// Read the header from the mmap and return the header and the offset of data page
     pub fn read_header(buffer: &[u8], offset: usize) -> Result<(Header, usize)> {
         let mut offset = offset;
         let version = match buffer[offset] {
             0 => Version::V0,
+            1 => Version::V1,
             default => return Err(anyhow!("Unknown version: {}", default)),
         };
         offset += 1;
@@ -85,6 +197,28 @@ impl FixedIndexFile {
         let posting_lists_and_metadata_len = LittleEndian::read_u64(&buffer[offset..]);
         offset += 8;
 
+        let mut sections = vec![];
+        if version == Version::V1 {
+            let num_sections = LittleEndian::read_u32(&buffer[offset..]) as usize;
+            offset += 4;
+            if num_sections != SECTION_NAMES.len() {
+                return Err(anyhow!(
+                    "Expected {} sections, found {}",
+                    SECTION_NAMES.len(),
+                    num_sections
+                ));
+            }
+            for _ in 0..num_sections {
+                let entry = &buffer[offset..offset + SECTION_ENTRY_LEN];
+                sections.push(SectionEntry {
+                    offset: LittleEndian::read_u64(entry),
+                    len: LittleEndian::read_u64(&entry[8..]),
+                    crc32: LittleEndian::read_u32(&entry[16..]),
+                });
+                offset += SECTION_ENTRY_LEN;
+            }
+        }
+
         let header = Header {
             version,
             num_features,
@@ -94,6 +228,7 @@ impl FixedIndexFile {
             doc_id_mapping_len,
             centroids_len,
             posting_lists_and_metadata_len,
+            sections,
         };
 
         // Align to the next 8-byte boundary
@@ -102,6 +237,24 @@ impl FixedIndexFile {
         Ok((header, offset))
     }
 
+    /// Returns where the doc id mapping, centroids and posting lists sections start, given where
+    /// the data following the header starts.
+    pub fn section_offsets(header: &Header, data_offset: usize) -> [usize; 3] {
+        let doc_id_mapping_offset = data_offset;
+        let centroid_offset = Self::align_to_next_boundary(
+            doc_id_mapping_offset + header.doc_id_mapping_len as usize,
+            8,
+        );
+        let posting_lists_offset =
+            Self::align_to_next_boundary(centroid_offset + header.centroids_len as usize, 8);
+        [doc_id_mapping_offset, centroid_offset, posting_lists_offset]
+    }
+
+    /// Returns where the data following a header of `header_len` bytes starts.
+    pub fn data_offset(header_len: usize) -> usize {
+        Self::align_to_next_boundary(header_len, 16)
+    }
+
     fn align_to_next_boundary(current_position: usize, alignment: usize) -> usize {
         let mask = alignment - 1;
         (current_position + mask) & !mask
@@ -154,9 +307,69 @@ impl FixedIndexFile {
         Ok(&self.mmap[pl_offset..pl_offset + pl_len])
     }
 
+    /// Identifier of a posting list, unique across index files (and offsets within them).
+    pub fn posting_list_id(&self, index: usize) -> String {
+        format!("{}@{}::{}", self.file_path, self.offset, index)
+    }
+
+    /// Returns the number of bytes this index occupies in the backing file.
+    pub fn size_in_bytes(&self) -> usize {
+        Self::align_to_next_boundary(self.centroid_offset + self.header.centroids_len as usize, 8)
+            + self.header.posting_lists_and_metadata_len as usize
+            - self.offset
+    }
+
     pub fn header(&self) -> &Header {
         &self.header
     }
+
+    /// Rewrites the content of a V0 index file as V1, adding the section directory with the
+    /// CRC32 of every section. The sections themselves are copied unchanged.
+    pub fn add_section_checksums(bytes: &[u8]) -> Result<Vec<u8>> {
+        if bytes.first() != Some(&0) {
+            return Err(anyhow!("Expected a V0 index file"));
+        }
+        let (header, old_data_offset) = Self::read_header(bytes, 0)?;
+        let old_offsets = Self::section_offsets(&header, old_data_offset);
+        let lens = [
+            header.doc_id_mapping_len,
+            header.centroids_len,
+            header.posting_lists_and_metadata_len,
+        ];
+
+        // The header length only depends on the number of sections, not on their content.
+        let placeholder = SectionEntry {
+            offset: 0,
+            len: 0,
+            crc32: 0,
+        };
+        let mut new_header = Header {
+            version: Version::V1,
+            sections: vec![placeholder; SECTION_NAMES.len()],
+            ..header
+        };
+        let new_data_offset = Self::data_offset(new_header.to_bytes().len());
+        // Both data offsets are 16-byte aligned, so the padding between sections is unchanged.
+        let new_offsets = Self::section_offsets(&new_header, new_data_offset);
+
+        let mut sections = vec![];
+        for ((old_offset, new_offset), len) in old_offsets.iter().zip(new_offsets).zip(lens) {
+            let content = bytes
+                .get(*old_offset..old_offset + len as usize)
+                .ok_or_else(|| anyhow!("Index file is truncated"))?;
+            sections.push(SectionEntry {
+                offset: new_offset as u64,
+                len,
+                crc32: crc32fast::hash(content),
+            });
+        }
+        new_header.sections = sections;
+
+        let mut result = new_header.to_bytes();
+        result.resize(new_data_offset, 0);
+        result.extend_from_slice(&bytes[old_data_offset..]);
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
@@ -3,8 +3,9 @@ use quantization::quantization::Quantizer;
 
 use super::Segment;
 use crate::collection::SegmentSearchable;
-use crate::index::Searchable;
+use crate::index::{DocIdFilter, Searchable};
 use crate::multi_spann::index::MultiSpannIndex;
+use crate::multi_spann::user_stats::{GlobalStats, UserStats};
 
 /// This is an immutable segment. This usually contains a single index.
 pub struct ImmutableSegment<Q: Quantizer> {
@@ -31,6 +32,30 @@ impl<Q: Quantizer> Segment for ImmutableSegment<Q> {
         // TODO(hicder): Implement this
         return true;
     }
+
+    fn describe(&self) -> String {
+        self.index.describe()
+    }
+
+    fn num_vectors(&self) -> u64 {
+        self.index.num_vectors()
+    }
+
+    fn size_in_bytes(&self) -> u64 {
+        self.index.size_in_bytes()
+    }
+
+    fn user_stats(&self, user_id: u128) -> Option<UserStats> {
+        self.index.user_stats(user_id)
+    }
+
+    fn global_stats(&self) -> GlobalStats {
+        self.index.global_stats()
+    }
+
+    fn prefetch(&self, num_vectors: usize) -> usize {
+        self.index.prefetch(num_vectors)
+    }
 }
 
 impl<Q: Quantizer> Searchable for ImmutableSegment<Q> {
@@ -38,10 +63,10 @@ impl<Q: Quantizer> Searchable for ImmutableSegment<Q> {
         &self,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
         context: &mut crate::utils::SearchContext,
-    ) -> Option<Vec<crate::utils::IdWithScore>> {
-        self.index.search(query, k, ef_construction, context)
+    ) -> Option<crate::utils::SearchResult> {
+        self.index.search(query, k, ef_search, context)
     }
 
     fn search_with_id(
@@ -49,11 +74,22 @@ impl<Q: Quantizer> Searchable for ImmutableSegment<Q> {
         id: u128,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
+        context: &mut crate::utils::SearchContext,
+    ) -> Option<crate::utils::SearchResult> {
+        self.index.search_with_id(id, query, k, ef_search, context)
+    }
+
+    fn search_with_filter(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        filter: &DocIdFilter,
         context: &mut crate::utils::SearchContext,
-    ) -> Option<Vec<crate::utils::IdWithScore>> {
+    ) -> Option<crate::utils::SearchResult> {
         self.index
-            .search_with_id(id, query, k, ef_construction, context)
+            .search_with_filter(query, k, ef_search, filter, context)
     }
 }
 
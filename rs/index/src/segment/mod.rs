@@ -3,6 +3,8 @@ pub mod mutable_segment;
 
 use anyhow::Result;
 
+use crate::multi_spann::user_stats::{GlobalStats, UserStats};
+
 /// A segment is a partial index: users can insert some documents, then flush
 /// the containing collection, to effectively create a segment.
 pub trait Segment {
@@ -19,4 +21,26 @@ pub trait Segment {
     /// Returns true if the segment may contain the given document.
     /// False if the segment definitely does not contain the document.
     fn may_contains(&self, doc_id: u64) -> bool;
+
+    /// Returns a multi-line, human-readable summary of the segment.
+    fn describe(&self) -> String;
+
+    /// Returns the number of vectors in the segment.
+    fn num_vectors(&self) -> u64;
+
+    /// Returns the size of the segment files on disk, in bytes.
+    fn size_in_bytes(&self) -> u64;
+
+    /// Returns the access statistics of the given user in this segment, or None if the user was
+    /// never queried in it.
+    fn user_stats(&self, user_id: u128) -> Option<UserStats>;
+
+    /// Returns the access statistics summed over all users of this segment.
+    fn global_stats(&self) -> GlobalStats;
+
+    /// Reads the first `num_vectors` vectors of the segment, to populate the page cache. Returns
+    /// the number of vectors read.
+    fn prefetch(&self, _num_vectors: usize) -> usize {
+        0
+    }
 }
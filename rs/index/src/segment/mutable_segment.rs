@@ -6,6 +6,8 @@ use crate::multi_spann::writer::MultiSpannWriter;
 
 pub struct MutableSegment {
     multi_spann_builder: MultiSpannBuilder,
+    // Number of inserted vectors, counting once per user
+    num_vectors: usize,
 
     // Prevent a mutable segment from being modified after it is built.
     finalized: bool,
@@ -15,6 +17,7 @@ impl MutableSegment {
     pub fn new(config: CollectionConfig, base_directory: String) -> Result<Self> {
         Ok(Self {
             multi_spann_builder: MultiSpannBuilder::new(config, base_directory)?,
+            num_vectors: 0,
             finalized: false,
         })
     }
@@ -28,15 +31,20 @@ impl MutableSegment {
     }
 
     /// Insert a document for a user
-    pub fn insert_for_user(&self, user_id: u128, doc_id: u128, data: &[f32]) -> Result<()> {
+    pub fn insert_for_user(&mut self, user_id: u128, doc_id: u128, data: &[f32]) -> Result<()> {
         if self.finalized {
             return Err(anyhow::anyhow!("Cannot insert into a finalized segment"));
         }
 
         self.multi_spann_builder.insert(user_id, doc_id, data)?;
+        self.num_vectors += 1;
         Ok(())
     }
 
+    pub fn num_vectors(&self) -> usize {
+        self.num_vectors
+    }
+
     pub fn build(&mut self, base_directory: String, name: String) -> Result<()> {
         if self.finalized {
             return Err(anyhow::anyhow!("Cannot build a finalized segment"));
@@ -1,13 +1,21 @@
-use anyhow::{Ok, Result};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
 use config::collection::CollectionConfig;
 use config::enums::{IntSeqEncodingType, QuantizerType};
 use log::debug;
 use quantization::noq::noq::NoQuantizer;
 use serde::{Deserialize, Serialize};
 use utils::distance::l2::L2DistanceCalculator;
+use utils::kmeans_builder::kmeans_builder::KMeansVariant;
 
 use crate::hnsw::builder::HnswBuilder;
-use crate::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+use crate::hnsw::index::LayerStats;
+use crate::hnsw::reader::HnswReader;
+use crate::ivf::builder::{imbalance_coefficient, IvfBuilder, IvfBuilderConfig};
+use crate::utils::SearchContext;
+use crate::vector::VectorStorageConfig;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SpannBuilderConfig {
@@ -45,9 +53,123 @@ pub struct SpannBuilderConfig {
 
     // Optimization parameters
     pub reindex: bool,
+
+    // Centroids directory of an existing SPANN index. When set, its centroids and their HNSW graph
+    // are reused instead of being built, and vectors are only assigned to the loaded centroids.
+    // This keeps the centroid layout when only the posting lists change, e.g. after deletions.
+    #[serde(default, alias = "centroid_hnsw_path")]
+    pub centroid_graph_path: Option<String>,
+}
+
+/// A single violation found by `SpannBuilderConfig::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpannConfigError {
+    ZeroNumFeatures,
+    SubvectorDimensionNotDivisor {
+        num_features: usize,
+        subvector_dimension: usize,
+    },
+    TooManyClusters {
+        num_clusters: usize,
+        num_data_points_for_clustering: usize,
+    },
+    ZeroMaxNeighbors,
+    EfConstructionTooSmall {
+        ef_construction: u32,
+        max_neighbors: usize,
+    },
+    ZeroFileSize {
+        field: &'static str,
+    },
+}
+
+impl fmt::Display for SpannConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpannConfigError::ZeroNumFeatures => write!(f, "num_features must be positive"),
+            SpannConfigError::SubvectorDimensionNotDivisor {
+                num_features,
+                subvector_dimension,
+            } => write!(
+                f,
+                "pq_subvector_dimension ({}) must divide num_features ({})",
+                subvector_dimension, num_features
+            ),
+            SpannConfigError::TooManyClusters {
+                num_clusters,
+                num_data_points_for_clustering,
+            } => write!(
+                f,
+                "ivf_num_clusters ({}) must not exceed ivf_num_data_points_for_clustering ({})",
+                num_clusters, num_data_points_for_clustering
+            ),
+            SpannConfigError::ZeroMaxNeighbors => {
+                write!(f, "centroids_max_neighbors must be positive")
+            }
+            SpannConfigError::EfConstructionTooSmall {
+                ef_construction,
+                max_neighbors,
+            } => write!(
+                f,
+                "centroids_ef_construction ({}) must be at least centroids_max_neighbors ({})",
+                ef_construction, max_neighbors
+            ),
+            SpannConfigError::ZeroFileSize { field } => write!(f, "{} must be positive", field),
+        }
+    }
 }
 
 impl SpannBuilderConfig {
+    /// Checks the config for values that would make the build fail, and returns all violations.
+    pub fn validate(&self) -> std::result::Result<(), Vec<SpannConfigError>> {
+        let mut errors = vec![];
+        if self.num_features == 0 {
+            errors.push(SpannConfigError::ZeroNumFeatures);
+        }
+        if self.quantizer_type == QuantizerType::ProductQuantizer
+            && (self.pq_subvector_dimension == 0
+                || !self
+                    .num_features
+                    .is_multiple_of(self.pq_subvector_dimension))
+        {
+            errors.push(SpannConfigError::SubvectorDimensionNotDivisor {
+                num_features: self.num_features,
+                subvector_dimension: self.pq_subvector_dimension,
+            });
+        }
+        if self.ivf_num_clusters > self.ivf_num_data_points_for_clustering {
+            errors.push(SpannConfigError::TooManyClusters {
+                num_clusters: self.ivf_num_clusters,
+                num_data_points_for_clustering: self.ivf_num_data_points_for_clustering,
+            });
+        }
+        if self.centroids_max_neighbors == 0 {
+            errors.push(SpannConfigError::ZeroMaxNeighbors);
+        }
+        if (self.centroids_ef_construction as usize) < self.centroids_max_neighbors {
+            errors.push(SpannConfigError::EfConstructionTooSmall {
+                ef_construction: self.centroids_ef_construction,
+                max_neighbors: self.centroids_max_neighbors,
+            });
+        }
+        if self.centroids_vector_storage_file_size == 0 {
+            errors.push(SpannConfigError::ZeroFileSize {
+                field: "centroids_vector_storage_file_size",
+            });
+        }
+        if self.ivf_vector_storage_file_size == 0 {
+            errors.push(SpannConfigError::ZeroFileSize {
+                field: "ivf_vector_storage_file_size",
+            });
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     pub fn from_collection_config(
         collection_config: &CollectionConfig,
         base_directory: String,
@@ -86,6 +208,7 @@ impl SpannBuilderConfig {
             ivf_max_posting_list_size: collection_config.max_posting_list_size,
 
             reindex: collection_config.reindex,
+            centroid_graph_path: None,
         }
     }
 }
@@ -121,18 +244,42 @@ impl Default for SpannBuilderConfig {
             ivf_max_posting_list_size: usize::MAX,
 
             reindex: true,
+            centroid_graph_path: None,
         }
     }
 }
 
+/// Statistics about a SPANN build, written to `build_metrics.json` next to the index.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SpannBuildMetrics {
+    pub total_vectors: u64,
+    pub num_centroids: usize,
+    pub build_duration_ms: u64,
+    // Size of the posting list of each centroid, by centroid id
+    pub centroid_coverage: Vec<usize>,
+    // See `imbalance_coefficient`
+    pub imbalance_coefficient: f32,
+    // Stats of the centroid graph, starting from the bottom layer
+    pub hnsw_layer_stats: Vec<LayerStats>,
+}
+
 pub struct SpannBuilder {
     pub config: SpannBuilderConfig,
     pub ivf_builder: IvfBuilder<L2DistanceCalculator>,
     pub centroid_builder: HnswBuilder<NoQuantizer<L2DistanceCalculator>>,
+    build_metrics: SpannBuildMetrics,
 }
 
 impl SpannBuilder {
     pub fn new(config: SpannBuilderConfig) -> Result<Self> {
+        if let Err(errors) = config.validate() {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(anyhow!(
+                "Invalid SPANN builder config: {}",
+                messages.join("; ")
+            ));
+        }
+
         let ivf_builder = IvfBuilder::<L2DistanceCalculator>::new(IvfBuilderConfig {
             max_iteration: config.pq_max_iteration,
             batch_size: config.pq_batch_size,
@@ -145,7 +292,10 @@ impl SpannBuilder {
             file_size: config.ivf_vector_storage_file_size,
             num_features: config.num_features,
             tolerance: config.centroids_clustering_tolerance,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: config.ivf_max_posting_list_size,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
         })?;
 
         let centroid_directory = format!("{}/centroids", config.ivf_base_directory.clone());
@@ -170,18 +320,64 @@ impl SpannBuilder {
             config,
             ivf_builder,
             centroid_builder,
+            build_metrics: SpannBuildMetrics::default(),
         })
     }
 
+    /// Disables the NaN/Inf check on added vectors, for inputs that are known to be valid.
+    pub fn set_skip_vector_validation(&mut self, skip_vector_validation: bool) {
+        self.ivf_builder
+            .set_skip_vector_validation(skip_vector_validation);
+    }
+
     #[allow(unused_variables)]
     pub fn add(&mut self, doc_id: u128, data: &[f32]) -> Result<()> {
         self.ivf_builder.add_vector(doc_id, data)
     }
 
     pub fn build(&mut self) -> Result<()> {
+        let start = Instant::now();
+        if let Some(centroid_graph_path) = self.config.centroid_graph_path.clone() {
+            self.build_from_centroid_graph(centroid_graph_path)?;
+        } else {
+            self.build_clusters()?;
+            self.build_centroid_graph()?;
+        }
+        self.build_metrics = self.compute_build_metrics(start.elapsed())?;
+        Ok(())
+    }
+
+    /// Metrics of the last `build`. All zeros if `build` was not called.
+    pub fn build_metrics(&self) -> SpannBuildMetrics {
+        self.build_metrics.clone()
+    }
+
+    fn compute_build_metrics(&self, build_duration: Duration) -> Result<SpannBuildMetrics> {
+        let posting_lists = self.ivf_builder.posting_lists();
+        let mut centroid_coverage = Vec::with_capacity(posting_lists.len());
+        for i in 0..posting_lists.len() {
+            centroid_coverage.push(posting_lists.get(i as u32)?.iter().count());
+        }
+        Ok(SpannBuildMetrics {
+            total_vectors: self.ivf_builder.vectors().borrow().len() as u64,
+            num_centroids: self.ivf_builder.centroids().borrow().len(),
+            build_duration_ms: build_duration.as_millis() as u64,
+            imbalance_coefficient: imbalance_coefficient(&centroid_coverage),
+            centroid_coverage,
+            hnsw_layer_stats: self.centroid_builder.layer_stats(),
+        })
+    }
+
+    /// Clusters the added vectors. First step of `build`, when no centroid graph is given.
+    pub fn build_clusters(&mut self) -> Result<()> {
         self.ivf_builder.build()?;
         debug!("Finish building IVF index");
+        Ok(())
+    }
 
+    /// Inserts the centroids found by `build_clusters` in the centroid graph. Second step of
+    /// `build`, when no centroid graph is given.
+    pub fn build_centroid_graph(&mut self) -> Result<()> {
         let centroid_storage = self.ivf_builder.centroids();
         let num_centroids = centroid_storage.borrow().len();
 
@@ -192,13 +388,139 @@ impl SpannBuilder {
         debug!("Finish building centroids");
         Ok(())
     }
+
+    /// Loads the centroids and their HNSW graph from `centroid_graph_path`, then assigns the
+    /// vectors to these centroids.
+    fn build_from_centroid_graph(&mut self, centroid_graph_path: String) -> Result<()> {
+        let hnsw = HnswReader::new(centroid_graph_path.clone())
+            .read::<NoQuantizer<L2DistanceCalculator>>()?;
+        if hnsw.get_header().quantized_dimension as usize != self.config.num_features {
+            return Err(anyhow!(
+                "Centroids in {} have dimension {}, expected {}",
+                centroid_graph_path,
+                hnsw.get_header().quantized_dimension,
+                self.config.num_features
+            ));
+        }
+
+        // Centroid ids are the doc ids of the graph points, and need to match the posting list ids.
+        let doc_id_mapping = hnsw.get_doc_id_mapping_slice();
+        let mut point_ids = vec![None; doc_id_mapping.len()];
+        for (point_id, centroid_id) in doc_id_mapping.iter().enumerate() {
+            let slot = point_ids
+                .get_mut(*centroid_id as usize)
+                .ok_or_else(|| anyhow!("Centroid id {} is out of range", centroid_id))?;
+            *slot = Some(point_id);
+        }
+        let mut context = SearchContext::new(false);
+        for (centroid_id, point_id) in point_ids.into_iter().enumerate() {
+            let point_id =
+                point_id.ok_or_else(|| anyhow!("Centroid {} is missing", centroid_id))?;
+            let centroid = hnsw
+                .vector_storage
+                .get(point_id, &mut context)
+                .ok_or_else(|| anyhow!("Failed to read centroid {}", centroid_id))?;
+            self.ivf_builder.add_centroid(centroid)?;
+        }
+        debug!(
+            "Loaded {} centroids from {}",
+            doc_id_mapping.len(),
+            centroid_graph_path
+        );
+
+        self.ivf_builder.build_posting_lists()?;
+        debug!("Finish building IVF index");
+
+        let hnsw_directory = format!("{}/centroids/hnsw", self.config.ivf_base_directory);
+        self.centroid_builder = HnswBuilder::from_hnsw(
+            hnsw,
+            hnsw_directory,
+            VectorStorageConfig {
+                memory_threshold: self.config.ivf_vector_storage_memory_size,
+                file_size: self.config.ivf_vector_storage_file_size,
+                num_features: self.config.num_features,
+            },
+            self.config.centroids_max_neighbors,
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use config::collection::CollectionConfig;
+    use config::enums::QuantizerType;
+    use quantization::noq::noq::NoQuantizer;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::test_utils::generate_random_vector;
+
+    use crate::spann::builder::{SpannBuilder, SpannBuilderConfig, SpannConfigError};
+    use crate::spann::reader::SpannReader;
+    use crate::spann::writer::SpannWriter;
+
+    #[test]
+    fn test_validate_config() {
+        assert!(SpannBuilderConfig::default().validate().is_ok());
+
+        let config = SpannBuilderConfig {
+            num_features: 10,
+            pq_subvector_dimension: 4,
+            quantizer_type: QuantizerType::ProductQuantizer,
+            ivf_num_clusters: 100,
+            ivf_num_data_points_for_clustering: 10,
+            centroids_max_neighbors: 0,
+            ivf_vector_storage_file_size: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(vec![
+                SpannConfigError::SubvectorDimensionNotDivisor {
+                    num_features: 10,
+                    subvector_dimension: 4,
+                },
+                SpannConfigError::TooManyClusters {
+                    num_clusters: 100,
+                    num_data_points_for_clustering: 10,
+                },
+                SpannConfigError::ZeroMaxNeighbors,
+                SpannConfigError::ZeroFileSize {
+                    field: "ivf_vector_storage_file_size",
+                },
+            ])
+        );
 
-    use crate::spann::builder::SpannBuilderConfig;
+        let config = SpannBuilderConfig {
+            num_features: 0,
+            centroids_max_neighbors: 10,
+            centroids_ef_construction: 5,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(vec![
+                SpannConfigError::ZeroNumFeatures,
+                SpannConfigError::EfConstructionTooSmall {
+                    ef_construction: 5,
+                    max_neighbors: 10,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_new_with_invalid_config() {
+        let temp_dir = tempdir::TempDir::new("test_new_with_invalid_config").unwrap();
+        let config = SpannBuilderConfig {
+            ivf_base_directory: temp_dir.path().to_str().unwrap().to_string(),
+            num_features: 0,
+            centroids_max_neighbors: 0,
+            ..Default::default()
+        };
+        let err = SpannBuilder::new(config).err().unwrap().to_string();
+        assert!(err.contains("num_features must be positive"));
+        assert!(err.contains("centroids_max_neighbors must be positive"));
+    }
 
     #[test]
     fn test_read_write_config() {
@@ -221,5 +543,83 @@ mod tests {
         let read_collection_config: SpannBuilderConfig =
             serde_json::from_reader(File::open(collection_config_path).unwrap()).unwrap();
         assert_eq!(collection_config, read_collection_config);
+
+        // `centroid_hnsw_path` is accepted as another name for `centroid_graph_path`
+        let json = serde_json::to_string(&collection_config).unwrap().replace(
+            "\"centroid_graph_path\":null",
+            "\"centroid_hnsw_path\":\"/path/to/centroids\"",
+        );
+        let read_collection_config: SpannBuilderConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            read_collection_config.centroid_graph_path,
+            Some("/path/to/centroids".to_string())
+        );
+    }
+
+    #[test]
+    fn test_build_from_centroid_graph() {
+        let temp_dir = tempdir::TempDir::new("test_build_from_centroid_graph").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let config = |directory: &str, centroid_graph_path: Option<String>| SpannBuilderConfig {
+            num_features: 4,
+            ivf_num_clusters: 10,
+            ivf_base_directory: format!("{}/{}", base_directory, directory),
+            ivf_vector_storage_file_size: 4096,
+            centroids_vector_storage_file_size: 4096,
+            reindex: false,
+            centroid_graph_path,
+            ..Default::default()
+        };
+
+        let mut first = SpannBuilder::new(config("first", None)).unwrap();
+        for i in 0..1000 {
+            first.add(i as u128, &generate_random_vector(4)).unwrap();
+        }
+        first.build().unwrap();
+        SpannWriter::new(format!("{}/first", base_directory))
+            .write(&mut first)
+            .unwrap();
+        let num_centroids = first.ivf_builder.centroids().borrow().len();
+
+        let mut second = SpannBuilder::new(config(
+            "second",
+            Some(format!("{}/first/centroids", base_directory)),
+        ))
+        .unwrap();
+        for i in 0..500 {
+            second.add(i as u128, &generate_random_vector(4)).unwrap();
+        }
+        second.build().unwrap();
+
+        // Same centroids, in the same order.
+        let first_centroids = first.ivf_builder.centroids().borrow();
+        let second_centroids = second.ivf_builder.centroids().borrow();
+        assert_eq!(second_centroids.len(), num_centroids);
+        for i in 0..num_centroids as u32 {
+            assert_eq!(
+                first_centroids.get(i).unwrap(),
+                second_centroids.get(i).unwrap()
+            );
+        }
+        drop(second_centroids);
+        assert_eq!(second.ivf_builder.posting_lists().len(), num_centroids);
+        assert_eq!(second.centroid_builder.doc_id_mapping.len(), num_centroids);
+
+        SpannWriter::new(format!("{}/second", base_directory))
+            .write(&mut second)
+            .unwrap();
+        let spann = SpannReader::new(format!("{}/second", base_directory))
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .unwrap();
+        assert_eq!(spann.get_posting_lists().num_clusters, num_centroids);
+
+        // Dimension mismatch
+        let mut mismatched = SpannBuilder::new(SpannBuilderConfig {
+            num_features: 8,
+            ..config("third", Some(format!("{}/first/centroids", base_directory)))
+        })
+        .unwrap();
+        mismatched.add(0, &generate_random_vector(8)).unwrap();
+        assert!(mismatched.build().is_err());
     }
 }
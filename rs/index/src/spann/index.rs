@@ -7,7 +7,7 @@ use quantization::quantization::Quantizer;
 use utils::distance::l2::L2DistanceCalculator;
 
 use crate::hnsw::index::Hnsw;
-use crate::index::Searchable;
+use crate::index::{DocIdFilter, FilteredSearch, Searchable};
 use crate::ivf::index::Ivf;
 
 pub struct Spann<Q: Quantizer> {
@@ -33,18 +33,35 @@ impl<Q: Quantizer> Spann<Q> {
     pub fn get_posting_lists(&self) -> &Ivf<Q, L2DistanceCalculator, PlainDecoder> {
         &self.posting_lists
     }
+
+    /// Returns the number of vectors in the posting lists.
+    pub fn num_vectors(&self) -> usize {
+        self.posting_lists.index_storage.header().num_vectors as usize
+    }
+
+    /// Returns a multi-line, human-readable summary of the centroid graph and posting lists.
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        description.push_str("SPANN index\n");
+        description.push_str("Centroids: ");
+        description.push_str(&self.centroids.describe());
+        description.push_str("Posting lists: ");
+        description.push_str(&self.posting_lists.describe());
+        description
+    }
 }
 
-impl<Q: Quantizer> Searchable for Spann<Q> {
-    fn search(
+impl<Q: Quantizer> Spann<Q> {
+    fn search_impl(
         &self,
         query: &[f32],
         k: usize,
-        ef_construction: u32,
+        ef_search: u32,
+        filter: Option<&dyn Fn(u128) -> bool>,
         context: &mut crate::utils::SearchContext,
-    ) -> Option<Vec<crate::utils::IdWithScore>> {
+    ) -> Option<crate::utils::SearchResult> {
         // TODO(hicder): Fully implement SPANN, which includes adjusting number of centroids
-        match self.centroids.search(query, k, ef_construction, context) {
+        match self.centroids.search(query, k, ef_search, context) {
             Some(nearest_centroids) => {
                 if nearest_centroids.is_empty() {
                     return None;
@@ -74,15 +91,53 @@ impl<Q: Quantizer> Searchable for Spann<Q> {
                     query,
                     nearest_centroid_ids,
                     k,
+                    filter,
                     context,
                 );
-                Some(results)
+                Some(results.into())
             }
             None => None,
         }
     }
 }
 
+impl<Q: Quantizer> Searchable for Spann<Q> {
+    fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        context: &mut crate::utils::SearchContext,
+    ) -> Option<crate::utils::SearchResult> {
+        self.search_impl(query, k, ef_search, None, context)
+    }
+
+    fn search_with_filter(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        filter: &DocIdFilter,
+        context: &mut crate::utils::SearchContext,
+    ) -> Option<crate::utils::SearchResult> {
+        self.search_impl(query, k, ef_search, Some(&**filter), context)
+    }
+}
+
+impl<Q: Quantizer> FilteredSearch for Spann<Q> {
+    fn search_filtered(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: u32,
+        context: &mut crate::utils::SearchContext,
+        filter: &dyn Fn(u128) -> bool,
+    ) -> Option<Vec<crate::utils::IdWithScore>> {
+        self.search_impl(query, k, ef_search, Some(filter), context)
+            .map(|result| result.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use config::enums::{IntSeqEncodingType, QuantizerType};
@@ -136,6 +191,7 @@ mod tests {
             centroids_clustering_tolerance: balance_factor,
             ivf_max_posting_list_size: max_posting_list_size,
             reindex: false,
+            centroid_graph_path: None,
         })
         .unwrap();
 
@@ -166,6 +222,23 @@ mod tests {
         assert_eq!(results.len(), k);
         assert_eq!(results[0].id, 4); // Closest to [4.0, 4.0, 4.0, 4.0]
         assert_eq!(results[1].id, 3); // Next is [3.0, 3.0, 3.0, 3.0]
+
+        // Only even doc ids
+        let k = 5;
+        let results = spann
+            .search_filtered(&query, k, num_probes, &mut context, &|doc_id| {
+                doc_id % 2 == 0
+            })
+            .expect("SPANN search should return a result");
+        assert_eq!(results.len(), k);
+        assert!(results.iter().all(|result| result.id % 2 == 0));
+        assert_eq!(results[0].id, 4);
+
+        let description = spann.describe();
+        assert!(description.contains("HNSW index"));
+        assert!(description.contains("layer 0:"));
+        assert!(description.contains("IVF index"));
+        assert!(description.contains("vectors: 1000"));
     }
 
     #[test]
@@ -208,6 +281,7 @@ mod tests {
             centroids_clustering_tolerance: balance_factor,
             ivf_max_posting_list_size: max_posting_list_size,
             reindex: false,
+            centroid_graph_path: None,
         })
         .unwrap();
 
@@ -236,11 +310,11 @@ mod tests {
             .expect("IVF search should return a result");
 
         assert_eq!(results.len(), k);
-        // subvector_dimension = 2 so very lossy!
-        assert_eq!(results[0].score, 0.0);
-        assert_eq!(results[1].score, 0.0);
-        assert_eq!(results[2].score, 0.0);
-        assert_eq!(results[3].score, 0.0);
-        assert_eq!(results[4].score, 0.0);
+        // subvector_dimension = 2 so very lossy! The nearest points share the same code, so they
+        // are all at the same distance from the query.
+        assert_eq!(results[1].score, results[0].score);
+        assert_eq!(results[2].score, results[0].score);
+        assert_eq!(results[3].score, results[0].score);
+        assert_eq!(results[4].score, results[0].score);
     }
 }
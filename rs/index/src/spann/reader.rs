@@ -1,5 +1,9 @@
-use anyhow::Result;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
 use compression::noc::noc::PlainDecoder;
+use config::enums::DistanceType;
 use quantization::noq::noq::NoQuantizer;
 use quantization::quantization::Quantizer;
 use utils::distance::l2::L2DistanceCalculator;
@@ -7,6 +11,8 @@ use utils::distance::l2::L2DistanceCalculator;
 use super::index::Spann;
 use crate::hnsw::reader::HnswReader;
 use crate::ivf::reader::IvfReader;
+use crate::spann::builder::SpannBuilderConfig;
+use crate::spann::writer::SPANN_BUILDER_CONFIG_FILE;
 
 pub struct SpannReader {
     base_directory: String,
@@ -14,6 +20,8 @@ pub struct SpannReader {
     centroids_vector_offset: usize,
     ivf_index_offset: usize,
     ivf_vector_offset: usize,
+    distance_metric: DistanceType,
+    vector_cache_capacity: Option<usize>,
 }
 
 impl SpannReader {
@@ -24,6 +32,8 @@ impl SpannReader {
             centroids_vector_offset: 0,
             ivf_index_offset: 0,
             ivf_vector_offset: 0,
+            distance_metric: DistanceType::L2,
+            vector_cache_capacity: None,
         }
     }
 
@@ -40,10 +50,42 @@ impl SpannReader {
             centroids_vector_offset,
             ivf_index_offset,
             ivf_vector_offset,
+            distance_metric: DistanceType::L2,
+            vector_cache_capacity: None,
+        }
+    }
+
+    /// Sets the distance metric the index is searched with. Defaults to L2.
+    pub fn with_distance_metric(mut self, distance_metric: DistanceType) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    /// Sets the capacity of the vector cache of the posting lists, see
+    /// `Ivf::set_vector_cache_capacity`. Defaults to None, which disables the cache.
+    pub fn with_vector_cache_capacity(mut self, vector_cache_capacity: Option<usize>) -> Self {
+        self.vector_cache_capacity = vector_cache_capacity;
+        self
+    }
+
+    /// Returns the config the index was built with, or None for indexes written without it.
+    pub fn read_builder_config(&self) -> Result<Option<SpannBuilderConfig>> {
+        let config_path = format!("{}/{}", self.base_directory, SPANN_BUILDER_CONFIG_FILE);
+        if !Path::new(&config_path).exists() {
+            return Ok(None);
         }
+        Ok(Some(serde_json::from_reader(File::open(config_path)?)?))
     }
 
     pub fn read<Q: Quantizer>(&self) -> Result<Spann<Q>> {
+        // Centroids and posting lists are only searched with L2 distance so far
+        if self.distance_metric != DistanceType::L2 {
+            return Err(anyhow!(
+                "{:?} distance is not supported for SPANN indexes",
+                self.distance_metric
+            ));
+        }
+
         let posting_list_path = format!("{}/ivf", self.base_directory);
         let centroid_path = format!("{}/centroids", self.base_directory);
 
@@ -53,11 +95,25 @@ impl SpannReader {
             self.centroids_vector_offset,
         )
         .read::<NoQuantizer<L2DistanceCalculator>>()?;
-        let posting_lists = IvfReader::new_with_offset(
+
+        // Centroids are not quantized, so their dimension is the number of features
+        if let Some(config) = self.read_builder_config()? {
+            let dimension = centroids.get_header().quantized_dimension as usize;
+            if config.num_features != dimension {
+                return Err(anyhow!(
+                    "SPANN builder config has {} features, but the index has {}",
+                    config.num_features,
+                    dimension
+                ));
+            }
+        }
+
+        let posting_lists = IvfReader::new_with_offsets(
             posting_list_path,
             self.ivf_index_offset,
             self.ivf_vector_offset,
         )
+        .with_vector_cache_capacity(self.vector_cache_capacity)
         .read::<Q, L2DistanceCalculator, PlainDecoder>()?;
 
         Ok(Spann::<_>::new(centroids, posting_lists))
@@ -111,6 +167,7 @@ mod tests {
             centroids_clustering_tolerance: balance_factor,
             ivf_max_posting_list_size: max_posting_list_size,
             reindex: false,
+            centroid_graph_path: None,
         })
         .unwrap();
 
@@ -135,6 +192,28 @@ mod tests {
             posting_lists.num_clusters,
             centroids.vector_storage.num_vectors
         );
+        assert_eq!(
+            spann_reader.read_builder_config().unwrap(),
+            Some(builder.config.clone())
+        );
+
+        // A config that doesn't match the index is rejected
+        let config_path = format!("{}/{}", base_directory, SPANN_BUILDER_CONFIG_FILE);
+        let config = SpannBuilderConfig {
+            num_features: num_features + 1,
+            ..builder.config.clone()
+        };
+        serde_json::to_writer(File::create(&config_path).unwrap(), &config).unwrap();
+        assert!(spann_reader
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .is_err());
+
+        // Indexes written without a config can still be read
+        std::fs::remove_file(&config_path).unwrap();
+        assert_eq!(spann_reader.read_builder_config().unwrap(), None);
+        assert!(spann_reader
+            .read::<NoQuantizer<L2DistanceCalculator>>()
+            .is_ok());
     }
 
     #[test]
@@ -171,6 +250,7 @@ mod tests {
             centroids_clustering_tolerance: balance_factor,
             ivf_max_posting_list_size: max_posting_list_size,
             reindex: false,
+            centroid_graph_path: None,
         })
         .unwrap();
 
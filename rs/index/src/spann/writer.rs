@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use compression::noc::noc::PlainEncoder;
 use config::enums::QuantizerType;
 use log::debug;
@@ -15,6 +15,11 @@ use crate::ivf::builder::IvfBuilder;
 use crate::ivf::writer::IvfWriter;
 use crate::spann::builder::SpannBuilderConfig;
 
+// Name of the file holding the `SpannBuilderConfig` the index was built with
+pub const SPANN_BUILDER_CONFIG_FILE: &str = "spann_builder_config.json";
+// Name of the file holding the `SpannBuildMetrics` of the build
+pub const SPANN_BUILD_METRICS_FILE: &str = "build_metrics.json";
+
 pub struct SpannWriter {
     base_directory: String,
 }
@@ -47,6 +52,7 @@ impl SpannWriter {
         let pq_builder_config = ProductQuantizerBuilderConfig {
             max_iteration: index_writer_config.pq_max_iteration,
             batch_size: index_writer_config.pq_batch_size,
+            use_annealing: false,
         };
 
         let mut pq_builder =
@@ -145,8 +151,28 @@ impl SpannWriter {
                     &mut spann_builder.ivf_builder,
                 )?;
             }
+            QuantizerType::ResidualQuantizer
+            | QuantizerType::Sq4
+            | QuantizerType::ScalarQuantizer
+            | QuantizerType::BinaryQuantizer
+            | QuantizerType::RandomProjection { .. } => {
+                return Err(anyhow!(
+                    "{:?} is not supported for SPANN",
+                    index_writer_config.quantizer_type
+                ));
+            }
         };
 
+        // Keep the config next to the index, so that readers can check it against the index
+        let config_path = format!("{}/{}", self.base_directory, SPANN_BUILDER_CONFIG_FILE);
+        serde_json::to_writer_pretty(std::fs::File::create(config_path)?, index_writer_config)?;
+
+        let metrics_path = format!("{}/{}", self.base_directory, SPANN_BUILD_METRICS_FILE);
+        serde_json::to_writer_pretty(
+            std::fs::File::create(metrics_path)?,
+            &spann_builder.build_metrics(),
+        )?;
+
         Ok(())
     }
 }
@@ -160,7 +186,7 @@ mod tests {
     use utils::test_utils::generate_random_vector;
 
     use super::*;
-    use crate::spann::builder::SpannBuilderConfig;
+    use crate::spann::builder::{SpannBuildMetrics, SpannBuilderConfig};
 
     #[test]
     fn test_write() {
@@ -197,6 +223,7 @@ mod tests {
             centroids_clustering_tolerance: balance_factor,
             ivf_max_posting_list_size: max_posting_list_size,
             reindex: false,
+            centroid_graph_path: None,
         })
         .unwrap();
 
@@ -229,5 +256,20 @@ mod tests {
         assert!(PathBuf::from(&ivf_directory_path).exists());
         assert!(PathBuf::from(&ivf_vector_storage_path).exists());
         assert!(PathBuf::from(&ivf_index_path).exists());
+
+        let metrics_path = format!("{}/{}", base_directory, SPANN_BUILD_METRICS_FILE);
+        let metrics: SpannBuildMetrics =
+            serde_json::from_reader(std::fs::File::open(metrics_path).unwrap()).unwrap();
+        assert_eq!(metrics, builder.build_metrics());
+        assert_eq!(metrics.total_vectors, num_vectors as u64);
+        assert_eq!(metrics.centroid_coverage.len(), metrics.num_centroids);
+        // Each vector is assigned to exactly one cluster
+        assert_eq!(metrics.centroid_coverage.iter().sum::<usize>(), num_vectors);
+        assert!(metrics.imbalance_coefficient >= 1.0);
+        assert_eq!(
+            metrics.hnsw_layer_stats.len(),
+            builder.centroid_builder.layers.len()
+        );
+        assert!(metrics.hnsw_layer_stats[0].num_points <= metrics.num_centroids);
     }
 }
@@ -1,32 +1,162 @@
 use std::cmp::{Ord, Ordering};
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::num::NonZeroUsize;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use lru::LruCache;
 use ordered_float::NotNan;
+use quantization::pq::pq::AdcTable;
+use quantization::quantization::Quantizer;
 use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
 
 pub struct SearchContext {
     pub visited: RoaringBitmap,
+
+    // When set, every page touched during the search is recorded, so that
+    // `num_pages_accessed` can be reported as a metric. It does not cache anything.
     pub record_pages: bool,
     pub visited_pages: Option<HashSet<String>>,
+
+    // Decoded posting lists, kept across searches that reuse this context.
+    pub posting_list_cache: Option<PostingListCache>,
+
+    // ADC table of the latest query, reused when the same query is searched again.
+    pub pq_table_cache: Option<AdcTableCache>,
+
+    // Vectors read while scanning posting lists, reused when a vector is scanned again (e.g. it
+    // belongs to several probed clusters).
+    pub vector_cache: Option<VectorCache>,
+
+    // When set, a fraction of the searches are compared against their exact results to track
+    // recall. Clones of a sampler share the running mean, so it can be set on several contexts.
+    pub recall_sampler: Option<RecallSampler>,
+
+    // Counters of the work done by the searches made with this context.
+    pub stats: SearchStats,
+
+    // Searches stop early once `timeout` has passed since it was set, see `set_timeout`.
+    pub timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    // Set when a search stopped early because of the timeout. Its results are partial.
+    pub timed_out: bool,
+}
+
+/// Counters accumulated across the searches made with the same `SearchContext`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchStats {
+    // Number of vectors scored while scanning posting lists
+    pub num_candidates_scanned: u64,
 }
 
 impl SearchContext {
+    /// `record_pages` enables recording the pages accessed during the search (for metrics).
     pub fn new(record_pages: bool) -> Self {
         if !record_pages {
             Self {
                 visited: RoaringBitmap::new(),
                 record_pages: false,
                 visited_pages: None,
+                posting_list_cache: None,
+                pq_table_cache: None,
+                vector_cache: None,
+                recall_sampler: None,
+                stats: SearchStats::default(),
+                timeout: None,
+                deadline: None,
+                timed_out: false,
             }
         } else {
             Self {
                 visited: RoaringBitmap::new(),
                 record_pages: true,
                 visited_pages: Some(HashSet::new()),
+                posting_list_cache: None,
+                pq_table_cache: None,
+                vector_cache: None,
+                recall_sampler: None,
+                stats: SearchStats::default(),
+                timeout: None,
+                deadline: None,
+                timed_out: false,
             }
         }
     }
 
+    /// Create a context that caches decoded posting lists, using up to `capacity_bytes` of
+    /// memory. Useful when the same context is reused for many queries probing the same clusters.
+    pub fn new_with_cache_size(capacity_bytes: usize) -> Self {
+        let mut context = Self::new(false);
+        context.posting_list_cache = Some(PostingListCache::new(capacity_bytes));
+        context
+    }
+
+    /// Create a context that caches the vectors read during posting list scans, evicting them
+    /// according to `policy`.
+    pub fn with_cache(policy: CachePolicy) -> Self {
+        let mut context = Self::new(false);
+        context.vector_cache = VectorCache::new(policy);
+        context
+    }
+
+    /// Create a context that keeps the ADC table of the latest query. Useful when the same context
+    /// is reused for repeated queries against a PQ-quantized index.
+    pub fn new_with_pq_table_cache() -> Self {
+        let mut context = Self::new(false);
+        context.pq_table_cache = Some(AdcTableCache::new());
+        context
+    }
+
+    /// Returns the ADC table of `query` for `quantizer`, from the cache when enabled and the same
+    /// query was searched last.
+    pub fn get_or_compute_adc_table<Q: Quantizer>(
+        &mut self,
+        query: &[f32],
+        quantizer: &Q,
+    ) -> Option<Arc<AdcTable>> {
+        match self.pq_table_cache.as_mut() {
+            Some(cache) => cache.get_or_compute(query, quantizer),
+            None => quantizer.adc_table(query).map(Arc::new),
+        }
+    }
+
+    /// Compare a `sample_rate` fraction of the searches made with this context against the exact
+    /// top-k ids returned by `ground_truth_fn`. See `mean_recall`.
+    pub fn enable_recall_sampling(&mut self, sample_rate: f32, ground_truth_fn: GroundTruthFn) {
+        self.recall_sampler = Some(RecallSampler::new(sample_rate, ground_truth_fn));
+    }
+
+    /// Mean Jaccard overlap between sampled results and their ground truth, or None if recall
+    /// sampling is disabled or no search was sampled yet.
+    pub fn mean_recall(&self) -> Option<f32> {
+        self.recall_sampler.as_ref().and_then(|s| s.mean_recall())
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    /// Stops the searches made with this context once `timeout` has passed from now. They then
+    /// return the results found so far, and set `timed_out`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+        self.deadline = Instant::now().checked_add(timeout);
+    }
+
+    /// Returns true once the timeout has passed, and records it in `timed_out`.
+    pub fn is_timed_out(&mut self) -> bool {
+        if !self.timed_out
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.timed_out = true;
+        }
+        self.timed_out
+    }
+
     pub fn num_pages_accessed(&self) -> usize {
         if !self.record_pages {
             return 0;
@@ -34,6 +164,354 @@ impl SearchContext {
 
         self.visited_pages.as_ref().unwrap().len()
     }
+
+    pub fn cache_hits(&self) -> u64 {
+        self.posting_list_cache.as_ref().map_or(0, |c| c.hits)
+    }
+
+    pub fn cache_misses(&self) -> u64 {
+        self.posting_list_cache.as_ref().map_or(0, |c| c.misses)
+    }
+
+    /// Number of vectors found in the vector cache. See `with_cache`.
+    pub fn cache_hit_count(&self) -> u64 {
+        self.vector_cache.as_ref().map_or(0, |c| c.hits)
+    }
+
+    /// Number of vectors looked up in the vector cache but read from storage.
+    pub fn cache_miss_count(&self) -> u64 {
+        self.vector_cache.as_ref().map_or(0, |c| c.misses)
+    }
+}
+
+/// Returns the exact top-k doc ids of a query.
+pub type GroundTruthFn = Arc<dyn Fn(&[f32], usize) -> Vec<u64> + Send + Sync>;
+
+/// Samples searches and measures their recall against a ground truth.
+#[derive(Clone)]
+pub struct RecallSampler {
+    sample_rate: f32,
+    ground_truth_fn: GroundTruthFn,
+    // (number of sampled searches, sum of their recalls), shared between clones
+    stats: Arc<Mutex<(u64, f64)>>,
+}
+
+impl RecallSampler {
+    pub fn new(sample_rate: f32, ground_truth_fn: GroundTruthFn) -> Self {
+        Self {
+            sample_rate,
+            ground_truth_fn,
+            stats: Arc::new(Mutex::new((0, 0.0))),
+        }
+    }
+
+    pub fn should_sample(&self) -> bool {
+        rand::random::<f32>() < self.sample_rate
+    }
+
+    /// Computes the ground truth of `query` and records the Jaccard overlap between it and
+    /// `doc_ids`. Returns the overlap.
+    pub fn record(&self, query: &[f32], k: usize, doc_ids: &[u128]) -> f32 {
+        let ground_truth = (self.ground_truth_fn)(query, k)
+            .into_iter()
+            .map(|id| id as u128)
+            .collect::<Vec<_>>();
+        let recall = jaccard_overlap(doc_ids, &ground_truth);
+        let mut stats = self.stats.lock().unwrap();
+        stats.0 += 1;
+        stats.1 += recall as f64;
+        recall
+    }
+
+    pub fn num_samples(&self) -> u64 {
+        self.stats.lock().unwrap().0
+    }
+
+    pub fn mean_recall(&self) -> Option<f32> {
+        let stats = self.stats.lock().unwrap();
+        if stats.0 == 0 {
+            return None;
+        }
+        Some((stats.1 / stats.0 as f64) as f32)
+    }
+}
+
+/// Size of the intersection over size of the union of two id sets. Two empty sets fully overlap.
+pub fn jaccard_overlap(a: &[u128], b: &[u128]) -> f32 {
+    let a = a.iter().collect::<HashSet<_>>();
+    let b = b.iter().collect::<HashSet<_>>();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+/// The most recently computed ADC table, along with the query and quantizer that produced it.
+pub struct AdcTableCache {
+    query: Vec<f32>,
+    // `Quantizer::instance_id` of the quantizer the table was computed with, so that segments
+    // with different codebooks searched with the same context don't share a table.
+    quantizer: Option<u64>,
+    table: Option<Arc<AdcTable>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl AdcTableCache {
+    pub fn new() -> Self {
+        Self {
+            query: vec![],
+            quantizer: None,
+            table: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Tables of quantizers without an instance id are computed on every call.
+    pub fn get_or_compute<Q: Quantizer>(
+        &mut self,
+        query: &[f32],
+        quantizer: &Q,
+    ) -> Option<Arc<AdcTable>> {
+        let quantizer_id = quantizer.instance_id();
+        if let Some(table) = &self.table {
+            if quantizer_id.is_some() && self.quantizer == quantizer_id && self.query == query {
+                self.hits += 1;
+                return Some(table.clone());
+            }
+        }
+
+        self.misses += 1;
+        let table = quantizer.adc_table(query).map(Arc::new);
+        self.query = query.to_vec();
+        self.quantizer = quantizer_id;
+        self.table = table.clone();
+        table
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+impl Default for AdcTableCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cache of decoded posting lists, bounded by the total size of the cached point ids.
+/// When full, the oldest entries are evicted first.
+pub struct PostingListCache {
+    capacity_bytes: usize,
+    size_bytes: usize,
+    entries: HashMap<String, Arc<Vec<u64>>>,
+    insertion_order: VecDeque<String>,
+    hits: u64,
+    misses: u64,
+}
+
+impl PostingListCache {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            capacity_bytes,
+            size_bytes: 0,
+            entries: HashMap::new(),
+            insertion_order: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<Arc<Vec<u64>>> {
+        match self.entries.get(key) {
+            Some(entry) => {
+                self.hits += 1;
+                Some(entry.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: String, point_ids: Arc<Vec<u64>>) {
+        let entry_size = Self::entry_size(&point_ids);
+        if entry_size > self.capacity_bytes || self.entries.contains_key(&key) {
+            return;
+        }
+
+        while self.size_bytes + entry_size > self.capacity_bytes {
+            match self.insertion_order.pop_front() {
+                Some(evicted) => {
+                    if let Some(evicted_ids) = self.entries.remove(&evicted) {
+                        self.size_bytes -= Self::entry_size(&evicted_ids);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        self.size_bytes += entry_size;
+        self.insertion_order.push_back(key.clone());
+        self.entries.insert(key, point_ids);
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn entry_size(point_ids: &[u64]) -> usize {
+        std::mem::size_of_val(point_ids)
+    }
+}
+
+/// Eviction policy of the vector cache of a `SearchContext`. Capacities are in number of vectors.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    None,
+    // Evicts the least recently used vector first
+    LRU(usize),
+    // Evicts the least frequently used vector first, the least recently used one among ties
+    LFU(usize),
+}
+
+// A cached vector: id of the storage it was read from, and its index in that storage. The id
+// tells apart the storages of different segments searched with the same context, and unlike the
+// address of a storage, is not reused by a storage opened after another one is dropped.
+pub type VectorCacheKey = (u64, u32);
+
+/// Cache of vectors read from storage, as raw bytes.
+pub struct VectorCache {
+    entries: VectorCacheEntries,
+    hits: u64,
+    misses: u64,
+}
+
+enum VectorCacheEntries {
+    Lru(LruCache<VectorCacheKey, Arc<Vec<u8>>>),
+    Lfu(LfuCache),
+}
+
+struct LfuCache {
+    capacity: usize,
+    // Key -> (vector, use count, last use)
+    entries: HashMap<VectorCacheKey, (Arc<Vec<u8>>, u64, u64)>,
+    // (use count, last use, key) of every entry, the next one to evict first
+    eviction_order: BTreeSet<(u64, u64, VectorCacheKey)>,
+    clock: u64,
+}
+
+impl LfuCache {
+    fn get(&mut self, key: &VectorCacheKey) -> Option<Arc<Vec<u8>>> {
+        let (vector, uses, last_use) = self.entries.get_mut(key)?;
+        self.eviction_order.remove(&(*uses, *last_use, *key));
+        self.clock += 1;
+        *uses += 1;
+        *last_use = self.clock;
+        self.eviction_order.insert((*uses, *last_use, *key));
+        Some(vector.clone())
+    }
+
+    fn insert(&mut self, key: VectorCacheKey, vector: Arc<Vec<u8>>) {
+        if self.entries.contains_key(&key) {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some((_, _, evicted)) = self.eviction_order.pop_first() {
+                self.entries.remove(&evicted);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(key, (vector, 1, self.clock));
+        self.eviction_order.insert((1, self.clock, key));
+    }
+}
+
+impl VectorCache {
+    /// Returns None for `CachePolicy::None` and zero capacities, which cache nothing.
+    pub fn new(policy: CachePolicy) -> Option<Self> {
+        let entries = match policy {
+            CachePolicy::None => return None,
+            CachePolicy::LRU(capacity) => {
+                VectorCacheEntries::Lru(LruCache::new(NonZeroUsize::new(capacity)?))
+            }
+            CachePolicy::LFU(capacity) => {
+                if capacity == 0 {
+                    return None;
+                }
+                VectorCacheEntries::Lfu(LfuCache {
+                    capacity,
+                    entries: HashMap::new(),
+                    eviction_order: BTreeSet::new(),
+                    clock: 0,
+                })
+            }
+        };
+        Some(Self {
+            entries,
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    pub fn get(&mut self, key: &VectorCacheKey) -> Option<Arc<Vec<u8>>> {
+        let vector = match &mut self.entries {
+            VectorCacheEntries::Lru(cache) => cache.get(key).cloned(),
+            VectorCacheEntries::Lfu(cache) => cache.get(key),
+        };
+        match vector {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        vector
+    }
+
+    pub fn insert(&mut self, key: VectorCacheKey, vector: Arc<Vec<u8>>) {
+        match &mut self.entries {
+            VectorCacheEntries::Lru(cache) => {
+                cache.put(key, vector);
+            }
+            VectorCacheEntries::Lfu(cache) => cache.insert(key, vector),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match &mut self.entries {
+            VectorCacheEntries::Lru(cache) => cache.clear(),
+            VectorCacheEntries::Lfu(cache) => {
+                cache.entries.clear();
+                cache.eviction_order.clear();
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.entries {
+            VectorCacheEntries::Lru(cache) => cache.len(),
+            VectorCacheEntries::Lfu(cache) => cache.entries.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 pub trait TraversalContext {
@@ -41,6 +519,8 @@ pub trait TraversalContext {
     fn set_visited(&mut self, i: u32);
     fn should_record_pages(&self) -> bool;
     fn record_pages(&mut self, page_id: String);
+    /// Traversals stop early, with the points found so far, once this returns true.
+    fn is_timed_out(&mut self) -> bool;
 }
 
 impl TraversalContext for SearchContext {
@@ -64,6 +544,10 @@ impl TraversalContext for SearchContext {
             None => {}
         }
     }
+
+    fn is_timed_out(&mut self) -> bool {
+        SearchContext::is_timed_out(self)
+    }
 }
 
 #[derive(PartialEq, Eq, Ord, PartialOrd, Clone, Debug)]
@@ -99,7 +583,7 @@ impl PointAndDistance {
 //     }
 // }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IdWithScore {
     pub id: u128,
     pub score: f32,
@@ -140,10 +624,226 @@ impl PartialEq for IdWithScore {
 
 impl Eq for IdWithScore {}
 
+/// Results of a search, usually sorted by ascending score.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult(pub Vec<IdWithScore>);
+
+impl SearchResult {
+    pub fn new(results: Vec<IdWithScore>) -> Self {
+        Self(results)
+    }
+
+    pub fn into_doc_ids(self) -> Vec<u128> {
+        self.0.into_iter().map(|x| x.id).collect()
+    }
+
+    pub fn into_scores(self) -> Vec<f32> {
+        self.0.into_iter().map(|x| x.score).collect()
+    }
+
+    /// Keep only the results with score at most `max_dist`
+    pub fn filter_by_score(self, max_dist: f32) -> Self {
+        Self(self.0.into_iter().filter(|x| x.score <= max_dist).collect())
+    }
+
+    /// Keep the `k` results with the lowest scores, sorted
+    pub fn top_k(mut self, k: usize) -> Self {
+        self.0.sort();
+        self.0.truncate(k);
+        self
+    }
+}
+
+impl Deref for SearchResult {
+    type Target = Vec<IdWithScore>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for SearchResult {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Vec<IdWithScore>> for SearchResult {
+    fn from(results: Vec<IdWithScore>) -> Self {
+        Self(results)
+    }
+}
+
+impl FromIterator<IdWithScore> for SearchResult {
+    fn from_iter<I: IntoIterator<Item = IdWithScore>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for SearchResult {
+    type Item = IdWithScore;
+    type IntoIter = std::vec::IntoIter<IdWithScore>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use quantization::noq::noq::NoQuantizer;
+    use quantization::pq::pq::ProductQuantizer;
+    use utils::distance::l2::L2DistanceCalculator;
+
     use super::*;
 
+    #[test]
+    fn test_posting_list_cache() {
+        let mut cache = PostingListCache::new(32);
+        assert!(cache.get("a").is_none());
+
+        cache.insert("a".to_string(), Arc::new(vec![1, 2]));
+        cache.insert("b".to_string(), Arc::new(vec![3, 4]));
+        assert_eq!(cache.size_bytes(), 32);
+        assert_eq!(*cache.get("a").unwrap(), vec![1, 2]);
+
+        // Inserting "c" evicts "a", the oldest entry
+        cache.insert("c".to_string(), Arc::new(vec![5]));
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size_bytes(), 24);
+
+        // Entries larger than the capacity are not cached
+        cache.insert("d".to_string(), Arc::new(vec![0; 5]));
+        assert!(cache.get("d").is_none());
+
+        assert_eq!(cache.hits, 2);
+        assert_eq!(cache.misses, 3);
+    }
+
+    #[test]
+    fn test_vector_cache() {
+        assert!(VectorCache::new(CachePolicy::None).is_none());
+        assert!(VectorCache::new(CachePolicy::LRU(0)).is_none());
+        assert!(VectorCache::new(CachePolicy::LFU(0)).is_none());
+
+        // LRU evicts the entry used longest ago
+        let mut cache = VectorCache::new(CachePolicy::LRU(2)).unwrap();
+        cache.insert((0, 1), Arc::new(vec![1]));
+        cache.insert((0, 2), Arc::new(vec![2]));
+        assert_eq!(*cache.get(&(0, 1)).unwrap(), vec![1]);
+        cache.insert((0, 3), Arc::new(vec![3]));
+        assert!(cache.get(&(0, 2)).is_none());
+        assert!(cache.get(&(0, 1)).is_some());
+        assert_eq!(cache.len(), 2);
+        assert_eq!((cache.hits, cache.misses), (2, 1));
+
+        // LFU evicts the entry used the least, even if it was used recently
+        let mut cache = VectorCache::new(CachePolicy::LFU(2)).unwrap();
+        cache.insert((0, 1), Arc::new(vec![1]));
+        cache.insert((0, 2), Arc::new(vec![2]));
+        cache.get(&(0, 1));
+        cache.get(&(0, 1));
+        cache.get(&(0, 2));
+        cache.insert((0, 3), Arc::new(vec![3]));
+        assert!(cache.get(&(0, 2)).is_none());
+        assert!(cache.get(&(0, 1)).is_some());
+        assert_eq!(cache.len(), 2);
+
+        // Among entries used as often, the least recently used one is evicted
+        let mut cache = VectorCache::new(CachePolicy::LFU(2)).unwrap();
+        cache.insert((0, 1), Arc::new(vec![1]));
+        cache.insert((1, 1), Arc::new(vec![4]));
+        cache.insert((0, 3), Arc::new(vec![3]));
+        assert!(cache.get(&(0, 1)).is_none());
+        assert_eq!(*cache.get(&(1, 1)).unwrap(), vec![4]);
+    }
+
+    #[test]
+    fn test_adc_table_cache() {
+        let codebook = vec![0.0, 10.0, 0.0, 10.0];
+        let pq = ProductQuantizer::<L2DistanceCalculator>::new(2, 1, 1, codebook, "".to_string())
+            .unwrap();
+        let other_pq = ProductQuantizer::<L2DistanceCalculator>::new(
+            2,
+            1,
+            1,
+            vec![0.0, 10.0, 0.0, 10.0],
+            "".to_string(),
+        )
+        .unwrap();
+        let mut cache = AdcTableCache::new();
+
+        let table = cache.get_or_compute(&[1.0, 2.0], &pq).unwrap();
+        assert_eq!(table.lookup(&[0, 0]), 5.0);
+        assert!(Arc::ptr_eq(
+            &table,
+            &cache.get_or_compute(&[1.0, 2.0], &pq).unwrap()
+        ));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // A different query or quantizer recomputes the table
+        let table = cache.get_or_compute(&[9.0, 2.0], &pq).unwrap();
+        assert_eq!(table.lookup(&[1, 0]), 5.0);
+        cache.get_or_compute(&[9.0, 2.0], &other_pq).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+
+        // A quantizer created in place of a dropped one doesn't reuse its table, even if it ends
+        // up at the same address
+        drop(other_pq);
+        let new_pq = ProductQuantizer::<L2DistanceCalculator>::new(
+            2,
+            1,
+            1,
+            vec![0.0, 20.0, 0.0, 20.0],
+            "".to_string(),
+        )
+        .unwrap();
+        let table = cache.get_or_compute(&[9.0, 2.0], &new_pq).unwrap();
+        assert_eq!(table.lookup(&[1, 0]), 125.0);
+        assert_eq!((cache.hits(), cache.misses()), (1, 4));
+
+        // Quantizers without a codebook have no table
+        let noq = NoQuantizer::<L2DistanceCalculator>::new(2);
+        assert!(cache.get_or_compute(&[9.0, 2.0], &noq).is_none());
+    }
+
+    #[test]
+    fn test_search_result() {
+        let result = SearchResult::new(vec![
+            IdWithScore { id: 3, score: 3.0 },
+            IdWithScore { id: 1, score: 1.0 },
+            IdWithScore { id: 2, score: 2.0 },
+        ]);
+
+        let top_2 = result.top_k(2);
+        assert_eq!(top_2.len(), 2);
+        assert_eq!(top_2.0[0].id, 1);
+
+        let filtered = top_2.filter_by_score(1.5);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered.into_doc_ids(), vec![1]);
+
+        let result: SearchResult = vec![IdWithScore { id: 7, score: 0.5 }].into();
+        assert_eq!(result.into_scores(), vec![0.5]);
+    }
+
+    #[test]
+    fn test_search_result_serde() {
+        let result = SearchResult::new(vec![
+            IdWithScore {
+                id: u64::MAX as u128 + 1,
+                score: 1.5,
+            },
+            IdWithScore { id: 1, score: 2.0 },
+        ]);
+
+        let serialized = serde_yaml::to_string(&result).unwrap();
+        let deserialized: SearchResult = serde_yaml::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, result);
+    }
+
     #[test]
     fn test_id_with_score_ord() {
         let a = IdWithScore { id: 2, score: 1.0 };
@@ -0,0 +1,134 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::BufWriter;
+
+use anyhow::{anyhow, Result};
+use utils::distance::hamming::{num_words, HammingDistanceCalculator};
+use utils::io::wrap_write;
+use utils::BinaryDistanceCalculator;
+
+use crate::utils::SearchContext;
+use crate::vector::fixed_file::FixedFileVectorStorage;
+
+/// Binary vectors packed into u64 words, 64 dimensions per word. The file has the layout of
+/// `FixedFileVectorStorage`: the number of vectors, followed by the words of each vector.
+pub struct BitPackedVectorStorage {
+    storage: FixedFileVectorStorage<u64>,
+    // Number of dimensions of each vector
+    num_bits: usize,
+}
+
+impl BitPackedVectorStorage {
+    pub fn new(file_path: String, num_bits: usize) -> Result<Self> {
+        Self::new_with_offset(file_path, num_bits, 0)
+    }
+
+    pub fn new_with_offset(file_path: String, num_bits: usize, offset: usize) -> Result<Self> {
+        let storage =
+            FixedFileVectorStorage::<u64>::new_with_offset(file_path, num_words(num_bits), offset)?;
+        Ok(Self { storage, num_bits })
+    }
+
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    pub fn num_vectors(&self) -> usize {
+        self.storage.num_vectors
+    }
+
+    pub fn get(&self, index: usize, context: &mut SearchContext) -> Option<Cow<'_, [u64]>> {
+        self.storage.get(index, context)
+    }
+
+    /// Hamming distance between `query` and the vector at `index`, or None if there is no such
+    /// vector.
+    pub fn distance(
+        &self,
+        query: &[u64],
+        index: usize,
+        context: &mut SearchContext,
+    ) -> Option<u32> {
+        self.get(index, context)
+            .map(|vector| HammingDistanceCalculator::calculate(query, &vector))
+    }
+
+    /// The underlying storage, e.g. to build an `Ivf` over binary vectors.
+    pub fn into_inner(self) -> FixedFileVectorStorage<u64> {
+        self.storage
+    }
+
+    /// Writes packed vectors of `num_bits` dimensions in the layout read by `new`. Returns the
+    /// number of bytes written.
+    pub fn write_vectors(
+        writer: &mut BufWriter<&mut File>,
+        num_bits: usize,
+        vectors: &[Vec<u64>],
+    ) -> Result<usize> {
+        let mut written = wrap_write(writer, &(vectors.len() as u64).to_le_bytes())?;
+        for vector in vectors {
+            if vector.len() != num_words(num_bits) {
+                return Err(anyhow!(
+                    "Expected {} words for {} bits, got {}",
+                    num_words(num_bits),
+                    num_bits,
+                    vector.len()
+                ));
+            }
+            for word in vector {
+                written += wrap_write(writer, &word.to_le_bytes())?;
+            }
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempdir::TempDir;
+    use utils::distance::hamming::pack_bits;
+
+    use super::*;
+
+    #[test]
+    fn test_bit_packed_vector_storage() {
+        let temp_dir = TempDir::new("test_bit_packed_vector_storage").unwrap();
+        let file_path = format!("{}/vectors", temp_dir.path().to_str().unwrap());
+
+        let num_bits = 70;
+        let vectors: Vec<Vec<u64>> = (0..10)
+            .map(|i| pack_bits(&(0..num_bits).map(|j| j < i).collect::<Vec<_>>()))
+            .collect();
+        let mut file = File::create(&file_path).unwrap();
+        let mut writer = BufWriter::new(&mut file);
+        let written =
+            BitPackedVectorStorage::write_vectors(&mut writer, num_bits, &vectors).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        assert_eq!(written, 8 + 10 * 2 * 8);
+        assert!(BitPackedVectorStorage::write_vectors(
+            &mut BufWriter::new(&mut file),
+            num_bits,
+            &[vec![0]]
+        )
+        .is_err());
+
+        let storage = BitPackedVectorStorage::new(file_path, num_bits).unwrap();
+        assert_eq!(storage.num_vectors(), 10);
+        assert_eq!(storage.num_bits(), num_bits);
+
+        let mut context = SearchContext::new(false);
+        for (idx, vector) in vectors.iter().enumerate() {
+            assert_eq!(&*storage.get(idx, &mut context).unwrap(), vector.as_slice());
+        }
+        assert!(storage.get(10, &mut context).is_none());
+
+        // Vector i has its first i bits set
+        let query = pack_bits(&vec![false; num_bits]);
+        assert_eq!(storage.distance(&query, 7, &mut context), Some(7));
+        assert_eq!(storage.distance(&vectors[3], 9, &mut context), Some(6));
+        assert_eq!(storage.distance(&query, 10, &mut context), None);
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use log::error;
+use memmap2::Mmap;
+use num_traits::ToBytes;
+use utils::io::wrap_write;
+use utils::mem::transmute_u8_to_slice;
+
+use crate::utils::SearchContext;
+use crate::vector::fixed_file::FixedFileVectorStorage;
+
+// Number of vectors compressed together. Larger blocks compress better, but reading a single
+// vector decompresses its whole block.
+pub const DEFAULT_VECTORS_PER_BLOCK: usize = 256;
+
+// Number of decompressed blocks kept in memory by `CompressedVectorStorage::get`
+pub const DEFAULT_NUM_CACHED_BLOCKS: usize = 16;
+
+// num_vectors, vector_size_in_bytes, vectors_per_block, num_blocks
+const HEADER_LEN: usize = 4;
+
+/// Path of the compressed copy of the vector file at `path`.
+pub fn compressed_vector_file_path(path: &str) -> String {
+    format!("{}.zst", path)
+}
+
+/// Opens the vector file at `path`, or its compressed copy when there is one, see
+/// `CompressedVectorStorage::compress_file`. Compressed vectors are decompressed block by block
+/// as they are read.
+pub fn open_vector_storage<T: ToBytes + Clone>(
+    path: &str,
+    num_features: usize,
+    offset: usize,
+) -> Result<FixedFileVectorStorage<T>> {
+    let compressed_path = compressed_vector_file_path(path);
+    if !Path::new(&compressed_path).is_file() {
+        return FixedFileVectorStorage::new_with_offset(path.to_string(), num_features, offset);
+    }
+    FixedFileVectorStorage::new_compressed(compressed_path, num_features, DEFAULT_NUM_CACHED_BLOCKS)
+}
+
+/// Vector storage compressed with zstd, in blocks of a fixed number of vectors. The file starts
+/// with a header and the offsets of the blocks, so that `get` only decompresses the block
+/// holding the vector. The last decompressed blocks are kept in a ring buffer shared by all
+/// reads.
+///
+/// File layout, all integers are u64 little-endian:
+/// - num_vectors, vector_size_in_bytes, vectors_per_block, num_blocks
+/// - num_blocks + 1 offsets of the blocks, from the end of the offsets
+/// - the compressed blocks
+pub struct CompressedVectorStorage<T> {
+    _marker: PhantomData<T>,
+
+    mmap: Mmap,
+    pub num_vectors: usize,
+    vector_size_in_bytes: usize,
+    vectors_per_block: usize,
+    num_blocks: usize,
+    file_path: String,
+
+    // Decompressed blocks by block id, oldest first
+    cached_blocks: Mutex<VecDeque<(usize, Arc<Vec<u64>>)>>,
+    num_cached_blocks: usize,
+}
+
+impl<T: Clone> CompressedVectorStorage<T> {
+    pub fn new(file_path: String, num_cached_blocks: usize) -> Result<Self> {
+        let file = File::open(&file_path)?;
+        let mmap = unsafe { Mmap::map(&file) }?;
+        if mmap.len() < HEADER_LEN * size_of::<u64>() {
+            return Err(anyhow!("Compressed vector file {} is truncated", file_path));
+        }
+        let header = transmute_u8_to_slice::<u64>(&mmap[..HEADER_LEN * size_of::<u64>()]);
+        let (num_vectors, vector_size_in_bytes, vectors_per_block, num_blocks) = (
+            header[0] as usize,
+            header[1] as usize,
+            header[2] as usize,
+            header[3] as usize,
+        );
+        if vector_size_in_bytes % size_of::<T>() != 0 {
+            return Err(anyhow!(
+                "Vectors of {} bytes in {} are not made of {} byte values",
+                vector_size_in_bytes,
+                file_path,
+                size_of::<T>()
+            ));
+        }
+
+        let storage = Self {
+            _marker: PhantomData,
+            mmap,
+            num_vectors,
+            vector_size_in_bytes,
+            vectors_per_block,
+            num_blocks,
+            file_path,
+            cached_blocks: Mutex::new(VecDeque::with_capacity(num_cached_blocks)),
+            num_cached_blocks,
+        };
+        if storage.data_offset() > storage.mmap.len()
+            || storage.data_offset() + storage.block_offset(num_blocks) > storage.mmap.len()
+        {
+            return Err(anyhow!(
+                "Compressed vector file {} is truncated",
+                storage.file_path
+            ));
+        }
+        Ok(storage)
+    }
+
+    /// Compresses the vector file at `source_path`, in the format of `FixedFileVectorStorage`,
+    /// into `destination_path`. The source is read one block at a time. Returns the number of
+    /// bytes written.
+    pub fn compress_file(
+        source_path: &str,
+        destination_path: &str,
+        vectors_per_block: usize,
+        level: i32,
+    ) -> Result<usize> {
+        if vectors_per_block == 0 {
+            return Err(anyhow!("Blocks must hold at least one vector"));
+        }
+        let source = File::open(source_path)?;
+        let vectors_len = (source.metadata()?.len() as usize)
+            .checked_sub(size_of::<u64>())
+            .ok_or_else(|| anyhow!("Vector file {} is truncated", source_path))?;
+        let mut reader = BufReader::new(source);
+        let mut num_vectors = [0u8; size_of::<u64>()];
+        reader.read_exact(&mut num_vectors)?;
+        let num_vectors = u64::from_le_bytes(num_vectors) as usize;
+        let vector_size_in_bytes = match num_vectors {
+            0 => 0,
+            _ => vectors_len / num_vectors,
+        };
+        if vector_size_in_bytes * num_vectors != vectors_len {
+            return Err(anyhow!(
+                "Vector file {} has {} bytes of vectors, which is not a multiple of {} vectors",
+                source_path,
+                vectors_len,
+                num_vectors
+            ));
+        }
+        let num_blocks = num_vectors.div_ceil(vectors_per_block);
+
+        let mut file = File::create(destination_path)?;
+        let mut writer = BufWriter::new(&mut file);
+        let mut bytes_written = 0;
+        for value in [
+            num_vectors,
+            vector_size_in_bytes,
+            vectors_per_block,
+            num_blocks,
+        ] {
+            bytes_written += wrap_write(&mut writer, &(value as u64).to_le_bytes())?;
+        }
+        // The offsets are only known once the blocks are compressed, they are written last
+        let offsets_position = bytes_written as u64;
+        for _ in 0..=num_blocks {
+            bytes_written += wrap_write(&mut writer, &0u64.to_le_bytes())?;
+        }
+
+        let mut offsets = vec![0u64];
+        let mut block = vec![0u8; vectors_per_block * vector_size_in_bytes];
+        for block_id in 0..num_blocks {
+            let num_vectors_in_block =
+                (num_vectors - block_id * vectors_per_block).min(vectors_per_block);
+            let block = &mut block[..num_vectors_in_block * vector_size_in_bytes];
+            reader.read_exact(block)?;
+            let compressed_block = zstd::bulk::compress(block, level)?;
+            bytes_written += wrap_write(&mut writer, &compressed_block)?;
+            offsets.push(offsets.last().unwrap() + compressed_block.len() as u64);
+        }
+
+        writer.seek(SeekFrom::Start(offsets_position))?;
+        for offset in offsets {
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        writer.flush()?;
+        Ok(bytes_written)
+    }
+
+    pub fn vector_size_in_bytes(&self) -> usize {
+        self.vector_size_in_bytes
+    }
+
+    fn data_offset(&self) -> usize {
+        (HEADER_LEN + self.num_blocks + 1) * size_of::<u64>()
+    }
+
+    fn block_offset(&self, block_id: usize) -> usize {
+        let start = (HEADER_LEN + block_id) * size_of::<u64>();
+        u64::from_le_bytes(
+            self.mmap[start..start + size_of::<u64>()]
+                .try_into()
+                .unwrap(),
+        ) as usize
+    }
+
+    fn decompress_block(&self, block_id: usize) -> Result<Arc<Vec<u64>>> {
+        let start = self.data_offset() + self.block_offset(block_id);
+        let end = self.data_offset() + self.block_offset(block_id + 1);
+        let num_vectors_in_block =
+            (self.num_vectors - block_id * self.vectors_per_block).min(self.vectors_per_block);
+        let len = num_vectors_in_block * self.vector_size_in_bytes;
+
+        // Decompress into u64s, so that the block is aligned for any type of values
+        let mut block = vec![0u64; len.div_ceil(size_of::<u64>())];
+        let block_bytes =
+            unsafe { std::slice::from_raw_parts_mut(block.as_mut_ptr() as *mut u8, len) };
+        let decompressed_len =
+            zstd::bulk::decompress_to_buffer(&self.mmap[start..end], block_bytes)?;
+        if decompressed_len != len {
+            return Err(anyhow!(
+                "Block {} of {} is {} bytes, expected {}",
+                block_id,
+                self.file_path,
+                decompressed_len,
+                len
+            ));
+        }
+        Ok(Arc::new(block))
+    }
+
+    /// Returns the block, from the cache if it was decompressed recently.
+    fn get_block(&self, block_id: usize) -> Result<Arc<Vec<u64>>> {
+        if let Some((_, block)) = self
+            .cached_blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(cached_block_id, _)| *cached_block_id == block_id)
+        {
+            return Ok(block.clone());
+        }
+        // Don't hold the lock while decompressing, other reads may hit the cache meanwhile
+        let block = self.decompress_block(block_id)?;
+        if self.num_cached_blocks > 0 {
+            let mut cached_blocks = self.cached_blocks.lock().unwrap();
+            if cached_blocks.len() == self.num_cached_blocks {
+                cached_blocks.pop_front();
+            }
+            cached_blocks.push_back((block_id, block.clone()));
+        }
+        Ok(block)
+    }
+
+    /// Returns the vector at `index`, or None if there is no such vector or it can't be
+    /// decompressed.
+    pub fn get(&self, index: usize, context: &mut SearchContext) -> Option<Vec<T>> {
+        if index >= self.num_vectors {
+            return None;
+        }
+        self.record_pages(&[index as u32], context);
+        match self.read(index) {
+            Ok(vector) => Some(vector),
+            Err(e) => {
+                error!(
+                    "Failed to read vector {} of {}: {}",
+                    index, self.file_path, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Like `get`, without recording the block read.
+    pub fn read(&self, index: usize) -> Result<Vec<T>> {
+        if index >= self.num_vectors {
+            return Err(anyhow!(
+                "Vector {} out of bound, {} has {} vectors",
+                index,
+                self.file_path,
+                self.num_vectors
+            ));
+        }
+        let block = self.get_block(index / self.vectors_per_block)?;
+        let start = (index % self.vectors_per_block) * self.vector_size_in_bytes;
+        let block_bytes = utils::mem::transmute_slice_to_u8(block.as_slice());
+        Ok(
+            transmute_u8_to_slice::<T>(&block_bytes[start..start + self.vector_size_in_bytes])
+                .to_vec(),
+        )
+    }
+
+    /// Records the blocks holding the vectors at `indices` in `context`, as `get` would.
+    pub fn record_pages(&self, indices: &[u32], context: &mut SearchContext) {
+        if !context.should_record_pages() {
+            return;
+        }
+        for &index in indices {
+            let block_id = index as usize / self.vectors_per_block;
+            context.record_pages(format!("{}::{}", self.file_path, block_id));
+        }
+    }
+
+    /// Decompresses all vectors, in the format of `FixedFileVectorStorage`.
+    pub fn decompress(&self) -> Result<Vec<u8>> {
+        let mut bytes =
+            Vec::with_capacity(size_of::<u64>() + self.num_vectors * self.vector_size_in_bytes);
+        bytes.extend_from_slice(&(self.num_vectors as u64).to_le_bytes());
+        for block_id in 0..self.num_blocks {
+            let block = self.decompress_block(block_id)?;
+            let block_bytes = utils::mem::transmute_slice_to_u8(block.as_slice());
+            let num_vectors_in_block =
+                (self.num_vectors - block_id * self.vectors_per_block).min(self.vectors_per_block);
+            bytes.extend_from_slice(
+                &block_bytes[..num_vectors_in_block * self.vector_size_in_bytes],
+            );
+        }
+        Ok(bytes)
+    }
+
+    /// Number of bytes of the compressed file.
+    pub fn size_in_bytes(&self) -> usize {
+        self.mmap.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn write_vector_file(path: &str, vectors: &[Vec<f32>]) {
+        let mut bytes = (vectors.len() as u64).to_le_bytes().to_vec();
+        for vector in vectors {
+            for value in vector {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_vector_storage() {
+        let temp_dir = TempDir::new("test_compressed_vector_storage").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let source_path = format!("{}/vectors", base_directory);
+        let destination_path = compressed_vector_file_path(&source_path);
+
+        // Values that don't round-trip through a decimal representation
+        let vectors = (0..1000)
+            .map(|i| {
+                vec![
+                    i as f32 / 3.0,
+                    f32::from_bits(0x3f80_0001 + i),
+                    -(i as f32).sqrt(),
+                    f32::MIN_POSITIVE * i as f32,
+                ]
+            })
+            .collect::<Vec<Vec<f32>>>();
+        write_vector_file(&source_path, &vectors);
+        CompressedVectorStorage::<f32>::compress_file(&source_path, &destination_path, 64, 3)
+            .unwrap();
+
+        let storage = CompressedVectorStorage::<f32>::new(destination_path, 2).unwrap();
+        assert_eq!(storage.num_vectors, vectors.len());
+        assert_eq!(storage.num_blocks, 16);
+        let storage_size_in_bytes = storage.size_in_bytes();
+        let mut context = SearchContext::new(false);
+        for index in [0, 1, 999, 64, 500, 63, 0] {
+            let vector = storage.get(index, &mut context).unwrap();
+            assert_eq!(
+                vector.iter().map(|v| v.to_bits()).collect::<Vec<u32>>(),
+                vectors[index]
+                    .iter()
+                    .map(|v| v.to_bits())
+                    .collect::<Vec<u32>>()
+            );
+        }
+        assert!(storage.get(1000, &mut context).is_none());
+        // Only the last 2 blocks are cached
+        let cached_block_ids = storage
+            .cached_blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(block_id, _)| *block_id)
+            .collect::<Vec<usize>>();
+        assert_eq!(cached_block_ids, vec![7, 0]);
+
+        // Decompressing restores the original file
+        let bytes = storage.decompress().unwrap();
+        assert_eq!(bytes, std::fs::read(&source_path).unwrap());
+        let fixed_storage = FixedFileVectorStorage::<f32>::new_from_bytes(&bytes, 4, 0).unwrap();
+        assert_eq!(
+            &*fixed_storage.get(999, &mut context).unwrap(),
+            vectors[999].as_slice()
+        );
+
+        // Searches read the compressed file directly
+        let storage = open_vector_storage::<f32>(&source_path, 4, 0).unwrap();
+        std::fs::remove_file(&source_path).unwrap();
+        assert_eq!(storage.num_vectors, vectors.len());
+        assert_eq!(storage.size_in_bytes(), storage_size_in_bytes);
+        assert_eq!(
+            &*storage.get(999, &mut context).unwrap(),
+            vectors[999].as_slice()
+        );
+        assert!(storage.get(1000, &mut context).is_none());
+        assert_eq!(
+            storage.read_batch(&[3, 64, 65]).unwrap(),
+            [3, 64, 65]
+                .iter()
+                .flat_map(|&i| vectors[i].clone())
+                .collect::<Vec<f32>>()
+        );
+        assert!(open_vector_storage::<f32>(&source_path, 3, 0).is_err());
+    }
+
+    #[test]
+    fn test_compressed_vector_storage_empty() {
+        let temp_dir = TempDir::new("test_compressed_vector_storage_empty").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let source_path = format!("{}/vectors", base_directory);
+        let destination_path = compressed_vector_file_path(&source_path);
+        write_vector_file(&source_path, &[]);
+        CompressedVectorStorage::<f32>::compress_file(&source_path, &destination_path, 64, 3)
+            .unwrap();
+
+        let storage = CompressedVectorStorage::<f32>::new(destination_path, 2).unwrap();
+        assert_eq!(storage.num_vectors, 0);
+        assert!(storage.get(0, &mut SearchContext::new(false)).is_none());
+        assert_eq!(
+            storage.decompress().unwrap(),
+            std::fs::read(&source_path).unwrap()
+        );
+    }
+}
@@ -1,20 +1,43 @@
+use std::borrow::Cow;
+#[cfg(not(feature = "wasm"))]
+use std::fs::File;
 use std::marker::PhantomData;
+#[cfg(not(feature = "wasm"))]
+use std::os::unix::fs::FileExt;
 
-use anyhow::Result;
-use memmap2::Mmap;
+use anyhow::{anyhow, Result};
+#[cfg(not(feature = "wasm"))]
+use memmap2::{Mmap, MmapMut};
 use num_traits::ToBytes;
-use utils::mem::transmute_u8_to_slice;
+use utils::mem::{
+    next_instance_id, transmute_slice_to_u8, transmute_slice_to_u8_mut, transmute_u8_to_slice,
+};
 
 use crate::utils::{SearchContext, TraversalContext};
+#[cfg(not(feature = "wasm"))]
+use crate::vector::compressed::CompressedVectorStorage;
+use crate::vector::{zeroed_vec, VectorReader};
 
 pub struct FixedFileVectorStorage<T> {
     _marker: PhantomData<T>,
 
+    #[cfg(not(feature = "wasm"))]
     mmaps: Mmap,
+    // Without file I/O, the whole storage is kept in memory.
+    #[cfg(feature = "wasm")]
+    mmaps: Vec<u8>,
+    // Backing file for `read_batch`. None when the storage was built from bytes.
+    #[cfg(not(feature = "wasm"))]
+    file: Option<File>,
+    // Set when the vectors are read from a compressed file, in which case `mmaps` is empty.
+    #[cfg(not(feature = "wasm"))]
+    compressed: Option<CompressedVectorStorage<T>>,
     pub num_vectors: usize,
     num_features: usize,
     file_path: String,
     offset: usize,
+    // Identifies the storage in the vector cache of a `SearchContext`
+    id: u64,
 }
 
 impl<T: ToBytes + Clone> FixedFileVectorStorage<T> {
@@ -22,27 +45,129 @@ impl<T: ToBytes + Clone> FixedFileVectorStorage<T> {
         Self::new_with_offset(file_path, num_features, 0)
     }
 
+    #[cfg(not(feature = "wasm"))]
     pub fn new_with_offset(file_path: String, num_features: usize, offset: usize) -> Result<Self> {
         let file = std::fs::OpenOptions::new()
             .read(true)
             .open(file_path.clone())?;
         let mmap = unsafe { Mmap::map(&file) }?;
-        let num_vectors = usize::from_le_bytes(mmap[offset..offset + 8].try_into().unwrap());
+        Ok(Self::new_with_storage(
+            file_path,
+            mmap,
+            Some(file),
+            num_features,
+            offset,
+        ))
+    }
+
+    #[cfg(feature = "wasm")]
+    pub fn new_with_offset(
+        file_path: String,
+        _num_features: usize,
+        _offset: usize,
+    ) -> Result<Self> {
+        Err(anyhow!(
+            "Cannot read {}: file I/O is disabled by the wasm feature, use new_from_bytes",
+            file_path
+        ))
+    }
+
+    /// Builds the storage from the content of a vector file that is already in memory.
+    #[cfg(not(feature = "wasm"))]
+    pub fn new_from_bytes(bytes: &[u8], num_features: usize, offset: usize) -> Result<Self> {
+        let mut mmap = MmapMut::map_anon(bytes.len().max(1))?;
+        mmap[..bytes.len()].copy_from_slice(bytes);
+        Ok(Self::new_with_storage(
+            "memory".to_string(),
+            mmap.make_read_only()?,
+            None,
+            num_features,
+            offset,
+        ))
+    }
+
+    /// Builds the storage from the content of a vector file that is already in memory.
+    #[cfg(feature = "wasm")]
+    pub fn new_from_bytes(bytes: &[u8], num_features: usize, offset: usize) -> Result<Self> {
+        Ok(Self::new_with_storage(
+            "memory".to_string(),
+            bytes.to_vec(),
+            num_features,
+            offset,
+        ))
+    }
+
+    /// Opens a vector file written by `CompressedVectorStorage::compress_file`. Vectors are
+    /// decompressed block by block as they are read, and the last `num_cached_blocks` blocks are
+    /// kept in memory.
+    #[cfg(not(feature = "wasm"))]
+    pub fn new_compressed(
+        file_path: String,
+        num_features: usize,
+        num_cached_blocks: usize,
+    ) -> Result<Self> {
+        let compressed = CompressedVectorStorage::<T>::new(file_path.clone(), num_cached_blocks)?;
+        if compressed.num_vectors > 0
+            && compressed.vector_size_in_bytes() != Self::vector_size_in_bytes(num_features)
+        {
+            return Err(anyhow!(
+                "Vectors of {} are {} bytes, expected {}",
+                file_path,
+                compressed.vector_size_in_bytes(),
+                Self::vector_size_in_bytes(num_features)
+            ));
+        }
         Ok(Self {
             _marker: PhantomData,
-            mmaps: mmap,
+            mmaps: MmapMut::map_anon(1)?.make_read_only()?,
+            file: None,
+            num_vectors: compressed.num_vectors,
+            compressed: Some(compressed),
+            num_features,
+            file_path,
+            offset: 0,
+            id: next_instance_id(),
+        })
+    }
+
+    fn new_with_storage(
+        file_path: String,
+        #[cfg(not(feature = "wasm"))] mmaps: Mmap,
+        #[cfg(feature = "wasm")] mmaps: Vec<u8>,
+        #[cfg(not(feature = "wasm"))] file: Option<File>,
+        num_features: usize,
+        offset: usize,
+    ) -> Self {
+        let num_vectors = usize::from_le_bytes(mmaps[offset..offset + 8].try_into().unwrap());
+        Self {
+            _marker: PhantomData,
+            mmaps,
+            #[cfg(not(feature = "wasm"))]
+            file,
+            #[cfg(not(feature = "wasm"))]
+            compressed: None,
             num_vectors,
             num_features,
             file_path,
             offset,
-        })
+            id: next_instance_id(),
+        }
     }
 
-    pub fn get(&self, index: usize, context: &mut SearchContext) -> Option<&[T]> {
+    pub fn num_features(&self) -> usize {
+        self.num_features
+    }
+
+    /// Borrows the vector from the file, unless it is compressed.
+    pub fn get(&self, index: usize, context: &mut SearchContext) -> Option<Cow<'_, [T]>> {
         if index >= self.num_vectors {
             return None;
         }
-        let start = self.offset + 8 + index * Self::vector_size_in_bytes(self.num_features);
+        #[cfg(not(feature = "wasm"))]
+        if let Some(compressed) = &self.compressed {
+            return compressed.get(index, context).map(Cow::Owned);
+        }
+        let start = self.vector_start(index);
 
         if context.should_record_pages() {
             let page_id = format!("{}::{}", self.file_path, self.get_page_id(start));
@@ -50,7 +175,111 @@ impl<T: ToBytes + Clone> FixedFileVectorStorage<T> {
         }
 
         let slice = &self.mmaps[start..start + Self::vector_size_in_bytes(self.num_features)];
-        Some(transmute_u8_to_slice::<T>(slice))
+        Some(Cow::Borrowed(transmute_u8_to_slice::<T>(slice)))
+    }
+
+    /// Records the pages holding the vectors at `indices` in `context`, as `get` would.
+    pub fn record_pages(&self, indices: &[u32], context: &mut SearchContext) {
+        if !context.should_record_pages() {
+            return;
+        }
+        #[cfg(not(feature = "wasm"))]
+        if let Some(compressed) = &self.compressed {
+            compressed.record_pages(indices, context);
+            return;
+        }
+        for &index in indices {
+            let page_id = self.get_page_id(self.vector_start(index as usize));
+            context.record_pages(format!("{}::{}", self.file_path, page_id));
+        }
+    }
+
+    /// Reads the vectors at `indices` into one buffer, `num_features` values per vector, in the
+    /// order of `indices`. Instead of faulting in mmap pages one vector at a time, each run of
+    /// consecutive indices is read with a single `pread` call. Posting lists are sorted, and
+    /// contiguous after reindexing, so this is usually a handful of calls per posting list.
+    /// Storages built from bytes copy from memory, compressed ones decompress vector by vector.
+    pub fn read_batch(&self, indices: &[u32]) -> Result<Vec<T>> {
+        self.check_indices(indices)?;
+        let vector_size = Self::vector_size_in_bytes(self.num_features);
+        let mut vectors = zeroed_vec::<T>(indices.len() * self.num_features);
+        let buffer = transmute_slice_to_u8_mut(&mut vectors);
+
+        #[cfg(not(feature = "wasm"))]
+        if let Some(compressed) = &self.compressed {
+            for (i, &index) in indices.iter().enumerate() {
+                let vector = compressed.read(index as usize)?;
+                buffer[i * vector_size..(i + 1) * vector_size]
+                    .copy_from_slice(transmute_slice_to_u8(&vector));
+            }
+            return Ok(vectors);
+        }
+
+        let mut run_start = 0;
+        while run_start < indices.len() {
+            let mut run_end = run_start + 1;
+            while run_end < indices.len() && indices[run_end] == indices[run_end - 1] + 1 {
+                run_end += 1;
+            }
+            let offset = self.vector_start(indices[run_start] as usize);
+            let run = &mut buffer[run_start * vector_size..run_end * vector_size];
+            #[cfg(not(feature = "wasm"))]
+            if let Some(file) = &self.file {
+                file.read_exact_at(run, offset as u64)?;
+                run_start = run_end;
+                continue;
+            }
+            run.copy_from_slice(&self.mmaps[offset..offset + run.len()]);
+            run_start = run_end;
+        }
+        Ok(vectors)
+    }
+
+    fn check_indices(&self, indices: &[u32]) -> Result<()> {
+        match indices
+            .iter()
+            .find(|&&index| index as usize >= self.num_vectors)
+        {
+            Some(index) => Err(anyhow!(
+                "Vector {} out of bound, the storage has {} vectors",
+                index,
+                self.num_vectors
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the number of bytes the vectors occupy in the backing file, including the
+    /// leading vector count.
+    pub fn size_in_bytes(&self) -> usize {
+        #[cfg(not(feature = "wasm"))]
+        if let Some(compressed) = &self.compressed {
+            return compressed.size_in_bytes();
+        }
+        8 + self.num_vectors * Self::vector_size_in_bytes(self.num_features)
+    }
+
+    /// Reads one byte of every page holding the first `num_vectors` vectors, so that they are in
+    /// the page cache before the first searches. Returns the number of vectors read, 0 for
+    /// compressed files, which are decompressed as they are searched.
+    pub fn prefetch(&self, num_vectors: usize) -> usize {
+        #[cfg(not(feature = "wasm"))]
+        if self.compressed.is_some() {
+            return 0;
+        }
+        let num_vectors = num_vectors.min(self.num_vectors);
+        let start = self.vector_start(0);
+        let end = self.vector_start(num_vectors);
+        let mut checksum = 0u8;
+        for offset in (start..end).step_by(4096) {
+            checksum ^= self.mmaps[offset];
+        }
+        std::hint::black_box(checksum);
+        num_vectors
+    }
+
+    fn vector_start(&self, index: usize) -> usize {
+        self.offset + 8 + index * Self::vector_size_in_bytes(self.num_features)
     }
 
     fn get_page_id(&self, index: usize) -> usize {
@@ -62,17 +291,56 @@ impl<T: ToBytes + Clone> FixedFileVectorStorage<T> {
     }
 }
 
+impl<T: ToBytes + Clone> VectorReader<T> for FixedFileVectorStorage<T> {
+    fn num_vectors(&self) -> usize {
+        self.num_vectors
+    }
+
+    fn num_features(&self) -> usize {
+        self.num_features
+    }
+
+    fn get(&self, index: usize, context: &mut SearchContext) -> Option<Cow<'_, [T]>> {
+        FixedFileVectorStorage::get(self, index, context)
+    }
+
+    fn read_batch(&self, indices: &[u32]) -> Result<Vec<T>> {
+        FixedFileVectorStorage::read_batch(self, indices)
+    }
+
+    fn record_pages(&self, indices: &[u32], context: &mut SearchContext) {
+        FixedFileVectorStorage::record_pages(self, indices, context)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        FixedFileVectorStorage::size_in_bytes(self)
+    }
+
+    fn cache_id(&self) -> u64 {
+        self.id
+    }
+}
+
 // Test
 #[cfg(test)]
 mod tests {
+    #[cfg(not(feature = "wasm"))]
     use std::fs::File;
+    #[cfg(not(feature = "wasm"))]
     use std::io::BufWriter;
 
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::DistanceCalculator;
+
     use super::*;
+    use crate::utils::CachePolicy;
+    #[cfg(not(feature = "wasm"))]
     use crate::vector::file::FileBackedAppendableVectorStorage;
+    #[cfg(not(feature = "wasm"))]
     use crate::vector::VectorStorage;
 
     #[test]
+    #[cfg(not(feature = "wasm"))]
     fn test_fixed_file_vector_storage() {
         let tempdir = tempdir::TempDir::new("vector_storage_test").unwrap();
         let base_directory = tempdir.path().to_str().unwrap().to_string();
@@ -92,15 +360,16 @@ mod tests {
 
         let mut context = SearchContext::new(true);
         let storage = FixedFileVectorStorage::<u32>::new(vectors_path, 4).unwrap();
-        assert_eq!(storage.get(0, &mut context).unwrap(), &[0, 0, 0, 0]);
+        assert_eq!(&*storage.get(0, &mut context).unwrap(), &[0, 0, 0, 0]);
         assert_eq!(
-            storage.get(256, &mut context).unwrap(),
+            &*storage.get(256, &mut context).unwrap(),
             &[256, 256, 256, 256]
         );
         assert_eq!(context.num_pages_accessed(), 2);
     }
 
     #[test]
+    #[cfg(not(feature = "wasm"))]
     fn test_fixed_file_vector_storage_f32() {
         let tempdir = tempdir::TempDir::new("vector_storage_test").unwrap();
         let base_directory = tempdir.path().to_str().unwrap().to_string();
@@ -127,10 +396,16 @@ mod tests {
         let mut context = SearchContext::new(false);
         let storage = FixedFileVectorStorage::<f32>::new(vectors_path, 4).unwrap();
         assert_eq!(storage.num_vectors, 3);
-        assert_eq!(storage.get(0, &mut context).unwrap(), &[1.0, 2.0, 3.0, 4.0]);
-        assert_eq!(storage.get(1, &mut context).unwrap(), &[5.0, 6.0, 7.0, 8.0]);
         assert_eq!(
-            storage.get(2, &mut context).unwrap(),
+            &*storage.get(0, &mut context).unwrap(),
+            &[1.0, 2.0, 3.0, 4.0]
+        );
+        assert_eq!(
+            &*storage.get(1, &mut context).unwrap(),
+            &[5.0, 6.0, 7.0, 8.0]
+        );
+        assert_eq!(
+            &*storage.get(2, &mut context).unwrap(),
             &[9.0, 10.0, 11.0, 12.0]
         );
 
@@ -138,6 +413,103 @@ mod tests {
         assert!(storage.get(3, &mut context).is_none());
     }
 
+    #[test]
+    fn test_flat_search_from_bytes() {
+        let vectors: Vec<Vec<f32>> = (0..10)
+            .map(|i| vec![i as f32, (i * 2) as f32, (i * 3) as f32])
+            .collect();
+        let mut bytes = vectors.len().to_le_bytes().to_vec();
+        for vector in &vectors {
+            for value in vector {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let storage = FixedFileVectorStorage::<f32>::new_from_bytes(&bytes, 3, 0).unwrap();
+        assert_eq!(storage.num_vectors, 10);
+
+        // Brute-force search over all vectors.
+        let query = [6.9, 14.2, 20.8];
+        let mut context = SearchContext::new(false);
+        let nearest = (0..storage.num_vectors)
+            .min_by(|&a, &b| {
+                let a =
+                    L2DistanceCalculator::calculate(&storage.get(a, &mut context).unwrap(), &query);
+                let b =
+                    L2DistanceCalculator::calculate(&storage.get(b, &mut context).unwrap(), &query);
+                a.total_cmp(&b)
+            })
+            .unwrap();
+        assert_eq!(nearest, 7);
+    }
+
+    #[test]
+    #[cfg(not(feature = "wasm"))]
+    fn test_read_batch() {
+        let tempdir = tempdir::TempDir::new("vector_storage_read_batch_test").unwrap();
+        let base_directory = tempdir.path().to_str().unwrap().to_string();
+        let mut bytes = 1500usize.to_le_bytes().to_vec();
+        for i in 0..1500 {
+            for j in 0..3 {
+                bytes.extend_from_slice(&((i * 3 + j) as f32).to_le_bytes());
+            }
+        }
+        let vectors_path = format!("{}/vector_storage", base_directory);
+        std::fs::write(&vectors_path, &bytes).unwrap();
+
+        // Runs of consecutive indices, and gaps
+        let mut indices = vec![0, 1, 2, 7, 9];
+        indices.extend(100..1300);
+        indices.push(1499);
+        let expected: Vec<f32> = indices
+            .iter()
+            .flat_map(|&i| (0..3).map(move |j| (i * 3 + j) as f32))
+            .collect();
+
+        let file_storage = FixedFileVectorStorage::<f32>::new(vectors_path, 3).unwrap();
+        assert_eq!(file_storage.read_batch(&indices).unwrap(), expected);
+        let memory_storage = FixedFileVectorStorage::<f32>::new_from_bytes(&bytes, 3, 0).unwrap();
+        assert_eq!(memory_storage.read_batch(&indices).unwrap(), expected);
+
+        assert!(file_storage.read_batch(&[]).unwrap().is_empty());
+        assert!(file_storage.read_batch(&[3, 1500]).is_err());
+    }
+
+    #[test]
+    fn test_read_batch_with_context_reopened_storage() {
+        let build_storage = |value: f32| {
+            let mut bytes = 2usize.to_le_bytes().to_vec();
+            for _ in 0..2 * 3 {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            FixedFileVectorStorage::<f32>::new_from_bytes(&bytes, 3, 0).unwrap()
+        };
+
+        let mut context = SearchContext::with_cache(CachePolicy::LRU(16));
+        let storage = build_storage(1.0);
+        assert_eq!(
+            storage.read_batch_with_context(&[1], &mut context).unwrap(),
+            vec![1.0; 3]
+        );
+        drop(storage);
+
+        // A storage opened in place of a dropped one doesn't see its cached vectors, even if it
+        // ends up at the same address
+        let storage = build_storage(2.0);
+        assert_eq!(
+            storage.read_batch_with_context(&[1], &mut context).unwrap(),
+            vec![2.0; 3]
+        );
+        assert_eq!(context.cache_hit_count(), 0);
+        assert_eq!(
+            storage
+                .read_batch_with_context(&[0, 1], &mut context)
+                .unwrap(),
+            vec![2.0; 6]
+        );
+        assert_eq!(context.cache_hit_count(), 1);
+    }
+
     #[test]
     fn test_vector_size_in_bytes() {
         assert_eq!(FixedFileVectorStorage::<f32>::vector_size_in_bytes(3), 12); // 3 features * 4 bytes (size of f32)
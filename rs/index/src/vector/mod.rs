@@ -1,9 +1,16 @@
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::BufWriter;
+use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use num_traits::ops::bytes::ToBytes;
+use utils::mem::{transmute_slice_to_u8, transmute_slice_to_u8_mut};
 
+use crate::utils::{SearchContext, VectorCache};
+
+pub mod bit_packed;
+pub mod compressed;
 pub mod file;
 pub mod fixed_file;
 
@@ -29,3 +36,126 @@ pub trait VectorStorage<T: ToBytes + Clone> {
     // Return the config for this vector storage. Useful when we want duplicate.
     fn config(&self) -> VectorStorageConfig;
 }
+
+/// Read-only storage of the vectors of an index, from which `Ivf` scans posting lists. Vectors
+/// have `num_features` values each and are looked up by index.
+pub trait VectorReader<T> {
+    fn num_vectors(&self) -> usize;
+
+    fn num_features(&self) -> usize;
+
+    /// Returns the vector at `index`, or None if it is out of range or can't be read.
+    fn get(&self, index: usize, context: &mut SearchContext) -> Option<Cow<'_, [T]>>;
+
+    /// Reads the vectors at `indices` into one buffer, `num_features` values per vector, in the
+    /// order of `indices`.
+    fn read_batch(&self, indices: &[u32]) -> Result<Vec<T>>;
+
+    /// Records the pages holding the vectors at `indices` in `context`, as `get` would.
+    fn record_pages(&self, indices: &[u32], context: &mut SearchContext);
+
+    /// Returns the number of bytes the vectors occupy in the backing file or object, including
+    /// the leading vector count.
+    fn size_in_bytes(&self) -> usize;
+
+    /// Identifies the storage in vector caches. Unlike its address, it is not reused by a
+    /// storage opened after this one is dropped.
+    fn cache_id(&self) -> u64;
+
+    /// Like `read_batch`, but vectors found in the vector cache of `context` are not read again,
+    /// and the ones read are added to it.
+    fn read_batch_with_context(
+        &self,
+        indices: &[u32],
+        context: &mut SearchContext,
+    ) -> Result<Vec<T>> {
+        let Some(cache) = context.vector_cache.as_mut() else {
+            return self.read_batch(indices);
+        };
+        let cached = get_cached(self.cache_id(), indices, cache);
+        let (vectors, read) = read_missing(self, indices, cached)?;
+        insert_cached(self.cache_id(), read, cache);
+        Ok(vectors)
+    }
+
+    /// Like `read_batch_with_context`, with a cache shared by several searches. The cache isn't
+    /// locked while reading, so that other searches can use it meanwhile.
+    fn read_batch_with_shared_cache(
+        &self,
+        indices: &[u32],
+        cache: &Mutex<VectorCache>,
+    ) -> Result<Vec<T>> {
+        let cached = get_cached(self.cache_id(), indices, &mut cache.lock().unwrap());
+        let (vectors, read) = read_missing(self, indices, cached)?;
+        insert_cached(self.cache_id(), read, &mut cache.lock().unwrap());
+        Ok(vectors)
+    }
+}
+
+fn get_cached(
+    cache_id: u64,
+    indices: &[u32],
+    cache: &mut VectorCache,
+) -> Vec<Option<Arc<Vec<u8>>>> {
+    indices
+        .iter()
+        .map(|&index| cache.get(&(cache_id, index)))
+        .collect()
+}
+
+fn insert_cached(cache_id: u64, read: Vec<(u32, Arc<Vec<u8>>)>, cache: &mut VectorCache) {
+    for (index, vector) in read {
+        cache.insert((cache_id, index), vector);
+    }
+}
+
+/// Returns the vectors at `indices` as `read_batch` does, given the ones already `cached`, and
+/// the (index, vector) of the ones that had to be read.
+fn read_missing<T, S: VectorReader<T> + ?Sized>(
+    storage: &S,
+    indices: &[u32],
+    cached: Vec<Option<Arc<Vec<u8>>>>,
+) -> Result<(Vec<T>, Vec<(u32, Arc<Vec<u8>>)>)> {
+    let missing = indices
+        .iter()
+        .zip(cached.iter())
+        .filter(|(_, vector)| vector.is_none())
+        .map(|(&index, _)| index)
+        .collect::<Vec<u32>>();
+    let read = storage.read_batch(&missing)?;
+
+    let vector_size = storage.num_features() * std::mem::size_of::<T>();
+    let mut read_vectors = transmute_slice_to_u8(&read).chunks_exact(vector_size);
+    let mut vectors = zeroed_vec::<T>(indices.len() * storage.num_features());
+    let buffer = transmute_slice_to_u8_mut(&mut vectors);
+    let mut newly_read = Vec::with_capacity(missing.len());
+    for ((&index, vector), destination) in indices
+        .iter()
+        .zip(cached)
+        .zip(buffer.chunks_exact_mut(vector_size))
+    {
+        match vector {
+            Some(vector) => destination.copy_from_slice(&vector),
+            None => {
+                let vector = read_vectors
+                    .next()
+                    .ok_or_else(|| anyhow!("Vector {} was not read", index))?;
+                destination.copy_from_slice(vector);
+                newly_read.push((index, Arc::new(vector.to_vec())));
+            }
+        }
+    }
+    Ok((vectors, newly_read))
+}
+
+/// Allocates `len` values with all their bytes set to 0, to be filled through their byte view.
+/// Vector values are plain numbers, for which this is a valid value.
+pub fn zeroed_vec<T>(len: usize) -> Vec<T> {
+    let mut vec = Vec::with_capacity(len);
+    // SAFETY: the capacity is at least `len`, and the values are initialized before `set_len`.
+    unsafe {
+        std::ptr::write_bytes(vec.as_mut_ptr(), 0, len);
+        vec.set_len(len);
+    }
+    vec
+}
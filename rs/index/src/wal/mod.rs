@@ -0,0 +1,326 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+
+const OP_INSERT: u8 = 0;
+const OP_DELETE: u8 = 1;
+
+// Length and checksum of the payload, before every record
+const RECORD_HEADER_SIZE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalRecord {
+    Insert {
+        user_id: u128,
+        doc_id: u128,
+        data: Vec<f32>,
+    },
+    Delete {
+        user_id: u128,
+        doc_id: u128,
+    },
+}
+
+impl WalRecord {
+    fn encode(&self, version: u64) -> Vec<u8> {
+        let mut payload = version.to_le_bytes().to_vec();
+        match self {
+            WalRecord::Insert {
+                user_id,
+                doc_id,
+                data,
+            } => {
+                payload.push(OP_INSERT);
+                payload.extend_from_slice(&user_id.to_le_bytes());
+                payload.extend_from_slice(&doc_id.to_le_bytes());
+                payload.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                for value in data {
+                    payload.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            WalRecord::Delete { user_id, doc_id } => {
+                payload.push(OP_DELETE);
+                payload.extend_from_slice(&user_id.to_le_bytes());
+                payload.extend_from_slice(&doc_id.to_le_bytes());
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    fn decode(payload: &[u8]) -> Result<(u64, Self)> {
+        let mut reader = PayloadReader { payload };
+        let version = u64::from_le_bytes(reader.take()?);
+        let [op] = reader.take()?;
+        let user_id = u128::from_le_bytes(reader.take()?);
+        let doc_id = u128::from_le_bytes(reader.take()?);
+        let record = match op {
+            OP_INSERT => {
+                let len = u32::from_le_bytes(reader.take()?) as usize;
+                let data = (0..len)
+                    .map(|_| Ok(f32::from_le_bytes(reader.take()?)))
+                    .collect::<Result<Vec<f32>>>()?;
+                WalRecord::Insert {
+                    user_id,
+                    doc_id,
+                    data,
+                }
+            }
+            OP_DELETE => WalRecord::Delete { user_id, doc_id },
+            _ => return Err(anyhow!("Unknown WAL operation {}", op)),
+        };
+        if !reader.payload.is_empty() {
+            return Err(anyhow!("Trailing bytes in WAL record"));
+        }
+        Ok((version, record))
+    }
+}
+
+struct PayloadReader<'a> {
+    payload: &'a [u8],
+}
+
+impl PayloadReader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.payload.len() < N {
+            return Err(anyhow!("Truncated WAL record"));
+        }
+        let (bytes, rest) = self.payload.split_at(N);
+        self.payload = rest;
+        Ok(bytes.try_into()?)
+    }
+}
+
+/// Write-ahead log of the mutations of an index that are not persisted yet, in
+/// `{base_directory}/wal.bin`. Every record is written before the mutation is applied, so that
+/// the mutation can be applied again after a crash.
+///
+/// Records are tagged with the current version of the log. The owner advances the version when
+/// it starts persisting the mutations logged so far, and checkpoints that version once they are
+/// persisted, which drops their records.
+///
+/// Records are written without fsync: they survive a crash of the process, call `sync` to make
+/// them survive a crash of the machine.
+pub struct Wal {
+    path: String,
+    file: File,
+    version: u64,
+}
+
+impl Wal {
+    pub fn path(base_directory: &str) -> String {
+        format!("{}/wal.bin", base_directory)
+    }
+
+    /// Opens the log in `base_directory`, creating it if needed, and calls `apply` on every
+    /// record in it with its version, oldest first. A record that was only partially written
+    /// when the process crashed is dropped.
+    pub fn open_and_replay(
+        base_directory: &str,
+        mut apply: impl FnMut(u64, &WalRecord) -> Result<()>,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(base_directory)?;
+        let path = Self::path(base_directory);
+        let (records, valid_len) = Self::read_records(&path)?;
+        for (version, record) in &records {
+            apply(*version, record)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+        file.set_len(valid_len)?;
+        Ok(Self {
+            path,
+            file,
+            version: records
+                .iter()
+                .map(|(version, _)| *version)
+                .max()
+                .unwrap_or(0),
+        })
+    }
+
+    /// Reads the valid records of the log at `path`, and the length of the file they span.
+    fn read_records(path: &str) -> Result<(Vec<(u64, WalRecord)>, u64)> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut records = vec![];
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let Some(header) = bytes.get(offset..offset + RECORD_HEADER_SIZE) else {
+                break;
+            };
+            let len = u32::from_le_bytes(header[0..4].try_into()?) as usize;
+            let crc32 = u32::from_le_bytes(header[4..8].try_into()?);
+            let start = offset + RECORD_HEADER_SIZE;
+            let Some(payload) = bytes.get(start..start + len) else {
+                break;
+            };
+            if crc32fast::hash(payload) != crc32 {
+                break;
+            }
+            records.push(WalRecord::decode(payload)?);
+            offset = start + len;
+        }
+        if offset < bytes.len() {
+            warn!(
+                "Dropping {} bytes of incomplete records at the end of {}",
+                bytes.len() - offset,
+                path
+            );
+        }
+        Ok((records, offset as u64))
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Tags the records appended from now on with the next version, and returns it.
+    pub fn advance_version(&mut self) -> u64 {
+        self.version += 1;
+        self.version
+    }
+
+    /// Makes sure that the records appended from now on are tagged with `version` or later,
+    /// e.g. when the log was checkpointed up to `version` and then reopened empty.
+    pub fn advance_to(&mut self, version: u64) {
+        self.version = self.version.max(version);
+    }
+
+    pub fn append(&mut self, record: &WalRecord) -> Result<()> {
+        self.file.write_all(&record.encode(self.version))?;
+        Ok(())
+    }
+
+    /// Drops the records older than `version`.
+    pub fn checkpoint(&mut self, version: u64) -> Result<()> {
+        let (records, _) = Self::read_records(&self.path)?;
+        let tmp_path = format!("{}.tmp", self.path);
+        {
+            let mut tmp_file = File::create(&tmp_path)?;
+            for (record_version, record) in records {
+                if record_version >= version {
+                    tmp_file.write_all(&record.encode(record_version))?;
+                }
+            }
+            tmp_file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        Ok(())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn replay(base_directory: &str) -> Vec<WalRecord> {
+        let mut records = vec![];
+        Wal::open_and_replay(base_directory, |_, record| {
+            records.push(record.clone());
+            Ok(())
+        })
+        .unwrap();
+        records
+    }
+
+    fn insert(doc_id: u128) -> WalRecord {
+        WalRecord::Insert {
+            user_id: 1,
+            doc_id,
+            data: vec![doc_id as f32, 0.5],
+        }
+    }
+
+    #[test]
+    fn test_wal_replay() {
+        let temp_dir = TempDir::new("test_wal_replay").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let delete = WalRecord::Delete {
+            user_id: 1,
+            doc_id: 7,
+        };
+        {
+            let mut wal = Wal::open_and_replay(base_directory, |_, _| Ok(())).unwrap();
+            wal.append(&insert(7)).unwrap();
+            wal.append(&delete).unwrap();
+            wal.append(&insert(8)).unwrap();
+        }
+        assert_eq!(replay(base_directory), vec![insert(7), delete, insert(8)]);
+
+        // Simulate a crash in the middle of writing a record
+        let path = Wal::path(base_directory);
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+        let mut wal = Wal::open_and_replay(base_directory, |_, _| Ok(())).unwrap();
+        wal.append(&insert(9)).unwrap();
+        assert_eq!(
+            replay(base_directory),
+            vec![
+                insert(7),
+                WalRecord::Delete {
+                    user_id: 1,
+                    doc_id: 7
+                },
+                insert(9)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wal_checkpoint() {
+        let temp_dir = TempDir::new("test_wal_checkpoint").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let mut wal = Wal::open_and_replay(base_directory, |_, _| Ok(())).unwrap();
+        wal.append(&insert(1)).unwrap();
+        assert_eq!(wal.advance_version(), 1);
+        wal.append(&insert(2)).unwrap();
+        assert_eq!(wal.advance_version(), 2);
+        wal.append(&insert(3)).unwrap();
+
+        wal.checkpoint(1).unwrap();
+        assert_eq!(replay(base_directory), vec![insert(2), insert(3)]);
+        // Appends go to the rewritten log
+        wal.append(&insert(4)).unwrap();
+        wal.checkpoint(2).unwrap();
+        assert_eq!(replay(base_directory), vec![insert(3), insert(4)]);
+
+        // The version is restored from the records
+        let mut wal = Wal::open_and_replay(base_directory, |_, _| Ok(())).unwrap();
+        assert_eq!(wal.version(), 2);
+        wal.advance_to(5);
+        assert_eq!(wal.version(), 5);
+        wal.advance_to(3);
+        assert_eq!(wal.version(), 5);
+    }
+}
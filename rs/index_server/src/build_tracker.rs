@@ -0,0 +1,459 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use dashmap::DashMap;
+use index_writer::config::{
+    HnswConfigWithBase, IndexWriterConfig, IvfConfigWithBase, SpannConfigWithBase,
+};
+use index_writer::index_writer::IndexWriter;
+use index_writer::input::jsonl::JsonlInput;
+use index_writer::input::sharded::ShardedInput;
+use index_writer::input::{Input, Row};
+use log::{error, info};
+use proto::muopdb::IndexType;
+
+pub type BuildId = String;
+
+// How many rows are read between two progress updates
+const PROGRESS_REPORT_INTERVAL: u64 = 1000;
+
+// How long the status of a finished build can still be polled
+const DEFAULT_FINISHED_BUILD_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildState {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildStatus {
+    pub state: BuildState,
+    // Human-readable description of what the build is currently doing
+    pub phase: String,
+    // Rows read so far in the current pass over the input
+    pub vectors_processed: u64,
+    pub total_vectors: u64,
+    pub submitted_at: Instant,
+    // Set once the build completed or failed
+    pub finished_at: Option<Instant>,
+    pub error: Option<String>,
+}
+
+impl BuildStatus {
+    fn new(total_vectors: u64) -> Self {
+        Self {
+            state: BuildState::Pending,
+            phase: "Pending".to_string(),
+            vectors_processed: 0,
+            total_vectors,
+            submitted_at: Instant::now(),
+            finished_at: None,
+            error: None,
+        }
+    }
+
+    pub fn percent_complete(&self) -> f32 {
+        match self.state {
+            BuildState::Completed => 100.0,
+            _ if self.total_vectors == 0 => 0.0,
+            _ => self.vectors_processed as f32 * 100.0 / self.total_vectors as f32,
+        }
+    }
+
+    /// Time since the build was submitted, or the total build time once it has finished.
+    pub fn elapsed(&self) -> Duration {
+        self.finished_at
+            .unwrap_or_else(Instant::now)
+            .saturating_duration_since(self.submitted_at)
+    }
+}
+
+/// Parses an index writer config in the same YAML format as the `index_writer` binary.
+pub fn parse_index_writer_config(
+    index_type: IndexType,
+    config: &str,
+    output_path: &str,
+) -> Result<IndexWriterConfig> {
+    let config = match index_type {
+        IndexType::Hnsw => {
+            let mut config: HnswConfigWithBase = serde_yaml::from_str(config)?;
+            config.base_config.output_path = output_path.to_string();
+            IndexWriterConfig::Hnsw(config)
+        }
+        IndexType::Ivf => {
+            let mut config: IvfConfigWithBase = serde_yaml::from_str(config)?;
+            config.base_config.output_path = output_path.to_string();
+            IndexWriterConfig::Ivf(config)
+        }
+        IndexType::Spann => {
+            let mut config: SpannConfigWithBase = serde_yaml::from_str(config)?;
+            config.base_config.output_path = output_path.to_string();
+            IndexWriterConfig::Spann(config)
+        }
+    };
+    Ok(config)
+}
+
+/// Keeps track of index builds running in the background, so that clients can poll their
+/// progress instead of holding a request open for the whole build. Finished builds are
+/// forgotten after a retention period.
+#[derive(Clone)]
+pub struct BuildTracker {
+    builds: Arc<DashMap<BuildId, BuildStatus>>,
+    finished_build_retention: Duration,
+}
+
+impl Default for BuildTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildTracker {
+    pub fn new() -> Self {
+        Self::new_with_retention(DEFAULT_FINISHED_BUILD_RETENTION)
+    }
+
+    pub fn new_with_retention(finished_build_retention: Duration) -> Self {
+        Self {
+            builds: Arc::new(DashMap::new()),
+            finished_build_retention,
+        }
+    }
+
+    /// Starts building an index from the JSONL files at `input_paths` on the blocking thread
+    /// pool and returns immediately with the id of the build. The inputs are opened by the
+    /// build, so failing to open one fails the build.
+    pub fn submit(&self, config: IndexWriterConfig, input_paths: Vec<PathBuf>) -> BuildId {
+        self.evict_finished_builds();
+        let build_id = new_build_id();
+        self.builds.insert(build_id.clone(), BuildStatus::new(0));
+
+        let builds = self.builds.clone();
+        let id = build_id.clone();
+        tokio::task::spawn_blocking(move || {
+            update(&builds, &id, |status| {
+                status.state = BuildState::Running;
+                status.phase = "Opening input".to_string();
+            });
+            info!("Starting index build {}", id);
+
+            let result = open_inputs(&input_paths).and_then(|input| {
+                update(&builds, &id, |status| {
+                    status.total_vectors = input.num_rows() as u64;
+                });
+                let mut index_writer = IndexWriter::new(config)?;
+                let mut input = ProgressInput::new(input, builds.clone(), id.clone());
+                index_writer.process(&mut input)
+            });
+
+            update(&builds, &id, |status| {
+                status.finished_at = Some(Instant::now());
+                match &result {
+                    Ok(_) => {
+                        status.state = BuildState::Completed;
+                        status.phase = "Completed".to_string();
+                        status.vectors_processed = status.total_vectors;
+                    }
+                    Err(e) => {
+                        status.state = BuildState::Failed;
+                        status.phase = "Failed".to_string();
+                        status.error = Some(e.to_string());
+                    }
+                }
+            });
+            match result {
+                Ok(_) => info!("Index build {} completed", id),
+                Err(e) => error!("Index build {} failed: {}", id, e),
+            }
+        });
+        build_id
+    }
+
+    pub fn get(&self, build_id: &str) -> Option<BuildStatus> {
+        self.evict_finished_builds();
+        self.builds.get(build_id).map(|status| status.clone())
+    }
+
+    /// Removes the builds that finished more than the retention period ago.
+    fn evict_finished_builds(&self) {
+        let retention = self.finished_build_retention;
+        self.builds.retain(|_, status| {
+            status
+                .finished_at
+                .is_none_or(|finished_at| finished_at.elapsed() < retention)
+        });
+    }
+}
+
+/// Opens the JSONL files at `input_paths` as a single input.
+fn open_inputs(input_paths: &[PathBuf]) -> Result<ShardedInput<JsonlInput>> {
+    let inputs = input_paths
+        .iter()
+        .map(|input_path| {
+            JsonlInput::new(&input_path.to_string_lossy(), false)
+                .map_err(|e| anyhow!("Failed to open input {}: {}", input_path.display(), e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ShardedInput::new(inputs))
+}
+
+fn update(
+    builds: &DashMap<BuildId, BuildStatus>,
+    build_id: &str,
+    f: impl FnOnce(&mut BuildStatus),
+) {
+    if let Some(mut status) = builds.get_mut(build_id) {
+        f(&mut status);
+    }
+}
+
+/// Random (version 4) UUID, formatted as 8-4-4-4-12 hex digits.
+fn new_build_id() -> BuildId {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Input wrapper reporting how far the index writer got into the input. The writer may read
+/// the input several times (e.g. once to train the quantizer and once to build the index), so
+/// each pass is reported as its own phase.
+struct ProgressInput<I: Input> {
+    inner: I,
+    builds: Arc<DashMap<BuildId, BuildStatus>>,
+    build_id: BuildId,
+    pass: usize,
+    rows_read: u64,
+}
+
+impl<I: Input> ProgressInput<I> {
+    fn new(inner: I, builds: Arc<DashMap<BuildId, BuildStatus>>, build_id: BuildId) -> Self {
+        let progress_input = Self {
+            inner,
+            builds,
+            build_id,
+            pass: 1,
+            rows_read: 0,
+        };
+        progress_input.report();
+        progress_input
+    }
+
+    fn report(&self) {
+        let pass = self.pass;
+        let rows_read = self.rows_read;
+        update(&self.builds, &self.build_id, |status| {
+            status.phase = format!("Reading input (pass {})", pass);
+            status.vectors_processed = rows_read;
+        });
+    }
+}
+
+impl<I: Input> Input for ProgressInput<I> {
+    fn has_next(&self) -> bool {
+        self.inner.has_next()
+    }
+
+    fn next(&mut self) -> Row<'_> {
+        self.rows_read += 1;
+        if self.rows_read.is_multiple_of(PROGRESS_REPORT_INTERVAL)
+            || self.rows_read == self.inner.num_rows() as u64
+        {
+            self.report();
+        }
+        self.inner.next()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.pass += 1;
+        self.rows_read = 0;
+        self.report();
+    }
+
+    fn num_rows(&self) -> usize {
+        self.inner.num_rows()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.inner.skip_to(row_idx);
+        self.rows_read = row_idx as u64;
+        self.report();
+    }
+
+    fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::enums::{IndexType as ConfigIndexType, QuantizerType};
+    use index_writer::config::{BaseConfig, HnswConfig, QuantizerConfig};
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_new_build_id() {
+        let build_id = new_build_id();
+        assert_eq!(build_id.len(), 36);
+        assert_eq!(&build_id[14..15], "4");
+        assert_ne!(build_id, new_build_id());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_build_tracker() {
+        let temp_dir = TempDir::new("test_build_tracker").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let dimension = 4;
+        let num_rows = 50;
+
+        let input_path = format!("{}/input.jsonl", base_directory);
+        let lines: Vec<String> = (0..num_rows)
+            .map(|i| {
+                let vector: Vec<f32> = (0..dimension).map(|j| (i * j) as f32).collect();
+                format!(
+                    "{{\"id\": {}, \"vector\": {}}}",
+                    i,
+                    serde_json::to_string(&vector).unwrap()
+                )
+            })
+            .collect();
+        std::fs::write(&input_path, lines.join("\n")).unwrap();
+
+        let config = serde_yaml::to_string(&HnswConfigWithBase {
+            base_config: BaseConfig {
+                dimension,
+                max_memory_size: 1024 * 1024,
+                file_size: 1024 * 1024,
+                index_type: ConfigIndexType::Hnsw,
+                ..Default::default()
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::NoQuantizer,
+                num_training_rows: 10,
+                ..Default::default()
+            },
+            hnsw_config: HnswConfig {
+                num_layers: 2,
+                max_num_neighbors: 10,
+                ef_construction: 100,
+                quantize_layer_0: false,
+                max_graph_memory_bytes: None,
+                parallel_construction: false,
+                normalize_on_insert: false,
+            },
+        })
+        .unwrap();
+        let output_path = format!("{}/output", base_directory);
+        let config = parse_index_writer_config(IndexType::Hnsw, &config, &output_path).unwrap();
+
+        let tracker = BuildTracker::new();
+        let build_id = tracker.submit(config, vec![PathBuf::from(input_path)]);
+
+        let status = loop {
+            let status = tracker.get(&build_id).unwrap();
+            if status.finished_at.is_some() {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(status.state, BuildState::Completed);
+        assert_eq!(status.total_vectors, num_rows as u64);
+        assert_eq!(status.vectors_processed, num_rows as u64);
+        assert_eq!(status.percent_complete(), 100.0);
+        assert!(status.error.is_none());
+        assert!(std::path::Path::new(&format!("{}/hnsw", output_path)).exists());
+
+        assert!(tracker.get("unknown").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_build_tracker_failed_build() {
+        let temp_dir = TempDir::new("test_build_tracker_failed_build").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let input_path = format!("{}/input.jsonl", base_directory);
+        std::fs::write(&input_path, "{\"id\": 0, \"vector\": [1.0, 2.0]}").unwrap();
+
+        // The output directory can't be created under a regular file, so the build fails
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: BaseConfig {
+                output_path: format!("{}/output", input_path),
+                dimension: 2,
+                max_memory_size: 1024 * 1024,
+                file_size: 1024 * 1024,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let tracker = BuildTracker::new();
+        let build_id = tracker.submit(config, vec![PathBuf::from(&input_path)]);
+
+        let status = loop {
+            let status = tracker.get(&build_id).unwrap();
+            if status.finished_at.is_some() {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(status.state, BuildState::Failed);
+        assert!(status.error.is_some());
+
+        // A missing input fails the build instead of the request
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase::default());
+        let build_id = tracker.submit(
+            config,
+            vec![PathBuf::from(format!("{}.missing", input_path))],
+        );
+        let status = loop {
+            let status = tracker.get(&build_id).unwrap();
+            if status.finished_at.is_some() {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(status.state, BuildState::Failed);
+        assert!(status.error.unwrap().contains("Failed to open input"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_build_tracker_evicts_finished_builds() {
+        let temp_dir = TempDir::new("test_build_tracker_evicts_finished_builds").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let input_path = format!("{}/input.jsonl", base_directory);
+        std::fs::write(&input_path, "{\"id\": 0, \"vector\": [1.0, 2.0]}").unwrap();
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: BaseConfig {
+                output_path: format!("{}/output", input_path),
+                dimension: 2,
+                max_memory_size: 1024 * 1024,
+                file_size: 1024 * 1024,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let tracker = BuildTracker::new_with_retention(Duration::from_millis(50));
+        let build_id = tracker.submit(config, vec![PathBuf::from(input_path)]);
+        while tracker.get(&build_id).unwrap().finished_at.is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(tracker.get(&build_id).is_none());
+    }
+}
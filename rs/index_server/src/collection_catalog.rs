@@ -1,23 +1,145 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use anyhow::Result;
+use index::collection::reader::CollectionReader;
 use index::collection::Collection;
+use index::multi_spann::user_stats::GlobalStats;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::collection_provider::num_segments_at;
+use crate::metrics::Metrics;
+use crate::rate_limit::RateLimiter;
+
+/// Statistics aggregated over all collections of a catalog.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CatalogStats {
+    pub total_collections: usize,
+    pub total_vectors: u64,
+    pub total_segments: usize,
+    pub total_size_on_disk_bytes: u64,
+    pub min_vectors_per_collection: u64,
+    pub max_vectors_per_collection: u64,
+    pub mean_vectors_per_collection: f64,
+}
+
+/// Counts segments as loading until dropped, see `CollectionCatalog::start_loading`.
+pub struct LoadingGuard {
+    loading_count: Arc<AtomicUsize>,
+    num_segments: usize,
+}
+
+impl Drop for LoadingGuard {
+    fn drop(&mut self) {
+        self.loading_count
+            .fetch_sub(self.num_segments, Ordering::SeqCst);
+    }
+}
 
 pub struct CollectionCatalog {
     collections: HashMap<String, Arc<Collection>>,
+    // Search rate limiters of the collections that have a rate limit
+    rate_limiters: HashMap<String, Arc<RateLimiter>>,
+    metrics: Arc<Metrics>,
+
+    // Number of segments being loaded, the server is not ready until it drops to 0
+    loading_count: Arc<AtomicUsize>,
+    // Whether the collections of the config were loaded once, see `set_initial_load_done`
+    initial_load_done: bool,
 }
 
 impl CollectionCatalog {
-    pub fn new() -> Self {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
         Self {
             collections: HashMap::new(),
+            rate_limiters: HashMap::new(),
+            metrics,
+            loading_count: Arc::new(AtomicUsize::new(0)),
+            initial_load_done: false,
+        }
+    }
+
+    /// Marks `num_segments` segments as loading until the returned guard is dropped. The guard
+    /// doesn't borrow the catalog, so that it can be locked by others while loading.
+    pub fn start_loading(&self, num_segments: usize) -> LoadingGuard {
+        self.loading_count.fetch_add(num_segments, Ordering::SeqCst);
+        LoadingGuard {
+            loading_count: self.loading_count.clone(),
+            num_segments,
+        }
+    }
+
+    pub fn loading_count(&self) -> usize {
+        self.loading_count.load(Ordering::SeqCst)
+    }
+
+    /// Marks the first scan of the collection config as done. Until then, no segment is loading
+    /// but the server has no collections yet, so it is not ready.
+    pub fn set_initial_load_done(&mut self) {
+        self.initial_load_done = true;
+    }
+
+    pub fn initial_load_done(&self) -> bool {
+        self.initial_load_done
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Updates the metrics that are read from the collections: their number of segments, and
+    /// the cache hit ratio of their indexes.
+    pub async fn update_metrics(&self) {
+        let mut global_stats = GlobalStats::default();
+        for (name, collection) in &self.collections {
+            self.metrics
+                .set_active_segments(name, collection.num_segments());
+            global_stats.merge(&collection.global_stats());
+        }
+        let num_queries = global_stats.cache_hits + global_stats.cache_misses;
+        if num_queries > 0 {
+            self.metrics
+                .set_cache_hit_ratio(global_stats.cache_hits as f64 / num_queries as f64);
         }
     }
 
     pub async fn add_collection(&mut self, name: String, collection: Arc<Collection>) {
+        match collection.rate_limit() {
+            Some(config) => {
+                let rate_limiter = RateLimiter::new(config.capacity, config.refill_rate_per_second);
+                self.rate_limiters
+                    .insert(name.clone(), Arc::new(rate_limiter));
+            }
+            None => {
+                self.rate_limiters.remove(&name);
+            }
+        }
         self.collections.insert(name, collection);
     }
 
+    /// Reads the collection at `path` in a background task and adds it as `name`. The catalog
+    /// is only locked to add the collection, so several collections can be loaded in parallel.
+    pub fn load_collection_async(
+        catalog: Arc<Mutex<Self>>,
+        name: String,
+        path: String,
+    ) -> JoinHandle<Result<()>> {
+        tokio::spawn(async move {
+            let loading_guard = catalog.lock().await.start_loading(num_segments_at(&path));
+            let collection = CollectionReader::new(path).read_async().await;
+            drop(loading_guard);
+            catalog.lock().await.add_collection(name, collection?).await;
+            Ok(())
+        })
+    }
+
+    /// Returns the limiter of the searches to the collection, or None if they are not limited.
+    pub async fn get_rate_limiter(&self, name: &str) -> Option<Arc<RateLimiter>> {
+        self.rate_limiters.get(name).cloned()
+    }
+
     pub async fn get_collection(&self, name: &str) -> Option<Arc<Collection>> {
         self.collections
             .get(name)
@@ -30,6 +152,44 @@ impl CollectionCatalog {
         v
     }
 
+    /// Returns the version of the collection's table of contents, which is incremented every
+    /// time segments are flushed or added. Clients can cache results and compare versions to
+    /// know when to re-fetch.
+    pub async fn get_version(&self, name: &str) -> Option<u64> {
+        self.collections
+            .get(name)
+            .map(|collection| collection.current_version())
+    }
+
+    /// Returns statistics over all collections. Min, max and mean are 0 when the catalog is
+    /// empty.
+    pub async fn aggregate_stats(&self) -> CatalogStats {
+        let mut stats = CatalogStats {
+            total_collections: self.collections.len(),
+            ..Default::default()
+        };
+        let vectors_per_collection: Vec<u64> = self
+            .collections
+            .values()
+            .map(|collection| {
+                stats.total_segments += collection.num_segments();
+                stats.total_size_on_disk_bytes += collection.size_in_bytes();
+                collection.num_vectors()
+            })
+            .collect();
+
+        stats.total_vectors = vectors_per_collection.iter().sum();
+        stats.min_vectors_per_collection =
+            vectors_per_collection.iter().copied().min().unwrap_or(0);
+        stats.max_vectors_per_collection =
+            vectors_per_collection.iter().copied().max().unwrap_or(0);
+        if !vectors_per_collection.is_empty() {
+            stats.mean_vectors_per_collection =
+                stats.total_vectors as f64 / vectors_per_collection.len() as f64;
+        }
+        stats
+    }
+
     pub async fn collection_exists(&self, name: &str) -> bool {
         self.collections.contains_key(name)
     }
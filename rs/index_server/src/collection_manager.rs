@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use index::collection::Collection;
-use log::{info, warn};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use utils::io::get_latest_version;
@@ -65,7 +65,14 @@ impl CollectionManager {
         )
         .unwrap();
 
-        match self.collection_provider.read_collection(&collection_name) {
+        let loading_guard = self
+            .collection_catalog
+            .lock()
+            .await
+            .start_loading(self.collection_provider.num_segments(&collection_name));
+        let collection_opt = self.collection_provider.read_collection(&collection_name);
+        drop(loading_guard);
+        match collection_opt {
             Some(collection) => {
                 self.collection_catalog
                     .lock()
@@ -156,22 +163,71 @@ impl CollectionManager {
             // TODO(hicder): Remove collections that are not in the new config
             let collections_to_add =
                 Self::get_collections_to_add(&current_collection_names, &new_collection_names);
-            for collection_name in collections_to_add.iter() {
-                info!("Fetching collection {}", collection_name);
-                let collection_opt = self.collection_provider.read_collection(collection_name);
-                if let Some(collection) = collection_opt {
-                    self.collection_catalog
-                        .lock()
-                        .await
-                        .add_collection(collection_name.clone(), collection)
-                        .await;
-                } else {
-                    warn!("Failed to fetch collection {}", collection_name);
+            // Load the new collections in parallel
+            let handles = collections_to_add
+                .iter()
+                .map(|collection_name| {
+                    info!("Fetching collection {}", collection_name);
+                    let collection_path = format!(
+                        "{}/{}",
+                        self.collection_provider.data_directory(),
+                        collection_name
+                    );
+                    CollectionCatalog::load_collection_async(
+                        self.collection_catalog.clone(),
+                        collection_name.clone(),
+                        collection_path,
+                    )
+                })
+                .collect::<Vec<_>>();
+            for (collection_name, handle) in collections_to_add.iter().zip(handles) {
+                match handle.await {
+                    Ok(Ok(())) => {
+                        if let Some(version) = self
+                            .collection_catalog
+                            .lock()
+                            .await
+                            .get_version(collection_name)
+                            .await
+                        {
+                            info!(
+                                "Added collection {} at version {}",
+                                collection_name, version
+                            );
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        warn!("Failed to fetch collection {}: {}", collection_name, e);
+                    }
+                    Err(e) => {
+                        warn!("Task fetching collection {} failed: {}", collection_name, e);
+                    }
                 }
             }
         } else {
             info!("No new version available");
         }
+        self.collection_catalog.lock().await.set_initial_load_done();
         Ok(())
     }
+
+    /// Deletes the expired segments of every collection. Returns the number of segments purged.
+    pub async fn purge_expired_segments(&self) -> usize {
+        let collection_catalog = self.collection_catalog.lock().await;
+        let mut num_purged = 0;
+        for name in collection_catalog.get_all_collection_names_sorted().await {
+            let Some(collection) = collection_catalog.get_collection(&name).await else {
+                continue;
+            };
+            match collection.purge_expired_segments() {
+                Ok(0) => {}
+                Ok(count) => {
+                    info!("Purged {} expired segments of collection {}", count, name);
+                    num_purged += count;
+                }
+                Err(e) => error!("Failed to purge expired segments of {}: {}", name, e),
+            }
+        }
+        num_purged
+    }
 }
@@ -1,7 +1,8 @@
 use std::sync::Arc;
 
 use index::collection::reader::CollectionReader;
-use index::collection::Collection;
+use index::collection::{Collection, TableOfContent};
+use utils::io::get_latest_version;
 
 pub struct CollectionProvider {
     data_directory: String,
@@ -24,7 +25,25 @@ impl CollectionProvider {
         }
     }
 
+    /// Number of segments in the latest version of the collection, 0 if it can't be read.
+    pub fn num_segments(&self, name: &str) -> usize {
+        num_segments_at(&format!("{}/{}", self.data_directory, name))
+    }
+
     pub fn data_directory(&self) -> &str {
         &self.data_directory
     }
 }
+
+/// Number of segments in the latest version of the collection at `collection_path`, 0 if it
+/// can't be read.
+pub fn num_segments_at(collection_path: &str) -> usize {
+    let Ok(latest_version) = get_latest_version(collection_path) else {
+        return 0;
+    };
+    let toc_path = format!("{}/version_{}", collection_path, latest_version);
+    std::fs::File::open(toc_path)
+        .ok()
+        .and_then(|file| serde_json::from_reader::<_, TableOfContent>(file).ok())
+        .map_or(0, |toc| toc.toc.len())
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use proto::muopdb::health_server::Health;
+use proto::muopdb::{LivenessRequest, LivenessResponse, ReadinessRequest, ReadinessResponse};
+use tokio::sync::Mutex;
+use tonic::{Request, Response, Status};
+
+use crate::collection_catalog::CollectionCatalog;
+
+/// Liveness and readiness probes of the server.
+pub struct HealthService {
+    collection_catalog: Arc<Mutex<CollectionCatalog>>,
+}
+
+impl HealthService {
+    pub fn new(collection_catalog: Arc<Mutex<CollectionCatalog>>) -> Self {
+        Self { collection_catalog }
+    }
+}
+
+#[tonic::async_trait]
+impl Health for HealthService {
+    async fn liveness(
+        &self,
+        _request: Request<LivenessRequest>,
+    ) -> Result<Response<LivenessResponse>, Status> {
+        Ok(Response::new(LivenessResponse { ok: true }))
+    }
+
+    async fn readiness(
+        &self,
+        _request: Request<ReadinessRequest>,
+    ) -> Result<Response<ReadinessResponse>, Status> {
+        let collection_catalog = self.collection_catalog.lock().await;
+        let loading_count = collection_catalog.loading_count();
+        Ok(Response::new(ReadinessResponse {
+            ok: collection_catalog.initial_load_done() && loading_count == 0,
+            loading_segments: loading_count as i32,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+
+    async fn readiness(service: &HealthService) -> ReadinessResponse {
+        service
+            .readiness(Request::new(ReadinessRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let catalog = Arc::new(Mutex::new(CollectionCatalog::new(metrics)));
+        let service = HealthService::new(catalog.clone());
+
+        // Not ready before the collections are loaded once, even though nothing is loading
+        let response = readiness(&service).await;
+        assert!(!response.ok);
+        assert_eq!(response.loading_segments, 0);
+
+        catalog.lock().await.set_initial_load_done();
+        let loading_guard = catalog.lock().await.start_loading(2);
+        let response = readiness(&service).await;
+        assert!(!response.ok);
+        assert_eq!(response.loading_segments, 2);
+
+        drop(loading_guard);
+        let response = readiness(&service).await;
+        assert!(response.ok);
+        assert_eq!(response.loading_segments, 0);
+
+        let response = service
+            .liveness(Request::new(LivenessRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.ok);
+    }
+}
@@ -1,36 +1,127 @@
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use std::vec;
 
-use config::collection::CollectionConfig;
+use config::collection::{CollectionConfig, TokenBucketConfig};
 use index::utils::SearchContext;
 use log::info;
+use prost::Message;
 use proto::muopdb::index_server_server::IndexServer;
 use proto::muopdb::{
-    CreateCollectionRequest, CreateCollectionResponse, FlushRequest, FlushResponse,
-    GetSegmentsRequest, GetSegmentsResponse, InsertPackedRequest, InsertPackedResponse,
-    InsertRequest, InsertResponse, SearchRequest, SearchResponse,
+    BuildIndexRequest, BuildIndexResponse, BuildStatusResponse, CreateCollectionRequest,
+    CreateCollectionResponse, DescribeIndexRequest, DescribeIndexResponse, FlushRequest,
+    FlushResponse, GetBuildStatusRequest, GetCatalogStatsRequest, GetCatalogStatsResponse,
+    GetSegmentsRequest, GetSegmentsResponse, GetUserStatsRequest, GetUserStatsResponse, IndexType,
+    InsertPackedRequest, InsertPackedResponse, InsertRequest, InsertResponse, SearchRequest,
+    SearchResponse,
 };
 use tokio::sync::Mutex;
 use utils::mem::{lows_and_highs_to_u128s, transmute_u8_to_slice, u128s_to_lows_highs};
 
+use crate::build_tracker::{parse_index_writer_config, BuildState, BuildTracker};
 use crate::collection_catalog::CollectionCatalog;
 use crate::collection_manager::CollectionManager;
+use crate::metrics::Metrics;
+use crate::rate_limit::rate_limit_exceeded;
 
 pub struct IndexServerImpl {
     pub collection_catalog: Arc<Mutex<CollectionCatalog>>,
     pub collection_manager: Arc<Mutex<CollectionManager>>,
+    // Index builds submitted through `BuildIndex`
+    pub build_tracker: BuildTracker,
+    // Directory under which `BuildIndex` reads its inputs and writes its outputs. `BuildIndex`
+    // is disabled when unset.
+    pub build_root: Option<PathBuf>,
+    pub metrics: Arc<Metrics>,
 }
 
 impl IndexServerImpl {
     pub fn new(
         index_catalog: Arc<Mutex<CollectionCatalog>>,
         collection_manager: Arc<Mutex<CollectionManager>>,
+        metrics: Arc<Metrics>,
     ) -> Self {
         Self {
             collection_catalog: index_catalog,
             collection_manager,
+            build_tracker: BuildTracker::new(),
+            build_root: None,
+            metrics,
         }
     }
+
+    pub fn with_build_root(mut self, build_root: Option<PathBuf>) -> Self {
+        self.build_root = build_root;
+        self
+    }
+
+    /// Resolves `path` of a `BuildIndex` request against the build root. Absolute paths and
+    /// paths leaving the root are rejected.
+    fn resolve_build_path(&self, path: &str) -> Result<PathBuf, tonic::Status> {
+        let build_root = self
+            .build_root
+            .as_ref()
+            .ok_or_else(|| tonic::Status::failed_precondition("No build root is configured"))?;
+        let relative_path = Path::new(path);
+        if path.is_empty()
+            || !relative_path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(tonic::Status::invalid_argument(format!(
+                "Path {} must be relative to the build root, without ..",
+                path
+            )));
+        }
+        let resolved_path = build_root.join(relative_path);
+        // Symlinks could still point outside of the root
+        if let Ok(canonical_path) = resolved_path.canonicalize() {
+            let canonical_root = build_root.canonicalize().map_err(|e| {
+                tonic::Status::internal(format!("Failed to resolve the build root: {}", e))
+            })?;
+            if !canonical_path.starts_with(canonical_root) {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "Path {} is outside of the build root",
+                    path
+                )));
+            }
+        }
+        Ok(resolved_path)
+    }
+}
+
+/// Parses the `grpc-timeout` header that clients send when the call has a deadline, e.g. "100m"
+/// for 100 milliseconds.
+fn grpc_timeout(metadata: &tonic::metadata::MetadataMap) -> Option<Duration> {
+    let value = metadata.get("grpc-timeout")?.to_str().ok()?;
+    if value.is_empty() {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount.saturating_mul(3600))),
+        "M" => Some(Duration::from_secs(amount.saturating_mul(60))),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// DEADLINE_EXCEEDED status of a search that timed out. The partial results found before the
+/// deadline are attached to the details of the status, as an encoded `SearchResponse`.
+fn search_timed_out(response: &SearchResponse) -> tonic::Status {
+    tonic::Status::with_details(
+        tonic::Code::DeadlineExceeded,
+        format!(
+            "Search timed out, {} partial results attached",
+            response.scores.len()
+        ),
+        response.encode_to_vec().into(),
+    )
 }
 
 #[tonic::async_trait]
@@ -39,6 +130,7 @@ impl IndexServer for IndexServerImpl {
         &self,
         request: tonic::Request<CreateCollectionRequest>,
     ) -> Result<tonic::Response<CreateCollectionResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("create_collection");
         let mut collection_config = CollectionConfig::default();
         let req = request.into_inner();
         let collection_name = req.collection_name;
@@ -110,6 +202,24 @@ impl IndexServer for IndexServerImpl {
         if let Some(reindex) = req.reindex {
             collection_config.reindex = reindex;
         }
+        if let Some(ttl_seconds) = req.ttl_seconds {
+            collection_config.ttl_seconds = Some(ttl_seconds);
+        }
+        if let Some(max_segment_vectors) = req.max_segment_vectors {
+            collection_config.max_segment_vectors = max_segment_vectors as usize;
+        }
+        if let Some(ef_search) = req.ef_search {
+            collection_config.ef_search = ef_search;
+        }
+        if let (Some(capacity), Some(refill_rate_per_second)) = (
+            req.rate_limit_capacity,
+            req.rate_limit_refill_rate_per_second,
+        ) {
+            collection_config.rate_limit = Some(TokenBucketConfig {
+                capacity,
+                refill_rate_per_second,
+            });
+        }
 
         let mut collection_manager_locked = self.collection_manager.lock().await;
         if collection_manager_locked
@@ -138,29 +248,41 @@ impl IndexServer for IndexServerImpl {
         &self,
         request: tonic::Request<SearchRequest>,
     ) -> Result<tonic::Response<SearchResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("search");
         let start = std::time::Instant::now();
+        let timeout = grpc_timeout(request.metadata());
         let req = request.into_inner();
         let collection_name = req.collection_name;
         let vec = req.vector;
         let k = req.top_k;
         let record_metrics = req.record_metrics;
-        let ef_construction = req.ef_construction;
         let user_ids = lows_and_highs_to_u128s(&req.low_user_ids, &req.high_user_ids);
 
-        let collection_opt = self
-            .collection_catalog
-            .lock()
-            .await
-            .get_collection(&collection_name)
-            .await;
+        let (collection_opt, rate_limiter) = {
+            let collection_catalog = self.collection_catalog.lock().await;
+            (
+                collection_catalog.get_collection(&collection_name).await,
+                collection_catalog.get_rate_limiter(&collection_name).await,
+            )
+        };
+        if let Some(rate_limiter) = rate_limiter {
+            rate_limiter.try_acquire().map_err(rate_limit_exceeded)?;
+        }
         if let Some(collection) = collection_opt {
+            let _query_timer = self.metrics.start_query_timer(&collection_name);
             let mut search_context = SearchContext::new(record_metrics);
+            if let Some(timeout) = timeout {
+                search_context.set_timeout(timeout);
+            }
+            let ef_search = req
+                .ef_search
+                .unwrap_or_else(|| collection.default_ef_search());
             if let Ok(snapshot) = collection.get_snapshot() {
                 let result = snapshot.search_for_ids(
                     &user_ids,
                     &vec,
                     k as usize,
-                    ef_construction,
+                    ef_search,
                     &mut search_context,
                 );
 
@@ -181,12 +303,17 @@ impl IndexServer for IndexServerImpl {
                             "[{}] Searched collection in {:?}",
                             collection_name, duration
                         );
-                        return Ok(tonic::Response::new(SearchResponse {
+                        let response = SearchResponse {
                             low_ids,
                             high_ids,
                             scores,
                             num_pages_accessed: search_context.num_pages_accessed() as u64,
-                        }));
+                            collection_version: snapshot.version(),
+                        };
+                        if search_context.timed_out {
+                            return Err(search_timed_out(&response));
+                        }
+                        return Ok(tonic::Response::new(response));
                     }
                     None => {
                         return Ok(tonic::Response::new(SearchResponse {
@@ -194,6 +321,7 @@ impl IndexServer for IndexServerImpl {
                             high_ids: vec![],
                             scores: vec![],
                             num_pages_accessed: 0,
+                            collection_version: snapshot.version(),
                         }));
                     }
                 }
@@ -214,6 +342,7 @@ impl IndexServer for IndexServerImpl {
         &self,
         request: tonic::Request<InsertRequest>,
     ) -> Result<tonic::Response<InsertResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("insert");
         let start = std::time::Instant::now();
         let req = request.into_inner();
         let collection_name = req.collection_name;
@@ -244,6 +373,7 @@ impl IndexServer for IndexServerImpl {
                         // TODO(hicder): Handle errors
                         collection.insert_for_users(&user_ids, *id, vector).unwrap()
                     });
+                self.metrics.record_vectors_indexed(ids.len() as u64);
 
                 // log the duration
                 let end = std::time::Instant::now();
@@ -272,6 +402,7 @@ impl IndexServer for IndexServerImpl {
         &self,
         request: tonic::Request<FlushRequest>,
     ) -> Result<tonic::Response<FlushResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("flush");
         let start = std::time::Instant::now();
         let req = request.into_inner();
         let collection_name = req.collection_name;
@@ -306,6 +437,7 @@ impl IndexServer for IndexServerImpl {
         &self,
         request: tonic::Request<InsertPackedRequest>,
     ) -> Result<tonic::Response<InsertPackedResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("insert_packed");
         let start = std::time::Instant::now();
         let req = request.into_inner();
         let collection_name = req.collection_name;
@@ -343,6 +475,7 @@ impl IndexServer for IndexServerImpl {
                         // TODO(hicder): Handle errors
                         collection.insert_for_users(&user_ids, id, vector).unwrap()
                     });
+                self.metrics.record_vectors_indexed(num_docs as u64);
 
                 // log the duration
                 let end = std::time::Instant::now();
@@ -364,6 +497,7 @@ impl IndexServer for IndexServerImpl {
         &self,
         request: tonic::Request<GetSegmentsRequest>,
     ) -> Result<tonic::Response<GetSegmentsResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("get_segments");
         let start = std::time::Instant::now();
         let req = request.into_inner();
         let collection_name = req.collection_name;
@@ -392,4 +526,666 @@ impl IndexServer for IndexServerImpl {
             )),
         }
     }
+
+    async fn describe_index(
+        &self,
+        request: tonic::Request<DescribeIndexRequest>,
+    ) -> Result<tonic::Response<DescribeIndexResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("describe_index");
+        let start = std::time::Instant::now();
+        let req = request.into_inner();
+        let collection_name = req.collection_name;
+
+        let collection_opt = self
+            .collection_catalog
+            .lock()
+            .await
+            .get_collection(&collection_name)
+            .await;
+
+        match collection_opt {
+            Some(collection) => {
+                let (segment_names, descriptions) =
+                    collection.describe_segments().into_iter().unzip();
+                let end = std::time::Instant::now();
+                let duration = end.duration_since(start);
+                info!("[{}] Describe index in {:?}", collection_name, duration);
+
+                Ok(tonic::Response::new(DescribeIndexResponse {
+                    segment_names,
+                    descriptions,
+                }))
+            }
+            None => Err(tonic::Status::new(
+                tonic::Code::NotFound,
+                "Collection not found",
+            )),
+        }
+    }
+
+    async fn get_catalog_stats(
+        &self,
+        _request: tonic::Request<GetCatalogStatsRequest>,
+    ) -> Result<tonic::Response<GetCatalogStatsResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("get_catalog_stats");
+        let stats = self.collection_catalog.lock().await.aggregate_stats().await;
+        Ok(tonic::Response::new(GetCatalogStatsResponse {
+            total_collections: stats.total_collections as u64,
+            total_vectors: stats.total_vectors,
+            total_segments: stats.total_segments as u64,
+            total_size_on_disk_bytes: stats.total_size_on_disk_bytes,
+            min_vectors_per_collection: stats.min_vectors_per_collection,
+            max_vectors_per_collection: stats.max_vectors_per_collection,
+            mean_vectors_per_collection: stats.mean_vectors_per_collection,
+        }))
+    }
+
+    async fn build_index(
+        &self,
+        request: tonic::Request<BuildIndexRequest>,
+    ) -> Result<tonic::Response<BuildIndexResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("build_index");
+        let req = request.into_inner();
+        let index_type = IndexType::from_i32(req.index_type)
+            .ok_or_else(|| tonic::Status::invalid_argument("Invalid index type"))?;
+        if req.input_paths.is_empty() {
+            return Err(tonic::Status::invalid_argument("No input paths"));
+        }
+        let input_paths = req
+            .input_paths
+            .iter()
+            .map(|input_path| self.resolve_build_path(input_path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let output_path = self.resolve_build_path(&req.output_path)?;
+        let output_path = output_path.to_string_lossy();
+        let config = parse_index_writer_config(index_type, &req.config, &output_path)
+            .map_err(|e| tonic::Status::invalid_argument(format!("Invalid config: {}", e)))?;
+
+        // Inputs are opened by the build, so that large files don't block this handler
+        let build_id = self.build_tracker.submit(config, input_paths);
+        info!("Submitted index build {} to {}", build_id, output_path);
+        Ok(tonic::Response::new(BuildIndexResponse { build_id }))
+    }
+
+    async fn get_build_status(
+        &self,
+        request: tonic::Request<GetBuildStatusRequest>,
+    ) -> Result<tonic::Response<BuildStatusResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("get_build_status");
+        let build_id = request.into_inner().build_id;
+        let status = self
+            .build_tracker
+            .get(&build_id)
+            .ok_or_else(|| tonic::Status::not_found(format!("Build {} not found", build_id)))?;
+        let state = match status.state {
+            BuildState::Pending => proto::muopdb::BuildState::BuildPending,
+            BuildState::Running => proto::muopdb::BuildState::BuildRunning,
+            BuildState::Completed => proto::muopdb::BuildState::BuildCompleted,
+            BuildState::Failed => proto::muopdb::BuildState::BuildFailed,
+        };
+        Ok(tonic::Response::new(BuildStatusResponse {
+            build_id,
+            status: state as i32,
+            phase: status.phase.clone(),
+            vectors_processed: status.vectors_processed,
+            total_vectors: status.total_vectors,
+            percent_complete: status.percent_complete(),
+            elapsed_ms: status.elapsed().as_millis() as u64,
+            error: status.error.clone().unwrap_or_default(),
+        }))
+    }
+
+    async fn get_user_stats(
+        &self,
+        request: tonic::Request<GetUserStatsRequest>,
+    ) -> Result<tonic::Response<GetUserStatsResponse>, tonic::Status> {
+        let _timer = self.metrics.start_request_timer("get_user_stats");
+        let req = request.into_inner();
+        let user_id = ((req.high_user_id as u128) << 64) | req.low_user_id as u128;
+
+        let collection_opt = self
+            .collection_catalog
+            .lock()
+            .await
+            .get_collection(&req.collection_name)
+            .await;
+
+        match collection_opt {
+            Some(collection) => {
+                let stats = collection.user_stats(user_id).unwrap_or_default();
+                Ok(tonic::Response::new(GetUserStatsResponse {
+                    query_count: stats.query_count,
+                    last_accessed_epoch_ms: stats.last_accessed_epoch_ms,
+                    cache_hits: stats.cache_hits,
+                    cache_misses: stats.cache_misses,
+                }))
+            }
+            None => Err(tonic::Status::new(
+                tonic::Code::NotFound,
+                "Collection not found",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use index::collection::{BoxedSegmentSearchable, Collection};
+    use index::mock::MockSearchable;
+    use index::utils::IdWithScore;
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::collection_provider::CollectionProvider;
+    use crate::metrics::serve_metrics;
+
+    async fn create_server(base_directory: &str, segment: MockSearchable) -> IndexServerImpl {
+        create_server_with_config(
+            base_directory,
+            CollectionConfig::default_test_config(),
+            segment,
+        )
+        .await
+    }
+
+    async fn create_server_with_config(
+        base_directory: &str,
+        collection_config: CollectionConfig,
+        segment: MockSearchable,
+    ) -> IndexServerImpl {
+        let collection =
+            Arc::new(Collection::new(base_directory.to_string(), collection_config).unwrap());
+        let segment: Arc<BoxedSegmentSearchable> = Arc::new(Box::new(segment));
+        collection
+            .add_segments(vec!["segment".to_string()], vec![segment])
+            .unwrap();
+
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let catalog = Arc::new(Mutex::new(CollectionCatalog::new(metrics.clone())));
+        catalog
+            .lock()
+            .await
+            .add_collection("test_collection".to_string(), collection)
+            .await;
+        let manager = Arc::new(Mutex::new(CollectionManager::new(
+            base_directory.to_string(),
+            CollectionProvider::new(base_directory.to_string()),
+            catalog.clone(),
+        )));
+        IndexServerImpl::new(catalog, manager, metrics)
+    }
+
+    fn search_request(collection_name: &str, vector: Vec<f32>) -> SearchRequest {
+        SearchRequest {
+            collection_name: collection_name.to_string(),
+            vector,
+            top_k: 2,
+            ef_search: Some(10),
+            record_metrics: false,
+            low_user_ids: vec![0],
+            high_user_ids: vec![0],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let temp_dir = TempDir::new("test_index_server_search").unwrap();
+        let mock = MockSearchable::returning(vec![
+            IdWithScore {
+                id: (1u128 << 64) + 3,
+                score: 0.5,
+            },
+            IdWithScore { id: 7, score: 1.0 },
+            IdWithScore { id: 9, score: 2.0 },
+        ]);
+        let server = create_server(temp_dir.path().to_str().unwrap(), mock.clone()).await;
+
+        let response = server
+            .search(tonic::Request::new(search_request(
+                "test_collection",
+                vec![1.0, 2.0],
+            )))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.low_ids, vec![3, 7]);
+        assert_eq!(response.high_ids, vec![1, 0]);
+        assert_eq!(response.scores, vec![0.5, 1.0]);
+        assert_eq!(mock.call_count(), 1);
+        assert_eq!(mock.last_query(), Some(vec![1.0, 2.0]));
+        assert_eq!(response.collection_version, 1);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let temp_dir = TempDir::new("test_index_server_metrics_endpoint").unwrap();
+        let mock = MockSearchable::returning(vec![IdWithScore { id: 7, score: 1.0 }]);
+        let server = create_server(temp_dir.path().to_str().unwrap(), mock).await;
+        for _ in 0..10 {
+            server
+                .search(tonic::Request::new(search_request(
+                    "test_collection",
+                    vec![1.0, 2.0],
+                )))
+                .await
+                .unwrap();
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_metrics(listener, server.collection_catalog.clone()));
+        let client = hyper::Client::new();
+        let response = client
+            .get(format!("http://{}/metrics", addr).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("query_latency_seconds_count{collection=\"test_collection\"} 10"));
+        // Searching a mock segment takes well under a second
+        assert!(text
+            .contains("query_latency_seconds_bucket{collection=\"test_collection\",le=\"1\"} 10"));
+        assert!(text.contains("request_latency_seconds_count{method=\"search\"} 10"));
+        assert!(text.contains("active_segments{collection=\"test_collection\"} 1"));
+
+        let response = client
+            .get(format!("http://{}/unknown", addr).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_collection_version() {
+        let temp_dir = TempDir::new("test_index_server_collection_version").unwrap();
+        let mock = MockSearchable::returning(vec![]);
+        let server = create_server(temp_dir.path().to_str().unwrap(), mock.clone()).await;
+
+        let catalog = server.collection_catalog.lock().await;
+        assert_eq!(catalog.get_version("test_collection").await, Some(1));
+        assert_eq!(catalog.get_version("unknown").await, None);
+
+        let segment: Arc<BoxedSegmentSearchable> = Arc::new(Box::new(mock));
+        catalog
+            .get_collection("test_collection")
+            .await
+            .unwrap()
+            .add_segments(vec!["segment_2".to_string()], vec![segment])
+            .unwrap();
+        assert_eq!(catalog.get_version("test_collection").await, Some(2));
+        drop(catalog);
+
+        let response = server
+            .search(tonic::Request::new(search_request(
+                "test_collection",
+                vec![1.0, 2.0],
+            )))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.collection_version, 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_failing_index() {
+        let temp_dir = TempDir::new("test_index_server_search_failing_index").unwrap();
+        let mock = MockSearchable::failing();
+        let server = create_server(temp_dir.path().to_str().unwrap(), mock.clone()).await;
+
+        let response = server
+            .search(tonic::Request::new(search_request(
+                "test_collection",
+                vec![1.0],
+            )))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.low_ids.is_empty());
+        assert_eq!(mock.call_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_collection_not_found() {
+        let temp_dir = TempDir::new("test_index_server_search_not_found").unwrap();
+        let mock = MockSearchable::returning(vec![]);
+        let server = create_server(temp_dir.path().to_str().unwrap(), mock.clone()).await;
+
+        let status = server
+            .search(tonic::Request::new(search_request("unknown", vec![1.0])))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        assert_eq!(mock.call_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_search_rate_limited() {
+        let temp_dir = TempDir::new("test_index_server_search_rate_limited").unwrap();
+        let mock = MockSearchable::returning(vec![IdWithScore { id: 7, score: 1.0 }]);
+        let collection_config = CollectionConfig {
+            rate_limit: Some(TokenBucketConfig {
+                capacity: 100,
+                refill_rate_per_second: 100,
+            }),
+            ..CollectionConfig::default_test_config()
+        };
+        let server = create_server_with_config(
+            temp_dir.path().to_str().unwrap(),
+            collection_config,
+            mock.clone(),
+        )
+        .await;
+
+        let mut num_exhausted = 0;
+        for _ in 0..200 {
+            match server
+                .search(tonic::Request::new(search_request(
+                    "test_collection",
+                    vec![1.0, 2.0],
+                )))
+                .await
+            {
+                Ok(_) => {}
+                Err(status) => {
+                    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+                    num_exhausted += 1;
+                }
+            }
+        }
+        assert!(num_exhausted >= 50, "{} searches rejected", num_exhausted);
+        // Rejected searches don't reach the segments
+        assert_eq!(mock.call_count(), 200 - num_exhausted);
+    }
+
+    #[tokio::test]
+    async fn test_describe_index() {
+        let temp_dir = TempDir::new("test_index_server_describe_index").unwrap();
+        let mock = MockSearchable::returning(vec![]);
+        let server = create_server(temp_dir.path().to_str().unwrap(), mock).await;
+
+        let response = server
+            .describe_index(tonic::Request::new(DescribeIndexRequest {
+                collection_name: "test_collection".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.segment_names, vec!["segment".to_string()]);
+        assert_eq!(response.descriptions, vec!["Mock index\n".to_string()]);
+
+        let status = server
+            .describe_index(tonic::Request::new(DescribeIndexRequest {
+                collection_name: "unknown".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_get_catalog_stats() {
+        let temp_dir = TempDir::new("test_index_server_get_catalog_stats").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let mock = MockSearchable::returning(vec![
+            IdWithScore { id: 1, score: 0.5 },
+            IdWithScore { id: 2, score: 1.0 },
+        ]);
+        for name in ["first", "second"] {
+            std::fs::create_dir_all(format!("{}/{}", base_directory, name)).unwrap();
+        }
+        let server = create_server(&format!("{}/first", base_directory), mock).await;
+
+        // Second collection with two segments of one vector each.
+        let collection = Arc::new(
+            Collection::new(
+                format!("{}/second", base_directory),
+                CollectionConfig::default_test_config(),
+            )
+            .unwrap(),
+        );
+        for name in ["segment_1", "segment_2"] {
+            let segment: Arc<BoxedSegmentSearchable> =
+                Arc::new(Box::new(MockSearchable::returning(vec![IdWithScore {
+                    id: 3,
+                    score: 0.1,
+                }])));
+            collection
+                .add_segments(vec![name.to_string()], vec![segment])
+                .unwrap();
+        }
+        server
+            .collection_catalog
+            .lock()
+            .await
+            .add_collection("second".to_string(), collection)
+            .await;
+
+        let response = server
+            .get_catalog_stats(tonic::Request::new(GetCatalogStatsRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.total_collections, 2);
+        assert_eq!(response.total_vectors, 4);
+        assert_eq!(response.total_segments, 3);
+        assert_eq!(response.total_size_on_disk_bytes, 0);
+        assert_eq!(response.min_vectors_per_collection, 2);
+        assert_eq!(response.max_vectors_per_collection, 2);
+        assert_eq!(response.mean_vectors_per_collection, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_get_user_stats() {
+        let temp_dir = TempDir::new("test_index_server_get_user_stats").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let server = create_server(base_directory, MockSearchable::returning(vec![])).await;
+
+        // Collection with a flushed segment, so that searches go through a Multi-SPANN index
+        let collection_directory = format!("{}/stats", base_directory);
+        let config = CollectionConfig::default_test_config();
+        Collection::init_new_collection(collection_directory.clone(), &config).unwrap();
+        let collection = Arc::new(Collection::new(collection_directory, config).unwrap());
+        for i in 0..10 {
+            let v = i as f32;
+            collection
+                .insert_for_users(&[7], i, &[v, v + 1.0, v + 2.0, v + 3.0])
+                .unwrap();
+        }
+        collection.flush().unwrap();
+        server
+            .collection_catalog
+            .lock()
+            .await
+            .add_collection("stats".to_string(), collection)
+            .await;
+
+        let user_stats_request = |collection_name: &str| {
+            tonic::Request::new(GetUserStatsRequest {
+                collection_name: collection_name.to_string(),
+                low_user_id: 7,
+                high_user_id: 0,
+            })
+        };
+        let response = server
+            .get_user_stats(user_stats_request("stats"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.query_count, 0);
+
+        for _ in 0..2 {
+            let mut request = search_request("stats", vec![1.0, 2.0, 3.0, 4.0]);
+            request.low_user_ids = vec![7];
+            request.high_user_ids = vec![0];
+            server.search(tonic::Request::new(request)).await.unwrap();
+        }
+        let response = server
+            .get_user_stats(user_stats_request("stats"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.query_count, 2);
+        assert_eq!(response.cache_hits + response.cache_misses, 2);
+        assert!(response.last_accessed_epoch_ms > 0);
+
+        let status = server
+            .get_user_stats(user_stats_request("unknown"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[test]
+    fn test_grpc_timeout() {
+        let timeout = |value: &str| {
+            let mut metadata = tonic::metadata::MetadataMap::new();
+            metadata.insert("grpc-timeout", value.parse().unwrap());
+            grpc_timeout(&metadata)
+        };
+        assert_eq!(timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(timeout("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(timeout("5S"), Some(Duration::from_secs(5)));
+        assert_eq!(timeout("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(timeout("7u"), Some(Duration::from_micros(7)));
+        assert_eq!(timeout("1n"), Some(Duration::from_nanos(1)));
+        assert_eq!(timeout("10x"), None);
+        assert_eq!(timeout("m"), None);
+        assert_eq!(grpc_timeout(&tonic::metadata::MetadataMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_search_deadline_exceeded() {
+        let temp_dir = TempDir::new("test_index_server_search_deadline_exceeded").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let server = create_server(base_directory, MockSearchable::returning(vec![])).await;
+
+        let collection_directory = format!("{}/deadline", base_directory);
+        let config = CollectionConfig::default_test_config();
+        Collection::init_new_collection(collection_directory.clone(), &config).unwrap();
+        let collection = Arc::new(Collection::new(collection_directory, config).unwrap());
+        for i in 0..100 {
+            let v = i as f32;
+            collection
+                .insert(i, &[v, v + 1.0, v + 2.0, v + 3.0])
+                .unwrap();
+        }
+        collection.flush().unwrap();
+        server
+            .collection_catalog
+            .lock()
+            .await
+            .add_collection("deadline".to_string(), collection)
+            .await;
+
+        let mut request = tonic::Request::new(search_request("deadline", vec![1.0, 2.0, 3.0, 4.0]));
+        request
+            .metadata_mut()
+            .insert("grpc-timeout", "1n".parse().unwrap());
+        let status = server.search(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+        let partial = SearchResponse::decode(status.details()).unwrap();
+        assert!(partial.low_ids.is_empty());
+
+        // Without a deadline, the same search completes
+        let response = server
+            .search(tonic::Request::new(search_request(
+                "deadline",
+                vec![1.0, 2.0, 3.0, 4.0],
+            )))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.low_ids, vec![1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_get_build_status_unknown_build() {
+        let temp_dir = TempDir::new("test_get_build_status_unknown_build").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let server = create_server(base_directory, MockSearchable::returning(vec![])).await;
+
+        let status = server
+            .get_build_status(tonic::Request::new(GetBuildStatusRequest {
+                build_id: "unknown".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_build_index_invalid_config() {
+        let temp_dir = TempDir::new("test_build_index_invalid_config").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let server = create_server(base_directory, MockSearchable::returning(vec![]))
+            .await
+            .with_build_root(Some(PathBuf::from(base_directory)));
+
+        let status = server
+            .build_index(tonic::Request::new(BuildIndexRequest {
+                index_type: IndexType::Hnsw as i32,
+                config: "not: [a valid config".to_string(),
+                input_paths: vec!["input.jsonl".to_string()],
+                output_path: "output".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_build_index_paths_confined_to_build_root() {
+        let temp_dir = TempDir::new("test_build_index_paths_confined_to_build_root").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let build_root = format!("{}/builds", base_directory);
+        std::fs::create_dir(&build_root).unwrap();
+        std::fs::write(
+            format!("{}/input.jsonl", base_directory),
+            "{\"id\": 0, \"vector\": [1.0, 2.0]}",
+        )
+        .unwrap();
+        let request = |input_path: &str, output_path: &str| {
+            tonic::Request::new(BuildIndexRequest {
+                index_type: IndexType::Hnsw as i32,
+                config: String::new(),
+                input_paths: vec![input_path.to_string()],
+                output_path: output_path.to_string(),
+            })
+        };
+
+        let server = create_server(base_directory, MockSearchable::returning(vec![])).await;
+        let status = server
+            .build_index(request("input.jsonl", "output"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+        let server = server.with_build_root(Some(PathBuf::from(&build_root)));
+        for (input_path, output_path) in [
+            ("../input.jsonl", "output"),
+            (&format!("{}/input.jsonl", base_directory) as &str, "output"),
+            ("input.jsonl", "../output"),
+        ] {
+            let status = server
+                .build_index(request(input_path, output_path))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(
+                format!("{}/input.jsonl", base_directory),
+                format!("{}/link.jsonl", build_root),
+            )
+            .unwrap();
+            let status = server
+                .build_index(request("link.jsonl", "output"))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+    }
 }
@@ -1,22 +1,35 @@
+mod build_tracker;
 mod collection_catalog;
 mod collection_manager;
 mod collection_provider;
+mod health;
 mod index_server;
+mod metrics;
+mod rate_limit;
+mod tls;
 
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use clap::Parser;
 use collection_catalog::CollectionCatalog;
 use collection_manager::CollectionManager;
 use collection_provider::CollectionProvider;
+use health::HealthService;
 use index_server::IndexServerImpl;
 use log::{error, info};
+use metrics::{serve_metrics, Metrics};
+use proto::muopdb::health_server::HealthServer;
 use proto::muopdb::index_server_server::IndexServerServer;
+use rate_limit::{RateLimitConfig, RateLimitInterceptor};
+use serde::Deserialize;
+use tls::TlsConfig;
 use tokio::spawn;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 use tonic::transport::Server;
+use utils::warmup::{warm_up, WarmupStrategy};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -32,6 +45,32 @@ struct Args {
 
     #[arg(long)]
     index_data_path: String,
+
+    // Load the index data into memory before serving: "sequential", "mlock" or
+    // "random:<fraction>".
+    #[arg(long)]
+    warmup_strategy: Option<WarmupStrategy>,
+
+    // YAML file with the server settings, see `ServerConfig`
+    #[arg(long)]
+    server_config_path: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ServerConfig {
+    // Per-client request rate limit. Requests are not limited when unset.
+    #[serde(default)]
+    rate_limit: Option<RateLimitConfig>,
+    // Serve over TLS. Connections are plaintext when unset.
+    #[serde(default)]
+    tls: Option<TlsConfig>,
+    // Serve Prometheus metrics over HTTP at /metrics on this port. Not served when unset.
+    #[serde(default)]
+    metrics_port: Option<u16>,
+    // Directory under which `BuildIndex` reads its inputs and writes its outputs. `BuildIndex`
+    // is disabled when unset.
+    #[serde(default)]
+    build_root: Option<String>,
 }
 
 #[tokio::main]
@@ -42,8 +81,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let collection_config_path = arg.index_config_path;
     let collection_data_path = arg.index_data_path;
     let node_id = arg.node_id;
+    let server_config: ServerConfig = match &arg.server_config_path {
+        Some(path) => serde_yaml::from_str(&std::fs::read_to_string(path)?)?,
+        None => ServerConfig::default(),
+    };
+
+    // Keep the report alive while serving, since it owns the regions locked by "mlock".
+    let _warmup_report = match arg.warmup_strategy {
+        Some(strategy) => {
+            let report = warm_up(&collection_data_path, strategy)?;
+            info!(
+                "Warmed up {} of {} bytes in {} files in {:?}",
+                report.warmed_bytes, report.total_bytes, report.num_files, report.duration
+            );
+            Some(report)
+        }
+        None => None,
+    };
 
-    let collection_catalog = Arc::new(Mutex::new(CollectionCatalog::new()));
+    let metrics = Arc::new(Metrics::new()?);
+    let collection_catalog = Arc::new(Mutex::new(CollectionCatalog::new(metrics.clone())));
     let collection_catalog_for_manager = collection_catalog.clone();
     let collection_catalog_for_server = collection_catalog.clone();
 
@@ -67,15 +124,67 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             {
                 error!("Error checking for index manager update: {}", e);
             }
+            collection_manager_clone
+                .lock()
+                .await
+                .purge_expired_segments()
+                .await;
             sleep(std::time::Duration::from_secs(60)).await;
         }
     });
 
-    let server_impl = IndexServerImpl::new(collection_catalog_for_server, collection_manager);
-    Server::builder()
-        .add_service(IndexServerServer::new(server_impl))
-        .serve(addr)
-        .await?;
+    if let Some(metrics_port) = server_config.metrics_port {
+        let listener = std::net::TcpListener::bind(format!("0.0.0.0:{}", metrics_port))?;
+        info!("Serving metrics on port {}", metrics_port);
+        let collection_catalog_for_metrics = collection_catalog.clone();
+        spawn(async move {
+            if let Err(e) = serve_metrics(listener, collection_catalog_for_metrics).await {
+                error!("Metrics server stopped: {}", e);
+            }
+        });
+    }
+
+    let server_impl =
+        IndexServerImpl::new(collection_catalog_for_server, collection_manager, metrics)
+            .with_build_root(server_config.build_root.map(PathBuf::from));
+    let mut server = Server::builder();
+    if let Some(tls_config) = &server_config.tls {
+        info!(
+            "Serving over TLS with certificate {}{}",
+            tls_config.cert_path,
+            if tls_config.ca_path.is_some() {
+                ", requiring client certificates"
+            } else {
+                ""
+            }
+        );
+        server = server.tls_config(tls_config.server_tls_config()?)?;
+    }
+    // Probes are not rate limited, so that a busy server isn't restarted
+    let health_service = HealthServer::new(HealthService::new(collection_catalog.clone()));
+    match server_config.rate_limit {
+        Some(rate_limit_config) => {
+            info!(
+                "Limiting each client to {} requests per second, with bursts of {}",
+                rate_limit_config.max_requests_per_second, rate_limit_config.burst_capacity
+            );
+            server
+                .add_service(IndexServerServer::with_interceptor(
+                    server_impl,
+                    RateLimitInterceptor::new(rate_limit_config),
+                ))
+                .add_service(health_service)
+                .serve(addr)
+                .await?;
+        }
+        None => {
+            server
+                .add_service(IndexServerServer::new(server_impl))
+                .add_service(health_service)
+                .serve(addr)
+                .await?;
+        }
+    }
 
     // TODO(hicder): Add graceful shutdown
     info!("Received signal, shutting down");
@@ -0,0 +1,168 @@
+use std::convert::Infallible;
+use std::net::TcpListener;
+use std::sync::Arc;
+
+use anyhow::Result;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use log::error;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tokio::sync::Mutex;
+
+use crate::collection_catalog::CollectionCatalog;
+
+/// Prometheus metrics of the server, scraped from `/metrics`, see `serve_metrics`.
+pub struct Metrics {
+    registry: Registry,
+    query_latency_seconds: HistogramVec,
+    request_latency_seconds: HistogramVec,
+    vectors_indexed_total: IntCounter,
+    active_segments: IntGaugeVec,
+    cache_hit_ratio: Gauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let query_latency_seconds = HistogramVec::new(
+            HistogramOpts::new("query_latency_seconds", "Latency of searches"),
+            &["collection"],
+        )?;
+        let request_latency_seconds = HistogramVec::new(
+            HistogramOpts::new("request_latency_seconds", "Latency of gRPC requests"),
+            &["method"],
+        )?;
+        let vectors_indexed_total =
+            IntCounter::new("vectors_indexed_total", "Number of vectors inserted")?;
+        let active_segments = IntGaugeVec::new(
+            Opts::new("active_segments", "Number of segments of the collection"),
+            &["collection"],
+        )?;
+        let cache_hit_ratio = Gauge::new(
+            "cache_hit_ratio",
+            "Share of the queries served by an already loaded index",
+        )?;
+
+        let registry = Registry::new();
+        registry.register(Box::new(query_latency_seconds.clone()))?;
+        registry.register(Box::new(request_latency_seconds.clone()))?;
+        registry.register(Box::new(vectors_indexed_total.clone()))?;
+        registry.register(Box::new(active_segments.clone()))?;
+        registry.register(Box::new(cache_hit_ratio.clone()))?;
+
+        Ok(Self {
+            registry,
+            query_latency_seconds,
+            request_latency_seconds,
+            vectors_indexed_total,
+            active_segments,
+            cache_hit_ratio,
+        })
+    }
+
+    /// Records the latency of a search in `collection` when the returned guard is dropped.
+    pub fn start_query_timer(&self, collection: &str) -> HistogramTimer {
+        self.query_latency_seconds
+            .with_label_values(&[collection])
+            .start_timer()
+    }
+
+    /// Records the latency of a gRPC request when the returned guard is dropped.
+    pub fn start_request_timer(&self, method: &str) -> HistogramTimer {
+        self.request_latency_seconds
+            .with_label_values(&[method])
+            .start_timer()
+    }
+
+    pub fn record_vectors_indexed(&self, num_vectors: u64) {
+        self.vectors_indexed_total.inc_by(num_vectors);
+    }
+
+    pub fn set_active_segments(&self, collection: &str, num_segments: usize) {
+        self.active_segments
+            .with_label_values(&[collection])
+            .set(num_segments as i64);
+    }
+
+    pub fn set_cache_hit_ratio(&self, cache_hit_ratio: f64) {
+        self.cache_hit_ratio.set(cache_hit_ratio);
+    }
+
+    /// Returns all metrics in the Prometheus text format.
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+async fn handle_metrics_request(
+    request: Request<Body>,
+    collection_catalog: Arc<Mutex<CollectionCatalog>>,
+) -> Response<Body> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let collection_catalog = collection_catalog.lock().await;
+    collection_catalog.update_metrics().await;
+    match collection_catalog.metrics().encode() {
+        Ok(text) => Response::builder()
+            .header(header::CONTENT_TYPE, TextEncoder::new().format_type())
+            .body(Body::from(text))
+            .unwrap(),
+        Err(e) => {
+            error!("Failed to encode metrics: {}", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap()
+        }
+    }
+}
+
+/// Serves the metrics of the catalog over HTTP at `/metrics` on `listener`, until an error.
+pub async fn serve_metrics(
+    listener: TcpListener,
+    collection_catalog: Arc<Mutex<CollectionCatalog>>,
+) -> Result<()> {
+    listener.set_nonblocking(true)?;
+    let make_service = make_service_fn(move |_| {
+        let collection_catalog = collection_catalog.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let collection_catalog = collection_catalog.clone();
+                async move {
+                    Ok::<_, Infallible>(handle_metrics_request(request, collection_catalog).await)
+                }
+            }))
+        }
+    });
+    Server::from_tcp(listener)?.serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_encode() {
+        let metrics = Metrics::new().unwrap();
+        drop(metrics.start_query_timer("collection"));
+        metrics.record_vectors_indexed(3);
+        metrics.set_active_segments("collection", 2);
+        metrics.set_cache_hit_ratio(0.5);
+
+        let text = metrics.encode().unwrap();
+        assert!(text.contains("query_latency_seconds_count{collection=\"collection\"} 1"));
+        assert!(text.contains("vectors_indexed_total 3"));
+        assert!(text.contains("active_segments{collection=\"collection\"} 2"));
+        assert!(text.contains("cache_hit_ratio 0.5"));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::Deserialize;
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+// Metadata key telling rejected clients how many seconds to wait
+pub const RETRY_AFTER_HEADER: &str = "retry-after";
+
+// How often idle buckets are evicted
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct RateLimitConfig {
+    // Steady-state number of requests allowed per second, for each client
+    pub max_requests_per_second: f32,
+    // Number of requests a client can send at once after being idle
+    pub burst_capacity: u32,
+}
+
+/// Bucket of `capacity` tokens, refilled continuously at `rate` tokens per second. Each request
+/// takes one token.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: u32,
+    rate: f32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, rate: f32, now: Instant) -> Self {
+        Self {
+            capacity,
+            rate,
+            tokens: capacity as f64,
+            last_refill: now,
+        }
+    }
+
+    /// Takes a token if one is available. Otherwise returns how long to wait for the next token.
+    pub fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.capacity as f64);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        if self.rate <= 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.rate as f64,
+        ))
+    }
+
+    /// Whether the bucket is refilled to its capacity at `now`, in which case it behaves like a
+    /// new bucket and can be dropped.
+    pub fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens + elapsed * self.rate as f64 >= self.capacity as f64
+    }
+}
+
+/// Token bucket shared by concurrent requests, such as all the searches of a collection.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u64, refill_rate_per_second: u64) -> Self {
+        Self {
+            bucket: Mutex::new(TokenBucket::new(
+                capacity.min(u32::MAX as u64) as u32,
+                refill_rate_per_second as f32,
+                Instant::now(),
+            )),
+        }
+    }
+
+    /// Takes a token if one is available. Otherwise returns how long to wait for the next token.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        self.bucket.lock().unwrap().try_acquire(Instant::now())
+    }
+}
+
+/// RESOURCE_EXHAUSTED status of a rejected request, telling the client to retry after
+/// `retry_after`, rounded up to the second.
+pub fn rate_limit_exceeded(retry_after: Duration) -> Status {
+    let mut status = Status::resource_exhausted("Rate limit exceeded");
+    let seconds = retry_after.as_secs_f64().ceil().min(u32::MAX as f64) as u32;
+    status
+        .metadata_mut()
+        .insert(RETRY_AFTER_HEADER, MetadataValue::from(seconds.max(1)));
+    status
+}
+
+/// Rejects requests with `Status::resource_exhausted` once a client runs out of tokens. Clients
+/// are identified by their certificate with mutual TLS, or by their IP address otherwise.
+/// Buckets of idle clients are evicted once they are refilled.
+#[derive(Clone)]
+pub struct RateLimitInterceptor {
+    config: RateLimitConfig,
+    buckets: Arc<DashMap<String, TokenBucket>>,
+    last_eviction: Arc<Mutex<Instant>>,
+}
+
+impl RateLimitInterceptor {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Arc::new(DashMap::new()),
+            last_eviction: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    fn client_key(request: &Request<()>) -> String {
+        // Certificates are verified by the TLS handshake, unlike anything in the metadata
+        if let Some(cert) = request
+            .peer_certs()
+            .and_then(|certs| certs.first().cloned())
+        {
+            let mut hasher = DefaultHasher::new();
+            cert.get_ref().hash(&mut hasher);
+            return format!("cert:{:x}", hasher.finish());
+        }
+        match request.remote_addr() {
+            Some(addr) => format!("ip:{}", addr.ip()),
+            None => "unknown".to_string(),
+        }
+    }
+
+    /// Drops the buckets that are full at `now`, at most once per `EVICTION_INTERVAL`.
+    fn evict_idle_buckets(&self, now: Instant) {
+        {
+            let mut last_eviction = self.last_eviction.lock().unwrap();
+            if now.saturating_duration_since(*last_eviction) < EVICTION_INTERVAL {
+                return;
+            }
+            *last_eviction = now;
+        }
+        self.buckets.retain(|_, bucket| !bucket.is_full(now));
+    }
+
+    fn check(&self, key: String, now: Instant) -> Result<(), Duration> {
+        self.evict_idle_buckets(now);
+        self.buckets
+            .entry(key)
+            .or_insert_with(|| {
+                TokenBucket::new(
+                    self.config.burst_capacity,
+                    self.config.max_requests_per_second,
+                    now,
+                )
+            })
+            .try_acquire(now)
+    }
+}
+
+impl Interceptor for RateLimitInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let key = Self::client_key(&request);
+        self.check(key, Instant::now())
+            .map_err(rate_limit_exceeded)?;
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Code;
+
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, 4.0, start);
+        assert!(bucket.try_acquire(start).is_ok());
+        assert!(bucket.try_acquire(start).is_ok());
+        assert_eq!(bucket.try_acquire(start), Err(Duration::from_millis(250)));
+
+        // One token every 250ms, never more than the capacity.
+        assert!(bucket
+            .try_acquire(start + Duration::from_millis(250))
+            .is_ok());
+        assert!(bucket
+            .try_acquire(start + Duration::from_millis(250))
+            .is_err());
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.try_acquire(later).is_ok());
+        assert!(bucket.try_acquire(later).is_ok());
+        assert!(bucket.try_acquire(later).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(3, 1);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire().is_ok());
+        }
+        let retry_after = limiter.try_acquire().unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_rate_limit_interceptor() {
+        let mut interceptor = RateLimitInterceptor::new(RateLimitConfig {
+            max_requests_per_second: 0.5,
+            burst_capacity: 3,
+        });
+        for _ in 0..3 {
+            assert!(interceptor.call(Request::new(())).is_ok());
+        }
+        let status = interceptor.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(
+            status.metadata().get(RETRY_AFTER_HEADER).unwrap(),
+            &MetadataValue::from(2u32)
+        );
+
+        // The metadata doesn't identify clients, so it can't be used to get a new bucket
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("x-api-key", "other".parse().unwrap());
+        assert!(interceptor.call(request).is_err());
+
+        // Other clients have their own bucket.
+        assert!(interceptor
+            .check("ip:10.0.0.1".to_string(), Instant::now())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_rate_limit_interceptor_eviction() {
+        // A token every 100s
+        let interceptor = RateLimitInterceptor::new(RateLimitConfig {
+            max_requests_per_second: 0.01,
+            burst_capacity: 1,
+        });
+        let start = Instant::now();
+        assert!(interceptor.check("ip:10.0.0.1".to_string(), start).is_ok());
+
+        // The first bucket isn't refilled yet, so it is kept
+        let later = start + EVICTION_INTERVAL;
+        assert!(interceptor.check("ip:10.0.0.2".to_string(), later).is_ok());
+        assert!(interceptor.check("ip:10.0.0.1".to_string(), later).is_err());
+        assert_eq!(interceptor.buckets.len(), 2);
+
+        // Now it is, and the second one isn't
+        let much_later = later + EVICTION_INTERVAL;
+        assert!(interceptor
+            .check("ip:10.0.0.3".to_string(), much_later)
+            .is_ok());
+        assert!(!interceptor.buckets.contains_key("ip:10.0.0.1"));
+        assert!(interceptor.buckets.contains_key("ip:10.0.0.2"));
+        assert_eq!(interceptor.buckets.len(), 2);
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct TlsConfig {
+    // PEM-encoded certificate chain of the server
+    pub cert_path: String,
+    // PEM-encoded private key of the server
+    pub key_path: String,
+    // PEM-encoded CA certificate. When set, clients must present a certificate signed by this CA.
+    #[serde(default)]
+    pub ca_path: Option<String>,
+}
+
+impl TlsConfig {
+    /// Reads the certificates and key into a tonic `ServerTlsConfig`.
+    pub fn server_tls_config(&self) -> Result<ServerTlsConfig> {
+        let cert = std::fs::read(&self.cert_path)
+            .with_context(|| format!("Failed to read TLS certificate {}", self.cert_path))?;
+        let key = std::fs::read(&self.key_path)
+            .with_context(|| format!("Failed to read TLS key {}", self.key_path))?;
+        let mut tls_config = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+
+        if let Some(ca_path) = &self.ca_path {
+            let ca = std::fs::read(ca_path)
+                .with_context(|| format!("Failed to read TLS CA certificate {}", ca_path))?;
+            tls_config = tls_config.client_ca_root(Certificate::from_pem(ca));
+        }
+        Ok(tls_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use proto::muopdb::index_server_client::IndexServerClient;
+    use proto::muopdb::index_server_server::IndexServerServer;
+    use proto::muopdb::GetCatalogStatsRequest;
+    use tempdir::TempDir;
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Channel, ClientTlsConfig, Server};
+
+    use super::*;
+    use crate::collection_catalog::CollectionCatalog;
+    use crate::collection_manager::CollectionManager;
+    use crate::collection_provider::CollectionProvider;
+    use crate::index_server::IndexServerImpl;
+    use crate::metrics::Metrics;
+
+    /// Writes a CA, and a server (localhost) and client certificate signed by it, to
+    /// `directory`.
+    fn write_test_certificates(directory: &str) {
+        let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new());
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+        let server = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+            "localhost".to_string(),
+        ]))
+        .unwrap();
+        let client = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+            "client".to_string()
+        ]))
+        .unwrap();
+
+        let files = [
+            ("ca.pem", ca.serialize_pem().unwrap()),
+            ("server.pem", server.serialize_pem_with_signer(&ca).unwrap()),
+            ("server.key", server.serialize_private_key_pem()),
+            ("client.pem", client.serialize_pem_with_signer(&ca).unwrap()),
+            ("client.key", client.serialize_private_key_pem()),
+        ];
+        for (name, contents) in files {
+            std::fs::write(format!("{}/{}", directory, name), contents).unwrap();
+        }
+    }
+
+    fn test_tls_config(certificate_directory: &str, require_client_cert: bool) -> TlsConfig {
+        TlsConfig {
+            cert_path: format!("{}/server.pem", certificate_directory),
+            key_path: format!("{}/server.key", certificate_directory),
+            ca_path: require_client_cert.then(|| format!("{}/ca.pem", certificate_directory)),
+        }
+    }
+
+    /// Starts a TLS server on a random local port and returns its address.
+    async fn start_server(base_directory: &str, tls_config: &TlsConfig) -> String {
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let catalog = Arc::new(Mutex::new(CollectionCatalog::new(metrics.clone())));
+        let manager = Arc::new(Mutex::new(CollectionManager::new(
+            base_directory.to_string(),
+            CollectionProvider::new(base_directory.to_string()),
+            catalog.clone(),
+        )));
+        let server_impl = IndexServerImpl::new(catalog, manager, metrics);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Server::builder()
+            .tls_config(tls_config.server_tls_config().unwrap())
+            .unwrap()
+            .add_service(IndexServerServer::new(server_impl));
+        tokio::spawn(server.serve_with_incoming(TcpListenerStream::new(listener)));
+        format!("https://localhost:{}", port)
+    }
+
+    async fn connect(
+        addr: String,
+        certificate_directory: &str,
+        client_identity: bool,
+    ) -> Result<IndexServerClient<Channel>> {
+        let read = |name: &str| std::fs::read(format!("{}/{}", certificate_directory, name));
+        let mut tls_config = ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(read("ca.pem")?))
+            .domain_name("localhost");
+        if client_identity {
+            tls_config =
+                tls_config.identity(Identity::from_pem(read("client.pem")?, read("client.key")?));
+        }
+        let channel = Channel::from_shared(addr)?
+            .tls_config(tls_config)?
+            .connect()
+            .await?;
+        Ok(IndexServerClient::new(channel))
+    }
+
+    #[test]
+    fn test_parse_tls_config() {
+        let config: TlsConfig =
+            serde_yaml::from_str("cert_path: server.pem\nkey_path: server.key\n").unwrap();
+        assert_eq!(
+            config,
+            TlsConfig {
+                cert_path: "server.pem".to_string(),
+                key_path: "server.key".to_string(),
+                ca_path: None,
+            }
+        );
+
+        let missing = TlsConfig {
+            cert_path: "/does/not/exist.pem".to_string(),
+            ..config
+        };
+        assert!(missing.server_tls_config().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tls_handshake() {
+        let temp_dir = TempDir::new("test_tls_handshake").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        write_test_certificates(base_directory);
+        let addr = start_server(base_directory, &test_tls_config(base_directory, false)).await;
+
+        let mut client = connect(addr, base_directory, false).await.unwrap();
+        let response = client
+            .get_catalog_stats(GetCatalogStatsRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.total_collections, 0);
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls_handshake() {
+        let temp_dir = TempDir::new("test_mutual_tls_handshake").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        write_test_certificates(base_directory);
+        let addr = start_server(base_directory, &test_tls_config(base_directory, true)).await;
+
+        let mut client = connect(addr.clone(), base_directory, true).await.unwrap();
+        let response = client
+            .get_catalog_stats(GetCatalogStatsRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.total_collections, 0);
+
+        // Clients without a certificate are turned away
+        let rejected = match connect(addr, base_directory, false).await {
+            Ok(mut client) => client
+                .get_catalog_stats(GetCatalogStatsRequest {})
+                .await
+                .is_err(),
+            Err(_) => true,
+        };
+        assert!(rejected);
+    }
+}
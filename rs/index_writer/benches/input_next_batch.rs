@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use index_writer::input::jsonl::JsonlInput;
+use index_writer::input::Input;
+use tempdir::TempDir;
+use utils::test_utils::generate_random_vector;
+
+const NUM_VECTORS: usize = 1_000_000;
+const NUM_FEATURES: usize = 16;
+const BATCH_SIZE: usize = 4096;
+
+fn write_input(temp_dir: &TempDir) -> String {
+    let path = format!("{}/input.jsonl", temp_dir.path().to_str().unwrap());
+    let mut writer = BufWriter::new(File::create(&path).unwrap());
+    for id in 0..NUM_VECTORS {
+        let vector = generate_random_vector(NUM_FEATURES);
+        writeln!(writer, "{{\"id\": {}, \"vector\": {:?}}}", id, vector).unwrap();
+    }
+    writer.flush().unwrap();
+    path
+}
+
+fn bench_next_batch(c: &mut Criterion) {
+    let temp_dir = TempDir::new("bench_input_next_batch").unwrap();
+    let path = write_input(&temp_dir);
+    let mut input = JsonlInput::new(&path, false).unwrap();
+
+    let mut group = c.benchmark_group("Input read");
+    group.sample_size(10);
+    group.bench_function("next", |bencher| {
+        bencher.iter(|| {
+            input.reset();
+            let mut sum = 0.0;
+            while input.has_next() {
+                sum += input.next().data[0];
+            }
+            sum
+        })
+    });
+    group.bench_function("next_batch", |bencher| {
+        bencher.iter(|| {
+            input.reset();
+            let mut sum = 0.0;
+            while input.has_next() {
+                for row in input.next_batch(BATCH_SIZE) {
+                    sum += row.data[0];
+                }
+            }
+            sum
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_next_batch);
+criterion_main!(benches);
@@ -1,7 +1,66 @@
-use config::enums::{DistanceType, IndexType, IntSeqEncodingType, QuantizerType};
+use std::sync::Arc;
+
+use config::enums::{DistanceType, IndexType, IntSeqEncodingType, QuantizerType, StorageBackend};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+use crate::progress::BuildProgressCallback;
+
+// Number of rows read from the input at once while building an index
+pub const DEFAULT_INPUT_BATCH_SIZE: usize = 4096;
+
+fn default_input_batch_size() -> usize {
+    DEFAULT_INPUT_BATCH_SIZE
+}
+
+// Number of items processed between two calls to the progress callback
+pub const DEFAULT_PROGRESS_INTERVAL: usize = 10000;
+
+fn default_progress_interval() -> usize {
+    DEFAULT_PROGRESS_INTERVAL
+}
+
+// Largest imbalance coefficient accepted by `auto_num_clusters` when the config doesn't set one
+pub const DEFAULT_TARGET_IMBALANCE: f32 = 1.5;
+
+fn default_target_imbalance() -> f32 {
+    DEFAULT_TARGET_IMBALANCE
+}
+
+// Format of the file the index is built from
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    #[default]
+    Hdf5,
+    // Arrow IPC file with an `id` column of u64 and a `vector` column of fixed size lists of f32
+    Arrow,
+    // NumPy .npy file with a 2D array of float32. Row indexes are used as doc ids.
+    Npy,
+    // NumPy .npz archive, read like `Npy` from one of its arrays
+    Npz,
+}
+
+// Compression of the vector file of IVF and HNSW indexes
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum VectorCompressionType {
+    #[default]
+    None,
+    // Blocks of vectors compressed with zstd at the given level, see `CompressedVectorStorage`
+    Zstd {
+        level: i32,
+    },
+}
+
+// Precision of the stored vectors of IVF and HNSW indexes without a quantizer
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum StoragePrecision {
+    #[default]
+    Float32,
+    // Half precision, see `Float16Quantizer`
+    Float16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BaseConfig {
     pub output_path: String,
     pub dimension: usize,
@@ -13,6 +72,77 @@ pub struct BaseConfig {
 
     pub index_type: IndexType,
     pub index_distance_type: DistanceType,
+
+    // Skip rows whose doc id has already been seen in the input
+    #[serde(default)]
+    pub deduplicate: bool,
+
+    // Skip the NaN/Inf check on input vectors. Only use this for trusted,
+    // pre-validated inputs.
+    #[serde(default)]
+    pub skip_vector_validation: bool,
+
+    // Where the index is read from when serving queries. With "s3", the index written to
+    // `output_path` is expected to be uploaded under `s3_prefix` in `s3_bucket`.
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+    #[serde(default)]
+    pub s3_bucket: Option<String>,
+    #[serde(default)]
+    pub s3_prefix: Option<String>,
+
+    // Number of seconds after which the index expires once it is added to a collection. It is
+    // recorded in `base_config.yaml` next to the index.
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+
+    // Number of rows read from the input at once while building the index
+    #[serde(default = "default_input_batch_size")]
+    pub batch_size: usize,
+
+    #[serde(default)]
+    pub input_format: InputFormat,
+
+    // Vectors are decompressed into memory when the index is read. Not supported by the S3
+    // storage backend.
+    #[serde(default)]
+    pub compression: VectorCompressionType,
+
+    // Only supported with `QuantizerType::NoQuantizer`
+    #[serde(default)]
+    pub storage_precision: StoragePrecision,
+
+    // Called with the progress of the build. It can only be set programmatically.
+    #[serde(skip)]
+    pub progress_callback: Option<Arc<dyn BuildProgressCallback + Send + Sync>>,
+    #[serde(default = "default_progress_interval")]
+    pub progress_interval: usize,
+}
+
+impl Default for BaseConfig {
+    fn default() -> Self {
+        Self {
+            output_path: String::new(),
+            dimension: 0,
+            reindex: false,
+            max_memory_size: 0,
+            file_size: 0,
+            index_type: IndexType::default(),
+            index_distance_type: DistanceType::default(),
+            deduplicate: false,
+            skip_vector_validation: false,
+            storage_backend: StorageBackend::default(),
+            s3_bucket: None,
+            s3_prefix: None,
+            ttl_seconds: None,
+            batch_size: DEFAULT_INPUT_BATCH_SIZE,
+            input_format: InputFormat::default(),
+            compression: VectorCompressionType::default(),
+            storage_precision: StoragePrecision::default(),
+            progress_callback: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -33,10 +163,29 @@ pub struct QuantizerConfig {
 pub struct HnswConfig {
     pub num_layers: u8,
     pub max_num_neighbors: usize,
+    // Size of the candidate list while building the graph
     pub ef_construction: u32,
+
+    // Keep full-precision vectors at layers >= 1 and only quantize layer 0
+    #[serde(default)]
+    pub quantize_layer_0: bool,
+
+    // Upper bound for the memory of the graph's neighbor lists while building. Above it, lists
+    // are spilled to disk next to the vectors.
+    #[serde(default)]
+    pub max_graph_memory_bytes: Option<usize>,
+
+    // Search the neighbors of new points on multiple threads while building the graph
+    #[serde(default)]
+    pub parallel_construction: bool,
+
+    // L2-normalize vectors before inserting them, so that the dot product distance ranks them
+    // by cosine similarity. Queries don't need to be normalized.
+    #[serde(default)]
+    pub normalize_on_insert: bool,
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IvfConfig {
     // IVF parameters
     pub num_clusters: usize,
@@ -50,6 +199,37 @@ pub struct IvfConfig {
     pub batch_size: usize,
     pub tolerance: f32,
     pub max_posting_list_size: usize,
+
+    // Pick the number of clusters from a sample of the input, with `num_clusters` as the upper
+    // bound, instead of using `num_clusters` as is
+    #[serde(default)]
+    pub auto_num_clusters: bool,
+    // Largest imbalance coefficient accepted when picking the number of clusters. Needs to be at
+    // least 1.0, which means perfectly balanced clusters
+    #[serde(default = "default_target_imbalance")]
+    pub target_imbalance: f32,
+    // Centroids to use instead of training KMeans, in FVECS format
+    #[serde(default)]
+    pub initial_centroids_fvecs_path: Option<String>,
+}
+
+impl Default for IvfConfig {
+    fn default() -> Self {
+        Self {
+            num_clusters: 0,
+            num_data_points: 0,
+            max_clusters_per_vector: 0,
+            distance_threshold: 0.0,
+            posting_list_encoding_type: IntSeqEncodingType::default(),
+            max_iteration: 0,
+            batch_size: 0,
+            tolerance: 0.0,
+            max_posting_list_size: 0,
+            auto_num_clusters: false,
+            target_imbalance: DEFAULT_TARGET_IMBALANCE,
+            initial_centroids_fvecs_path: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -74,11 +254,18 @@ pub struct SpannConfigWithBase {
     pub ivf_config: IvfConfig,
 }
 
+// A flat index stores the vectors as is, it has no parameters of its own
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlatConfigWithBase {
+    pub base_config: BaseConfig,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum IndexWriterConfig {
     Hnsw(HnswConfigWithBase),
     Ivf(IvfConfigWithBase),
     Spann(SpannConfigWithBase),
+    Flat(FlatConfigWithBase),
 }
 
 impl Default for IndexWriterConfig {
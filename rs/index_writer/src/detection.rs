@@ -0,0 +1,872 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use compression::adaptive::adaptive::AdaptiveDecoder;
+use compression::compression::IntSeqDecoder;
+use compression::elias_fano::ef::EliasFanoDecoder;
+use compression::noc::noc::PlainDecoder;
+use compression::roaring::roaring::RoaringDecoder;
+use config::enums::{DistanceType, IndexType, IntSeqEncodingType, QuantizerType, StorageBackend};
+use index::flat::reader::FlatIndexReader;
+use index::hnsw::reader::HnswReader;
+use index::index::BoxedSearchable;
+use index::ivf::reader::IvfReader;
+use index::spann::reader::SpannReader;
+use quantization::bq::bq::BinaryQuantizer;
+use quantization::fp16::fp16::Float16Quantizer;
+use quantization::noq::noq::NoQuantizer;
+use quantization::pq::pq::ProductQuantizer;
+use quantization::quantization::Quantizer;
+use quantization::rp::rp::RandomProjection;
+use quantization::rq::rq::ResidualQuantizer;
+use quantization::sq::sq::ScalarQuantizer;
+use quantization::sq4::sq4::Sq4Quantizer;
+use storage::ivf::S3IndexReader;
+use storage::s3::{ObjectReader, S3ObjectReader};
+use utils::distance::dot_product::DotProductDistanceCalculator;
+use utils::distance::l2::L2DistanceCalculator;
+use utils::DistanceCalculator;
+
+use crate::config::{BaseConfig, IvfConfig, QuantizerConfig, StoragePrecision};
+
+/// Opens an index written by `IndexWriter`, without knowing its type upfront.
+/// The type is detected from the `base_config.yaml` written next to the index.
+/// With the S3 storage backend, only the configs are read from `directory` and the index files
+/// are read from the configured bucket.
+pub struct IndexReader {
+    directory: String,
+    base_config: BaseConfig,
+    quantizer_config: QuantizerConfig,
+}
+
+impl IndexReader {
+    pub fn new(directory: &str) -> Result<Self> {
+        let base_config: BaseConfig =
+            Self::read_config(&format!("{}/base_config.yaml", directory))?;
+        let quantizer_config: QuantizerConfig =
+            Self::read_config(&format!("{}/quantizer_config.yaml", directory))?;
+        Ok(Self {
+            directory: directory.to_string(),
+            base_config,
+            quantizer_config,
+        })
+    }
+
+    pub fn index_type(&self) -> &IndexType {
+        &self.base_config.index_type
+    }
+
+    pub fn read(&self) -> Result<BoxedSearchable> {
+        if self.base_config.storage_backend == StorageBackend::S3 {
+            let bucket = self
+                .base_config
+                .s3_bucket
+                .clone()
+                .ok_or_else(|| anyhow!("s3_bucket is required for the S3 storage backend"))?;
+            return self.read_from_object_store(Arc::new(S3ObjectReader::new(bucket)?));
+        }
+
+        match self.base_config.index_type {
+            IndexType::Hnsw => match self.base_config.index_distance_type {
+                DistanceType::DotProduct | DistanceType::Cosine => {
+                    self.read_hnsw::<DotProductDistanceCalculator>()
+                }
+                DistanceType::L2 => self.read_hnsw::<L2DistanceCalculator>(),
+            },
+            IndexType::Ivf => {
+                let reader = IvfReader::new(self.directory.clone());
+                match self.base_config.index_distance_type {
+                    DistanceType::DotProduct | DistanceType::Cosine => {
+                        self.read_ivf::<DotProductDistanceCalculator>(&reader)
+                    }
+                    DistanceType::L2 => self.read_ivf::<L2DistanceCalculator>(&reader),
+                }
+            }
+            IndexType::Spann => self.read_spann(),
+            IndexType::Flat => {
+                let reader = FlatIndexReader::new(self.directory.clone());
+                match self.base_config.index_distance_type {
+                    DistanceType::DotProduct | DistanceType::Cosine => {
+                        Ok(Box::new(reader.read::<DotProductDistanceCalculator>()?))
+                    }
+                    DistanceType::L2 => Ok(Box::new(reader.read::<L2DistanceCalculator>()?)),
+                }
+            }
+        }
+    }
+
+    /// Reads the index files under `s3_prefix` from `object_reader`. Only IVF indexes are
+    /// supported.
+    pub fn read_from_object_store(
+        &self,
+        object_reader: Arc<dyn ObjectReader>,
+    ) -> Result<BoxedSearchable> {
+        if self.base_config.index_type != IndexType::Ivf {
+            return Err(anyhow!(
+                "Index type {:?} cannot be read from object storage",
+                self.base_config.index_type
+            ));
+        }
+        let prefix = self.base_config.s3_prefix.clone().unwrap_or_default();
+        let reader = S3IndexReader::new(object_reader, prefix);
+        match self.base_config.index_distance_type {
+            DistanceType::DotProduct | DistanceType::Cosine => {
+                self.read_ivf::<DotProductDistanceCalculator>(&reader)
+            }
+            DistanceType::L2 => self.read_ivf::<L2DistanceCalculator>(&reader),
+        }
+    }
+
+    fn read_config<T: serde::de::DeserializeOwned>(path: &str) -> Result<T> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read config {}: {}", path, e))?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    fn is_float16(&self) -> bool {
+        self.base_config.storage_precision == StoragePrecision::Float16
+    }
+
+    fn read_hnsw<D: DistanceCalculator + Send + Sync + 'static>(&self) -> Result<BoxedSearchable> {
+        let reader = HnswReader::new(self.directory.clone());
+        match self.quantizer_config.quantizer_type {
+            QuantizerType::NoQuantizer if self.is_float16() => {
+                Ok(Box::new(reader.read::<Float16Quantizer<D>>()?))
+            }
+            QuantizerType::ProductQuantizer => Ok(Box::new(reader.read::<ProductQuantizer<D>>()?)),
+            QuantizerType::NoQuantizer => Ok(Box::new(reader.read::<NoQuantizer<D>>()?)),
+            QuantizerType::ResidualQuantizer => {
+                Ok(Box::new(reader.read::<ResidualQuantizer<D>>()?))
+            }
+            QuantizerType::Sq4 => Ok(Box::new(reader.read::<Sq4Quantizer<D>>()?)),
+            QuantizerType::ScalarQuantizer => Ok(Box::new(reader.read::<ScalarQuantizer<D>>()?)),
+            QuantizerType::BinaryQuantizer => Ok(Box::new(reader.read::<BinaryQuantizer>()?)),
+            QuantizerType::RandomProjection { .. } => {
+                Ok(Box::new(reader.read::<RandomProjection<D>>()?))
+            }
+        }
+    }
+
+    fn read_ivf<D: DistanceCalculator + Send + Sync + 'static>(
+        &self,
+        reader: &impl IvfSource,
+    ) -> Result<BoxedSearchable> {
+        let ivf_config: IvfConfig =
+            Self::read_config(&format!("{}/ivf_config.yaml", self.directory))?;
+        let encoding = &ivf_config.posting_list_encoding_type;
+        match self.quantizer_config.quantizer_type {
+            QuantizerType::NoQuantizer if self.is_float16() => {
+                read_ivf_with_encoding::<Float16Quantizer<D>, D>(reader, encoding)
+            }
+            QuantizerType::ProductQuantizer => {
+                read_ivf_with_encoding::<ProductQuantizer<D>, D>(reader, encoding)
+            }
+            QuantizerType::NoQuantizer => {
+                read_ivf_with_encoding::<NoQuantizer<D>, D>(reader, encoding)
+            }
+            QuantizerType::ResidualQuantizer => {
+                read_ivf_with_encoding::<ResidualQuantizer<D>, D>(reader, encoding)
+            }
+            QuantizerType::Sq4 => read_ivf_with_encoding::<Sq4Quantizer<D>, D>(reader, encoding),
+            QuantizerType::ScalarQuantizer => {
+                read_ivf_with_encoding::<ScalarQuantizer<D>, D>(reader, encoding)
+            }
+            QuantizerType::BinaryQuantizer => {
+                read_ivf_with_encoding::<BinaryQuantizer, D>(reader, encoding)
+            }
+            QuantizerType::RandomProjection { .. } => Err(anyhow!(
+                "{:?} is only supported for HNSW",
+                self.quantizer_config.quantizer_type
+            )),
+        }
+    }
+
+    // SPANN is always built with L2 distance
+    fn read_spann(&self) -> Result<BoxedSearchable> {
+        let reader = SpannReader::new(self.directory.clone());
+        match self.quantizer_config.quantizer_type {
+            QuantizerType::ProductQuantizer => Ok(Box::new(
+                reader.read::<ProductQuantizer<L2DistanceCalculator>>()?,
+            )),
+            QuantizerType::NoQuantizer => Ok(Box::new(
+                reader.read::<NoQuantizer<L2DistanceCalculator>>()?,
+            )),
+            QuantizerType::ResidualQuantizer
+            | QuantizerType::Sq4
+            | QuantizerType::ScalarQuantizer
+            | QuantizerType::BinaryQuantizer
+            | QuantizerType::RandomProjection { .. } => Err(anyhow!(
+                "{:?} is not supported for SPANN",
+                self.quantizer_config.quantizer_type
+            )),
+        }
+    }
+}
+
+/// Files of an IVF index, read from local disk or from object storage.
+trait IvfSource {
+    fn read_boxed<Q, DC, D>(&self) -> Result<BoxedSearchable>
+    where
+        Q: Quantizer + Send + Sync + 'static,
+        Q::QuantizedT: Send + Sync + 'static,
+        DC: DistanceCalculator + Send + Sync + 'static,
+        D: IntSeqDecoder<Item = u64> + Send + Sync + 'static;
+}
+
+impl IvfSource for IvfReader {
+    fn read_boxed<Q, DC, D>(&self) -> Result<BoxedSearchable>
+    where
+        Q: Quantizer + Send + Sync + 'static,
+        Q::QuantizedT: Send + Sync + 'static,
+        DC: DistanceCalculator + Send + Sync + 'static,
+        D: IntSeqDecoder<Item = u64> + Send + Sync + 'static,
+    {
+        Ok(Box::new(self.read::<Q, DC, D>()?))
+    }
+}
+
+impl IvfSource for S3IndexReader {
+    fn read_boxed<Q, DC, D>(&self) -> Result<BoxedSearchable>
+    where
+        Q: Quantizer + Send + Sync + 'static,
+        Q::QuantizedT: Send + Sync + 'static,
+        DC: DistanceCalculator + Send + Sync + 'static,
+        D: IntSeqDecoder<Item = u64> + Send + Sync + 'static,
+    {
+        Ok(Box::new(self.read::<Q, DC, D>()?))
+    }
+}
+
+fn read_ivf_with_encoding<Q, DC>(
+    reader: &impl IvfSource,
+    encoding: &IntSeqEncodingType,
+) -> Result<BoxedSearchable>
+where
+    Q: Quantizer + Send + Sync + 'static,
+    Q::QuantizedT: Send + Sync + 'static,
+    DC: DistanceCalculator + Send + Sync + 'static,
+{
+    match encoding {
+        IntSeqEncodingType::PlainEncoding => reader.read_boxed::<Q, DC, PlainDecoder>(),
+        IntSeqEncodingType::EliasFano => reader.read_boxed::<Q, DC, EliasFanoDecoder>(),
+        IntSeqEncodingType::Adaptive => reader.read_boxed::<Q, DC, AdaptiveDecoder>(),
+        IntSeqEncodingType::Roaring => reader.read_boxed::<Q, DC, RoaringDecoder>(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use index::utils::SearchContext;
+    use storage::s3::LocalObjectReader;
+    use tempdir::TempDir;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::config::{
+        FlatConfigWithBase, HnswConfig, HnswConfigWithBase, IndexWriterConfig, InputFormat,
+        IvfConfigWithBase, SpannConfigWithBase, VectorCompressionType, DEFAULT_TARGET_IMBALANCE,
+    };
+    use crate::index_writer::IndexWriter;
+    use crate::input::MockInput;
+
+    const DIMENSION: usize = 4;
+
+    fn base_config(output_path: &str, index_type: IndexType) -> BaseConfig {
+        BaseConfig {
+            output_path: output_path.to_string(),
+            dimension: DIMENSION,
+            reindex: false,
+            max_memory_size: 1024 * 1024,
+            file_size: 1024 * 1024,
+            index_type,
+            index_distance_type: DistanceType::L2,
+            deduplicate: false,
+            skip_vector_validation: false,
+            storage_backend: StorageBackend::Local,
+            s3_bucket: None,
+            s3_prefix: None,
+            ttl_seconds: None,
+            batch_size: 4096,
+            input_format: InputFormat::Hdf5,
+            progress_callback: None,
+            progress_interval: 10000,
+            compression: VectorCompressionType::None,
+            storage_precision: StoragePrecision::Float32,
+        }
+    }
+
+    fn quantizer_config() -> QuantizerConfig {
+        QuantizerConfig {
+            quantizer_type: QuantizerType::NoQuantizer,
+            subvector_dimension: 1,
+            ..Default::default()
+        }
+    }
+
+    fn hnsw_config() -> HnswConfig {
+        HnswConfig {
+            num_layers: 4,
+            max_num_neighbors: 8,
+            ef_construction: 20,
+            quantize_layer_0: false,
+            max_graph_memory_bytes: None,
+            parallel_construction: false,
+            normalize_on_insert: false,
+        }
+    }
+
+    fn ivf_config() -> IvfConfig {
+        IvfConfig {
+            num_clusters: 2,
+            num_data_points: 100,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            posting_list_encoding_type: IntSeqEncodingType::EliasFano,
+            max_iteration: 10,
+            batch_size: 10,
+            tolerance: 0.0,
+            max_posting_list_size: usize::MAX,
+            auto_num_clusters: false,
+            target_imbalance: 0.0,
+            initial_centroids_fvecs_path: None,
+        }
+    }
+
+    fn write_and_read(config: IndexWriterConfig, directory: &str, ef: u32) -> IndexReader {
+        write_and_read_with_dimension(config, directory, ef, DIMENSION)
+    }
+
+    fn write_and_read_with_dimension(
+        config: IndexWriterConfig,
+        directory: &str,
+        ef: u32,
+        dimension: usize,
+    ) -> IndexReader {
+        let mut input = MockInput::new(
+            (0..100)
+                .map(|_| generate_random_vector(dimension))
+                .collect(),
+        );
+        let query = input.data[42].clone();
+
+        let mut index_writer = IndexWriter::new(config).unwrap();
+        index_writer.process(&mut input).unwrap();
+
+        let reader = IndexReader::new(directory).unwrap();
+        let index = reader.read().unwrap();
+        let results = index
+            .search(&query, 1, ef, &mut SearchContext::new(false))
+            .unwrap();
+        assert_eq!(results[0].id, 42);
+        reader
+    }
+
+    #[test]
+    fn test_index_reader_hnsw() {
+        let temp_dir = TempDir::new("test_index_reader_hnsw").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Hnsw),
+            quantizer_config: quantizer_config(),
+            hnsw_config: hnsw_config(),
+        });
+
+        let reader = write_and_read(config, &format!("{}/hnsw", base_directory), 10);
+        assert_eq!(*reader.index_type(), IndexType::Hnsw);
+    }
+
+    #[test]
+    fn test_index_reader_flat() {
+        let temp_dir = TempDir::new("test_index_reader_flat").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Flat(FlatConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Flat),
+        });
+
+        let reader = write_and_read(config, &format!("{}/flat", base_directory), 0);
+        assert_eq!(*reader.index_type(), IndexType::Flat);
+    }
+
+    #[test]
+    fn test_index_reader_ivf() {
+        let temp_dir = TempDir::new("test_index_reader_ivf").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Ivf),
+            quantizer_config: quantizer_config(),
+            ivf_config: ivf_config(),
+        });
+
+        // Probe all clusters
+        let reader = write_and_read(config, &format!("{}/ivf", base_directory), 2);
+        assert_eq!(*reader.index_type(), IndexType::Ivf);
+    }
+
+    #[test]
+    fn test_index_reader_distance_types() {
+        // For the query [1, 0, 0, 0], the closest document is 0 by L2 distance, 1 by dot product
+        // and 2 by cosine similarity. The other documents point away from the query.
+        let mut data = vec![
+            vec![1.0, 0.5, 0.0, 0.0],
+            vec![3.0, 3.0, 0.0, 0.0],
+            vec![0.2, 0.0, 0.0, 0.0],
+        ];
+        data.extend((0..97).map(|i| vec![-1.0 - i as f32 * 0.1, 0.0, 0.0, 1.0]));
+        let query = [1.0, 0.0, 0.0, 0.0];
+
+        for (distance_type, expected_id) in [
+            (DistanceType::L2, 0),
+            (DistanceType::DotProduct, 1),
+            (DistanceType::Cosine, 2),
+        ] {
+            for (index_type, ef) in [
+                (IndexType::Flat, 0),
+                (IndexType::Hnsw, 100),
+                (IndexType::Ivf, 2),
+            ] {
+                let temp_dir = TempDir::new("test_index_reader_distance_types").unwrap();
+                let base_directory = temp_dir.path().to_str().unwrap();
+                let mut base_config = base_config(base_directory, index_type.clone());
+                base_config.index_distance_type = distance_type.clone();
+                let config = match index_type {
+                    IndexType::Flat => IndexWriterConfig::Flat(FlatConfigWithBase { base_config }),
+                    IndexType::Hnsw => IndexWriterConfig::Hnsw(HnswConfigWithBase {
+                        base_config,
+                        quantizer_config: quantizer_config(),
+                        hnsw_config: hnsw_config(),
+                    }),
+                    _ => IndexWriterConfig::Ivf(IvfConfigWithBase {
+                        base_config,
+                        quantizer_config: quantizer_config(),
+                        ivf_config: ivf_config(),
+                    }),
+                };
+
+                let mut input = MockInput::new(data.clone());
+                IndexWriter::new(config)
+                    .unwrap()
+                    .process(&mut input)
+                    .unwrap();
+
+                let index_type_str = format!("{:?}", index_type).to_lowercase();
+                let directory = format!("{}/{}", base_directory, index_type_str);
+                let reader = IndexReader::new(&directory).unwrap();
+                assert_eq!(reader.base_config.index_distance_type, distance_type);
+                let results = reader
+                    .read()
+                    .unwrap()
+                    .search(&query, 1, ef, &mut SearchContext::new(false))
+                    .unwrap();
+                assert_eq!(
+                    results[0].id, expected_id,
+                    "{:?} index with {:?} distance",
+                    index_type, distance_type
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_index_writer_spann_rejects_cosine() {
+        let temp_dir = TempDir::new("test_index_writer_spann_rejects_cosine").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let mut base_config = base_config(base_directory, IndexType::Spann);
+        base_config.index_distance_type = DistanceType::Cosine;
+        let config = IndexWriterConfig::Spann(SpannConfigWithBase {
+            base_config,
+            quantizer_config: quantizer_config(),
+            hnsw_config: hnsw_config(),
+            ivf_config: ivf_config(),
+        });
+
+        let mut input =
+            MockInput::new((0..10).map(|_| generate_random_vector(DIMENSION)).collect());
+        assert!(IndexWriter::new(config)
+            .unwrap()
+            .process(&mut input)
+            .is_err());
+    }
+
+    #[test]
+    fn test_index_reader_compressed_vectors() {
+        let temp_dir = TempDir::new("test_index_reader_compressed_vectors").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let compression = VectorCompressionType::Zstd { level: 3 };
+
+        let ivf_directory = format!("{}/ivf", base_directory);
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: BaseConfig {
+                compression: compression.clone(),
+                ..base_config(&ivf_directory, IndexType::Ivf)
+            },
+            quantizer_config: quantizer_config(),
+            ivf_config: ivf_config(),
+        });
+        write_and_read(config, &format!("{}/ivf", ivf_directory), 2);
+        assert!(!Path::new(&format!("{}/ivf/vectors", ivf_directory)).exists());
+        assert!(Path::new(&format!("{}/ivf/vectors.zst", ivf_directory)).is_file());
+
+        let hnsw_directory = format!("{}/hnsw", base_directory);
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: BaseConfig {
+                compression,
+                ..base_config(&hnsw_directory, IndexType::Hnsw)
+            },
+            quantizer_config: quantizer_config(),
+            hnsw_config: hnsw_config(),
+        });
+        write_and_read(config, &format!("{}/hnsw", hnsw_directory), 10);
+        let vector_storage_path = format!("{}/hnsw/hnsw/vector_storage", hnsw_directory);
+        assert!(!Path::new(&vector_storage_path).exists());
+        assert!(Path::new(&format!("{}.zst", vector_storage_path)).is_file());
+    }
+
+    // Points along a smooth curve, so that neighbors differ by much more than the f16 rounding
+    fn smooth_vector(t: f32, dimension: usize) -> Vec<f32> {
+        (0..dimension)
+            .map(|j| (t * 0.05 + j as f32 * 0.3).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_index_reader_ivf_float16() {
+        let temp_dir = TempDir::new("test_index_reader_ivf_float16").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let dimension = 32;
+        let data = (0..200)
+            .map(|i| smooth_vector(i as f32, dimension))
+            .collect::<Vec<Vec<f32>>>();
+
+        let mut indexes = vec![];
+        let mut vector_file_sizes = vec![];
+        for precision in [StoragePrecision::Float32, StoragePrecision::Float16] {
+            let output_path = format!("{}/{:?}", base_directory, precision);
+            let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+                base_config: BaseConfig {
+                    dimension,
+                    storage_precision: precision,
+                    ..base_config(&output_path, IndexType::Ivf)
+                },
+                quantizer_config: quantizer_config(),
+                ivf_config: ivf_config(),
+            });
+            let mut input = MockInput::new(data.clone());
+            IndexWriter::new(config)
+                .unwrap()
+                .process(&mut input)
+                .unwrap();
+
+            let directory = format!("{}/ivf", output_path);
+            vector_file_sizes.push(
+                std::fs::metadata(format!("{}/vectors", directory))
+                    .unwrap()
+                    .len(),
+            );
+            indexes.push(IndexReader::new(&directory).unwrap().read().unwrap());
+        }
+        assert_eq!(vector_file_sizes[1] - 8, (vector_file_sizes[0] - 8) / 2);
+
+        // Probe all clusters
+        let mut context = SearchContext::new(false);
+        for i in 0..100 {
+            let query = smooth_vector(i as f32 * 2.0 + 0.25, dimension);
+            let float32_results = indexes[0].search(&query, 1, 2, &mut context).unwrap();
+            let float16_results = indexes[1].search(&query, 1, 2, &mut context).unwrap();
+            assert_eq!(float32_results[0].id, i * 2);
+            assert_eq!(float16_results[0].id, float32_results[0].id);
+        }
+    }
+
+    #[test]
+    fn test_index_writer_float16_requires_no_quantizer() {
+        let temp_dir = TempDir::new("test_index_writer_float16_requires_no_quantizer").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: BaseConfig {
+                storage_precision: StoragePrecision::Float16,
+                ..base_config(base_directory, IndexType::Ivf)
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::ScalarQuantizer,
+                ..quantizer_config()
+            },
+            ivf_config: ivf_config(),
+        });
+        let mut input = MockInput::new(
+            (0..100)
+                .map(|_| generate_random_vector(DIMENSION))
+                .collect(),
+        );
+        assert!(IndexWriter::new(config)
+            .unwrap()
+            .process(&mut input)
+            .is_err());
+    }
+
+    #[test]
+    fn test_index_reader_ivf_auto_num_clusters() {
+        let temp_dir = TempDir::new("test_index_reader_ivf_auto_num_clusters").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Ivf),
+            quantizer_config: quantizer_config(),
+            ivf_config: IvfConfig {
+                num_clusters: 4,
+                auto_num_clusters: true,
+                target_imbalance: 2.0,
+                ..ivf_config()
+            },
+        });
+
+        // Probe all clusters
+        let reader = write_and_read(config, &format!("{}/ivf", base_directory), 4);
+        assert_eq!(*reader.index_type(), IndexType::Ivf);
+    }
+
+    #[test]
+    fn test_ivf_config_default_target_imbalance() {
+        let ivf_config: IvfConfig = serde_yaml::from_str(
+            "num_clusters: 4\n\
+             num_data_points: 100\n\
+             max_clusters_per_vector: 1\n\
+             distance_threshold: 0.1\n\
+             posting_list_encoding_type: PlainEncoding\n\
+             max_iteration: 10\n\
+             batch_size: 10\n\
+             tolerance: 0.0\n\
+             max_posting_list_size: 1000\n\
+             auto_num_clusters: true\n",
+        )
+        .unwrap();
+        assert_eq!(ivf_config.target_imbalance, DEFAULT_TARGET_IMBALANCE);
+        assert_eq!(
+            IvfConfig::default().target_imbalance,
+            DEFAULT_TARGET_IMBALANCE
+        );
+    }
+
+    #[test]
+    fn test_index_reader_ivf_residual_quantizer() {
+        let temp_dir = TempDir::new("test_index_reader_ivf_residual_quantizer").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Ivf),
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::ResidualQuantizer,
+                subvector_dimension: 2,
+                num_bits: 4,
+                num_training_rows: 100,
+                max_iteration: 10,
+                batch_size: 10,
+                ..Default::default()
+            },
+            ivf_config: ivf_config(),
+        });
+
+        let reader = write_and_read(config, &format!("{}/ivf", base_directory), 2);
+        assert_eq!(
+            reader.quantizer_config.quantizer_type,
+            QuantizerType::ResidualQuantizer
+        );
+    }
+
+    #[test]
+    fn test_index_reader_hnsw_sq4() {
+        let temp_dir = TempDir::new("test_index_reader_hnsw_sq4").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Hnsw),
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::Sq4,
+                num_training_rows: 100,
+                ..Default::default()
+            },
+            hnsw_config: hnsw_config(),
+        });
+
+        let reader = write_and_read(config, &format!("{}/hnsw", base_directory), 10);
+        assert_eq!(reader.quantizer_config.quantizer_type, QuantizerType::Sq4);
+    }
+
+    #[test]
+    fn test_index_reader_hnsw_scalar_quantizer() {
+        let temp_dir = TempDir::new("test_index_reader_hnsw_scalar_quantizer").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Hnsw),
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::ScalarQuantizer,
+                num_bits: 8,
+                num_training_rows: 100,
+                ..Default::default()
+            },
+            hnsw_config: hnsw_config(),
+        });
+
+        let reader = write_and_read(config, &format!("{}/hnsw", base_directory), 10);
+        assert_eq!(
+            reader.quantizer_config.quantizer_type,
+            QuantizerType::ScalarQuantizer
+        );
+    }
+
+    #[test]
+    fn test_index_reader_hnsw_binary_quantizer() {
+        let temp_dir = TempDir::new("test_index_reader_hnsw_binary_quantizer").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            // Few dimensions would give many vectors the same binary code
+            base_config: BaseConfig {
+                dimension: 128,
+                ..base_config(base_directory, IndexType::Hnsw)
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::BinaryQuantizer,
+                num_training_rows: 100,
+                ..Default::default()
+            },
+            hnsw_config: hnsw_config(),
+        });
+
+        let reader =
+            write_and_read_with_dimension(config, &format!("{}/hnsw", base_directory), 10, 128);
+        assert_eq!(
+            reader.quantizer_config.quantizer_type,
+            QuantizerType::BinaryQuantizer
+        );
+    }
+
+    #[test]
+    fn test_index_reader_hnsw_random_projection() {
+        let temp_dir = TempDir::new("test_index_reader_hnsw_random_projection").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let quantizer_type = QuantizerType::RandomProjection {
+            output_dimension: 64,
+            seed: 42,
+        };
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: BaseConfig {
+                dimension: 128,
+                ..base_config(base_directory, IndexType::Hnsw)
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: quantizer_type.clone(),
+                ..Default::default()
+            },
+            hnsw_config: hnsw_config(),
+        });
+
+        // Queries are projected with the matrix written next to the index
+        let directory = format!("{}/hnsw", base_directory);
+        let reader = write_and_read_with_dimension(config, &directory, 10, 128);
+        assert_eq!(reader.quantizer_config.quantizer_type, quantizer_type);
+        assert!(Path::new(&format!("{}/quantizer/projection_matrix", directory)).is_file());
+    }
+
+    #[test]
+    fn test_index_reader_ivf_binary_quantizer() {
+        let temp_dir = TempDir::new("test_index_reader_ivf_binary_quantizer").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            // Few dimensions would give many vectors the same binary code
+            base_config: BaseConfig {
+                dimension: 128,
+                ..base_config(base_directory, IndexType::Ivf)
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::BinaryQuantizer,
+                num_training_rows: 100,
+                ..Default::default()
+            },
+            ivf_config: ivf_config(),
+        });
+
+        let reader =
+            write_and_read_with_dimension(config, &format!("{}/ivf", base_directory), 2, 128);
+        assert_eq!(
+            reader.quantizer_config.quantizer_type,
+            QuantizerType::BinaryQuantizer
+        );
+    }
+
+    #[test]
+    fn test_index_reader_ivf_scalar_quantizer() {
+        let temp_dir = TempDir::new("test_index_reader_ivf_scalar_quantizer").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Ivf),
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::ScalarQuantizer,
+                num_bits: 8,
+                num_training_rows: 100,
+                ..Default::default()
+            },
+            ivf_config: ivf_config(),
+        });
+
+        let reader = write_and_read(config, &format!("{}/ivf", base_directory), 2);
+        assert_eq!(
+            reader.quantizer_config.quantizer_type,
+            QuantizerType::ScalarQuantizer
+        );
+    }
+
+    #[test]
+    fn test_index_reader_spann() {
+        let temp_dir = TempDir::new("test_index_reader_spann").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let mut ivf_config = ivf_config();
+        ivf_config.posting_list_encoding_type = IntSeqEncodingType::PlainEncoding;
+        let config = IndexWriterConfig::Spann(SpannConfigWithBase {
+            base_config: base_config(base_directory, IndexType::Spann),
+            quantizer_config: quantizer_config(),
+            hnsw_config: hnsw_config(),
+            ivf_config,
+        });
+
+        let reader = write_and_read(config, &format!("{}/spann", base_directory), 10);
+        assert_eq!(*reader.index_type(), IndexType::Spann);
+    }
+
+    #[test]
+    fn test_index_reader_object_store() {
+        let temp_dir = TempDir::new("test_index_reader_object_store").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let mut base_config = base_config(base_directory, IndexType::Ivf);
+        base_config.storage_backend = StorageBackend::S3;
+        base_config.s3_bucket = Some("bucket".to_string());
+        base_config.s3_prefix = Some("ivf".to_string());
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config,
+            quantizer_config: quantizer_config(),
+            ivf_config: ivf_config(),
+        });
+
+        let mut input = MockInput::new(
+            (0..100)
+                .map(|_| generate_random_vector(DIMENSION))
+                .collect(),
+        );
+        let query = input.data[42].clone();
+        let mut index_writer = IndexWriter::new(config).unwrap();
+        index_writer.process(&mut input).unwrap();
+
+        // Serve the output directory as if it was uploaded to the bucket.
+        let reader = IndexReader::new(&format!("{}/ivf", base_directory)).unwrap();
+        let index = reader
+            .read_from_object_store(Arc::new(LocalObjectReader::new(base_directory.to_string())))
+            .unwrap();
+        let results = index
+            .search(&query, 1, 2, &mut SearchContext::new(false))
+            .unwrap();
+        assert_eq!(results[0].id, 42);
+    }
+
+    #[test]
+    fn test_index_reader_missing_config() {
+        let temp_dir = TempDir::new("test_index_reader_missing_config").unwrap();
+        assert!(IndexReader::new(temp_dir.path().to_str().unwrap()).is_err());
+    }
+}
@@ -1,29 +1,60 @@
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Ok, Result};
+use compression::adaptive::adaptive::AdaptiveEncoder;
 use compression::compression::IntSeqEncoder;
 use compression::elias_fano::ef::EliasFano;
 use compression::noc::noc::PlainEncoder;
+use compression::roaring::roaring::RoaringEncoder;
 use config::enums::{DistanceType, IntSeqEncodingType, QuantizerType};
+use index::flat::builder::{FlatIndexBuilder, FlatIndexBuilderConfig};
+use index::flat::writer::FlatIndexWriter;
 use index::hnsw::builder::HnswBuilder;
 use index::hnsw::writer::HnswWriter;
 use index::ivf::builder::{IvfBuilder, IvfBuilderConfig};
 use index::ivf::writer::IvfWriter;
 use index::spann::builder::{SpannBuilder, SpannBuilderConfig};
 use index::spann::writer::SpannWriter;
+use index::vector::compressed::{
+    compressed_vector_file_path, CompressedVectorStorage, DEFAULT_VECTORS_PER_BLOCK,
+};
 use log::{debug, info};
+use quantization::bq::bq::BinaryQuantizer;
+use quantization::bq::bq_builder::BinaryQuantizerBuilder;
+use quantization::fp16::fp16::Float16Quantizer;
 use quantization::noq::noq::{NoQuantizer, NoQuantizerConfig};
 use quantization::noq::noq_builder::NoQuantizerBuilder;
 use quantization::pq::pq::{ProductQuantizer, ProductQuantizerConfig};
 use quantization::pq::pq_builder::{ProductQuantizerBuilder, ProductQuantizerBuilderConfig};
 use quantization::quantization::{Quantizer, WritableQuantizer};
+use quantization::rp::rp::RandomProjection;
+use quantization::rq::rq::{ResidualQuantizer, ResidualQuantizerConfig};
+use quantization::rq::rq_builder::ResidualQuantizerBuilder;
+use quantization::sq::sq::{ScalarQuantizer, ScalarQuantizerConfig};
+use quantization::sq::sq_builder::ScalarQuantizerBuilder;
+use quantization::sq4::sq4::Sq4Quantizer;
+use quantization::sq4::sq4_builder::Sq4QuantizerBuilder;
 use rand::seq::SliceRandom;
 use utils::distance::dot_product::DotProductDistanceCalculator;
 use utils::distance::l2::L2DistanceCalculator;
+use utils::kmeans_builder::kmeans_builder::KMeansVariant;
 use utils::{CalculateSquared, DistanceCalculator};
 
 use crate::config::{
-    HnswConfigWithBase, IndexWriterConfig, IvfConfigWithBase, SpannConfigWithBase,
+    BaseConfig, FlatConfigWithBase, HnswConfigWithBase, IndexWriterConfig, IvfConfigWithBase,
+    QuantizerConfig, SpannConfigWithBase, StoragePrecision, VectorCompressionType,
 };
-use crate::input::Input;
+use crate::input::async_input::{AsyncInput, ChannelInput};
+use crate::input::dedup::DeduplicatedInput;
+use crate::input::normalized::NormalizedInput;
+use crate::input::sharded::ShardedInput;
+use crate::input::{Input, OwnedRow};
+use crate::progress::{BuildPhase, PhaseProgress};
+use crate::telemetry::BuildSpan;
+
+// Number of rows buffered for each `HnswBuilder::par_insert_batch` call
+const PARALLEL_HNSW_BATCH_SIZE: usize = 64 * 1024;
+
+// Number of rows `process_async` lets its input produce ahead of the builder
+const ASYNC_INPUT_CHANNEL_CAPACITY: usize = 1024;
 
 pub struct IndexWriter {
     config: IndexWriterConfig,
@@ -32,11 +63,7 @@ pub struct IndexWriter {
 
 impl IndexWriter {
     pub fn new(config: IndexWriterConfig) -> Result<Self> {
-        let base_config = match config.clone() {
-            IndexWriterConfig::Hnsw(hnsw_config) => hnsw_config.base_config,
-            IndexWriterConfig::Ivf(ivf_config) => ivf_config.base_config,
-            IndexWriterConfig::Spann(hnsw_ivf_config) => hnsw_ivf_config.base_config,
-        };
+        let base_config = Self::get_base_config(&config).clone();
 
         let index_type_str = format!("{:?}", base_config.index_type).to_lowercase();
         let output_root = format!("{}/{}", base_config.output_path, index_type_str);
@@ -48,6 +75,52 @@ impl IndexWriter {
         })
     }
 
+    fn get_base_config(config: &IndexWriterConfig) -> &BaseConfig {
+        match config {
+            IndexWriterConfig::Hnsw(hnsw_config) => &hnsw_config.base_config,
+            IndexWriterConfig::Ivf(ivf_config) => &ivf_config.base_config,
+            IndexWriterConfig::Spann(hnsw_ivf_config) => &hnsw_ivf_config.base_config,
+            IndexWriterConfig::Flat(flat_config) => &flat_config.base_config,
+        }
+    }
+
+    fn base_config(&self) -> &BaseConfig {
+        Self::get_base_config(&self.config)
+    }
+
+    // A batch size of 0 would never make progress through the input
+    fn input_batch_size(&self) -> usize {
+        self.base_config().batch_size.max(1)
+    }
+
+    fn progress(&self, phase: BuildPhase, total: usize) -> PhaseProgress {
+        PhaseProgress::new(
+            self.base_config().progress_callback.clone(),
+            phase,
+            total,
+            self.base_config().progress_interval,
+        )
+    }
+
+    /// Reads the next `batch_size` rows, failing if the input couldn't read one of them.
+    fn next_batch(input: &mut impl Input, batch_size: usize) -> Result<Vec<OwnedRow>> {
+        let rows = input.next_batch(batch_size);
+        match input.take_error() {
+            Some(e) => Err(e),
+            None => Ok(rows),
+        }
+    }
+
+    /// Reads the vector of the row at `row_idx`, e.g. to train a quantizer.
+    fn read_row(input: &mut impl Input, row_idx: usize) -> Result<Vec<f32>> {
+        input.skip_to(row_idx);
+        let data = input.next().data.to_vec();
+        match input.take_error() {
+            Some(e) => Err(e),
+            None => Ok(data),
+        }
+    }
+
     fn get_sorted_random_rows(num_rows: usize, num_random_rows: usize) -> Vec<u64> {
         let mut v = (0..num_rows).map(|x| x as u64).collect::<Vec<_>>();
         v.shuffle(&mut rand::thread_rng());
@@ -56,7 +129,54 @@ impl IndexWriter {
         ret
     }
 
-    fn write_quantizer_and_build_hnsw_index<Q: Quantizer + WritableQuantizer>(
+    // Half precision storage takes the place of the quantizer, see `Float16Quantizer`
+    fn check_storage_precision(&self) -> Result<()> {
+        if self.base_config().storage_precision == StoragePrecision::Float32 {
+            return Ok(());
+        }
+        let quantizer_type = match &self.config {
+            IndexWriterConfig::Hnsw(hnsw_config) => &hnsw_config.quantizer_config.quantizer_type,
+            IndexWriterConfig::Ivf(ivf_config) => &ivf_config.quantizer_config.quantizer_type,
+            _ => {
+                return Err(anyhow!(
+                    "Storage precision {:?} is only supported by HNSW and IVF indexes",
+                    self.base_config().storage_precision
+                ))
+            }
+        };
+        if *quantizer_type != QuantizerType::NoQuantizer {
+            return Err(anyhow!(
+                "Storage precision {:?} cannot be used with quantizer {:?}",
+                self.base_config().storage_precision,
+                quantizer_type
+            ));
+        }
+        Ok(())
+    }
+
+    /// Replaces the vector file at `path` with its compressed copy, when `compression` asks for
+    /// it. Readers pick the compressed copy up, see `open_vector_storage`.
+    fn compress_vector_file(path: &str, compression: &VectorCompressionType) -> Result<()> {
+        if let VectorCompressionType::Zstd { level } = compression {
+            let compressed_path = compressed_vector_file_path(path);
+            let bytes_written = CompressedVectorStorage::<u8>::compress_file(
+                path,
+                &compressed_path,
+                DEFAULT_VECTORS_PER_BLOCK,
+                *level,
+            )?;
+            info!(
+                "Compressed {} from {} to {} bytes",
+                path,
+                std::fs::metadata(path)?.len(),
+                bytes_written
+            );
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn write_quantizer_and_build_hnsw_index<Q: Quantizer + WritableQuantizer + Sync>(
         &mut self,
         input: &mut impl Input,
         index_builder_config: &HnswConfigWithBase,
@@ -75,32 +195,74 @@ impl IndexWriter {
         let vector_directory = format!("{}/vectors", path);
         std::fs::create_dir_all(&vector_directory)?;
 
+        let quantized_dimension = quantizer.quantized_dimension();
         let mut hnsw_builder = HnswBuilder::<Q>::new(
             index_builder_config.hnsw_config.max_num_neighbors,
             index_builder_config.hnsw_config.num_layers,
             index_builder_config.hnsw_config.ef_construction,
             index_builder_config.base_config.max_memory_size,
             index_builder_config.base_config.file_size,
-            index_builder_config.base_config.dimension
-                / index_builder_config.quantizer_config.subvector_dimension,
+            quantized_dimension,
             quantizer,
             vector_directory.clone(),
         );
-
+        hnsw_builder
+            .set_skip_vector_validation(index_builder_config.base_config.skip_vector_validation);
+        hnsw_builder.set_quantize_layer_0(index_builder_config.hnsw_config.quantize_layer_0);
+        hnsw_builder
+            .set_max_graph_memory_bytes(index_builder_config.hnsw_config.max_graph_memory_bytes);
+        hnsw_builder.set_normalize_on_insert(index_builder_config.hnsw_config.normalize_on_insert);
+
+        let span = BuildSpan::phase("build_hnsw_graph");
+        span.set_num_vectors(input.num_rows());
         input.reset();
-        while input.has_next() {
-            let row = input.next();
-            hnsw_builder.insert(row.id as u128, row.data)?;
-            if row.id % 10000 == 0 {
-                debug!("Inserted {} rows", row.id);
+        let mut progress = self.progress(BuildPhase::Inserting, input.num_rows());
+        let mut num_inserted = 0;
+        if index_builder_config.hnsw_config.parallel_construction {
+            while input.has_next() {
+                let rows = Self::next_batch(input, PARALLEL_HNSW_BATCH_SIZE)?;
+                let batch = rows
+                    .iter()
+                    .map(|row| (row.id, row.data.as_slice()))
+                    .collect::<Vec<_>>();
+                hnsw_builder.par_insert_batch(&batch)?;
+                debug!("Inserted {} rows", hnsw_builder.doc_id_mapping.len());
+                num_inserted += rows.len();
+                progress.advance_to(num_inserted);
+            }
+        } else {
+            let batch_size = self.input_batch_size();
+            while input.has_next() {
+                for row in Self::next_batch(input, batch_size)? {
+                    hnsw_builder.insert(row.id as u128, &row.data)?;
+                    if row.id % 10000 == 0 {
+                        debug!("Inserted {} rows", row.id);
+                    }
+                    num_inserted += 1;
+                    progress.advance_to(num_inserted);
+                }
             }
         }
+        progress.finish();
+        drop(span);
 
-        std::fs::create_dir_all(&path)?;
+        // Same layout as the centroids of SPANN, so that HnswReader can read it back
+        let hnsw_directory = format!("{}/hnsw", path);
+        std::fs::create_dir_all(&hnsw_directory)?;
 
         info!("Start writing index");
-        let hnsw_writer = HnswWriter::new(path.to_string());
+        let span = BuildSpan::phase("write_index");
+        span.set_num_vectors(input.num_rows());
+        let mut progress = self.progress(BuildPhase::Writing, input.num_rows());
+        progress.start();
+        let hnsw_writer = HnswWriter::new(hnsw_directory.clone());
         hnsw_writer.write(&mut hnsw_builder, index_builder_config.base_config.reindex)?;
+        Self::compress_vector_file(
+            &format!("{}/vector_storage", hnsw_directory),
+            &index_builder_config.base_config.compression,
+        )?;
+        progress.finish();
+        drop(span);
 
         // Cleanup tmp directory. It's ok to fail
         std::fs::remove_dir_all(&vector_directory).unwrap_or_default();
@@ -108,7 +270,7 @@ impl IndexWriter {
         Ok(())
     }
 
-    fn build_hnsw_pq<D: DistanceCalculator>(
+    fn build_hnsw_pq<D: DistanceCalculator + Send + Sync>(
         &mut self,
         input: &mut impl Input,
         index_builder_config: &HnswConfigWithBase,
@@ -123,35 +285,217 @@ impl IndexWriter {
         let pq_builder_config = ProductQuantizerBuilderConfig {
             max_iteration: index_builder_config.quantizer_config.max_iteration,
             batch_size: index_builder_config.quantizer_config.batch_size,
+            use_annealing: false,
         };
 
         let mut pq_builder = ProductQuantizerBuilder::<D>::new(pq_config, pq_builder_config);
 
         info!("Start training product quantizer");
+        let span = BuildSpan::phase("train_quantizer");
         let sorted_random_rows = Self::get_sorted_random_rows(
             input.num_rows(),
             index_builder_config.quantizer_config.num_training_rows,
         );
+        span.set_num_vectors(sorted_random_rows.len());
 
-        for row_idx in sorted_random_rows {
-            input.skip_to(row_idx as usize);
-            pq_builder.add(input.next().data.to_vec());
+        let mut progress = self.progress(BuildPhase::Training, sorted_random_rows.len());
+        for (i, row_idx) in sorted_random_rows.into_iter().enumerate() {
+            pq_builder.add(Self::read_row(input, row_idx as usize)?);
+            progress.advance_to(i + 1);
         }
 
         let pq = pq_builder.build(format!("{}/pq_tmp", &self.output_root))?;
+        drop(span);
 
         self.write_quantizer_and_build_hnsw_index(input, index_builder_config, pq)
     }
 
-    fn build_hnsw_noq<D: DistanceCalculator>(
+    /// Trains both stages of a residual quantizer on a random sample of the input.
+    fn train_rq<D: DistanceCalculator>(
+        &self,
+        input: &mut impl Input,
+        dimension: usize,
+        quantizer_config: &QuantizerConfig,
+    ) -> Result<ResidualQuantizer<D>> {
+        let rq_config = ResidualQuantizerConfig {
+            dimension,
+            subvector_dimension: quantizer_config.subvector_dimension,
+            num_bits: quantizer_config.num_bits,
+        };
+
+        let rq_builder_config = ProductQuantizerBuilderConfig {
+            max_iteration: quantizer_config.max_iteration,
+            batch_size: quantizer_config.batch_size,
+            use_annealing: false,
+        };
+
+        let mut rq_builder = ResidualQuantizerBuilder::<D>::new(rq_config, rq_builder_config);
+
+        info!("Start training residual quantizer");
+        let span = BuildSpan::phase("train_quantizer");
+        let sorted_random_rows =
+            Self::get_sorted_random_rows(input.num_rows(), quantizer_config.num_training_rows);
+        span.set_num_vectors(sorted_random_rows.len());
+
+        let mut progress = self.progress(BuildPhase::Training, sorted_random_rows.len());
+        for (i, row_idx) in sorted_random_rows.into_iter().enumerate() {
+            rq_builder.add(Self::read_row(input, row_idx as usize)?);
+            progress.advance_to(i + 1);
+        }
+
+        rq_builder.build(format!("{}/rq_tmp", self.output_root))
+    }
+
+    fn build_hnsw_rq<D: DistanceCalculator + Send + Sync>(
         &mut self,
         input: &mut impl Input,
         index_builder_config: &HnswConfigWithBase,
     ) -> Result<()> {
-        // Create NoQuantizer
-        let noq_config = NoQuantizerConfig {
-            dimension: index_builder_config.base_config.dimension,
+        let rq = self.train_rq::<D>(
+            input,
+            index_builder_config.base_config.dimension,
+            &index_builder_config.quantizer_config,
+        )?;
+
+        self.write_quantizer_and_build_hnsw_index(input, index_builder_config, rq)
+    }
+
+    /// Learns the per-dimension ranges of a SQ4 quantizer on a random sample of the input.
+    fn train_sq4<D: DistanceCalculator>(
+        &self,
+        input: &mut impl Input,
+        dimension: usize,
+        quantizer_config: &QuantizerConfig,
+    ) -> Result<Sq4Quantizer<D>> {
+        let mut sq4_builder = Sq4QuantizerBuilder::<D>::new(dimension);
+
+        info!("Start training SQ4 quantizer");
+        let span = BuildSpan::phase("train_quantizer");
+        let sorted_random_rows =
+            Self::get_sorted_random_rows(input.num_rows(), quantizer_config.num_training_rows);
+        span.set_num_vectors(sorted_random_rows.len());
+
+        let mut progress = self.progress(BuildPhase::Training, sorted_random_rows.len());
+        for (i, row_idx) in sorted_random_rows.into_iter().enumerate() {
+            sq4_builder.add(Self::read_row(input, row_idx as usize)?);
+            progress.advance_to(i + 1);
+        }
+
+        sq4_builder.build(String::new())
+    }
+
+    fn build_hnsw_sq4<D: DistanceCalculator + Send + Sync>(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &HnswConfigWithBase,
+    ) -> Result<()> {
+        let sq4 = self.train_sq4::<D>(
+            input,
+            index_builder_config.base_config.dimension,
+            &index_builder_config.quantizer_config,
+        )?;
+
+        self.write_quantizer_and_build_hnsw_index(input, index_builder_config, sq4)
+    }
+
+    /// Learns the per-dimension ranges of a scalar quantizer on a random sample of the input.
+    fn train_sq<D: DistanceCalculator>(
+        &self,
+        input: &mut impl Input,
+        dimension: usize,
+        quantizer_config: &QuantizerConfig,
+    ) -> Result<ScalarQuantizer<D>> {
+        let sq_config = ScalarQuantizerConfig {
+            dimension,
+            num_bits: quantizer_config.num_bits,
         };
+        sq_config.validate()?;
+        let mut sq_builder = ScalarQuantizerBuilder::<D>::new(sq_config);
+
+        info!("Start training scalar quantizer");
+        let span = BuildSpan::phase("train_quantizer");
+        let sorted_random_rows =
+            Self::get_sorted_random_rows(input.num_rows(), quantizer_config.num_training_rows);
+        span.set_num_vectors(sorted_random_rows.len());
+
+        let mut progress = self.progress(BuildPhase::Training, sorted_random_rows.len());
+        for (i, row_idx) in sorted_random_rows.into_iter().enumerate() {
+            sq_builder.add(Self::read_row(input, row_idx as usize)?);
+            progress.advance_to(i + 1);
+        }
+
+        sq_builder.build(String::new())
+    }
+
+    fn build_hnsw_sq<D: DistanceCalculator + Send + Sync>(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &HnswConfigWithBase,
+    ) -> Result<()> {
+        let sq = self.train_sq::<D>(
+            input,
+            index_builder_config.base_config.dimension,
+            &index_builder_config.quantizer_config,
+        )?;
+
+        self.write_quantizer_and_build_hnsw_index(input, index_builder_config, sq)
+    }
+
+    /// Learns the per-dimension thresholds of a binary quantizer on a random sample of the input.
+    fn train_bq(
+        &self,
+        input: &mut impl Input,
+        dimension: usize,
+        quantizer_config: &QuantizerConfig,
+    ) -> Result<BinaryQuantizer> {
+        let mut bq_builder = BinaryQuantizerBuilder::new(dimension);
+
+        info!("Start training binary quantizer");
+        let span = BuildSpan::phase("train_quantizer");
+        let sorted_random_rows =
+            Self::get_sorted_random_rows(input.num_rows(), quantizer_config.num_training_rows);
+        span.set_num_vectors(sorted_random_rows.len());
+
+        let mut progress = self.progress(BuildPhase::Training, sorted_random_rows.len());
+        for (i, row_idx) in sorted_random_rows.into_iter().enumerate() {
+            bq_builder.add(Self::read_row(input, row_idx as usize)?);
+            progress.advance_to(i + 1);
+        }
+
+        bq_builder.build(String::new())
+    }
+
+    fn build_hnsw_bq(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &HnswConfigWithBase,
+    ) -> Result<()> {
+        let bq = self.train_bq(
+            input,
+            index_builder_config.base_config.dimension,
+            &index_builder_config.quantizer_config,
+        )?;
+
+        self.write_quantizer_and_build_hnsw_index(input, index_builder_config, bq)
+    }
+
+    fn build_hnsw_noq<D: DistanceCalculator + Send + Sync>(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &HnswConfigWithBase,
+    ) -> Result<()> {
+        let dimension = index_builder_config.base_config.dimension;
+        if index_builder_config.base_config.storage_precision == StoragePrecision::Float16 {
+            let quantizer = Float16Quantizer::<D>::new(dimension);
+            return self.write_quantizer_and_build_hnsw_index(
+                input,
+                index_builder_config,
+                quantizer,
+            );
+        }
+
+        // Create NoQuantizer
+        let noq_config = NoQuantizerConfig { dimension };
 
         let mut noq_builder = NoQuantizerBuilder::<D>::new(noq_config);
 
@@ -160,7 +504,23 @@ impl IndexWriter {
         self.write_quantizer_and_build_hnsw_index(input, index_builder_config, noq)
     }
 
-    fn do_build_hnsw_index<D: DistanceCalculator>(
+    fn build_hnsw_rp<D: DistanceCalculator + Send + Sync>(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &HnswConfigWithBase,
+        output_dimension: usize,
+        seed: u64,
+    ) -> Result<()> {
+        let rp = RandomProjection::<D>::new(
+            index_builder_config.base_config.dimension,
+            output_dimension,
+            seed,
+        )?;
+
+        self.write_quantizer_and_build_hnsw_index(input, index_builder_config, rp)
+    }
+
+    fn do_build_hnsw_index<D: DistanceCalculator + Send + Sync>(
         &mut self,
         input: &mut impl Input,
         index_builder_config: &HnswConfigWithBase,
@@ -172,6 +532,24 @@ impl IndexWriter {
             QuantizerType::NoQuantizer => {
                 self.build_hnsw_noq::<D>(input, index_builder_config)?;
             }
+            QuantizerType::ResidualQuantizer => {
+                self.build_hnsw_rq::<D>(input, index_builder_config)?;
+            }
+            QuantizerType::Sq4 => {
+                self.build_hnsw_sq4::<D>(input, index_builder_config)?;
+            }
+            QuantizerType::ScalarQuantizer => {
+                self.build_hnsw_sq::<D>(input, index_builder_config)?;
+            }
+            QuantizerType::BinaryQuantizer => {
+                self.build_hnsw_bq(input, index_builder_config)?;
+            }
+            QuantizerType::RandomProjection {
+                output_dimension,
+                seed,
+            } => {
+                self.build_hnsw_rp::<D>(input, index_builder_config, output_dimension, seed)?;
+            }
         };
         Ok(())
     }
@@ -210,26 +588,75 @@ impl IndexWriter {
             file_size: index_builder_config.base_config.file_size,
             num_features: index_builder_config.base_config.dimension,
             tolerance: index_builder_config.ivf_config.tolerance,
+            kmeans_variant: KMeansVariant::Lloyd,
             max_posting_list_size: index_builder_config.ivf_config.max_posting_list_size,
+            initial_centroids_fvecs_path: index_builder_config
+                .ivf_config
+                .initial_centroids_fvecs_path
+                .clone(),
+            allow_online_insertion: false,
         })?;
+        ivf_builder
+            .set_skip_vector_validation(index_builder_config.base_config.skip_vector_validation);
 
         input.reset();
+        let mut progress = self.progress(BuildPhase::Inserting, input.num_rows());
+        let mut num_inserted = 0;
+        let batch_size = self.input_batch_size();
         while input.has_next() {
-            let row = input.next();
-            ivf_builder.add_vector(row.id as u128, row.data)?;
-            if row.id % 10000 == 0 {
-                debug!("Inserted {} rows", row.id);
+            for row in Self::next_batch(input, batch_size)? {
+                ivf_builder.add_vector(row.id as u128, &row.data)?;
+                if row.id % 10000 == 0 {
+                    debug!("Inserted {} rows", row.id);
+                }
+                num_inserted += 1;
+                progress.advance_to(num_inserted);
             }
         }
+        progress.finish();
+
+        // Clustering only reports when it starts and when it's done
+        let mut progress = self.progress(BuildPhase::Training, num_inserted);
+        progress.start();
+
+        // The number of clusters is fixed by the given centroids
+        if index_builder_config.ivf_config.auto_num_clusters
+            && index_builder_config
+                .ivf_config
+                .initial_centroids_fvecs_path
+                .is_none()
+        {
+            let num_clusters = ivf_builder.suggest_num_clusters(
+                index_builder_config.ivf_config.target_imbalance,
+                index_builder_config.ivf_config.num_clusters,
+            )?;
+            info!("Picked {} clusters", num_clusters);
+            ivf_builder.set_num_clusters(num_clusters);
+        }
 
         info!("Start building index");
+        let span = BuildSpan::phase("assign_clusters");
         ivf_builder.build()?;
+        let num_vectors = ivf_builder.vectors().borrow().len();
+        span.set_num_vectors(num_vectors);
+        progress.finish();
+        drop(span);
 
         std::fs::create_dir_all(&path)?;
 
         info!("Start writing index");
+        let span = BuildSpan::phase("write_index");
+        span.set_num_vectors(num_vectors);
+        let mut progress = self.progress(BuildPhase::Writing, num_vectors);
+        progress.start();
         let ivf_writer = IvfWriter::<_, E, D>::new(path.to_string(), quantizer);
         ivf_writer.write(&mut ivf_builder, index_builder_config.base_config.reindex)?;
+        Self::compress_vector_file(
+            &format!("{}/vectors", path),
+            &index_builder_config.base_config.compression,
+        )?;
+        progress.finish();
+        drop(span);
 
         // Cleanup tmp directory. It's ok to fail
         ivf_builder.cleanup()?;
@@ -255,22 +682,27 @@ impl IndexWriter {
         let pq_builder_config = ProductQuantizerBuilderConfig {
             max_iteration: index_builder_config.quantizer_config.max_iteration,
             batch_size: index_builder_config.quantizer_config.batch_size,
+            use_annealing: false,
         };
 
         let mut pq_builder = ProductQuantizerBuilder::<D>::new(pq_config, pq_builder_config);
 
         info!("Start training product quantizer");
+        let span = BuildSpan::phase("train_quantizer");
         let sorted_random_rows = Self::get_sorted_random_rows(
             input.num_rows(),
             index_builder_config.quantizer_config.num_training_rows,
         );
+        span.set_num_vectors(sorted_random_rows.len());
 
-        for row_idx in sorted_random_rows {
-            input.skip_to(row_idx as usize);
-            pq_builder.add(input.next().data.to_vec());
+        let mut progress = self.progress(BuildPhase::Training, sorted_random_rows.len());
+        for (i, row_idx) in sorted_random_rows.into_iter().enumerate() {
+            pq_builder.add(Self::read_row(input, row_idx as usize)?);
+            progress.advance_to(i + 1);
         }
 
         let pq = pq_builder.build(format!("{}/pq_tmp", &self.output_root))?;
+        drop(span);
 
         // Define the writer function for ProductQuantizer
         let pq_writer_fn =
@@ -292,10 +724,22 @@ impl IndexWriter {
         input: &mut impl Input,
         index_builder_config: &IvfConfigWithBase,
     ) -> Result<()> {
+        let dimension = index_builder_config.base_config.dimension;
+        if index_builder_config.base_config.storage_precision == StoragePrecision::Float16 {
+            let quantizer = Float16Quantizer::<D>::new(dimension);
+            let writer_fn = |directory: &String, quantizer: &Float16Quantizer<D>| {
+                quantizer.write_to_directory(directory)
+            };
+            return self.write_quantizer_and_build_ivf_index::<_, E, D, _>(
+                input,
+                index_builder_config,
+                quantizer,
+                writer_fn,
+            );
+        }
+
         // Create NoQuantizer
-        let noq_config = NoQuantizerConfig {
-            dimension: index_builder_config.base_config.dimension,
-        };
+        let noq_config = NoQuantizerConfig { dimension };
 
         let mut noq_builder = NoQuantizerBuilder::<D>::new(noq_config);
 
@@ -313,6 +757,106 @@ impl IndexWriter {
         )
     }
 
+    fn build_ivf_rq<
+        E: IntSeqEncoder + 'static,
+        D: DistanceCalculator + CalculateSquared + Send + Sync,
+    >(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &IvfConfigWithBase,
+    ) -> Result<()> {
+        let rq = self.train_rq::<D>(
+            input,
+            index_builder_config.base_config.dimension,
+            &index_builder_config.quantizer_config,
+        )?;
+
+        let rq_writer_fn =
+            |directory: &String, rq: &ResidualQuantizer<D>| rq.write_to_directory(directory);
+
+        self.write_quantizer_and_build_ivf_index::<_, E, D, _>(
+            input,
+            index_builder_config,
+            rq,
+            rq_writer_fn,
+        )
+    }
+
+    fn build_ivf_sq4<
+        E: IntSeqEncoder + 'static,
+        D: DistanceCalculator + CalculateSquared + Send + Sync,
+    >(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &IvfConfigWithBase,
+    ) -> Result<()> {
+        let sq4 = self.train_sq4::<D>(
+            input,
+            index_builder_config.base_config.dimension,
+            &index_builder_config.quantizer_config,
+        )?;
+
+        let sq4_writer_fn =
+            |directory: &String, sq4: &Sq4Quantizer<D>| sq4.write_to_directory(directory);
+
+        self.write_quantizer_and_build_ivf_index::<_, E, D, _>(
+            input,
+            index_builder_config,
+            sq4,
+            sq4_writer_fn,
+        )
+    }
+
+    fn build_ivf_sq<
+        E: IntSeqEncoder + 'static,
+        D: DistanceCalculator + CalculateSquared + Send + Sync,
+    >(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &IvfConfigWithBase,
+    ) -> Result<()> {
+        let sq = self.train_sq::<D>(
+            input,
+            index_builder_config.base_config.dimension,
+            &index_builder_config.quantizer_config,
+        )?;
+
+        let sq_writer_fn =
+            |directory: &String, sq: &ScalarQuantizer<D>| sq.write_to_directory(directory);
+
+        self.write_quantizer_and_build_ivf_index::<_, E, D, _>(
+            input,
+            index_builder_config,
+            sq,
+            sq_writer_fn,
+        )
+    }
+
+    fn build_ivf_bq<
+        E: IntSeqEncoder + 'static,
+        D: DistanceCalculator + CalculateSquared + Send + Sync,
+    >(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &IvfConfigWithBase,
+    ) -> Result<()> {
+        let bq = self.train_bq(
+            input,
+            index_builder_config.base_config.dimension,
+            &index_builder_config.quantizer_config,
+        )?;
+
+        let bq_writer_fn =
+            |directory: &String, bq: &BinaryQuantizer| bq.write_to_directory(directory);
+
+        self.write_quantizer_and_build_ivf_index::<_, E, D, _>(
+            input,
+            index_builder_config,
+            bq,
+            bq_writer_fn,
+        )
+    }
+
     fn build_ivf_index_with_encoder<
         E: IntSeqEncoder + 'static,
         D: DistanceCalculator + CalculateSquared + Send + Sync,
@@ -328,6 +872,24 @@ impl IndexWriter {
             QuantizerType::NoQuantizer => {
                 self.build_ivf_noq::<E, D>(input, index_builder_config)?;
             }
+            QuantizerType::ResidualQuantizer => {
+                self.build_ivf_rq::<E, D>(input, index_builder_config)?;
+            }
+            QuantizerType::Sq4 => {
+                self.build_ivf_sq4::<E, D>(input, index_builder_config)?;
+            }
+            QuantizerType::ScalarQuantizer => {
+                self.build_ivf_sq::<E, D>(input, index_builder_config)?;
+            }
+            QuantizerType::BinaryQuantizer => {
+                self.build_ivf_bq::<E, D>(input, index_builder_config)?;
+            }
+            QuantizerType::RandomProjection { .. } => {
+                return Err(anyhow!(
+                    "{:?} is only supported by HNSW indexes",
+                    index_builder_config.quantizer_config.quantizer_type
+                ));
+            }
         };
 
         Ok(())
@@ -353,6 +915,18 @@ impl IndexWriter {
             IntSeqEncodingType::EliasFano => {
                 self.build_ivf_index_with_encoder::<EliasFano, D>(input, index_builder_config)?;
             }
+            IntSeqEncodingType::Adaptive => {
+                self.build_ivf_index_with_encoder::<AdaptiveEncoder, D>(
+                    input,
+                    index_builder_config,
+                )?;
+            }
+            IntSeqEncodingType::Roaring => {
+                self.build_ivf_index_with_encoder::<RoaringEncoder, D>(
+                    input,
+                    index_builder_config,
+                )?;
+            }
         };
 
         Ok(())
@@ -404,34 +978,85 @@ impl IndexWriter {
             centroids_clustering_tolerance: index_writer_config.ivf_config.tolerance,
             ivf_max_posting_list_size: index_writer_config.ivf_config.max_posting_list_size,
             reindex: index_writer_config.base_config.reindex,
+            centroid_graph_path: None,
         };
         let mut spann_builder = SpannBuilder::new(spann_config)?;
+        spann_builder
+            .set_skip_vector_validation(index_writer_config.base_config.skip_vector_validation);
 
         input.reset();
+        let batch_size = self.input_batch_size();
         while input.has_next() {
-            let row = input.next();
-            spann_builder.add(row.id as u128, row.data)?;
-            if row.id % 10000 == 0 {
-                debug!("Inserted {} rows", row.id);
+            for row in Self::next_batch(input, batch_size)? {
+                spann_builder.add(row.id as u128, &row.data)?;
+                if row.id % 10000 == 0 {
+                    debug!("Inserted {} rows", row.id);
+                }
             }
         }
 
         info!("Start building IVF index");
-        spann_builder.build()?;
-
+        let span = BuildSpan::phase("assign_clusters");
+        spann_builder.build_clusters()?;
+        let num_vectors = spann_builder.ivf_builder.vectors().borrow().len();
+        span.set_num_vectors(num_vectors);
+        drop(span);
+
+        let span = BuildSpan::phase("build_hnsw_graph");
+        span.set_num_vectors(spann_builder.ivf_builder.centroids().borrow().len());
+        spann_builder.build_centroid_graph()?;
+        drop(span);
+
+        let span = BuildSpan::phase("write_index");
+        span.set_num_vectors(num_vectors);
         let spann_writer = SpannWriter::new(root_path.to_string());
         spann_writer.write(&mut spann_builder)?;
+        drop(span);
 
         Ok(())
     }
 
+    fn do_build_flat_index(
+        &mut self,
+        input: &mut impl Input,
+        index_builder_config: &FlatConfigWithBase,
+    ) -> Result<()> {
+        let path = &self.output_root;
+        let mut flat_builder = FlatIndexBuilder::new(FlatIndexBuilderConfig {
+            base_directory: path.to_string(),
+            memory_size: index_builder_config.base_config.max_memory_size,
+            file_size: index_builder_config.base_config.file_size,
+            num_features: index_builder_config.base_config.dimension,
+        })?;
+        flat_builder
+            .set_skip_vector_validation(index_builder_config.base_config.skip_vector_validation);
+
+        input.reset();
+        let batch_size = self.input_batch_size();
+        while input.has_next() {
+            for row in Self::next_batch(input, batch_size)? {
+                flat_builder.add_vector(row.id as u128, &row.data)?;
+            }
+        }
+
+        info!("Start writing index");
+        let span = BuildSpan::phase("write_index");
+        span.set_num_vectors(flat_builder.doc_ids().len());
+        FlatIndexWriter::new(path.to_string()).write(&flat_builder)?;
+        drop(span);
+
+        flat_builder.cleanup()?;
+        Ok(())
+    }
+
     // TODO(hicder): Support multiple inputs
-    pub fn process(&mut self, input: &mut impl Input) -> Result<()> {
+    fn build_index(&mut self, input: &mut impl Input) -> Result<(BaseConfig, QuantizerConfig)> {
+        self.check_storage_precision()?;
         let cfg = self.config.clone();
-        let (base_config, quantizer_config) = match cfg {
+        let configs = match cfg {
             IndexWriterConfig::Hnsw(hnsw_config) => {
                 match hnsw_config.base_config.index_distance_type {
-                    DistanceType::DotProduct => {
+                    DistanceType::DotProduct | DistanceType::Cosine => {
                         self.do_build_hnsw_index::<DotProductDistanceCalculator>(
                             input,
                             &hnsw_config,
@@ -445,7 +1070,7 @@ impl IndexWriter {
             }
             IndexWriterConfig::Ivf(ivf_config) => {
                 match ivf_config.base_config.index_distance_type {
-                    DistanceType::DotProduct => {
+                    DistanceType::DotProduct | DistanceType::Cosine => {
                         self.do_build_ivf_index::<DotProductDistanceCalculator>(input, &ivf_config)?
                     }
                     DistanceType::L2 => {
@@ -455,12 +1080,81 @@ impl IndexWriter {
                 (ivf_config.base_config, ivf_config.quantizer_config)
             }
             IndexWriterConfig::Spann(hnsw_ivf_config) => {
+                // SPANN indexes are only searched with L2 distance
+                if hnsw_ivf_config.base_config.index_distance_type == DistanceType::Cosine {
+                    return Err(anyhow!(
+                        "Cosine distance is not supported for SPANN indexes"
+                    ));
+                }
                 self.do_build_ivf_hnsw_index(input, &hnsw_ivf_config)?;
                 (
                     hnsw_ivf_config.base_config,
                     hnsw_ivf_config.quantizer_config,
                 )
             }
+            IndexWriterConfig::Flat(flat_config) => {
+                self.do_build_flat_index(input, &flat_config)?;
+                // Vectors are not quantized, the config is only written for `IndexReader`
+                (flat_config.base_config, QuantizerConfig::default())
+            }
+        };
+        Ok(configs)
+    }
+
+    // Cosine indexes are dot product indexes over normalized vectors
+    fn build_index_for_distance(
+        &mut self,
+        input: &mut impl Input,
+    ) -> Result<(BaseConfig, QuantizerConfig)> {
+        if self.base_config().index_distance_type == DistanceType::Cosine {
+            self.build_index(&mut NormalizedInput::new(input)?)
+        } else {
+            self.build_index(input)
+        }
+    }
+
+    /// Same as `process`, for rows produced asynchronously. The index is built on a blocking
+    /// thread, which reads the rows from a bounded channel as this task receives them from
+    /// `input`. Builders make several passes over the rows, so they are kept until the index is
+    /// built.
+    pub async fn process_async(&mut self, input: &mut impl AsyncInput) -> Result<()> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(ASYNC_INPUT_CHANNEL_CAPACITY);
+        let mut writer = IndexWriter {
+            config: self.config.clone(),
+            output_root: self.output_root.clone(),
+        };
+        let build =
+            tokio::task::spawn_blocking(move || writer.process(&mut ChannelInput::new(receiver)));
+
+        let mut num_rows = 0;
+        while let Some(row) = input.next().await {
+            // The builder stopped reading, e.g. because it failed
+            if sender.send(row).await.is_err() {
+                break;
+            }
+            num_rows += 1;
+        }
+        drop(sender);
+        info!("Received {} rows", num_rows);
+        build.await?
+    }
+
+    pub fn process(&mut self, input: &mut impl Input) -> Result<()> {
+        let _span = BuildSpan::root(
+            &format!("{:?}", self.base_config().index_type).to_lowercase(),
+            input.num_rows() as u64,
+            self.base_config().dimension,
+            &self.output_root,
+        );
+        let (base_config, quantizer_config) = if self.base_config().deduplicate {
+            let mut deduplicated_input = DeduplicatedInput::new(&mut *input, false);
+            info!(
+                "Dropped {} rows with duplicate doc ids",
+                deduplicated_input.duplicate_count()
+            );
+            self.build_index_for_distance(&mut deduplicated_input)?
+        } else {
+            self.build_index_for_distance(input)?
         };
 
         // Finally, write the base config and the quantizer config
@@ -474,62 +1168,40 @@ impl IndexWriter {
             serde_yaml::to_string(&quantizer_config)?,
         )?;
 
+        // The posting list encoding is needed to read an IVF index back
+        if let IndexWriterConfig::Ivf(ivf_config) = &self.config {
+            std::fs::write(
+                format!("{}/ivf_config.yaml", self.output_root),
+                serde_yaml::to_string(&ivf_config.ivf_config)?,
+            )?;
+        }
+
         Ok(())
     }
+
+    /// Build a single index from the rows of all `inputs`, read one after another.
+    pub fn process_multi(&mut self, inputs: &mut [Box<dyn Input>]) -> Result<()> {
+        let mut sharded_input =
+            ShardedInput::new(inputs.iter_mut().map(|input| input.as_mut()).collect());
+        self.process(&mut sharded_input)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::path::Path;
+    use std::sync::Arc;
 
-    use config::enums::IndexType;
+    use config::enums::{IndexType, StorageBackend};
+    use index::utils::SearchContext;
     use rand::Rng;
     use tempdir::TempDir;
 
     use super::*;
-    use crate::config::{BaseConfig, HnswConfig, IvfConfig, QuantizerConfig};
-    use crate::input::Row;
-    // Mock Input implementation for testing
-    struct MockInput {
-        data: Vec<Vec<f32>>,
-        current_index: usize,
-    }
-
-    impl MockInput {
-        fn new(data: Vec<Vec<f32>>) -> Self {
-            Self {
-                data,
-                current_index: 0,
-            }
-        }
-    }
-
-    impl Input for MockInput {
-        fn num_rows(&self) -> usize {
-            self.data.len()
-        }
-
-        fn skip_to(&mut self, index: usize) {
-            self.current_index = index;
-        }
-
-        fn next(&mut self) -> Row {
-            let row = Row {
-                id: self.current_index as u64,
-                data: &self.data[self.current_index],
-            };
-            self.current_index += 1;
-            row
-        }
-
-        fn has_next(&self) -> bool {
-            self.current_index < self.data.len()
-        }
-
-        fn reset(&mut self) {
-            self.current_index = 0;
-        }
-    }
+    use crate::config::{HnswConfig, InputFormat, IvfConfig};
+    use crate::detection::IndexReader;
+    use crate::input::{MockInput, OwnedRow};
+    use crate::progress::BuildProgressCallback;
 
     #[test]
     fn test_get_sorted_random_rows() {
@@ -542,6 +1214,70 @@ mod tests {
         }
     }
 
+    // The default runtime has a single thread
+    #[tokio::test]
+    async fn test_index_writer_process_async() {
+        let dimension = 10;
+        let num_rows = 100;
+        let data: Vec<Vec<f32>> = (0..num_rows)
+            .map(|_| (0..dimension).map(|_| rand::random::<f32>()).collect())
+            .collect();
+        let query = data[42].clone();
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(8);
+        let producer = tokio::spawn(async move {
+            for (id, data) in data.into_iter().enumerate() {
+                sender
+                    .send(OwnedRow {
+                        id: id as u64,
+                        data,
+                    })
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let temp_dir = TempDir::new("test_index_writer_process_async")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: BaseConfig {
+                output_path: base_directory.clone(),
+                dimension,
+                max_memory_size: 1024 * 1024,
+                file_size: 1024 * 1024,
+                index_type: IndexType::Hnsw,
+                ..Default::default()
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::NoQuantizer,
+                num_training_rows: 50,
+                ..Default::default()
+            },
+            hnsw_config: HnswConfig {
+                num_layers: 2,
+                max_num_neighbors: 10,
+                ef_construction: 100,
+                quantize_layer_0: false,
+                max_graph_memory_bytes: None,
+                parallel_construction: false,
+                normalize_on_insert: false,
+            },
+        });
+
+        let mut index_writer = IndexWriter::new(config).expect("Failed to create index writer");
+        index_writer.process_async(&mut receiver).await.unwrap();
+        producer.await.unwrap();
+
+        let index = IndexReader::new(&format!("{}/hnsw", base_directory))
+            .unwrap()
+            .read()
+            .unwrap();
+        let results = index
+            .search(&query, 1, 100, &mut SearchContext::new(false))
+            .unwrap();
+        assert_eq!(results[0].id, 42);
+    }
+
     #[test]
     fn test_index_writer_process_hnsw() {
         // Setup test data
@@ -572,6 +1308,18 @@ mod tests {
             file_size: 1024 * 1024 * 1024,       // 1 GB
             index_type: IndexType::Hnsw,
             index_distance_type: DistanceType::L2,
+            deduplicate: false,
+            skip_vector_validation: false,
+            storage_backend: StorageBackend::Local,
+            s3_bucket: None,
+            s3_prefix: None,
+            ttl_seconds: None,
+            batch_size: 4096,
+            input_format: InputFormat::Hdf5,
+            progress_callback: None,
+            progress_interval: 10000,
+            compression: VectorCompressionType::None,
+            storage_precision: StoragePrecision::Float32,
         };
         let quantizer_config = QuantizerConfig {
             quantizer_type: QuantizerType::ProductQuantizer,
@@ -587,6 +1335,10 @@ mod tests {
             num_layers: 2,
             max_num_neighbors: 10,
             ef_construction: 100,
+            quantize_layer_0: false,
+            max_graph_memory_bytes: None,
+            parallel_construction: false,
+            normalize_on_insert: false,
         };
         let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
             base_config,
@@ -605,9 +1357,9 @@ mod tests {
         let pq_directory_path = format!("{}/quantizer", hnsw_directory_path);
         let pq_directory = Path::new(&pq_directory_path);
         let hnsw_vector_storage_path =
-            format!("{}/vector_storage", hnsw_directory.to_str().unwrap());
+            format!("{}/hnsw/vector_storage", hnsw_directory.to_str().unwrap());
         let hnsw_vector_storage = Path::new(&hnsw_vector_storage_path);
-        let hnsw_index_path = format!("{}/index", hnsw_directory.to_str().unwrap());
+        let hnsw_index_path = format!("{}/hnsw/index", hnsw_directory.to_str().unwrap());
         let hnsw_index = Path::new(&hnsw_index_path);
         assert!(pq_directory.exists());
         assert!(hnsw_directory.exists());
@@ -645,6 +1397,18 @@ mod tests {
             file_size: 1024 * 1024 * 1024,       // 1 GB
             index_type: IndexType::Ivf,
             index_distance_type: DistanceType::DotProduct,
+            deduplicate: false,
+            skip_vector_validation: false,
+            storage_backend: StorageBackend::Local,
+            s3_bucket: None,
+            s3_prefix: None,
+            ttl_seconds: None,
+            batch_size: 4096,
+            input_format: InputFormat::Hdf5,
+            progress_callback: None,
+            progress_interval: 10000,
+            compression: VectorCompressionType::None,
+            storage_precision: StoragePrecision::Float32,
         };
         let quantizer_config = QuantizerConfig {
             quantizer_type: QuantizerType::ProductQuantizer,
@@ -667,6 +1431,9 @@ mod tests {
             batch_size: 10,
             tolerance: 0.0,
             max_posting_list_size: usize::MAX,
+            auto_num_clusters: false,
+            target_imbalance: 0.0,
+            initial_centroids_fvecs_path: None,
         };
         let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
             base_config,
@@ -691,6 +1458,217 @@ mod tests {
         assert!(ivf_index.exists());
     }
 
+    #[derive(Default)]
+    struct RecordingProgressCallback {
+        events: std::sync::Mutex<Vec<(BuildPhase, usize, usize)>>,
+    }
+
+    impl BuildProgressCallback for RecordingProgressCallback {
+        fn on_progress(&self, phase: BuildPhase, processed: usize, total: usize) {
+            self.events.lock().unwrap().push((phase, processed, total));
+        }
+    }
+
+    impl RecordingProgressCallback {
+        // Reported phases, without consecutive duplicates
+        fn phases(&self) -> Vec<BuildPhase> {
+            let mut phases = self
+                .events
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(phase, _, _)| *phase)
+                .collect::<Vec<_>>();
+            phases.dedup();
+            phases
+        }
+    }
+
+    #[test]
+    fn test_index_writer_progress_callback() {
+        let mut rng = rand::thread_rng();
+        let dimension = 10;
+        let data: Vec<Vec<f32>> = (0..100)
+            .map(|_| (0..dimension).map(|_| rng.gen::<f32>()).collect())
+            .collect();
+        let temp_dir = TempDir::new("test_index_writer_progress_callback")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+
+        let callback = Arc::new(RecordingProgressCallback::default());
+        let base_config = BaseConfig {
+            output_path: base_directory.clone(),
+            dimension,
+            max_memory_size: 1024 * 1024 * 1024, // 1 GB
+            file_size: 1024 * 1024 * 1024,       // 1 GB
+            index_type: IndexType::Hnsw,
+            index_distance_type: DistanceType::L2,
+            progress_callback: Some(callback.clone()),
+            progress_interval: 10,
+            ..Default::default()
+        };
+        let quantizer_config = QuantizerConfig {
+            quantizer_type: QuantizerType::ProductQuantizer,
+            quantizer_distance_type: DistanceType::L2,
+            subvector_dimension: 2,
+            num_bits: 2,
+            num_training_rows: 50,
+            max_iteration: 10,
+            batch_size: 10,
+        };
+        let config = IndexWriterConfig::Hnsw(HnswConfigWithBase {
+            base_config: base_config.clone(),
+            quantizer_config: quantizer_config.clone(),
+            hnsw_config: HnswConfig {
+                num_layers: 2,
+                max_num_neighbors: 10,
+                ef_construction: 100,
+                ..Default::default()
+            },
+        });
+        let mut index_writer = IndexWriter::new(config).expect("Failed to create index writer");
+        index_writer
+            .process(&mut MockInput::new(data.clone()))
+            .unwrap();
+
+        assert_eq!(
+            callback.phases(),
+            vec![
+                BuildPhase::Training,
+                BuildPhase::Inserting,
+                BuildPhase::Writing
+            ]
+        );
+        {
+            let events = callback.events.lock().unwrap();
+            assert!(events.contains(&(BuildPhase::Training, 50, 50)));
+            // Every 10 rows, from the first interval to the last row
+            let inserted: Vec<usize> = events
+                .iter()
+                .filter(|(phase, _, _)| *phase == BuildPhase::Inserting)
+                .map(|(_, processed, _)| *processed)
+                .collect();
+            assert_eq!(inserted, (1..=10).map(|i| i * 10).collect::<Vec<_>>());
+            assert_eq!(events.last(), Some(&(BuildPhase::Writing, 100, 100)));
+        }
+
+        // IVF trains its centroids once all rows are inserted
+        let callback = Arc::new(RecordingProgressCallback::default());
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: BaseConfig {
+                index_type: IndexType::Ivf,
+                progress_callback: Some(callback.clone()),
+                ..base_config
+            },
+            quantizer_config,
+            ivf_config: IvfConfig {
+                num_clusters: 2,
+                num_data_points: 100,
+                max_clusters_per_vector: 1,
+                max_iteration: 10,
+                batch_size: 10,
+                max_posting_list_size: usize::MAX,
+                ..Default::default()
+            },
+        });
+        let mut index_writer = IndexWriter::new(config).expect("Failed to create index writer");
+        index_writer.process(&mut MockInput::new(data)).unwrap();
+        assert_eq!(
+            callback.phases(),
+            vec![
+                BuildPhase::Training,
+                BuildPhase::Inserting,
+                BuildPhase::Training,
+                BuildPhase::Writing
+            ]
+        );
+    }
+
+    #[test]
+    fn test_index_writer_process_multi() {
+        // Setup test data, split across two inputs
+        let mut rng = rand::thread_rng();
+        let dimension = 10;
+        let mut inputs: Vec<Box<dyn Input>> = (0..2)
+            .map(|_| {
+                let data: Vec<Vec<f32>> = (0..50)
+                    .map(|_| (0..dimension).map(|_| rng.gen::<f32>()).collect())
+                    .collect();
+                Box::new(MockInput::new(data)) as Box<dyn Input>
+            })
+            .collect();
+
+        // Create a temporary directory for output
+        let temp_dir = TempDir::new("test_index_writer_process_multi")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        // Configure IndexWriter
+        let base_config = BaseConfig {
+            output_path: base_directory.clone(),
+            dimension,
+            reindex: false,
+            max_memory_size: 1024 * 1024 * 1024, // 1 GB
+            file_size: 1024 * 1024 * 1024,       // 1 GB
+            index_type: IndexType::Ivf,
+            index_distance_type: DistanceType::L2,
+            deduplicate: false,
+            skip_vector_validation: false,
+            storage_backend: StorageBackend::Local,
+            s3_bucket: None,
+            s3_prefix: None,
+            ttl_seconds: None,
+            batch_size: 4096,
+            input_format: InputFormat::Hdf5,
+            progress_callback: None,
+            progress_interval: 10000,
+            compression: VectorCompressionType::None,
+            storage_precision: StoragePrecision::Float32,
+        };
+        let quantizer_config = QuantizerConfig {
+            quantizer_type: QuantizerType::NoQuantizer,
+            quantizer_distance_type: DistanceType::L2,
+            subvector_dimension: 0,
+            num_bits: 0,
+            num_training_rows: 0,
+
+            max_iteration: 0,
+            batch_size: 0,
+        };
+        let ivf_config = IvfConfig {
+            posting_list_encoding_type: IntSeqEncodingType::PlainEncoding,
+            num_clusters: 2,
+            num_data_points: 100,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+
+            max_iteration: 10,
+            batch_size: 10,
+            tolerance: 0.0,
+            max_posting_list_size: usize::MAX,
+            auto_num_clusters: false,
+            target_imbalance: 0.0,
+            initial_centroids_fvecs_path: None,
+        };
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config,
+            quantizer_config,
+            ivf_config,
+        });
+
+        let mut index_writer = IndexWriter::new(config).expect("Failed to create index writer");
+
+        // Process the inputs
+        index_writer.process_multi(&mut inputs).unwrap();
+
+        let ivf_index_path = format!("{}/ivf/index", base_directory);
+        assert!(Path::new(&ivf_index_path).exists());
+    }
+
     #[test]
     fn test_index_writer_process_ivf_hnsw() {
         // Setup test data
@@ -721,6 +1699,18 @@ mod tests {
             file_size: 1024 * 1024 * 1024,       // 1 GB
             index_type: IndexType::Spann,
             index_distance_type: DistanceType::L2,
+            deduplicate: false,
+            skip_vector_validation: false,
+            storage_backend: StorageBackend::Local,
+            s3_bucket: None,
+            s3_prefix: None,
+            ttl_seconds: None,
+            batch_size: 4096,
+            input_format: InputFormat::Hdf5,
+            progress_callback: None,
+            progress_interval: 10000,
+            compression: VectorCompressionType::None,
+            storage_precision: StoragePrecision::Float32,
         };
         let quantizer_config = QuantizerConfig {
             quantizer_type: QuantizerType::ProductQuantizer,
@@ -736,6 +1726,10 @@ mod tests {
             num_layers: 2,
             max_num_neighbors: 10,
             ef_construction: 100,
+            quantize_layer_0: false,
+            max_graph_memory_bytes: None,
+            parallel_construction: false,
+            normalize_on_insert: false,
         };
         let ivf_config = IvfConfig {
             posting_list_encoding_type: IntSeqEncodingType::PlainEncoding,
@@ -748,6 +1742,9 @@ mod tests {
             batch_size: 10,
             tolerance: 0.0,
             max_posting_list_size: usize::MAX,
+            auto_num_clusters: false,
+            target_imbalance: 0.0,
+            initial_centroids_fvecs_path: None,
         };
         let config = IndexWriterConfig::Spann(SpannConfigWithBase {
             base_config,
@@ -0,0 +1,321 @@
+use std::fs::File;
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use arrow::array::{AsArray, FixedSizeListArray, Float32Array, UInt64Array};
+use arrow::buffer::Buffer;
+use arrow::datatypes::{DataType, Float32Type, Schema, UInt64Type};
+use arrow::ipc::convert::fb_to_schema;
+use arrow::ipc::reader::{read_footer_length, FileDecoder};
+use arrow::ipc::{root_as_footer, Block};
+use memmap2::Mmap;
+
+use super::{Input, Row};
+
+pub const DEFAULT_ID_COLUMN: &str = "id";
+pub const DEFAULT_VECTOR_COLUMN: &str = "vector";
+
+// Size of the footer length and the magic bytes at the end of an Arrow IPC file
+const TRAILER_SIZE: usize = 10;
+
+struct ArrowBatch {
+    ids: UInt64Array,
+    vectors: FixedSizeListArray,
+    // Values of `vectors`, flattened
+    values: Float32Array,
+}
+
+/// Reads vectors from an Arrow IPC file (Feather v2), with a `u64` id column and a
+/// `FixedSizeList<f32>` vector column. Record batches are decoded in place from the
+/// memory-mapped file, and rows are returned as slices of the batches' buffers.
+pub struct ArrowInput {
+    batches: Vec<ArrowBatch>,
+    dimension: usize,
+    // Global index of the first row of every batch, followed by the number of rows
+    batch_offsets: Vec<usize>,
+    batch_idx: usize,
+    row_idx: usize,
+}
+
+impl ArrowInput {
+    pub fn new(file_path: &str, id_column: &str, vector_column: &str) -> Result<Self> {
+        let file = File::open(file_path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        let ptr = NonNull::new(mmap.as_ptr() as *mut u8)
+            .ok_or_else(|| anyhow!("Failed to map {}", file_path))?;
+        // The buffer keeps the mapping alive for as long as the batches reference it
+        let buffer = unsafe { Buffer::from_custom_allocation(ptr, mmap.len(), Arc::new(mmap)) };
+        Self::from_buffer(buffer, id_column, vector_column)
+    }
+
+    /// Reads an Arrow IPC file that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>, id_column: &str, vector_column: &str) -> Result<Self> {
+        Self::from_buffer(Buffer::from_vec(bytes), id_column, vector_column)
+    }
+
+    fn from_buffer(buffer: Buffer, id_column: &str, vector_column: &str) -> Result<Self> {
+        if buffer.len() < TRAILER_SIZE {
+            return Err(anyhow!("Input is too small to be an Arrow IPC file"));
+        }
+        let trailer_start = buffer.len() - TRAILER_SIZE;
+        let footer_len = read_footer_length(buffer[trailer_start..].try_into()?)?;
+        if footer_len > trailer_start {
+            return Err(anyhow!("Invalid Arrow footer length {}", footer_len));
+        }
+        let footer = root_as_footer(&buffer[trailer_start - footer_len..trailer_start])
+            .map_err(|e| anyhow!("Failed to read Arrow footer: {}", e))?;
+        let schema = fb_to_schema(
+            footer
+                .schema()
+                .ok_or_else(|| anyhow!("Arrow file has no schema"))?,
+        );
+        let dimension = Self::validate_schema(&schema, id_column, vector_column)?;
+
+        let mut decoder = FileDecoder::new(Arc::new(schema), footer.version());
+        let dictionaries: Vec<Block> = footer
+            .dictionaries()
+            .map(|blocks| blocks.iter().copied().collect())
+            .unwrap_or_default();
+        for block in &dictionaries {
+            decoder.read_dictionary(block, &Self::block_data(&buffer, block))?;
+        }
+
+        let mut batches = vec![];
+        let mut batch_offsets = vec![0];
+        let record_batches: Vec<Block> = footer
+            .recordBatches()
+            .map(|blocks| blocks.iter().copied().collect())
+            .unwrap_or_default();
+        for block in &record_batches {
+            let Some(batch) =
+                decoder.read_record_batch(block, &Self::block_data(&buffer, block))?
+            else {
+                continue;
+            };
+
+            let ids = batch
+                .column_by_name(id_column)
+                .ok_or_else(|| anyhow!("Missing column {}", id_column))?
+                .as_primitive::<UInt64Type>()
+                .clone();
+            let vectors = batch
+                .column_by_name(vector_column)
+                .ok_or_else(|| anyhow!("Missing column {}", vector_column))?
+                .as_fixed_size_list()
+                .clone();
+            let values = vectors.values().as_primitive::<Float32Type>().clone();
+            batch_offsets.push(batch_offsets.last().unwrap() + batch.num_rows());
+            batches.push(ArrowBatch {
+                ids,
+                vectors,
+                values,
+            });
+        }
+
+        Ok(Self {
+            batches,
+            dimension,
+            batch_offsets,
+            batch_idx: 0,
+            row_idx: 0,
+        })
+    }
+
+    fn block_data(buffer: &Buffer, block: &Block) -> Buffer {
+        let block_len = block.bodyLength() as usize + block.metaDataLength() as usize;
+        buffer.slice_with_length(block.offset() as usize, block_len)
+    }
+
+    /// Checks the types of the id and vector columns, and returns the dimension of the vectors.
+    fn validate_schema(schema: &Schema, id_column: &str, vector_column: &str) -> Result<usize> {
+        let id_type = schema.field_with_name(id_column)?.data_type();
+        if id_type != &DataType::UInt64 {
+            return Err(anyhow!(
+                "Column {} has type {}, expected UInt64",
+                id_column,
+                id_type
+            ));
+        }
+
+        match schema.field_with_name(vector_column)?.data_type() {
+            DataType::FixedSizeList(field, size) if field.data_type() == &DataType::Float32 => {
+                Ok(*size as usize)
+            }
+            data_type => Err(anyhow!(
+                "Column {} has type {}, expected FixedSizeList<Float32>",
+                vector_column,
+                data_type
+            )),
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+}
+
+impl Input for ArrowInput {
+    fn reset(&mut self) {
+        self.skip_to(0);
+    }
+
+    fn has_next(&self) -> bool {
+        self.row_idx < self.num_rows()
+    }
+
+    // Caller is responsible for checking has_next() before calling this
+    fn next(&mut self) -> Row<'_> {
+        // Skip the batches we're done with, including empty ones
+        while self.row_idx >= self.batch_offsets[self.batch_idx + 1] {
+            self.batch_idx += 1;
+        }
+        let idx = self.row_idx - self.batch_offsets[self.batch_idx];
+        self.row_idx += 1;
+
+        let batch = &self.batches[self.batch_idx];
+        let start = batch.vectors.value_offset(idx) as usize;
+        Row {
+            id: batch.ids.value(idx),
+            data: &batch.values.values()[start..start + self.dimension],
+        }
+    }
+
+    fn num_rows(&self) -> usize {
+        *self.batch_offsets.last().unwrap()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        // Last batch that starts at or before `row_idx`
+        self.batch_idx = self
+            .batch_offsets
+            .partition_point(|offset| *offset <= row_idx)
+            .saturating_sub(1)
+            .min(self.batches.len().saturating_sub(1));
+        self.row_idx = row_idx;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use arrow::datatypes::Field;
+    use arrow::ipc::writer::FileWriter;
+    use arrow::record_batch::RecordBatch;
+    use config::enums::{DistanceType, IndexType, QuantizerType};
+    use rand::Rng;
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::config::{
+        BaseConfig, IndexWriterConfig, IvfConfig, IvfConfigWithBase, QuantizerConfig,
+    };
+    use crate::index_writer::IndexWriter;
+
+    fn write_arrow_file(vectors: &[Vec<f32>], batch_size: usize) -> Vec<u8> {
+        let dimension = vectors[0].len() as i32;
+        let item = Arc::new(Field::new("item", DataType::Float32, true));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(DEFAULT_ID_COLUMN, DataType::UInt64, false),
+            Field::new(
+                DEFAULT_VECTOR_COLUMN,
+                DataType::FixedSizeList(item.clone(), dimension),
+                false,
+            ),
+        ]));
+
+        let mut bytes = vec![];
+        let mut writer = FileWriter::try_new(&mut bytes, &schema).unwrap();
+        for (chunk_idx, chunk) in vectors.chunks(batch_size).enumerate() {
+            let first_id = (chunk_idx * batch_size) as u64;
+            let ids = UInt64Array::from_iter_values(first_id..first_id + chunk.len() as u64);
+            let values = Float32Array::from(chunk.concat());
+            let vectors = FixedSizeListArray::new(item.clone(), dimension, Arc::new(values), None);
+            let batch =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(ids), Arc::new(vectors)])
+                    .unwrap();
+            writer.write(&batch).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        bytes
+    }
+
+    #[test]
+    fn test_arrow_input() {
+        let mut rng = rand::thread_rng();
+        let dimension = 8;
+        let vectors: Vec<Vec<f32>> = (0..200)
+            .map(|_| (0..dimension).map(|_| rng.gen::<f32>()).collect())
+            .collect();
+        let bytes = write_arrow_file(&vectors, 64);
+
+        let mut input =
+            ArrowInput::from_bytes(bytes, DEFAULT_ID_COLUMN, DEFAULT_VECTOR_COLUMN).unwrap();
+        assert_eq!(input.num_rows(), 200);
+        assert_eq!(input.dimension(), dimension);
+        let mut num_rows = 0;
+        while input.has_next() {
+            let row = input.next();
+            assert_eq!(row.id, num_rows as u64);
+            assert_eq!(row.data, vectors[num_rows].as_slice());
+            num_rows += 1;
+        }
+        assert_eq!(num_rows, 200);
+
+        // Rows in the middle and at the boundaries of batches
+        for row_idx in [0, 63, 64, 130, 199] {
+            input.skip_to(row_idx);
+            let row = input.next();
+            assert_eq!(row.id, row_idx as u64);
+            assert_eq!(row.data, vectors[row_idx].as_slice());
+        }
+
+        input.reset();
+        let temp_dir = TempDir::new("arrow_input_test").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: BaseConfig {
+                output_path: base_directory.clone(),
+                dimension,
+                max_memory_size: 1024 * 1024 * 1024, // 1 GB
+                file_size: 1024 * 1024 * 1024,       // 1 GB
+                index_type: IndexType::Ivf,
+                index_distance_type: DistanceType::L2,
+                ..Default::default()
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::NoQuantizer,
+                quantizer_distance_type: DistanceType::L2,
+                ..Default::default()
+            },
+            ivf_config: IvfConfig {
+                num_clusters: 2,
+                num_data_points: 200,
+                max_clusters_per_vector: 1,
+                max_iteration: 10,
+                batch_size: 10,
+                max_posting_list_size: usize::MAX,
+                ..Default::default()
+            },
+        });
+        let mut index_writer = IndexWriter::new(config).unwrap();
+        assert!(index_writer.process(&mut input).is_ok());
+        assert!(Path::new(&format!("{}/ivf/index", base_directory)).exists());
+        assert!(Path::new(&format!("{}/ivf/vectors", base_directory)).exists());
+    }
+
+    #[test]
+    fn test_arrow_input_invalid_schema() {
+        let vectors = vec![vec![0.1, 0.2], vec![0.3, 0.4]];
+        let bytes = write_arrow_file(&vectors, 2);
+        assert!(ArrowInput::from_bytes(bytes.clone(), "doc_id", DEFAULT_VECTOR_COLUMN).is_err());
+        // The id column doesn't hold vectors
+        assert!(ArrowInput::from_bytes(bytes, DEFAULT_ID_COLUMN, DEFAULT_ID_COLUMN).is_err());
+        assert!(
+            ArrowInput::from_bytes(vec![1, 2, 3], DEFAULT_ID_COLUMN, DEFAULT_VECTOR_COLUMN)
+                .is_err()
+        );
+    }
+}
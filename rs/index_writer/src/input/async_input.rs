@@ -0,0 +1,190 @@
+use std::cell::RefCell;
+use std::future::Future;
+
+use tokio::sync::mpsc::Receiver;
+
+use super::{Input, OwnedRow, Row};
+
+/// Input whose rows are produced asynchronously, e.g. by an embedding model running on other
+/// Tokio tasks.
+pub trait AsyncInput: Send {
+    // Return the next row, or None once the input is exhausted
+    fn next(&mut self) -> impl Future<Output = Option<OwnedRow>> + Send;
+
+    // Return true if more rows may come. `next` can still return None afterwards.
+    fn has_next(&self) -> impl Future<Output = bool> + Send;
+}
+
+impl AsyncInput for Receiver<OwnedRow> {
+    async fn next(&mut self) -> Option<OwnedRow> {
+        self.recv().await
+    }
+
+    async fn has_next(&self) -> bool {
+        !self.is_empty() || !self.is_closed()
+    }
+}
+
+/// `Input` over rows sent through a bounded channel, for index builders running on a blocking
+/// thread while the rows are produced. Rows are received as the builder reads them, and kept for
+/// its later passes. `num_rows` waits until the sender is dropped.
+pub struct ChannelInput {
+    state: RefCell<ChannelInputState>,
+    current_index: usize,
+}
+
+struct ChannelInputState {
+    receiver: Receiver<OwnedRow>,
+    rows: Vec<OwnedRow>,
+    closed: bool,
+}
+
+impl ChannelInputState {
+    // Receive rows until there are more than `len`, or the sender is dropped
+    fn receive_until(&mut self, len: usize) {
+        while self.rows.len() <= len && !self.closed {
+            match self.receiver.blocking_recv() {
+                Some(row) => self.rows.push(row),
+                None => self.closed = true,
+            }
+        }
+    }
+}
+
+impl ChannelInput {
+    /// Must be read outside of asynchronous code, e.g. in `tokio::task::spawn_blocking`.
+    pub fn new(receiver: Receiver<OwnedRow>) -> Self {
+        Self {
+            state: RefCell::new(ChannelInputState {
+                receiver,
+                rows: vec![],
+                closed: false,
+            }),
+            current_index: 0,
+        }
+    }
+}
+
+impl Input for ChannelInput {
+    fn has_next(&self) -> bool {
+        let mut state = self.state.borrow_mut();
+        state.receive_until(self.current_index);
+        self.current_index < state.rows.len()
+    }
+
+    fn next(&mut self) -> Row<'_> {
+        let state = self.state.get_mut();
+        state.receive_until(self.current_index);
+        let row = &state.rows[self.current_index];
+        self.current_index += 1;
+        Row {
+            id: row.id,
+            data: &row.data,
+        }
+    }
+
+    fn next_batch(&mut self, batch_size: usize) -> Vec<OwnedRow> {
+        let state = self.state.get_mut();
+        state.receive_until(self.current_index + batch_size.saturating_sub(1));
+        let end = (self.current_index + batch_size).min(state.rows.len());
+        let rows = state.rows[self.current_index..end].to_vec();
+        self.current_index = end;
+        rows
+    }
+
+    fn reset(&mut self) {
+        self.current_index = 0;
+    }
+
+    fn num_rows(&self) -> usize {
+        let mut state = self.state.borrow_mut();
+        state.receive_until(usize::MAX);
+        state.rows.len()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.current_index = row_idx;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_receiver_input() {
+        let (sender, mut receiver) = mpsc::channel(4);
+        let producer = tokio::spawn(async move {
+            for id in 0..10u64 {
+                sender
+                    .send(OwnedRow {
+                        id,
+                        data: vec![id as f32; 2],
+                    })
+                    .await
+                    .unwrap();
+            }
+        });
+
+        assert!(receiver.has_next().await);
+        let mut ids = vec![];
+        while let Some(row) = receiver.next().await {
+            ids.push(row.id);
+        }
+        producer.await.unwrap();
+        assert!(!receiver.has_next().await);
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_channel_input() {
+        let (sender, receiver) = mpsc::channel(4);
+        let reader = tokio::task::spawn_blocking(move || {
+            let mut input = ChannelInput::new(receiver);
+            // Rows are read while they are still being sent
+            assert!(input.has_next());
+            assert_eq!(input.next().id, 0);
+            assert_eq!(
+                input
+                    .next_batch(2)
+                    .iter()
+                    .map(|row| row.id)
+                    .collect::<Vec<_>>(),
+                vec![1, 2]
+            );
+
+            assert_eq!(input.num_rows(), 10);
+            input.reset();
+            let mut ids = vec![];
+            while input.has_next() {
+                let row = input.next();
+                assert_eq!(row.data, &[row.id as f32; 2]);
+                ids.push(row.id);
+            }
+            assert_eq!(ids, (0..10).collect::<Vec<_>>());
+
+            input.skip_to(7);
+            assert_eq!(input.next().id, 7);
+            let batch = input.next_batch(4);
+            assert_eq!(
+                batch.iter().map(|row| row.id).collect::<Vec<_>>(),
+                vec![8, 9]
+            );
+            assert!(!input.has_next());
+        });
+
+        for id in 0..10u64 {
+            sender
+                .send(OwnedRow {
+                    id,
+                    data: vec![id as f32; 2],
+                })
+                .await
+                .unwrap();
+        }
+        drop(sender);
+        reader.await.unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use super::{Input, Row};
+
+/// Wraps an `Input` and drops rows whose doc id has already been seen.
+///
+/// The wrapped input is scanned once on construction to find the row to keep for each doc id,
+/// so `num_rows` and `skip_to` operate on the deduplicated rows.
+pub struct DeduplicatedInput<I: Input> {
+    input: I,
+
+    // Sorted indices (in the wrapped input) of the rows to keep
+    kept_rows: Vec<usize>,
+    duplicate_count: u64,
+
+    // Position in `kept_rows`
+    row_idx: usize,
+    // Position in the wrapped input
+    input_row_idx: usize,
+}
+
+impl<I: Input> DeduplicatedInput<I> {
+    /// If `keep_last` is set, the last occurrence of a doc id is kept instead of the first one.
+    pub fn new(mut input: I, keep_last: bool) -> Self {
+        let mut row_for_id: HashMap<u64, usize> = HashMap::new();
+        let mut num_input_rows = 0;
+        input.reset();
+        while input.has_next() {
+            let id = input.next().id;
+            if keep_last {
+                row_for_id.insert(id, num_input_rows);
+            } else {
+                row_for_id.entry(id).or_insert(num_input_rows);
+            }
+            num_input_rows += 1;
+        }
+        input.reset();
+
+        let mut kept_rows: Vec<usize> = row_for_id.into_values().collect();
+        kept_rows.sort();
+        let duplicate_count = (num_input_rows - kept_rows.len()) as u64;
+        Self {
+            input,
+            kept_rows,
+            duplicate_count,
+            row_idx: 0,
+            input_row_idx: 0,
+        }
+    }
+
+    /// Number of rows dropped because of a duplicate doc id
+    pub fn duplicate_count(&self) -> u64 {
+        self.duplicate_count
+    }
+}
+
+impl<I: Input> Input for DeduplicatedInput<I> {
+    fn has_next(&self) -> bool {
+        self.row_idx < self.kept_rows.len()
+    }
+
+    // Caller is responsible for checking has_next() before calling this
+    fn next(&mut self) -> Row<'_> {
+        let target = self.kept_rows[self.row_idx];
+        if target < self.input_row_idx {
+            self.input.skip_to(target);
+            self.input_row_idx = target;
+        }
+        // Duplicates are usually sparse, so read past them instead of seeking
+        while self.input_row_idx < target {
+            self.input.next();
+            self.input_row_idx += 1;
+        }
+
+        self.row_idx += 1;
+        self.input_row_idx += 1;
+        self.input.next()
+    }
+
+    fn reset(&mut self) {
+        self.input.reset();
+        self.row_idx = 0;
+        self.input_row_idx = 0;
+    }
+
+    fn num_rows(&self) -> usize {
+        self.kept_rows.len()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.row_idx = row_idx;
+        if let Some(&target) = self.kept_rows.get(row_idx) {
+            self.input.skip_to(target);
+            self.input_row_idx = target;
+        }
+    }
+
+    fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.input.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::MockInput;
+
+    // Rows with the given ids, whose vector is their row index
+    fn mock_input(ids: Vec<u64>) -> MockInput {
+        let data = (0..ids.len()).map(|i| vec![i as f32]).collect();
+        MockInput::with_ids(ids, data)
+    }
+
+    fn collect(input: &mut impl Input) -> Vec<(u64, f32)> {
+        let mut rows = vec![];
+        while input.has_next() {
+            let row = input.next();
+            rows.push((row.id, row.data[0]));
+        }
+        rows
+    }
+
+    #[test]
+    fn test_deduplicated_input_keep_first() {
+        let mut input = DeduplicatedInput::new(mock_input(vec![1, 2, 1, 3, 2, 4]), false);
+        assert_eq!(input.duplicate_count(), 2);
+        assert_eq!(input.num_rows(), 4);
+        assert_eq!(
+            collect(&mut input),
+            vec![(1, 0.0), (2, 1.0), (3, 3.0), (4, 5.0)]
+        );
+
+        input.skip_to(2);
+        assert_eq!(input.next().id, 3);
+
+        input.reset();
+        assert_eq!(collect(&mut input).len(), 4);
+    }
+
+    #[test]
+    fn test_deduplicated_input_keep_last() {
+        let mut input = DeduplicatedInput::new(mock_input(vec![1, 2, 1, 3, 2, 4]), true);
+        assert_eq!(input.duplicate_count(), 2);
+        assert_eq!(
+            collect(&mut input),
+            vec![(1, 2.0), (3, 3.0), (2, 4.0), (4, 5.0)]
+        );
+    }
+}
@@ -4,7 +4,7 @@ use anyhow::Result;
 use log::error;
 use ndarray::s;
 
-use super::{Input, Row};
+use super::{Input, OwnedRow, Row};
 
 pub struct Hdf5Reader {
     dataset: hdf5::Dataset,
@@ -76,6 +76,40 @@ impl Input for Hdf5Reader {
         }
     }
 
+    // Reads the whole batch with a single slice, instead of going through `chunk`
+    fn next_batch(&mut self, batch_size: usize) -> Vec<OwnedRow> {
+        let start_idx = self.row_idx;
+        let end_idx = min(start_idx + batch_size, self.num_rows);
+        if start_idx >= end_idx {
+            return vec![];
+        }
+
+        let selection = s![start_idx..end_idx, ..];
+        let rows = match self.dataset.read_slice_2d(selection) {
+            Ok(batch) => batch
+                .axis_iter(ndarray::Axis(0))
+                .enumerate()
+                .map(|(i, row)| OwnedRow {
+                    id: (start_idx + i) as u64,
+                    data: row.to_vec(),
+                })
+                .collect(),
+            Err(e) => {
+                error!("Failed to read slice from dataset: {}", e);
+                vec![]
+            }
+        };
+
+        // `next` only fetches a chunk when it starts one, so fetch the chunk we stopped in
+        if end_idx < self.num_rows && end_idx % self.chunk_size != 0 {
+            self.first_chunk_fetched = false;
+            self.skip_to(end_idx);
+        } else {
+            self.row_idx = end_idx;
+        }
+        rows
+    }
+
     fn num_rows(&self) -> usize {
         self.num_rows
     }
@@ -117,6 +151,35 @@ mod tests {
         assert_eq!(it, 1000);
     }
 
+    #[test]
+    fn test_hdf5_reader_next_batch() {
+        let path = format!("{}/resources/test.hdf5", env!("CARGO_MANIFEST_DIR"));
+        let mut reader = Hdf5Reader::new(101, "test", &path).expect("Failed to create Hdf5Reader");
+        let mut expected = vec![];
+        while reader.has_next() {
+            expected.push(reader.next().data.to_vec());
+        }
+
+        // Mix batches with single rows, so that batches end in the middle of chunks
+        reader.reset();
+        let mut rows = vec![];
+        while reader.has_next() {
+            rows.extend(reader.next_batch(150));
+            if reader.has_next() {
+                let row = reader.next();
+                rows.push(OwnedRow {
+                    id: row.id,
+                    data: row.data.to_vec(),
+                });
+            }
+        }
+        assert_eq!(rows.len(), 1000);
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row.id, i as u64);
+            assert_eq!(row.data, expected[i]);
+        }
+    }
+
     #[test]
     fn test_hdf5_reader_kmeans() {
         let path = format!(
@@ -0,0 +1,305 @@
+use std::fs::File;
+
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+use serde::Deserialize;
+
+use super::{Input, OwnedRow, Row};
+
+#[derive(Deserialize)]
+struct JsonLinesRow {
+    id: u64,
+    vector: Vec<f32>,
+}
+
+/// Reads newline-delimited JSON objects of the form `{"id": 42, "vector": [0.1, 0.2, ...]}`
+/// from a memory-mapped file. The byte offset of every line is indexed when the input is
+/// created, without parsing the rows, so `skip_to` doesn't rescan the file. Rows are parsed when
+/// they are read, and a row that fails to be parsed ends the input and is reported by
+/// `take_error`.
+pub struct JsonLinesInput {
+    mmap: Mmap,
+    // Expected dimension of every vector, if known
+    dimension: Option<usize>,
+    // Byte offset of the start of every line, followed by the length of the file
+    line_offsets: Vec<usize>,
+    row_idx: usize,
+    error: Option<anyhow::Error>,
+
+    // Buffer for the current row
+    current: Vec<f32>,
+}
+
+impl JsonLinesInput {
+    pub fn new(file_path: &str, dimension: usize) -> Result<Self> {
+        Self::open(file_path, Some(dimension))
+    }
+
+    /// Like `new`, but vectors of any dimension are accepted.
+    pub fn open(file_path: &str, dimension: Option<usize>) -> Result<Self> {
+        let file = File::open(file_path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let mut line_offsets = vec![0];
+        for (i, byte) in mmap.iter().enumerate() {
+            // A newline at the end of the file doesn't start another line
+            if *byte == b'\n' && i + 1 < mmap.len() {
+                line_offsets.push(i + 1);
+            }
+        }
+        if mmap.is_empty() {
+            line_offsets.clear();
+        }
+        line_offsets.push(mmap.len());
+
+        Ok(Self {
+            mmap,
+            dimension,
+            line_offsets,
+            row_idx: 0,
+            error: None,
+            current: vec![],
+        })
+    }
+
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    /// Sets the dimension that every vector must have.
+    pub fn set_dimension(&mut self, dimension: usize) {
+        self.dimension = Some(dimension);
+    }
+
+    /// Parses the row at `row_idx`. Fails if the line is not valid JSON, or if its vector doesn't
+    /// have the expected dimension.
+    pub fn read_row(&self, row_idx: usize) -> Result<OwnedRow> {
+        if row_idx >= self.num_rows() {
+            return Err(anyhow!(
+                "Row {} is out of bounds, the input has {} rows",
+                row_idx,
+                self.num_rows()
+            ));
+        }
+
+        let line = &self.mmap[self.line_offsets[row_idx]..self.line_offsets[row_idx + 1]];
+        let row: JsonLinesRow = serde_json::from_slice(line.trim_ascii_end())
+            .map_err(|e| anyhow!("Failed to parse row {}: {}", row_idx, e))?;
+        match self.dimension {
+            Some(dimension) if dimension != row.vector.len() => Err(anyhow!(
+                "Row {} (id {}) has dimension {}, expected {}",
+                row_idx,
+                row.id,
+                row.vector.len(),
+                dimension
+            )),
+            _ => Ok(OwnedRow {
+                id: row.id,
+                data: row.vector,
+            }),
+        }
+    }
+
+    /// Reads the next row. On failure, records the error and ends the input.
+    fn read_next(&mut self) -> Option<OwnedRow> {
+        let row_idx = self.row_idx;
+        match self.read_row(row_idx) {
+            Ok(row) => {
+                self.row_idx += 1;
+                Some(row)
+            }
+            Err(e) => {
+                self.error = Some(e);
+                self.row_idx = self.num_rows();
+                None
+            }
+        }
+    }
+}
+
+impl Input for JsonLinesInput {
+    fn reset(&mut self) {
+        self.row_idx = 0;
+    }
+
+    fn has_next(&self) -> bool {
+        self.row_idx < self.num_rows()
+    }
+
+    // Caller is responsible for checking has_next() before calling this. A row that fails to be
+    // parsed is returned with an empty vector, and the error is reported by `take_error`.
+    fn next(&mut self) -> Row<'_> {
+        let row_idx = self.row_idx as u64;
+        match self.read_next() {
+            Some(row) => {
+                self.current = row.data;
+                Row {
+                    id: row.id,
+                    data: &self.current,
+                }
+            }
+            None => {
+                self.current.clear();
+                Row {
+                    id: row_idx,
+                    data: &self.current,
+                }
+            }
+        }
+    }
+
+    // Parsed vectors are moved into the rows. The batch stops before a row that fails to be
+    // parsed.
+    fn next_batch(&mut self, batch_size: usize) -> Vec<OwnedRow> {
+        let mut rows =
+            Vec::with_capacity(batch_size.min(self.num_rows().saturating_sub(self.row_idx)));
+        while rows.len() < batch_size && self.has_next() {
+            match self.read_next() {
+                Some(row) => rows.push(row),
+                None => break,
+            }
+        }
+        rows
+    }
+
+    fn num_rows(&self) -> usize {
+        self.line_offsets.len() - 1
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.row_idx = row_idx.min(self.num_rows());
+    }
+
+    fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.error.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use config::enums::{DistanceType, IndexType, QuantizerType};
+    use rand::Rng;
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::config::{
+        BaseConfig, IndexWriterConfig, IvfConfig, IvfConfigWithBase, QuantizerConfig,
+    };
+    use crate::index_writer::IndexWriter;
+
+    fn write_file(dir: &TempDir, content: &str) -> String {
+        let path = format!("{}/input.jsonl", dir.path().to_str().unwrap());
+        let mut file = File::create(&path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        path
+    }
+
+    fn ivf_config(base_directory: &str, dimension: usize) -> IndexWriterConfig {
+        IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: BaseConfig {
+                output_path: base_directory.to_string(),
+                dimension,
+                max_memory_size: 1024 * 1024 * 1024, // 1 GB
+                file_size: 1024 * 1024 * 1024,       // 1 GB
+                index_type: IndexType::Ivf,
+                index_distance_type: DistanceType::L2,
+                ..Default::default()
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::NoQuantizer,
+                quantizer_distance_type: DistanceType::L2,
+                ..Default::default()
+            },
+            ivf_config: IvfConfig {
+                num_clusters: 2,
+                num_data_points: 100,
+                max_clusters_per_vector: 1,
+                max_iteration: 10,
+                batch_size: 10,
+                max_posting_list_size: usize::MAX,
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn test_json_lines_input() {
+        let temp_dir = TempDir::new("json_lines_input_test").unwrap();
+        let mut rng = rand::thread_rng();
+        let dimension = 8;
+        let vectors: Vec<Vec<f32>> = (0..100)
+            .map(|_| (0..dimension).map(|_| rng.gen::<f32>()).collect())
+            .collect();
+        let content: String = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, vector)| format!("{{\"id\": {}, \"vector\": {:?}}}\n", i * 10, vector))
+            .collect();
+        let path = write_file(&temp_dir, &content);
+
+        let mut input = JsonLinesInput::new(&path, dimension).unwrap();
+        assert_eq!(input.num_rows(), 100);
+        let mut num_rows = 0;
+        while input.has_next() {
+            let row = input.next();
+            assert_eq!(row.id, num_rows as u64 * 10);
+            assert_eq!(row.data, vectors[num_rows].as_slice());
+            num_rows += 1;
+        }
+        assert_eq!(num_rows, 100);
+        assert!(input.take_error().is_none());
+
+        input.skip_to(42);
+        let row = input.next();
+        assert_eq!(row.id, 420);
+        assert_eq!(row.data, vectors[42].as_slice());
+
+        input.reset();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let config = ivf_config(&base_directory, dimension);
+        let mut index_writer = IndexWriter::new(config).unwrap();
+        assert!(index_writer.process(&mut input).is_ok());
+        assert!(std::path::Path::new(&format!("{}/ivf/index", base_directory)).exists());
+    }
+
+    #[test]
+    fn test_json_lines_input_malformed() {
+        let temp_dir = TempDir::new("json_lines_input_malformed_test").unwrap();
+        let path = write_file(
+            &temp_dir,
+            "{\"id\": 1, \"vector\": [0.1, 0.2]}\n\
+             {\"id\": 2, \"vector\": [0.1]}\n\
+             {\"id\": 3, \"vector\": [0.1, \n\
+             {\"id\": 4, \"vector\": [0.3, 0.4]}",
+        );
+
+        let mut input = JsonLinesInput::new(&path, 2).unwrap();
+        assert_eq!(input.num_rows(), 4);
+        assert!(input.read_row(0).is_ok());
+        assert!(input.read_row(1).is_err());
+        assert!(input.read_row(2).is_err());
+        assert!(input.read_row(4).is_err());
+
+        // A malformed row ends the batch and the input, and its error is reported
+        input.skip_to(2);
+        assert!(input.next_batch(2).is_empty());
+        assert!(!input.has_next());
+        let err = input.take_error().unwrap();
+        assert!(err.to_string().contains("Failed to parse row 2"));
+        assert!(input.take_error().is_none());
+
+        input.skip_to(3);
+        let row = input.next();
+        assert_eq!(row.id, 4);
+        assert_eq!(row.data, &[0.3, 0.4]);
+        assert!(!input.has_next());
+
+        // The error fails the build instead of inserting an empty vector
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let mut index_writer = IndexWriter::new(ivf_config(&base_directory, 2)).unwrap();
+        let err = index_writer.process(&mut input).unwrap_err();
+        assert!(err.to_string().contains("Row 1 (id 2) has dimension 1"));
+    }
+}
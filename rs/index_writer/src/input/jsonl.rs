@@ -0,0 +1,149 @@
+use anyhow::Result;
+
+use super::json_lines::JsonLinesInput;
+use super::{Input, OwnedRow, Row};
+
+/// Reads newline-delimited JSON objects of the form `{"id": 42, "vector": [0.1, 0.2, ...]}`,
+/// when the dimension of the vectors is not known upfront. Rows are read by `JsonLinesInput`.
+pub struct JsonlInput {
+    input: JsonLinesInput,
+}
+
+impl JsonlInput {
+    /// If `dimension_check` is set, every vector must have the same dimension as the first row.
+    /// Only the first row is parsed here, other rows are checked when they are read.
+    pub fn new(file_path: &str, dimension_check: bool) -> Result<Self> {
+        let mut input = JsonLinesInput::open(file_path, None)?;
+        if dimension_check && input.num_rows() > 0 {
+            let dimension = input.read_row(0)?.data.len();
+            input.set_dimension(dimension);
+        }
+        Ok(Self { input })
+    }
+}
+
+impl Input for JsonlInput {
+    fn reset(&mut self) {
+        self.input.reset();
+    }
+
+    fn has_next(&self) -> bool {
+        self.input.has_next()
+    }
+
+    // Caller is responsible for checking has_next() before calling this
+    fn next(&mut self) -> Row<'_> {
+        self.input.next()
+    }
+
+    fn next_batch(&mut self, batch_size: usize) -> Vec<OwnedRow> {
+        self.input.next_batch(batch_size)
+    }
+
+    fn num_rows(&self) -> usize {
+        self.input.num_rows()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.input.skip_to(row_idx);
+    }
+
+    fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.input.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn write_file(dir: &TempDir, content: &str) -> String {
+        let path = format!("{}/input.jsonl", dir.path().to_str().unwrap());
+        let mut file = File::create(&path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_jsonl_input() {
+        let temp_dir = TempDir::new("jsonl_input_test").unwrap();
+        let path = write_file(
+            &temp_dir,
+            "{\"id\": 42, \"vector\": [0.1, 0.2, 0.3]}\n\
+             {\"id\": 7, \"vector\": [1.0, 2.0, 3.0]}\n\
+             {\"id\": 100, \"vector\": [4.0, 5.0, 6.0]}",
+        );
+
+        let mut input = JsonlInput::new(&path, false).unwrap();
+        assert_eq!(input.num_rows(), 3);
+
+        let mut ids = vec![];
+        while input.has_next() {
+            let row = input.next();
+            assert_eq!(row.data.len(), 3);
+            ids.push(row.id);
+        }
+        assert_eq!(ids, vec![42, 7, 100]);
+
+        input.skip_to(1);
+        assert!(input.has_next());
+        let row = input.next();
+        assert_eq!(row.id, 7);
+        assert_eq!(row.data, &[1.0, 2.0, 3.0]);
+
+        input.reset();
+        assert_eq!(input.next().id, 42);
+
+        let batch = input.next_batch(4);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].id, 7);
+        assert_eq!(batch[1].data, vec![4.0, 5.0, 6.0]);
+        assert!(!input.has_next());
+    }
+
+    #[test]
+    fn test_jsonl_input_dimension_check() {
+        let temp_dir = TempDir::new("jsonl_input_dimension_check_test").unwrap();
+        let path = write_file(
+            &temp_dir,
+            "{\"id\": 1, \"vector\": [0.1, 0.2]}\n{\"id\": 2, \"vector\": [0.1]}\n",
+        );
+
+        let mut input = JsonlInput::new(&path, true).unwrap();
+        assert_eq!(input.next_batch(2).len(), 1);
+        assert!(input
+            .take_error()
+            .unwrap()
+            .to_string()
+            .contains("dimension 1"));
+
+        let mut input = JsonlInput::new(&path, false).unwrap();
+        assert_eq!(input.num_rows(), 2);
+        assert_eq!(input.next_batch(2).len(), 2);
+        assert!(input.take_error().is_none());
+    }
+
+    #[test]
+    fn test_jsonl_input_malformed() {
+        let temp_dir = TempDir::new("jsonl_input_malformed_test").unwrap();
+        let path = write_file(
+            &temp_dir,
+            "{\"id\": 1, \"vector\": [0.1, 0.2]}\n\
+             {\"id\": 3, \"vector\": [0.1, \n\
+             {\"id\": 4, \"vector\": [0.3, 0.4]}",
+        );
+
+        // Malformed rows end the input and are reported instead of being read with an empty vector
+        let mut input = JsonlInput::new(&path, false).unwrap();
+        assert_eq!(input.num_rows(), 3);
+        assert_eq!(input.next_batch(3).len(), 1);
+        assert!(!input.has_next());
+        let err = input.take_error().unwrap();
+        assert!(err.to_string().contains("row 1"));
+    }
+}
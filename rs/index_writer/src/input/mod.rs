@@ -1,10 +1,27 @@
+pub mod arrow;
+pub mod async_input;
+pub mod dedup;
 pub mod hdf5;
+pub mod json_lines;
+pub mod jsonl;
+pub mod normalized;
+pub mod npy;
+pub mod npz;
+pub mod sharded;
 
 pub struct Row<'a> {
     pub id: u64,
     pub data: &'a [f32],
 }
 
+/// A row that owns its vector, so that it can outlive the next call to the input, or be sent
+/// across tasks.
+#[derive(Debug, Clone)]
+pub struct OwnedRow {
+    pub id: u64,
+    pub data: Vec<f32>,
+}
+
 pub trait Input {
     // Return true if there are more rows to read
     fn has_next(&self) -> bool;
@@ -12,6 +29,20 @@ pub trait Input {
     // Return the next row of data
     fn next(&mut self) -> Row;
 
+    // Return the next `batch_size` rows, or fewer at the end of the input. Inputs that can read
+    // many rows at once should override this.
+    fn next_batch(&mut self, batch_size: usize) -> Vec<OwnedRow> {
+        let mut rows = Vec::with_capacity(batch_size);
+        while rows.len() < batch_size && self.has_next() {
+            let row = self.next();
+            rows.push(OwnedRow {
+                id: row.id,
+                data: row.data.to_vec(),
+            });
+        }
+        rows
+    }
+
     // Reset the state of the input to the beginning
     // This is helpful when we want to do multiple passes over the same input
     fn reset(&mut self);
@@ -21,4 +52,94 @@ pub trait Input {
 
     // Skip to a specific row
     fn skip_to(&mut self, row_idx: usize);
+
+    // Return the error hit while reading rows, if any, and clear it. Inputs that parse rows
+    // lazily report malformed rows here: such rows end the input and are left out of
+    // `next_batch`.
+    fn take_error(&mut self) -> Option<anyhow::Error> {
+        None
+    }
+}
+
+impl<T: Input + ?Sized> Input for &mut T {
+    fn has_next(&self) -> bool {
+        (**self).has_next()
+    }
+
+    fn next(&mut self) -> Row<'_> {
+        (**self).next()
+    }
+
+    fn next_batch(&mut self, batch_size: usize) -> Vec<OwnedRow> {
+        (**self).next_batch(batch_size)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+
+    fn num_rows(&self) -> usize {
+        (**self).num_rows()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        (**self).skip_to(row_idx)
+    }
+
+    fn take_error(&mut self) -> Option<anyhow::Error> {
+        (**self).take_error()
+    }
+}
+
+/// In-memory input for tests.
+#[cfg(test)]
+pub(crate) struct MockInput {
+    ids: Vec<u64>,
+    data: Vec<Vec<f32>>,
+    current_index: usize,
+}
+
+#[cfg(test)]
+impl MockInput {
+    /// Rows with ids 0, 1, 2, ...
+    pub(crate) fn new(data: Vec<Vec<f32>>) -> Self {
+        Self::with_ids((0..data.len() as u64).collect(), data)
+    }
+
+    pub(crate) fn with_ids(ids: Vec<u64>, data: Vec<Vec<f32>>) -> Self {
+        assert_eq!(ids.len(), data.len());
+        Self {
+            ids,
+            data,
+            current_index: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Input for MockInput {
+    fn has_next(&self) -> bool {
+        self.current_index < self.data.len()
+    }
+
+    fn next(&mut self) -> Row<'_> {
+        let row = Row {
+            id: self.ids[self.current_index],
+            data: &self.data[self.current_index],
+        };
+        self.current_index += 1;
+        row
+    }
+
+    fn reset(&mut self) {
+        self.current_index = 0;
+    }
+
+    fn num_rows(&self) -> usize {
+        self.data.len()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.current_index = row_idx;
+    }
 }
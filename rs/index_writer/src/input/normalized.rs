@@ -0,0 +1,125 @@
+use anyhow::{anyhow, Result};
+use log::error;
+use utils::l2_normalize;
+
+use super::{Input, OwnedRow, Row};
+
+// Number of rows read at once while checking the input
+const CHECK_BATCH_SIZE: usize = 4096;
+
+/// Wraps an `Input` and L2-normalizes the vector of every row, so that the dot product of two
+/// rows is their cosine similarity. Vectors are normalized like with
+/// `HnswBuilder::set_normalize_on_insert`, and zero vectors are an error.
+pub struct NormalizedInput<I: Input> {
+    input: I,
+
+    // Normalized vector of the last row returned by `next`
+    current: Vec<f32>,
+}
+
+impl<I: Input> NormalizedInput<I> {
+    /// Reads the input once to check that every vector can be normalized, since rows can't fail
+    /// once they are returned by `next`.
+    pub fn new(mut input: I) -> Result<Self> {
+        input.reset();
+        while input.has_next() {
+            for mut row in input.next_batch(CHECK_BATCH_SIZE) {
+                l2_normalize(&mut row.data).map_err(|e| anyhow!("Row {}: {}", row.id, e))?;
+            }
+        }
+        if let Some(e) = input.take_error() {
+            return Err(e);
+        }
+        input.reset();
+
+        Ok(Self {
+            input,
+            current: vec![],
+        })
+    }
+}
+
+fn normalize_in_place(id: u64, vector: &mut [f32]) {
+    if let Err(e) = l2_normalize(vector) {
+        error!("Row {}: {}", id, e);
+    }
+}
+
+impl<I: Input> Input for NormalizedInput<I> {
+    fn has_next(&self) -> bool {
+        self.input.has_next()
+    }
+
+    fn next(&mut self) -> Row<'_> {
+        let row = self.input.next();
+        let id = row.id;
+        self.current.clear();
+        self.current.extend_from_slice(row.data);
+        normalize_in_place(id, &mut self.current);
+        Row {
+            id,
+            data: &self.current,
+        }
+    }
+
+    fn next_batch(&mut self, batch_size: usize) -> Vec<OwnedRow> {
+        let mut rows = self.input.next_batch(batch_size);
+        rows.iter_mut()
+            .for_each(|row| normalize_in_place(row.id, &mut row.data));
+        rows
+    }
+
+    fn reset(&mut self) {
+        self.input.reset();
+    }
+
+    fn num_rows(&self) -> usize {
+        self.input.num_rows()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.input.skip_to(row_idx);
+    }
+
+    fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.input.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::MockInput;
+
+    #[test]
+    fn test_normalized_input() {
+        let mut input = NormalizedInput::new(MockInput::new(vec![
+            vec![3.0, 4.0],
+            vec![0.0, 0.5],
+            vec![0.0, -2.0],
+        ]))
+        .unwrap();
+        assert_eq!(input.num_rows(), 3);
+
+        let row = input.next();
+        assert_eq!(row.id, 0);
+        assert_eq!(row.data, &[0.6, 0.8]);
+        assert_eq!(input.next().data, &[0.0, 1.0]);
+
+        input.skip_to(2);
+        assert_eq!(input.next().data, &[0.0, -1.0]);
+        assert!(!input.has_next());
+
+        input.reset();
+        let rows = input.next_batch(10);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].data, vec![0.6, 0.8]);
+        assert_eq!(rows[2].data, vec![0.0, -1.0]);
+    }
+
+    #[test]
+    fn test_normalized_input_zero_vector() {
+        let result = NormalizedInput::new(MockInput::new(vec![vec![3.0, 4.0], vec![0.0, 0.0]]));
+        assert!(result.err().unwrap().to_string().contains("Row 1"));
+    }
+}
@@ -0,0 +1,326 @@
+use std::fs::File;
+
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+
+use super::{Input, Row};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+enum NpyData {
+    // Data section of a memory-mapped file, starting at `data_offset`
+    Mapped { mmap: Mmap, data_offset: usize },
+    Owned(Vec<f32>),
+}
+
+/// Reads vectors from a NumPy `.npy` file holding a 2D array of little endian `float32`, in C
+/// order. The row index is used as the doc id. Files are memory-mapped, and rows are returned as
+/// slices of the mapping.
+pub struct NpyInput {
+    data: NpyData,
+    num_rows: usize,
+    dimension: usize,
+    row_idx: usize,
+}
+
+impl NpyInput {
+    pub fn new(file_path: &str) -> Result<Self> {
+        if cfg!(target_endian = "big") {
+            return Err(anyhow!(
+                "Memory-mapped npy files are only supported on little endian"
+            ));
+        }
+        let file = File::open(file_path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        let (num_rows, dimension, data_offset) = Self::parse_header(&mmap)?;
+        // The header is padded so that the data is aligned, and the mapping is page aligned
+        if data_offset % std::mem::align_of::<f32>() != 0 {
+            return Err(anyhow!("Data of {} is not aligned", file_path));
+        }
+        Ok(Self {
+            data: NpyData::Mapped { mmap, data_offset },
+            num_rows,
+            dimension,
+            row_idx: 0,
+        })
+    }
+
+    /// Reads a `.npy` file that is already in memory. The data is copied, since `bytes` may not
+    /// be aligned for `f32`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (num_rows, dimension, data_offset) = Self::parse_header(bytes)?;
+        let values = bytes[data_offset..data_offset + num_rows * dimension * 4]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        Ok(Self {
+            data: NpyData::Owned(values),
+            num_rows,
+            dimension,
+            row_idx: 0,
+        })
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// Parses the header of a `.npy` file, and returns the shape of the array with the offset of
+    /// its data. Only 2D arrays of little endian `float32` in C order are supported.
+    fn parse_header(bytes: &[u8]) -> Result<(usize, usize, usize)> {
+        if bytes.len() < 10 || !bytes.starts_with(NPY_MAGIC) {
+            return Err(anyhow!("Input is not a npy file"));
+        }
+        // Version 1 stores the header length in 2 bytes, versions 2 and 3 in 4 bytes
+        let (header_start, header_len) = match bytes[6] {
+            1 => (10, u16::from_le_bytes([bytes[8], bytes[9]]) as usize),
+            2 | 3 if bytes.len() >= 12 => {
+                (12, u32::from_le_bytes(bytes[8..12].try_into()?) as usize)
+            }
+            version => return Err(anyhow!("Unsupported npy version {}", version)),
+        };
+        let data_offset = header_start + header_len;
+        if data_offset > bytes.len() {
+            return Err(anyhow!("Truncated npy header"));
+        }
+        let header = std::str::from_utf8(&bytes[header_start..data_offset])?;
+
+        let descr = Self::header_value(header, "descr")?;
+        if descr != "'<f4'" {
+            return Err(anyhow!("Unsupported dtype {}, expected '<f4'", descr));
+        }
+        let fortran_order = Self::header_value(header, "fortran_order")?;
+        if fortran_order != "False" {
+            return Err(anyhow!("Arrays in Fortran order are not supported"));
+        }
+        let shape = Self::header_value(header, "shape")?;
+        let dims = shape
+            .trim_matches(|c| c == '(' || c == ')')
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| d.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("Invalid shape {}: {}", shape, e))?;
+        let [num_rows, dimension] = dims[..] else {
+            return Err(anyhow!("Expected a 2D array, got shape {}", shape));
+        };
+
+        let data_len = num_rows
+            .checked_mul(dimension)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| anyhow!("Invalid shape {}", shape))?;
+        if bytes.len() - data_offset < data_len {
+            return Err(anyhow!(
+                "Truncated npy data: expected {} bytes, got {}",
+                data_len,
+                bytes.len() - data_offset
+            ));
+        }
+        Ok((num_rows, dimension, data_offset))
+    }
+
+    /// Returns the literal value of `key` in the header, a Python dict such as
+    /// `{'descr': '<f4', 'fortran_order': False, 'shape': (10, 4), }`.
+    fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str> {
+        let pattern = format!("'{}':", key);
+        let start = header
+            .find(&pattern)
+            .ok_or_else(|| anyhow!("Missing {} in npy header", key))?
+            + pattern.len();
+        let value = header[start..].trim_start();
+        // Tuples contain commas, so they end at the closing parenthesis
+        let end = if value.starts_with('(') {
+            value.find(')').map(|i| i + 1)
+        } else {
+            value.find([',', '}'])
+        }
+        .ok_or_else(|| anyhow!("Invalid {} in npy header", key))?;
+        Ok(value[..end].trim())
+    }
+
+    fn values(&self) -> &[f32] {
+        match &self.data {
+            NpyData::Mapped { mmap, data_offset } => unsafe {
+                std::slice::from_raw_parts(
+                    mmap[*data_offset..].as_ptr() as *const f32,
+                    self.num_rows * self.dimension,
+                )
+            },
+            NpyData::Owned(values) => values,
+        }
+    }
+}
+
+impl Input for NpyInput {
+    fn has_next(&self) -> bool {
+        self.row_idx < self.num_rows
+    }
+
+    // Caller is responsible for checking has_next() before calling this
+    fn next(&mut self) -> Row<'_> {
+        let row_idx = self.row_idx;
+        self.row_idx += 1;
+        let start = row_idx * self.dimension;
+        Row {
+            id: row_idx as u64,
+            data: &self.values()[start..start + self.dimension],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.row_idx = 0;
+    }
+
+    fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.row_idx = row_idx;
+    }
+}
+
+/// Serializes `vectors` as a version 1 `.npy` file. Used by tests of the npy inputs.
+#[cfg(test)]
+pub(crate) fn npy_bytes(vectors: &[Vec<f32>]) -> Vec<u8> {
+    let dimension = vectors.first().map_or(0, |v| v.len());
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        vectors.len(),
+        dimension
+    );
+    // The data starts at a multiple of 64 bytes, and the header ends with a newline
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut bytes = NPY_MAGIC.to_vec();
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for value in vectors.iter().flatten() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use config::enums::{DistanceType, IndexType, IntSeqEncodingType, QuantizerType};
+    use index::utils::SearchContext;
+    use tempdir::TempDir;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::config::{
+        BaseConfig, IndexWriterConfig, IvfConfig, IvfConfigWithBase, QuantizerConfig,
+    };
+    use crate::detection::IndexReader;
+    use crate::index_writer::IndexWriter;
+
+    #[test]
+    fn test_npy_input() {
+        let vectors = (0..10)
+            .map(|i| vec![i as f32, -(i as f32), 0.5])
+            .collect::<Vec<_>>();
+        let bytes = npy_bytes(&vectors);
+
+        let temp_dir = TempDir::new("test_npy_input").unwrap();
+        let path = temp_dir.path().join("vectors.npy");
+        std::fs::write(&path, &bytes).unwrap();
+
+        for mut input in [
+            NpyInput::new(path.to_str().unwrap()).unwrap(),
+            NpyInput::from_bytes(&bytes).unwrap(),
+        ] {
+            assert_eq!(input.num_rows(), 10);
+            assert_eq!(input.dimension(), 3);
+            let row = input.next();
+            assert_eq!(row.id, 0);
+            assert_eq!(row.data, &vectors[0][..]);
+
+            input.skip_to(7);
+            let row = input.next();
+            assert_eq!(row.id, 7);
+            assert_eq!(row.data, &vectors[7][..]);
+
+            let rows = input.next_batch(10);
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows[1].data, vectors[9]);
+            assert!(!input.has_next());
+
+            input.reset();
+            assert!(input.has_next());
+        }
+    }
+
+    #[test]
+    fn test_npy_input_invalid_header() {
+        assert!(NpyInput::from_bytes(b"not a npy file").is_err());
+
+        let bytes = npy_bytes(&[vec![1.0, 2.0]]);
+        let header_len = 10 + u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let header = std::str::from_utf8(&bytes[..header_len]).unwrap();
+        for (from, to) in [("'<f4'", "'<f8'"), ("False", "True "), ("(1, 2)", "(2,  )")] {
+            let invalid = [header.replace(from, to).as_bytes(), &bytes[header_len..]].concat();
+            assert!(NpyInput::from_bytes(&invalid).is_err());
+        }
+
+        // The data is shorter than the shape
+        assert!(NpyInput::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_npy_input_build_ivf() {
+        let dimension = 4;
+        let vectors = (0..100)
+            .map(|_| generate_random_vector(dimension))
+            .collect::<Vec<_>>();
+        let mut input = NpyInput::from_bytes(&npy_bytes(&vectors)).unwrap();
+
+        let temp_dir = TempDir::new("test_npy_input_build_ivf").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap();
+        let config = IndexWriterConfig::Ivf(IvfConfigWithBase {
+            base_config: BaseConfig {
+                output_path: base_directory.to_string(),
+                dimension,
+                max_memory_size: 1024 * 1024,
+                file_size: 1024 * 1024,
+                index_type: IndexType::Ivf,
+                index_distance_type: DistanceType::L2,
+                ..Default::default()
+            },
+            quantizer_config: QuantizerConfig {
+                quantizer_type: QuantizerType::NoQuantizer,
+                subvector_dimension: 1,
+                ..Default::default()
+            },
+            ivf_config: IvfConfig {
+                num_clusters: 2,
+                num_data_points: 100,
+                max_clusters_per_vector: 1,
+                posting_list_encoding_type: IntSeqEncodingType::PlainEncoding,
+                max_iteration: 10,
+                batch_size: 10,
+                max_posting_list_size: usize::MAX,
+                ..Default::default()
+            },
+        });
+        IndexWriter::new(config)
+            .unwrap()
+            .process(&mut input)
+            .unwrap();
+
+        // Probe all clusters
+        let index = IndexReader::new(&format!("{}/ivf", base_directory))
+            .unwrap()
+            .read()
+            .unwrap();
+        let results = index
+            .search(&vectors[42], 1, 2, &mut SearchContext::new(false))
+            .unwrap();
+        assert_eq!(results[0].id, 42);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::npy::NpyInput;
+use super::{Input, OwnedRow, Row};
+
+/// Array read by default from `.npz` archives, as written by `np.savez(path, embeddings=...)`.
+pub const DEFAULT_NPZ_ARRAY: &str = "embeddings";
+
+/// Reads vectors from an array of a NumPy `.npz` archive, written by `np.savez` or
+/// `np.savez_compressed`. The array is decompressed in memory, then read by `NpyInput`.
+pub struct NpzInput {
+    input: NpyInput,
+}
+
+impl NpzInput {
+    pub fn new(file_path: &str, array_name: &str) -> Result<Self> {
+        let file = File::open(file_path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::from_bytes(&mmap, array_name)
+    }
+
+    /// Reads a `.npz` archive that is already in memory.
+    pub fn from_bytes(bytes: &[u8], array_name: &str) -> Result<Self> {
+        let npy = extract_entry(bytes, &format!("{}.npy", array_name))?;
+        Ok(Self {
+            input: NpyInput::from_bytes(&npy)?,
+        })
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.input.dimension()
+    }
+}
+
+impl Input for NpzInput {
+    fn has_next(&self) -> bool {
+        self.input.has_next()
+    }
+
+    fn next(&mut self) -> Row<'_> {
+        self.input.next()
+    }
+
+    fn next_batch(&mut self, batch_size: usize) -> Vec<OwnedRow> {
+        self.input.next_batch(batch_size)
+    }
+
+    fn reset(&mut self) {
+        self.input.reset()
+    }
+
+    fn num_rows(&self) -> usize {
+        self.input.num_rows()
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        self.input.skip_to(row_idx)
+    }
+}
+
+/// Returns the decompressed content of the entry `name` of a zip archive.
+fn extract_entry(bytes: &[u8], name: &str) -> Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => {
+            return Err(anyhow!("Missing array {} in npz archive", name))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut content = vec![];
+    entry.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use super::*;
+    use crate::input::npy::npy_bytes;
+
+    /// Writes a zip archive of `entries`, deflated if `deflate` is set.
+    fn zip_bytes(entries: &[(&str, Vec<u8>)], deflate: bool) -> Vec<u8> {
+        let method = if deflate {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+        let options = SimpleFileOptions::default().compression_method(method);
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_npz_input() {
+        let ids = (0..5).map(|i| vec![i as f32]).collect::<Vec<_>>();
+        let vectors = (0..20)
+            .map(|i| vec![i as f32, 1.0, -(i as f32)])
+            .collect::<Vec<_>>();
+        for deflate in [false, true] {
+            let bytes = zip_bytes(
+                &[
+                    ("ids.npy", npy_bytes(&ids)),
+                    ("embeddings.npy", npy_bytes(&vectors)),
+                ],
+                deflate,
+            );
+
+            let mut input = NpzInput::from_bytes(&bytes, DEFAULT_NPZ_ARRAY).unwrap();
+            assert_eq!(input.num_rows(), 20);
+            assert_eq!(input.dimension(), 3);
+            input.skip_to(12);
+            let row = input.next();
+            assert_eq!(row.id, 12);
+            assert_eq!(row.data, &vectors[12][..]);
+
+            let input = NpzInput::from_bytes(&bytes, "ids").unwrap();
+            assert_eq!(input.num_rows(), 5);
+            assert!(NpzInput::from_bytes(&bytes, "missing").is_err());
+        }
+        assert!(NpzInput::from_bytes(&npy_bytes(&vectors), DEFAULT_NPZ_ARRAY).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use super::{Input, Row};
+
+/// Chains multiple inputs into a single stream of rows.
+///
+/// Rows keep the ids reported by the underlying inputs, so callers are responsible for making
+/// sure that ids don't collide across inputs.
+pub struct ShardedInput<I: Input> {
+    inputs: Vec<I>,
+
+    // Global row index at which each input starts
+    start_rows: Vec<usize>,
+    num_rows: usize,
+    current_input: usize,
+}
+
+impl<I: Input> ShardedInput<I> {
+    pub fn new(mut inputs: Vec<I>) -> Self {
+        let mut start_rows = Vec::with_capacity(inputs.len());
+        let mut num_rows = 0;
+        for input in inputs.iter_mut() {
+            input.reset();
+            start_rows.push(num_rows);
+            num_rows += input.num_rows();
+        }
+
+        Self {
+            inputs,
+            start_rows,
+            num_rows,
+            current_input: 0,
+        }
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    // Move to the next input that still has rows to read, if any.
+    // Inputs after the current one are always positioned at their first row.
+    fn skip_exhausted_inputs(&mut self) {
+        while self.current_input + 1 < self.inputs.len()
+            && !self.inputs[self.current_input].has_next()
+        {
+            self.current_input += 1;
+        }
+    }
+}
+
+impl<I: Input> Input for ShardedInput<I> {
+    fn has_next(&self) -> bool {
+        self.inputs
+            .iter()
+            .skip(self.current_input)
+            .any(|input| input.has_next())
+    }
+
+    // Caller is responsible for checking has_next() before calling this
+    fn next(&mut self) -> Row<'_> {
+        self.skip_exhausted_inputs();
+        self.inputs[self.current_input].next()
+    }
+
+    fn reset(&mut self) {
+        self.current_input = 0;
+        self.inputs.iter_mut().for_each(|input| input.reset());
+    }
+
+    fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    fn skip_to(&mut self, row_idx: usize) {
+        if self.inputs.is_empty() {
+            return;
+        }
+
+        // Find the last input starting at or before row_idx
+        let input_idx = self.start_rows.partition_point(|start| *start <= row_idx) - 1;
+        self.current_input = input_idx;
+        self.inputs[input_idx].skip_to(row_idx - self.start_rows[input_idx]);
+        self.inputs
+            .iter_mut()
+            .skip(input_idx + 1)
+            .for_each(|input| input.reset());
+    }
+
+    fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.inputs.iter_mut().find_map(|input| input.take_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::MockInput;
+
+    fn shard(first_id: u64, num_rows: usize) -> MockInput {
+        let ids: Vec<u64> = (first_id..first_id + num_rows as u64).collect();
+        let data = ids.iter().map(|id| vec![*id as f32]).collect();
+        MockInput::with_ids(ids, data)
+    }
+
+    fn collect_ids(input: &mut impl Input) -> Vec<u64> {
+        let mut ids = vec![];
+        while input.has_next() {
+            let row = input.next();
+            assert_eq!(row.data[0], row.id as f32);
+            ids.push(row.id);
+        }
+        ids
+    }
+
+    #[test]
+    fn test_sharded_input() {
+        let mut input = ShardedInput::new(vec![shard(0, 3), shard(100, 0), shard(200, 2)]);
+        assert_eq!(input.num_inputs(), 3);
+        assert_eq!(input.num_rows(), 5);
+        assert_eq!(collect_ids(&mut input), vec![0, 1, 2, 200, 201]);
+
+        input.reset();
+        assert_eq!(collect_ids(&mut input), vec![0, 1, 2, 200, 201]);
+
+        input.skip_to(3);
+        assert_eq!(collect_ids(&mut input), vec![200, 201]);
+
+        input.skip_to(1);
+        assert_eq!(collect_ids(&mut input), vec![1, 2, 200, 201]);
+    }
+
+    #[test]
+    fn test_sharded_input_empty() {
+        let mut input = ShardedInput::<MockInput>::new(vec![]);
+        assert_eq!(input.num_rows(), 0);
+        assert!(!input.has_next());
+        input.reset();
+        input.skip_to(0);
+        assert!(!input.has_next());
+    }
+}
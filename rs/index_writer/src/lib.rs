@@ -1,3 +1,6 @@
 pub mod config;
+pub mod detection;
 pub mod index_writer;
 pub mod input;
+pub mod progress;
+pub mod telemetry;
@@ -1,18 +1,26 @@
 use std::io::Read;
 use std::path::Path;
+use std::sync::Arc;
 
 use clap::Parser;
 use index_writer::config::{
-    HnswConfigWithBase, IndexWriterConfig, IvfConfigWithBase, SpannConfigWithBase,
+    FlatConfigWithBase, HnswConfigWithBase, IndexWriterConfig, InputFormat, IvfConfigWithBase,
+    SpannConfigWithBase,
 };
 use index_writer::index_writer::IndexWriter;
+use index_writer::input::arrow::{ArrowInput, DEFAULT_ID_COLUMN, DEFAULT_VECTOR_COLUMN};
 use index_writer::input::hdf5::Hdf5Reader;
+use index_writer::input::npy::NpyInput;
+use index_writer::input::npz::{NpzInput, DEFAULT_NPZ_ARRAY};
+use index_writer::input::Input;
+use index_writer::progress::LoggingProgressCallback;
 
 #[derive(clap::ValueEnum, Clone, Debug)]
 enum IndexTypeArgs {
     Hnsw,
     Ivf,
     Spann,
+    Flat,
 }
 
 #[derive(Parser, Debug)]
@@ -23,7 +31,9 @@ struct Args {
     #[arg(long, required = true)]
     input_path: String,
 
-    #[arg(long, required = true)]
+    /// Name of the dataset in the input file. Only used for HDF5 inputs, and for NPZ inputs to
+    /// read another array than `embeddings`.
+    #[arg(long, default_value = "")]
     dataset_name: String,
 
     #[arg(long, required = true)]
@@ -46,34 +56,67 @@ fn main() {
     file.read_to_string(&mut buf)
         .expect("Failed to read config file");
 
-    let config = match arg.index_type {
+    let (config, input_format) = match arg.index_type {
         IndexTypeArgs::Hnsw => {
             let mut config: HnswConfigWithBase =
                 serde_yaml::from_str(&buf).expect("Failed to parse config");
             config.base_config.output_path = arg.output_path;
-            IndexWriterConfig::Hnsw(config)
+            config.base_config.progress_callback = Some(Arc::new(LoggingProgressCallback));
+            let input_format = config.base_config.input_format.clone();
+            (IndexWriterConfig::Hnsw(config), input_format)
         }
         IndexTypeArgs::Ivf => {
             let mut config: IvfConfigWithBase =
                 serde_yaml::from_str(&buf).expect("Failed to parse config");
             config.base_config.output_path = arg.output_path;
-            IndexWriterConfig::Ivf(config)
+            config.base_config.progress_callback = Some(Arc::new(LoggingProgressCallback));
+            let input_format = config.base_config.input_format.clone();
+            (IndexWriterConfig::Ivf(config), input_format)
         }
         IndexTypeArgs::Spann => {
             let mut config: SpannConfigWithBase =
                 serde_yaml::from_str(&buf).expect("Failed to parse config");
             config.base_config.output_path = arg.output_path;
-            IndexWriterConfig::Spann(config)
+            config.base_config.progress_callback = Some(Arc::new(LoggingProgressCallback));
+            let input_format = config.base_config.input_format.clone();
+            (IndexWriterConfig::Spann(config), input_format)
+        }
+        IndexTypeArgs::Flat => {
+            let mut config: FlatConfigWithBase =
+                serde_yaml::from_str(&buf).expect("Failed to parse config");
+            config.base_config.output_path = arg.output_path;
+            config.base_config.progress_callback = Some(Arc::new(LoggingProgressCallback));
+            let input_format = config.base_config.input_format.clone();
+            (IndexWriterConfig::Flat(config), input_format)
         }
     };
 
     // Open input
-    let mut input = Hdf5Reader::new(1000, &arg.dataset_name, &arg.input_path)
-        .expect("Failed to create Hdf5Reader");
+    let mut input: Box<dyn Input> = match input_format {
+        InputFormat::Hdf5 => Box::new(
+            Hdf5Reader::new(1000, &arg.dataset_name, &arg.input_path)
+                .expect("Failed to create Hdf5Reader"),
+        ),
+        InputFormat::Arrow => Box::new(
+            ArrowInput::new(&arg.input_path, DEFAULT_ID_COLUMN, DEFAULT_VECTOR_COLUMN)
+                .expect("Failed to create ArrowInput"),
+        ),
+        InputFormat::Npy => {
+            Box::new(NpyInput::new(&arg.input_path).expect("Failed to create NpyInput"))
+        }
+        InputFormat::Npz => {
+            let array_name = if arg.dataset_name.is_empty() {
+                DEFAULT_NPZ_ARRAY
+            } else {
+                &arg.dataset_name
+            };
+            Box::new(NpzInput::new(&arg.input_path, array_name).expect("Failed to create NpzInput"))
+        }
+    };
     let mut index_writer = IndexWriter::new(config).expect("Failed to create index writer");
 
     // Process
     index_writer
-        .process(&mut input)
+        .process(&mut input.as_mut())
         .expect("Index writer processing should succeed");
 }
@@ -0,0 +1,134 @@
+use std::fmt;
+use std::sync::Arc;
+
+use log::info;
+
+/// Phases of an index build, in the order they are reported. IVF indexes train their centroids
+/// once all rows are inserted, so `Training` is reported again after `Inserting` for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildPhase {
+    // Training the quantizer, or the centroids of an IVF index
+    Training,
+    // Adding the input rows to the builder
+    Inserting,
+    // Writing the index files
+    Writing,
+}
+
+/// Receives the progress of an index build, e.g. to display it in an interactive tool.
+pub trait BuildProgressCallback {
+    /// Called every `progress_interval` items of a phase, and when the phase is done.
+    fn on_progress(&self, phase: BuildPhase, processed: usize, total: usize);
+}
+
+// Needed by `BaseConfig`, which derives Debug
+impl fmt::Debug for dyn BuildProgressCallback + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BuildProgressCallback")
+    }
+}
+
+/// Logs the progress of the build with `info!`.
+pub struct LoggingProgressCallback;
+
+impl BuildProgressCallback for LoggingProgressCallback {
+    fn on_progress(&self, phase: BuildPhase, processed: usize, total: usize) {
+        info!("{:?}: {}/{}", phase, processed, total);
+    }
+}
+
+/// Progress of a single phase, which calls the callback (if any) once at least `interval` items
+/// were processed since the last call.
+pub(crate) struct PhaseProgress {
+    callback: Option<Arc<dyn BuildProgressCallback + Send + Sync>>,
+    phase: BuildPhase,
+    total: usize,
+    interval: usize,
+    next_report: usize,
+    last_reported: Option<usize>,
+}
+
+impl PhaseProgress {
+    pub(crate) fn new(
+        callback: Option<Arc<dyn BuildProgressCallback + Send + Sync>>,
+        phase: BuildPhase,
+        total: usize,
+        interval: usize,
+    ) -> Self {
+        let interval = interval.max(1);
+        Self {
+            callback,
+            phase,
+            total,
+            interval,
+            next_report: interval,
+            last_reported: None,
+        }
+    }
+
+    /// Reports that the phase started, for phases that don't report their progress.
+    pub(crate) fn start(&mut self) {
+        self.report(0);
+    }
+
+    pub(crate) fn advance_to(&mut self, processed: usize) {
+        if processed >= self.next_report || processed == self.total {
+            self.report(processed);
+        }
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.report(self.total);
+    }
+
+    fn report(&mut self, processed: usize) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        if self.last_reported == Some(processed) {
+            return;
+        }
+        callback.on_progress(self.phase, processed, self.total);
+        self.last_reported = Some(processed);
+        self.next_report = (processed / self.interval + 1) * self.interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingCallback {
+        events: Mutex<Vec<(BuildPhase, usize, usize)>>,
+    }
+
+    impl BuildProgressCallback for RecordingCallback {
+        fn on_progress(&self, phase: BuildPhase, processed: usize, total: usize) {
+            self.events.lock().unwrap().push((phase, processed, total));
+        }
+    }
+
+    #[test]
+    fn test_phase_progress() {
+        let callback = Arc::new(RecordingCallback::default());
+        let mut progress =
+            PhaseProgress::new(Some(callback.clone()), BuildPhase::Inserting, 25, 10);
+        progress.start();
+        for processed in [3, 10, 12, 21, 25] {
+            progress.advance_to(processed);
+        }
+        progress.finish();
+        assert_eq!(
+            *callback.events.lock().unwrap(),
+            vec![
+                (BuildPhase::Inserting, 0, 25),
+                (BuildPhase::Inserting, 10, 25),
+                (BuildPhase::Inserting, 21, 25),
+                (BuildPhase::Inserting, 25, 25),
+            ]
+        );
+    }
+}
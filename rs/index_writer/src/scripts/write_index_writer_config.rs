@@ -17,10 +17,15 @@ enum IndexTypeArgs {
     Spann,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
 enum QuantizerTypeArgs {
     ProductQuantizer,
     NoQuantizer,
+    ResidualQuantizer,
+    Sq4,
+    ScalarQuantizer,
+    BinaryQuantizer,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
@@ -79,8 +84,14 @@ fn generate_config(args: &Args) -> IndexWriterConfig {
     base_config.index_type = IndexType::Spann;
 
     let mut quantizer_config = QuantizerConfig::default();
-    if args.quantizer_type == QuantizerTypeArgs::ProductQuantizer {
-        quantizer_config.quantizer_type = QuantizerType::ProductQuantizer;
+    if args.quantizer_type != QuantizerTypeArgs::NoQuantizer {
+        quantizer_config.quantizer_type = match args.quantizer_type {
+            QuantizerTypeArgs::ResidualQuantizer => QuantizerType::ResidualQuantizer,
+            QuantizerTypeArgs::Sq4 => QuantizerType::Sq4,
+            QuantizerTypeArgs::ScalarQuantizer => QuantizerType::ScalarQuantizer,
+            QuantizerTypeArgs::BinaryQuantizer => QuantizerType::BinaryQuantizer,
+            _ => QuantizerType::ProductQuantizer,
+        };
         quantizer_config.subvector_dimension = 8;
         quantizer_config.num_bits = 8;
         quantizer_config.num_training_rows = 10000;
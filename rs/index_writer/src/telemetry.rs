@@ -0,0 +1,71 @@
+#[cfg(feature = "tracing")]
+use opentelemetry::trace::{TraceContextExt, Tracer};
+#[cfg(feature = "tracing")]
+use opentelemetry::{global, Context, ContextGuard, KeyValue};
+
+#[cfg(feature = "tracing")]
+const TRACER_NAME: &str = "index_writer";
+
+/// OpenTelemetry span covering an index build or one of its phases, ended when dropped.
+/// Without the `tracing` feature, this is a no-op.
+pub struct BuildSpan {
+    #[cfg(feature = "tracing")]
+    context: Context,
+    // Only set for the root span, which is the parent of the phase spans while it is alive
+    #[cfg(feature = "tracing")]
+    _guard: Option<ContextGuard>,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl BuildSpan {
+    /// Starts the `index.build` span. Phase spans started while it is alive are its children.
+    pub fn root(index_type: &str, num_vectors: u64, dimension: usize, output_path: &str) -> Self {
+        #[cfg(feature = "tracing")]
+        {
+            let span = global::tracer(TRACER_NAME).start("index.build");
+            let context = Context::current_with_span(span);
+            context.span().set_attributes([
+                KeyValue::new("index_type", index_type.to_string()),
+                KeyValue::new("num_vectors", num_vectors as i64),
+                KeyValue::new("dimension", dimension as i64),
+                KeyValue::new("output_path", output_path.to_string()),
+            ]);
+            let guard = context.clone().attach();
+            Self {
+                context,
+                _guard: Some(guard),
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        Self {}
+    }
+
+    /// Starts the span of a build phase, e.g. `train_quantizer` or `write_index`.
+    pub fn phase(name: &'static str) -> Self {
+        #[cfg(feature = "tracing")]
+        {
+            let span = global::tracer(TRACER_NAME).start(name);
+            Self {
+                context: Context::current_with_span(span),
+                _guard: None,
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        Self {}
+    }
+
+    /// Records how many vectors the phase went through.
+    pub fn set_num_vectors(&self, num_vectors: usize) {
+        #[cfg(feature = "tracing")]
+        self.context
+            .span()
+            .set_attribute(KeyValue::new("num_vectors", num_vectors as i64));
+    }
+}
+
+impl Drop for BuildSpan {
+    fn drop(&mut self) {
+        #[cfg(feature = "tracing")]
+        self.context.span().end();
+    }
+}
@@ -0,0 +1,184 @@
+//! Python bindings to build a collection from in-memory vectors, and to search and describe it.
+
+use std::sync::Arc;
+
+use config::collection::CollectionConfig;
+use index::collection::reader::CollectionReader;
+use index::collection::Collection;
+use index::index::Searchable;
+use index::utils::SearchContext;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+// Number of probed centroids used for every search.
+const EF_CONSTRUCTION: u32 = 100;
+
+// Keys of the `build_index` config that are not collection config fields.
+const COLLECTION_PATH_KEY: &str = "collection_path";
+const VECTORS_KEY: &str = "vectors";
+const DOC_IDS_KEY: &str = "doc_ids";
+
+fn runtime_error(e: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+fn read_collection(collection_path: &str) -> PyResult<Arc<Collection>> {
+    CollectionReader::new(collection_path.to_string())
+        .read()
+        .map_err(runtime_error)
+}
+
+/// Builds the collection config from the remaining keys of the `build_index` config, on top of
+/// the default config.
+fn collection_config(
+    config: &Bound<'_, PyDict>,
+    num_features: usize,
+) -> PyResult<CollectionConfig> {
+    let overrides = config.copy()?;
+    for key in [COLLECTION_PATH_KEY, VECTORS_KEY, DOC_IDS_KEY] {
+        if overrides.contains(key)? {
+            overrides.del_item(key)?;
+        }
+    }
+    if !overrides.contains("num_features")? {
+        overrides.set_item("num_features", num_features)?;
+    }
+
+    // Go through JSON so that the keys and values follow the serde representation of the config.
+    let json = config
+        .py()
+        .import("json")?
+        .call_method1("dumps", (overrides,))?
+        .extract::<String>()?;
+    let overrides: serde_json::Value = serde_json::from_str(&json)
+        .map_err(|e| PyValueError::new_err(format!("Invalid config: {}", e)))?;
+
+    let mut collection_config = serde_json::to_value(CollectionConfig::default())
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    if let (Some(fields), Some(overrides)) =
+        (collection_config.as_object_mut(), overrides.as_object())
+    {
+        for (key, value) in overrides.iter() {
+            if !fields.contains_key(key) {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown config key: {}",
+                    key
+                )));
+            }
+            fields.insert(key.clone(), value.clone());
+        }
+    }
+    serde_json::from_value(collection_config)
+        .map_err(|e| PyValueError::new_err(format!("Invalid config: {}", e)))
+}
+
+/// A collection opened once, and searched and described without being read again.
+#[pyclass]
+pub struct MuopDB {
+    collection_path: String,
+    collection: Arc<Collection>,
+}
+
+#[pymethods]
+impl MuopDB {
+    /// Opens the collection at `collection_path`.
+    #[new]
+    pub fn new(py: Python<'_>, collection_path: &str) -> PyResult<Self> {
+        let collection = py.allow_threads(|| read_collection(collection_path))?;
+        Ok(Self {
+            collection_path: collection_path.to_string(),
+            collection,
+        })
+    }
+
+    /// Builds a collection from in-memory vectors.
+    /// `config` must contain `collection_path` and `vectors` (a list of lists of floats), and may
+    /// contain `doc_ids` (defaults to the position of each vector) as well as any field of the
+    /// collection config.
+    #[staticmethod]
+    pub fn build_index(py: Python<'_>, config: &Bound<'_, PyDict>) -> PyResult<()> {
+        let collection_path: String = config
+            .get_item(COLLECTION_PATH_KEY)?
+            .ok_or_else(|| PyValueError::new_err("Missing config key: collection_path"))?
+            .extract()?;
+        let vectors: Vec<Vec<f32>> = config
+            .get_item(VECTORS_KEY)?
+            .ok_or_else(|| PyValueError::new_err("Missing config key: vectors"))?
+            .extract()?;
+        let doc_ids: Vec<u128> = match config.get_item(DOC_IDS_KEY)? {
+            Some(doc_ids) => doc_ids.extract()?,
+            None => (0..vectors.len() as u128).collect(),
+        };
+        if doc_ids.len() != vectors.len() {
+            return Err(PyValueError::new_err(
+                "doc_ids and vectors must have the same length",
+            ));
+        }
+
+        let num_features = vectors.first().map_or(0, |v| v.len());
+        let collection_config = collection_config(config, num_features)?;
+        if vectors
+            .iter()
+            .any(|v| v.len() != collection_config.num_features)
+        {
+            return Err(PyValueError::new_err(format!(
+                "All vectors must have {} features",
+                collection_config.num_features
+            )));
+        }
+
+        py.allow_threads(|| -> anyhow::Result<()> {
+            Collection::init_new_collection(collection_path.clone(), &collection_config)?;
+            let collection = Collection::new(collection_path, collection_config)?;
+            for (doc_id, vector) in doc_ids.iter().zip(vectors.iter()) {
+                collection.insert(*doc_id, vector)?;
+            }
+            collection.flush()
+        })
+        .map_err(runtime_error)
+    }
+
+    /// Returns the `k` nearest neighbors of `query` as a list of (doc id, score), sorted by
+    /// increasing score.
+    pub fn search(&self, py: Python<'_>, query: Vec<f32>, k: usize) -> PyResult<Vec<(u128, f32)>> {
+        if query.len() != self.collection.dimensions() {
+            return Err(PyValueError::new_err(format!(
+                "Query must have {} features",
+                self.collection.dimensions()
+            )));
+        }
+        py.allow_threads(|| {
+            let snapshot = self.collection.get_snapshot().map_err(runtime_error)?;
+            let mut context = SearchContext::new(false);
+            let results = snapshot
+                .search(&query, k, EF_CONSTRUCTION, &mut context)
+                .ok_or_else(|| PyRuntimeError::new_err("Search failed"))?;
+            Ok(results
+                .iter()
+                .map(|result| (result.id, result.score))
+                .collect())
+        })
+    }
+
+    /// Returns the version, dimension and per-segment description of the collection.
+    pub fn describe<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let segments = PyDict::new(py);
+        for (name, description) in self.collection.describe_segments() {
+            segments.set_item(name, description)?;
+        }
+
+        let description = PyDict::new(py);
+        description.set_item("collection_path", &self.collection_path)?;
+        description.set_item("version", self.collection.current_version())?;
+        description.set_item("num_features", self.collection.dimensions())?;
+        description.set_item("segments", segments)?;
+        Ok(description)
+    }
+}
+
+#[pymodule]
+fn py_muopdb(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<MuopDB>()?;
+    Ok(())
+}
@@ -0,0 +1,46 @@
+import random
+
+from py_muopdb import MuopDB
+
+NUM_VECTORS = 100
+NUM_FEATURES = 8
+
+
+def build_collection(collection_path):
+    rng = random.Random(0)
+    vectors = [[rng.random() for _ in range(NUM_FEATURES)] for _ in range(NUM_VECTORS)]
+    MuopDB.build_index(
+        {
+            "collection_path": collection_path,
+            "vectors": vectors,
+            "initial_num_centroids": 4,
+            "centroids_builder_vector_storage_memory_size": 1024,
+            "centroids_builder_vector_storage_file_size": 1024,
+            "posting_list_builder_vector_storage_memory_size": 1024,
+            "posting_list_builder_vector_storage_file_size": 1024,
+        }
+    )
+    return vectors
+
+
+def test_nearest_neighbor_is_itself(tmp_path):
+    collection_path = str(tmp_path / "collection")
+    vectors = build_collection(collection_path)
+
+    db = MuopDB(collection_path)
+    for doc_id, vector in enumerate(vectors):
+        results = db.search(vector, 1)
+        assert len(results) == 1
+        assert results[0][0] == doc_id
+        assert results[0][1] == 0.0
+
+
+def test_describe(tmp_path):
+    collection_path = str(tmp_path / "collection")
+    build_collection(collection_path)
+
+    description = MuopDB(collection_path).describe()
+    assert description["num_features"] == NUM_FEATURES
+    assert description["version"] == 1
+    assert len(description["segments"]) == 1
+    assert "vectors: 100" in next(iter(description["segments"].values()))
@@ -0,0 +1,59 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use quantization::bq::bq_builder::BinaryQuantizerBuilder;
+use quantization::noq::noq::NoQuantizer;
+use quantization::quantization::Quantizer;
+use utils::distance::l2::{L2DistanceCalculator, L2DistanceCalculatorImpl};
+use utils::test_utils::generate_random_vector;
+
+const DIMENSION: usize = 768;
+const NUM_POINTS: usize = 1000;
+
+fn bench_bq_distance(c: &mut Criterion) {
+    let points: Vec<Vec<f32>> = (0..NUM_POINTS)
+        .map(|_| generate_random_vector(DIMENSION))
+        .collect();
+    let mut bq_builder = BinaryQuantizerBuilder::new(DIMENSION);
+    for point in points.iter() {
+        bq_builder.add(point.clone());
+    }
+    let bq = bq_builder
+        .build(String::new())
+        .expect("Failed to build BinaryQuantizer");
+    let noq = NoQuantizer::<L2DistanceCalculator>::new(DIMENSION);
+
+    let bq_bytes = bq.quantized_dimension() * std::mem::size_of::<u64>();
+    let noq_bytes = noq.quantized_dimension() * std::mem::size_of::<f32>();
+    println!(
+        "dimension {}: {} bytes per vector with BinaryQuantizer, {} with NoQuantizer ({:.1}x smaller)",
+        DIMENSION,
+        bq_bytes,
+        noq_bytes,
+        noq_bytes as f64 / bq_bytes as f64
+    );
+
+    let mut group = c.benchmark_group("BQ Distance");
+    let bq_point = bq.quantize(&points[0]);
+    let bq_query = bq.quantize(&points[1]);
+    group.bench_function(format!("bq_distance_{}", DIMENSION), |bencher| {
+        bencher.iter(|| {
+            bq.distance(
+                black_box(&bq_query),
+                black_box(&bq_point),
+                L2DistanceCalculatorImpl::StreamingSIMD,
+            )
+        })
+    });
+    group.bench_function(format!("noq_distance_{}", DIMENSION), |bencher| {
+        bencher.iter(|| {
+            noq.distance(
+                black_box(&points[1]),
+                black_box(&points[0]),
+                L2DistanceCalculatorImpl::StreamingSIMD,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_bq_distance);
+criterion_main!(benches);
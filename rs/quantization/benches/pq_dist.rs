@@ -23,6 +23,7 @@ fn bench_pq_distance(c: &mut Criterion) {
                     ProductQuantizerBuilderConfig {
                         max_iteration: 1000,
                         batch_size: 4,
+                        use_annealing: false,
                     },
                 );
                 let sample_size = 1 << *num_bits;
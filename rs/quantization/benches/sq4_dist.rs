@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use quantization::pq::pq::ProductQuantizerConfig;
+use quantization::pq::pq_builder::{ProductQuantizerBuilder, ProductQuantizerBuilderConfig};
+use quantization::quantization::Quantizer;
+use quantization::sq4::sq4_builder::Sq4QuantizerBuilder;
+use strum::IntoEnumIterator;
+use utils::distance::l2::{L2DistanceCalculator, L2DistanceCalculatorImpl};
+use utils::test_utils::generate_random_vector;
+use utils::CalculateSquared;
+
+const NUM_POINTS: usize = 2000;
+const NUM_QUERIES: usize = 50;
+const K: usize = 10;
+
+fn top_k(distances: Vec<f32>) -> HashSet<usize> {
+    let mut ids: Vec<usize> = (0..distances.len()).collect();
+    ids.sort_by(|a, b| distances[*a].total_cmp(&distances[*b]));
+    ids.into_iter().take(K).collect()
+}
+
+/// Recall@K of a brute-force search over quantized points, against the exact search.
+fn recall<Q: Quantizer<QuantizedT = u8>>(q: &Q, points: &[Vec<f32>], queries: &[Vec<f32>]) -> f64 {
+    let codes: Vec<Vec<u8>> = points.iter().map(|p| q.quantize(p)).collect();
+    let mut found = 0;
+    for query in queries {
+        let exact = top_k(
+            points
+                .iter()
+                .map(|p| L2DistanceCalculator::calculate_squared(query, p))
+                .collect(),
+        );
+        let quantized_query = q.quantize(query);
+        let approximate = top_k(
+            codes
+                .iter()
+                .map(|c| q.distance(&quantized_query, c, L2DistanceCalculatorImpl::StreamingSIMD))
+                .collect(),
+        );
+        found += exact.intersection(&approximate).count();
+    }
+    found as f64 / (queries.len() * K) as f64
+}
+
+fn bench_sq4_distance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("SQ4 Distance");
+    for dimension in [128, 256].iter() {
+        let points: Vec<Vec<f32>> = (0..NUM_POINTS)
+            .map(|_| generate_random_vector(*dimension))
+            .collect();
+        let queries: Vec<Vec<f32>> = (0..NUM_QUERIES)
+            .map(|_| generate_random_vector(*dimension))
+            .collect();
+
+        let mut sq4_builder = Sq4QuantizerBuilder::<L2DistanceCalculator>::new(*dimension);
+        // PQ with the same code size as SQ4: one byte per 2 dimensions.
+        let mut pq_builder = ProductQuantizerBuilder::<L2DistanceCalculator>::new(
+            ProductQuantizerConfig {
+                dimension: *dimension,
+                subvector_dimension: 2,
+                num_bits: 8,
+            },
+            ProductQuantizerBuilderConfig {
+                max_iteration: 100,
+                batch_size: 64,
+                use_annealing: false,
+            },
+        );
+        for point in points.iter() {
+            sq4_builder.add(point.clone());
+            pq_builder.add(point.clone());
+        }
+        let tmpdir =
+            tempdir::TempDir::new("sq4_bench").expect("Failed to create temporary directory");
+        let path_str = tmpdir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+        let sq4 = sq4_builder
+            .build(path_str.clone())
+            .expect("Failed to build Sq4Quantizer");
+        let pq = pq_builder
+            .build(path_str)
+            .expect("Failed to build ProductQuantizer");
+
+        println!(
+            "dimension {}: {} bytes per vector with SQ4, {} with SQ8 ({:.0}% reduction)",
+            dimension,
+            sq4.quantized_dimension(),
+            dimension,
+            100.0 * (1.0 - sq4.quantized_dimension() as f64 / *dimension as f64)
+        );
+        println!(
+            "dimension {}: recall@{} is {:.4} with SQ4, {:.4} with PQ",
+            dimension,
+            K,
+            recall(&sq4, &points, &queries),
+            recall(&pq, &points, &queries)
+        );
+
+        let point = sq4.quantize(&points[0]);
+        let query = sq4.quantize(&queries[0]);
+        for implementation in L2DistanceCalculatorImpl::iter() {
+            group.bench_with_input(
+                BenchmarkId::new(
+                    format!("sq4_distance_{}", dimension),
+                    format!("{:?}", implementation),
+                ),
+                &implementation,
+                |bencher, _| {
+                    bencher.iter(|| {
+                        sq4.distance(black_box(&query), black_box(&point), implementation.clone())
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_sq4_distance);
+criterion_main!(benches);
@@ -0,0 +1,188 @@
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use utils::distance::hamming::{num_words, pack_bits, HammingDistanceCalculator, BITS_PER_WORD};
+use utils::distance::l2::L2DistanceCalculatorImpl;
+use utils::BinaryDistanceCalculator;
+
+use crate::quantization::{Quantizer, WritableQuantizer};
+
+pub const BINARY_QUANTIZER_CONFIG_NAME: &str = "binary_quantizer_config.yaml";
+
+/// Quantizer keeping one bit per dimension: whether the value is above the mean of that
+/// dimension seen during training. 64 dimensions are packed in each word, and points are
+/// compared by Hamming distance.
+pub struct BinaryQuantizer {
+    pub dimension: usize,
+    // Per-dimension mean of the training vectors, the threshold of each bit.
+    pub thresholds: Vec<f32>,
+    // Per-dimension standard deviation of the training vectors. A bit is reconstructed as its
+    // threshold plus or minus this value.
+    pub scales: Vec<f32>,
+    pub base_directory: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BinaryQuantizerConfig {
+    pub dimension: usize,
+    pub thresholds: Vec<f32>,
+    pub scales: Vec<f32>,
+}
+
+pub struct BinaryQuantizerReader {
+    base_directory: String,
+}
+
+impl BinaryQuantizerReader {
+    pub fn new(base_directory: String) -> Self {
+        Self { base_directory }
+    }
+
+    pub fn read(&self) -> Result<BinaryQuantizer> {
+        let config_path = Path::new(&self.base_directory).join(BINARY_QUANTIZER_CONFIG_NAME);
+        if !config_path.is_file() {
+            return Err(Error::msg("Config file does not exist"));
+        }
+        let config: BinaryQuantizerConfig = serde_yaml::from_slice(&std::fs::read(config_path)?)?;
+        BinaryQuantizer::new(
+            config.dimension,
+            config.thresholds,
+            config.scales,
+            self.base_directory.clone(),
+        )
+    }
+}
+
+impl BinaryQuantizer {
+    pub fn new(
+        dimension: usize,
+        thresholds: Vec<f32>,
+        scales: Vec<f32>,
+        base_directory: String,
+    ) -> Result<Self> {
+        if thresholds.len() != dimension || scales.len() != dimension {
+            return Err(Error::msg(
+                "Thresholds and scales need to have one value per dimension.",
+            ));
+        }
+        Ok(Self {
+            dimension,
+            thresholds,
+            scales,
+            base_directory,
+        })
+    }
+
+    pub fn config(&self) -> BinaryQuantizerConfig {
+        BinaryQuantizerConfig {
+            dimension: self.dimension,
+            thresholds: self.thresholds.clone(),
+            scales: self.scales.clone(),
+        }
+    }
+}
+
+impl Quantizer for BinaryQuantizer {
+    type QuantizedT = u64;
+
+    fn quantize(&self, value: &[f32]) -> Vec<u64> {
+        let bits: Vec<bool> = value
+            .iter()
+            .zip(self.thresholds.iter())
+            .map(|(v, threshold)| v > threshold)
+            .collect();
+        pack_bits(&bits)
+    }
+
+    fn quantized_dimension(&self) -> usize {
+        num_words(self.dimension)
+    }
+
+    fn original_vector(&self, quantized_vector: &[u64]) -> Vec<f32> {
+        (0..self.dimension)
+            .map(|dim| {
+                let bit = (quantized_vector[dim / BITS_PER_WORD] >> (dim % BITS_PER_WORD)) & 1;
+                if bit == 1 {
+                    self.thresholds[dim] + self.scales[dim]
+                } else {
+                    self.thresholds[dim] - self.scales[dim]
+                }
+            })
+            .collect()
+    }
+
+    /// Number of differing bits.
+    fn distance(&self, a: &[u64], b: &[u64], _implem: L2DistanceCalculatorImpl) -> f32 {
+        HammingDistanceCalculator::calculate(a, b) as f32
+    }
+
+    fn read(dir: String) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        BinaryQuantizerReader::new(dir).read()
+    }
+}
+
+impl WritableQuantizer for BinaryQuantizer {
+    fn write_to_directory(&self, base_directory: &str) -> Result<()> {
+        std::fs::write(
+            Path::new(base_directory).join(BINARY_QUANTIZER_CONFIG_NAME),
+            serde_yaml::to_string(&self.config())?,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_quantizer() {
+        let bq = BinaryQuantizer::new(3, vec![0.0, 1.0, -1.0], vec![1.0, 0.5, 2.0], "".to_string())
+            .unwrap();
+        assert_eq!(bq.quantized_dimension(), 1);
+
+        let code = bq.quantize(&[0.5, 0.5, 0.0]);
+        assert_eq!(code, vec![0b101]);
+        assert_eq!(bq.original_vector(&code), vec![1.0, 0.5, 1.0]);
+
+        let other = bq.quantize(&[-0.5, 2.0, 0.0]);
+        assert_eq!(
+            bq.distance(&code, &other, L2DistanceCalculatorImpl::StreamingSIMD),
+            2.0
+        );
+
+        assert!(BinaryQuantizer::new(2, vec![0.0], vec![1.0, 1.0], "".to_string()).is_err());
+
+        // 65 dimensions need 2 words
+        let bq = BinaryQuantizer::new(65, vec![0.0; 65], vec![1.0; 65], "".to_string()).unwrap();
+        assert_eq!(bq.quantized_dimension(), 2);
+        assert_eq!(bq.quantize(&[1.0; 65]), vec![u64::MAX, 1]);
+    }
+
+    #[test]
+    fn test_binary_quantizer_read_write() {
+        let temp_dir = tempdir::TempDir::new("binary_quantizer_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+
+        let bq = BinaryQuantizer::new(
+            4,
+            vec![0.0, -1.5, 2.0, 0.1],
+            vec![1.0, 1.5, 3.0, 0.7],
+            base_directory.clone(),
+        )
+        .unwrap();
+        bq.write_to_directory(&base_directory)
+            .expect("Failed to write the quantizer");
+
+        let new_bq = BinaryQuantizer::read(base_directory).expect("Failed to read the quantizer");
+        assert_eq!(new_bq.thresholds, bq.thresholds);
+        assert_eq!(new_bq.scales, bq.scales);
+        let value = vec![0.5, 0.0, 2.5, 0.3];
+        assert_eq!(new_bq.quantize(&value), bq.quantize(&value));
+    }
+}
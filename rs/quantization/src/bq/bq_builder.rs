@@ -0,0 +1,162 @@
+use anyhow::{Error, Result};
+
+use crate::bq::bq::BinaryQuantizer;
+
+pub struct BinaryQuantizerBuilder {
+    dimension: usize,
+
+    // Per-dimension sums of the values and squared values added so far.
+    sums: Vec<f64>,
+    sums_of_squares: Vec<f64>,
+    num_vectors: usize,
+}
+
+impl BinaryQuantizerBuilder {
+    /// Create a new BinaryQuantizerBuilder
+    pub fn new(dimension: usize) -> Self {
+        Self {
+            dimension,
+            sums: vec![0.0; dimension],
+            sums_of_squares: vec![0.0; dimension],
+            num_vectors: 0,
+        }
+    }
+
+    /// Add a new vector to the training set. Only the per-dimension moments are kept.
+    pub fn add(&mut self, data: Vec<f32>) {
+        for (dim, value) in data.iter().take(self.dimension).enumerate() {
+            self.sums[dim] += *value as f64;
+            self.sums_of_squares[dim] += (*value as f64) * (*value as f64);
+        }
+        self.num_vectors += 1;
+    }
+
+    pub fn build(&mut self, base_directory: String) -> Result<BinaryQuantizer> {
+        if self.num_vectors == 0 {
+            return Err(Error::msg("No training vectors for binary quantizer"));
+        }
+        let n = self.num_vectors as f64;
+        let thresholds: Vec<f32> = self.sums.iter().map(|sum| (sum / n) as f32).collect();
+        let scales = self
+            .sums
+            .iter()
+            .zip(self.sums_of_squares.iter())
+            .map(|(sum, sum_of_squares)| {
+                let mean = sum / n;
+                (sum_of_squares / n - mean * mean).max(0.0).sqrt() as f32
+            })
+            .collect();
+        BinaryQuantizer::new(self.dimension, thresholds, scales, base_directory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::Rng;
+    use utils::distance::l2::{L2DistanceCalculator, L2DistanceCalculatorImpl};
+    use utils::CalculateSquared;
+
+    use super::*;
+    use crate::quantization::Quantizer;
+
+    #[test]
+    fn test_binary_quantizer_builder() {
+        let mut builder = BinaryQuantizerBuilder::new(2);
+        assert!(builder.build("".to_string()).is_err());
+
+        builder.add(vec![1.0, 10.0]);
+        builder.add(vec![3.0, 10.0]);
+        let bq = builder.build("".to_string()).unwrap();
+        assert_eq!(bq.thresholds, vec![2.0, 10.0]);
+        assert_eq!(bq.scales, vec![1.0, 0.0]);
+        assert_eq!(
+            bq.original_vector(&bq.quantize(&[3.0, 10.0])),
+            vec![3.0, 10.0]
+        );
+    }
+
+    fn top_k(distances: Vec<f32>, k: usize) -> Vec<usize> {
+        let mut ids: Vec<usize> = (0..distances.len()).collect();
+        ids.sort_by(|a, b| distances[*a].total_cmp(&distances[*b]));
+        ids.truncate(k);
+        ids
+    }
+
+    #[test]
+    fn test_binary_quantizer_recall() {
+        const DIMENSION: usize = 128;
+        const NUM_VECTORS: usize = 10000;
+        const NUM_QUERIES: usize = 20;
+        const K: usize = 10;
+        const NUM_CANDIDATES: usize = 100;
+
+        // Vectors of a low-dimensional subspace, like real embeddings, plus a little noise
+        let mut rng = rand::thread_rng();
+        let num_latent_dims = 8;
+        let projection: Vec<Vec<f32>> = (0..num_latent_dims)
+            .map(|_| (0..DIMENSION).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect();
+        let mut generate = || -> Vec<f32> {
+            let latent: Vec<f32> = (0..num_latent_dims)
+                .map(|_| rng.gen_range(-1.0..1.0))
+                .collect();
+            (0..DIMENSION)
+                .map(|dim| {
+                    latent
+                        .iter()
+                        .zip(projection.iter())
+                        .map(|(l, p)| l * p[dim])
+                        .sum::<f32>()
+                        + rng.gen_range(-0.01..0.01)
+                })
+                .collect()
+        };
+        let points: Vec<Vec<f32>> = (0..NUM_VECTORS).map(|_| generate()).collect();
+        let queries: Vec<Vec<f32>> = (0..NUM_QUERIES).map(|_| generate()).collect();
+
+        let mut builder = BinaryQuantizerBuilder::new(DIMENSION);
+        for point in points.iter() {
+            builder.add(point.clone());
+        }
+        let bq = builder.build("".to_string()).unwrap();
+        let codes: Vec<Vec<u64>> = points.iter().map(|p| bq.quantize(p)).collect();
+
+        // Binary codes select candidates, which are then reranked with the exact distance
+        let mut found = 0;
+        for query in queries.iter() {
+            let exact: HashSet<usize> = top_k(
+                points
+                    .iter()
+                    .map(|p| L2DistanceCalculator::calculate_squared(query, p))
+                    .collect(),
+                K,
+            )
+            .into_iter()
+            .collect();
+
+            let quantized_query = bq.quantize(query);
+            let candidates = top_k(
+                codes
+                    .iter()
+                    .map(|c| bq.distance(&quantized_query, c, L2DistanceCalculatorImpl::Scalar))
+                    .collect(),
+                NUM_CANDIDATES,
+            );
+            let reranked = top_k(
+                candidates
+                    .iter()
+                    .map(|&id| L2DistanceCalculator::calculate_squared(query, &points[id]))
+                    .collect(),
+                K,
+            );
+            found += reranked
+                .iter()
+                .filter(|&&idx| exact.contains(&candidates[idx]))
+                .count();
+        }
+        let recall = found as f64 / (NUM_QUERIES * K) as f64;
+        assert!(recall > 0.8, "recall@{} is {}", K, recall);
+    }
+}
@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception)]
+pub mod bq;
+pub mod bq_builder;
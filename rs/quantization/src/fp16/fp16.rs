@@ -0,0 +1,160 @@
+use std::marker::PhantomData;
+use std::path::Path;
+use std::simd::f32x16;
+use std::simd::num::SimdFloat;
+
+use anyhow::{Error, Result};
+use half::f16;
+use serde::{Deserialize, Serialize};
+use utils::distance::l2::L2DistanceCalculatorImpl;
+use utils::DistanceCalculator;
+
+use crate::quantization::{Quantizer, WritableQuantizer};
+
+pub const FLOAT16_QUANTIZER_CONFIG_NAME: &str = "float16_quantizer_config.yaml";
+
+/// Stores every dimension as a half precision float, halving the size of unquantized vectors.
+/// `f16` doesn't implement `ToBytes`, so quantized vectors hold the bits of each `f16` as `u16`.
+pub struct Float16Quantizer<D: DistanceCalculator> {
+    dimension: usize,
+
+    _marker: PhantomData<D>,
+}
+
+impl<D: DistanceCalculator> Float16Quantizer<D> {
+    pub fn new(dimension: usize) -> Self {
+        Self {
+            dimension,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Converts `f16` bits, as stored by `Float16Quantizer`, back to `f32`.
+pub fn decode_f16(bits: &[u16]) -> Vec<f32> {
+    bits.iter().map(|b| f16::from_bits(*b).to_f32()).collect()
+}
+
+fn decode_f16_into(bits: &[u16], out: &mut [f32]) {
+    for (value, b) in out.iter_mut().zip(bits) {
+        *value = f16::from_bits(*b).to_f32();
+    }
+}
+
+impl<D: DistanceCalculator> Quantizer for Float16Quantizer<D> {
+    type QuantizedT = u16;
+
+    fn quantize(&self, value: &[f32]) -> Vec<u16> {
+        value.iter().map(|v| f16::from_f32(*v).to_bits()).collect()
+    }
+
+    fn quantized_dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn original_vector(&self, quantized_vector: &[u16]) -> Vec<f32> {
+        decode_f16(quantized_vector)
+    }
+
+    // Decodes 16 dimensions at a time into stack buffers, so that no vector is allocated
+    fn distance(&self, query: &[u16], point: &[u16], _implem: L2DistanceCalculatorImpl) -> f32 {
+        let mut sum_16 = f32x16::splat(0.0);
+        let mut a_block = [0.0f32; 16];
+        let mut b_block = [0.0f32; 16];
+        let mut query_chunks = query.chunks_exact(16);
+        let mut point_chunks = point.chunks_exact(16);
+        for (a, b) in query_chunks.by_ref().zip(point_chunks.by_ref()) {
+            decode_f16_into(a, &mut a_block);
+            decode_f16_into(b, &mut b_block);
+            D::accumulate_lanes::<16>(&a_block, &b_block, &mut sum_16);
+        }
+
+        let rest = query_chunks.remainder().len();
+        decode_f16_into(query_chunks.remainder(), &mut a_block);
+        decode_f16_into(point_chunks.remainder(), &mut b_block);
+        D::outermost_op(
+            sum_16.reduce_sum() + D::accumulate_scalar(&a_block[..rest], &b_block[..rest]),
+        )
+    }
+
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        D::calculate(a, b)
+    }
+
+    fn read(dir: String) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let config_path = Path::new(&dir).join(FLOAT16_QUANTIZER_CONFIG_NAME);
+        if !config_path.is_file() {
+            return Err(Error::msg("Config file does not exist"));
+        }
+        let config: Float16QuantizerConfig = serde_yaml::from_slice(&std::fs::read(config_path)?)?;
+        Ok(Self::new(config.dimension))
+    }
+}
+
+impl<D: DistanceCalculator> WritableQuantizer for Float16Quantizer<D> {
+    fn write_to_directory(&self, base_directory: &str) -> Result<()> {
+        let config = Float16QuantizerConfig {
+            dimension: self.dimension,
+        };
+        std::fs::write(
+            Path::new(base_directory).join(FLOAT16_QUANTIZER_CONFIG_NAME),
+            serde_yaml::to_string(&config)?,
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Float16QuantizerConfig {
+    pub dimension: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+
+    #[test]
+    fn test_float16_quantizer() {
+        let quantizer = Float16Quantizer::<L2DistanceCalculator>::new(4);
+        assert_eq!(quantizer.quantized_dimension(), 4);
+
+        // Values representable in half precision round-trip exactly
+        let value = vec![0.5, -2.0, 1024.0, 0.0];
+        assert_eq!(
+            quantizer.original_vector(&quantizer.quantize(&value)),
+            value
+        );
+
+        let a = generate_random_vector(128);
+        let b = generate_random_vector(128);
+        let distance = quantizer.distance(
+            &quantizer.quantize(&a),
+            &quantizer.quantize(&b),
+            L2DistanceCalculatorImpl::Scalar,
+        );
+        let expected = L2DistanceCalculator::calculate(&a, &b);
+        assert!((distance - expected).abs() <= expected * 1e-2);
+
+        // Dimensions that are not a multiple of the block size
+        let quantizer_20 = Float16Quantizer::<L2DistanceCalculator>::new(20);
+        let a = quantizer_20.quantize(&generate_random_vector(20));
+        let b = quantizer_20.quantize(&generate_random_vector(20));
+        let distance = quantizer_20.distance(&a, &b, L2DistanceCalculatorImpl::Scalar);
+        let expected = L2DistanceCalculator::calculate(&decode_f16(&a), &decode_f16(&b));
+        assert!((distance - expected).abs() <= expected * 1e-5);
+
+        let temp_dir = TempDir::new("test_float16_quantizer").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        quantizer.write_to_directory(&base_directory).unwrap();
+        let read_quantizer =
+            Float16Quantizer::<L2DistanceCalculator>::read(base_directory).unwrap();
+        assert_eq!(read_quantizer.quantized_dimension(), 4);
+    }
+}
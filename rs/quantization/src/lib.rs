@@ -1,6 +1,12 @@
 #![feature(portable_simd)]
+pub mod bq;
+pub mod fp16;
 pub mod noq;
 pub mod pq;
 pub mod quantization;
 pub mod rabitq;
+pub mod rp;
+pub mod rq;
+pub mod sq;
+pub mod sq4;
 pub mod typing;
@@ -45,6 +45,10 @@ impl<D: DistanceCalculator> Quantizer for NoQuantizer<D> {
         D::calculate(_query, _point)
     }
 
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        D::calculate(a, b)
+    }
+
     fn read(dir: String) -> Result<Self>
     where
         Self: Sized,
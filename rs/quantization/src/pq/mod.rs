@@ -1,2 +1,3 @@
+pub mod opq;
 pub mod pq;
 pub mod pq_builder;
@@ -0,0 +1,498 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use log::debug;
+use utils::distance::l2::{L2DistanceCalculator, L2DistanceCalculatorImpl};
+use utils::{CalculateSquared, DistanceCalculator};
+
+use crate::pq::pq::{AdcTable, ProductQuantizer, ProductQuantizerConfig};
+use crate::pq::pq_builder::{ProductQuantizerBuilder, ProductQuantizerBuilderConfig};
+use crate::quantization::{Quantizer, WritableQuantizer};
+
+pub const OPQ_ROTATION_NAME: &str = "opq_rotation";
+
+// Maximum number of sweeps of the Jacobi eigenvalue algorithm.
+const MAX_JACOBI_SWEEPS: usize = 100;
+
+pub struct OPQConfig {
+    // Number of rounds of alternating optimization. Each round trains the codebooks for the
+    // current rotation, then updates the rotation for these codebooks.
+    pub num_iterations: usize,
+}
+
+/// Product quantizer applied after an orthogonal rotation `R` of the vectors, learned so that
+/// subspaces align with the data and the quantization error is lower than with plain PQ.
+pub struct OptimizedProductQuantizer<D: DistanceCalculator> {
+    // [dimension x dimension] orthogonal matrix, row major. Vectors are quantized as `R * x`.
+    pub rotation: Vec<f32>,
+    pub pq: ProductQuantizer<D>,
+}
+
+impl<D: DistanceCalculator> OptimizedProductQuantizer<D> {
+    pub fn new(rotation: Vec<f32>, pq: ProductQuantizer<D>) -> Result<Self> {
+        if rotation.len() != pq.dimension * pq.dimension {
+            return Err(Error::msg(
+                "Rotation needs to be a dimension x dimension matrix.",
+            ));
+        }
+        Ok(Self { rotation, pq })
+    }
+
+    /// Learns the rotation and the codebooks by alternating optimization: with `R` fixed, train
+    /// the PQ codebooks on the rotated data; with the codebooks fixed, set `R` to the orthogonal
+    /// matrix minimizing the distance between rotated vectors and their reconstructions.
+    /// `R` starts from the principal components of the data, allocated to subspaces so that each
+    /// subspace gets a similar share of the variance.
+    pub fn train(
+        pq_config: ProductQuantizerConfig,
+        builder_config: ProductQuantizerBuilderConfig,
+        opq_config: &OPQConfig,
+        data: Vec<Vec<f32>>,
+        base_directory: String,
+    ) -> Result<Self> {
+        pq_config.validate()?;
+        let dimension = pq_config.dimension;
+        let mut rotation = eigenvalue_allocation(&data, dimension, pq_config.subvector_dimension);
+        for iteration in 0..=opq_config.num_iterations {
+            let rotated: Vec<Vec<f32>> = data.iter().map(|x| multiply(&rotation, x)).collect();
+            let mut pq_builder =
+                ProductQuantizerBuilder::<D>::new(pq_config.clone(), builder_config.clone());
+            for vector in rotated.iter() {
+                pq_builder.add(vector.clone());
+            }
+            let pq = pq_builder.build(base_directory.clone())?;
+            if iteration == opq_config.num_iterations {
+                return Self::new(rotation, pq);
+            }
+            debug!(
+                "OPQ iteration {} - error: {:.4}",
+                iteration,
+                pq.mean_squared_error(&rotated)
+            );
+
+            // Orthogonal Procrustes: R = argmin sum ||R x - y||^2 is the orthogonal polar factor
+            // of M = sum y x^T
+            let mut m = vec![0.0f64; dimension * dimension];
+            for (x, y) in data.iter().zip(rotated.iter()) {
+                let y = pq.original_vector(&pq.quantize(y));
+                for i in 0..dimension {
+                    let row = &mut m[i * dimension..(i + 1) * dimension];
+                    for (value, x_j) in row.iter_mut().zip(x.iter()) {
+                        *value += y[i] as f64 * *x_j as f64;
+                    }
+                }
+            }
+            match polar_factor(&m, dimension) {
+                Some(new_rotation) => rotation = new_rotation,
+                // Rank-deficient data does not determine the rotation, keep the current one
+                None => {
+                    debug!("OPQ iteration {} - rotation not updated", iteration);
+                }
+            }
+        }
+        unreachable!("The last iteration returns")
+    }
+
+    /// Applies the rotation to `value`.
+    pub fn rotate(&self, value: &[f32]) -> Vec<f32> {
+        multiply(&self.rotation, value)
+    }
+
+    /// Applies the inverse rotation, i.e. the transpose, to `value`.
+    fn rotate_back(&self, value: &[f32]) -> Vec<f32> {
+        let dimension = self.pq.dimension;
+        let mut result = vec![0.0f32; dimension];
+        for (i, v) in value.iter().enumerate() {
+            let row = &self.rotation[i * dimension..(i + 1) * dimension];
+            for (r, x) in result.iter_mut().zip(row.iter()) {
+                *r += v * x;
+            }
+        }
+        result
+    }
+
+    /// Mean squared error between `vectors` and their quantized-then-reconstructed versions.
+    pub fn mean_squared_error(&self, vectors: &[Vec<f32>]) -> f32 {
+        if vectors.is_empty() {
+            return 0.0;
+        }
+        let total_error: f64 = vectors
+            .iter()
+            .map(|vector| {
+                let reconstructed = self.original_vector(&self.quantize(vector));
+                L2DistanceCalculator::calculate_squared(vector, &reconstructed) as f64
+            })
+            .sum();
+        (total_error / vectors.len() as f64) as f32
+    }
+}
+
+/// Rotation whose rows are the principal components of `data`. Components are assigned greedily,
+/// by decreasing variance, to the non-full subspace with the lowest product of variances.
+fn eigenvalue_allocation(
+    data: &[Vec<f32>],
+    dimension: usize,
+    subvector_dimension: usize,
+) -> Vec<f32> {
+    let mut mean = vec![0.0f64; dimension];
+    for vector in data.iter() {
+        for (m, x) in mean.iter_mut().zip(vector.iter()) {
+            *m += *x as f64;
+        }
+    }
+    mean.iter_mut().for_each(|m| *m /= data.len().max(1) as f64);
+    let mut covariance = vec![0.0f64; dimension * dimension];
+    for vector in data.iter() {
+        let centered: Vec<f64> = vector
+            .iter()
+            .zip(mean.iter())
+            .map(|(x, m)| *x as f64 - m)
+            .collect();
+        for (i, c_i) in centered.iter().enumerate() {
+            let row = &mut covariance[i * dimension..(i + 1) * dimension];
+            for (value, c_j) in row.iter_mut().zip(centered.iter()) {
+                *value += c_i * c_j;
+            }
+        }
+    }
+    let (eigenvalues, eigenvectors) = symmetric_eigen(&covariance, dimension);
+
+    let mut order: Vec<usize> = (0..dimension).collect();
+    order.sort_by(|a, b| eigenvalues[*b].total_cmp(&eigenvalues[*a]));
+    let num_subspaces = dimension / subvector_dimension;
+    // Sum of log variances and assigned components of each subspace
+    let mut subspaces: Vec<(f64, Vec<usize>)> = vec![(0.0, vec![]); num_subspaces];
+    for component in order {
+        let (log_product, components) = subspaces
+            .iter_mut()
+            .filter(|(_, components)| components.len() < subvector_dimension)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap();
+        *log_product += eigenvalues[component].max(1e-12).ln();
+        components.push(component);
+    }
+
+    let mut rotation = Vec::with_capacity(dimension * dimension);
+    for component in subspaces
+        .iter()
+        .flat_map(|(_, components)| components.iter())
+    {
+        rotation.extend((0..dimension).map(|i| eigenvectors[i * dimension + component] as f32));
+    }
+    rotation
+}
+
+/// Product of a row-major square matrix and a vector.
+fn multiply(matrix: &[f32], vector: &[f32]) -> Vec<f32> {
+    matrix
+        .chunks_exact(vector.len())
+        .map(|row| row.iter().zip(vector.iter()).map(|(a, b)| a * b).sum())
+        .collect()
+}
+
+/// Eigen-decomposition of a symmetric row-major matrix with the cyclic Jacobi method. Returns the
+/// eigenvalues and the eigenvectors as the columns of a row-major matrix.
+fn symmetric_eigen(matrix: &[f64], n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut a = matrix.to_vec();
+    let mut v = vec![0.0f64; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.0;
+    }
+    let norm: f64 = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    for _ in 0..MAX_JACOBI_SWEEPS {
+        let off_diagonal: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i * n + j] * a[i * n + j])
+            .sum::<f64>()
+            .sqrt();
+        if off_diagonal <= 1e-12 * norm {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq == 0.0 {
+                    continue;
+                }
+                // Rotation in the (p, q) plane zeroing a[p][q]
+                let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let t = if theta == 0.0 { 1.0 } else { t };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let akp = a[k * n + p];
+                    let akq = a[k * n + q];
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let apk = a[p * n + k];
+                    let aqk = a[q * n + k];
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let vkp = v[k * n + p];
+                    let vkq = v[k * n + q];
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i * n + i]).collect(), v)
+}
+
+/// Orthogonal polar factor `U V^T` of `m = U S V^T`, computed as `m (m^T m)^(-1/2)` from the
+/// eigen-decomposition of `m^T m`. Returns None if `m` is (numerically) singular.
+fn polar_factor(m: &[f64], n: usize) -> Option<Vec<f32>> {
+    let mut mtm = vec![0.0f64; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            mtm[i * n + j] = (0..n).map(|k| m[k * n + i] * m[k * n + j]).sum();
+        }
+    }
+    let (eigenvalues, eigenvectors) = symmetric_eigen(&mtm, n);
+    let max_eigenvalue = eigenvalues.iter().cloned().fold(0.0f64, f64::max);
+    if max_eigenvalue <= 0.0 || eigenvalues.iter().any(|&e| e <= 1e-10 * max_eigenvalue) {
+        return None;
+    }
+
+    // (m^T m)^(-1/2) = V diag(1 / sqrt(eigenvalues)) V^T
+    let mut inverse_sqrt = vec![0.0f64; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            inverse_sqrt[i * n + j] = (0..n)
+                .map(|k| eigenvectors[i * n + k] * eigenvectors[j * n + k] / eigenvalues[k].sqrt())
+                .sum();
+        }
+    }
+    let mut result = vec![0.0f32; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            result[i * n + j] = (0..n)
+                .map(|k| m[i * n + k] * inverse_sqrt[k * n + j])
+                .sum::<f64>() as f32;
+        }
+    }
+    Some(result)
+}
+
+impl<D: DistanceCalculator> Quantizer for OptimizedProductQuantizer<D> {
+    type QuantizedT = u8;
+
+    fn quantize(&self, value: &[f32]) -> Vec<u8> {
+        self.pq.quantize(&self.rotate(value))
+    }
+
+    fn quantized_dimension(&self) -> usize {
+        self.pq.quantized_dimension()
+    }
+
+    fn original_vector(&self, quantized_vector: &[u8]) -> Vec<f32> {
+        self.rotate_back(&self.pq.original_vector(quantized_vector))
+    }
+
+    /// The rotation preserves distances, so codes are compared as with plain PQ.
+    fn distance(&self, a: &[u8], b: &[u8], implem: L2DistanceCalculatorImpl) -> f32 {
+        self.pq.distance(a, b, implem)
+    }
+
+    fn adc_table(&self, query: &[f32]) -> Option<AdcTable> {
+        self.pq.adc_table(&self.rotate(query))
+    }
+
+    fn distance_with_adc_table(&self, table: &AdcTable, point: &[u8]) -> Result<f32> {
+        self.pq.distance_with_adc_table(table, point)
+    }
+
+    fn instance_id(&self) -> Option<u64> {
+        self.pq.instance_id()
+    }
+
+    // The rotation preserves distances
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        self.pq.original_distance(a, b)
+    }
+
+    fn read(dir: String) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let pq = ProductQuantizer::<D>::read(dir.clone())?;
+        let rotation_buffer = std::fs::read(Path::new(&dir).join(OPQ_ROTATION_NAME))?;
+        let rotation = rotation_buffer
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        Self::new(rotation, pq)
+    }
+}
+
+impl<D: DistanceCalculator> WritableQuantizer for OptimizedProductQuantizer<D> {
+    fn write_to_directory(&self, base_directory: &str) -> Result<()> {
+        self.pq.write_to_directory(base_directory)?;
+        let mut rotation_buffer = Vec::with_capacity(self.rotation.len() * 4);
+        for value in self.rotation.iter() {
+            rotation_buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        File::create(Path::new(base_directory).join(OPQ_ROTATION_NAME))?
+            .write_all(&rotation_buffer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    fn gaussian(rng: &mut impl Rng) -> f32 {
+        // Box-Muller transform
+        let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+        let u2: f32 = rng.gen();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+    }
+
+    /// Rows of a random orthogonal matrix, from Gram-Schmidt on gaussian vectors.
+    fn random_orthogonal(dimension: usize, rng: &mut impl Rng) -> Vec<Vec<f32>> {
+        let mut rows: Vec<Vec<f32>> = vec![];
+        while rows.len() < dimension {
+            let mut row: Vec<f32> = (0..dimension).map(|_| gaussian(rng)).collect();
+            for other in rows.iter() {
+                let dot: f32 = row.iter().zip(other.iter()).map(|(a, b)| a * b).sum();
+                for (r, o) in row.iter_mut().zip(other.iter()) {
+                    *r -= dot * o;
+                }
+            }
+            let norm = row.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm > 1e-3 {
+                rows.push(row.into_iter().map(|x| x / norm).collect());
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn test_polar_factor() {
+        let mut rng = rand::thread_rng();
+        let n = 6;
+        let m: Vec<f64> = (0..n * n).map(|_| gaussian(&mut rng) as f64).collect();
+        let r = polar_factor(&m, n).unwrap();
+        // R^T R = I
+        for i in 0..n {
+            for j in 0..n {
+                let dot: f32 = (0..n).map(|k| r[k * n + i] * r[k * n + j]).sum();
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((dot - expected).abs() < 1e-4, "{} vs {}", dot, expected);
+            }
+        }
+        assert!(polar_factor(&vec![0.0; n * n], n).is_none());
+    }
+
+    #[test]
+    fn test_opq_lower_error_than_pq() {
+        const DIMENSION: usize = 64;
+        const NUM_VECTORS: usize = 2000;
+        let mut rng = rand::thread_rng();
+
+        // Anisotropic gaussian: decaying variances along random orthogonal directions
+        let directions = random_orthogonal(DIMENSION, &mut rng);
+        let data: Vec<Vec<f32>> = (0..NUM_VECTORS)
+            .map(|_| {
+                let mut vector = vec![0.0f32; DIMENSION];
+                for (i, direction) in directions.iter().enumerate() {
+                    let coefficient = gaussian(&mut rng) * 4.0 / (1.0 + i as f32);
+                    for (v, d) in vector.iter_mut().zip(direction.iter()) {
+                        *v += coefficient * d;
+                    }
+                }
+                vector
+            })
+            .collect();
+
+        let pq_config = ProductQuantizerConfig {
+            dimension: DIMENSION,
+            subvector_dimension: 8,
+            num_bits: 4,
+        };
+        let builder_config = ProductQuantizerBuilderConfig {
+            max_iteration: 20,
+            batch_size: 64,
+            use_annealing: true,
+        };
+
+        let mut pq_builder = ProductQuantizerBuilder::<L2DistanceCalculator>::new(
+            pq_config.clone(),
+            builder_config.clone(),
+        );
+        for vector in data.iter() {
+            pq_builder.add(vector.clone());
+        }
+        let pq = pq_builder.build("".to_string()).unwrap();
+
+        let opq = OptimizedProductQuantizer::<L2DistanceCalculator>::train(
+            pq_config,
+            builder_config,
+            &OPQConfig { num_iterations: 5 },
+            data.clone(),
+            "".to_string(),
+        )
+        .unwrap();
+
+        let pq_error = pq.mean_squared_error(&data);
+        let opq_error = opq.mean_squared_error(&data);
+        assert!(opq_error < pq_error, "OPQ {} vs PQ {}", opq_error, pq_error);
+
+        // Rotating and rotating back is the identity
+        let rotated_back = opq.rotate_back(&opq.rotate(&data[0]));
+        for (a, b) in rotated_back.iter().zip(data[0].iter()) {
+            assert!((a - b).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_opq_read_write() {
+        let temp_dir =
+            tempdir::TempDir::new("opq_test").expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        let mut rng = rand::thread_rng();
+        let data: Vec<Vec<f32>> = (0..100)
+            .map(|_| (0..8).map(|_| gaussian(&mut rng)).collect())
+            .collect();
+
+        let opq = OptimizedProductQuantizer::<L2DistanceCalculator>::train(
+            ProductQuantizerConfig {
+                dimension: 8,
+                subvector_dimension: 2,
+                num_bits: 2,
+            },
+            ProductQuantizerBuilderConfig {
+                max_iteration: 5,
+                batch_size: 10,
+                use_annealing: true,
+            },
+            &OPQConfig { num_iterations: 2 },
+            data.clone(),
+            base_directory.clone(),
+        )
+        .unwrap();
+        opq.write_to_directory(&base_directory).unwrap();
+
+        let new_opq =
+            OptimizedProductQuantizer::<L2DistanceCalculator>::read(base_directory).unwrap();
+        assert_eq!(new_opq.rotation, opq.rotation);
+        assert_eq!(new_opq.quantize(&data[0]), opq.quantize(&data[0]));
+        let table = new_opq.adc_table(&data[1]).unwrap();
+        let code = opq.quantize(&data[0]);
+        let expected = L2DistanceCalculator::calculate_squared(
+            &opq.rotate(&data[1]),
+            &opq.pq.original_vector(&code),
+        );
+        assert!((new_opq.distance_with_adc_table(&table, &code).unwrap() - expected).abs() < 1e-4);
+    }
+}
@@ -9,11 +9,13 @@ use anyhow::{Error, Result};
 use serde::{Deserialize, Serialize};
 use utils::distance::l2::L2DistanceCalculatorImpl::Scalar;
 use utils::distance::l2::{L2DistanceCalculator, L2DistanceCalculatorImpl};
+use utils::mem::next_instance_id;
 use utils::{CalculateSquared, DistanceCalculator};
 
 use crate::quantization::{Quantizer, WritableQuantizer};
 
 pub const CODEBOOK_NAME: &str = "codebook";
+pub const DIMENSION_PERMUTATION_NAME: &str = "dimension_permutation";
 
 // (TODO): support inner PQ distance template
 pub struct ProductQuantizer<D: DistanceCalculator> {
@@ -23,10 +25,17 @@ pub struct ProductQuantizer<D: DistanceCalculator> {
     pub codebook: Vec<f32>,
     pub base_directory: String,
 
+    // If set, dimension `i` of the permuted vector is dimension `dimension_permutation[i]` of the
+    // original vector. Vectors are permuted before being split into subvectors.
+    pub dimension_permutation: Option<Vec<usize>>,
+
+    // Identifies the quantizer in caches of its ADC tables
+    id: u64,
+
     _marker: PhantomData<D>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProductQuantizerConfig {
     pub dimension: usize,
     pub subvector_dimension: usize,
@@ -41,6 +50,42 @@ impl ProductQuantizerConfig {
         Ok(())
     }
 }
+/// Squared L2 distances from a query to every centroid of every subvector. The distance from the
+/// query to a quantized point is then a sum of lookups (asymmetric distance computation).
+pub struct AdcTable {
+    num_centroids: usize,
+    distances: Vec<f32>,
+
+    // Added to every lookup
+    offset: f32,
+}
+
+impl AdcTable {
+    /// Chains the tables of codes that are concatenated into a single code. All tables need the
+    /// same number of centroids per subvector.
+    pub(crate) fn chain(tables: Vec<AdcTable>, offset: f32) -> AdcTable {
+        AdcTable {
+            num_centroids: tables.first().map_or(0, |table| table.num_centroids),
+            offset: tables.iter().map(|table| table.offset).sum::<f32>() + offset,
+            distances: tables
+                .into_iter()
+                .flat_map(|table| table.distances)
+                .collect(),
+        }
+    }
+
+    /// Returns the squared L2 distance between the query and the point with the given code.
+    pub fn lookup(&self, code: &[u8]) -> f32 {
+        code.iter()
+            .enumerate()
+            .map(|(subvector_idx, centroid)| {
+                self.distances[subvector_idx * self.num_centroids + *centroid as usize]
+            })
+            .sum::<f32>()
+            + self.offset
+    }
+}
+
 pub struct ProductQuantizerReader {
     base_directory: String,
 }
@@ -94,10 +139,48 @@ impl<D: DistanceCalculator> ProductQuantizer<D> {
             num_bits,
             codebook,
             base_directory,
+            dimension_permutation: None,
+            id: next_instance_id(),
             _marker: PhantomData,
         })
     }
 
+    /// Sets the permutation applied to vectors before they are split into subvectors.
+    pub fn set_dimension_permutation(&mut self, dimension_permutation: Vec<usize>) -> Result<()> {
+        if dimension_permutation.len() != self.dimension {
+            return Err(Error::msg("Dimension permutation is not valid"));
+        }
+        let mut seen = vec![false; self.dimension];
+        for &dim in dimension_permutation.iter() {
+            if dim >= self.dimension || seen[dim] {
+                return Err(Error::msg("Dimension permutation is not valid"));
+            }
+            seen[dim] = true;
+        }
+        self.dimension_permutation = Some(dimension_permutation);
+        Ok(())
+    }
+
+    fn permute(&self, value: &[f32]) -> Vec<f32> {
+        match &self.dimension_permutation {
+            Some(permutation) => permutation.iter().map(|&dim| value[dim]).collect(),
+            None => value.to_vec(),
+        }
+    }
+
+    fn inverse_permute(&self, value: Vec<f32>) -> Vec<f32> {
+        match &self.dimension_permutation {
+            Some(permutation) => {
+                let mut result = vec![0.0; value.len()];
+                for (i, &dim) in permutation.iter().enumerate() {
+                    result[dim] = value[i];
+                }
+                result
+            }
+            None => value,
+        }
+    }
+
     pub fn load(config: ProductQuantizerConfig, base_directory: &str) -> Result<Self> {
         let codebook_path = Path::new(&base_directory).join("codebook");
 
@@ -116,13 +199,49 @@ impl<D: DistanceCalculator> ProductQuantizer<D> {
             offset += 4;
         }
 
-        Self::new(
+        let mut pq = Self::new(
             config.dimension,
             config.subvector_dimension,
             config.num_bits,
             codebook,
             base_directory.to_string(),
-        )
+        )?;
+
+        let permutation_path = Path::new(&base_directory).join(DIMENSION_PERMUTATION_NAME);
+        if permutation_path.exists() {
+            let permutation_buffer = std::fs::read(permutation_path)?;
+            let permutation = permutation_buffer
+                .chunks_exact(8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+                .collect();
+            pq.set_dimension_permutation(permutation)?;
+        }
+        Ok(pq)
+    }
+
+    /// Computes the distances from `query` to all centroids, to be used with `AdcTable::lookup`.
+    pub fn compute_adc_table(&self, query: &[f32]) -> AdcTable {
+        let num_centroids = (1 << self.num_bits) as usize;
+        let subvector_size_in_codebook = self.subvector_dimension * num_centroids;
+        let query = self.permute(query);
+
+        let mut distances = Vec::with_capacity(self.quantized_dimension() * num_centroids);
+        query
+            .chunks_exact(self.subvector_dimension)
+            .enumerate()
+            .for_each(|(subvector_idx, subvector)| {
+                let subvector_offset = subvector_idx * subvector_size_in_codebook;
+                for i in 0..num_centroids {
+                    let offset = subvector_offset + i * self.subvector_dimension;
+                    let centroid = &self.codebook[offset..offset + self.subvector_dimension];
+                    distances.push(L2DistanceCalculator::calculate_squared(subvector, centroid));
+                }
+            });
+        AdcTable {
+            num_centroids,
+            distances,
+            offset: 0.0,
+        }
     }
 
     pub fn codebook_to_buffer(&self) -> Vec<u8> {
@@ -135,6 +254,21 @@ impl<D: DistanceCalculator> ProductQuantizer<D> {
         codebook_buffer
     }
 
+    /// Mean squared error between `vectors` and their quantized-then-reconstructed versions.
+    pub fn mean_squared_error(&self, vectors: &[Vec<f32>]) -> f32 {
+        if vectors.is_empty() {
+            return 0.0;
+        }
+        let total_error: f64 = vectors
+            .iter()
+            .map(|vector| {
+                let reconstructed = self.original_vector(&self.quantize(vector));
+                L2DistanceCalculator::calculate_squared(vector, &reconstructed) as f64
+            })
+            .sum();
+        (total_error / vectors.len() as f64) as f32
+    }
+
     pub fn config(&self) -> ProductQuantizerConfig {
         ProductQuantizerConfig {
             dimension: self.dimension,
@@ -153,6 +287,7 @@ impl<D: DistanceCalculator> Quantizer for ProductQuantizer<D> {
         let mut result = Vec::<u8>::with_capacity(self.dimension / self.subvector_dimension);
         let num_centroids = (1 << self.num_bits) as usize;
         let subvector_size_in_codebook = self.subvector_dimension * num_centroids;
+        let value = self.permute(value);
 
         value
             .chunks_exact(self.subvector_dimension as usize)
@@ -196,7 +331,19 @@ impl<D: DistanceCalculator> Quantizer for ProductQuantizer<D> {
                     result.push(self.codebook[centroid_offset + i]);
                 }
             });
-        result
+        self.inverse_permute(result)
+    }
+
+    fn adc_table(&self, query: &[f32]) -> Option<AdcTable> {
+        Some(self.compute_adc_table(query))
+    }
+
+    fn distance_with_adc_table(&self, table: &AdcTable, point: &[u8]) -> Result<f32> {
+        Ok(table.lookup(point))
+    }
+
+    fn instance_id(&self) -> Option<u64> {
+        Some(self.id)
     }
 
     fn distance(&self, a: &[u8], b: &[u8], implem: L2DistanceCalculatorImpl) -> f32 {
@@ -257,7 +404,7 @@ impl<D: DistanceCalculator> Quantizer for ProductQuantizer<D> {
                             b_vec = b_vec.chunks_exact(4).remainder()
                         }
                         if a_vec.len() > 0 {
-                            sum_1 = D::accumulate_scalar(a_vec, b_vec);
+                            sum_1 += D::accumulate_scalar(a_vec, b_vec);
                         }
                     });
                 D::outermost_op(
@@ -277,6 +424,11 @@ impl<D: DistanceCalculator> Quantizer for ProductQuantizer<D> {
         }
     }
 
+    // Same scale as the sum of the partial distances of the subvectors in `distance`
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        D::outermost_op(D::accumulate_scalar(a, b))
+    }
+
     fn read(dir: String) -> Result<Self>
     where
         Self: Sized,
@@ -305,6 +457,20 @@ impl<D: DistanceCalculator> WritableQuantizer for ProductQuantizer<D> {
         let mut codebook_file = File::create(Path::new(&base_directory).join(&CODEBOOK_NAME))?;
         codebook_file.write(&codebook_buffer)?;
 
+        // Write dimension permutation
+        let permutation_path = Path::new(&base_directory).join(DIMENSION_PERMUTATION_NAME);
+        if permutation_path.exists() {
+            // Delete the file if exists
+            std::fs::remove_file(&permutation_path)?;
+        }
+        if let Some(permutation) = &self.dimension_permutation {
+            let mut permutation_buffer = Vec::with_capacity(permutation.len() * 8);
+            for dim in permutation.iter() {
+                permutation_buffer.extend_from_slice(&(*dim as u64).to_le_bytes());
+            }
+            File::create(permutation_path)?.write_all(&permutation_buffer)?;
+        }
+
         // Write config
         let mut config_file =
             File::create(Path::new(&base_directory).join("product_quantizer_config.yaml"))?;
@@ -367,5 +533,81 @@ mod tests {
         assert_eq!(new_pq.dimension, 10);
         assert_eq!(new_pq.subvector_dimension, 2);
         assert_eq!(new_pq.num_bits, 1);
+        assert!(new_pq.dimension_permutation.is_none());
+    }
+
+    #[test]
+    fn test_product_quantizer_with_dimension_permutation() {
+        // 2 subvectors of dimension 2, 1 bit each.
+        let codebook = vec![
+            0.0, 0.0, 1.0, 1.0, // subvector 0
+            0.0, 0.0, 5.0, 5.0, // subvector 1
+        ];
+        let temp_dir = tempdir::TempDir::new("product_quantizer_permutation_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir
+            .path()
+            .to_str()
+            .expect("Failed to convert temporary directory path to string")
+            .to_string();
+
+        let mut pq = ProductQuantizer::<L2DistanceCalculator>::new(
+            4,
+            2,
+            1,
+            codebook,
+            base_directory.clone(),
+        )
+        .expect("ProductQuantizer should be created.");
+        assert!(pq.set_dimension_permutation(vec![0, 0, 1, 2]).is_err());
+        assert!(pq.set_dimension_permutation(vec![0, 1, 2]).is_err());
+        // Subvector 0 holds dimensions 1 and 3, subvector 1 holds dimensions 0 and 2.
+        pq.set_dimension_permutation(vec![1, 3, 0, 2])
+            .expect("Permutation should be valid");
+
+        let value = vec![5.0, 1.0, 5.0, 1.0];
+        let quantized_value = pq.quantize(&value);
+        assert_eq!(quantized_value, vec![1, 1]);
+        assert_eq!(pq.original_vector(&quantized_value), value);
+
+        pq.write_to_directory(&base_directory)
+            .expect("Failed to write the quantizer");
+        let new_pq = ProductQuantizerReader::new(base_directory)
+            .read::<L2DistanceCalculator>()
+            .expect("Failed to read the quantizer");
+        assert_eq!(new_pq.dimension_permutation, Some(vec![1, 3, 0, 2]));
+        assert_eq!(new_pq.quantize(&value), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_adc_table() {
+        // 2 subvectors of dimension 2, 1 bit each.
+        let codebook = vec![
+            0.0, 0.0, 1.0, 1.0, // subvector 0
+            0.0, 0.0, 5.0, 5.0, // subvector 1
+        ];
+        let mut pq =
+            ProductQuantizer::<L2DistanceCalculator>::new(4, 2, 1, codebook, "".to_string())
+                .expect("ProductQuantizer should be created.");
+        pq.set_dimension_permutation(vec![1, 3, 0, 2])
+            .expect("Permutation should be valid");
+
+        // Subvectors are shorter than any SIMD lane, so they all go through the scalar remainder.
+        let scalar = pq.distance(&[0, 1], &[1, 0], Scalar);
+        let streaming = pq.distance(&[0, 1], &[1, 0], L2DistanceCalculatorImpl::StreamingSIMD);
+        assert!((scalar - 52.0).abs() < 1e-5);
+        assert!((streaming - scalar).abs() < 1e-5);
+        // Original vectors are compared on the same scale as quantized ones
+        let a = pq.original_vector(&[0, 1]);
+        let b = pq.original_vector(&[1, 0]);
+        assert!((pq.original_distance(&a, &b) - streaming).abs() < 1e-5);
+
+        let query = vec![4.0, 1.0, 3.0, 0.0];
+        let table = pq.compute_adc_table(&query);
+        for code in [[0u8, 0], [0, 1], [1, 0], [1, 1]] {
+            let expected =
+                L2DistanceCalculator::calculate_squared(&query, &pq.original_vector(&code));
+            assert!((table.lookup(&code) - expected).abs() < 1e-5);
+        }
     }
 }
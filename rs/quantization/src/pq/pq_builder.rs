@@ -3,13 +3,100 @@ use std::marker::PhantomData;
 use anyhow::Result;
 use kmeans::*;
 use log::debug;
+use rand::seq::index::sample;
 use utils::DistanceCalculator;
 
 use crate::pq::pq::{ProductQuantizer, ProductQuantizerConfig};
 
+// Temperature of the first centroid update when annealing. It decreases linearly to 1.0, a plain
+// KMeans update, at the last iteration.
+const INITIAL_ANNEALING_TEMPERATURE: f32 = 1.8;
+
+#[derive(Clone)]
 pub struct ProductQuantizerBuilderConfig {
     pub max_iteration: usize,
     pub batch_size: usize,
+
+    // Train each subspace with full-batch KMeans whose centroid updates overshoot the mean of
+    // their points in early iterations, see `kmeans_with_annealing`. Otherwise, use mini-batch
+    // KMeans.
+    pub use_annealing: bool,
+}
+
+/// KMeans where each update moves a centroid by `temperature` times the distance to the mean of
+/// its assigned points: `new_centroid = old_centroid + temperature * (mean - old_centroid)`.
+/// The temperature starts at `INITIAL_ANNEALING_TEMPERATURE` and cools down to 1.0, so centroids
+/// take large steps early, which helps them escape local minima on clustered data, and settle
+/// like plain KMeans at the end. Points are assigned to their nearest centroid with `D`.
+/// Returns the flattened centroids.
+fn kmeans_with_annealing<D: DistanceCalculator>(
+    samples: &[f32],
+    dimension: usize,
+    num_centroids: usize,
+    max_iteration: usize,
+) -> Vec<f32> {
+    let num_samples = samples.len() / dimension;
+    if num_samples == 0 {
+        return vec![0.0; num_centroids * dimension];
+    }
+
+    // Start from distinct random samples, reusing samples if there are not enough of them
+    let mut rng = rand::thread_rng();
+    let initial_samples = sample(&mut rng, num_samples, num_centroids.min(num_samples));
+    let mut centroids = Vec::with_capacity(num_centroids * dimension);
+    for i in 0..num_centroids {
+        let sample_idx = initial_samples.index(i % initial_samples.len());
+        centroids.extend_from_slice(&samples[sample_idx * dimension..(sample_idx + 1) * dimension]);
+    }
+
+    let mut sums = vec![0.0f32; num_centroids * dimension];
+    let mut counts = vec![0usize; num_centroids];
+    for iteration in 0..max_iteration {
+        let progress = if max_iteration > 1 {
+            iteration as f32 / (max_iteration - 1) as f32
+        } else {
+            1.0
+        };
+        let temperature =
+            INITIAL_ANNEALING_TEMPERATURE - (INITIAL_ANNEALING_TEMPERATURE - 1.0) * progress;
+
+        sums.fill(0.0);
+        counts.fill(0);
+        let mut distsum = 0.0;
+        for point in samples.chunks_exact(dimension) {
+            let (nearest, distance) = centroids
+                .chunks_exact(dimension)
+                .map(|centroid| D::calculate(point, centroid))
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            distsum += distance;
+            counts[nearest] += 1;
+            for (sum, value) in sums[nearest * dimension..(nearest + 1) * dimension]
+                .iter_mut()
+                .zip(point)
+            {
+                *sum += value;
+            }
+        }
+
+        // Centroids without points stay where they are
+        for (centroid_idx, count) in counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            let range = centroid_idx * dimension..(centroid_idx + 1) * dimension;
+            for (centroid_value, sum) in centroids[range.clone()].iter_mut().zip(&sums[range]) {
+                let mean = sum / *count as f32;
+                *centroid_value += temperature * (mean - *centroid_value);
+            }
+        }
+        debug!(
+            "Annealing iteration {} - temperature {:.2}, error {:.2}",
+            iteration, temperature, distsum
+        );
+    }
+    centroids
 }
 
 pub struct ProductQuantizerBuilder<D: DistanceCalculator> {
@@ -17,6 +104,9 @@ pub struct ProductQuantizerBuilder<D: DistanceCalculator> {
     builder_config: ProductQuantizerBuilderConfig,
     pub dataset: Vec<Vec<f32>>,
 
+    // Group dimensions into subvectors of equal total variance instead of contiguous ranges.
+    balance_subspace_variance: bool,
+
     _marker: PhantomData<D>,
 }
 
@@ -30,10 +120,66 @@ impl<D: DistanceCalculator> ProductQuantizerBuilder<D> {
             pq_config: config,
             builder_config,
             dataset: Vec::new(),
+            balance_subspace_variance: false,
             _marker: PhantomData,
         }
     }
 
+    /// If enabled, `build` groups dimensions with `compute_pca_subspaces` and stores the resulting
+    /// dimension permutation in the product quantizer.
+    pub fn set_balance_subspace_variance(&mut self, balance_subspace_variance: bool) {
+        self.balance_subspace_variance = balance_subspace_variance;
+    }
+
+    /// Computes the variance of each dimension, then greedily groups dimensions into
+    /// `num_subspaces` groups of equal size such that each group has roughly the same total
+    /// variance. Dimensions within a group are sorted.
+    pub fn compute_pca_subspaces(
+        training_vectors: &[Vec<f32>],
+        num_subspaces: usize,
+    ) -> Vec<Vec<usize>> {
+        if num_subspaces == 0 {
+            return vec![];
+        }
+        let dimension = training_vectors.first().map_or(0, |v| v.len());
+        let num_vectors = training_vectors.len() as f64;
+
+        let mut means = vec![0.0f64; dimension];
+        for vector in training_vectors {
+            for (dim, value) in vector.iter().enumerate() {
+                means[dim] += *value as f64 / num_vectors;
+            }
+        }
+        let mut variances = vec![0.0f64; dimension];
+        for vector in training_vectors {
+            for (dim, value) in vector.iter().enumerate() {
+                let diff = *value as f64 - means[dim];
+                variances[dim] += diff * diff / num_vectors;
+            }
+        }
+
+        // Assign dimensions from highest to lowest variance, each to the non-full group with the
+        // lowest total variance so far.
+        let mut dims: Vec<usize> = (0..dimension).collect();
+        dims.sort_by(|a, b| variances[*b].total_cmp(&variances[*a]));
+        let capacity = dimension.div_ceil(num_subspaces);
+        let mut groups: Vec<Vec<usize>> = vec![Vec::with_capacity(capacity); num_subspaces];
+        let mut group_variances = vec![0.0f64; num_subspaces];
+        for dim in dims {
+            let group = (0..num_subspaces)
+                .filter(|group| groups[*group].len() < capacity)
+                .min_by(|a, b| group_variances[*a].total_cmp(&group_variances[*b]))
+                .unwrap();
+            groups[group].push(dim);
+            group_variances[group] += variances[dim];
+        }
+
+        for group in groups.iter_mut() {
+            group.sort();
+        }
+        groups
+    }
+
     /// Add a new vector to the dataset for training
     pub fn add(&mut self, data: Vec<f32>) {
         self.dataset.push(data);
@@ -42,6 +188,16 @@ impl<D: DistanceCalculator> ProductQuantizerBuilder<D> {
     /// Train kmeans on the dataset, and returns the product quantizer
     pub fn build(&mut self, base_directory: String) -> Result<ProductQuantizer<D>> {
         let num_subvector = self.pq_config.dimension / self.pq_config.subvector_dimension;
+        let dimension_permutation = if self.balance_subspace_variance {
+            let permutation: Vec<usize> =
+                Self::compute_pca_subspaces(&self.dataset, num_subvector).concat();
+            for point in self.dataset.iter_mut() {
+                *point = permutation.iter().map(|&dim| point[dim]).collect();
+            }
+            Some(permutation)
+        } else {
+            None
+        };
         let mut codebook = Vec::<f32>::with_capacity(
             num_subvector * self.pq_config.subvector_dimension * (1 << self.pq_config.num_bits),
         );
@@ -57,6 +213,16 @@ impl<D: DistanceCalculator> ProductQuantizerBuilder<D> {
                     idx += 1;
                 }
             }
+            if self.builder_config.use_annealing {
+                codebook.extend(kmeans_with_annealing::<D>(
+                    &samples,
+                    self.pq_config.subvector_dimension,
+                    1 << self.pq_config.num_bits,
+                    self.builder_config.max_iteration,
+                ));
+                continue;
+            }
+
             let conf = KMeansConfig::build()
                 .init_done(&|_| debug!("Initialization completed."))
                 .iteration_done(&|s, nr, new_distsum| {
@@ -84,13 +250,17 @@ impl<D: DistanceCalculator> ProductQuantizerBuilder<D> {
             result.centroids.iter().for_each(|x| codebook.push(*x));
             debug!("Error: {}", result.distsum);
         }
-        ProductQuantizer::new(
+        let mut pq = ProductQuantizer::new(
             self.pq_config.dimension,
             self.pq_config.subvector_dimension,
             self.pq_config.num_bits,
             codebook,
             base_directory,
-        )
+        )?;
+        if let Some(permutation) = dimension_permutation {
+            pq.set_dimension_permutation(permutation)?;
+        }
+        Ok(pq)
     }
 }
 
@@ -100,6 +270,7 @@ mod tests {
     use utils::distance::l2::L2DistanceCalculator;
     use utils::distance::l2::L2DistanceCalculatorImpl::{Scalar, StreamingSIMD, SIMD};
     use utils::test_utils::generate_random_vector;
+    use utils::CalculateSquared;
 
     use super::*;
     use crate::quantization::Quantizer;
@@ -120,6 +291,7 @@ mod tests {
             ProductQuantizerBuilderConfig {
                 max_iteration: 1000,
                 batch_size: 4,
+                use_annealing: false,
             },
         );
         // Generate 10000 vectors of f32, dimension 128
@@ -143,6 +315,77 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compute_pca_subspaces() {
+        // Dimension `i` has variance proportional to (i + 1)^2.
+        let training_vectors: Vec<Vec<f32>> = (0..100)
+            .map(|i| {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                (0..8).map(|dim| sign * (dim + 1) as f32).collect()
+            })
+            .collect();
+
+        let groups = ProductQuantizerBuilder::<L2DistanceCalculator>::compute_pca_subspaces(
+            &training_vectors,
+            2,
+        );
+        assert_eq!(groups.len(), 2);
+        assert!(groups.iter().all(|group| group.len() == 4));
+
+        let mut all_dims = groups.concat();
+        all_dims.sort();
+        assert_eq!(all_dims, (0..8).collect::<Vec<usize>>());
+
+        // Variances are 1, 4, 9, ..., 64 (total 204). Contiguous grouping would give 30 and 174.
+        let group_variance = |group: &Vec<usize>| {
+            group
+                .iter()
+                .map(|d| ((d + 1) * (d + 1)) as i64)
+                .sum::<i64>()
+        };
+        assert!((group_variance(&groups[0]) - group_variance(&groups[1])).abs() <= 20);
+    }
+
+    #[test]
+    fn test_product_quantizer_builder_balance_subspace_variance() {
+        const DIMENSION: usize = 16;
+        let mut pqb = ProductQuantizerBuilder::<L2DistanceCalculator>::new(
+            ProductQuantizerConfig {
+                dimension: DIMENSION,
+                subvector_dimension: 4,
+                num_bits: 2,
+            },
+            ProductQuantizerBuilderConfig {
+                max_iteration: 100,
+                batch_size: 4,
+                use_annealing: false,
+            },
+        );
+        pqb.set_balance_subspace_variance(true);
+        for _ in 0..100 {
+            pqb.add(generate_random_vector(DIMENSION));
+        }
+
+        let temp_dir = tempdir::TempDir::new("product_quantizer_builder_balance_test")
+            .expect("Failed to create temporary directory");
+        let pq = pqb
+            .build(
+                temp_dir
+                    .path()
+                    .to_str()
+                    .expect("Failed to convert temporary directory path to string")
+                    .to_string(),
+            )
+            .expect("ProductQuantizer should be built");
+        let mut permutation = pq
+            .dimension_permutation
+            .clone()
+            .expect("Permutation should be set");
+        permutation.sort();
+        assert_eq!(permutation, (0..DIMENSION).collect::<Vec<usize>>());
+        assert_eq!(pq.quantize(&generate_random_vector(DIMENSION)).len(), 4);
+    }
+
     #[test]
     fn test_product_quantizer_distance() {
         const DIMENSION: usize = 128;
@@ -155,6 +398,7 @@ mod tests {
             ProductQuantizerBuilderConfig {
                 max_iteration: 1000,
                 batch_size: 4,
+                use_annealing: false,
             },
         );
         // Generate 10000 vectors of f32, dimension 128
@@ -183,4 +427,73 @@ mod tests {
         assert!((dist_simd - dist_scalar).abs() < epsilon);
         assert!((dist_stream - dist_scalar).abs() < epsilon);
     }
+
+    #[test]
+    fn test_product_quantizer_builder_annealing() {
+        let temp_dir = tempdir::TempDir::new("product_quantizer_builder_annealing_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+
+        // Tight clusters of very different sizes
+        const DIMENSION: usize = 8;
+        let cluster_centers: Vec<Vec<f32>> = (0..24)
+            .map(|_| {
+                generate_random_vector(DIMENSION)
+                    .iter()
+                    .map(|x| x * 100.0)
+                    .collect()
+            })
+            .collect();
+        let mut dataset = vec![];
+        for (i, center) in cluster_centers.iter().enumerate() {
+            for _ in 0..(10 + i * i) {
+                let noise = generate_random_vector(DIMENSION);
+                dataset.push(
+                    center
+                        .iter()
+                        .zip(noise)
+                        .map(|(c, n)| c + n * 0.1)
+                        .collect::<Vec<f32>>(),
+                );
+            }
+        }
+
+        let mut pqb = ProductQuantizerBuilder::<L2DistanceCalculator>::new(
+            ProductQuantizerConfig {
+                dimension: DIMENSION,
+                subvector_dimension: 4,
+                num_bits: 4,
+            },
+            ProductQuantizerBuilderConfig {
+                max_iteration: 20,
+                batch_size: 64,
+                use_annealing: true,
+            },
+        );
+        for vector in dataset.iter() {
+            pqb.add(vector.clone());
+        }
+        let pq = pqb.build(base_directory).unwrap();
+
+        // Error of quantizing every vector to the mean of the dataset
+        let mean = (0..DIMENSION)
+            .map(|dim| dataset.iter().map(|v| v[dim]).sum::<f32>() / dataset.len() as f32)
+            .collect::<Vec<f32>>();
+        let variance = dataset
+            .iter()
+            .map(|v| L2DistanceCalculator::calculate_squared(v, &mean))
+            .sum::<f32>()
+            / dataset.len() as f32;
+
+        // 16 centroids per subspace can cover the largest of the 24 clusters, leaving only the
+        // smallest ones merged with their neighbors
+        let annealing_mse = pq.mean_squared_error(&dataset);
+        assert!(annealing_mse > 0.0);
+        assert!(
+            annealing_mse < 0.2 * variance,
+            "Annealing error {} for a variance of {}",
+            annealing_mse,
+            variance
+        );
+    }
 }
@@ -1,6 +1,7 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use utils::distance::l2::L2DistanceCalculatorImpl;
 
+use crate::pq::pq::AdcTable;
 use crate::typing::VectorT;
 
 pub trait Quantizer {
@@ -31,6 +32,42 @@ pub trait Quantizer {
     where
         Self: Sized;
 
+    /// Compute the distance between two original vectors, with the same metric and on the same
+    /// scale as `distance`, so that the two can be mixed. Defaults to the distance between their
+    /// quantized vectors.
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32
+    where
+        Self: Sized,
+    {
+        self.distance(
+            &self.quantize(a),
+            &self.quantize(b),
+            L2DistanceCalculatorImpl::StreamingSIMD,
+        )
+    }
+
+    /// Precomputes the distances from `query` to every codeword, so that distances to quantized
+    /// points become table lookups (asymmetric distance computation). Returns `None` for
+    /// quantizers without a codebook.
+    fn adc_table(&self, _query: &[f32]) -> Option<AdcTable> {
+        None
+    }
+
+    /// Compute the distance between the query of `table` and a quantized point. Only called with
+    /// tables returned by `adc_table`.
+    fn distance_with_adc_table(&self, _table: &AdcTable, _point: &[Self::QuantizedT]) -> Result<f32>
+    where
+        Self: Sized,
+    {
+        Err(anyhow!("Quantizer does not support ADC tables"))
+    }
+
+    /// Identifies the quantizer in caches of its ADC tables. Quantizers returning tables from
+    /// `adc_table` override it, tables of quantizers without an id are not cached.
+    fn instance_id(&self) -> Option<u64> {
+        None
+    }
+
     /// Read a quantizer
     fn read(dir: String) -> Result<Self>
     where
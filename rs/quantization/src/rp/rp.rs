@@ -0,0 +1,263 @@
+use std::marker::PhantomData;
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use ndarray_rand::rand_distr::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use utils::distance::l2::L2DistanceCalculatorImpl;
+use utils::DistanceCalculator;
+
+use crate::quantization::{Quantizer, WritableQuantizer};
+
+pub const RANDOM_PROJECTION_CONFIG_NAME: &str = "random_projection_config.yaml";
+pub const PROJECTION_MATRIX_NAME: &str = "projection_matrix";
+
+/// Reduces the dimension of vectors by multiplying them with a Gaussian random matrix. Entries are
+/// drawn from N(0, 1 / output_dimension), so distances are preserved in expectation
+/// (Johnson-Lindenstrauss). The matrix is written next to the config, so that indexes don't
+/// depend on the random number generator staying the same across versions.
+pub struct RandomProjection<D: DistanceCalculator> {
+    input_dimension: usize,
+    output_dimension: usize,
+    seed: u64,
+
+    // `output_dimension` rows of `input_dimension` values, in row-major order
+    matrix: Vec<f32>,
+
+    _marker: PhantomData<D>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RandomProjectionConfig {
+    pub input_dimension: usize,
+    pub output_dimension: usize,
+    pub seed: u64,
+}
+
+impl<D: DistanceCalculator> RandomProjection<D> {
+    pub fn new(input_dimension: usize, output_dimension: usize, seed: u64) -> Result<Self> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let scale = 1.0 / (output_dimension as f32).sqrt();
+        let matrix = (0..input_dimension * output_dimension)
+            .map(|_| rng.sample::<f32, _>(StandardNormal) * scale)
+            .collect();
+        Self::with_matrix(input_dimension, output_dimension, seed, matrix)
+    }
+
+    fn with_matrix(
+        input_dimension: usize,
+        output_dimension: usize,
+        seed: u64,
+        matrix: Vec<f32>,
+    ) -> Result<Self> {
+        if output_dimension == 0 || output_dimension > input_dimension {
+            return Err(Error::msg(format!(
+                "Output dimension {} must be between 1 and the input dimension {}",
+                output_dimension, input_dimension
+            )));
+        }
+        if matrix.len() != input_dimension * output_dimension {
+            return Err(Error::msg("Projection matrix doesn't match the dimensions"));
+        }
+        Ok(Self {
+            input_dimension,
+            output_dimension,
+            seed,
+            matrix,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn input_dimension(&self) -> usize {
+        self.input_dimension
+    }
+
+    /// Multiplies `value` with the projection matrix.
+    pub fn project(&self, value: &[f32]) -> Vec<f32> {
+        self.matrix
+            .chunks_exact(self.input_dimension)
+            .map(|row| row.iter().zip(value).map(|(m, v)| m * v).sum())
+            .collect()
+    }
+}
+
+impl<D: DistanceCalculator> Quantizer for RandomProjection<D> {
+    type QuantizedT = f32;
+
+    fn quantize(&self, value: &[f32]) -> Vec<f32> {
+        self.project(value)
+    }
+
+    fn quantized_dimension(&self) -> usize {
+        self.output_dimension
+    }
+
+    /// The projection isn't invertible: this multiplies with the transposed matrix, which only
+    /// approximates the original vector.
+    fn original_vector(&self, quantized_vector: &[f32]) -> Vec<f32> {
+        let mut result = vec![0.0; self.input_dimension];
+        for (row, q) in self
+            .matrix
+            .chunks_exact(self.input_dimension)
+            .zip(quantized_vector)
+        {
+            result.iter_mut().zip(row).for_each(|(r, m)| *r += m * q);
+        }
+        result
+    }
+
+    fn distance(&self, query: &[f32], point: &[f32], _implem: L2DistanceCalculatorImpl) -> f32 {
+        D::calculate(query, point)
+    }
+
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        D::calculate(a, b)
+    }
+
+    fn read(dir: String) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let config_path = Path::new(&dir).join(RANDOM_PROJECTION_CONFIG_NAME);
+        if !config_path.is_file() {
+            return Err(Error::msg("Config file does not exist"));
+        }
+        let config: RandomProjectionConfig = serde_yaml::from_slice(&std::fs::read(config_path)?)?;
+        let matrix = std::fs::read(Path::new(&dir).join(PROJECTION_MATRIX_NAME))?
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        Self::with_matrix(
+            config.input_dimension,
+            config.output_dimension,
+            config.seed,
+            matrix,
+        )
+    }
+}
+
+impl<D: DistanceCalculator> WritableQuantizer for RandomProjection<D> {
+    fn write_to_directory(&self, base_directory: &str) -> Result<()> {
+        let config = RandomProjectionConfig {
+            input_dimension: self.input_dimension,
+            output_dimension: self.output_dimension,
+            seed: self.seed,
+        };
+        std::fs::write(
+            Path::new(base_directory).join(RANDOM_PROJECTION_CONFIG_NAME),
+            serde_yaml::to_string(&config)?,
+        )?;
+        let matrix_buffer = self
+            .matrix
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        std::fs::write(
+            Path::new(base_directory).join(PROJECTION_MATRIX_NAME),
+            matrix_buffer,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::evaluation::mean_recall_at_k;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+
+    fn top_k(query: &[f32], points: &[Vec<f32>], k: usize) -> Vec<u128> {
+        let mut distances = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (L2DistanceCalculator::calculate(query, p), i as u128))
+            .collect::<Vec<_>>();
+        distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+        distances.into_iter().take(k).map(|(_, i)| i).collect()
+    }
+
+    #[test]
+    fn test_random_projection() {
+        let projection = RandomProjection::<L2DistanceCalculator>::new(64, 16, 42).unwrap();
+        assert_eq!(projection.quantized_dimension(), 16);
+
+        // The matrix only depends on the seed
+        let value = generate_random_vector(64);
+        let same_seed = RandomProjection::<L2DistanceCalculator>::new(64, 16, 42).unwrap();
+        assert_eq!(projection.project(&value), same_seed.project(&value));
+        let other_seed = RandomProjection::<L2DistanceCalculator>::new(64, 16, 43).unwrap();
+        assert_ne!(projection.project(&value), other_seed.project(&value));
+
+        let temp_dir = TempDir::new("test_random_projection").unwrap();
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        projection.write_to_directory(&base_directory).unwrap();
+        let read_projection =
+            RandomProjection::<L2DistanceCalculator>::read(base_directory).unwrap();
+        assert_eq!(read_projection.input_dimension(), 64);
+        assert_eq!(read_projection.quantized_dimension(), 16);
+        assert_eq!(read_projection.project(&value), projection.project(&value));
+
+        assert!(RandomProjection::<L2DistanceCalculator>::new(64, 0, 42).is_err());
+        assert!(RandomProjection::<L2DistanceCalculator>::new(64, 128, 42).is_err());
+    }
+
+    #[test]
+    fn test_random_projection_recall() {
+        let input_dimension = 768;
+        let num_vectors = 10000;
+        let num_queries = 100;
+        let k = 10;
+
+        // Embeddings have a low intrinsic dimension, which is what random projections preserve.
+        // Vectors are random combinations of a few directions, with some noise.
+        let mut rng = StdRng::seed_from_u64(0);
+        let num_directions = 4;
+        let directions = (0..num_directions * input_dimension)
+            .map(|_| rng.sample::<f32, _>(StandardNormal))
+            .collect::<Vec<_>>();
+        let generate = |rng: &mut StdRng| {
+            let weights = (0..num_directions)
+                .map(|_| rng.sample::<f32, _>(StandardNormal))
+                .collect::<Vec<_>>();
+            let mut vector = (0..input_dimension)
+                .map(|_| 0.1 * rng.sample::<f32, _>(StandardNormal))
+                .collect::<Vec<_>>();
+            for (direction, weight) in directions.chunks_exact(input_dimension).zip(&weights) {
+                vector
+                    .iter_mut()
+                    .zip(direction)
+                    .for_each(|(v, d)| *v += weight * d);
+            }
+            vector
+        };
+        let vectors = (0..num_vectors)
+            .map(|_| generate(&mut rng))
+            .collect::<Vec<_>>();
+        let queries = (0..num_queries)
+            .map(|_| generate(&mut rng))
+            .collect::<Vec<_>>();
+
+        let projection =
+            RandomProjection::<L2DistanceCalculator>::new(input_dimension, 128, 42).unwrap();
+        let projected = vectors
+            .iter()
+            .map(|v| projection.project(v))
+            .collect::<Vec<_>>();
+
+        let ground_truth = queries
+            .iter()
+            .map(|q| top_k(q, &vectors, k))
+            .collect::<Vec<_>>();
+        let results = queries
+            .iter()
+            .map(|q| top_k(&projection.project(q), &projected, k))
+            .collect::<Vec<_>>();
+        let recall = mean_recall_at_k(&ground_truth, &results, k);
+        assert!(recall > 0.75, "recall@10 is {}", recall);
+    }
+}
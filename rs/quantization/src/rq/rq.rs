@@ -0,0 +1,308 @@
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use utils::distance::l2::L2DistanceCalculatorImpl;
+use utils::mem::next_instance_id;
+use utils::DistanceCalculator;
+
+use crate::pq::pq::{AdcTable, ProductQuantizer, ProductQuantizerConfig, ProductQuantizerReader};
+use crate::quantization::{Quantizer, WritableQuantizer};
+
+pub const RESIDUAL_QUANTIZER_CONFIG_NAME: &str = "residual_quantizer_config.yaml";
+pub const FIRST_STAGE_DIRECTORY_NAME: &str = "first_stage";
+pub const SECOND_STAGE_DIRECTORY_NAME: &str = "second_stage";
+
+/// Code words of the first stage followed by the code words of the second stage.
+pub type RqCode = Vec<u8>;
+
+/// Two-stage residual quantizer. The first product quantizer quantizes the vector, the second one
+/// quantizes the residual, i.e. the vector minus its first-stage reconstruction.
+pub struct ResidualQuantizer<D: DistanceCalculator> {
+    pub first_stage: ProductQuantizer<D>,
+    pub second_stage: ProductQuantizer<D>,
+    pub base_directory: String,
+
+    // Identifies the quantizer in caches of its ADC tables
+    id: u64,
+}
+
+// Both stages share the same configuration.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResidualQuantizerConfig {
+    pub dimension: usize,
+    pub subvector_dimension: usize,
+    pub num_bits: u8,
+}
+
+impl ResidualQuantizerConfig {
+    pub fn validate(&self) -> Result<()> {
+        self.stage_config().validate()
+    }
+
+    pub fn stage_config(&self) -> ProductQuantizerConfig {
+        ProductQuantizerConfig {
+            dimension: self.dimension,
+            subvector_dimension: self.subvector_dimension,
+            num_bits: self.num_bits,
+        }
+    }
+}
+
+pub struct ResidualQuantizerReader {
+    base_directory: String,
+}
+
+impl ResidualQuantizerReader {
+    pub fn new(base_directory: String) -> Self {
+        Self { base_directory }
+    }
+
+    pub fn read<D: DistanceCalculator>(&self) -> Result<ResidualQuantizer<D>> {
+        let config_path = Path::new(&self.base_directory).join(RESIDUAL_QUANTIZER_CONFIG_NAME);
+        if !config_path.is_file() {
+            return Err(Error::msg("Config file does not exist"));
+        }
+        let config: ResidualQuantizerConfig = serde_yaml::from_slice(&std::fs::read(config_path)?)?;
+        config.validate()?;
+
+        let read_stage = |name: &str| -> Result<ProductQuantizer<D>> {
+            let directory = format!("{}/{}", self.base_directory, name);
+            let stage = ProductQuantizerReader::new(directory).read::<D>()?;
+            if stage.dimension != config.dimension
+                || stage.subvector_dimension != config.subvector_dimension
+                || stage.num_bits != config.num_bits
+            {
+                return Err(Error::msg(format!(
+                    "Stage {} does not match the residual quantizer config",
+                    name
+                )));
+            }
+            Ok(stage)
+        };
+        ResidualQuantizer::new(
+            read_stage(FIRST_STAGE_DIRECTORY_NAME)?,
+            read_stage(SECOND_STAGE_DIRECTORY_NAME)?,
+            self.base_directory.clone(),
+        )
+    }
+}
+
+impl<D: DistanceCalculator> ResidualQuantizer<D> {
+    pub fn new(
+        first_stage: ProductQuantizer<D>,
+        second_stage: ProductQuantizer<D>,
+        base_directory: String,
+    ) -> Result<Self> {
+        if first_stage.dimension != second_stage.dimension {
+            return Err(Error::msg("Both stages need to have the same dimension."));
+        }
+        Ok(Self {
+            first_stage,
+            second_stage,
+            base_directory,
+            id: next_instance_id(),
+        })
+    }
+
+    /// Quantizes `value` with the first stage, then quantizes the residual with the second stage.
+    pub fn encode(&self, value: &[f32]) -> RqCode {
+        let mut code = self.first_stage.quantize(value);
+        let residual = Self::residual(value, &self.first_stage.original_vector(&code));
+        code.extend(self.second_stage.quantize(&residual));
+        code
+    }
+
+    /// Reconstructs a vector by summing the reconstructions of both stages.
+    pub fn decode(&self, code: &[u8]) -> Vec<f32> {
+        let (first_code, second_code) = code.split_at(self.first_stage.quantized_dimension());
+        let mut result = self.first_stage.original_vector(first_code);
+        result
+            .iter_mut()
+            .zip(self.second_stage.original_vector(second_code))
+            .for_each(|(x, r)| *x += r);
+        result
+    }
+
+    /// Computes the ADC table for `query`, chaining the tables of both stages. The first-stage
+    /// table is computed from the query and the second-stage table from the query residual `r`
+    /// after its own first-stage quantization, using
+    /// `|q - c1 - c2|^2 = |q - c1|^2 + |r - c2|^2 - |r|^2` when `c1` is the query's centroid.
+    /// Lookups are exact for points that share their first-stage code with the query, and
+    /// approximate otherwise.
+    pub fn compute_adc_table(&self, query: &[f32]) -> AdcTable {
+        let first_code = self.first_stage.quantize(query);
+        let residual = Self::residual(query, &self.first_stage.original_vector(&first_code));
+        let residual_norm: f32 = residual.iter().map(|x| x * x).sum();
+        AdcTable::chain(
+            vec![
+                self.first_stage.compute_adc_table(query),
+                self.second_stage.compute_adc_table(&residual),
+            ],
+            -residual_norm,
+        )
+    }
+
+    pub fn config(&self) -> ResidualQuantizerConfig {
+        ResidualQuantizerConfig {
+            dimension: self.first_stage.dimension,
+            subvector_dimension: self.first_stage.subvector_dimension,
+            num_bits: self.first_stage.num_bits,
+        }
+    }
+
+    fn residual(value: &[f32], reconstruction: &[f32]) -> Vec<f32> {
+        value
+            .iter()
+            .zip(reconstruction.iter())
+            .map(|(x, y)| x - y)
+            .collect()
+    }
+}
+
+impl<D: DistanceCalculator> Quantizer for ResidualQuantizer<D> {
+    type QuantizedT = u8;
+
+    fn quantize(&self, value: &[f32]) -> Vec<u8> {
+        self.encode(value)
+    }
+
+    fn quantized_dimension(&self) -> usize {
+        self.first_stage.quantized_dimension() + self.second_stage.quantized_dimension()
+    }
+
+    fn original_vector(&self, quantized_vector: &[u8]) -> Vec<f32> {
+        self.decode(quantized_vector)
+    }
+
+    /// Residual codes of different stages live in different spaces, so points are compared on
+    /// their reconstructions.
+    fn distance(&self, a: &[u8], b: &[u8], _implem: L2DistanceCalculatorImpl) -> f32 {
+        D::calculate(&self.decode(a), &self.decode(b))
+    }
+
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        D::calculate(a, b)
+    }
+
+    fn adc_table(&self, query: &[f32]) -> Option<AdcTable> {
+        Some(self.compute_adc_table(query))
+    }
+
+    fn distance_with_adc_table(&self, table: &AdcTable, point: &[u8]) -> Result<f32> {
+        Ok(table.lookup(point))
+    }
+
+    fn instance_id(&self) -> Option<u64> {
+        Some(self.id)
+    }
+
+    fn read(dir: String) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        ResidualQuantizerReader::new(dir).read()
+    }
+}
+
+impl<D: DistanceCalculator> WritableQuantizer for ResidualQuantizer<D> {
+    fn write_to_directory(&self, base_directory: &str) -> Result<()> {
+        for (name, stage) in [
+            (FIRST_STAGE_DIRECTORY_NAME, &self.first_stage),
+            (SECOND_STAGE_DIRECTORY_NAME, &self.second_stage),
+        ] {
+            let directory = format!("{}/{}", base_directory, name);
+            std::fs::create_dir_all(&directory)?;
+            stage.write_to_directory(&directory)?;
+        }
+
+        std::fs::write(
+            Path::new(base_directory).join(RESIDUAL_QUANTIZER_CONFIG_NAME),
+            serde_yaml::to_string(&self.config())?,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::CalculateSquared;
+
+    use super::*;
+
+    fn new_test_rq() -> ResidualQuantizer<L2DistanceCalculator> {
+        // 2 subvectors of dimension 2, 1 bit each.
+        let first_codebook = vec![
+            0.0, 0.0, 4.0, 4.0, // subvector 0
+            0.0, 0.0, 8.0, 8.0, // subvector 1
+        ];
+        let second_codebook = vec![
+            0.0, 0.0, 1.0, 1.0, // subvector 0
+            0.0, 0.0, -1.0, -1.0, // subvector 1
+        ];
+        let first_stage = ProductQuantizer::new(4, 2, 1, first_codebook, "".to_string()).unwrap();
+        let second_stage = ProductQuantizer::new(4, 2, 1, second_codebook, "".to_string()).unwrap();
+        ResidualQuantizer::new(first_stage, second_stage, "".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_residual_quantizer() {
+        let rq = new_test_rq();
+        assert_eq!(rq.quantized_dimension(), 4);
+
+        let value = vec![5.0, 5.0, 7.0, 7.0];
+        let code = rq.encode(&value);
+        assert_eq!(code, vec![1, 1, 1, 1]);
+        assert_eq!(rq.decode(&code), value);
+        assert_eq!(rq.original_vector(&rq.quantize(&value)), value);
+
+        let other = rq.encode(&[0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(other, vec![0, 0, 0, 0]);
+        let expected = L2DistanceCalculator::calculate(&value, &[0.0, 0.0, 0.0, 0.0]);
+        assert!(
+            (rq.distance(&code, &other, L2DistanceCalculatorImpl::Scalar) - expected).abs() < 1e-5
+        );
+    }
+
+    #[test]
+    fn test_residual_quantizer_adc() {
+        let rq = new_test_rq();
+        let query = vec![5.0, 5.0, 7.0, 7.0];
+        let table = rq.compute_adc_table(&query);
+
+        // The query residual is exactly represented by the second stage.
+        assert!(table.lookup(&rq.encode(&query)).abs() < 1e-5);
+
+        // Exact for points sharing the first-stage code of the query.
+        let code = vec![1, 1, 0, 0];
+        let expected = L2DistanceCalculator::calculate_squared(&query, &rq.decode(&code));
+        assert!((table.lookup(&code) - expected).abs() < 1e-5);
+
+        // The quantizer serves the same table through the trait
+        let table = rq.adc_table(&query).unwrap();
+        assert!((rq.distance_with_adc_table(&table, &code).unwrap() - expected).abs() < 1e-5);
+        assert!(rq.instance_id().is_some());
+        assert_ne!(rq.instance_id(), new_test_rq().instance_id());
+    }
+
+    #[test]
+    fn test_residual_quantizer_read_write() {
+        let temp_dir = tempdir::TempDir::new("residual_quantizer_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+
+        let rq = new_test_rq();
+        rq.write_to_directory(&base_directory)
+            .expect("Failed to write the quantizer");
+
+        let new_rq = ResidualQuantizerReader::new(base_directory)
+            .read::<L2DistanceCalculator>()
+            .expect("Failed to read the quantizer");
+        assert_eq!(new_rq.first_stage.codebook, rq.first_stage.codebook);
+        assert_eq!(new_rq.second_stage.codebook, rq.second_stage.codebook);
+        assert_eq!(new_rq.config().subvector_dimension, 2);
+        let value = vec![5.0, 5.0, 7.0, 7.0];
+        assert_eq!(new_rq.encode(&value), rq.encode(&value));
+    }
+}
@@ -0,0 +1,153 @@
+use std::marker::PhantomData;
+
+use anyhow::Result;
+use log::debug;
+use utils::DistanceCalculator;
+
+use crate::pq::pq_builder::{ProductQuantizerBuilder, ProductQuantizerBuilderConfig};
+use crate::quantization::Quantizer;
+use crate::rq::rq::{ResidualQuantizer, ResidualQuantizerConfig};
+
+pub struct ResidualQuantizerBuilder<D: DistanceCalculator> {
+    rq_config: ResidualQuantizerConfig,
+    builder_config: ProductQuantizerBuilderConfig,
+    pub dataset: Vec<Vec<f32>>,
+
+    _marker: PhantomData<D>,
+}
+
+impl<D: DistanceCalculator> ResidualQuantizerBuilder<D> {
+    /// Create a new ResidualQuantizerBuilder
+    pub fn new(
+        config: ResidualQuantizerConfig,
+        builder_config: ProductQuantizerBuilderConfig,
+    ) -> Self {
+        Self {
+            rq_config: config,
+            builder_config,
+            dataset: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add a new vector to the dataset for training
+    pub fn add(&mut self, data: Vec<f32>) {
+        self.dataset.push(data);
+    }
+
+    fn new_stage_builder(&self) -> ProductQuantizerBuilder<D> {
+        ProductQuantizerBuilder::new(
+            self.rq_config.stage_config(),
+            ProductQuantizerBuilderConfig {
+                max_iteration: self.builder_config.max_iteration,
+                batch_size: self.builder_config.batch_size,
+                use_annealing: self.builder_config.use_annealing,
+            },
+        )
+    }
+
+    /// Trains the first stage on the dataset, then the second stage on the residuals of the
+    /// first stage, and returns the residual quantizer
+    pub fn build(&mut self, base_directory: String) -> Result<ResidualQuantizer<D>> {
+        self.rq_config.validate()?;
+
+        let mut first_stage_builder = self.new_stage_builder();
+        first_stage_builder.dataset = std::mem::take(&mut self.dataset);
+        let first_stage = first_stage_builder.build(base_directory.clone())?;
+        debug!("Finished training the first stage");
+
+        let mut second_stage_builder = self.new_stage_builder();
+        for point in first_stage_builder.dataset.iter() {
+            let reconstruction = first_stage.original_vector(&first_stage.quantize(point));
+            second_stage_builder.add(
+                point
+                    .iter()
+                    .zip(reconstruction.iter())
+                    .map(|(x, y)| x - y)
+                    .collect(),
+            );
+        }
+        let second_stage = second_stage_builder.build(base_directory.clone())?;
+        debug!("Finished training the second stage");
+
+        ResidualQuantizer::new(first_stage, second_stage, base_directory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::test_utils::generate_random_vector;
+    use utils::CalculateSquared;
+
+    use super::*;
+    use crate::pq::pq::ProductQuantizerConfig;
+
+    fn mean_squared_error<Q: Quantizer<QuantizedT = u8>>(q: &Q, dataset: &[Vec<f32>]) -> f32 {
+        dataset
+            .iter()
+            .map(|v| L2DistanceCalculator::calculate_squared(v, &q.original_vector(&q.quantize(v))))
+            .sum::<f32>()
+            / dataset.len() as f32
+    }
+
+    #[test]
+    fn test_residual_quantizer_lower_mse_than_product_quantizer() {
+        const DIMENSION: usize = 16;
+        // Every block of 4 dimensions is [a, b, a, b]. A single PQ stage spends its code words on
+        // [a, b] and [a, b] independently, while RQ refines [a, b] in the second stage.
+        let dataset: Vec<Vec<f32>> = (0..2000)
+            .map(|_| {
+                generate_random_vector(DIMENSION / 2)
+                    .chunks_exact(2)
+                    .flat_map(|ab| [ab[0], ab[1], ab[0], ab[1]])
+                    .collect()
+            })
+            .collect();
+        let temp_dir = tempdir::TempDir::new("residual_quantizer_builder_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+
+        // Both quantizers use 8 code words of 4 bits.
+        let mut pq_builder = ProductQuantizerBuilder::<L2DistanceCalculator>::new(
+            ProductQuantizerConfig {
+                dimension: DIMENSION,
+                subvector_dimension: 2,
+                num_bits: 4,
+            },
+            ProductQuantizerBuilderConfig {
+                max_iteration: 100,
+                batch_size: 64,
+                use_annealing: false,
+            },
+        );
+        let mut rq_builder = ResidualQuantizerBuilder::<L2DistanceCalculator>::new(
+            ResidualQuantizerConfig {
+                dimension: DIMENSION,
+                subvector_dimension: 4,
+                num_bits: 4,
+            },
+            ProductQuantizerBuilderConfig {
+                max_iteration: 100,
+                batch_size: 64,
+                use_annealing: false,
+            },
+        );
+        for v in dataset.iter() {
+            pq_builder.add(v.clone());
+            rq_builder.add(v.clone());
+        }
+        let pq = pq_builder.build(base_directory.clone()).unwrap();
+        let rq = rq_builder.build(base_directory).unwrap();
+        assert_eq!(pq.quantized_dimension(), rq.quantized_dimension());
+
+        let pq_mse = mean_squared_error(&pq, &dataset);
+        let rq_mse = mean_squared_error(&rq, &dataset);
+        assert!(
+            rq_mse < pq_mse,
+            "RQ MSE {} should be lower than PQ MSE {}",
+            rq_mse,
+            pq_mse
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use std::marker::PhantomData;
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use utils::distance::l2::L2DistanceCalculatorImpl;
+use utils::DistanceCalculator;
+
+use crate::quantization::{Quantizer, WritableQuantizer};
+
+pub const SCALAR_QUANTIZER_CONFIG_NAME: &str = "scalar_quantizer_config.yaml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScalarQuantizerConfig {
+    pub dimension: usize,
+    // Number of bits of each code, at most 8. Codes are stored one byte per dimension.
+    pub num_bits: u8,
+}
+
+impl ScalarQuantizerConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.num_bits == 0 || self.num_bits > 8 {
+            return Err(Error::msg(format!(
+                "Scalar quantizer supports 1 to 8 bits, got {}",
+                self.num_bits
+            )));
+        }
+        Ok(())
+    }
+
+    // Largest code, e.g. 255 for 8 bits.
+    fn max_code(&self) -> f32 {
+        ((1u32 << self.num_bits) - 1) as f32
+    }
+}
+
+/// Scalar quantizer mapping every dimension linearly to `num_bits` bits, between the
+/// per-dimension min and max seen during training. Each dimension is stored in its own byte.
+pub struct ScalarQuantizer<D: DistanceCalculator> {
+    pub config: ScalarQuantizerConfig,
+    pub min_values: Vec<f32>,
+    pub max_values: Vec<f32>,
+    pub base_directory: String,
+
+    // Width of a quantization step, for each dimension.
+    steps: Vec<f32>,
+
+    _marker: PhantomData<D>,
+}
+
+// Content of the config file: the config along with the trained ranges.
+#[derive(Serialize, Deserialize, Debug)]
+struct ScalarQuantizerFile {
+    #[serde(flatten)]
+    config: ScalarQuantizerConfig,
+    min_values: Vec<f32>,
+    max_values: Vec<f32>,
+}
+
+pub struct ScalarQuantizerReader {
+    base_directory: String,
+}
+
+impl ScalarQuantizerReader {
+    pub fn new(base_directory: String) -> Self {
+        Self { base_directory }
+    }
+
+    pub fn read<D: DistanceCalculator>(&self) -> Result<ScalarQuantizer<D>> {
+        let config_path = Path::new(&self.base_directory).join(SCALAR_QUANTIZER_CONFIG_NAME);
+        if !config_path.is_file() {
+            return Err(Error::msg("Config file does not exist"));
+        }
+        let file: ScalarQuantizerFile = serde_yaml::from_slice(&std::fs::read(config_path)?)?;
+        ScalarQuantizer::new(
+            file.config,
+            file.min_values,
+            file.max_values,
+            self.base_directory.clone(),
+        )
+    }
+}
+
+impl<D: DistanceCalculator> ScalarQuantizer<D> {
+    pub fn new(
+        config: ScalarQuantizerConfig,
+        min_values: Vec<f32>,
+        max_values: Vec<f32>,
+        base_directory: String,
+    ) -> Result<Self> {
+        config.validate()?;
+        if min_values.len() != config.dimension || max_values.len() != config.dimension {
+            return Err(Error::msg(
+                "Min and max values need to have one value per dimension.",
+            ));
+        }
+        let max_code = config.max_code();
+        let steps = min_values
+            .iter()
+            .zip(max_values.iter())
+            .map(|(min, max)| (max - min).max(0.0) / max_code)
+            .collect();
+        Ok(Self {
+            config,
+            min_values,
+            max_values,
+            base_directory,
+            steps,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn encode(&self, value: &[f32]) -> Vec<u8> {
+        let max_code = self.config.max_code();
+        value
+            .iter()
+            .take(self.config.dimension)
+            .enumerate()
+            .map(|(dim, v)| {
+                if self.steps[dim] == 0.0 {
+                    return 0;
+                }
+                ((v - self.min_values[dim]) / self.steps[dim])
+                    .round()
+                    .clamp(0.0, max_code) as u8
+            })
+            .collect()
+    }
+
+    pub fn decode(&self, code: &[u8]) -> Vec<f32> {
+        code.iter()
+            .enumerate()
+            .map(|(dim, &c)| self.min_values[dim] + c as f32 * self.steps[dim])
+            .collect()
+    }
+}
+
+impl<D: DistanceCalculator> Quantizer for ScalarQuantizer<D> {
+    type QuantizedT = u8;
+
+    fn quantize(&self, value: &[f32]) -> Vec<u8> {
+        self.encode(value)
+    }
+
+    fn quantized_dimension(&self) -> usize {
+        self.config.dimension
+    }
+
+    fn original_vector(&self, quantized_vector: &[u8]) -> Vec<f32> {
+        self.decode(quantized_vector)
+    }
+
+    /// Same distance as `NoQuantizer` between the reconstructed vectors.
+    fn distance(&self, a: &[u8], b: &[u8], _implem: L2DistanceCalculatorImpl) -> f32 {
+        D::calculate(&self.decode(a), &self.decode(b))
+    }
+
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        D::calculate(a, b)
+    }
+
+    fn read(dir: String) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        ScalarQuantizerReader::new(dir).read()
+    }
+}
+
+impl<D: DistanceCalculator> WritableQuantizer for ScalarQuantizer<D> {
+    fn write_to_directory(&self, base_directory: &str) -> Result<()> {
+        let file = ScalarQuantizerFile {
+            config: self.config.clone(),
+            min_values: self.min_values.clone(),
+            max_values: self.max_values.clone(),
+        };
+        std::fs::write(
+            Path::new(base_directory).join(SCALAR_QUANTIZER_CONFIG_NAME),
+            serde_yaml::to_string(&file)?,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::distance::l2::L2DistanceCalculator;
+
+    use super::*;
+
+    fn config(dimension: usize, num_bits: u8) -> ScalarQuantizerConfig {
+        ScalarQuantizerConfig {
+            dimension,
+            num_bits,
+        }
+    }
+
+    #[test]
+    fn test_scalar_quantizer() {
+        let sq = ScalarQuantizer::<L2DistanceCalculator>::new(
+            config(3, 8),
+            vec![0.0, -1.0, 2.0],
+            vec![255.0, 254.0, 2.0],
+            "".to_string(),
+        )
+        .unwrap();
+        assert_eq!(sq.quantized_dimension(), 3);
+
+        // Dimension 2 has a single value -> 0
+        let code = sq.encode(&[3.0, 254.0, 5.0]);
+        assert_eq!(code, vec![3, 255, 0]);
+        assert_eq!(sq.decode(&code), vec![3.0, 254.0, 2.0]);
+
+        // Out of range values are clamped
+        assert_eq!(sq.encode(&[-5.0, 300.0, 0.0]), vec![0, 255, 0]);
+
+        // Fewer bits, fewer codes
+        let sq = ScalarQuantizer::<L2DistanceCalculator>::new(
+            config(1, 2),
+            vec![0.0],
+            vec![3.0],
+            "".to_string(),
+        )
+        .unwrap();
+        assert_eq!(sq.encode(&[2.2]), vec![2]);
+        assert_eq!(sq.encode(&[10.0]), vec![3]);
+
+        assert!(ScalarQuantizer::<L2DistanceCalculator>::new(
+            config(1, 9),
+            vec![0.0],
+            vec![1.0],
+            "".to_string()
+        )
+        .is_err());
+        assert!(ScalarQuantizer::<L2DistanceCalculator>::new(
+            config(2, 8),
+            vec![0.0],
+            vec![1.0],
+            "".to_string()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_scalar_quantizer_distance() {
+        let sq = ScalarQuantizer::<L2DistanceCalculator>::new(
+            config(2, 8),
+            vec![0.0, 0.0],
+            vec![255.0, 255.0],
+            "".to_string(),
+        )
+        .unwrap();
+        let a = sq.encode(&[0.0, 3.0]);
+        let b = sq.encode(&[4.0, 0.0]);
+        for implem in [
+            L2DistanceCalculatorImpl::Scalar,
+            L2DistanceCalculatorImpl::SIMD,
+            L2DistanceCalculatorImpl::StreamingSIMD,
+        ] {
+            assert!((sq.distance(&a, &b, implem) - 5.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_scalar_quantizer_read_write() {
+        let temp_dir = tempdir::TempDir::new("scalar_quantizer_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+
+        let sq = ScalarQuantizer::<L2DistanceCalculator>::new(
+            config(4, 8),
+            vec![0.0, -1.5, 2.0, 0.1],
+            vec![1.0, 1.5, 3.0, 0.7],
+            base_directory.clone(),
+        )
+        .unwrap();
+        sq.write_to_directory(&base_directory)
+            .expect("Failed to write the quantizer");
+
+        let new_sq = ScalarQuantizer::<L2DistanceCalculator>::read(base_directory)
+            .expect("Failed to read the quantizer");
+        assert_eq!(new_sq.config, sq.config);
+        assert_eq!(new_sq.min_values, sq.min_values);
+        assert_eq!(new_sq.max_values, sq.max_values);
+        let value = vec![0.5, 0.0, 2.5, 0.3];
+        assert_eq!(new_sq.encode(&value), sq.encode(&value));
+    }
+}
@@ -0,0 +1,90 @@
+use std::marker::PhantomData;
+
+use anyhow::{Error, Result};
+use utils::DistanceCalculator;
+
+use crate::sq::sq::{ScalarQuantizer, ScalarQuantizerConfig};
+
+pub struct ScalarQuantizerBuilder<D: DistanceCalculator> {
+    config: ScalarQuantizerConfig,
+
+    // Per-dimension min and max of the vectors added so far.
+    min_values: Vec<f32>,
+    max_values: Vec<f32>,
+    num_vectors: usize,
+
+    _marker: PhantomData<D>,
+}
+
+impl<D: DistanceCalculator> ScalarQuantizerBuilder<D> {
+    /// Create a new ScalarQuantizerBuilder
+    pub fn new(config: ScalarQuantizerConfig) -> Self {
+        let dimension = config.dimension;
+        Self {
+            config,
+            min_values: vec![f32::MAX; dimension],
+            max_values: vec![f32::MIN; dimension],
+            num_vectors: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add a new vector to the training set. Only the per-dimension ranges are kept.
+    pub fn add(&mut self, data: Vec<f32>) {
+        for (dim, value) in data.iter().take(self.config.dimension).enumerate() {
+            self.min_values[dim] = self.min_values[dim].min(*value);
+            self.max_values[dim] = self.max_values[dim].max(*value);
+        }
+        self.num_vectors += 1;
+    }
+
+    pub fn build(&mut self, base_directory: String) -> Result<ScalarQuantizer<D>> {
+        if self.num_vectors == 0 {
+            return Err(Error::msg("No training vectors for scalar quantizer"));
+        }
+        ScalarQuantizer::new(
+            self.config.clone(),
+            self.min_values.clone(),
+            self.max_values.clone(),
+            base_directory,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::quantization::Quantizer;
+
+    #[test]
+    fn test_scalar_quantizer_builder() {
+        const DIMENSION: usize = 64;
+        let mut builder =
+            ScalarQuantizerBuilder::<L2DistanceCalculator>::new(ScalarQuantizerConfig {
+                dimension: DIMENSION,
+                num_bits: 8,
+            });
+        assert!(builder.build("".to_string()).is_err());
+
+        let dataset: Vec<Vec<f32>> = (0..1000)
+            .map(|_| generate_random_vector(DIMENSION))
+            .collect();
+        for v in dataset.iter() {
+            builder.add(v.clone());
+        }
+        let sq = builder.build("".to_string()).unwrap();
+        assert_eq!(sq.quantized_dimension(), DIMENSION);
+
+        // Each dimension is off by at most half a step.
+        for v in dataset.iter() {
+            let reconstructed = sq.original_vector(&sq.quantize(v));
+            for dim in 0..DIMENSION {
+                let half_step = (sq.max_values[dim] - sq.min_values[dim]) / 510.0;
+                assert!((reconstructed[dim] - v[dim]).abs() <= half_step + 1e-6);
+            }
+        }
+    }
+}
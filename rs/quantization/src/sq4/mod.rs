@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception)]
+pub mod sq4;
+pub mod sq4_builder;
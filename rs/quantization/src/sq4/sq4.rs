@@ -0,0 +1,313 @@
+use std::marker::PhantomData;
+use std::path::Path;
+use std::simd::num::{SimdFloat, SimdUint};
+use std::simd::{f32x16, u8x16};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use utils::distance::l2::L2DistanceCalculatorImpl;
+use utils::DistanceCalculator;
+
+use crate::quantization::{Quantizer, WritableQuantizer};
+
+pub const SQ4_QUANTIZER_CONFIG_NAME: &str = "sq4_quantizer_config.yaml";
+
+// Largest 4-bit code.
+const MAX_CODE: f32 = 15.0;
+
+/// Scalar quantizer mapping every dimension linearly to 4 bits, between the per-dimension min and
+/// max seen during training. Two dimensions are packed in each byte: dimension `2i` in the upper
+/// nibble of byte `i` and dimension `2i + 1` in the lower nibble.
+pub struct Sq4Quantizer<D: DistanceCalculator> {
+    pub dimension: usize,
+    pub min_values: Vec<f32>,
+    pub max_values: Vec<f32>,
+    pub base_directory: String,
+
+    // Width of a quantization step, for each dimension.
+    steps: Vec<f32>,
+
+    _marker: PhantomData<D>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Sq4QuantizerConfig {
+    pub dimension: usize,
+    pub min_values: Vec<f32>,
+    pub max_values: Vec<f32>,
+}
+
+pub struct Sq4QuantizerReader {
+    base_directory: String,
+}
+
+impl Sq4QuantizerReader {
+    pub fn new(base_directory: String) -> Self {
+        Self { base_directory }
+    }
+
+    pub fn read<D: DistanceCalculator>(&self) -> Result<Sq4Quantizer<D>> {
+        let config_path = Path::new(&self.base_directory).join(SQ4_QUANTIZER_CONFIG_NAME);
+        if !config_path.is_file() {
+            return Err(Error::msg("Config file does not exist"));
+        }
+        let config: Sq4QuantizerConfig = serde_yaml::from_slice(&std::fs::read(config_path)?)?;
+        Sq4Quantizer::new(
+            config.dimension,
+            config.min_values,
+            config.max_values,
+            self.base_directory.clone(),
+        )
+    }
+}
+
+impl<D: DistanceCalculator> Sq4Quantizer<D> {
+    pub fn new(
+        dimension: usize,
+        min_values: Vec<f32>,
+        max_values: Vec<f32>,
+        base_directory: String,
+    ) -> Result<Self> {
+        if min_values.len() != dimension || max_values.len() != dimension {
+            return Err(Error::msg(
+                "Min and max values need to have one value per dimension.",
+            ));
+        }
+        let steps = min_values
+            .iter()
+            .zip(max_values.iter())
+            .map(|(min, max)| (max - min).max(0.0) / MAX_CODE)
+            .collect();
+        Ok(Self {
+            dimension,
+            min_values,
+            max_values,
+            base_directory,
+            steps,
+            _marker: PhantomData,
+        })
+    }
+
+    fn quantize_value(&self, dim: usize, value: f32) -> u8 {
+        if self.steps[dim] == 0.0 {
+            return 0;
+        }
+        ((value - self.min_values[dim]) / self.steps[dim])
+            .round()
+            .clamp(0.0, MAX_CODE) as u8
+    }
+
+    /// Maps every dimension to 4 bits and packs two of them per byte.
+    pub fn encode(&self, value: &[f32]) -> Vec<u8> {
+        let mut result = vec![0u8; self.dimension.div_ceil(2)];
+        for (dim, v) in value.iter().take(self.dimension).enumerate() {
+            let code = self.quantize_value(dim, *v);
+            if dim % 2 == 0 {
+                result[dim / 2] |= code << 4;
+            } else {
+                result[dim / 2] |= code;
+            }
+        }
+        result
+    }
+
+    /// Decodes the dimensions of `code` starting at `start` into `out`, one per element of `out`.
+    fn decode_range(&self, code: &[u8], start: usize, out: &mut [f32]) {
+        for (dim, value) in (start..).zip(out.iter_mut()) {
+            let byte = code[dim / 2];
+            let nibble = if dim % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+            *value = self.min_values[dim] + nibble as f32 * self.steps[dim];
+        }
+    }
+
+    pub fn decode(&self, code: &[u8]) -> Vec<f32> {
+        let mut result = vec![0.0; self.dimension];
+        self.decode_range(code, 0, &mut result);
+        result
+    }
+
+    /// Decodes the 32 dimensions packed in 16 bytes, starting at dimension `offset`. Nibbles are
+    /// extracted with a shift and a mask, then put back in dimension order with an interleaving
+    /// shuffle.
+    #[inline(always)]
+    fn decode_block(&self, bytes: &[u8], offset: usize, out: &mut [f32; 32]) {
+        let bytes = u8x16::from_slice(bytes);
+        let upper = bytes >> u8x16::splat(4);
+        let lower = bytes & u8x16::splat(0x0f);
+        let (first, second) = upper.interleave(lower);
+        for (i, nibbles) in [first, second].into_iter().enumerate() {
+            let start = offset + i * 16;
+            let min = f32x16::from_slice(&self.min_values[start..start + 16]);
+            let step = f32x16::from_slice(&self.steps[start..start + 16]);
+            let values = nibbles.cast::<f32>() * step + min;
+            values.copy_to_slice(&mut out[i * 16..(i + 1) * 16]);
+        }
+    }
+
+    fn distance_simd(&self, a: &[u8], b: &[u8]) -> f32 {
+        let mut sum_16 = f32x16::splat(0.0);
+        let mut a_block = [0.0f32; 32];
+        let mut b_block = [0.0f32; 32];
+        let num_blocks = self.dimension / 32;
+        for block in 0..num_blocks {
+            let bytes = block * 16..(block + 1) * 16;
+            self.decode_block(&a[bytes.clone()], block * 32, &mut a_block);
+            self.decode_block(&b[bytes], block * 32, &mut b_block);
+            D::accumulate_lanes::<16>(&a_block, &b_block, &mut sum_16);
+        }
+
+        // The remaining dimensions, fewer than a block, are decoded into the block buffers
+        let rest = self.dimension - num_blocks * 32;
+        self.decode_range(a, num_blocks * 32, &mut a_block[..rest]);
+        self.decode_range(b, num_blocks * 32, &mut b_block[..rest]);
+        D::outermost_op(
+            sum_16.reduce_sum() + D::accumulate_scalar(&a_block[..rest], &b_block[..rest]),
+        )
+    }
+
+    pub fn config(&self) -> Sq4QuantizerConfig {
+        Sq4QuantizerConfig {
+            dimension: self.dimension,
+            min_values: self.min_values.clone(),
+            max_values: self.max_values.clone(),
+        }
+    }
+}
+
+impl<D: DistanceCalculator> Quantizer for Sq4Quantizer<D> {
+    type QuantizedT = u8;
+
+    fn quantize(&self, value: &[f32]) -> Vec<u8> {
+        self.encode(value)
+    }
+
+    fn quantized_dimension(&self) -> usize {
+        self.dimension.div_ceil(2)
+    }
+
+    fn original_vector(&self, quantized_vector: &[u8]) -> Vec<f32> {
+        self.decode(quantized_vector)
+    }
+
+    fn distance(&self, a: &[u8], b: &[u8], implem: L2DistanceCalculatorImpl) -> f32 {
+        match implem {
+            L2DistanceCalculatorImpl::Scalar => {
+                let a = self.decode(a);
+                let b = self.decode(b);
+                D::outermost_op(D::accumulate_scalar(&a, &b))
+            }
+            L2DistanceCalculatorImpl::SIMD | L2DistanceCalculatorImpl::StreamingSIMD => {
+                self.distance_simd(a, b)
+            }
+        }
+    }
+
+    fn original_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        D::outermost_op(D::accumulate_scalar(a, b))
+    }
+
+    fn read(dir: String) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Sq4QuantizerReader::new(dir).read()
+    }
+}
+
+impl<D: DistanceCalculator> WritableQuantizer for Sq4Quantizer<D> {
+    fn write_to_directory(&self, base_directory: &str) -> Result<()> {
+        std::fs::write(
+            Path::new(base_directory).join(SQ4_QUANTIZER_CONFIG_NAME),
+            serde_yaml::to_string(&self.config())?,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::distance::dot_product::DotProductDistanceCalculator;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+
+    #[test]
+    fn test_sq4_quantizer() {
+        let sq4 = Sq4Quantizer::<L2DistanceCalculator>::new(
+            3,
+            vec![0.0, -1.0, 2.0],
+            vec![15.0, 14.0, 2.0],
+            "".to_string(),
+        )
+        .unwrap();
+        assert_eq!(sq4.quantized_dimension(), 2);
+
+        let code = sq4.encode(&[3.0, 14.0, 5.0]);
+        // Dimension 0 -> 3, dimension 1 -> 15, dimension 2 has a single value -> 0.
+        assert_eq!(code, vec![0x3f, 0x00]);
+        assert_eq!(sq4.decode(&code), vec![3.0, 14.0, 2.0]);
+
+        // Out of range values are clamped.
+        assert_eq!(sq4.encode(&[-5.0, 20.0, 0.0]), vec![0x0f, 0x00]);
+    }
+
+    fn check_distance<D: DistanceCalculator>(dimension: usize) {
+        let sq4 = Sq4Quantizer::<D>::new(
+            dimension,
+            vec![-1.0; dimension],
+            vec![1.0; dimension],
+            "".to_string(),
+        )
+        .unwrap();
+        let a = sq4.encode(&generate_random_vector(dimension));
+        let b = sq4.encode(&generate_random_vector(dimension));
+        let expected = D::outermost_op(D::accumulate_scalar(&sq4.decode(&a), &sq4.decode(&b)));
+        for implem in [
+            L2DistanceCalculatorImpl::Scalar,
+            L2DistanceCalculatorImpl::SIMD,
+            L2DistanceCalculatorImpl::StreamingSIMD,
+        ] {
+            let distance = sq4.distance(&a, &b, implem);
+            assert!(
+                (distance - expected).abs() < 1e-3,
+                "{} vs {}",
+                distance,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_sq4_distance() {
+        for dimension in [7, 32, 100, 128] {
+            check_distance::<L2DistanceCalculator>(dimension);
+            check_distance::<DotProductDistanceCalculator>(dimension);
+        }
+    }
+
+    #[test]
+    fn test_sq4_quantizer_read_write() {
+        let temp_dir = tempdir::TempDir::new("sq4_quantizer_test")
+            .expect("Failed to create temporary directory");
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+
+        let sq4 = Sq4Quantizer::<L2DistanceCalculator>::new(
+            4,
+            vec![0.0, -1.5, 2.0, 0.1],
+            vec![1.0, 1.5, 3.0, 0.7],
+            base_directory.clone(),
+        )
+        .unwrap();
+        sq4.write_to_directory(&base_directory)
+            .expect("Failed to write the quantizer");
+
+        let new_sq4 = Sq4QuantizerReader::new(base_directory)
+            .read::<L2DistanceCalculator>()
+            .expect("Failed to read the quantizer");
+        assert_eq!(new_sq4.min_values, sq4.min_values);
+        assert_eq!(new_sq4.max_values, sq4.max_values);
+        let value = vec![0.5, 0.0, 2.5, 0.3];
+        assert_eq!(new_sq4.encode(&value), sq4.encode(&value));
+    }
+}
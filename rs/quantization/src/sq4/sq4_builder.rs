@@ -0,0 +1,85 @@
+use std::marker::PhantomData;
+
+use anyhow::{Error, Result};
+use utils::DistanceCalculator;
+
+use crate::sq4::sq4::Sq4Quantizer;
+
+pub struct Sq4QuantizerBuilder<D: DistanceCalculator> {
+    dimension: usize,
+
+    // Per-dimension min and max of the vectors added so far.
+    min_values: Vec<f32>,
+    max_values: Vec<f32>,
+    num_vectors: usize,
+
+    _marker: PhantomData<D>,
+}
+
+impl<D: DistanceCalculator> Sq4QuantizerBuilder<D> {
+    /// Create a new Sq4QuantizerBuilder
+    pub fn new(dimension: usize) -> Self {
+        Self {
+            dimension,
+            min_values: vec![f32::MAX; dimension],
+            max_values: vec![f32::MIN; dimension],
+            num_vectors: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add a new vector to the training set. Only the per-dimension ranges are kept.
+    pub fn add(&mut self, data: Vec<f32>) {
+        for (dim, value) in data.iter().take(self.dimension).enumerate() {
+            self.min_values[dim] = self.min_values[dim].min(*value);
+            self.max_values[dim] = self.max_values[dim].max(*value);
+        }
+        self.num_vectors += 1;
+    }
+
+    pub fn build(&mut self, base_directory: String) -> Result<Sq4Quantizer<D>> {
+        if self.num_vectors == 0 {
+            return Err(Error::msg("No training vectors for SQ4 quantizer"));
+        }
+        Sq4Quantizer::new(
+            self.dimension,
+            self.min_values.clone(),
+            self.max_values.clone(),
+            base_directory,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::quantization::Quantizer;
+
+    #[test]
+    fn test_sq4_quantizer_builder() {
+        const DIMENSION: usize = 64;
+        let mut builder = Sq4QuantizerBuilder::<L2DistanceCalculator>::new(DIMENSION);
+        assert!(builder.build("".to_string()).is_err());
+
+        let dataset: Vec<Vec<f32>> = (0..1000)
+            .map(|_| generate_random_vector(DIMENSION))
+            .collect();
+        for v in dataset.iter() {
+            builder.add(v.clone());
+        }
+        let sq4 = builder.build("".to_string()).unwrap();
+        assert_eq!(sq4.quantized_dimension(), DIMENSION / 2);
+
+        // Each dimension is off by at most half a step.
+        for v in dataset.iter().take(10) {
+            let reconstructed = sq4.original_vector(&sq4.quantize(v));
+            for dim in 0..DIMENSION {
+                let half_step = (sq4.max_values[dim] - sq4.min_values[dim]) / 30.0;
+                assert!((reconstructed[dim] - v[dim]).abs() <= half_step + 1e-6);
+            }
+        }
+    }
+}
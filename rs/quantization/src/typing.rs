@@ -39,11 +39,39 @@ impl<Q: Quantizer> VectorOps<Q> for f32 {
     }
 }
 
+impl<Q: Quantizer> VectorOps<Q> for u16 {
+    fn process_vector(vector: &[f32], quantizer: &Q) -> Vec<Q::QuantizedT> {
+        quantizer.quantize(vector)
+    }
+
+    fn distance(vector: &[Q::QuantizedT], other: &[Q::QuantizedT], quantizer: &Q) -> f32
+    where
+        Self: Sized,
+    {
+        quantizer.distance(vector, other, StreamingSIMD)
+    }
+}
+
+impl<Q: Quantizer> VectorOps<Q> for u64 {
+    fn process_vector(vector: &[f32], quantizer: &Q) -> Vec<Q::QuantizedT> {
+        quantizer.quantize(vector)
+    }
+
+    fn distance(vector: &[Q::QuantizedT], other: &[Q::QuantizedT], quantizer: &Q) -> f32
+    where
+        Self: Sized,
+    {
+        quantizer.distance(vector, other, StreamingSIMD)
+    }
+}
+
 pub trait VectorT<Q: Quantizer>:
-    ToBytes + Clone + std::fmt::Debug + 'static + VectorOps<Q>
+    ToBytes + Clone + std::fmt::Debug + Send + Sync + 'static + VectorOps<Q>
 {
 }
 
-// Only u8 and f32
+// u8 and f32, plus u16 for half precision floats and u64 for bit-packed binary vectors
 impl<Q: Quantizer> VectorT<Q> for u8 {}
 impl<Q: Quantizer> VectorT<Q> for f32 {}
+impl<Q: Quantizer> VectorT<Q> for u16 {}
+impl<Q: Quantizer> VectorT<Q> for u64 {}
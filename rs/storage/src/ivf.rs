@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use compression::compression::IntSeqDecoder;
+use index::ivf::index::Ivf;
+use index::posting_list::combined_file::FixedIndexFile;
+use quantization::quantization::Quantizer;
+use tempdir::TempDir;
+use utils::DistanceCalculator;
+
+use crate::s3::{ObjectReader, S3VectorStorage};
+
+/// IVF index served from an object store. The index file (centroids, posting lists and doc ids)
+/// is loaded in memory, while vectors are fetched on demand. Searches are the ones of `Ivf`.
+pub type S3Ivf<Q, DC, D> = Ivf<Q, DC, D, S3VectorStorage<<Q as Quantizer>::QuantizedT>>;
+
+/// Reads an IVF index written by `IvfWriter` and uploaded under `prefix`, without a local copy
+/// of the vectors.
+pub struct S3IndexReader {
+    reader: Arc<dyn ObjectReader>,
+    prefix: String,
+    index_offset: usize,
+    vector_offset: usize,
+}
+
+impl S3IndexReader {
+    pub fn new(reader: Arc<dyn ObjectReader>, prefix: String) -> Self {
+        Self::new_with_offset(reader, prefix, 0, 0)
+    }
+
+    pub fn new_with_offset(
+        reader: Arc<dyn ObjectReader>,
+        prefix: String,
+        index_offset: usize,
+        vector_offset: usize,
+    ) -> Self {
+        Self {
+            reader,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            index_offset,
+            vector_offset,
+        }
+    }
+
+    pub fn read<Q: Quantizer, DC: DistanceCalculator, D: IntSeqDecoder<Item = u64>>(
+        &self,
+    ) -> Result<S3Ivf<Q, DC, D>> {
+        let index_key = self.key("index");
+        let index_bytes = self.reader.read_object(&index_key)?;
+        let index_storage =
+            FixedIndexFile::new_from_bytes(index_key, &index_bytes, self.index_offset)?;
+
+        let vector_storage = S3VectorStorage::<Q::QuantizedT>::new_with_offset(
+            Arc::clone(&self.reader),
+            self.key("vectors"),
+            index_storage.header().quantized_dimension as usize,
+            self.vector_offset,
+        )?;
+
+        let num_clusters = index_storage.header().num_clusters as usize;
+        let quantizer = self.read_quantizer::<Q>()?;
+
+        Ok(Ivf::new(
+            vector_storage,
+            index_storage,
+            num_clusters,
+            quantizer,
+        ))
+    }
+
+    /// Quantizers are read from a directory, so the (small) quantizer files are downloaded to a
+    /// temporary one.
+    fn read_quantizer<Q: Quantizer>(&self) -> Result<Q> {
+        let quantizer_prefix = format!("{}/", self.key("quantizer"));
+        let temp_dir = TempDir::new("s3_quantizer")?;
+        let directory = temp_dir
+            .path()
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid temporary directory"))?
+            .to_string();
+        for key in self.reader.list_objects(&quantizer_prefix)? {
+            let path = format!("{}/{}", directory, &key[quantizer_prefix.len()..]);
+            if let Some(parent) = std::path::Path::new(&path).parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, self.reader.read_object(&key)?)?;
+        }
+        Q::read(directory)
+    }
+
+    fn key(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.prefix, name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use compression::elias_fano::ef::{EliasFano, EliasFanoDecoder};
+    use index::index::Searchable;
+    use index::ivf::builder::{IvfBuilder, IvfBuilderConfig};
+    use index::ivf::writer::IvfWriter;
+    use index::utils::SearchContext;
+    use quantization::noq::noq::NoQuantizer;
+    use quantization::quantization::WritableQuantizer;
+    use utils::distance::l2::L2DistanceCalculator;
+    use utils::kmeans_builder::kmeans_builder::KMeansVariant;
+    use utils::test_utils::generate_random_vector;
+
+    use super::*;
+    use crate::s3::LocalObjectReader;
+
+    #[test]
+    fn test_s3_index_reader() {
+        let temp_dir = TempDir::new("test_s3_index_reader").unwrap();
+        let root = temp_dir.path().to_str().unwrap().to_string();
+        let base_directory = format!("{}/collection/ivf", root);
+        let num_clusters = 4;
+        let num_vectors = 200;
+        let num_features = 4;
+
+        let quantizer = NoQuantizer::<L2DistanceCalculator>::new(num_features);
+        let quantizer_directory = format!("{}/quantizer", base_directory);
+        std::fs::create_dir_all(&quantizer_directory).unwrap();
+        quantizer.write_to_directory(&quantizer_directory).unwrap();
+        let writer =
+            IvfWriter::<_, EliasFano, L2DistanceCalculator>::new(base_directory.clone(), quantizer);
+
+        let mut builder: IvfBuilder<L2DistanceCalculator> = IvfBuilder::new(IvfBuilderConfig {
+            max_iteration: 1000,
+            batch_size: 4,
+            num_clusters,
+            num_data_points_for_clustering: num_vectors,
+            max_clusters_per_vector: 1,
+            distance_threshold: 0.1,
+            base_directory: base_directory.clone(),
+            memory_size: 1024,
+            file_size: 4096,
+            num_features,
+            tolerance: 0.0,
+            kmeans_variant: KMeansVariant::Lloyd,
+            max_posting_list_size: usize::MAX,
+            initial_centroids_fvecs_path: None,
+            allow_online_insertion: false,
+        })
+        .unwrap();
+        let vectors: Vec<Vec<f32>> = (0..num_vectors)
+            .map(|_| generate_random_vector(num_features))
+            .collect();
+        for (i, vector) in vectors.iter().enumerate() {
+            builder.add_vector((i + 100) as u128, vector).unwrap();
+        }
+        builder.build().unwrap();
+        writer.write(&mut builder, false).unwrap();
+
+        let reader = S3IndexReader::new(
+            Arc::new(LocalObjectReader::new(root)),
+            "collection/ivf/".to_string(),
+        );
+        let index = reader
+            .read::<NoQuantizer<L2DistanceCalculator>, L2DistanceCalculator, EliasFanoDecoder>()
+            .unwrap();
+        assert_eq!(index.num_clusters, num_clusters);
+        assert_eq!(index.vector_storage.num_vectors, num_vectors);
+
+        // Probe all clusters so that the exact nearest neighbor is found.
+        let mut context = SearchContext::new(false);
+        for i in [0, 42, 199] {
+            let results = index
+                .search(&vectors[i], 1, num_clusters as u32, &mut context)
+                .unwrap();
+            assert_eq!(results[0].id, (i + 100) as u128);
+        }
+    }
+}
@@ -0,0 +1,614 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::num::NonZeroUsize;
+use std::sync::{mpsc, Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::Client;
+use index::utils::{SearchContext, TraversalContext};
+use index::vector::VectorReader;
+use log::error;
+use lru::LruCache;
+use num_traits::ToBytes;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+use tokio::task::block_in_place;
+use utils::mem::next_instance_id;
+
+// Size of the blocks fetched with a single range request and kept in the cache.
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+// Number of blocks kept in the cache of each storage.
+pub const DEFAULT_NUM_CACHED_BLOCKS: usize = 1024;
+
+/// Reads objects, or byte ranges of objects, from an object store.
+pub trait ObjectReader: Send + Sync {
+    /// Reads `length` bytes of the object `key`, starting at `offset`.
+    fn read_range(&self, key: &str, offset: u64, length: u64) -> Result<Vec<u8>>;
+
+    /// Reads the whole object `key`.
+    fn read_object(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Returns the keys of all objects starting with `prefix`.
+    fn list_objects(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// Reads objects from an S3 bucket, using credentials and region from the environment.
+pub struct S3ObjectReader {
+    client: Client,
+    bucket: String,
+    // The SDK is async while index reads are blocking, so requests run on a dedicated runtime.
+    // Only taken when the reader is dropped.
+    runtime: Option<Runtime>,
+}
+
+impl S3ObjectReader {
+    pub fn new(bucket: String) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()?;
+        let config = block_on(
+            &runtime,
+            aws_config::load_defaults(BehaviorVersion::latest()),
+        )?;
+        Ok(Self {
+            client: Client::new(&config),
+            bucket,
+            runtime: Some(runtime),
+        })
+    }
+
+    fn runtime(&self) -> &Runtime {
+        self.runtime
+            .as_ref()
+            .expect("Runtime is only taken on drop")
+    }
+}
+
+impl Drop for S3ObjectReader {
+    // Dropping a runtime waits for its threads, which panics on the thread of another runtime,
+    // e.g. when a gRPC handler drops the last reference to an index.
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Runs `future` on `runtime` and waits for its output. Unlike `Runtime::block_on`, this can be
+/// called from within another runtime, e.g. from a gRPC handler. On a worker thread of a
+/// multi-threaded runtime, the other tasks of the worker are handed off while it waits, so they
+/// aren't blocked by the request.
+fn block_on<F>(runtime: &Runtime, future: F) -> Result<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    runtime.spawn(async move {
+        let _ = sender.send(future.await);
+    });
+    let receive = || {
+        receiver
+            .recv()
+            .map_err(|_| anyhow!("S3 request was cancelled"))
+    };
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            block_in_place(receive)
+        }
+        _ => receive(),
+    }
+}
+
+impl ObjectReader for S3ObjectReader {
+    fn read_range(&self, key: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(vec![]);
+        }
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .range(format!("bytes={}-{}", offset, offset + length - 1));
+        let bytes = block_on(self.runtime(), async move {
+            let output = request
+                .send()
+                .await
+                .map_err(|e| anyhow!("{}", DisplayErrorContext(e)))?;
+            Ok::<_, anyhow::Error>(output.body.collect().await?.into_bytes().to_vec())
+        })??;
+        if bytes.len() as u64 != length {
+            return Err(anyhow!(
+                "Short read of {}: expected {} bytes at offset {}, got {}",
+                key,
+                length,
+                offset,
+                bytes.len()
+            ));
+        }
+        Ok(bytes)
+    }
+
+    fn read_object(&self, key: &str) -> Result<Vec<u8>> {
+        let request = self.client.get_object().bucket(&self.bucket).key(key);
+        block_on(self.runtime(), async move {
+            let output = request
+                .send()
+                .await
+                .map_err(|e| anyhow!("{}", DisplayErrorContext(e)))?;
+            Ok(output.body.collect().await?.into_bytes().to_vec())
+        })?
+    }
+
+    fn list_objects(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+        block_on(self.runtime(), async move {
+            let mut keys = vec![];
+            while let Some(page) = pages.next().await {
+                let page = page.map_err(|e| anyhow!("{}", DisplayErrorContext(e)))?;
+                keys.extend(
+                    page.contents()
+                        .iter()
+                        .filter_map(|o| o.key().map(String::from)),
+                );
+            }
+            Ok(keys)
+        })?
+    }
+}
+
+/// Reads objects from a local directory, where the key of an object is its path relative to the
+/// directory. This serves an index laid out as in a bucket without any network access.
+pub struct LocalObjectReader {
+    root: String,
+}
+
+impl LocalObjectReader {
+    pub fn new(root: String) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> String {
+        format!("{}/{}", self.root, key)
+    }
+}
+
+impl ObjectReader for LocalObjectReader {
+    fn read_range(&self, key: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+        let bytes = self.read_object(key)?;
+        let start = offset as usize;
+        let end = start + length as usize;
+        if end > bytes.len() {
+            return Err(anyhow!(
+                "Short read of {}: expected {} bytes at offset {}",
+                key,
+                length,
+                offset
+            ));
+        }
+        Ok(bytes[start..end].to_vec())
+    }
+
+    fn read_object(&self, key: &str) -> Result<Vec<u8>> {
+        Ok(std::fs::read(self.path(key))?)
+    }
+
+    fn list_objects(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = vec![];
+        let mut directories = vec![self.root.clone()];
+        while let Some(directory) = directories.pop() {
+            for entry in std::fs::read_dir(&directory)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    directories.push(path.to_string_lossy().to_string());
+                    continue;
+                }
+                let key = path.strip_prefix(&self.root)?.to_string_lossy().to_string();
+                if key.starts_with(prefix) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Vector storage backed by an object, in the same layout as `FixedFileVectorStorage`.
+/// Vectors are read in fixed size blocks with range requests, and the most recently used blocks
+/// are cached in memory.
+pub struct S3VectorStorage<T> {
+    _marker: PhantomData<T>,
+
+    reader: Arc<dyn ObjectReader>,
+    key: String,
+    pub num_vectors: usize,
+    num_features: usize,
+    offset: usize,
+    block_size: usize,
+
+    // Block index -> block content
+    blocks: Mutex<LruCache<usize, Arc<Vec<u8>>>>,
+
+    // Identifies the storage in the vector cache of a `SearchContext`
+    id: u64,
+}
+
+impl<T: ToBytes + Clone> S3VectorStorage<T> {
+    pub fn new(reader: Arc<dyn ObjectReader>, key: String, num_features: usize) -> Result<Self> {
+        Self::new_with_offset(reader, key, num_features, 0)
+    }
+
+    pub fn new_with_offset(
+        reader: Arc<dyn ObjectReader>,
+        key: String,
+        num_features: usize,
+        offset: usize,
+    ) -> Result<Self> {
+        Self::new_with_cache(
+            reader,
+            key,
+            num_features,
+            offset,
+            DEFAULT_BLOCK_SIZE,
+            DEFAULT_NUM_CACHED_BLOCKS,
+        )
+    }
+
+    pub fn new_with_cache(
+        reader: Arc<dyn ObjectReader>,
+        key: String,
+        num_features: usize,
+        offset: usize,
+        block_size: usize,
+        num_cached_blocks: usize,
+    ) -> Result<Self> {
+        if block_size == 0 {
+            return Err(anyhow!("block_size must be greater than 0"));
+        }
+        let num_cached_blocks = NonZeroUsize::new(num_cached_blocks)
+            .ok_or_else(|| anyhow!("num_cached_blocks must be greater than 0"))?;
+
+        let header = reader.read_range(&key, offset as u64, 8)?;
+        let num_vectors = usize::from_le_bytes(header[..8].try_into()?);
+        Ok(Self {
+            _marker: PhantomData,
+            reader,
+            key,
+            num_vectors,
+            num_features,
+            offset,
+            block_size,
+            blocks: Mutex::new(LruCache::new(num_cached_blocks)),
+            id: next_instance_id(),
+        })
+    }
+
+    /// Returns a copy of the vector at `index`, or None if it is out of range or cannot be read.
+    pub fn get(&self, index: usize, context: &mut SearchContext) -> Option<Vec<T>> {
+        if index >= self.num_vectors {
+            return None;
+        }
+        self.record_pages(&[index as u32], context);
+        let vector_size = Self::vector_size_in_bytes(self.num_features);
+        match self.read_bytes(self.vector_start(index), vector_size) {
+            Ok(bytes) => Some(Self::bytes_to_vector(&bytes, self.num_features)),
+            Err(e) => {
+                error!("Failed to read vector {} from {}: {}", index, self.key, e);
+                None
+            }
+        }
+    }
+
+    /// Returns the number of bytes the vectors occupy in the backing object, including the
+    /// leading vector count.
+    pub fn size_in_bytes(&self) -> usize {
+        8 + self.num_vectors * Self::vector_size_in_bytes(self.num_features)
+    }
+
+    /// Number of blocks currently in the cache.
+    pub fn num_cached_blocks(&self) -> usize {
+        self.blocks.lock().unwrap().len()
+    }
+
+    /// Reads the vectors at `indices`, one after the other, from the cached blocks.
+    pub fn read_batch(&self, indices: &[u32]) -> Result<Vec<T>> {
+        let vector_size = Self::vector_size_in_bytes(self.num_features);
+        let mut bytes = Vec::with_capacity(indices.len() * vector_size);
+        for &index in indices {
+            if index as usize >= self.num_vectors {
+                return Err(anyhow!(
+                    "Vector {} out of bound, the storage has {} vectors",
+                    index,
+                    self.num_vectors
+                ));
+            }
+            bytes.extend(self.read_bytes(self.vector_start(index as usize), vector_size)?);
+        }
+        Ok(Self::bytes_to_vector(
+            &bytes,
+            indices.len() * self.num_features,
+        ))
+    }
+
+    /// Records the blocks holding the vectors at `indices` in `context`, as `get` would.
+    pub fn record_pages(&self, indices: &[u32], context: &mut SearchContext) {
+        if !context.should_record_pages() {
+            return;
+        }
+        let vector_size = Self::vector_size_in_bytes(self.num_features);
+        for &index in indices {
+            let start = self.vector_start(index as usize);
+            let last = start + vector_size.max(1) - 1;
+            for block_id in start / self.block_size..=last / self.block_size {
+                context.record_pages(format!("{}::{}", self.key, block_id));
+            }
+        }
+    }
+
+    fn vector_start(&self, index: usize) -> usize {
+        self.offset + 8 + index * Self::vector_size_in_bytes(self.num_features)
+    }
+
+    fn read_bytes(&self, start: usize, len: usize) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(len);
+        let end = start + len;
+        let mut position = start;
+        while position < end {
+            let block_id = position / self.block_size;
+            let block = self.get_block(block_id)?;
+            let block_start = block_id * self.block_size;
+            let from = position - block_start;
+            let to = (end - block_start).min(block.len());
+            if from >= to {
+                return Err(anyhow!(
+                    "Offset {} is past the end of {}",
+                    position,
+                    self.key
+                ));
+            }
+            bytes.extend_from_slice(&block[from..to]);
+            position = block_start + to;
+        }
+        Ok(bytes)
+    }
+
+    fn get_block(&self, block_id: usize) -> Result<Arc<Vec<u8>>> {
+        if let Some(block) = self.blocks.lock().unwrap().get(&block_id) {
+            return Ok(Arc::clone(block));
+        }
+
+        // The last block may be shorter than the block size.
+        let block_start = block_id * self.block_size;
+        let block_end = (block_start + self.block_size).min(self.offset + self.size_in_bytes());
+        let block = Arc::new(self.reader.read_range(
+            &self.key,
+            block_start as u64,
+            block_end.saturating_sub(block_start) as u64,
+        )?);
+        self.blocks
+            .lock()
+            .unwrap()
+            .put(block_id, Arc::clone(&block));
+        Ok(block)
+    }
+
+    fn bytes_to_vector(bytes: &[u8], len: usize) -> Vec<T> {
+        let mut vector = Vec::<T>::with_capacity(len);
+        // SAFETY: the vector has room for `len` elements, which is exactly `bytes.len()` bytes,
+        // and elements are plain numbers valid for any bit pattern.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                vector.as_mut_ptr() as *mut u8,
+                bytes.len(),
+            );
+            vector.set_len(len);
+        }
+        vector
+    }
+
+    fn vector_size_in_bytes(num_features: usize) -> usize {
+        num_features * std::mem::size_of::<T>()
+    }
+}
+
+impl<T: ToBytes + Clone> VectorReader<T> for S3VectorStorage<T> {
+    fn num_vectors(&self) -> usize {
+        self.num_vectors
+    }
+
+    fn num_features(&self) -> usize {
+        self.num_features
+    }
+
+    fn get(&self, index: usize, context: &mut SearchContext) -> Option<Cow<'_, [T]>> {
+        S3VectorStorage::get(self, index, context).map(Cow::Owned)
+    }
+
+    fn read_batch(&self, indices: &[u32]) -> Result<Vec<T>> {
+        S3VectorStorage::read_batch(self, indices)
+    }
+
+    fn record_pages(&self, indices: &[u32], context: &mut SearchContext) {
+        S3VectorStorage::record_pages(self, indices, context)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        S3VectorStorage::size_in_bytes(self)
+    }
+
+    fn cache_id(&self) -> u64 {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    // Counts the range requests sent to the underlying reader.
+    struct CountingObjectReader {
+        reader: LocalObjectReader,
+        num_requests: AtomicUsize,
+    }
+
+    impl ObjectReader for CountingObjectReader {
+        fn read_range(&self, key: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+            self.num_requests.fetch_add(1, Ordering::Relaxed);
+            self.reader.read_range(key, offset, length)
+        }
+
+        fn read_object(&self, key: &str) -> Result<Vec<u8>> {
+            self.reader.read_object(key)
+        }
+
+        fn list_objects(&self, prefix: &str) -> Result<Vec<String>> {
+            self.reader.list_objects(prefix)
+        }
+    }
+
+    fn write_vectors(path: &str, offset: usize, vectors: &[Vec<f32>]) {
+        let mut bytes = vec![0u8; offset];
+        bytes.extend_from_slice(&vectors.len().to_le_bytes());
+        for vector in vectors {
+            for value in vector {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_s3_vector_storage() {
+        let temp_dir = TempDir::new("test_s3_vector_storage").unwrap();
+        let root = temp_dir.path().to_str().unwrap().to_string();
+        let num_features = 3;
+        let vectors: Vec<Vec<f32>> = (0..100)
+            .map(|i| (0..num_features).map(|j| (i * 10 + j) as f32).collect())
+            .collect();
+        write_vectors(&format!("{}/vectors", root), 5, &vectors);
+
+        let reader = Arc::new(CountingObjectReader {
+            reader: LocalObjectReader::new(root),
+            num_requests: AtomicUsize::new(0),
+        });
+        // Blocks of 20 bytes don't align with vectors of 12 bytes, so some vectors span two
+        // blocks.
+        let storage = S3VectorStorage::<f32>::new_with_cache(
+            reader.clone(),
+            "vectors".to_string(),
+            num_features,
+            5,
+            20,
+            4,
+        )
+        .unwrap();
+        assert_eq!(storage.num_vectors, 100);
+        assert_eq!(storage.size_in_bytes(), 8 + 100 * 12);
+
+        let mut context = SearchContext::new(false);
+        for (i, vector) in vectors.iter().enumerate() {
+            assert_eq!(storage.get(i, &mut context).unwrap(), *vector);
+        }
+        assert!(storage.get(100, &mut context).is_none());
+        assert_eq!(
+            storage.read_batch(&[99, 98]).unwrap(),
+            [vectors[99].clone(), vectors[98].clone()].concat()
+        );
+        assert!(storage.read_batch(&[100]).is_err());
+        assert_eq!(storage.num_cached_blocks(), 4);
+
+        // Vector 0 spans the first two blocks, which were evicted. Reading it twice only
+        // fetches them once.
+        let num_requests = reader.num_requests.load(Ordering::Relaxed);
+        storage.get(0, &mut context).unwrap();
+        storage.get(0, &mut context).unwrap();
+        assert_eq!(
+            reader.num_requests.load(Ordering::Relaxed),
+            num_requests + 2
+        );
+    }
+
+    #[test]
+    fn test_s3_vector_storage_invalid_cache() {
+        let temp_dir = TempDir::new("test_s3_vector_storage_invalid_cache").unwrap();
+        let root = temp_dir.path().to_str().unwrap().to_string();
+        write_vectors(&format!("{}/vectors", root), 0, &[vec![1.0]]);
+        let reader: Arc<dyn ObjectReader> = Arc::new(LocalObjectReader::new(root));
+
+        assert!(S3VectorStorage::<f32>::new_with_cache(
+            reader.clone(),
+            "vectors".to_string(),
+            1,
+            0,
+            0,
+            1
+        )
+        .is_err());
+        assert!(
+            S3VectorStorage::<f32>::new_with_cache(reader, "vectors".to_string(), 1, 0, 16, 0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_local_object_reader() {
+        let temp_dir = TempDir::new("test_local_object_reader").unwrap();
+        let root = temp_dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir_all(format!("{}/index/quantizer", root)).unwrap();
+        std::fs::write(format!("{}/index/vectors", root), b"0123456789").unwrap();
+        std::fs::write(format!("{}/index/quantizer/config", root), b"config").unwrap();
+
+        let reader = LocalObjectReader::new(root);
+        assert_eq!(reader.read_range("index/vectors", 2, 3).unwrap(), b"234");
+        assert!(reader.read_range("index/vectors", 8, 3).is_err());
+        assert_eq!(
+            reader.list_objects("index/").unwrap(),
+            vec!["index/quantizer/config", "index/vectors"]
+        );
+        assert_eq!(
+            reader.list_objects("index/quantizer").unwrap(),
+            vec!["index/quantizer/config"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_block_on_in_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .unwrap();
+        // The request waits for a task of the calling runtime, which only has one worker, so
+        // it completes only if that worker is handed off while waiting
+        let value = tokio::spawn(async move {
+            let (sender, receiver) = mpsc::channel();
+            tokio::spawn(async move { sender.send(42).unwrap() });
+            let value = block_on(&runtime, async move {
+                receiver.recv_timeout(std::time::Duration::from_secs(10))
+            })
+            .unwrap()
+            .unwrap();
+            runtime.shutdown_background();
+            value
+        })
+        .await
+        .unwrap();
+        assert_eq!(value, 42);
+    }
+}
@@ -36,11 +36,15 @@ impl CalculateSquared for DotProductDistanceCalculator {
 impl DistanceCalculator for DotProductDistanceCalculator {
     #[inline(always)]
     fn calculate(a: &[f32], b: &[f32]) -> f32 {
+        if cfg!(feature = "wasm") {
+            return Self::calculate_scalar(a, b);
+        }
+
         let mut res = 0.0;
         let mut a_vec = a;
         let mut b_vec = b;
 
-        if a_vec.len() > 16 {
+        if a_vec.len() >= 16 {
             let mut accumulator = Simd::<f32, 16>::splat(0.0);
             Self::accumulate_lanes::<16>(a_vec, b_vec, &mut accumulator);
             res += accumulator.reduce_sum();
@@ -48,7 +52,7 @@ impl DistanceCalculator for DotProductDistanceCalculator {
             b_vec = b_vec.chunks_exact(16).remainder();
         }
 
-        if a_vec.len() > 8 {
+        if a_vec.len() >= 8 {
             let mut accumulator = Simd::<f32, 8>::splat(0.0);
             Self::accumulate_lanes::<8>(a_vec, b_vec, &mut accumulator);
             res += accumulator.reduce_sum();
@@ -56,7 +60,7 @@ impl DistanceCalculator for DotProductDistanceCalculator {
             b_vec = b_vec.chunks_exact(8).remainder();
         }
 
-        if a_vec.len() > 4 {
+        if a_vec.len() >= 4 {
             let mut accumulator = Simd::<f32, 4>::splat(0.0);
             Self::accumulate_lanes::<4>(a_vec, b_vec, &mut accumulator);
             res += accumulator.reduce_sum();
@@ -122,4 +126,28 @@ mod tests {
         let accumulate_scalar = DotProductDistanceCalculator::accumulate_scalar(&a, &b);
         assert!((distance_scalar - accumulate_scalar.sqrt()) < epsilon)
     }
+
+    #[test]
+    fn test_accumulate_lanes_chunks() {
+        let a = generate_random_vector(512);
+        let b = generate_random_vector(512);
+
+        // Stream the vectors 16 elements at a time, as the streaming SIMD distances do
+        let mut accumulator = Simd::<f32, 16>::splat(0.0);
+        for (a_chunk, b_chunk) in a.chunks(16).zip(b.chunks(16)) {
+            DotProductDistanceCalculator::accumulate_lanes::<16>(
+                a_chunk,
+                b_chunk,
+                &mut accumulator,
+            );
+        }
+        let streamed = DotProductDistanceCalculator::outermost_op(accumulator.reduce_sum());
+
+        let expected = DotProductDistanceCalculator::calculate_scalar(&a, &b);
+        assert!((streamed - expected).abs() <= 1e-5 * expected.abs());
+        assert!(
+            (DotProductDistanceCalculator::calculate(&a, &b) - expected).abs()
+                <= 1e-5 * expected.abs()
+        );
+    }
 }
@@ -0,0 +1,70 @@
+use crate::{ceil_div, BinaryDistanceCalculator};
+
+// Number of dimensions packed into each word
+pub const BITS_PER_WORD: usize = 64;
+
+/// Number of differing bits between two bit-packed binary vectors.
+pub struct HammingDistanceCalculator {}
+
+impl BinaryDistanceCalculator for HammingDistanceCalculator {
+    #[inline(always)]
+    fn calculate(a: &[u64], b: &[u64]) -> u32 {
+        // count_ones compiles to POPCNT when the target supports it
+        a.iter()
+            .zip(b.iter())
+            .map(|(x, y)| (x ^ y).count_ones())
+            .sum()
+    }
+}
+
+/// Number of words needed to pack `num_bits` dimensions.
+pub fn num_words(num_bits: usize) -> usize {
+    ceil_div(num_bits, BITS_PER_WORD)
+}
+
+/// Packs `bits` into words, least significant bit first. Bits past the end of `bits` in the last
+/// word are zero, so they never contribute to distances.
+pub fn pack_bits(bits: &[bool]) -> Vec<u64> {
+    let mut words = vec![0u64; num_words(bits.len())];
+    for (idx, &bit) in bits.iter().enumerate() {
+        if bit {
+            words[idx / BITS_PER_WORD] |= 1 << (idx % BITS_PER_WORD);
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_pack_bits() {
+        assert_eq!(pack_bits(&[]), Vec::<u64>::new());
+        assert_eq!(pack_bits(&[true, false, true]), vec![0b101]);
+
+        let mut bits = vec![false; 130];
+        bits[0] = true;
+        bits[64] = true;
+        bits[129] = true;
+        assert_eq!(pack_bits(&bits), vec![1, 1, 1 << 1]);
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        let mut rng = rand::thread_rng();
+        let a: Vec<bool> = (0..200).map(|_| rng.gen()).collect();
+        let b: Vec<bool> = (0..200).map(|_| rng.gen()).collect();
+        let expected = a.iter().zip(b.iter()).filter(|(x, y)| x != y).count() as u32;
+
+        let (a, b) = (pack_bits(&a), pack_bits(&b));
+        assert_eq!(HammingDistanceCalculator::calculate(&a, &b), expected);
+        assert_eq!(HammingDistanceCalculator::calculate(&a, &a), 0);
+        assert_eq!(
+            HammingDistanceCalculator::calculate(&[0, u64::MAX], &[u64::MAX, 0]),
+            128
+        );
+    }
+}
@@ -29,6 +29,10 @@ impl L2DistanceCalculator {
 impl CalculateSquared for L2DistanceCalculator {
     #[inline(always)]
     fn calculate_squared(a: &[f32], b: &[f32]) -> f32 {
+        if cfg!(feature = "wasm") {
+            return Self::accumulate_scalar(a, b);
+        }
+
         let mut a_vec = a;
         let mut b_vec = b;
         let mut ret = 0.0;
@@ -126,4 +130,19 @@ mod tests {
         let accumulate_scalar = L2DistanceCalculator::accumulate_scalar(&a, &b);
         assert!((distance_scalar - accumulate_scalar.sqrt()) < epsilon)
     }
+
+    #[test]
+    fn test_accumulate_scalar_chunks() {
+        let a = generate_random_vector(100);
+        let b = generate_random_vector(100);
+
+        // Partial distances of chunks add up to the distance of the whole vectors
+        let streamed = a
+            .chunks(7)
+            .zip(b.chunks(7))
+            .map(|(a_chunk, b_chunk)| L2DistanceCalculator::accumulate_scalar(a_chunk, b_chunk))
+            .sum::<f32>();
+        let expected = L2DistanceCalculator::calculate_scalar(&a, &b);
+        assert!((L2DistanceCalculator::outermost_op(streamed).sqrt() - expected).abs() < 1e-4);
+    }
 }
@@ -20,6 +20,10 @@ where
 {
     #[inline(always)]
     fn calculate_squared(a: &[f32], b: &[f32]) -> f32 {
+        if cfg!(feature = "wasm") {
+            return D::outermost_op(D::accumulate_scalar(a, b));
+        }
+
         let mut simd = Simd::<f32, LANES>::splat(0.0);
         D::accumulate_lanes(a, b, &mut simd);
         D::outermost_op(simd.reduce_sum())
@@ -1,3 +1,4 @@
 pub mod dot_product;
+pub mod hamming;
 pub mod l2;
 pub mod lane_conforming;
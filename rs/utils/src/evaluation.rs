@@ -0,0 +1,65 @@
+use std::collections::HashSet;
+
+/// Fraction of the first `k` ids of `ground_truth` found in the first `k` ids of `results`.
+/// An empty ground truth is fully recalled.
+pub fn recall_at_k(ground_truth: &[u128], results: &[u128], k: usize) -> f64 {
+    let ground_truth: HashSet<&u128> = ground_truth.iter().take(k).collect();
+    if ground_truth.is_empty() {
+        return 1.0;
+    }
+    let found = results
+        .iter()
+        .take(k)
+        .collect::<HashSet<_>>()
+        .intersection(&ground_truth)
+        .count();
+    found as f64 / ground_truth.len() as f64
+}
+
+/// Mean of `recall_at_k` over queries, pairing each ground truth with the results at the same
+/// position. Queries without results have a recall of 0. Returns 0 without queries.
+pub fn mean_recall_at_k(ground_truth: &[Vec<u128>], results: &[Vec<u128>], k: usize) -> f64 {
+    if ground_truth.is_empty() {
+        return 0.0;
+    }
+    let total_recall: f64 = ground_truth
+        .iter()
+        .enumerate()
+        .map(|(i, ground_truth)| {
+            recall_at_k(
+                ground_truth,
+                results.get(i).map_or(&[], |r| r.as_slice()),
+                k,
+            )
+        })
+        .sum();
+    total_recall / ground_truth.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recall_at_k() {
+        assert_eq!(recall_at_k(&[1, 2, 3, 4], &[4, 3, 2, 1], 4), 1.0);
+        assert_eq!(recall_at_k(&[1, 2, 3, 4], &[1, 5, 2, 6], 4), 0.5);
+        // Only the first k ids of each list count
+        assert_eq!(recall_at_k(&[1, 2, 3, 4], &[3, 1, 2], 2), 0.5);
+        assert_eq!(recall_at_k(&[], &[1], 1), 1.0);
+        assert_eq!(recall_at_k(&[1], &[], 1), 0.0);
+    }
+
+    #[test]
+    fn test_mean_recall_at_k() {
+        let ground_truth = vec![vec![1, 2], vec![3, 4]];
+        assert_eq!(mean_recall_at_k(&ground_truth, &ground_truth, 2), 1.0);
+        assert_eq!(
+            mean_recall_at_k(&ground_truth, &[vec![1, 5], vec![3, 4]], 2),
+            0.75
+        );
+        // Missing results count as a recall of 0
+        assert_eq!(mean_recall_at_k(&ground_truth, &[vec![2, 1]], 2), 0.5);
+        assert_eq!(mean_recall_at_k(&[], &[], 2), 0.0);
+    }
+}
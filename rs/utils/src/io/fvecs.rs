@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+
+use anyhow::{anyhow, Result};
+
+/// Reader for the FVECS format used by standard ANN benchmarks (SIFT1M, GIST1M, Deep1B)
+/// to store base and query vectors.
+///
+/// Each entry is a little-endian `i32` dimension `d`, followed by `d` little-endian `f32` values.
+pub struct FvecsReader {}
+
+impl FvecsReader {
+    /// Read all entries of the FVECS file at `path`.
+    pub fn read(path: &str) -> Result<Vec<Vec<f32>>> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+        let mut result = vec![];
+        let mut dimension_buffer = [0u8; 4];
+        loop {
+            match reader.read_exact(&mut dimension_buffer) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+
+            let dimension = i32::from_le_bytes(dimension_buffer);
+            if dimension < 0 {
+                return Err(anyhow!(
+                    "Invalid dimension {} at entry {}",
+                    dimension,
+                    result.len()
+                ));
+            }
+
+            let mut buffer = vec![0u8; dimension as usize * 4];
+            reader
+                .read_exact(&mut buffer)
+                .map_err(|e| anyhow!("Truncated entry {} in {}: {}", result.len(), path, e))?;
+            let vector = buffer
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+                .collect();
+            result.push(vector);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_fvecs_reader() -> Result<()> {
+        let temp_dir = TempDir::new("fvecs_reader_test")?;
+        let path = format!("{}/query.fvecs", temp_dir.path().to_str().unwrap());
+
+        let expected: Vec<Vec<f32>> = vec![vec![1.0, -2.5, 3.0], vec![0.5, 0.25, 0.0]];
+        let mut file = File::create(&path)?;
+        for entry in &expected {
+            file.write_all(&(entry.len() as i32).to_le_bytes())?;
+            for value in entry {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        drop(file);
+
+        assert_eq!(FvecsReader::read(&path)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fvecs_reader_truncated() -> Result<()> {
+        let temp_dir = TempDir::new("fvecs_reader_truncated_test")?;
+        let path = format!("{}/query.fvecs", temp_dir.path().to_str().unwrap());
+
+        let mut file = File::create(&path)?;
+        file.write_all(&2i32.to_le_bytes())?;
+        file.write_all(&1.0f32.to_le_bytes())?;
+        drop(file);
+
+        assert!(FvecsReader::read(&path).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+
+use anyhow::{anyhow, Result};
+
+/// Reader for the IVECS format used by standard ANN benchmarks (SIFT1M, GIST1M, Deep1B)
+/// to store ground-truth nearest neighbors.
+///
+/// Each entry is a little-endian `i32` count `n`, followed by `n` little-endian `i32` indices.
+pub struct IvecsReader {}
+
+impl IvecsReader {
+    /// Read all entries of the IVECS file at `path`.
+    pub fn read(path: &str) -> Result<Vec<Vec<u32>>> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+        let mut result = vec![];
+        let mut count_buffer = [0u8; 4];
+        loop {
+            match reader.read_exact(&mut count_buffer) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+
+            let count = i32::from_le_bytes(count_buffer);
+            if count < 0 {
+                return Err(anyhow!("Invalid count {} at entry {}", count, result.len()));
+            }
+
+            let mut buffer = vec![0u8; count as usize * 4];
+            reader
+                .read_exact(&mut buffer)
+                .map_err(|e| anyhow!("Truncated entry {} in {}: {}", result.len(), path, e))?;
+            let indices = buffer
+                .chunks_exact(4)
+                .map(|c| i32::from_le_bytes(c.try_into().unwrap()) as u32)
+                .collect();
+            result.push(indices);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_ivecs_reader() -> Result<()> {
+        let temp_dir = TempDir::new("ivecs_reader_test")?;
+        let path = format!("{}/groundtruth.ivecs", temp_dir.path().to_str().unwrap());
+
+        let expected: Vec<Vec<u32>> = vec![vec![1, 2, 3], vec![], vec![42, 7, 100000, 0]];
+        let mut file = File::create(&path)?;
+        for entry in &expected {
+            file.write_all(&(entry.len() as i32).to_le_bytes())?;
+            for idx in entry {
+                file.write_all(&(*idx as i32).to_le_bytes())?;
+            }
+        }
+        drop(file);
+
+        assert_eq!(IvecsReader::read(&path)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ivecs_reader_truncated() -> Result<()> {
+        let temp_dir = TempDir::new("ivecs_reader_truncated_test")?;
+        let path = format!("{}/groundtruth.ivecs", temp_dir.path().to_str().unwrap());
+
+        let mut file = File::create(&path)?;
+        file.write_all(&3i32.to_le_bytes())?;
+        file.write_all(&1i32.to_le_bytes())?;
+        drop(file);
+
+        assert!(IvecsReader::read(&path).is_err());
+        Ok(())
+    }
+}
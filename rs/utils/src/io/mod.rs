@@ -3,6 +3,9 @@ use std::io::{BufReader, BufWriter, Read, Write};
 
 use anyhow::{anyhow, Result};
 
+pub mod fvecs;
+pub mod ivecs;
+
 /// Convenient wrapper for going from io::Result<usize> to Result<usize, String>
 pub fn wrap_write(writer: &mut BufWriter<&mut File>, buf: &[u8]) -> Result<usize> {
     anyhow::Ok(writer.write(buf)?)
@@ -45,6 +48,26 @@ pub fn get_latest_version(config_path: &str) -> Result<u64> {
     Ok(latest_version)
 }
 
+/// Returns the total size, in bytes, of all files under `path`.
+pub fn directory_size(path: &str) -> Result<u64> {
+    let mut size = 0;
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += directory_size(
+                entry
+                    .path()
+                    .to_str()
+                    .ok_or_else(|| anyhow!("Invalid path"))?,
+            )?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
 pub fn write_pad(
     written: usize,
     writer: &mut BufWriter<&mut File>,
@@ -98,4 +121,17 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_directory_size() -> Result<()> {
+        let temp_dir = TempDir::new("directory_size_test")?;
+        let base_directory = temp_dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir_all(format!("{}/nested", base_directory))?;
+        write(format!("{}/a", base_directory), [0u8; 10])?;
+        write(format!("{}/nested/b", base_directory), [0u8; 5])?;
+
+        assert_eq!(directory_size(&base_directory)?, 15);
+        assert!(directory_size(&format!("{}/missing", base_directory)).is_err());
+        Ok(())
+    }
 }
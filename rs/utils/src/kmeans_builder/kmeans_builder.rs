@@ -1,33 +1,49 @@
-use std::cmp::min;
+use std::cmp::{min, Ordering};
+use std::collections::BinaryHeap;
 use std::marker::PhantomData;
 use std::simd::{LaneCount, Simd, SupportedLaneCount};
 
 use anyhow::{anyhow, Ok, Result};
 use kmeans::KMeansConfig;
 use log::debug;
+use rand::seq::index::sample;
 use rand::seq::SliceRandom;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use rayon::slice::ParallelSlice;
+use serde::{Deserialize, Serialize};
 
 use crate::distance::lane_conforming::LaneConformingDistanceCalculator;
 use crate::{CalculateSquared, DistanceCalculator};
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum KMeansVariant {
     Lloyd,
+    // Each iteration only looks at `mini_batch_size` points sampled at random, which is much
+    // faster than Lloyd on large datasets. Training stops once no centroid moves by more than
+    // `convergence_tolerance` in an iteration.
+    MiniBatch {
+        mini_batch_size: usize,
+        convergence_tolerance: f32,
+    },
+    // Lloyd, with points moved between clusters after every assignment step so that all clusters
+    // have between `1 - slack` and `1 + slack` times the ideal size. See `rebalance_assignments`.
+    Balanced {
+        slack: f32,
+    },
 }
 
 pub struct KMeansBuilder<D: DistanceCalculator + CalculateSquared + Send + Sync> {
     pub num_clusters: usize,
     pub max_iter: usize,
 
-    // Factor which determine how much penalty large cluster has over small cluster.
+    // For Lloyd, factor which determine how much penalty large cluster has over small cluster.
+    // Unused by MiniBatch, which has its own `convergence_tolerance`.
     pub tolerance: f32,
 
     // data shape
     pub dimension: usize,
 
-    // Variant for this algorithm. Currently only Lloyd is supported.
+    // Variant for this algorithm.
     pub variant: KMeansVariant,
 
     pub cluster_init_values: Option<Vec<usize>>,
@@ -124,7 +140,7 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> KMeansBuilder<D> {
         }
 
         match self.variant {
-            KMeansVariant::Lloyd => {
+            KMeansVariant::Lloyd | KMeansVariant::Balanced { .. } => {
                 if self.dimension % 16 == 0 {
                     return self
                         .run_lloyd::<LaneConformingDistanceCalculator<16, D>, 16>(flattened_data);
@@ -138,6 +154,36 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> KMeansBuilder<D> {
                     return self.run_lloyd::<D, 1>(flattened_data);
                 }
             }
+            KMeansVariant::MiniBatch {
+                mini_batch_size,
+                convergence_tolerance,
+            } => {
+                if self.dimension % 16 == 0 {
+                    return self.run_mini_batch::<LaneConformingDistanceCalculator<16, D>>(
+                        flattened_data,
+                        mini_batch_size,
+                        convergence_tolerance,
+                    );
+                } else if self.dimension % 8 == 0 {
+                    return self.run_mini_batch::<LaneConformingDistanceCalculator<8, D>>(
+                        flattened_data,
+                        mini_batch_size,
+                        convergence_tolerance,
+                    );
+                } else if self.dimension % 4 == 0 {
+                    return self.run_mini_batch::<LaneConformingDistanceCalculator<4, D>>(
+                        flattened_data,
+                        mini_batch_size,
+                        convergence_tolerance,
+                    );
+                } else {
+                    return self.run_mini_batch::<D>(
+                        flattened_data,
+                        mini_batch_size,
+                        convergence_tolerance,
+                    );
+                }
+            }
         }
     }
 
@@ -224,6 +270,31 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> KMeansBuilder<D> {
                 })
                 .collect::<Vec<(usize, f32)>>();
 
+            if let KMeansVariant::Balanced { slack } = self.variant {
+                let mut labels = cluster_labels_with_min_cost
+                    .iter()
+                    .map(|(label, _)| *label)
+                    .collect::<Vec<usize>>();
+                rebalance_assignments::<T>(
+                    &data_points,
+                    &centroids,
+                    self.dimension,
+                    &mut labels,
+                    slack,
+                );
+                cluster_labels_with_min_cost = data_points
+                    .iter()
+                    .zip(labels)
+                    .map(|(data_point, label)| {
+                        let centroid =
+                            &centroids[label * self.dimension..(label + 1) * self.dimension];
+                        let distance =
+                            T::calculate_squared(data_point, centroid) + penalties[label];
+                        (label, distance)
+                    })
+                    .collect();
+            }
+
             let mut total_dist = 0.0;
             rayon::scope(|s| {
                 s.spawn(|_| {
@@ -365,15 +436,263 @@ impl<D: DistanceCalculator + CalculateSquared + Send + Sync> KMeansBuilder<D> {
             error: last_dist,
         })
     }
+
+    /// Returns the closest centroid to `point`, with its squared distance.
+    fn nearest_centroid<T: CalculateSquared>(
+        &self,
+        point: &[f32],
+        centroids: &[f32],
+    ) -> (usize, f32) {
+        centroids
+            .chunks_exact(self.dimension)
+            .enumerate()
+            .map(|(centroid_id, centroid)| (centroid_id, T::calculate_squared(point, centroid)))
+            .fold((0, f32::MAX), |(min_label, min_cost), (label, distance)| {
+                if distance < min_cost {
+                    (label, distance)
+                } else {
+                    (min_label, min_cost)
+                }
+            })
+    }
+
+    /// Mini-batch K-means: every iteration samples `mini_batch_size` points, and moves each
+    /// centroid towards the mean of the sampled points assigned to it, with a learning rate of
+    /// `1 / (1 + iteration)`. Points are only all assigned once, after training.
+    fn run_mini_batch<T: CalculateSquared + Send + Sync>(
+        &self,
+        flattened_data_points: Vec<f32>,
+        mini_batch_size: usize,
+        convergence_tolerance: f32,
+    ) -> Result<KMeansResult> {
+        if mini_batch_size == 0 {
+            return Err(anyhow!("Mini batch size needs to be greater than 0"));
+        }
+
+        let data_points = flattened_data_points
+            .par_chunks_exact(self.dimension)
+            .map(|x| x)
+            .collect::<Vec<&[f32]>>();
+
+        let num_data_points = data_points.len();
+        let num_clusters = min(self.num_clusters, num_data_points);
+        let mini_batch_size = min(mini_batch_size, num_data_points);
+
+        let mut centroids = self.init_random_points(&data_points, num_clusters)?;
+        let mut rng = rand::thread_rng();
+        let mut iteration = 0;
+        loop {
+            let batch = sample(&mut rng, num_data_points, mini_batch_size).into_vec();
+            let labels = batch
+                .par_iter()
+                .map(|point_id| {
+                    self.nearest_centroid::<T>(data_points[*point_id], &centroids)
+                        .0
+                })
+                .collect::<Vec<usize>>();
+
+            let mut sums = vec![0.0; num_clusters * self.dimension];
+            let mut counts = vec![0; num_clusters];
+            for (point_id, label) in batch.iter().zip(labels.iter()) {
+                counts[*label] += 1;
+                sums[*label * self.dimension..(*label + 1) * self.dimension]
+                    .iter_mut()
+                    .zip(data_points[*point_id].iter())
+                    .for_each(|(sum, x)| *sum += *x);
+            }
+
+            let learning_rate = 1.0 / (1.0 + iteration as f32);
+            let mut max_shift: f32 = 0.0;
+            for cluster_id in 0..num_clusters {
+                if counts[cluster_id] == 0 {
+                    continue;
+                }
+                let range = cluster_id * self.dimension..(cluster_id + 1) * self.dimension;
+                let mut shift = 0.0;
+                centroids[range.clone()]
+                    .iter_mut()
+                    .zip(sums[range].iter())
+                    .for_each(|(c, sum)| {
+                        let mean = *sum / counts[cluster_id] as f32;
+                        let updated = *c * (1.0 - learning_rate) + mean * learning_rate;
+                        shift += (updated - *c) * (updated - *c);
+                        *c = updated;
+                    });
+                max_shift = max_shift.max(shift.sqrt());
+            }
+
+            debug!(
+                "Iteration: {}, learning rate: {}, max centroid shift: {}",
+                iteration, learning_rate, max_shift
+            );
+            iteration += 1;
+            if max_shift <= convergence_tolerance || iteration >= self.max_iter {
+                debug!("Converged at iteration {}", iteration);
+                break;
+            }
+        }
+
+        let labels_with_distance = data_points
+            .par_iter()
+            .map(|data_point| self.nearest_centroid::<T>(data_point, &centroids))
+            .collect::<Vec<(usize, f32)>>();
+        Ok(KMeansResult {
+            centroids,
+            error: labels_with_distance
+                .iter()
+                .map(|(_, distance)| distance.sqrt())
+                .sum::<f32>(),
+            assignments: labels_with_distance
+                .into_iter()
+                .map(|(label, _)| label)
+                .collect(),
+        })
+    }
+}
+
+/// Moves points between clusters so that every cluster has between `(1 - slack)` and
+/// `(1 + slack)` times the ideal size `num_points / num_clusters`.
+///
+/// Over-full clusters first give their farthest points to the nearest centroid with room left
+/// (their second-nearest, unless it is full). Under-full clusters then take the points closest
+/// to them from clusters that are above the lower bound. Points of clusters within the bounds
+/// keep their assignment.
+pub fn rebalance_assignments<T: CalculateSquared>(
+    points: &[&[f32]],
+    centroids: &[f32],
+    dimension: usize,
+    assignments: &mut [usize],
+    slack: f32,
+) {
+    let num_clusters = centroids.len() / dimension;
+    if num_clusters == 0 || points.is_empty() {
+        return;
+    }
+    let centroid =
+        |cluster_id: usize| &centroids[cluster_id * dimension..(cluster_id + 1) * dimension];
+    let target_size = points.len() as f32 / num_clusters as f32;
+    // Bounds are rounded inwards, but never past the ideal size, so that they can always be met
+    let max_size =
+        ((target_size * (1.0 + slack)).floor() as usize).max(target_size.ceil() as usize);
+    let min_size =
+        ((target_size * (1.0 - slack)).max(0.0).ceil() as usize).min(target_size.floor() as usize);
+
+    // Points of every cluster, only read for over-full clusters, which never receive points
+    let mut cluster_members = vec![vec![]; num_clusters];
+    for (point_id, cluster_id) in assignments.iter().enumerate() {
+        cluster_members[*cluster_id].push(point_id);
+    }
+    let mut cluster_sizes = cluster_members
+        .iter()
+        .map(|members| members.len())
+        .collect::<Vec<usize>>();
+
+    for cluster_id in 0..num_clusters {
+        if cluster_sizes[cluster_id] <= max_size {
+            continue;
+        }
+        // Farthest points first
+        let mut members = cluster_members[cluster_id]
+            .iter()
+            .map(|point_id| {
+                (
+                    *point_id,
+                    T::calculate_squared(points[*point_id], centroid(cluster_id)),
+                )
+            })
+            .collect::<Vec<(usize, f32)>>();
+        members.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let excess = cluster_sizes[cluster_id] - max_size;
+        for (point_id, _) in members.into_iter().take(excess) {
+            // There is always room left, since `max_size * num_clusters >= points.len()`
+            let Some((new_cluster_id, _)) = (0..num_clusters)
+                .filter(|other| *other != cluster_id && cluster_sizes[*other] < max_size)
+                .map(|other| {
+                    (
+                        other,
+                        T::calculate_squared(points[point_id], centroid(other)),
+                    )
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+            else {
+                break;
+            };
+            assignments[point_id] = new_cluster_id;
+            cluster_sizes[cluster_id] -= 1;
+            cluster_sizes[new_cluster_id] += 1;
+        }
+    }
+
+    for cluster_id in 0..num_clusters {
+        if cluster_sizes[cluster_id] >= min_size {
+            continue;
+        }
+        // Only as many candidates as needed are popped, instead of sorting all of them
+        let mut candidates = (0..points.len())
+            .filter(|point_id| cluster_sizes[assignments[*point_id]] > min_size)
+            .map(|point_id| RebalanceCandidate {
+                point_id,
+                distance: T::calculate_squared(points[point_id], centroid(cluster_id)),
+            })
+            .collect::<BinaryHeap<RebalanceCandidate>>();
+
+        while cluster_sizes[cluster_id] < min_size {
+            let Some(RebalanceCandidate { point_id, .. }) = candidates.pop() else {
+                break;
+            };
+            // The donor may have reached the lower bound since the candidates were collected
+            let old_cluster_id = assignments[point_id];
+            if cluster_sizes[old_cluster_id] <= min_size {
+                continue;
+            }
+            assignments[point_id] = cluster_id;
+            cluster_sizes[old_cluster_id] -= 1;
+            cluster_sizes[cluster_id] += 1;
+        }
+    }
 }
 
+// Point that an under-full cluster can take. The heap pops the closest point first, and the
+// lowest point id among equally close points.
+struct RebalanceCandidate {
+    point_id: usize,
+    distance: f32,
+}
+
+impl Ord for RebalanceCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance
+            .total_cmp(&self.distance)
+            .then_with(|| other.point_id.cmp(&self.point_id))
+    }
+}
+
+impl PartialOrd for RebalanceCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RebalanceCandidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RebalanceCandidate {}
+
 #[cfg(test)]
 mod tests {
 
     use std::collections::HashSet;
 
+    use rand::Rng;
+
     use super::*;
     use crate::distance::l2::L2DistanceCalculator;
+    use crate::evaluation::recall_at_k;
 
     #[test]
     fn test_kmeans_lloyd() {
@@ -507,4 +826,127 @@ mod tests {
 
         assert_eq!(asigned_clusters, expected_clusters);
     }
+
+    #[test]
+    fn test_kmeans_mini_batch() {
+        let mut rng = rand::thread_rng();
+        let num_clusters = 10;
+        let dimension = 8;
+        // Point i belongs to the blob i % num_clusters
+        let flattened_data: Vec<f32> = (0..50000)
+            .flat_map(|i| {
+                let center = (i % num_clusters) as f32 * 10.0;
+                (0..dimension)
+                    .map(|_| center + rng.gen_range(-2.0..2.0))
+                    .collect::<Vec<f32>>()
+            })
+            .collect();
+        let init_values: Vec<usize> = (0..num_clusters).collect();
+
+        let full_batch = KMeansBuilder::<L2DistanceCalculator>::new_with_cluster_init_values(
+            num_clusters,
+            100,
+            0.0,
+            dimension,
+            KMeansVariant::Lloyd,
+            init_values.clone(),
+        )
+        .fit(flattened_data.clone())
+        .expect("KMeans run should succeed");
+        let mini_batch = KMeansBuilder::<L2DistanceCalculator>::new_with_cluster_init_values(
+            num_clusters,
+            100,
+            0.0,
+            dimension,
+            KMeansVariant::MiniBatch {
+                mini_batch_size: 512,
+                convergence_tolerance: 0.001,
+            },
+            init_values,
+        )
+        .fit(flattened_data)
+        .expect("KMeans run should succeed");
+        assert_eq!(mini_batch.centroids.len(), num_clusters * dimension);
+
+        // Match every mini batch centroid to the closest full batch centroid
+        let matching: Vec<u128> = mini_batch
+            .centroids
+            .chunks_exact(dimension)
+            .map(|centroid| {
+                full_batch
+                    .centroids
+                    .chunks_exact(dimension)
+                    .enumerate()
+                    .map(|(id, other)| (id, L2DistanceCalculator::calculate(centroid, other)))
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .unwrap()
+                    .0 as u128
+            })
+            .collect();
+        let recall = full_batch
+            .assignments
+            .iter()
+            .zip(mini_batch.assignments.iter())
+            .map(|(expected, label)| recall_at_k(&[*expected as u128], &[matching[*label]], 1))
+            .sum::<f64>()
+            / full_batch.assignments.len() as f64;
+        assert!(recall > 0.9, "Recall@1 is {}", recall);
+    }
+
+    #[test]
+    fn test_kmeans_balanced() {
+        let mut rng = rand::thread_rng();
+        let num_clusters = 10;
+        let dimension = 4;
+        // 1100 copies of the same point, and 9 blobs of 100 points
+        let mut flattened_data = vec![0.0; 1100 * dimension];
+        for blob in 1..num_clusters {
+            for _ in 0..100 * dimension {
+                flattened_data.push(blob as f32 * 10.0 + rng.gen_range(-1.0..1.0));
+            }
+        }
+        // One initial centroid in each blob
+        let init_values: Vec<usize> = (0..num_clusters).map(|blob| blob * 100 + 1000).collect();
+        let cluster_sizes = |variant: KMeansVariant| {
+            let result = KMeansBuilder::<L2DistanceCalculator>::new_with_cluster_init_values(
+                num_clusters,
+                100,
+                0.0,
+                dimension,
+                variant,
+                init_values.clone(),
+            )
+            .fit(flattened_data.clone())
+            .expect("KMeans run should succeed");
+            let mut sizes = vec![0; num_clusters];
+            for assignment in result.assignments {
+                sizes[assignment] += 1;
+            }
+            sizes
+        };
+
+        assert!(cluster_sizes(KMeansVariant::Lloyd).contains(&1100));
+        for size in cluster_sizes(KMeansVariant::Balanced { slack: 0.2 }) {
+            assert!((160..=240).contains(&size), "Cluster of size {}", size);
+        }
+    }
+
+    #[test]
+    fn test_rebalance_assignments() {
+        // Points on a line, all assigned to the first centroid
+        let flattened_points: Vec<f32> = (0..10).map(|i| i as f32).collect();
+        let points: Vec<&[f32]> = flattened_points.chunks_exact(1).collect();
+        let centroids = vec![0.0, 5.0, 9.0];
+        let mut assignments = vec![0; 10];
+        rebalance_assignments::<L2DistanceCalculator>(
+            &points,
+            &centroids,
+            1,
+            &mut assignments,
+            0.0,
+        );
+        // Sizes are between floor(10 / 3) and ceil(10 / 3). The farthest points leave the first
+        // cluster, and the third cluster takes the points closest to it.
+        assert_eq!(assignments, vec![0, 0, 0, 0, 1, 1, 1, 2, 2, 2]);
+    }
 }
@@ -1,11 +1,16 @@
 #![feature(portable_simd)]
 
 use std::simd::{LaneCount, Simd, SupportedLaneCount};
+
+use anyhow::{anyhow, Result};
 pub mod distance;
+pub mod evaluation;
 pub mod io;
 pub mod kmeans_builder;
 pub mod mem;
 pub mod test_utils;
+pub mod validation;
+pub mod warmup;
 
 pub trait DistanceCalculator {
     /// Compute distance between two vectors.
@@ -19,6 +24,9 @@ pub trait DistanceCalculator {
     ) where
         LaneCount<LANES>: SupportedLaneCount;
 
+    /// Scalar counterpart of `accumulate_lanes`: the partial distance of `a` and `b` before
+    /// `outermost_op`. Partial distances of consecutive chunks of the same vectors add up, so
+    /// callers can stream chunks and apply `outermost_op` once to the sum.
     fn accumulate_scalar(a: &[f32], b: &[f32]) -> f32;
 
     /*
@@ -28,6 +36,12 @@ pub trait DistanceCalculator {
     fn outermost_op(x: f32) -> f32;
 }
 
+/// Distance between binary vectors packed into u64 words. Separate from `DistanceCalculator`,
+/// which works on f32 vectors.
+pub trait BinaryDistanceCalculator {
+    fn calculate(a: &[u64], b: &[u64]) -> u32;
+}
+
 pub trait CalculateSquared {
     fn calculate_squared(a: &[f32], b: &[f32]) -> f32;
 }
@@ -35,3 +49,14 @@ pub trait CalculateSquared {
 pub fn ceil_div(a: usize, b: usize) -> usize {
     (a + b - 1) / b
 }
+
+/// Divides `vector` by its L2 norm, and returns the norm. Zero vectors have no direction, so
+/// they can't be normalized and are an error.
+pub fn l2_normalize(vector: &mut [f32]) -> Result<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return Err(anyhow!("Cannot normalize a zero vector"));
+    }
+    vector.iter_mut().for_each(|v| *v /= norm);
+    Ok(norm)
+}
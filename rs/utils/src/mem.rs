@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 pub fn transmute_u8_to_slice<T>(data: &[u8]) -> &[T] {
     unsafe {
         std::slice::from_raw_parts(
@@ -16,6 +18,18 @@ pub fn transmute_slice_to_u8<T>(slice: &[T]) -> &[u8] {
     unsafe { std::slice::from_raw_parts(slice.as_ptr() as *const u8, byte_count) }
 }
 
+pub fn transmute_slice_to_u8_mut<T>(slice: &mut [T]) -> &mut [u8] {
+    let byte_count = slice.len() * std::mem::size_of::<T>();
+    unsafe { std::slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut u8, byte_count) }
+}
+
+/// Returns an id that was never returned before in this process. Unlike addresses, which are
+/// reused once a value is dropped, it identifies a value in caches that can outlive it.
+pub fn next_instance_id() -> u64 {
+    static NEXT_INSTANCE_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 pub fn get_ith_val_from_raw_ptr<T: Copy>(raw_ptr: *const T, index: usize) -> T {
     unsafe { *raw_ptr.add(index) }
 }
@@ -0,0 +1,75 @@
+use std::fmt;
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidVectorReason {
+    NaN,
+    Inf,
+}
+
+/// Error returned when a vector contains a component that is not a finite number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidVector {
+    pub reason: InvalidVectorReason,
+    pub context: String,
+    // Index of the first offending component.
+    pub index: usize,
+}
+
+impl fmt::Display for InvalidVector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.reason {
+            InvalidVectorReason::NaN => "NaN",
+            InvalidVectorReason::Inf => "Inf",
+        };
+        write!(
+            f,
+            "{}: vector has {} at component {}",
+            self.context, reason, self.index
+        )
+    }
+}
+
+impl std::error::Error for InvalidVector {}
+
+/// Returns an `InvalidVector` error if any component of `v` is NaN or infinite.
+/// `context` describes the caller and is included in the error.
+pub fn validate_vector(v: &[f32], context: &str) -> Result<()> {
+    match v.iter().position(|x| !x.is_finite()) {
+        Some(index) => {
+            let reason = if v[index].is_nan() {
+                InvalidVectorReason::NaN
+            } else {
+                InvalidVectorReason::Inf
+            };
+            Err(InvalidVector {
+                reason,
+                context: context.to_string(),
+                index,
+            }
+            .into())
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_vector() {
+        assert!(validate_vector(&[1.0, -2.0, 0.0], "test").is_ok());
+        assert!(validate_vector(&[], "test").is_ok());
+
+        let err = validate_vector(&[1.0, f32::NAN], "test").unwrap_err();
+        let err = err.downcast_ref::<InvalidVector>().unwrap();
+        assert_eq!(err.reason, InvalidVectorReason::NaN);
+        assert_eq!(err.index, 1);
+        assert_eq!(err.context, "test");
+
+        let err = validate_vector(&[f32::NEG_INFINITY, f32::NAN], "insert").unwrap_err();
+        assert_eq!(err.to_string(), "insert: vector has Inf at component 0");
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+use rand::seq::index::sample;
+
+const PAGE_SIZE: usize = 4096;
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarmupStrategy {
+    // Read every file from start to end.
+    Sequential,
+    // Map every file and lock it in memory. Files stay locked as long as the report is alive.
+    Mlock,
+    // Read a random fraction, in (0, 1], of the pages of every file.
+    Random(f64),
+}
+
+/// Parses "sequential", "mlock" or "random:<fraction>".
+impl FromStr for WarmupStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "sequential" => Ok(WarmupStrategy::Sequential),
+            None if s == "mlock" => Ok(WarmupStrategy::Mlock),
+            Some(("random", fraction)) => {
+                let fraction: f64 = fraction
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid sample fraction: {}", fraction))?;
+                Ok(WarmupStrategy::Random(fraction))
+            }
+            _ => Err(anyhow!("Unknown warm-up strategy: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WarmupReport {
+    pub num_files: usize,
+    // Total size of the files under the directory.
+    pub total_bytes: usize,
+    // Number of bytes read or locked.
+    pub warmed_bytes: usize,
+    pub duration: Duration,
+
+    // Regions locked with `WarmupStrategy::Mlock`. They are unlocked when dropped.
+    locked_regions: Vec<Mmap>,
+}
+
+impl WarmupReport {
+    pub fn num_locked_regions(&self) -> usize {
+        self.locked_regions.len()
+    }
+}
+
+/// Loads all files under `index_dir` into the page cache, so that the first queries don't pay
+/// for reading them from disk.
+pub fn warm_up(index_dir: &str, strategy: WarmupStrategy) -> Result<WarmupReport> {
+    if let WarmupStrategy::Random(fraction) = strategy {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(anyhow!(
+                "Sample fraction must be in (0, 1], got {}",
+                fraction
+            ));
+        }
+    }
+
+    let start = Instant::now();
+    let mut report = WarmupReport::default();
+    for path in list_files(Path::new(index_dir))? {
+        let file = File::open(&path)?;
+        let len = file.metadata()?.len() as usize;
+        report.num_files += 1;
+        report.total_bytes += len;
+        if len == 0 {
+            continue;
+        }
+
+        match strategy {
+            WarmupStrategy::Sequential => {
+                report.warmed_bytes += read_sequentially(file)?;
+            }
+            WarmupStrategy::Mlock => {
+                let mmap = unsafe { Mmap::map(&file) }?;
+                mmap.lock()
+                    .map_err(|e| anyhow!("Failed to lock {}: {}", path.display(), e))?;
+                report.warmed_bytes += len;
+                report.locked_regions.push(mmap);
+            }
+            WarmupStrategy::Random(fraction) => {
+                let mmap = unsafe { Mmap::map(&file) }?;
+                report.warmed_bytes += touch_random_pages(&mmap, fraction);
+            }
+        }
+    }
+    report.duration = start.elapsed();
+    Ok(report)
+}
+
+fn list_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn read_sequentially(mut file: File) -> Result<usize> {
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+    let mut total = 0;
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(total);
+        }
+        total += n;
+    }
+}
+
+/// Reads one byte of a random `fraction` of the pages of `mmap`. Returns the number of bytes
+/// in the touched pages.
+fn touch_random_pages(mmap: &Mmap, fraction: f64) -> usize {
+    let num_pages = mmap.len().div_ceil(PAGE_SIZE);
+    let num_samples = ((num_pages as f64 * fraction).ceil() as usize).clamp(1, num_pages);
+    let mut rng = rand::thread_rng();
+    let mut warmed_bytes = 0;
+    for page in sample(&mut rng, num_pages, num_samples).iter() {
+        let offset = page * PAGE_SIZE;
+        std::hint::black_box(mmap[offset]);
+        warmed_bytes += PAGE_SIZE.min(mmap.len() - offset);
+    }
+    warmed_bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn create_index_dir(temp_dir: &TempDir) -> String {
+        let index_dir = temp_dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir_all(format!("{}/segment/quantizer", index_dir)).unwrap();
+        std::fs::write(
+            format!("{}/segment/vectors", index_dir),
+            vec![1u8; 10 * PAGE_SIZE],
+        )
+        .unwrap();
+        std::fs::write(format!("{}/segment/quantizer/config", index_dir), b"config").unwrap();
+        std::fs::write(format!("{}/empty", index_dir), b"").unwrap();
+        index_dir
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let temp_dir = TempDir::new("test_warm_up").unwrap();
+        let index_dir = create_index_dir(&temp_dir);
+        let total_bytes = 10 * PAGE_SIZE + 6;
+
+        let report = warm_up(&index_dir, WarmupStrategy::Sequential).unwrap();
+        assert_eq!(report.num_files, 3);
+        assert_eq!(report.total_bytes, total_bytes);
+        assert_eq!(report.warmed_bytes, total_bytes);
+        assert_eq!(report.num_locked_regions(), 0);
+
+        // One page of the vectors and the single page of the config.
+        let report = warm_up(&index_dir, WarmupStrategy::Random(0.1)).unwrap();
+        assert_eq!(report.total_bytes, total_bytes);
+        assert_eq!(report.warmed_bytes, PAGE_SIZE + 6);
+
+        let report = warm_up(&index_dir, WarmupStrategy::Random(1.0)).unwrap();
+        assert_eq!(report.warmed_bytes, total_bytes);
+
+        assert!(warm_up(&index_dir, WarmupStrategy::Random(0.0)).is_err());
+        assert!(warm_up(&index_dir, WarmupStrategy::Random(1.5)).is_err());
+    }
+
+    #[test]
+    fn test_warm_up_mlock() {
+        let temp_dir = TempDir::new("test_warm_up_mlock").unwrap();
+        let index_dir = create_index_dir(&temp_dir);
+
+        // Locking may not be permitted in the test environment.
+        if let Ok(report) = warm_up(&index_dir, WarmupStrategy::Mlock) {
+            assert_eq!(report.num_locked_regions(), 2);
+            assert_eq!(report.warmed_bytes, report.total_bytes);
+        }
+    }
+
+    #[test]
+    fn test_parse_warmup_strategy() {
+        assert_eq!(
+            "sequential".parse::<WarmupStrategy>().unwrap(),
+            WarmupStrategy::Sequential
+        );
+        assert_eq!(
+            "mlock".parse::<WarmupStrategy>().unwrap(),
+            WarmupStrategy::Mlock
+        );
+        assert_eq!(
+            "random:0.25".parse::<WarmupStrategy>().unwrap(),
+            WarmupStrategy::Random(0.25)
+        );
+        assert!("random".parse::<WarmupStrategy>().is_err());
+        assert!("random:abc".parse::<WarmupStrategy>().is_err());
+        assert!("lazy".parse::<WarmupStrategy>().is_err());
+    }
+}